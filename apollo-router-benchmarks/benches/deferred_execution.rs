@@ -0,0 +1,116 @@
+// Executes a defer-heavy query on a request with a large context, with the default
+// configuration, to compare the router before and after a change with criterion baselines:
+//
+//   git checkout <base commit>
+//   cargo bench --bench deferred_execution -- --save-baseline before
+//   git checkout <change>
+//   cargo bench --bench deferred_execution -- --baseline before
+//
+// The benchmark only uses public APIs, so the file can be copied to the base commit if it does
+// not have it yet.
+
+use apollo_router::services::subgraph;
+use apollo_router::services::supergraph;
+use apollo_router::Context;
+use apollo_router::TestHarness;
+use criterion::criterion_group;
+use criterion::criterion_main;
+use criterion::Criterion;
+use serde_json_bytes::json;
+use serde_json_bytes::Value;
+use tower::BoxError;
+use tower::ServiceExt;
+
+// each field is deferred on its own, so every deferred part fetches the entities of all the
+// products in its own task
+static QUERY: &str = r#"{ topProducts(first: 20) { upc ... @defer { name } ... @defer { price } ... @defer { inStock } ... @defer { shippingEstimate } ... @defer { reviews { id body } } } }"#;
+
+/// Number of entries added to the request context, like the data of plugins
+const CONTEXT_ENTRIES: usize = 100;
+
+/// Answers every subgraph request: a list of products for the root fetch, and all the fields of
+/// the product entities for the entity fetches
+fn subgraph_response(request: subgraph::Request) -> subgraph::Response {
+    let data = match request
+        .subgraph_request
+        .body()
+        .variables
+        .get("representations")
+    {
+        Some(Value::Array(representations)) => {
+            let entities: Vec<Value> = representations
+                .iter()
+                .map(|representation| {
+                    let mut entity = representation.as_object().cloned().unwrap_or_default();
+                    if let Value::Object(fields) = json!({
+                        "name": "Table",
+                        "price": 100,
+                        "weight": 10,
+                        "inStock": true,
+                        "shippingEstimate": 5,
+                        "reviews": [{ "id": "1", "body": "Great" }]
+                    }) {
+                        entity.extend(fields);
+                    }
+                    Value::Object(entity)
+                })
+                .collect();
+            json!({ "_entities": entities })
+        }
+        _ => {
+            let products: Vec<Value> = (0..20)
+                .map(|upc| json!({ "__typename": "Product", "upc": upc.to_string() }))
+                .collect();
+            json!({ "topProducts": products })
+        }
+    };
+    subgraph::Response::builder()
+        .data(data)
+        .context(request.context)
+        .build()
+}
+
+fn setup() -> TestHarness<'static> {
+    TestHarness::builder()
+        .schema(include_str!("fixtures/supergraph.graphql"))
+        .subgraph_hook(|_name, _service| {
+            tower::service_fn(|request: subgraph::Request| async move {
+                Ok::<_, BoxError>(subgraph_response(request))
+            })
+            .boxed()
+        })
+}
+
+async fn deferred_execution(supergraph_service: supergraph::BoxCloneService) {
+    let context = Context::new();
+    for i in 0..CONTEXT_ENTRIES {
+        context
+            .insert(format!("plugin_data_{}", i), "x".repeat(100))
+            .unwrap();
+    }
+    let request = supergraph::Request::fake_builder()
+        .query(QUERY.to_string())
+        .header("Accept", "multipart/mixed; deferSpec=20220824")
+        .context(context)
+        .build()
+        .expect("expecting valid request");
+
+    let mut response = supergraph_service.oneshot(request).await.unwrap();
+    let mut parts = 0;
+    while response.next_response().await.is_some() {
+        parts += 1;
+    }
+    assert!(parts > 1, "the deferred parts were not executed");
+}
+
+fn from_elem(c: &mut Criterion) {
+    c.bench_function("deferred_execution", move |b| {
+        let runtime = tokio::runtime::Runtime::new().unwrap();
+        let router = runtime.block_on(setup().build()).unwrap();
+        b.to_async(runtime)
+            .iter(|| deferred_execution(router.clone()));
+    });
+}
+
+criterion_group!(benches, from_elem);
+criterion_main!(benches);
@@ -0,0 +1,43 @@
+use std::time::Duration;
+
+use apollo_router::_private::DurationHistogram;
+use criterion::black_box;
+use criterion::criterion_group;
+use criterion::criterion_main;
+use criterion::Criterion;
+
+// Request latencies spread between 250µs and 200ms
+fn latencies() -> Vec<Duration> {
+    (0..10_000u64)
+        .map(|i| Duration::from_nanos(250_000 + (i * 7_919 % 10_000) * 19_975 + i % 997))
+        .collect()
+}
+
+fn histogram(latencies: &[Duration]) -> DurationHistogram {
+    let mut histogram = DurationHistogram::new();
+    for latency in latencies {
+        histogram.increment_duration(*latency);
+    }
+    histogram
+}
+
+fn from_elem(c: &mut Criterion) {
+    let latencies = latencies();
+    // as sent with the `millisecond` latency precision
+    let rounded: Vec<Duration> = latencies
+        .iter()
+        .map(|latency| Duration::from_millis(((latency.as_nanos() + 500_000) / 1_000_000) as u64))
+        .collect();
+
+    let mut group = c.benchmark_group("duration_histogram");
+    group.bench_function("nanosecond_precision", |b| {
+        b.iter(|| histogram(black_box(&latencies)))
+    });
+    group.bench_function("millisecond_precision", |b| {
+        b.iter(|| histogram(black_box(&rounded)))
+    });
+    group.finish();
+}
+
+criterion_group!(benches, from_elem);
+criterion_main!(benches);
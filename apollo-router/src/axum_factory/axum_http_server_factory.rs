@@ -21,22 +21,25 @@ use hyper::Body;
 use itertools::Itertools;
 use multimap::MultiMap;
 use serde::Serialize;
-use tokio::net::TcpListener;
 #[cfg(unix)]
 use tokio::net::UnixListener;
 use tower::service_fn;
 use tower::BoxError;
 use tower::ServiceExt;
 use tower_http::compression::predicate::NotForContentType;
+use tower_http::compression::predicate::SizeAbove;
 use tower_http::compression::CompressionLayer;
-use tower_http::compression::DefaultPredicate;
 use tower_http::compression::Predicate;
 use tower_http::trace::TraceLayer;
 use tracing::Span;
 
+use super::handlers::handle_batch_post;
 use super::handlers::handle_get;
 use super::handlers::handle_get_with_static;
 use super::handlers::handle_post;
+use super::handlers::GraphQLBody;
+use super::handlers::GraphQLRequests;
+use super::handlers::OnDisconnect;
 use super::listeners::ensure_endpoints_consistency;
 use super::listeners::ensure_listenaddrs_consistency;
 use super::listeners::extra_endpoints;
@@ -45,11 +48,12 @@ use super::utils::check_accept_header;
 use super::utils::decompress_request_body;
 use super::utils::PropagatingMakeSpan;
 use super::ListenAddrAndRouter;
+use crate::axum_factory::listeners::bind_tcp_listener;
 use crate::axum_factory::listeners::get_extra_listeners;
 use crate::axum_factory::listeners::serve_router_on_listen_addr;
 use crate::cache::DeduplicatingCache;
+use crate::configuration::Compression;
 use crate::configuration::Configuration;
-use crate::configuration::Homepage;
 use crate::configuration::ListenAddr;
 use crate::configuration::Sandbox;
 use crate::graphql;
@@ -184,8 +188,7 @@ impl HttpServerFactory for AxumHttpServerFactory {
                     }) {
                         Some(listener) => listener,
                         None => Listener::Tcp(
-                            TcpListener::bind(addr)
-                                .await
+                            bind_tcp_listener(addr, &configuration.server.socket)
                                 .map_err(ApolloRouterError::ServerCreationError)?,
                         ),
                     }
@@ -213,8 +216,13 @@ impl HttpServerFactory for AxumHttpServerFactory {
                 .local_addr()
                 .map_err(ApolloRouterError::ServerCreationError)?;
 
-            let (main_server, main_shutdown_sender) =
-                serve_router_on_listen_addr(main_listener, all_routers.main.1);
+            let (main_server, main_shutdown_sender) = serve_router_on_listen_addr(
+                main_listener,
+                all_routers.main.1,
+                configuration.server.socket.clone(),
+                configuration.server.idle_timeout,
+                configuration.server.connection_limit.clone(),
+            );
 
             tracing::info!(
                 "GraphQL endpoint exposed at {}{} 🚀",
@@ -224,8 +232,12 @@ impl HttpServerFactory for AxumHttpServerFactory {
 
             // serve extra routers
 
-            let listeners_and_routers =
-                get_extra_listeners(previous_listeners, all_routers.extra).await?;
+            let listeners_and_routers = get_extra_listeners(
+                previous_listeners,
+                all_routers.extra,
+                &configuration.server.socket,
+            )
+            .await?;
 
             let actual_extra_listen_adresses = listeners_and_routers
                 .iter()
@@ -247,8 +259,13 @@ impl HttpServerFactory for AxumHttpServerFactory {
                 listeners_and_routers
                     .into_iter()
                     .map(|((listen_addr, listener), router)| {
-                        let (server, shutdown_sender) =
-                            serve_router_on_listen_addr(listener, router);
+                        let (server, shutdown_sender) = serve_router_on_listen_addr(
+                            listener,
+                            router,
+                            configuration.server.socket.clone(),
+                            configuration.server.idle_timeout,
+                            configuration.server.connection_limit.clone(),
+                        );
                         (
                             server.map(|listener| (listen_addr, listener)),
                             shutdown_sender,
@@ -330,9 +347,7 @@ where
         .layer(cors)
         // Compress the response body, except for multipart responses such as with `@defer`.
         // This is a work-around for https://github.com/apollographql/router/issues/1572
-        .layer(CompressionLayer::new().compress_when(
-            DefaultPredicate::new().and(NotForContentType::const_new("multipart/")),
-        ));
+        .layer(compression_layer(&configuration.server.compression));
 
     let route = endpoints_on_main_listener
         .into_iter()
@@ -342,6 +357,29 @@ where
     Ok(ListenAddrAndRouter(listener, route))
 }
 
+fn compression_layer(configuration: &Compression) -> CompressionLayer<impl Predicate> {
+    let enabled = configuration.enabled;
+    CompressionLayer::new()
+        .gzip(enabled)
+        .br(enabled)
+        .deflate(enabled)
+        .compress_when(
+            SizeAbove::new(configuration.min_size)
+                .and(NotForContentType::GRPC)
+                .and(NotForContentType::IMAGES)
+                .and(NotForContentType::const_new("multipart/")),
+        )
+}
+
+/// Records the disconnections of the clients of the current supergraph service
+fn on_disconnect<RF>(service: &RF) -> OnDisconnect
+where
+    RF: SupergraphServiceFactory,
+{
+    let service = service.clone();
+    Arc::new(move || service.record_client_disconnect())
+}
+
 pub(super) fn main_router<RF>(configuration: &Configuration, apq: APQLayer) -> axum::Router
 where
     RF: SupergraphServiceFactory,
@@ -361,18 +399,21 @@ where
                     host,
                     apq2,
                     service.new_service().boxed(),
+                    on_disconnect(&service),
                     http_request,
                 )
             }
         })
     } else if configuration.homepage.enabled {
+        let homepage = configuration.homepage.display_page();
         get({
             move |host: Host, Extension(service): Extension<RF>, http_request: Request<Body>| {
                 handle_get_with_static(
-                    Homepage::display_page(),
+                    homepage.clone(),
                     host,
                     apq2,
                     service.new_service().boxed(),
+                    on_disconnect(&service),
                     http_request,
                 )
             }
@@ -380,32 +421,72 @@ where
     } else {
         get({
             move |host: Host, Extension(service): Extension<RF>, http_request: Request<Body>| {
-                handle_get(host, apq2, service.new_service().boxed(), http_request)
+                handle_get(
+                    host,
+                    apq2,
+                    service.new_service().boxed(),
+                    on_disconnect(&service),
+                    http_request,
+                )
             }
         })
     };
 
-    Router::<hyper::Body>::new().route(
-        &graphql_configuration.path,
-        get_handler
-            .post({
-                move |host: Host,
-                      uri: OriginalUri,
-                      request: Json<graphql::Request>,
-                      Extension(service): Extension<RF>,
-                      header_map: HeaderMap| {
-                    {
-                        handle_post(
+    let accept_graphql_content_type = configuration.supergraph.accept_graphql_content_type;
+    let method_router = if configuration.batching.enabled {
+        let max_size = configuration.batching.max_size;
+        get_handler.post({
+            move |host: Host,
+                  uri: OriginalUri,
+                  body: GraphQLBody<GraphQLRequests>,
+                  Extension(service): Extension<RF>,
+                  header_map: HeaderMap| async move {
+                match body.into_request(accept_graphql_content_type) {
+                    Ok(requests) => {
+                        let on_disconnect = on_disconnect(&service);
+                        handle_batch_post(
                             host,
                             uri,
-                            request,
+                            requests,
+                            max_size,
                             apq,
-                            service.new_service().boxed(),
+                            move || service.new_service().boxed(),
+                            on_disconnect,
                             header_map,
                         )
+                        .await
                     }
+                    Err(response) => response,
+                }
+            }
+        })
+    } else {
+        get_handler.post({
+            move |host: Host,
+                  uri: OriginalUri,
+                  body: GraphQLBody<graphql::Request>,
+                  Extension(service): Extension<RF>,
+                  header_map: HeaderMap| async move {
+                match body.into_request(accept_graphql_content_type) {
+                    Ok(request) => handle_post(
+                        host,
+                        uri,
+                        request,
+                        apq,
+                        service.new_service().boxed(),
+                        on_disconnect(&service),
+                        header_map,
+                    )
+                    .await
+                    .into_response(),
+                    Err(response) => response,
                 }
-            })
-            .layer(middleware::from_fn(check_accept_header)),
+            }
+        })
+    };
+
+    Router::<hyper::Body>::new().route(
+        &graphql_configuration.path,
+        method_router.layer(middleware::from_fn(check_accept_header)),
     )
 }
@@ -0,0 +1,182 @@
+//! Limit of the connections of each client IP, a coarse protection against a single client
+//! opening many connections.
+//!
+//! The connections are counted by peer IP when they are accepted, and the connections over the
+//! limit are closed right away. The connections of trusted proxies are not counted, since they
+//! carry the requests of many clients: the requests they forward are counted by the client IP
+//! of their `X-Forwarded-For` header instead, and rejected with a 429 status over the limit.
+
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use axum::middleware::Next;
+use axum::response::IntoResponse;
+use axum::response::Response;
+use http::HeaderMap;
+use http::Request;
+use http::StatusCode;
+
+use crate::configuration::ConnectionLimit;
+
+const X_FORWARDED_FOR: &str = "x-forwarded-for";
+
+/// Counts the open connections, or the requests in flight, of each client IP
+#[derive(Clone)]
+pub(super) struct ConnectionLimiter {
+    config: Arc<ConnectionLimit>,
+    open: Arc<Mutex<HashMap<IpAddr, usize>>>,
+}
+
+impl ConnectionLimiter {
+    pub(super) fn new(config: ConnectionLimit) -> Self {
+        Self {
+            config: Arc::new(config),
+            open: Default::default(),
+        }
+    }
+
+    pub(super) fn is_enabled(&self) -> bool {
+        self.config.max_connections_per_ip.is_some()
+    }
+
+    pub(super) fn is_trusted_proxy(&self, ip: &IpAddr) -> bool {
+        self.config.trusted_proxies.contains(ip)
+    }
+
+    /// Counts a connection of the IP, or returns `None` if the IP is at the limit. The
+    /// connection is counted until the guard is dropped
+    pub(super) fn acquire(&self, ip: IpAddr) -> Option<ConnectionGuard> {
+        let max = match self.config.max_connections_per_ip {
+            Some(max) => max.get(),
+            None => return Some(ConnectionGuard { limiter: None, ip }),
+        };
+        let mut open = self.open.lock().expect("lock poisoned");
+        let count = open.entry(ip).or_default();
+        if *count >= max {
+            return None;
+        }
+        *count += 1;
+        Some(ConnectionGuard {
+            limiter: Some(self.clone()),
+            ip,
+        })
+    }
+
+    /// The client IP of a request forwarded by a trusted proxy: the last address of the
+    /// `X-Forwarded-For` header that is not a trusted proxy
+    fn forwarded_client(&self, headers: &HeaderMap) -> Option<IpAddr> {
+        let addresses: Vec<IpAddr> = headers
+            .get_all(X_FORWARDED_FOR)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .filter_map(|address| address.trim().parse().ok())
+            .collect();
+        addresses
+            .iter()
+            .rev()
+            .find(|address| !self.is_trusted_proxy(address))
+            .or_else(|| addresses.first())
+            .copied()
+    }
+}
+
+pub(super) struct ConnectionGuard {
+    limiter: Option<ConnectionLimiter>,
+    ip: IpAddr,
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        if let Some(limiter) = &self.limiter {
+            let mut open = limiter.open.lock().expect("lock poisoned");
+            if let Some(count) = open.get_mut(&self.ip) {
+                *count -= 1;
+                if *count == 0 {
+                    open.remove(&self.ip);
+                }
+            }
+        }
+    }
+}
+
+/// Limits the requests in flight of each client of a trusted proxy
+pub(super) async fn limit_forwarded_clients<B>(
+    request: Request<B>,
+    next: Next<B>,
+    limiter: ConnectionLimiter,
+) -> Response {
+    // the requests of the proxy itself are not limited
+    let _guard = match limiter.forwarded_client(request.headers()) {
+        Some(client) => match limiter.acquire(client) {
+            Some(guard) => Some(guard),
+            None => {
+                tracing::debug!(
+                    "rejecting a request of {}: too many requests in flight",
+                    client
+                );
+                return (
+                    StatusCode::TOO_MANY_REQUESTS,
+                    "Too many requests in flight for this client",
+                )
+                    .into_response();
+            }
+        },
+        None => None,
+    };
+    next.run(request).await
+}
+
+#[cfg(test)]
+mod tests {
+    use std::num::NonZeroUsize;
+
+    use super::*;
+
+    fn limiter(max: usize) -> ConnectionLimiter {
+        ConnectionLimiter::new(
+            ConnectionLimit::builder()
+                .max_connections_per_ip(NonZeroUsize::new(max).unwrap())
+                .trusted_proxy("10.0.0.1".parse().unwrap())
+                .trusted_proxy("10.0.0.2".parse().unwrap())
+                .build(),
+        )
+    }
+
+    #[test]
+    fn it_counts_connections_per_ip() {
+        let limiter = limiter(2);
+        let ip: IpAddr = "192.168.0.1".parse().unwrap();
+        let first = limiter.acquire(ip).unwrap();
+        let _second = limiter.acquire(ip).unwrap();
+        assert!(limiter.acquire(ip).is_none());
+        assert!(limiter.acquire("192.168.0.2".parse().unwrap()).is_some());
+
+        drop(first);
+        assert!(limiter.acquire(ip).is_some());
+    }
+
+    #[test]
+    fn it_reads_the_client_ip_behind_trusted_proxies() {
+        let limiter = limiter(1);
+        let mut headers = HeaderMap::new();
+        assert_eq!(limiter.forwarded_client(&headers), None);
+
+        headers.append(X_FORWARDED_FOR, "1.2.3.4, 5.6.7.8".parse().unwrap());
+        headers.append(X_FORWARDED_FOR, "10.0.0.2".parse().unwrap());
+        // the client can prepend any address, only the ones added by the proxies are trusted
+        assert_eq!(
+            limiter.forwarded_client(&headers),
+            Some("5.6.7.8".parse().unwrap())
+        );
+
+        let mut headers = HeaderMap::new();
+        headers.insert(X_FORWARDED_FOR, "10.0.0.1,10.0.0.2".parse().unwrap());
+        assert_eq!(
+            limiter.forwarded_client(&headers),
+            Some("10.0.0.1".parse().unwrap())
+        );
+    }
+}
@@ -1,21 +1,33 @@
 //! Http handlers
 use std::str::FromStr;
+use std::sync::Arc;
+use std::task::Poll;
 
+use axum::body::HttpBody;
 use axum::body::StreamBody;
+use axum::extract::FromRequest;
 use axum::extract::Host;
 use axum::extract::OriginalUri;
+use axum::extract::RequestParts;
 use axum::http::header::HeaderMap;
 use axum::http::StatusCode;
 use axum::response::*;
 use bytes::Bytes;
+use futures::future::join_all;
 use futures::future::ready;
 use futures::stream::once;
+use futures::stream::poll_fn;
 use futures::StreamExt;
+use http::header::ACCEPT;
 use http::header::CONTENT_TYPE;
 use http::HeaderValue;
 use http::Request;
 use http::Uri;
 use hyper::Body;
+use serde::de::DeserializeOwned;
+use serde::de::Error as _;
+use serde::Deserialize;
+use serde::Deserializer;
 use tower::util::BoxService;
 use tower::BoxError;
 use tower::ServiceExt;
@@ -26,6 +38,7 @@ use super::utils::accepts_multipart;
 use super::utils::accepts_wildcard;
 use super::utils::prefers_html;
 use super::utils::process_vary_header;
+use super::utils::APPLICATION_GRAPHQL_HEADER_VALUE;
 use super::utils::APPLICATION_JSON_HEADER_VALUE;
 use super::utils::GRAPHQL_JSON_RESPONSE_HEADER_VALUE;
 use crate::graphql;
@@ -42,6 +55,7 @@ pub(super) async fn handle_get_with_static(
     Host(host): Host,
     apq: APQLayer,
     service: BoxService<SupergraphRequest, SupergraphResponse, BoxError>,
+    on_disconnect: OnDisconnect,
     http_request: Request<Body>,
 ) -> impl IntoResponse {
     if prefers_html(http_request.headers()) {
@@ -56,9 +70,7 @@ pub(super) async fn handle_get_with_static(
         let mut http_request = http_request.map(|_| request);
         *http_request.uri_mut() = Uri::from_str(&format!("http://{}{}", host, http_request.uri()))
             .expect("the URL is already valid because it comes from axum; qed");
-        return run_graphql_request(service, apq, http_request)
-            .await
-            .into_response();
+        return run_graphql_request(service, apq, http_request, on_disconnect).await;
     }
 
     (StatusCode::BAD_REQUEST, "Invalid GraphQL request").into_response()
@@ -68,6 +80,7 @@ pub(super) async fn handle_get(
     Host(host): Host,
     apq: APQLayer,
     service: BoxService<SupergraphRequest, SupergraphResponse, BoxError>,
+    on_disconnect: OnDisconnect,
     http_request: Request<Body>,
 ) -> impl IntoResponse {
     if let Some(request) = http_request
@@ -78,20 +91,94 @@ pub(super) async fn handle_get(
         let mut http_request = http_request.map(|_| request);
         *http_request.uri_mut() = Uri::from_str(&format!("http://{}{}", host, http_request.uri()))
             .expect("the URL is already valid because it comes from axum; qed");
-        return run_graphql_request(service, apq, http_request)
-            .await
-            .into_response();
+        return run_graphql_request(service, apq, http_request, on_disconnect).await;
     }
 
     (StatusCode::BAD_REQUEST, "Invalid Graphql request").into_response()
 }
 
+/// The body of a POST request, depending on its content type
+pub(super) enum GraphQLBody<T> {
+    /// A JSON request, with the `application/json` content type
+    Json(T),
+    /// A raw query string, with the `application/graphql` content type
+    Query(String),
+    /// Any other content type
+    Unsupported,
+}
+
+impl<T> GraphQLBody<T>
+where
+    T: From<graphql::Request>,
+{
+    /// Returns the request, or a `415 Unsupported Media Type` response if its content type is not accepted
+    pub(super) fn into_request(self, accept_graphql_content_type: bool) -> Result<T, Response> {
+        match self {
+            GraphQLBody::Json(request) => Ok(request),
+            GraphQLBody::Query(query) if accept_graphql_content_type => {
+                Ok(graphql::Request::builder().query(query).build().into())
+            }
+            _ => {
+                let expected = if accept_graphql_content_type {
+                    "`Content-Type: application/json` or `Content-Type: application/graphql`"
+                } else {
+                    "`Content-Type: application/json`"
+                };
+                Err((
+                    StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                    format!("Expected request with {}", expected),
+                )
+                    .into_response())
+            }
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl<T, B> FromRequest<B> for GraphQLBody<T>
+where
+    T: DeserializeOwned,
+    B: HttpBody + Send,
+    B::Data: Send,
+    B::Error: Into<BoxError>,
+{
+    type Rejection = Response;
+
+    async fn from_request(req: &mut RequestParts<B>) -> Result<Self, Self::Rejection> {
+        let mime_type = req
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|content_type| content_type.to_str().ok())
+            .and_then(|content_type| content_type.parse::<mime::Mime>().ok());
+        match mime_type {
+            // any parameter, like the charset, is accepted
+            Some(mime_type)
+                if mime_type.essence_str() == APPLICATION_JSON_HEADER_VALUE
+                    || mime_type.suffix() == Some(mime::JSON) =>
+            {
+                Json::<T>::from_request(req)
+                    .await
+                    .map(|Json(request)| GraphQLBody::Json(request))
+                    .map_err(IntoResponse::into_response)
+            }
+            Some(mime_type) if mime_type.essence_str() == APPLICATION_GRAPHQL_HEADER_VALUE => {
+                String::from_request(req)
+                    .await
+                    .map(GraphQLBody::Query)
+                    .map_err(IntoResponse::into_response)
+            }
+            _ => Ok(GraphQLBody::Unsupported),
+        }
+    }
+}
+
 pub(super) async fn handle_post(
     Host(host): Host,
     OriginalUri(uri): OriginalUri,
-    Json(request): Json<graphql::Request>,
+    request: graphql::Request,
     apq: APQLayer,
     service: BoxService<SupergraphRequest, SupergraphResponse, BoxError>,
+    on_disconnect: OnDisconnect,
     header_map: HeaderMap,
 ) -> impl IntoResponse {
     let mut http_request = Request::post(
@@ -102,15 +189,193 @@ pub(super) async fn handle_post(
     .expect("body has already been parsed; qed");
     *http_request.headers_mut() = header_map;
 
-    run_graphql_request(service, apq, http_request)
-        .await
+    run_graphql_request(service, apq, http_request, on_disconnect).await
+}
+
+/// A single GraphQL request, or a batch of requests sent as a JSON array
+pub(super) enum GraphQLRequests {
+    Single(graphql::Request),
+    Batch(Vec<graphql::Request>),
+}
+
+impl<'de> Deserialize<'de> for GraphQLRequests {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        match serde_json::Value::deserialize(deserializer)? {
+            serde_json::Value::Array(requests) => requests
+                .into_iter()
+                .map(serde_json::from_value)
+                .collect::<Result<_, _>>()
+                .map(GraphQLRequests::Batch),
+            request => serde_json::from_value(request).map(GraphQLRequests::Single),
+        }
+        .map_err(D::Error::custom)
+    }
+}
+
+impl From<graphql::Request> for GraphQLRequests {
+    fn from(request: graphql::Request) -> Self {
+        GraphQLRequests::Single(request)
+    }
+}
+
+pub(super) async fn handle_batch_post<F>(
+    Host(host): Host,
+    OriginalUri(uri): OriginalUri,
+    requests: GraphQLRequests,
+    max_size: usize,
+    apq: APQLayer,
+    new_service: F,
+    on_disconnect: OnDisconnect,
+    mut header_map: HeaderMap,
+) -> Response
+where
+    F: Fn() -> BoxService<SupergraphRequest, SupergraphResponse, BoxError>,
+{
+    let requests = match requests {
+        GraphQLRequests::Single(request) => {
+            return handle_post(
+                Host(host),
+                OriginalUri(uri),
+                request,
+                apq,
+                new_service(),
+                on_disconnect,
+                header_map,
+            )
+            .await
+            .into_response()
+        }
+        GraphQLRequests::Batch(requests) => requests,
+    };
+    if requests.is_empty() || requests.len() > max_size {
+        return (
+            StatusCode::BAD_REQUEST,
+            format!(
+                "Invalid GraphQL batch: it must contain between 1 and {} requests",
+                max_size
+            ),
+        )
+            .into_response();
+    }
+
+    let uri = Uri::from_str(&format!("http://{}{}", host, uri))
+        .expect("the URL is already valid because it comes from axum; qed");
+    // Deferred responses cannot be part of a batch
+    header_map.insert(
+        ACCEPT,
+        HeaderValue::from_static(APPLICATION_JSON_HEADER_VALUE),
+    );
+
+    // Each operation goes through the supergraph service on its own,
+    // so that it is planned, executed and reported like any other request
+    let responses = join_all(requests.into_iter().map(|request| {
+        let mut http_request = Request::post(uri.clone())
+            .body(request)
+            .expect("body has already been parsed; qed");
+        *http_request.headers_mut() = header_map.clone();
+        let response = run_graphql_request(
+            new_service(),
+            apq.clone(),
+            http_request,
+            on_disconnect.clone(),
+        );
+        async move { batched_response(response.await).await }
+    }))
+    .await;
+
+    let mut body = Vec::from(&b"["[..]);
+    for (index, response) in responses.iter().enumerate() {
+        if index > 0 {
+            body.push(b',');
+        }
+        body.extend_from_slice(response);
+    }
+    body.push(b']');
+
+    (
+        [(
+            CONTENT_TYPE,
+            HeaderValue::from_static(APPLICATION_JSON_HEADER_VALUE),
+        )],
+        body,
+    )
         .into_response()
 }
 
+/// The JSON body of the response to one operation of a batch,
+/// or a GraphQL error if the router did not answer with JSON
+async fn batched_response(response: Response) -> Bytes {
+    let is_json = response
+        .headers()
+        .get(CONTENT_TYPE)
+        .and_then(|content_type| content_type.to_str().ok())
+        .map(|content_type| content_type.contains(APPLICATION_JSON_HEADER_VALUE))
+        .unwrap_or_default();
+    let message = match hyper::body::to_bytes(response.into_body()).await {
+        Ok(body) if is_json => return body,
+        Ok(body) => String::from_utf8_lossy(&body).into_owned(),
+        Err(e) => format!("could not read the response: {}", e),
+    };
+    let response = graphql::Response::builder()
+        .error(graphql::Error::builder().message(message).build())
+        .build();
+    serde_json::to_vec(&response)
+        .expect("JSON serialization should not fail")
+        .into()
+}
+
+/// Called when a client disconnects before receiving the whole response
+pub(super) type OnDisconnect = Arc<dyn Fn() + Send + Sync>;
+
+/// Calls the disconnection callback when dropped before the whole response was produced.
+///
+/// Hyper drops the request handler, or the body of a streamed response, when the client
+/// disconnects. A response that the router ends early, like over a size limit, is still complete
+/// from here, so it is not counted.
+struct ClientConnection(Option<OnDisconnect>);
+
+impl ClientConnection {
+    fn finished(mut self) {
+        self.0 = None;
+    }
+}
+
+impl Drop for ClientConnection {
+    fn drop(&mut self) {
+        if let Some(on_disconnect) = self.0.take() {
+            on_disconnect();
+        }
+    }
+}
+
 async fn run_graphql_request<RS>(
     service: RS,
     apq: APQLayer,
     http_request: Request<graphql::Request>,
+    on_disconnect: OnDisconnect,
+) -> Response
+where
+    RS: Service<SupergraphRequest, Response = SupergraphResponse, Error = BoxError> + Send,
+{
+    let mut connection = Some(ClientConnection(Some(on_disconnect)));
+    let response = graphql_response(service, apq, http_request, &mut connection)
+        .await
+        .into_response();
+    // the response is complete, unless its body is a stream that took the connection
+    if let Some(connection) = connection {
+        connection.finished();
+    }
+    response
+}
+
+async fn graphql_response<RS>(
+    service: RS,
+    apq: APQLayer,
+    http_request: Request<graphql::Request>,
+    connection: &mut Option<ClientConnection>,
 ) -> impl IntoResponse
 where
     RS: Service<SupergraphRequest, Response = SupergraphResponse, Error = BoxError> + Send,
@@ -223,6 +488,14 @@ where
                                         Ok::<_, BoxError>(buf.into())
                                     }),
                                 );
+                                // the whole response was produced once the stream ends
+                                let mut connection = connection.take();
+                                let body = body.chain(poll_fn(move |_| {
+                                    if let Some(connection) = connection.take() {
+                                        connection.finished();
+                                    }
+                                    Poll::Ready(None)
+                                }));
 
                                 (parts, StreamBody::new(body)).into_response()
                             } else {
@@ -2,23 +2,40 @@
 
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::net::SocketAddr;
+use std::pin::Pin;
 use std::sync::Arc;
+use std::sync::Mutex;
+use std::task::Context;
+use std::task::Poll;
 use std::time::Duration;
 use std::time::Instant;
 
+use axum::middleware;
 use axum::response::*;
 use axum::Router;
 use futures::channel::oneshot;
 use futures::prelude::*;
 use hyper::server::conn::Http;
 use multimap::MultiMap;
+use pin_project_lite::pin_project;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncWrite;
+use tokio::io::ReadBuf;
 use tokio::net::TcpListener;
+use tokio::net::TcpSocket;
+use tokio::net::TcpStream;
 #[cfg(unix)]
 use tokio::net::UnixListener;
 use tokio::sync::Notify;
 
+use super::connection_limit::limit_forwarded_clients;
+use super::connection_limit::ConnectionGuard;
+use super::connection_limit::ConnectionLimiter;
 use crate::configuration::Configuration;
+use crate::configuration::ConnectionLimit;
 use crate::configuration::ListenAddr;
+use crate::configuration::Socket;
 use crate::http_server_factory::Listener;
 use crate::http_server_factory::NetworkStream;
 use crate::router::ApolloRouterError;
@@ -139,9 +156,69 @@ pub(super) fn ensure_listenaddrs_consistency(
     Ok(())
 }
 
+/// Binds a TCP listener with the socket options of the configuration
+pub(super) fn bind_tcp_listener(
+    addr: SocketAddr,
+    options: &Socket,
+) -> std::io::Result<TcpListener> {
+    let socket = if addr.is_ipv4() {
+        TcpSocket::new_v4()?
+    } else {
+        TcpSocket::new_v6()?
+    };
+    // on Windows, SO_REUSEADDR allows several sockets to listen on the same port
+    #[cfg(not(windows))]
+    socket.set_reuseaddr(options.reuse_address)?;
+    socket.bind(addr)?;
+    socket.listen(options.backlog)
+}
+
+/// The router serving an accepted connection and the count of the connection in the limit of
+/// its IP, or `None` if the IP is over its limit
+fn admit(
+    limiter: &ConnectionLimiter,
+    stream: &NetworkStream,
+    app: axum::Router,
+) -> Option<(axum::Router, Option<ConnectionGuard>)> {
+    let ip = match stream {
+        NetworkStream::Tcp(stream) => match stream.peer_addr() {
+            Ok(addr) => addr.ip(),
+            Err(_) => return Some((app, None)),
+        },
+        #[cfg(unix)]
+        NetworkStream::Unix(_) => return Some((app, None)),
+    };
+
+    // the connections of the proxies carry the requests of many clients, which are limited
+    // separately
+    if limiter.is_enabled() && limiter.is_trusted_proxy(&ip) {
+        let limiter = limiter.clone();
+        let app = app.layer(middleware::from_fn(
+            move |request: http::Request<axum::body::Body>, next: middleware::Next<_>| {
+                limit_forwarded_clients(request, next, limiter.clone())
+            },
+        ));
+        return Some((app, None));
+    }
+
+    match limiter.acquire(ip) {
+        Some(guard) => Some((app, Some(guard))),
+        None => {
+            tracing::debug!("closing a connection of {}: too many open connections", ip);
+            None
+        }
+    }
+}
+
+/// Applies the socket options of the configuration to an accepted connection
+fn configure_stream(stream: &TcpStream, options: &Socket) -> std::io::Result<()> {
+    stream.set_nodelay(options.nodelay)
+}
+
 pub(super) async fn get_extra_listeners(
     previous_listeners: Vec<(ListenAddr, Listener)>,
     mut extra_routers: MultiMap<ListenAddr, Router>,
+    socket_options: &Socket,
 ) -> Result<Vec<((ListenAddr, Listener), axum::Router)>, ApolloRouterError> {
     let mut listeners_and_routers: Vec<((ListenAddr, Listener), axum::Router)> =
         Vec::with_capacity(extra_routers.len());
@@ -164,8 +241,7 @@ pub(super) async fn get_extra_listeners(
         #[cfg_attr(not(unix), allow(unused_mut))]
         let listener = match listen_addr.clone() {
             ListenAddr::SocketAddr(addr) => Listener::Tcp(
-                TcpListener::bind(addr)
-                    .await
+                bind_tcp_listener(addr, socket_options)
                     .map_err(ApolloRouterError::ServerCreationError)?,
             ),
             #[cfg(unix)]
@@ -187,7 +263,11 @@ pub(super) async fn get_extra_listeners(
 pub(super) fn serve_router_on_listen_addr(
     mut listener: Listener,
     router: axum::Router,
+    socket_options: Socket,
+    idle_timeout: Duration,
+    connection_limit: ConnectionLimit,
 ) -> (impl Future<Output = Listener>, oneshot::Sender<()>) {
+    let limiter = ConnectionLimiter::new(connection_limit);
     let (shutdown_sender, shutdown_receiver) = oneshot::channel::<()>();
     // this server reproduces most of hyper::server::Server's behaviour
     // we select over the stop_listen_receiver channel and the listener's
@@ -208,6 +288,7 @@ pub(super) fn serve_router_on_listen_addr(
                 res = listener.accept() => {
                     let app = router.clone();
                     let connection_shutdown = connection_shutdown.clone();
+                    let socket_options = socket_options.clone();
 
                     match res {
                         Ok(res) => {
@@ -216,55 +297,25 @@ pub(super) fn serve_router_on_listen_addr(
                                 max_open_file_warning = None;
                             }
 
+                            // the connection is counted until it is closed
+                            let (app, guard) = match admit(&limiter, &res, app) {
+                                Some(admitted) => admitted,
+                                None => continue,
+                            };
+
                             tokio::task::spawn(async move {
+                                let _guard = guard;
                                 match res {
                                     NetworkStream::Tcp(stream) => {
-                                        stream
-                                            .set_nodelay(true)
+                                        configure_stream(&stream, &socket_options)
                                             .expect(
                                                 "this should not fail unless the socket is invalid",
                                             );
-                                            let connection = Http::new()
-                                            .http1_keep_alive(true)
-                                            .serve_connection(stream, app);
-
-                                        tokio::pin!(connection);
-                                        tokio::select! {
-                                            // the connection finished first
-                                            _res = &mut connection => {
-                                            }
-                                            // the shutdown receiver was triggered first,
-                                            // so we tell the connection to do a graceful shutdown
-                                            // on the next request, then we wait for it to finish
-                                            _ = connection_shutdown.notified() => {
-                                                let c = connection.as_mut();
-                                                c.graceful_shutdown();
-
-                                                let _= connection.await;
-                                            }
-                                        }
+                                        serve_connection(stream, app, connection_shutdown, idle_timeout).await;
                                     }
                                     #[cfg(unix)]
                                     NetworkStream::Unix(stream) => {
-                                        let connection = Http::new()
-                                        .http1_keep_alive(true)
-                                        .serve_connection(stream, app);
-
-                                        tokio::pin!(connection);
-                                        tokio::select! {
-                                            // the connection finished first
-                                            _res = &mut connection => {
-                                            }
-                                            // the shutdown receiver was triggered first,
-                                            // so we tell the connection to do a graceful shutdown
-                                            // on the next request, then we wait for it to finish
-                                            _ = connection_shutdown.notified() => {
-                                                let c = connection.as_mut();
-                                                c.graceful_shutdown();
-
-                                                let _= connection.await;
-                                            }
-                                        }
+                                        serve_connection(stream, app, connection_shutdown, idle_timeout).await;
                                     }
                                 }
                             });
@@ -356,12 +407,125 @@ pub(super) fn serve_router_on_listen_addr(
     (server, shutdown_sender)
 }
 
+async fn serve_connection<S>(
+    stream: S,
+    app: axum::Router,
+    connection_shutdown: Arc<Notify>,
+    idle_timeout: Duration,
+) where
+    S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+{
+    let stream = ActivityTracker::new(stream);
+    let last_activity = stream.last_activity.clone();
+    let connection = Http::new()
+        .http1_keep_alive(true)
+        .serve_connection(stream, app);
+
+    tokio::pin!(connection);
+    tokio::select! {
+        // the connection finished first
+        _res = &mut connection => {
+        }
+        // the shutdown receiver was triggered first,
+        // so we tell the connection to do a graceful shutdown
+        // on the next request, then we wait for it to finish
+        _ = connection_shutdown.notified() => {
+            let c = connection.as_mut();
+            c.graceful_shutdown();
+
+            let _= connection.await;
+        }
+        // nothing was read or written for too long: an idle keep-alive
+        // connection is closed right away, while a request in flight
+        // still gets its response
+        _ = idle(last_activity, idle_timeout) => {
+            let c = connection.as_mut();
+            c.graceful_shutdown();
+
+            let _= connection.await;
+        }
+    }
+}
+
+/// Resolves once the connection saw no activity for `idle_timeout`, never if it is zero
+async fn idle(last_activity: Arc<Mutex<Instant>>, idle_timeout: Duration) {
+    if idle_timeout.is_zero() {
+        return future::pending().await;
+    }
+
+    loop {
+        let deadline = *last_activity.lock().expect("lock poisoned") + idle_timeout;
+        if deadline <= Instant::now() {
+            return;
+        }
+        tokio::time::sleep_until(deadline.into()).await;
+    }
+}
+
+pin_project! {
+    /// Records the time of the last read or write on a connection
+    struct ActivityTracker<S> {
+        #[pin]
+        inner: S,
+        last_activity: Arc<Mutex<Instant>>,
+    }
+}
+
+impl<S> ActivityTracker<S> {
+    fn new(inner: S) -> Self {
+        Self {
+            inner,
+            last_activity: Arc::new(Mutex::new(Instant::now())),
+        }
+    }
+}
+
+impl<S: AsyncRead> AsyncRead for ActivityTracker<S> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        let this = self.project();
+        let res = this.inner.poll_read(cx, buf);
+        if res.is_ready() {
+            *this.last_activity.lock().expect("lock poisoned") = Instant::now();
+        }
+        res
+    }
+}
+
+impl<S: AsyncWrite> AsyncWrite for ActivityTracker<S> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        let this = self.project();
+        let res = this.inner.poll_write(cx, buf);
+        if res.is_ready() {
+            *this.last_activity.lock().expect("lock poisoned") = Instant::now();
+        }
+        res
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        self.project().inner.poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        self.project().inner.poll_shutdown(cx)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::net::SocketAddr;
     use std::str::FromStr;
 
     use axum::BoxError;
+    use tokio::io::AsyncReadExt;
+    use tokio::io::AsyncWriteExt;
     use tower::service_fn;
     use tower::ServiceExt;
 
@@ -372,6 +536,131 @@ mod tests {
     use crate::configuration::Supergraph;
     use crate::services::transport;
 
+    #[tokio::test]
+    async fn it_applies_socket_options() {
+        for nodelay in [true, false] {
+            let options = Socket::builder().nodelay(nodelay).backlog(16).build();
+            let listener =
+                bind_tcp_listener(SocketAddr::from_str("127.0.0.1:0").unwrap(), &options).unwrap();
+            let client = TcpStream::connect(listener.local_addr().unwrap());
+            let (accepted, client) = tokio::join!(listener.accept(), client);
+            let (stream, _) = accepted.unwrap();
+            client.unwrap();
+
+            configure_stream(&stream, &options).unwrap();
+            assert_eq!(stream.nodelay().unwrap(), nodelay);
+        }
+    }
+
+    #[tokio::test]
+    async fn it_closes_idle_connections() {
+        let listener = bind_tcp_listener(
+            SocketAddr::from_str("127.0.0.1:0").unwrap(),
+            &Socket::default(),
+        )
+        .unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (server, _shutdown_sender) = serve_router_on_listen_addr(
+            Listener::Tcp(listener),
+            axum::Router::new(),
+            Socket::default(),
+            Duration::from_millis(100),
+            ConnectionLimit::default(),
+        );
+        tokio::task::spawn(server);
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let mut buf = [0u8; 1];
+        let read = tokio::time::timeout(Duration::from_secs(5), client.read(&mut buf))
+            .await
+            .expect("the idle connection should have been closed");
+        assert_eq!(read.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn it_keeps_idle_connections_without_timeout() {
+        let listener = bind_tcp_listener(
+            SocketAddr::from_str("127.0.0.1:0").unwrap(),
+            &Socket::default(),
+        )
+        .unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (server, _shutdown_sender) = serve_router_on_listen_addr(
+            Listener::Tcp(listener),
+            axum::Router::new(),
+            Socket::default(),
+            Duration::ZERO,
+            ConnectionLimit::default(),
+        );
+        tokio::task::spawn(server);
+
+        let mut client = TcpStream::connect(addr).await.unwrap();
+        let mut buf = [0u8; 1];
+        assert!(
+            tokio::time::timeout(Duration::from_millis(300), client.read(&mut buf))
+                .await
+                .is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn it_limits_the_connections_per_ip() {
+        let listener = bind_tcp_listener(
+            SocketAddr::from_str("127.0.0.1:0").unwrap(),
+            &Socket::default(),
+        )
+        .unwrap();
+        let addr = listener.local_addr().unwrap();
+        let (server, _shutdown_sender) = serve_router_on_listen_addr(
+            Listener::Tcp(listener),
+            axum::Router::new(),
+            Socket::default(),
+            Duration::ZERO,
+            ConnectionLimit::builder()
+                .max_connections_per_ip(std::num::NonZeroUsize::new(3).unwrap())
+                .build(),
+        );
+        tokio::task::spawn(server);
+
+        // an admitted connection answers a request, the others are closed without an answer
+        async fn answers(client: &mut TcpStream) -> bool {
+            if client
+                .write_all(b"GET / HTTP/1.1\r\nhost: localhost\r\n\r\n")
+                .await
+                .is_err()
+            {
+                return false;
+            }
+            let mut buf = [0u8; 1024];
+            matches!(client.read(&mut buf).await, Ok(read) if read > 0)
+        }
+
+        let mut clients = Vec::new();
+        for _ in 0..10 {
+            clients.push(TcpStream::connect(addr).await.unwrap());
+        }
+        let mut answered = 0;
+        for client in clients.iter_mut() {
+            if answers(client).await {
+                answered += 1;
+            }
+        }
+        assert_eq!(answered, 3);
+        for client in clients.iter_mut().take(3) {
+            assert!(answers(client).await);
+        }
+
+        // closing a connection lets a new one in, once the server sees it closed
+        clients.truncate(2);
+        tokio::time::timeout(Duration::from_secs(10), async {
+            while !answers(&mut TcpStream::connect(addr).await.unwrap()).await {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .expect("a new connection should have been admitted");
+    }
+
     #[tokio::test]
     async fn it_makes_sure_same_listenaddrs_are_accepted() {
         let configuration = Configuration::fake_builder().build().unwrap();
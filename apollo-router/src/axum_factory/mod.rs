@@ -1,5 +1,6 @@
 //! axum factory is useful to create an [`AxumHttpServerFactory`] which implements [`crate::http_server_factory::HttpServerFactory`]
 mod axum_http_server_factory;
+mod connection_limit;
 mod handlers;
 mod listeners;
 #[cfg(test)]
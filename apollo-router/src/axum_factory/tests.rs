@@ -46,9 +46,12 @@ use tower::ServiceExt;
 
 use super::*;
 use crate::configuration::cors::Cors;
+use crate::configuration::Batching;
+use crate::configuration::Compression;
 use crate::configuration::HealthCheck;
 use crate::configuration::Homepage;
 use crate::configuration::Sandbox;
+use crate::configuration::Server;
 use crate::configuration::Supergraph;
 use crate::graphql;
 use crate::http_ext;
@@ -58,6 +61,7 @@ use crate::json_ext::Path;
 use crate::router_factory::Endpoint;
 use crate::router_factory::SupergraphServiceFactory;
 use crate::services::new_service::NewService;
+use crate::services::subgraph;
 use crate::services::transport;
 use crate::services::SupergraphRequest;
 use crate::services::SupergraphResponse;
@@ -121,6 +125,7 @@ type MockSupergraphServiceType = tower_test::mock::Mock<SupergraphRequest, Super
 #[derive(Clone)]
 struct TestSupergraphServiceFactory {
     inner: MockSupergraphServiceType,
+    disconnects: Arc<AtomicU32>,
 }
 
 impl NewService<SupergraphRequest> for TestSupergraphServiceFactory {
@@ -142,6 +147,10 @@ impl SupergraphServiceFactory for TestSupergraphServiceFactory {
     fn web_endpoints(&self) -> MultiMap<ListenAddr, Endpoint> {
         MultiMap::new()
     }
+
+    fn record_client_disconnect(&self) {
+        self.disconnects.fetch_add(1, Ordering::SeqCst);
+    }
 }
 
 async fn init(mut mock: MockSupergraphService) -> (HttpServerHandle, Client) {
@@ -162,6 +171,7 @@ async fn init(mut mock: MockSupergraphService) -> (HttpServerHandle, Client) {
         .create(
             TestSupergraphServiceFactory {
                 inner: service.into_inner(),
+                disconnects: Default::default(),
             },
             Arc::new(
                 Configuration::fake_builder()
@@ -223,6 +233,7 @@ pub(super) async fn init_with_config(
         .create(
             TestSupergraphServiceFactory {
                 inner: service.into_inner(),
+                disconnects: Default::default(),
             },
             Arc::new(conf),
             None,
@@ -265,6 +276,7 @@ async fn init_unix(
         .create(
             TestSupergraphServiceFactory {
                 inner: service.into_inner(),
+                disconnects: Default::default(),
             },
             Arc::new(
                 Configuration::fake_builder()
@@ -434,6 +446,96 @@ async fn it_compress_response_body() -> Result<(), ApolloRouterError> {
     Ok(())
 }
 
+#[tokio::test]
+async fn it_does_not_compress_small_response_body() -> Result<(), ApolloRouterError> {
+    let expected_response = graphql::Response::builder()
+        .data(json!({"response": "yayyyyyyyyyyyyyyyyyyyyyyyyyyyyyyyyyyyyyyyyyyy"}))
+        .build();
+    let example_response = expected_response.clone();
+    let mut expectations = MockSupergraphService::new();
+    expectations
+        .expect_service_call()
+        .times(1)
+        .returning(move |_req| {
+            let example_response = example_response.clone();
+            Ok(SupergraphResponse::new_from_graphql_response(
+                example_response,
+                Context::new(),
+            ))
+        });
+    let conf = Configuration::fake_builder()
+        .server(
+            Server::builder()
+                .compression(Compression::builder().min_size(1024).build())
+                .build(),
+        )
+        .build()
+        .unwrap();
+    let (server, client) = init_with_config(expectations, conf, MultiMap::new()).await?;
+    let url = format!("{}/", server.graphql_listen_address().as_ref().unwrap());
+
+    let response = client
+        .post(url.as_str())
+        .header(ACCEPT_ENCODING, HeaderValue::from_static("gzip"))
+        .body(json!({ "query": "query" }).to_string())
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    assert_eq!(response.headers().get(&CONTENT_ENCODING), None);
+    let graphql_resp: graphql::Response = response.json().await.unwrap();
+    assert_eq!(graphql_resp, expected_response);
+
+    server.shutdown().await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn it_does_not_compress_response_body_when_disabled() -> Result<(), ApolloRouterError> {
+    let expected_response = graphql::Response::builder()
+        .data(json!({"response": "yayyyyyyyyyyyyyyyyyyyyyyyyyyyyyyyyyyyyyyyyyyy"})) // Body bigger than 32
+        .build();
+    let example_response = expected_response.clone();
+    let mut expectations = MockSupergraphService::new();
+    expectations
+        .expect_service_call()
+        .times(1)
+        .returning(move |_req| {
+            let example_response = example_response.clone();
+            Ok(SupergraphResponse::new_from_graphql_response(
+                example_response,
+                Context::new(),
+            ))
+        });
+    let conf = Configuration::fake_builder()
+        .server(
+            Server::builder()
+                .compression(Compression::builder().enabled(false).build())
+                .build(),
+        )
+        .build()
+        .unwrap();
+    let (server, client) = init_with_config(expectations, conf, MultiMap::new()).await?;
+    let url = format!("{}/", server.graphql_listen_address().as_ref().unwrap());
+
+    let response = client
+        .post(url.as_str())
+        .header(ACCEPT_ENCODING, HeaderValue::from_static("gzip, br"))
+        .body(json!({ "query": "query" }).to_string())
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    assert_eq!(response.headers().get(&CONTENT_ENCODING), None);
+    let graphql_resp: graphql::Response = response.json().await.unwrap();
+    assert_eq!(graphql_resp, expected_response);
+
+    server.shutdown().await?;
+    Ok(())
+}
+
 #[tokio::test]
 async fn it_decompress_request_body() -> Result<(), ApolloRouterError> {
     let original_body = json!({ "query": "query" });
@@ -566,6 +668,137 @@ async fn response() -> Result<(), ApolloRouterError> {
     Ok(())
 }
 
+#[tokio::test]
+async fn batched_response() -> Result<(), ApolloRouterError> {
+    let mut expectations = MockSupergraphService::new();
+    // every operation of the batch is a separate supergraph request,
+    // and is counted in metrics and studio reports on its own
+    expectations
+        .expect_service_call()
+        .times(2)
+        .returning(move |req| {
+            let query = req.supergraph_request.body().query.clone().unwrap();
+            Ok(SupergraphResponse::new_from_graphql_response(
+                graphql::Response::builder()
+                    .data(json!({ "query": query }))
+                    .build(),
+                Context::new(),
+            ))
+        });
+    let conf = Configuration::fake_builder()
+        .batching(Batching::builder().enabled(true).max_size(2).build())
+        .build()
+        .unwrap();
+    let (server, client) = init_with_config(expectations, conf, MultiMap::new()).await?;
+    let url = format!("{}/", server.graphql_listen_address().as_ref().unwrap());
+
+    let response = client
+        .post(url.as_str())
+        .body(json!([{ "query": "query a" }, { "query": "query b" }]).to_string())
+        .send()
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+    assert_eq!(
+        response.json::<Vec<graphql::Response>>().await.unwrap(),
+        vec![
+            graphql::Response::builder()
+                .data(json!({ "query": "query a" }))
+                .build(),
+            graphql::Response::builder()
+                .data(json!({ "query": "query b" }))
+                .build(),
+        ]
+    );
+
+    // batches larger than the maximum size are rejected before execution
+    let response = client
+        .post(url.as_str())
+        .body(json!([{ "query": "a" }, { "query": "b" }, { "query": "c" }]).to_string())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+
+    server.shutdown().await?;
+    Ok(())
+}
+
+#[tokio::test]
+async fn batched_operations_are_counted_in_metrics() {
+    let mut service = TestHarness::builder()
+        .configuration_json(json!({
+            "batching": { "enabled": true, "max_size": 2 },
+            "telemetry": {
+                "metrics": {
+                    "common": { "service_name": "apollo-router" },
+                    // served by the main router, which listens there by default
+                    "prometheus": { "enabled": true, "listen": "127.0.0.1:4000" }
+                }
+            }
+        }))
+        .unwrap()
+        .build_http_service()
+        .await
+        .unwrap();
+
+    // the canned query, answered by the canned subgraphs
+    let query = SupergraphRequest::canned_builder()
+        .build()
+        .unwrap()
+        .supergraph_request
+        .into_body();
+    let request = http::Request::post("http://127.0.0.1:4000/")
+        .header(CONTENT_TYPE, "application/json")
+        .header(ACCEPT, "application/json")
+        .body(json!([query, query]).to_string().into())
+        .unwrap();
+    let response = service.ready().await.unwrap().call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let responses: Vec<graphql::Response> = serde_json::from_slice(&body).unwrap();
+    assert_eq!(responses.len(), 2);
+    assert!(responses.iter().all(|response| response.errors.is_empty()));
+
+    let request = http::Request::get("http://127.0.0.1:4000/metrics")
+        .body(hyper::Body::empty())
+        .unwrap();
+    let response = service.ready().await.unwrap().call(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let metrics = String::from_utf8_lossy(&body);
+    // each operation of the batch is counted as a request
+    assert!(
+        metrics.contains(r#"apollo_router_http_requests_total{operation_kind="query",service_name="apollo-router",status="200"} 2"#),
+        "{}",
+        metrics
+    );
+    assert!(
+        metrics.contains(r#"apollo_router_http_request_duration_seconds_count{operation_kind="query",service_name="apollo-router",status="200"} 2"#),
+        "{}",
+        metrics
+    );
+}
+
+#[tokio::test]
+async fn batched_request_without_batching() -> Result<(), ApolloRouterError> {
+    let expectations = MockSupergraphService::new();
+    let (server, client) = init(expectations).await;
+    let url = format!("{}/", server.graphql_listen_address().as_ref().unwrap());
+
+    let response = client
+        .post(url.as_str())
+        .body(json!([{ "query": "query a" }, { "query": "query b" }]).to_string())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+    server.shutdown().await?;
+    Ok(())
+}
+
 #[tokio::test]
 async fn bad_response() -> Result<(), ApolloRouterError> {
     let expectations = MockSupergraphService::new();
@@ -1057,6 +1290,119 @@ async fn it_send_bad_content_type() -> Result<(), ApolloRouterError> {
         .unwrap();
 
     assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE,);
+    assert_eq!(
+        response.text().await.unwrap(),
+        "Expected request with `Content-Type: application/json`"
+    );
+
+    server.shutdown().await
+}
+
+#[tokio::test]
+async fn it_accepts_json_content_type_with_charset() -> Result<(), ApolloRouterError> {
+    let mut expectations = MockSupergraphService::new();
+    expectations
+        .expect_service_call()
+        .times(1)
+        .withf(|req| req.supergraph_request.body().query.as_deref() == Some("{ me }"))
+        .returning(|_| {
+            Ok(SupergraphResponse::new_from_graphql_response(
+                graphql::Response::builder()
+                    .data(json!({"response": "yay"}))
+                    .build(),
+                Context::new(),
+            ))
+        });
+    let (server, client) = init(expectations).await;
+    let url = format!("{}/", server.graphql_listen_address().as_ref().unwrap());
+
+    let response = client
+        .post(url.as_str())
+        .header(CONTENT_TYPE, "application/json; charset=utf-8")
+        .body(json!({ "query": "{ me }" }).to_string())
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+
+    server.shutdown().await
+}
+
+#[tokio::test]
+async fn it_accepts_graphql_content_type() -> Result<(), ApolloRouterError> {
+    let mut expectations = MockSupergraphService::new();
+    expectations
+        .expect_service_call()
+        .times(1)
+        .withf(|req| {
+            let body = req.supergraph_request.body();
+            body.query.as_deref() == Some("query Me { me }") && body.variables.is_empty()
+        })
+        .returning(|_| {
+            Ok(SupergraphResponse::new_from_graphql_response(
+                graphql::Response::builder()
+                    .data(json!({"response": "yay"}))
+                    .build(),
+                Context::new(),
+            ))
+        });
+    let conf = Configuration::fake_builder()
+        .supergraph(
+            Supergraph::fake_builder()
+                .accept_graphql_content_type(true)
+                .build(),
+        )
+        .build()
+        .unwrap();
+    let (server, client) = init_with_config(expectations, conf, MultiMap::new()).await?;
+    let url = format!("{}/", server.graphql_listen_address().as_ref().unwrap());
+
+    let response = client
+        .post(url.as_str())
+        .header(CONTENT_TYPE, "application/graphql")
+        .body("query Me { me }")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.json::<graphql::Response>().await.unwrap(),
+        graphql::Response::builder()
+            .data(json!({"response": "yay"}))
+            .build(),
+    );
+
+    // other content types are still rejected
+    let response = client
+        .post(url.as_str())
+        .header(CONTENT_TYPE, "text/plain")
+        .body("query Me { me }")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    assert_eq!(
+        response.text().await.unwrap(),
+        "Expected request with `Content-Type: application/json` or `Content-Type: application/graphql`"
+    );
+
+    server.shutdown().await
+}
+
+#[tokio::test]
+async fn it_rejects_graphql_content_type_by_default() -> Result<(), ApolloRouterError> {
+    let expectations = MockSupergraphService::new();
+    let (server, client) = init(expectations).await;
+    let url = format!("{}/", server.graphql_listen_address().as_ref().unwrap());
+
+    let response = client
+        .post(url.as_str())
+        .header(CONTENT_TYPE, "application/graphql")
+        .body("query Me { me }")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
 
     server.shutdown().await
 }
@@ -1143,6 +1489,61 @@ async fn it_doesnt_display_disabled_homepage() -> Result<(), ApolloRouterError>
     server.shutdown().await
 }
 
+#[test(tokio::test)]
+async fn it_displays_homepage() -> Result<(), ApolloRouterError> {
+    let expectations = MockSupergraphService::new();
+    let conf = Configuration::fake_builder().build().unwrap();
+    let (server, client) = init_with_config(expectations, conf, MultiMap::new()).await?;
+    let response = client
+        .get(&format!(
+            "{}/",
+            server.graphql_listen_address().as_ref().unwrap()
+        ))
+        .header(ACCEPT, "text/html")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.bytes().await.unwrap(),
+        Homepage::fake_builder().build().display_page()
+    );
+
+    server.shutdown().await
+}
+
+#[test(tokio::test)]
+async fn it_displays_custom_homepage() -> Result<(), ApolloRouterError> {
+    let expectations = MockSupergraphService::new();
+    let conf = Configuration::fake_builder()
+        .homepage(
+            Homepage::fake_builder()
+                .html("<html><body>custom landing page</body></html>")
+                .build(),
+        )
+        .build()
+        .unwrap();
+    let (server, client) = init_with_config(expectations, conf, MultiMap::new()).await?;
+    let response = client
+        .get(&format!(
+            "{}/",
+            server.graphql_listen_address().as_ref().unwrap()
+        ))
+        .header(ACCEPT, "text/html")
+        .send()
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(
+        response.text().await.unwrap(),
+        "<html><body>custom landing page</body></html>"
+    );
+
+    server.shutdown().await
+}
+
 #[test(tokio::test)]
 async fn it_answers_to_custom_endpoint() -> Result<(), ApolloRouterError> {
     let expectations = MockSupergraphService::new();
@@ -1646,6 +2047,79 @@ async fn multipart_response_shape_with_one_chunk() -> Result<(), ApolloRouterErr
     server.shutdown().await
 }
 
+#[tokio::test]
+async fn client_disconnections_are_recorded() -> Result<(), ApolloRouterError> {
+    let (service, mut handle) = tower_test::mock::spawn();
+    let disconnects = Arc::new(AtomicU32::new(0));
+    let server = AxumHttpServerFactory::new()
+        .create(
+            TestSupergraphServiceFactory {
+                inner: service.into_inner(),
+                disconnects: disconnects.clone(),
+            },
+            Arc::new(Configuration::fake_builder().build().unwrap()),
+            None,
+            vec![],
+            MultiMap::new(),
+        )
+        .await?;
+    let url = format!("{}/", server.graphql_listen_address().as_ref().unwrap());
+    let deferred_request = || {
+        reqwest::Client::new()
+            .post(&url)
+            .header(CONTENT_TYPE, "application/json")
+            .header(ACCEPT, MULTIPART_DEFER_CONTENT_TYPE)
+            .body(json!({ "query": "query { test ... @defer { other } }" }).to_string())
+            .send()
+    };
+    let first_part = || {
+        graphql::Response::builder()
+            .data(json!({ "test": "hello" }))
+            .has_next(true)
+            .build()
+    };
+
+    // the router ends the response before its last part, like over a size limit
+    let response = tokio::spawn(deferred_request());
+    let (_request, responder) = handle.next_request().await.unwrap();
+    responder.send_response(SupergraphResponse::new_from_response(
+        http::Response::builder()
+            .status(200)
+            .body(stream::iter(vec![first_part()]).boxed())
+            .unwrap(),
+        Context::new(),
+    ));
+    let response = response.await.unwrap().unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    response.bytes().await.unwrap();
+    assert_eq!(disconnects.load(Ordering::SeqCst), 0);
+
+    // the client disconnects while the router waits for a deferred part
+    let (stream_alive, stream_dropped) = tokio::sync::oneshot::channel::<()>();
+    let response = tokio::spawn(deferred_request());
+    let (_request, responder) = handle.next_request().await.unwrap();
+    let body = stream::iter(vec![first_part()])
+        .chain(stream::pending())
+        .map(move |response| {
+            let _stream_alive = &stream_alive;
+            response
+        })
+        .boxed();
+    responder.send_response(SupergraphResponse::new_from_response(
+        http::Response::builder().status(200).body(body).unwrap(),
+        Context::new(),
+    ));
+    let mut response = response.await.unwrap().unwrap();
+    assert!(response.chunk().await.unwrap().is_some());
+    drop(response);
+
+    // the router stops waiting for the deferred part and records the disconnection
+    assert!(stream_dropped.await.is_err());
+    assert_eq!(disconnects.load(Ordering::SeqCst), 1);
+
+    server.shutdown().await
+}
+
 #[tokio::test]
 async fn it_supports_server_restart() {
     let configuration = Arc::new(
@@ -1664,6 +2138,7 @@ async fn it_supports_server_restart() {
 
     let supergraph_service_factory = TestSupergraphServiceFactory {
         inner: service.into_inner(),
+        disconnects: Default::default(),
     };
 
     let server = server_factory
@@ -1960,6 +2435,81 @@ async fn test_defer_is_not_buffered() {
     assert_eq!(counts, [1, 2]);
 }
 
+#[tokio::test]
+async fn deferred_execution_stops_when_the_client_disconnects() {
+    // notifies when the fetch is dropped
+    struct Fetching(Arc<tokio::sync::Notify>);
+    impl Drop for Fetching {
+        fn drop(&mut self) {
+            self.0.notify_one();
+        }
+    }
+
+    let started = Arc::new(tokio::sync::Notify::new());
+    let cancelled = Arc::new(tokio::sync::Notify::new());
+    let (fetch_started, fetch_cancelled) = (started.clone(), cancelled.clone());
+    let server = TestHarness::builder()
+        .configuration_json(json!({
+            "supergraph": { "listen": "127.0.0.1:0" },
+            "health-check": { "enabled": false }
+        }))
+        .unwrap()
+        .subgraph_hook(move |name, service| match name {
+            "products" => service_fn(|request: subgraph::Request| async move {
+                Ok::<_, BoxError>(
+                    subgraph::Response::fake_builder()
+                        .data(json!({
+                            "topProducts": [{ "__typename": "Product", "upc": "1", "name": "Table" }]
+                        }))
+                        .context(request.context)
+                        .build(),
+                )
+            })
+            .boxed(),
+            // the deferred fetch never completes
+            "reviews" => {
+                let (fetch_started, fetch_cancelled) =
+                    (fetch_started.clone(), fetch_cancelled.clone());
+                service_fn(move |_request: subgraph::Request| {
+                    let fetching = Fetching(fetch_cancelled.clone());
+                    fetch_started.notify_one();
+                    async move {
+                        let _fetching = fetching;
+                        futures::future::pending::<Result<subgraph::Response, BoxError>>().await
+                    }
+                })
+                .boxed()
+            }
+            _ => service,
+        })
+        .build_http_server()
+        .await
+        .unwrap();
+
+    let url = format!("{}/", server.graphql_listen_address().as_ref().unwrap());
+    let mut response = reqwest::Client::new()
+        .post(&url)
+        .header(CONTENT_TYPE, "application/json")
+        .header(ACCEPT, MULTIPART_DEFER_CONTENT_TYPE)
+        .body(
+            json!({ "query": "{ topProducts { name ... @defer { reviews { id } } } }" })
+                .to_string(),
+        )
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let first = response.chunk().await.unwrap().unwrap();
+    assert!(std::str::from_utf8(&first).unwrap().contains("Table"));
+    started.notified().await;
+
+    // the client disconnects, which cancels the deferred fetch
+    drop(response);
+    cancelled.notified().await;
+
+    server.shutdown().await.unwrap();
+}
+
 #[tokio::test]
 #[cfg(unix)]
 async fn listening_to_unix_socket() {
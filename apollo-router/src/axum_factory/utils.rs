@@ -24,7 +24,6 @@ use mediatype::names::TEXT;
 use mediatype::MediaType;
 use mediatype::MediaTypeList;
 use mediatype::ReadParams;
-use opentelemetry::global;
 use opentelemetry::trace::SpanKind;
 use opentelemetry::trace::TraceContextExt;
 use tokio::io::AsyncWriteExt;
@@ -32,12 +31,14 @@ use tower_http::trace::MakeSpan;
 use tracing::Level;
 use tracing::Span;
 
+use crate::plugins::telemetry::extract_context;
 use crate::services::MULTIPART_DEFER_CONTENT_TYPE;
 use crate::services::MULTIPART_DEFER_SPEC_PARAMETER;
 use crate::services::MULTIPART_DEFER_SPEC_VALUE;
 
 pub(crate) const REQUEST_SPAN_NAME: &str = "request";
 pub(crate) const APPLICATION_JSON_HEADER_VALUE: &str = "application/json";
+pub(crate) const APPLICATION_GRAPHQL_HEADER_VALUE: &str = "application/graphql";
 pub(crate) const GRAPHQL_JSON_RESPONSE_HEADER_VALUE: &str = "application/graphql-response+json";
 
 pub(super) fn prefers_html(headers: &HeaderMap) -> bool {
@@ -232,9 +233,7 @@ impl<B> MakeSpan<B> for PropagatingMakeSpan {
         // This method needs to be moved to the telemetry plugin once we have a hook for the http request.
 
         // Before we make the span we need to attach span info that may have come in from the request.
-        let context = global::get_text_map_propagator(|propagator| {
-            propagator.extract(&opentelemetry_http::HeaderExtractor(request.headers()))
-        });
+        let context = extract_context(request.headers());
 
         // If there was no span from the request then it will default to the NOOP span.
         // Attaching the NOOP span has the effect of preventing further tracing.
@@ -340,4 +339,34 @@ mod tests {
         );
         assert!(accepts_multipart(&default_headers));
     }
+
+    #[test]
+    fn it_creates_the_root_span_in_the_incoming_trace() {
+        use opentelemetry::sdk::propagation::TraceContextPropagator;
+        use opentelemetry::trace::TracerProvider;
+        use tracing_opentelemetry::OpenTelemetrySpanExt;
+        use tracing_subscriber::layer::SubscriberExt;
+
+        opentelemetry::global::set_text_map_propagator(TraceContextPropagator::new());
+        let tracer = opentelemetry::sdk::trace::TracerProvider::builder()
+            .build()
+            .tracer("test");
+        let subscriber = tracing_subscriber::Registry::default()
+            .with(tracing_opentelemetry::layer().with_tracer(tracer));
+
+        let request = Request::builder()
+            .header(
+                "traceparent",
+                "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01",
+            )
+            .body(())
+            .unwrap();
+        tracing::subscriber::with_default(subscriber, || {
+            let span = PropagatingMakeSpan::new().make_span(&request);
+            assert_eq!(
+                span.context().span().span_context().trace_id().to_string(),
+                "0af7651916cd43dd8448eb211c80319c"
+            );
+        });
+    }
 }
@@ -0,0 +1,215 @@
+//! Metrics shared by the router's internal caches.
+
+use std::sync::atomic::AtomicI64;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+
+use opentelemetry::KeyValue;
+
+use crate::plugins::telemetry::metrics::AggregateCounter;
+use crate::plugins::telemetry::metrics::AggregateMeterProvider;
+use crate::plugins::telemetry::metrics::AggregateUpDownCounter;
+
+/// Records the size, hits, misses and evictions of a cache, labeled by cache name
+///
+/// The clones record the metrics of the same cache: each cache needs its own `CacheMetrics`.
+#[derive(Clone)]
+pub(crate) struct CacheMetrics {
+    instruments: Arc<Instruments>,
+}
+
+struct Instruments {
+    attributes: [KeyValue; 1],
+    entries: AggregateUpDownCounter<i64>,
+    // the entries added to the size by this cache, removed when the cache is dropped
+    len: AtomicI64,
+    hits: AggregateCounter<u64>,
+    misses: AggregateCounter<u64>,
+    evictions: AggregateCounter<u64>,
+}
+
+impl Drop for Instruments {
+    fn drop(&mut self) {
+        // the cache of a replaced schema does not count in the size anymore
+        self.entries
+            .add(-self.len.load(Ordering::Relaxed), &self.attributes);
+    }
+}
+
+impl CacheMetrics {
+    pub(crate) fn new(cache_name: &'static str, meter_provider: &AggregateMeterProvider) -> Self {
+        let meter = meter_provider.meter("apollo/router", None);
+        let instruments = Instruments {
+            attributes: [KeyValue::new("cache", cache_name)],
+            entries: meter.build_up_down_counter(|m| {
+                m.i64_up_down_counter("apollo_router_cache_size")
+                    .with_description("Number of entries in the cache.")
+                    .init()
+            }),
+            len: AtomicI64::new(0),
+            hits: meter.build_counter(|m| {
+                m.u64_counter("apollo_router_cache_hits_total")
+                    .with_description("Number of cache lookups that found an entry.")
+                    .init()
+            }),
+            misses: meter.build_counter(|m| {
+                m.u64_counter("apollo_router_cache_misses_total")
+                    .with_description("Number of cache lookups that did not find an entry.")
+                    .init()
+            }),
+            evictions: meter.build_counter(|m| {
+                m.u64_counter("apollo_router_cache_evictions_total")
+                    .with_description(
+                        "Number of entries removed from the cache to make room for new ones.",
+                    )
+                    .init()
+            }),
+        };
+        CacheMetrics {
+            instruments: Arc::new(instruments),
+        }
+    }
+
+    /// Records an entry added to the cache, without replacing an entry with the same key
+    pub(crate) fn record_insert(&self) {
+        self.add_entries(1);
+    }
+
+    pub(crate) fn record_hit(&self) {
+        let instruments = &self.instruments;
+        instruments.hits.add(1, &instruments.attributes);
+    }
+
+    pub(crate) fn record_miss(&self) {
+        let instruments = &self.instruments;
+        instruments.misses.add(1, &instruments.attributes);
+    }
+
+    /// Records an entry removed from the cache to make room for a new one
+    pub(crate) fn record_eviction(&self) {
+        let instruments = &self.instruments;
+        instruments.evictions.add(1, &instruments.attributes);
+        self.add_entries(-1);
+    }
+
+    fn add_entries(&self, entries: i64) {
+        let instruments = &self.instruments;
+        instruments.len.fetch_add(entries, Ordering::Relaxed);
+        instruments.entries.add(entries, &instruments.attributes);
+    }
+}
+
+impl Default for CacheMetrics {
+    /// Metrics that are not sent anywhere
+    fn default() -> Self {
+        Self::new("default", &AggregateMeterProvider::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use prometheus::Registry;
+
+    use super::*;
+    use crate::cache::storage::CacheStorage;
+
+    fn counter_value(registry: &Registry, name: &str) -> f64 {
+        test_metrics(registry, name)
+            .map(|metric| metric.get_counter().get_value())
+            .sum()
+    }
+
+    fn gauge_value(registry: &Registry, name: &str) -> f64 {
+        test_metrics(registry, name)
+            .map(|metric| metric.get_gauge().get_value())
+            .sum()
+    }
+
+    fn test_metrics<'a>(
+        registry: &Registry,
+        name: &'a str,
+    ) -> impl Iterator<Item = prometheus::proto::Metric> + 'a {
+        registry
+            .gather()
+            .into_iter()
+            .filter(move |family| family.get_name().starts_with(name))
+            .flat_map(|mut family| family.take_metric().into_iter())
+            .filter(|metric| {
+                metric
+                    .get_label()
+                    .iter()
+                    .any(|label| label.get_name() == "cache" && label.get_value() == "test")
+            })
+    }
+
+    #[tokio::test]
+    async fn it_counts_evictions() {
+        let exporter = opentelemetry_prometheus::exporter().init();
+        let meter_provider = AggregateMeterProvider::new(vec![Arc::new(
+            exporter.provider().expect("meter provider"),
+        )]);
+        let cache: CacheStorage<usize, usize> = CacheStorage::new(2, None)
+            .await
+            .with_metrics(CacheMetrics::new("test", &meter_provider));
+
+        cache.insert(1, 1).await;
+        cache.insert(2, 2).await;
+        // replacing an entry does not evict anything
+        cache.insert(2, 2).await;
+        assert_eq!(
+            counter_value(exporter.registry(), "apollo_router_cache_evictions"),
+            0.0
+        );
+
+        cache.insert(3, 3).await;
+        assert_eq!(
+            counter_value(exporter.registry(), "apollo_router_cache_evictions"),
+            1.0
+        );
+
+        assert!(cache.get(&1).await.is_none());
+        assert_eq!(cache.get(&3).await, Some(3));
+        assert_eq!(
+            counter_value(exporter.registry(), "apollo_router_cache_misses"),
+            1.0
+        );
+        assert_eq!(
+            counter_value(exporter.registry(), "apollo_router_cache_hits"),
+            1.0
+        );
+    }
+
+    #[tokio::test]
+    async fn it_tracks_the_size_of_the_cache() {
+        let exporter = opentelemetry_prometheus::exporter().init();
+        let meter_provider = AggregateMeterProvider::new(vec![Arc::new(
+            exporter.provider().expect("meter provider"),
+        )]);
+        let cache: CacheStorage<usize, usize> = CacheStorage::new(2, None)
+            .await
+            .with_metrics(CacheMetrics::new("test", &meter_provider));
+
+        cache.insert(1, 1).await;
+        assert_eq!(
+            gauge_value(exporter.registry(), "apollo_router_cache_size"),
+            1.0
+        );
+        // replacing an entry or evicting one to make room keeps the size
+        cache.insert(1, 1).await;
+        cache.insert(2, 2).await;
+        cache.insert(3, 3).await;
+        assert_eq!(
+            gauge_value(exporter.registry(), "apollo_router_cache_size"),
+            2.0
+        );
+
+        // the entries of a dropped cache are not counted anymore
+        drop(cache);
+        assert_eq!(
+            gauge_value(exporter.registry(), "apollo_router_cache_size"),
+            0.0
+        );
+    }
+}
@@ -5,10 +5,12 @@ use tokio::sync::broadcast;
 use tokio::sync::oneshot;
 use tokio::sync::Mutex;
 
+use self::metrics::CacheMetrics;
 use self::storage::CacheStorage;
 use self::storage::KeyType;
 use self::storage::ValueType;
 
+pub(crate) mod metrics;
 pub(crate) mod storage;
 
 type WaitMap<K, V> = Arc<Mutex<HashMap<K, broadcast::Sender<V>>>>;
@@ -37,6 +39,12 @@ where
         }
     }
 
+    /// Sends the size, hits, misses and evictions of this cache to `metrics`
+    pub(crate) fn with_metrics(mut self, metrics: CacheMetrics) -> Self {
+        self.storage = self.storage.with_metrics(metrics);
+        self
+    }
+
     pub(crate) async fn get(&self, key: &K) -> Entry<K, V> {
         // waiting on a value from the cache is a potentially long(millisecond scale) task that
         // can involve a network call to an external database. To reduce the waiting time, we
@@ -164,7 +172,6 @@ where
     }
 
     /// sends the value without storing it into the cache
-    #[allow(unused)]
     pub(crate) async fn send(self, value: V) {
         if let EntryInner::First {
             sender, cache, key, ..
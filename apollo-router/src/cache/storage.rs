@@ -7,6 +7,8 @@ use serde::de::DeserializeOwned;
 use serde::Serialize;
 use tokio::sync::Mutex;
 
+use super::metrics::CacheMetrics;
+
 pub(crate) trait KeyType:
     Clone + fmt::Debug + fmt::Display + Hash + Eq + Send + Sync
 {
@@ -44,6 +46,7 @@ use redis_storage::*;
 #[derive(Clone)]
 pub(crate) struct CacheStorage<K: KeyType, V: ValueType> {
     inner: Arc<Mutex<LruCache<K, V>>>,
+    metrics: CacheMetrics,
     #[cfg(feature = "experimental_cache")]
     redis: Option<RedisCacheStorage>,
 }
@@ -56,6 +59,7 @@ where
     pub(crate) async fn new(max_capacity: usize, _redis_urls: Option<Vec<String>>) -> Self {
         Self {
             inner: Arc::new(Mutex::new(LruCache::new(max_capacity))),
+            metrics: CacheMetrics::default(),
             #[cfg(feature = "experimental_cache")]
             redis: if let Some(urls) = _redis_urls {
                 Some(RedisCacheStorage::new(urls).await)
@@ -65,7 +69,22 @@ where
         }
     }
 
+    /// Sends the size, hits, misses and evictions of this cache to `metrics`
+    pub(crate) fn with_metrics(mut self, metrics: CacheMetrics) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
     pub(crate) async fn get(&self, key: &K) -> Option<V> {
+        let value = self.get_inner(key).await;
+        match value {
+            Some(_) => self.metrics.record_hit(),
+            None => self.metrics.record_miss(),
+        }
+        value
+    }
+
+    async fn get_inner(&self, key: &K) -> Option<V> {
         let mut guard = self.inner.lock().await;
         match guard.get(key) {
             Some(v) => Some(v.clone()),
@@ -75,7 +94,7 @@ where
                     let inner_key = RedisKey(key.clone());
                     match redis.get::<K, V>(inner_key).await {
                         Some(v) => {
-                            guard.put(key.clone(), v.0.clone());
+                            self.put(&mut guard, key.clone(), v.0.clone());
                             Some(v.0)
                         }
                         None => None,
@@ -90,7 +109,7 @@ where
     }
 
     pub(crate) async fn insert(&self, key: K, value: V) {
-        self.inner.lock().await.put(key.clone(), value.clone());
+        self.put(&mut *self.inner.lock().await, key.clone(), value.clone());
 
         #[cfg(feature = "experimental_cache")]
         if let Some(redis) = self.redis.as_ref() {
@@ -98,6 +117,19 @@ where
         }
     }
 
+    fn put(&self, cache: &mut LruCache<K, V>, key: K, value: V) {
+        // `push` returns the previous value when the key was already present,
+        // and the least recently used entry when one was evicted to make room
+        match cache.push(key.clone(), value) {
+            Some((previous_key, _)) if previous_key == key => {}
+            Some(_) => {
+                self.metrics.record_eviction();
+                self.metrics.record_insert();
+            }
+            None => self.metrics.record_insert(),
+        }
+    }
+
     #[cfg(test)]
     pub(crate) async fn len(&self) -> usize {
         self.inner.lock().await.len()
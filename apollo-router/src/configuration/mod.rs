@@ -7,10 +7,14 @@ mod schema;
 mod tests;
 mod yaml;
 
+use std::collections::HashMap;
+use std::collections::HashSet;
 use std::fmt;
 use std::net::IpAddr;
 use std::net::SocketAddr;
+use std::num::NonZeroUsize;
 use std::str::FromStr;
+use std::time::Duration;
 
 use askama::Template;
 use bytes::Bytes;
@@ -18,8 +22,10 @@ use cors::*;
 use derivative::Derivative;
 use displaydoc::Display;
 use expansion::*;
+use http::HeaderValue;
 use itertools::Itertools;
 pub(crate) use schema::generate_config_schema;
+pub(crate) use schema::schema_settings;
 use schemars::gen::SchemaGenerator;
 use schemars::schema::ObjectValidation;
 use schemars::schema::Schema;
@@ -33,7 +39,11 @@ use serde_json::Value;
 use thiserror::Error;
 
 use crate::executable::APOLLO_ROUTER_DEV_ENV;
+use crate::json_ext::ArrayMergeStrategy;
+use crate::json_ext::Path;
+use crate::json_ext::PathElement;
 use crate::plugin::plugins;
+use crate::services::subgraph_service::UrlTemplate;
 
 /// Configuration error.
 #[derive(Debug, Error, Display)]
@@ -58,6 +68,13 @@ pub enum ConfigurationError {
     /// could not deserialize configuration: {0}
     DeserializeConfigError(serde_json::Error),
 
+    /// could not parse yaml at line {line}, column {column}: {error}
+    InvalidYaml {
+        line: usize,
+        column: usize,
+        error: String,
+    },
+
     /// APOLLO_ROUTER_CONFIG_SUPPORTED_MODES must be of the format env,file,... Possible modes are 'env' and 'file'.
     InvalidExpansionModeConfig,
 }
@@ -85,10 +102,36 @@ pub struct Configuration {
 
     #[serde(default)]
     pub(crate) supergraph: Supergraph,
+
+    #[serde(default)]
+    pub(crate) schema: SchemaReload,
+
+    #[serde(default)]
+    pub(crate) limits: Limits,
+
+    #[serde(default)]
+    pub(crate) batching: Batching,
+
+    #[serde(default)]
+    pub(crate) warmup: Warmup,
+
+    #[serde(default)]
+    pub(crate) fallbacks: Fallbacks,
+
+    #[serde(default)]
+    pub(crate) redaction: Redaction,
+
+    #[serde(default)]
+    pub(crate) subgraph: SubgraphConfiguration,
+
     /// Cross origin request headers.
     #[serde(default)]
     pub(crate) cors: Cors,
 
+    /// What to do when a plugin fails to initialize
+    #[serde(default)]
+    pub(crate) on_plugin_error: OnPluginError,
+
     /// Plugin configuration
     #[serde(default)]
     plugins: UserPlugins,
@@ -120,8 +163,24 @@ impl<'de> serde::Deserialize<'de> for Configuration {
             #[serde(default)]
             supergraph: Supergraph,
             #[serde(default)]
+            schema: SchemaReload,
+            #[serde(default)]
+            limits: Limits,
+            #[serde(default)]
+            batching: Batching,
+            #[serde(default)]
+            warmup: Warmup,
+            #[serde(default)]
+            fallbacks: Fallbacks,
+            #[serde(default)]
+            redaction: Redaction,
+            #[serde(default)]
+            subgraph: SubgraphConfiguration,
+            #[serde(default)]
             cors: Cors,
             #[serde(default)]
+            on_plugin_error: OnPluginError,
+            #[serde(default)]
             plugins: UserPlugins,
             #[serde(default)]
             #[serde(flatten)]
@@ -135,7 +194,15 @@ impl<'de> serde::Deserialize<'de> for Configuration {
             .sandbox(ad_hoc.sandbox)
             .homepage(ad_hoc.homepage)
             .supergraph(ad_hoc.supergraph)
+            .schema(ad_hoc.schema)
+            .limits(ad_hoc.limits)
+            .batching(ad_hoc.batching)
+            .warmup(ad_hoc.warmup)
+            .fallbacks(ad_hoc.fallbacks)
+            .redaction(ad_hoc.redaction)
+            .subgraph(ad_hoc.subgraph)
             .cors(ad_hoc.cors)
+            .on_plugin_error(ad_hoc.on_plugin_error)
             .plugins(ad_hoc.plugins.plugins.unwrap_or_default())
             .apollo_plugins(ad_hoc.apollo_plugins.plugins)
             .build()
@@ -145,6 +212,8 @@ impl<'de> serde::Deserialize<'de> for Configuration {
 
 const APOLLO_PLUGIN_PREFIX: &str = "apollo.";
 const TELEMETRY_KEY: &str = "telemetry";
+const OVERRIDE_SUBGRAPH_URL_KEY: &str = "override_subgraph_url";
+const TRAFFIC_SHAPING_KEY: &str = "traffic_shaping";
 
 fn default_graphql_listen() -> ListenAddr {
     SocketAddr::from_str("127.0.0.1:4000").unwrap().into()
@@ -165,7 +234,15 @@ impl Configuration {
         health_check: Option<HealthCheck>,
         sandbox: Option<Sandbox>,
         homepage: Option<Homepage>,
+        schema: Option<SchemaReload>,
+        limits: Option<Limits>,
+        batching: Option<Batching>,
+        warmup: Option<Warmup>,
+        fallbacks: Option<Fallbacks>,
+        redaction: Option<Redaction>,
+        subgraph: Option<SubgraphConfiguration>,
         cors: Option<Cors>,
+        on_plugin_error: Option<OnPluginError>,
         plugins: Map<String, Value>,
         apollo_plugins: Map<String, Value>,
         dev: Option<bool>,
@@ -176,7 +253,15 @@ impl Configuration {
             health_check: health_check.unwrap_or_default(),
             sandbox: sandbox.unwrap_or_default(),
             homepage: homepage.unwrap_or_default(),
+            schema: schema.unwrap_or_default(),
+            limits: limits.unwrap_or_default(),
+            batching: batching.unwrap_or_default(),
+            warmup: warmup.unwrap_or_default(),
+            fallbacks: fallbacks.unwrap_or_default(),
+            redaction: redaction.unwrap_or_default(),
+            subgraph: subgraph.unwrap_or_default(),
             cors: cors.unwrap_or_default(),
+            on_plugin_error: on_plugin_error.unwrap_or_default(),
             plugins: UserPlugins {
                 plugins: Some(plugins),
             },
@@ -249,14 +334,120 @@ impl Configuration {
 
     // checks that we can reload configuration from the current one to the new one
     pub(crate) fn is_compatible(&self, new: &Configuration) -> Result<(), &'static str> {
-        if self.apollo_plugins.plugins.get(TELEMETRY_KEY)
-            == new.apollo_plugins.plugins.get(TELEMETRY_KEY)
-        {
+        if self.static_telemetry() == new.static_telemetry() {
             Ok(())
         } else {
             Err("incompatible telemetry configuration. Telemetry cannot be reloaded and its configuration must stay the same for the entire life of the process")
         }
     }
+
+    /// Configuration of the subgraph URL overrides, an empty object if there is none
+    pub(crate) fn subgraph_url_overrides(&self) -> Value {
+        self.apollo_plugins
+            .plugins
+            .get(OVERRIDE_SUBGRAPH_URL_KEY)
+            .cloned()
+            .unwrap_or_else(|| Value::Object(Map::new()))
+    }
+
+    // checks if the new configuration only changes the subgraph URL overrides, which
+    // can be applied without rebuilding the router
+    pub(crate) fn only_subgraph_urls_changed(&self, new: &Configuration) -> bool {
+        if self.subgraph_url_overrides() == new.subgraph_url_overrides() {
+            return false;
+        }
+        let without_overrides = |configuration: &Configuration| {
+            let mut configuration = configuration.clone();
+            configuration
+                .apollo_plugins
+                .plugins
+                .remove(OVERRIDE_SUBGRAPH_URL_KEY);
+            serde_json::to_value(configuration).ok()
+        };
+        let current = without_overrides(self);
+        current.is_some() && current == without_overrides(new)
+    }
+
+    /// Names of the plugins with a new configuration, if the new configuration changes nothing
+    /// else. The plugins may then apply their new configuration without rebuilding the router
+    pub(crate) fn changed_plugins(&self, new: &Configuration) -> Option<Vec<String>> {
+        let without_plugins = |configuration: &Configuration| {
+            let mut configuration = configuration.clone();
+            configuration.apollo_plugins.plugins.clear();
+            configuration.plugins.plugins = None;
+            serde_json::to_value(configuration).ok()
+        };
+        let current = without_plugins(self);
+        if current.is_none() || current != without_plugins(new) {
+            return None;
+        }
+
+        let current_plugins: HashMap<String, Value> = self.plugins().into_iter().collect();
+        let new_plugins: HashMap<String, Value> = new.plugins().into_iter().collect();
+        // adding or removing a plugin changes the services of the router
+        if current_plugins.len() != new_plugins.len()
+            || current_plugins
+                .keys()
+                .any(|name| !new_plugins.contains_key(name))
+        {
+            return None;
+        }
+        let changed: Vec<String> = new_plugins
+            .into_iter()
+            .filter(|(name, configuration)| current_plugins.get(name) != Some(configuration))
+            .map(|(name, _)| name)
+            .collect();
+        (!changed.is_empty()).then(|| changed)
+    }
+
+    /// Names of the subgraphs with an explicit URL override, subgraph or traffic shaping
+    /// configuration
+    pub(crate) fn configured_subgraphs(&self) -> HashSet<String> {
+        let mut subgraphs = HashSet::new();
+        if let Some(overrides) = self
+            .apollo_plugins
+            .plugins
+            .get(OVERRIDE_SUBGRAPH_URL_KEY)
+            .and_then(|overrides| overrides.as_object())
+        {
+            subgraphs.extend(overrides.keys().cloned());
+        }
+        if let Some(shaping) = self
+            .apollo_plugins
+            .plugins
+            .get(TRAFFIC_SHAPING_KEY)
+            .and_then(|shaping| shaping.get("subgraphs"))
+            .and_then(|shaping| shaping.as_object())
+        {
+            subgraphs.extend(shaping.keys().cloned());
+        }
+        if let Some(timeouts) = self
+            .apollo_plugins
+            .plugins
+            .get(TRAFFIC_SHAPING_KEY)
+            .and_then(|shaping| shaping.get("timeouts"))
+            .and_then(|timeouts| timeouts.get("subgraphs"))
+            .and_then(|timeouts| timeouts.as_object())
+        {
+            subgraphs.extend(timeouts.keys().cloned());
+        }
+        subgraphs.extend(self.subgraph.subgraphs.keys().cloned());
+        subgraphs
+    }
+
+    /// The telemetry configuration, without the parts that can change on reload
+    fn static_telemetry(&self) -> Option<Value> {
+        self.apollo_plugins
+            .plugins
+            .get(TELEMETRY_KEY)
+            .map(|telemetry| {
+                let mut telemetry = telemetry.clone();
+                if let Some(telemetry) = telemetry.as_object_mut() {
+                    telemetry.remove("logging");
+                }
+                telemetry
+            })
+    }
 }
 
 #[cfg(test)]
@@ -269,7 +460,15 @@ impl Configuration {
         health_check: Option<HealthCheck>,
         sandbox: Option<Sandbox>,
         homepage: Option<Homepage>,
+        schema: Option<SchemaReload>,
+        limits: Option<Limits>,
+        batching: Option<Batching>,
+        warmup: Option<Warmup>,
+        fallbacks: Option<Fallbacks>,
+        redaction: Option<Redaction>,
+        subgraph: Option<SubgraphConfiguration>,
         cors: Option<Cors>,
+        on_plugin_error: Option<OnPluginError>,
         plugins: Map<String, Value>,
         apollo_plugins: Map<String, Value>,
         dev: Option<bool>,
@@ -280,7 +479,15 @@ impl Configuration {
             health_check: health_check.unwrap_or_else(|| HealthCheck::fake_builder().build()),
             sandbox: sandbox.unwrap_or_else(|| Sandbox::fake_builder().build()),
             homepage: homepage.unwrap_or_else(|| Homepage::fake_builder().build()),
+            schema: schema.unwrap_or_default(),
+            limits: limits.unwrap_or_default(),
+            batching: batching.unwrap_or_default(),
+            warmup: warmup.unwrap_or_default(),
+            fallbacks: fallbacks.unwrap_or_default(),
+            redaction: redaction.unwrap_or_default(),
+            subgraph: subgraph.unwrap_or_default(),
             cors: cors.unwrap_or_default(),
+            on_plugin_error: on_plugin_error.unwrap_or_default(),
             plugins: UserPlugins {
                 plugins: Some(plugins),
             },
@@ -344,6 +551,56 @@ impl Configuration {
                 },
             );
         }
+        if self.batching.enabled && self.batching.max_size == 0 {
+            return Err(ConfigurationError::InvalidConfiguration {
+                message: "invalid 'batching.max_size' configuration",
+                error: "the maximum size of a batch must be at least 1".to_string(),
+            });
+        }
+        for path in self.fallbacks.fields.keys() {
+            if !matches!(Path::from(path).last(), Some(PathElement::Key(_))) {
+                return Err(ConfigurationError::InvalidConfiguration {
+                    message: "invalid 'fallbacks.fields' configuration",
+                    error: format!("'{}' is invalid, the path must end with a field name", path),
+                });
+            }
+        }
+        for path in self.redaction.fields.keys() {
+            if !matches!(Path::from(path).last(), Some(PathElement::Key(_))) {
+                return Err(ConfigurationError::InvalidConfiguration {
+                    message: "invalid 'redaction.fields' configuration",
+                    error: format!("'{}' is invalid, the path must end with a field name", path),
+                });
+            }
+        }
+        for accept in self
+            .subgraph
+            .all
+            .iter()
+            .chain(self.subgraph.subgraphs.values())
+            .filter_map(|settings| settings.accept.as_ref())
+        {
+            if HeaderValue::from_str(accept).is_err() {
+                return Err(ConfigurationError::InvalidConfiguration {
+                    message: "invalid 'subgraph' configuration",
+                    error: format!("invalid Accept header value: {}", accept),
+                });
+            }
+        }
+        for url_template in self
+            .subgraph
+            .all
+            .iter()
+            .chain(self.subgraph.subgraphs.values())
+            .filter_map(|settings| settings.url_template.as_ref())
+        {
+            if let Err(err) = UrlTemplate::parse(url_template) {
+                return Err(ConfigurationError::InvalidConfiguration {
+                    message: "invalid 'subgraph' configuration",
+                    error: format!("invalid URL template {}: {}", url_template, err),
+                });
+            }
+        }
         Ok(self)
     }
 }
@@ -405,6 +662,22 @@ impl JsonSchema for ApolloPlugins {
     }
 }
 
+/// What to do when a plugin fails to initialize.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum OnPluginError {
+    /// Fail the router startup, or the reload, with the plugin's error
+    Abort,
+    /// Log the plugin's error and continue without it. Mandatory plugins always abort
+    Skip,
+}
+
+impl Default for OnPluginError {
+    fn default() -> Self {
+        OnPluginError::Abort
+    }
+}
+
 /// Plugins provided by a user.
 ///
 /// These plugins are compiled into a router by and their configuration is performed
@@ -456,6 +729,68 @@ pub(crate) struct Supergraph {
     #[serde(default = "default_defer_support")]
     pub(crate) preview_defer_support: bool,
 
+    /// Context keys copied to the context of the deferred parts of a request, in addition to the
+    /// entries of the router itself. The deferred subgraph requests don't see the other keys, and
+    /// their writes are not visible to the rest of the request.
+    /// default: the deferred parts share the whole context of the request
+    #[serde(default)]
+    pub(crate) defer_context_keys: Option<Vec<String>>,
+
+    /// How variables are coerced to the types declared by the operation
+    /// default: lenient
+    #[serde(default)]
+    pub(crate) variable_coercion: VariableCoercion,
+
+    /// What to do with the variables sent by a client that the operation does not declare
+    /// default: ignore
+    #[serde(default)]
+    pub(crate) unknown_variables: UnknownVariables,
+
+    /// How integers of ID and custom scalar fields are sent when JavaScript numbers
+    /// cannot represent them exactly
+    /// default: preserve
+    #[serde(default)]
+    pub(crate) large_integers: LargeIntegers,
+
+    /// How the arrays returned by the root fetches of the query plan are merged into the
+    /// response, like the same list returned by parallel fetches. Entities are always merged by
+    /// index
+    /// default: by_index
+    #[serde(default)]
+    pub(crate) array_merge_strategy: ArrayMergeStrategy,
+
+    /// The message of the GraphQL error returned when processing a request panics
+    /// default: "Internal server error"
+    #[serde(default = "default_panic_message")]
+    pub(crate) panic_message: String,
+
+    /// Reject the operations without a name, introspection queries excepted
+    /// default: false
+    #[serde(default)]
+    pub(crate) require_operation_name: bool,
+
+    /// Accept POST requests with the `application/graphql` content type, whose body is the query
+    /// default: false
+    #[serde(default)]
+    pub(crate) accept_graphql_content_type: bool,
+
+    /// Values of the variables that requests do not set, by variable name. They are inserted
+    /// as is, before query planning, and the values sent by clients take precedence
+    #[serde(default)]
+    pub(crate) default_variables: HashMap<String, Value>,
+
+    /// How the trailing slash of the subgraph URLs, from the supergraph schema or overridden,
+    /// is normalized
+    /// default: preserve
+    #[serde(default)]
+    pub(crate) subgraph_url_trailing_slash: TrailingSlash,
+
+    /// Remove the fields that subgraphs return without the operation selecting them, so the
+    /// responses only contain the selection set of the client
+    /// default: true
+    #[serde(default = "default_strip_unrequested_fields")]
+    pub(crate) strip_unrequested_fields: bool,
+
     #[cfg(feature = "experimental_cache")]
     /// URLs of Redis cache used for query planning
     pub(crate) cache_redis_urls: Option<Vec<String>>,
@@ -465,6 +800,81 @@ fn default_defer_support() -> bool {
     true
 }
 
+fn default_panic_message() -> String {
+    "Internal server error".to_string()
+}
+
+fn default_strip_unrequested_fields() -> bool {
+    true
+}
+
+/// How the trailing slash of the subgraph URLs is normalized
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum TrailingSlash {
+    /// Keep the URLs as they are written
+    Preserve,
+    /// Remove the trailing slash of the URL paths, except for the root path
+    Remove,
+    /// Add a trailing slash to the URL paths that do not end with one
+    Add,
+}
+
+impl Default for TrailingSlash {
+    fn default() -> Self {
+        TrailingSlash::Preserve
+    }
+}
+
+/// How variables are coerced to the types declared by the operation
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum VariableCoercion {
+    /// Reject variables that need an implicit coercion, like a single value for a list or an integer for a float
+    Strict,
+    /// Coerce variables with the input coercion rules of the GraphQL specification
+    Lenient,
+}
+
+impl Default for VariableCoercion {
+    fn default() -> Self {
+        VariableCoercion::Lenient
+    }
+}
+
+/// What to do with the variables that the operation does not declare
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum UnknownVariables {
+    /// Accept the request and ignore the unknown variables
+    Ignore,
+    /// Reject the request with a validation error
+    Reject,
+}
+
+impl Default for UnknownVariables {
+    fn default() -> Self {
+        UnknownVariables::Ignore
+    }
+}
+
+/// How integers of ID and custom scalar fields are sent when JavaScript numbers
+/// cannot represent them exactly
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum LargeIntegers {
+    /// Send the integers as returned by subgraphs, with all their digits
+    Preserve,
+    /// Send the integers larger than 2^53 - 1 in absolute value as strings
+    String,
+}
+
+impl Default for LargeIntegers {
+    fn default() -> Self {
+        LargeIntegers::Preserve
+    }
+}
+
 #[cfg(feature = "experimental_cache")]
 #[buildstructor::buildstructor]
 impl Supergraph {
@@ -474,6 +884,17 @@ impl Supergraph {
         path: Option<String>,
         introspection: Option<bool>,
         preview_defer_support: Option<bool>,
+        defer_context_keys: Option<Vec<String>>,
+        variable_coercion: Option<VariableCoercion>,
+        unknown_variables: Option<UnknownVariables>,
+        large_integers: Option<LargeIntegers>,
+        array_merge_strategy: Option<ArrayMergeStrategy>,
+        panic_message: Option<String>,
+        require_operation_name: Option<bool>,
+        accept_graphql_content_type: Option<bool>,
+        default_variables: Option<HashMap<String, Value>>,
+        subgraph_url_trailing_slash: Option<TrailingSlash>,
+        strip_unrequested_fields: Option<bool>,
         cache_redis_urls: Option<Vec<String>>,
     ) -> Self {
         Self {
@@ -481,6 +902,18 @@ impl Supergraph {
             path: path.unwrap_or_else(default_graphql_path),
             introspection: introspection.unwrap_or_else(default_graphql_introspection),
             preview_defer_support: preview_defer_support.unwrap_or_else(default_defer_support),
+            defer_context_keys,
+            variable_coercion: variable_coercion.unwrap_or_default(),
+            unknown_variables: unknown_variables.unwrap_or_default(),
+            large_integers: large_integers.unwrap_or_default(),
+            array_merge_strategy: array_merge_strategy.unwrap_or_default(),
+            panic_message: panic_message.unwrap_or_else(default_panic_message),
+            require_operation_name: require_operation_name.unwrap_or_default(),
+            accept_graphql_content_type: accept_graphql_content_type.unwrap_or_default(),
+            default_variables: default_variables.unwrap_or_default(),
+            subgraph_url_trailing_slash: subgraph_url_trailing_slash.unwrap_or_default(),
+            strip_unrequested_fields: strip_unrequested_fields
+                .unwrap_or_else(default_strip_unrequested_fields),
             cache_redis_urls,
         }
     }
@@ -496,6 +929,17 @@ impl Supergraph {
         path: Option<String>,
         introspection: Option<bool>,
         preview_defer_support: Option<bool>,
+        defer_context_keys: Option<Vec<String>>,
+        variable_coercion: Option<VariableCoercion>,
+        unknown_variables: Option<UnknownVariables>,
+        large_integers: Option<LargeIntegers>,
+        array_merge_strategy: Option<ArrayMergeStrategy>,
+        panic_message: Option<String>,
+        require_operation_name: Option<bool>,
+        accept_graphql_content_type: Option<bool>,
+        default_variables: Option<HashMap<String, Value>>,
+        subgraph_url_trailing_slash: Option<TrailingSlash>,
+        strip_unrequested_fields: Option<bool>,
         cache_redis_urls: Option<Vec<String>>,
     ) -> Self {
         Self {
@@ -503,6 +947,18 @@ impl Supergraph {
             path: path.unwrap_or_else(default_graphql_path),
             introspection: introspection.unwrap_or_else(default_graphql_introspection),
             preview_defer_support: preview_defer_support.unwrap_or_else(default_defer_support),
+            defer_context_keys,
+            variable_coercion: variable_coercion.unwrap_or_default(),
+            unknown_variables: unknown_variables.unwrap_or_default(),
+            large_integers: large_integers.unwrap_or_default(),
+            array_merge_strategy: array_merge_strategy.unwrap_or_default(),
+            panic_message: panic_message.unwrap_or_else(default_panic_message),
+            require_operation_name: require_operation_name.unwrap_or_default(),
+            accept_graphql_content_type: accept_graphql_content_type.unwrap_or_default(),
+            default_variables: default_variables.unwrap_or_default(),
+            subgraph_url_trailing_slash: subgraph_url_trailing_slash.unwrap_or_default(),
+            strip_unrequested_fields: strip_unrequested_fields
+                .unwrap_or_else(default_strip_unrequested_fields),
             cache_redis_urls,
         }
     }
@@ -517,12 +973,35 @@ impl Supergraph {
         path: Option<String>,
         introspection: Option<bool>,
         preview_defer_support: Option<bool>,
+        defer_context_keys: Option<Vec<String>>,
+        variable_coercion: Option<VariableCoercion>,
+        unknown_variables: Option<UnknownVariables>,
+        large_integers: Option<LargeIntegers>,
+        array_merge_strategy: Option<ArrayMergeStrategy>,
+        panic_message: Option<String>,
+        require_operation_name: Option<bool>,
+        accept_graphql_content_type: Option<bool>,
+        default_variables: Option<HashMap<String, Value>>,
+        subgraph_url_trailing_slash: Option<TrailingSlash>,
+        strip_unrequested_fields: Option<bool>,
     ) -> Self {
         Self {
             listen: listen.unwrap_or_else(default_graphql_listen),
             path: path.unwrap_or_else(default_graphql_path),
             introspection: introspection.unwrap_or_else(default_graphql_introspection),
             preview_defer_support: preview_defer_support.unwrap_or_else(default_defer_support),
+            defer_context_keys,
+            variable_coercion: variable_coercion.unwrap_or_default(),
+            unknown_variables: unknown_variables.unwrap_or_default(),
+            large_integers: large_integers.unwrap_or_default(),
+            array_merge_strategy: array_merge_strategy.unwrap_or_default(),
+            panic_message: panic_message.unwrap_or_else(default_panic_message),
+            require_operation_name: require_operation_name.unwrap_or_default(),
+            accept_graphql_content_type: accept_graphql_content_type.unwrap_or_default(),
+            default_variables: default_variables.unwrap_or_default(),
+            subgraph_url_trailing_slash: subgraph_url_trailing_slash.unwrap_or_default(),
+            strip_unrequested_fields: strip_unrequested_fields
+                .unwrap_or_else(default_strip_unrequested_fields),
         }
     }
 }
@@ -537,12 +1016,35 @@ impl Supergraph {
         path: Option<String>,
         introspection: Option<bool>,
         preview_defer_support: Option<bool>,
+        defer_context_keys: Option<Vec<String>>,
+        variable_coercion: Option<VariableCoercion>,
+        unknown_variables: Option<UnknownVariables>,
+        large_integers: Option<LargeIntegers>,
+        array_merge_strategy: Option<ArrayMergeStrategy>,
+        panic_message: Option<String>,
+        require_operation_name: Option<bool>,
+        accept_graphql_content_type: Option<bool>,
+        default_variables: Option<HashMap<String, Value>>,
+        subgraph_url_trailing_slash: Option<TrailingSlash>,
+        strip_unrequested_fields: Option<bool>,
     ) -> Self {
         Self {
             listen: listen.unwrap_or_else(test_listen),
             path: path.unwrap_or_else(default_graphql_path),
             introspection: introspection.unwrap_or_else(default_graphql_introspection),
             preview_defer_support: preview_defer_support.unwrap_or_else(default_defer_support),
+            defer_context_keys,
+            variable_coercion: variable_coercion.unwrap_or_default(),
+            unknown_variables: unknown_variables.unwrap_or_default(),
+            large_integers: large_integers.unwrap_or_default(),
+            array_merge_strategy: array_merge_strategy.unwrap_or_default(),
+            panic_message: panic_message.unwrap_or_else(default_panic_message),
+            require_operation_name: require_operation_name.unwrap_or_default(),
+            accept_graphql_content_type: accept_graphql_content_type.unwrap_or_default(),
+            default_variables: default_variables.unwrap_or_default(),
+            subgraph_url_trailing_slash: subgraph_url_trailing_slash.unwrap_or_default(),
+            strip_unrequested_fields: strip_unrequested_fields
+                .unwrap_or_else(default_strip_unrequested_fields),
         }
     }
 }
@@ -621,6 +1123,9 @@ impl Sandbox {
 pub(crate) struct Homepage {
     #[serde(default = "default_homepage")]
     pub(crate) enabled: bool,
+
+    /// Custom HTML served instead of the default home page
+    pub(crate) html: Option<String>,
 }
 
 fn default_homepage() -> bool {
@@ -630,9 +1135,10 @@ fn default_homepage() -> bool {
 #[buildstructor::buildstructor]
 impl Homepage {
     #[builder]
-    pub(crate) fn new(enabled: Option<bool>) -> Self {
+    pub(crate) fn new(enabled: Option<bool>, html: Option<String>) -> Self {
         Self {
             enabled: enabled.unwrap_or_else(default_homepage),
+            html,
         }
     }
 }
@@ -641,9 +1147,10 @@ impl Homepage {
 #[buildstructor::buildstructor]
 impl Homepage {
     #[builder]
-    pub(crate) fn fake_new(enabled: Option<bool>) -> Self {
+    pub(crate) fn fake_new(enabled: Option<bool>, html: Option<String>) -> Self {
         Self {
             enabled: enabled.unwrap_or_else(default_homepage),
+            html,
         }
     }
 }
@@ -659,9 +1166,14 @@ impl Default for Homepage {
 struct HomepageTemplate {}
 
 impl Homepage {
-    pub(crate) fn display_page() -> Bytes {
-        let template = HomepageTemplate {};
-        template.render().unwrap().into()
+    pub(crate) fn display_page(&self) -> Bytes {
+        match &self.html {
+            Some(html) => Bytes::from(html.clone()),
+            None => {
+                let template = HomepageTemplate {};
+                template.render().unwrap().into()
+            }
+        }
     }
 }
 
@@ -713,6 +1225,321 @@ impl Default for HealthCheck {
     }
 }
 
+/// Configuration options pertaining to schema reloads.
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct SchemaReload {
+    /// Reject a new schema, and keep the current one, when it removes a subgraph
+    /// that has a URL override or traffic shaping configuration. Otherwise, only log a warning
+    /// default: false
+    #[serde(default)]
+    pub(crate) strict_subgraph_check: bool,
+}
+
+#[buildstructor::buildstructor]
+impl SchemaReload {
+    #[builder]
+    pub(crate) fn new(strict_subgraph_check: Option<bool>) -> Self {
+        Self {
+            strict_subgraph_check: strict_subgraph_check.unwrap_or_default(),
+        }
+    }
+}
+
+impl Default for SchemaReload {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+/// Configuration options pertaining to request limits.
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Limits {
+    /// Maximum size in bytes of the query string of a request, variables excluded.
+    /// Longer queries are rejected before parsing
+    /// default: unbounded
+    pub(crate) max_query_bytes: Option<usize>,
+
+    /// Maximum number of variables sent with a request.
+    /// Requests with more variables are rejected before planning
+    /// default: unbounded
+    pub(crate) max_variables: Option<usize>,
+
+    /// Maximum depth of the response data merged from subgraph responses.
+    /// Deeper values are replaced with null and an error is added to the response
+    /// default: unbounded
+    pub(crate) max_response_depth: Option<usize>,
+
+    /// Maximum size in bytes of the responses sent for a request, the deferred responses added up.
+    /// The response exceeding the limit is replaced with an error and ends the response stream
+    /// default: unbounded
+    pub(crate) max_response_bytes: Option<usize>,
+
+    /// Maximum number of subgraph fetches executed for a single request, deferred parts included.
+    /// The fetches over the limit are not sent and an error is added to the response
+    /// default: unbounded
+    pub(crate) max_subgraph_fetches: Option<usize>,
+
+    /// Maximum number of deferred responses waiting to be sent to a client.
+    /// Once it is reached, the execution of the deferred parts waits for the client to read
+    /// default: 10
+    #[serde(default = "default_max_buffered_deferred_responses")]
+    pub(crate) max_buffered_deferred_responses: usize,
+
+    /// Maximum nesting of fragment spreads in a query, a fragment spreading another fragment
+    /// counting as two levels. Deeper queries are rejected before planning.
+    /// Cyclic fragments are always rejected
+    /// default: unbounded
+    pub(crate) max_fragment_depth: Option<usize>,
+
+    /// Respond with an error when planning a query takes longer than this duration.
+    /// The planner still finishes its work, but the request stops waiting for it
+    /// default: 30s
+    #[serde(with = "humantime_serde", default = "default_query_planning_timeout")]
+    #[schemars(with = "String")]
+    pub(crate) query_planning_timeout: Duration,
+}
+
+fn default_max_buffered_deferred_responses() -> usize {
+    10
+}
+
+fn default_query_planning_timeout() -> Duration {
+    Duration::from_secs(30)
+}
+
+#[buildstructor::buildstructor]
+impl Limits {
+    #[builder]
+    pub(crate) fn new(
+        max_query_bytes: Option<usize>,
+        max_variables: Option<usize>,
+        max_response_depth: Option<usize>,
+        max_response_bytes: Option<usize>,
+        max_subgraph_fetches: Option<usize>,
+        max_buffered_deferred_responses: Option<usize>,
+        max_fragment_depth: Option<usize>,
+        query_planning_timeout: Option<Duration>,
+    ) -> Self {
+        Self {
+            max_query_bytes,
+            max_variables,
+            max_response_depth,
+            max_response_bytes,
+            max_subgraph_fetches,
+            max_buffered_deferred_responses: max_buffered_deferred_responses
+                .unwrap_or_else(default_max_buffered_deferred_responses),
+            max_fragment_depth,
+            query_planning_timeout: query_planning_timeout
+                .unwrap_or_else(default_query_planning_timeout),
+        }
+    }
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+/// Configuration options pertaining to request batching.
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Batching {
+    /// Accept JSON arrays of GraphQL requests in POST requests, and answer with an array of responses
+    #[serde(default)]
+    pub(crate) enabled: bool,
+
+    /// Maximum number of GraphQL requests in a batch
+    #[serde(default = "default_batching_max_size")]
+    pub(crate) max_size: usize,
+}
+
+fn default_batching_max_size() -> usize {
+    10
+}
+
+#[buildstructor::buildstructor]
+impl Batching {
+    #[builder]
+    pub(crate) fn new(enabled: Option<bool>, max_size: Option<usize>) -> Self {
+        Self {
+            enabled: enabled.unwrap_or_default(),
+            max_size: max_size.unwrap_or_else(default_batching_max_size),
+        }
+    }
+}
+
+impl Default for Batching {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+/// Configuration options pertaining to the query plans computed before serving traffic.
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Warmup {
+    /// When the configured operations are planned
+    /// default: lazy
+    #[serde(default)]
+    pub(crate) mode: WarmupMode,
+
+    /// Operations planned before serving traffic in eager mode
+    #[serde(default)]
+    pub(crate) operations: Vec<WarmupOperation>,
+
+    /// Maximum duration of the eager warmup. The router serves traffic once it elapses,
+    /// even if some operations are not planned yet
+    /// default: 30s
+    #[serde(with = "humantime_serde", default = "default_warmup_timeout")]
+    #[schemars(with = "String")]
+    pub(crate) timeout: Duration,
+}
+
+fn default_warmup_timeout() -> Duration {
+    Duration::from_secs(30)
+}
+
+#[buildstructor::buildstructor]
+impl Warmup {
+    #[builder]
+    pub(crate) fn new(
+        mode: Option<WarmupMode>,
+        operations: Vec<WarmupOperation>,
+        timeout: Option<Duration>,
+    ) -> Self {
+        Self {
+            mode: mode.unwrap_or_default(),
+            operations,
+            timeout: timeout.unwrap_or_else(default_warmup_timeout),
+        }
+    }
+}
+
+impl Default for Warmup {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+/// When the warmup operations are planned
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum WarmupMode {
+    /// Plan the operations before serving traffic, the router starts or switches to a new
+    /// schema or configuration once they are planned
+    Eager,
+    /// Serve traffic immediately, the operations are planned on their first request
+    Lazy,
+}
+
+impl Default for WarmupMode {
+    fn default() -> Self {
+        WarmupMode::Lazy
+    }
+}
+
+/// An operation planned during the warmup
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct WarmupOperation {
+    /// The GraphQL document of the operation
+    pub(crate) query: String,
+
+    /// The name of the operation, required if the document contains several operations
+    #[serde(default)]
+    pub(crate) operation_name: Option<String>,
+}
+
+/// Configuration options pertaining to the values used when subgraph fetches fail.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Fallbacks {
+    /// Values used instead of an error when the fetch of a field fails, by response path,
+    /// like `topProducts/@/reviews`. Only nullable fields get a fallback value
+    #[serde(default)]
+    pub(crate) fields: HashMap<String, Value>,
+}
+
+/// Configuration of the requests to the subgraphs.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct SubgraphConfiguration {
+    /// Applied to all the subgraphs
+    #[serde(default)]
+    pub(crate) all: Option<SubgraphSettings>,
+    /// Applied to specific subgraphs, taking precedence over `all`
+    #[serde(default)]
+    pub(crate) subgraphs: HashMap<String, SubgraphSettings>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct SubgraphSettings {
+    /// Value of the Accept header of the requests to this subgraph, for subgraphs negotiating the format of their responses (default: application/json, application/graphql-response+json)
+    pub(crate) accept: Option<String>,
+    /// URL of the requests to this subgraph, with `{name}` placeholders filled with the context values of each request, like `https://{region}.example.com/graphql` (default: the URL of the subgraph in the supergraph schema)
+    pub(crate) url_template: Option<String>,
+    /// Fail the requests to this subgraph answered with neither data nor errors, instead of handling them as responses without data (default: false)
+    pub(crate) reject_empty_responses: Option<bool>,
+}
+
+impl SubgraphConfiguration {
+    /// The value of a setting for this subgraph, else for all the subgraphs
+    fn setting<T>(
+        &self,
+        name: &str,
+        setting: impl Fn(&SubgraphSettings) -> Option<T>,
+    ) -> Option<T> {
+        self.subgraphs
+            .get(name)
+            .and_then(&setting)
+            .or_else(|| self.all.as_ref().and_then(&setting))
+    }
+
+    /// Accept header of the requests to this subgraph, if not the default one
+    pub(crate) fn accept(&self, name: &str) -> Option<HeaderValue> {
+        self.setting(name, |settings| settings.accept.clone())
+            .and_then(|accept| HeaderValue::from_str(&accept).ok())
+    }
+
+    /// URL template of the requests to this subgraph, if they are not sent to the schema URL
+    pub(crate) fn url_template(&self, name: &str) -> Option<UrlTemplate> {
+        self.setting(name, |settings| settings.url_template.clone())
+            .and_then(|url_template| UrlTemplate::parse(&url_template).ok())
+    }
+
+    /// Whether the responses of this subgraph with neither data nor errors fail the request
+    pub(crate) fn reject_empty_responses(&self, name: &str) -> bool {
+        self.setting(name, |settings| settings.reject_empty_responses)
+            .unwrap_or_default()
+    }
+}
+
+/// Configuration options pertaining to the redaction of sensitive response data.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Redaction {
+    /// How the values of fields are redacted, by response path, like `me/email` or
+    /// `topProducts/@/reviews/@/author`. Applies to the response data, whatever subgraph returned it
+    #[serde(default)]
+    pub(crate) fields: HashMap<String, RedactionStrategy>,
+}
+
+/// How the value of a redacted field is replaced.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+pub(crate) enum RedactionStrategy {
+    /// Replace the value with null. Only allowed for nullable fields
+    Nullify,
+    /// Replace the value with this string. Only allowed for String and ID fields
+    Mask(String),
+    /// Replace the value with the hex encoded SHA-256 hash of the string, or of the JSON of a numeric ID. Only allowed for String and ID fields
+    Hash,
+}
+
 /// Configuration options pertaining to the http server component.
 #[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
@@ -721,20 +1548,180 @@ pub(crate) struct Server {
     /// default: 4096
     #[serde(default = "default_parser_recursion_limit")]
     pub(crate) experimental_parser_recursion_limit: usize,
+
+    /// Compression of the responses sent to clients
+    #[serde(default)]
+    pub(crate) compression: Compression,
+
+    /// Options of the TCP sockets the router listens on
+    #[serde(default)]
+    pub(crate) socket: Socket,
+
+    /// Close client connections that have not sent or received anything for this duration.
+    /// 0s disables the timeout
+    /// default: 60s
+    #[serde(with = "humantime_serde", default = "default_idle_timeout")]
+    #[schemars(with = "String")]
+    pub(crate) idle_timeout: Duration,
+
+    /// Limit of the connections of each client IP
+    #[serde(default)]
+    pub(crate) connection_limit: ConnectionLimit,
+}
+
+fn default_idle_timeout() -> Duration {
+    Duration::from_secs(60)
 }
 
 #[buildstructor::buildstructor]
 impl Server {
     #[builder]
     #[allow(clippy::too_many_arguments)] // Used through a builder, not directly
-    pub(crate) fn new(parser_recursion_limit: Option<usize>) -> Self {
+    pub(crate) fn new(
+        parser_recursion_limit: Option<usize>,
+        compression: Option<Compression>,
+        socket: Option<Socket>,
+        idle_timeout: Option<Duration>,
+        connection_limit: Option<ConnectionLimit>,
+    ) -> Self {
         Self {
             experimental_parser_recursion_limit: parser_recursion_limit
                 .unwrap_or_else(default_parser_recursion_limit),
+            compression: compression.unwrap_or_default(),
+            socket: socket.unwrap_or_default(),
+            idle_timeout: idle_timeout.unwrap_or_else(default_idle_timeout),
+            connection_limit: connection_limit.unwrap_or_default(),
+        }
+    }
+}
+
+/// Configuration options pertaining to the number of connections of each client IP.
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ConnectionLimit {
+    /// Maximum number of open connections from a single IP, further connections are closed
+    /// right away. No limit if not set
+    #[serde(default)]
+    pub(crate) max_connections_per_ip: Option<NonZeroUsize>,
+
+    /// IPs of the proxies in front of the router, whose connections are not limited. The client
+    /// IP of their requests is read from the `X-Forwarded-For` header instead, and a client IP
+    /// with as many requests in flight as the connection limit gets 429 responses
+    #[serde(default)]
+    pub(crate) trusted_proxies: Vec<IpAddr>,
+}
+
+#[buildstructor::buildstructor]
+impl ConnectionLimit {
+    #[builder]
+    pub(crate) fn new(
+        max_connections_per_ip: Option<NonZeroUsize>,
+        trusted_proxies: Vec<IpAddr>,
+    ) -> Self {
+        Self {
+            max_connections_per_ip,
+            trusted_proxies,
         }
     }
 }
 
+/// Configuration options pertaining to the compression of responses.
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Compression {
+    /// Compress responses when the client supports it, with gzip, brotli or deflate.
+    /// Multipart responses, such as `@defer` ones, are never compressed
+    /// default: true
+    #[serde(default = "default_compression")]
+    pub(crate) enabled: bool,
+
+    /// Responses with a body smaller than this size in bytes are not compressed
+    /// default: 32
+    #[serde(default = "default_compression_min_size")]
+    pub(crate) min_size: u16,
+}
+
+fn default_compression() -> bool {
+    true
+}
+
+fn default_compression_min_size() -> u16 {
+    32
+}
+
+#[buildstructor::buildstructor]
+impl Compression {
+    #[builder]
+    pub(crate) fn new(enabled: Option<bool>, min_size: Option<u16>) -> Self {
+        Self {
+            enabled: enabled.unwrap_or_else(default_compression),
+            min_size: min_size.unwrap_or_else(default_compression_min_size),
+        }
+    }
+}
+
+impl Default for Compression {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
+/// Configuration options pertaining to the TCP sockets of the listeners.
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Socket {
+    /// Send small packets of the accepted connections without delay, disabling Nagle's algorithm
+    /// (TCP_NODELAY)
+    /// default: true
+    #[serde(default = "default_nodelay")]
+    pub(crate) nodelay: bool,
+
+    /// Allow binding the listen address while connections of a previous listener on it are
+    /// still closing (SO_REUSEADDR). Ignored on Windows
+    /// default: true
+    #[serde(default = "default_reuse_address")]
+    pub(crate) reuse_address: bool,
+
+    /// Maximum number of connections waiting to be accepted
+    /// default: 1024
+    #[serde(default = "default_backlog")]
+    pub(crate) backlog: u32,
+}
+
+fn default_nodelay() -> bool {
+    true
+}
+
+fn default_reuse_address() -> bool {
+    true
+}
+
+fn default_backlog() -> u32 {
+    1024
+}
+
+#[buildstructor::buildstructor]
+impl Socket {
+    #[builder]
+    pub(crate) fn new(
+        nodelay: Option<bool>,
+        reuse_address: Option<bool>,
+        backlog: Option<u32>,
+    ) -> Self {
+        Self {
+            nodelay: nodelay.unwrap_or_else(default_nodelay),
+            reuse_address: reuse_address.unwrap_or_else(default_reuse_address),
+            backlog: backlog.unwrap_or_else(default_backlog),
+        }
+    }
+}
+
+impl Default for Socket {
+    fn default() -> Self {
+        Self::builder().build()
+    }
+}
+
 /// Listening address.
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Deserialize, Serialize, JsonSchema)]
 #[serde(untagged)]
@@ -19,13 +19,18 @@ use super::Configuration;
 use super::ConfigurationError;
 use super::APOLLO_PLUGIN_PREFIX;
 
-/// Generate a JSON schema for the configuration.
-pub(crate) fn generate_config_schema() -> RootSchema {
-    let settings = SchemaSettings::draft07().with(|s| {
+/// Settings of the generation of configuration schemas.
+pub(crate) fn schema_settings() -> SchemaSettings {
+    SchemaSettings::draft07().with(|s| {
         s.option_nullable = true;
         s.option_add_null_type = false;
         s.inline_subschemas = true;
-    });
+    })
+}
+
+/// Generate a JSON schema for the configuration.
+pub(crate) fn generate_config_schema() -> RootSchema {
+    let settings = schema_settings();
 
     // Manually patch up the schema
     // We don't want to allow unknown fields, but serde doesn't work if we put the annotation on Configuration as the struct has a flattened type.
@@ -37,6 +42,35 @@ pub(crate) fn generate_config_schema() -> RootSchema {
     schema
 }
 
+/// Convert a yaml parsing error into a nice error snippet pointing at its location, if it is known.
+fn yaml_parse_error(raw_yaml: &str, error: serde_yaml::Error) -> ConfigurationError {
+    const NUMBER_OF_PREVIOUS_LINES_TO_DISPLAY: usize = 5;
+    match error.location() {
+        Some(location) => {
+            let yaml_split_by_lines = raw_yaml.split('\n').collect::<Vec<_>>();
+            let end = location.line().min(yaml_split_by_lines.len());
+            let lines = yaml_split_by_lines
+                [end.saturating_sub(NUMBER_OF_PREVIOUS_LINES_TO_DISPLAY)..end]
+                .iter()
+                .join("\n");
+            ConfigurationError::InvalidYaml {
+                line: location.line(),
+                column: location.column(),
+                error: format!(
+                    "{}\n\n{}\n{}^-----",
+                    error,
+                    lines,
+                    " ".repeat(location.column().saturating_sub(1)),
+                ),
+            }
+        }
+        None => ConfigurationError::InvalidConfiguration {
+            message: "failed to parse yaml",
+            error: error.to_string(),
+        },
+    }
+}
+
 /// Validate config yaml against the generated json schema.
 /// This is a tricky problem, and the solution here is by no means complete.
 /// In the case that validation cannot be performed then it will let serde validate as normal. The
@@ -61,12 +95,8 @@ pub(crate) fn validate_yaml_configuration(
         raw_yaml.to_string()
     };
 
-    let yaml = &serde_yaml::from_str(&defaulted_yaml).map_err(|e| {
-        ConfigurationError::InvalidConfiguration {
-            message: "failed to parse yaml",
-            error: e.to_string(),
-        }
-    })?;
+    let yaml =
+        &serde_yaml::from_str(&defaulted_yaml).map_err(|e| yaml_parse_error(&defaulted_yaml, e))?;
 
     let expanded_yaml = expand_env_variables(yaml, expansion)?;
     let schema = serde_json::to_value(generate_config_schema()).map_err(|e| {
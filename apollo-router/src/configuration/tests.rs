@@ -117,6 +117,41 @@ fn missing_subgraph_url() {
     }
 }
 
+#[test]
+fn malformed_subgraph_url() {
+    let schema_error = r#"
+        schema
+          @core(feature: "https://specs.apollo.dev/core/v0.1"),
+          @core(feature: "https://specs.apollo.dev/join/v0.1")
+        {
+          query: Query
+        }
+
+        type Query {
+          me: String
+        }
+
+        directive @core(feature: String!) repeatable on SCHEMA
+
+        directive @join__graph(name: String!, url: String!) on ENUM_VALUE
+
+        enum join__Graph {
+          ACCOUNTS @join__graph(name: "accounts" url: "http://localhost:4001/graphql")
+          REVIEWS @join__graph(name: "reviews" url: "localhost:4002/graphql")
+        }"#;
+    let schema_error = crate::Schema::parse(schema_error, &Default::default())
+        .expect_err("Must have an error because we have one malformed subgraph routing url");
+
+    if let SchemaError::UrlParse(subgraph, _) = &schema_error {
+        assert_eq!(subgraph, "reviews");
+    } else {
+        panic!(
+            "expected a malformed subgraph URL for 'reviews', got: {:?}",
+            schema_error
+        );
+    }
+}
+
 #[test]
 fn cors_defaults() {
     let cors = Cors::builder().build();
@@ -226,6 +261,30 @@ telemetry:
     insta::assert_snapshot!(error.to_string());
 }
 
+#[test]
+fn line_precise_yaml_parse_errors() {
+    let error = validate_yaml_configuration(
+        r#"supergraph:
+  listen: 127.0.0.1:4000
+  path: /graphql: bad
+  introspection: true
+  "#,
+        Expansion::default().unwrap(),
+    )
+    .expect_err("should have resulted in an error");
+    assert!(matches!(
+        error,
+        ConfigurationError::InvalidYaml { line: 3, .. }
+    ));
+    let error = error.to_string();
+    assert!(
+        error.starts_with("could not parse yaml at line 3"),
+        "{}",
+        error
+    );
+    assert!(error.contains("  path: /graphql: bad\n"), "{}", error);
+}
+
 #[test]
 fn line_precise_config_errors_with_errors_after_first_field() {
     let error = validate_yaml_configuration(
@@ -549,3 +608,116 @@ supergraph:
 
     assert!(config.supergraph.introspection);
 }
+
+#[test]
+fn telemetry_logging_is_compatible_on_reload() {
+    let config = |telemetry: &str| {
+        validate_yaml_configuration(
+            &format!("telemetry:\n{telemetry}"),
+            Expansion::default().unwrap(),
+        )
+        .expect("must be valid configuration")
+    };
+
+    let current = config("  logging:\n    filter: info\n");
+    assert!(current
+        .is_compatible(&config("  logging:\n    filter: apollo_router=debug\n"))
+        .is_ok());
+    assert!(current
+        .is_compatible(&config(
+            "  logging:\n    filter: info\n  tracing:\n    trace_config:\n      service_name: router\n"
+        ))
+        .is_err());
+}
+
+#[test]
+fn only_subgraph_urls_changed() {
+    let config = |yaml: &str| {
+        validate_yaml_configuration(yaml, Expansion::default().unwrap())
+            .expect("must be valid configuration")
+    };
+
+    let current = config("override_subgraph_url:\n  accounts: http://localhost:8080\n");
+    assert!(current.only_subgraph_urls_changed(&config(
+        "override_subgraph_url:\n  accounts: http://localhost:8081\n"
+    )));
+    assert!(!current.only_subgraph_urls_changed(&current));
+    assert!(!current.only_subgraph_urls_changed(&config(
+        "override_subgraph_url:\n  accounts: http://localhost:8081\nsupergraph:\n  path: /custom\n"
+    )));
+}
+
+#[test]
+fn changed_plugins() {
+    let config = |yaml: &str| {
+        validate_yaml_configuration(yaml, Expansion::default().unwrap())
+            .expect("must be valid configuration")
+    };
+    let headers = |name: &str| {
+        format!(
+            "headers:\n  all:\n    request:\n      - remove:\n          named: {}\n",
+            name
+        )
+    };
+    let csrf = "csrf:\n  unsafe_disabled: false\n";
+
+    let current = config(&format!("{}{}", headers("test"), csrf));
+    assert_eq!(
+        current.changed_plugins(&config(&format!("{}{}", headers("other"), csrf))),
+        Some(vec!["apollo.headers".to_string()])
+    );
+    assert_eq!(current.changed_plugins(&current), None);
+    // removing a plugin
+    assert_eq!(current.changed_plugins(&config(&headers("other"))), None);
+    // changing more than plugins
+    assert_eq!(
+        current.changed_plugins(&config(&format!(
+            "{}{}supergraph:\n  path: /custom\n",
+            headers("other"),
+            csrf
+        ))),
+        None
+    );
+}
+
+#[test]
+fn subgraph_settings() {
+    let config = Configuration::from_str(
+        r#"
+subgraph:
+  all:
+    accept: application/json
+    reject_empty_responses: true
+  subgraphs:
+    products:
+      accept: application/graphql-response+json
+      url_template: "https://{region}.example.com/graphql"
+      reject_empty_responses: false
+"#,
+    )
+    .expect("must be valid configuration");
+    assert_eq!(
+        config.subgraph.accept("reviews"),
+        Some(HeaderValue::from_static("application/json"))
+    );
+    assert_eq!(
+        config.subgraph.accept("products"),
+        Some(HeaderValue::from_static(
+            "application/graphql-response+json"
+        ))
+    );
+    assert!(config.subgraph.url_template("products").is_some());
+    assert!(config.subgraph.url_template("reviews").is_none());
+    assert!(config.subgraph.reject_empty_responses("reviews"));
+    assert!(!config.subgraph.reject_empty_responses("products"));
+    assert!(config.configured_subgraphs().contains("products"));
+
+    assert!(
+        Configuration::from_str("subgraph:\n  all:\n    accept: \"application/json\\n\"\n")
+            .is_err()
+    );
+    assert!(Configuration::from_str(
+        "subgraph:\n  subgraphs:\n    products:\n      url_template: \"https://{region.example.com\"\n"
+    )
+    .is_err());
+}
@@ -3,12 +3,18 @@
 //! Router plugins accept a mutable [`Context`] when invoked and this contains a DashMap which
 //! allows additional data to be passed back and forth along the request invocation pipeline.
 
+use std::collections::BTreeMap;
+use std::collections::HashMap;
 use std::sync::Arc;
 use std::time::Instant;
 
+use dashmap::mapref::entry::Entry;
 use dashmap::mapref::multiple::RefMulti;
 use dashmap::mapref::multiple::RefMutMulti;
 use dashmap::DashMap;
+use once_cell::sync::OnceCell;
+use schemars::JsonSchema;
+use serde::Deserialize;
 use serde::Serialize;
 use tower::BoxError;
 
@@ -17,6 +23,53 @@ use crate::json_ext::Value;
 /// Holds [`Context`] entries.
 pub(crate) type Entries = Arc<DashMap<String, Value>>;
 
+/// Prefixes of the keys of the entries used by the router itself
+const INTERNAL_KEY_PREFIXES: [&str; 2] = ["apollo_", "experimental::"];
+
+fn is_internal(key: &str) -> bool {
+    INTERNAL_KEY_PREFIXES
+        .iter()
+        .any(|prefix| key.starts_with(prefix))
+}
+
+/// Merge of a value written in the context with the current value of its key, like the values
+/// written by the responses of several subgraphs
+#[derive(Clone, Copy, Debug, Deserialize, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ContextMerge {
+    /// Keep the smallest number
+    Min,
+    /// Keep the largest number
+    Max,
+    /// Keep the last value written
+    Last,
+    /// Keep the last value written, and all the values written in an array under the
+    /// `<key>::collected` key
+    Collect,
+}
+
+impl ContextMerge {
+    /// Merges a value with the current one. Values that are not numbers replace the current
+    /// value for `min` and `max`
+    fn merge(self, current: &Value, value: Value) -> Value {
+        match (self, current.as_f64(), value.as_f64()) {
+            (ContextMerge::Min, Some(current_number), Some(number)) if current_number <= number => {
+                current.clone()
+            }
+            (ContextMerge::Max, Some(current_number), Some(number)) if current_number >= number => {
+                current.clone()
+            }
+            _ => value,
+        }
+    }
+}
+
+/// The key holding the values written to a key merged with [`ContextMerge::Collect`], so that
+/// the key itself keeps the type of the written values
+fn collected_key(key: &str) -> String {
+    format!("{}::collected", key)
+}
+
 /// A map of arbitrary JSON values, for use by plugins.
 ///
 /// Context makes use of [`DashMap`] under the hood which tries to handle concurrency
@@ -32,6 +85,10 @@ pub struct Context {
     // Allows adding custom entries to the context.
     entries: Entries,
 
+    // Merge strategies of the keys, set once when the request enters the supergraph service,
+    // before any plugin writes to the context.
+    merges: Arc<OnceCell<Arc<HashMap<String, ContextMerge>>>>,
+
     /// Creation time
     pub(crate) created_at: Instant,
 }
@@ -41,6 +98,7 @@ impl Context {
     pub fn new() -> Self {
         Context {
             entries: Default::default(),
+            merges: Default::default(),
             created_at: Instant::now(),
         }
     }
@@ -52,6 +110,8 @@ impl Context {
     /// Semantics:
     ///  - If the operation fails, then the key is not present.
     ///  - If the operation succeeds, the value is an [`Option`].
+    ///  - If the key is merged with the `collect` strategy, the value is the last written one,
+    ///    and all the written values are in an array under the `<key>::collected` key.
     pub fn get<K, V>(&self, key: K) -> Result<Option<V>, BoxError>
     where
         K: Into<String>,
@@ -69,30 +129,92 @@ impl Context {
     /// Semantics:
     ///  - If the operation fails, then the pair has not been inserted.
     ///  - If the operation succeeds, the result is the old value as an [`Option`].
+    ///  - If the key has a merge strategy, the value is merged with the old value instead of
+    ///    replacing it, and an old value that is not a `V` is returned as `None`.
     pub fn insert<K, V>(&self, key: K, value: V) -> Result<Option<V>, BoxError>
     where
         K: Into<String>,
         V: for<'de> serde::Deserialize<'de> + Serialize,
     {
+        let key = key.into();
+        let merged = self.merge_of(&key).is_some();
         match serde_json_bytes::to_value(value) {
-            Ok(value) => self
-                .entries
-                .insert(key.into(), value)
-                .map(|v| serde_json_bytes::from_value(v))
-                .transpose()
-                .map_err(|e| e.into()),
+            Ok(value) => match self.insert_json_value(key, value) {
+                Some(v) if merged => Ok(serde_json_bytes::from_value(v).ok()),
+                v => v
+                    .map(|v| serde_json_bytes::from_value(v))
+                    .transpose()
+                    .map_err(|e| e.into()),
+            },
             Err(e) => Err(e.into()),
         }
     }
 
     /// Insert a value in the context using the provided key and value.
     ///
-    /// Semantics: the result is the old value as an [`Option`].
+    /// Semantics:
+    ///  - The result is the old value as an [`Option`].
+    ///  - If the key has a merge strategy, the value is merged with the old value instead of
+    ///    replacing it.
     pub fn insert_json_value<K>(&self, key: K, value: Value) -> Option<Value>
     where
         K: Into<String>,
     {
-        self.entries.insert(key.into(), value)
+        let key = key.into();
+        match self.merge_of(&key) {
+            Some(ContextMerge::Collect) => {
+                // the entry holds the lock of the collected key, and is released before writing
+                // the key itself, which may be in the same shard
+                match self.entries.entry(collected_key(&key)) {
+                    Entry::Occupied(mut entry) => match entry.get_mut() {
+                        Value::Array(values) => values.push(value.clone()),
+                        current => *current = Value::Array(vec![current.clone(), value.clone()]),
+                    },
+                    Entry::Vacant(entry) => {
+                        entry.insert(Value::Array(vec![value.clone()]));
+                    }
+                }
+                self.entries.insert(key, value)
+            }
+            // the entry holds the lock of the key, so concurrent writes are merged one by one
+            Some(merge) => match self.entries.entry(key) {
+                Entry::Occupied(mut entry) => {
+                    let merged = merge.merge(entry.get(), value);
+                    Some(entry.insert(merged))
+                }
+                Entry::Vacant(entry) => {
+                    entry.insert(value);
+                    None
+                }
+            },
+            None => self.entries.insert(key, value),
+        }
+    }
+
+    /// Copies the entries of the given keys, and the entries of the router itself, into a new
+    /// context. The writes to the copy are not visible in this context
+    pub(crate) fn with_keys(&self, keys: &[String]) -> Context {
+        Context {
+            entries: Arc::new(
+                self.entries
+                    .iter()
+                    .filter(|entry| is_internal(entry.key()) || keys.contains(entry.key()))
+                    .map(|entry| (entry.key().clone(), entry.value().clone()))
+                    .collect(),
+            ),
+            merges: self.merges.clone(),
+            created_at: self.created_at,
+        }
+    }
+
+    /// Sets the merge strategies of the keys, before the request is handled. They are set only
+    /// once: the strategies set first are kept
+    pub(crate) fn set_merges(&self, merges: Arc<HashMap<String, ContextMerge>>) {
+        let _ = self.merges.set(merges);
+    }
+
+    fn merge_of(&self, key: &str) -> Option<ContextMerge> {
+        self.merges.get()?.get(key).copied()
     }
 
     /// Get a json value from the context using the provided key.
@@ -151,6 +273,46 @@ impl Context {
     pub fn iter_mut(&self) -> impl Iterator<Item = RefMutMulti<'_, String, Value>> + '_ {
         self.entries.iter_mut()
     }
+
+    /// Serialize the entries with the provided keys to JSON, to store them outside of the router.
+    ///
+    /// Semantics:
+    ///  - Missing keys and the entries used internally by the router are skipped.
+    ///  - The result can be restored in another context with [`Context::deserialize_entries`].
+    pub fn serialize_entries<K>(
+        &self,
+        keys: impl IntoIterator<Item = K>,
+    ) -> Result<Vec<u8>, BoxError>
+    where
+        K: Into<String>,
+    {
+        let entries: BTreeMap<String, Value> = keys
+            .into_iter()
+            .map(Into::into)
+            .filter(|key| !is_internal(key))
+            .filter_map(|key| {
+                let value = self.entries.get(&key)?.value().clone();
+                Some((key, value))
+            })
+            .collect();
+        Ok(serde_json::to_vec(&entries)?)
+    }
+
+    /// Insert the entries serialized by [`Context::serialize_entries`] in the context.
+    ///
+    /// Semantics:
+    ///  - If the operation fails, then no entry has been inserted.
+    ///  - If the operation succeeds, the entries replace the existing ones with the same keys,
+    ///    except the entries used internally by the router, which are skipped.
+    pub fn deserialize_entries(&self, serialized: &[u8]) -> Result<(), BoxError> {
+        let entries: BTreeMap<String, Value> = serde_json::from_slice(serialized)?;
+        for (key, value) in entries {
+            if !is_internal(&key) {
+                self.entries.insert(key, value);
+            }
+        }
+        Ok(())
+    }
 }
 
 impl Default for Context {
@@ -224,4 +386,40 @@ mod test {
         assert_eq!(c.get("one").unwrap(), Some(2));
         assert_eq!(c.get("two").unwrap(), Some(3));
     }
+
+    #[test]
+    fn it_restores_serialized_entries() {
+        let c = Context::new();
+        assert!(c.insert("user", "alice".to_string()).is_ok());
+        assert!(c.insert("cart", vec![1, 2, 3]).is_ok());
+        assert!(c.insert("not_selected", true).is_ok());
+        assert!(c
+            .insert("apollo_telemetry::client_name", "client".to_string())
+            .is_ok());
+
+        let serialized = c
+            .serialize_entries(["user", "cart", "missing", "apollo_telemetry::client_name"])
+            .unwrap();
+
+        let restored = Context::new();
+        assert!(restored.insert("user", "bob".to_string()).is_ok());
+        restored.deserialize_entries(&serialized).unwrap();
+        assert_eq!(restored.iter().count(), 2);
+        assert_eq!(restored.get("user").unwrap(), Some("alice".to_string()));
+        assert_eq!(restored.get("cart").unwrap(), Some(vec![1, 2, 3]));
+        assert_eq!(restored.get::<_, bool>("not_selected").unwrap(), None);
+        assert_eq!(
+            restored
+                .get::<_, String>("apollo_telemetry::client_name")
+                .unwrap(),
+            None
+        );
+
+        // internal entries cannot be injected from the outside
+        restored
+            .deserialize_entries(br#"{"apollo_telemetry::studio::exclude": true}"#)
+            .unwrap();
+        assert_eq!(restored.iter().count(), 2);
+        assert!(restored.deserialize_entries(b"not json").is_err());
+    }
 }
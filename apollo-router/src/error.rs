@@ -1,5 +1,6 @@
 //! Router errors.
 use std::sync::Arc;
+use std::time::Duration;
 
 use displaydoc::Display;
 use lazy_static::__Deref;
@@ -20,6 +21,7 @@ pub(crate) use crate::configuration::ConfigurationError;
 pub(crate) use crate::graphql::Error;
 use crate::graphql::IntoGraphQLErrors;
 use crate::graphql::Response;
+use crate::json_ext::Object;
 use crate::json_ext::Path;
 use crate::json_ext::Value;
 use crate::spec::SpecError;
@@ -46,6 +48,18 @@ pub(crate) enum FetchError {
         name: String,
     },
 
+    /// variable '{name}' needs an implicit coercion, which is disabled by 'supergraph.variable_coercion: strict'
+    ValidationCoercedVariable {
+        /// Name of the variable.
+        name: String,
+    },
+
+    /// variable '{name}' is not declared by the operation, which is rejected by 'supergraph.unknown_variables: reject'
+    ValidationUnknownVariable {
+        /// Name of the variable.
+        name: String,
+    },
+
     /// query could not be planned: {reason}
     ValidationPlanningError {
         /// The failure reason.
@@ -73,6 +87,21 @@ pub(crate) enum FetchError {
         reason: String,
     },
 
+    /// service '{service}' returned a response with neither data nor errors
+    SubrequestEmptyResponse {
+        /// The service that returned the empty response.
+        service: String,
+    },
+
+    /// could not build the URL of service '{service}': {reason}
+    SubrequestUrlError {
+        /// The service of the request.
+        service: String,
+
+        /// The reason the URL could not be built.
+        reason: String,
+    },
+
     /// service '{service}' returned a PATCH response which was not expected
     SubrequestUnexpectedPatchResponse {
         /// The service that returned the PATCH response.
@@ -200,15 +229,25 @@ pub(crate) enum QueryPlannerError {
 
     /// introspection error: {0}
     Introspection(IntrospectionError),
+
+    /// query planning took longer than {0:?}
+    Timeout(Duration),
 }
 
 impl IntoGraphQLErrors for QueryPlannerError {
     fn into_graphql_errors(self) -> Result<Vec<Error>, Self> {
         match self {
-            QueryPlannerError::SpecError(err) => Ok(vec![Error {
-                message: err.to_string(),
-                ..Default::default()
-            }]),
+            QueryPlannerError::SpecError(err) => {
+                let mut extensions = Object::new();
+                if let Some(code) = err.extension_code() {
+                    extensions.insert("code", code.into());
+                }
+                Ok(vec![Error {
+                    message: err.to_string(),
+                    extensions,
+                    ..Default::default()
+                }])
+            }
             QueryPlannerError::SchemaValidationErrors(errs) => errs
                 .into_graphql_errors()
                 .map_err(QueryPlannerError::SchemaValidationErrors),
@@ -217,6 +256,15 @@ impl IntoGraphQLErrors for QueryPlannerError {
                 .iter()
                 .map(|p_err| Error::from(p_err.clone()))
                 .collect()),
+            err @ QueryPlannerError::Timeout(_) => {
+                let mut extensions = Object::new();
+                extensions.insert("code", "QUERY_PLANNING_TIMEOUT".into());
+                Ok(vec![Error {
+                    message: err.to_string(),
+                    extensions,
+                    ..Default::default()
+                }])
+            }
             err => Err(err),
         }
     }
@@ -334,7 +382,7 @@ impl std::fmt::Display for PlanErrors {
 #[non_exhaustive]
 pub(crate) enum SchemaError {
     /// URL parse error for subgraph {0}: {1}
-    UrlParse(String, http::uri::InvalidUri),
+    UrlParse(String, String),
     /// Could not find an URL for subgraph {0}
     MissingSubgraphUrl(String),
     /// Parsing error(s).
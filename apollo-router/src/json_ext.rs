@@ -5,6 +5,7 @@
 use std::cmp::min;
 use std::fmt;
 
+use schemars::JsonSchema;
 use serde::Deserialize;
 use serde::Serialize;
 use serde_json_bytes::ByteString;
@@ -42,6 +43,21 @@ macro_rules! ensure_object {
     }};
 }
 
+/// How arrays are combined when merging JSON values, like the lists of entities returned by
+/// several fetches
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum ArrayMergeStrategy {
+    /// Merge the elements at the same index, and append the extra elements of the other array
+    #[default]
+    ByIndex,
+    /// Append all the elements of the other array
+    Concatenate,
+    /// Merge the objects with the same value for this key, like entities with the same `id`, and
+    /// append the other elements
+    ByKey(String),
+}
+
 #[doc(hidden)]
 /// Extension trait for [`serde_json::Value`].
 pub(crate) trait ValueExt {
@@ -50,6 +66,11 @@ pub(crate) trait ValueExt {
     #[track_caller]
     fn deep_merge(&mut self, other: Self);
 
+    /// Deep merge the JSON objects like [`ValueExt::deep_merge`], combining the arrays with the
+    /// given strategy.
+    #[track_caller]
+    fn deep_merge_with(&mut self, other: Self, strategy: &ArrayMergeStrategy);
+
     /// Returns `true` if the values are equal and the objects are ordered the same.
     ///
     /// **Note:** this is recursive.
@@ -94,6 +115,10 @@ pub(crate) trait ValueExt {
 
 impl ValueExt for Value {
     fn deep_merge(&mut self, other: Self) {
+        self.deep_merge_with(other, &ArrayMergeStrategy::ByIndex)
+    }
+
+    fn deep_merge_with(&mut self, other: Self, strategy: &ArrayMergeStrategy) {
         match (self, other) {
             (Value::Object(a), Value::Object(b)) => {
                 for (key, value) in b.into_iter() {
@@ -102,18 +127,35 @@ impl ValueExt for Value {
                             e.insert(value);
                         }
                         Entry::Occupied(e) => {
-                            e.into_mut().deep_merge(value);
+                            e.into_mut().deep_merge_with(value, strategy);
                         }
                     }
                 }
             }
-            (Value::Array(a), Value::Array(mut b)) => {
-                for (b_value, a_value) in b.drain(..min(a.len(), b.len())).zip(a.iter_mut()) {
-                    a_value.deep_merge(b_value);
-                }
+            (Value::Array(a), Value::Array(mut b)) => match strategy {
+                ArrayMergeStrategy::ByIndex => {
+                    for (b_value, a_value) in b.drain(..min(a.len(), b.len())).zip(a.iter_mut()) {
+                        a_value.deep_merge_with(b_value, strategy);
+                    }
 
-                a.extend(b.into_iter());
-            }
+                    a.extend(b.into_iter());
+                }
+                ArrayMergeStrategy::Concatenate => a.extend(b.into_iter()),
+                ArrayMergeStrategy::ByKey(key) => {
+                    for b_value in b {
+                        let a_value = match b_value.get(key.as_str()) {
+                            Some(b_key) if !b_key.is_null() => a
+                                .iter_mut()
+                                .find(|a_value| a_value.get(key.as_str()) == Some(b_key)),
+                            _ => None,
+                        };
+                        match a_value {
+                            Some(a_value) => a_value.deep_merge_with(b_value, strategy),
+                            None => a.push(b_value),
+                        }
+                    }
+                }
+            },
             (_, Value::Null) => {}
             (Value::Object(_), Value::Array(_)) => {
                 failfast_debug!("trying to replace an object with an array");
@@ -678,6 +720,61 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_deep_merge_by_index() {
+        let mut json = json!({"obj":{"arr":[{"id":1,"prop1":1},{"id":2}]}});
+        json.deep_merge_with(
+            json!({"obj":{"arr":[{"id":2,"prop2":2},{"id":1},{"id":3}]}}),
+            &ArrayMergeStrategy::ByIndex,
+        );
+        assert_eq!(
+            json,
+            json!({"obj":{"arr":[{"id":2,"prop1":1,"prop2":2},{"id":1},{"id":3}]}})
+        );
+    }
+
+    #[test]
+    fn test_deep_merge_concatenate() {
+        let mut json = json!({"obj":{"arr":[{"id":1,"prop1":1},{"id":2}]},"other":1});
+        json.deep_merge_with(
+            json!({"obj":{"arr":[{"id":2,"prop2":2}]},"other":2}),
+            &ArrayMergeStrategy::Concatenate,
+        );
+        assert_eq!(
+            json,
+            json!({"obj":{"arr":[{"id":1,"prop1":1},{"id":2},{"id":2,"prop2":2}]},"other":2})
+        );
+    }
+
+    #[test]
+    fn test_deep_merge_by_key() {
+        // the entities are returned in a different order, one of them is new
+        let mut json = json!({"obj":{"arr":[
+            {"id":1,"prop1":1,"reviews":[{"id":"a"}]},
+            {"id":2,"prop1":2},
+            {"prop1":3},
+        ]}});
+        json.deep_merge_with(
+            json!({"obj":{"arr":[
+                {"id":2,"prop2":2},
+                {"id":1,"prop2":1,"reviews":[{"id":"a","body":"good"},{"id":"b"}]},
+                {"id":4},
+                {"prop2":3},
+            ]}}),
+            &ArrayMergeStrategy::ByKey("id".to_string()),
+        );
+        assert_eq!(
+            json,
+            json!({"obj":{"arr":[
+                {"id":1,"prop1":1,"prop2":1,"reviews":[{"id":"a","body":"good"},{"id":"b"}]},
+                {"id":2,"prop1":2,"prop2":2},
+                {"prop1":3},
+                {"id":4},
+                {"prop2":3},
+            ]}})
+        );
+    }
+
     #[test]
     fn test_is_subset_eq() {
         assert_is_subset!(
@@ -76,13 +76,21 @@ mod uplink;
 pub use crate::configuration::Configuration;
 pub use crate::configuration::ListenAddr;
 pub use crate::context::Context;
+pub use crate::context::ContextMerge;
 pub use crate::executable::main;
 pub use crate::executable::Executable;
 pub use crate::router::ApolloRouterError;
 pub use crate::router::ConfigurationSource;
 pub use crate::router::RouterHttpServer;
+pub use crate::router::SchemaDiagnostic;
+pub use crate::router::SchemaReloadCallback;
 pub use crate::router::SchemaSource;
+pub use crate::router::SchemaStreamConnector;
+pub use crate::router::SchemaValidator;
 pub use crate::router::ShutdownSource;
+pub use crate::router::SubgraphClientHook;
+pub use crate::router::SubgraphResponseAdapter;
+pub use crate::router::SupergraphSchema;
 pub use crate::router_factory::Endpoint;
 pub use crate::test_harness::MockedSubgraphs;
 pub use crate::test_harness::TestHarness;
@@ -95,6 +103,9 @@ pub mod _private {
     pub use router_bridge;
     pub use serde_json;
 
+    // For benchmarks
+    #[cfg(feature = "bench")]
+    pub use crate::plugins::telemetry::metrics::apollo::duration_histogram::bench::DurationHistogram;
     // For tests
     pub use crate::plugins::telemetry::Telemetry as TelemetryPlugin;
     pub use crate::router_factory::create_test_service_factory_from_yaml;
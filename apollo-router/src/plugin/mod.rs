@@ -19,6 +19,7 @@ pub mod serde;
 pub mod test;
 
 use std::any::TypeId;
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::sync::Arc;
 use std::sync::Mutex;
@@ -32,19 +33,21 @@ use futures::future::BoxFuture;
 use multimap::MultiMap;
 use once_cell::sync::Lazy;
 use schemars::gen::SchemaGenerator;
+use schemars::schema::RootSchema;
 use schemars::JsonSchema;
 use tower::buffer::future::ResponseFuture;
-use tower::buffer::Buffer;
 use tower::BoxError;
 use tower::Service;
-use tower::ServiceBuilder;
 
-use crate::layers::ServiceBuilderExt;
+use crate::configuration::schema_settings;
 use crate::router_factory::Endpoint;
 use crate::services::execution;
+use crate::services::measured_buffer::BufferMetrics;
+use crate::services::measured_buffer::MeasuredBuffer;
 use crate::services::subgraph;
 use crate::services::supergraph;
 use crate::transport;
+use crate::ContextMerge;
 use crate::ListenAddr;
 
 type InstanceFactory =
@@ -148,6 +151,26 @@ pub(crate) fn plugins() -> HashMap<String, PluginFactory> {
     PLUGIN_REGISTRY.lock().expect("Lock poisoned").clone()
 }
 
+/// Get the names of the registered plugins, with the JSON schema of their configuration.
+///
+/// Each schema is generated like the router's configuration schema, and can validate the
+/// configuration of its plugin on its own or be combined into a larger configuration schema.
+pub fn plugin_schemas() -> BTreeMap<String, RootSchema> {
+    plugins()
+        .into_iter()
+        .map(|(name, factory)| {
+            let mut gen = schema_settings().into_generator();
+            let schema = factory.create_schema(&mut gen).into_object();
+            let root = RootSchema {
+                meta_schema: gen.settings().meta_schema.clone(),
+                schema,
+                definitions: gen.take_definitions(),
+            };
+            (name, root)
+        })
+        .collect()
+}
+
 /// All router plugins must implement the Plugin trait.
 ///
 /// This trait defines lifecycle hooks that enable hooking into Apollo Router services.
@@ -210,6 +233,27 @@ pub trait Plugin: Send + Sync + 'static {
         MultiMap::new()
     }
 
+    /// Return the merge strategies of the context keys written by this plugin.
+    /// They are set on the context of each request before any plugin runs, so that the values
+    /// written to those keys by several stages, like several subgraph responses, are merged
+    /// instead of replacing each other.
+    fn context_merges(&self) -> HashMap<String, ContextMerge> {
+        HashMap::new()
+    }
+
+    /// This is invoked when the configuration of this plugin changes on a hot reload.
+    /// Return `true` if the plugin applied the new configuration to itself: the router then
+    /// keeps serving requests with the same plugins.
+    /// By default, and when returning `false`, the router is rebuilt with new instances of all
+    /// the plugins, created by [`Plugin::new`]. In that case, the plugins that already applied
+    /// their new configuration are called again with their previous one.
+    ///
+    /// The services created by the plugin may still be handling requests, so the configuration
+    /// must be updated through interior mutability.
+    async fn on_config_reload(&self, _init: PluginInit<Self::Config>) -> Result<bool, BoxError> {
+        Ok(false)
+    }
+
     /// Support downcasting.
     #[cfg(test)]
     fn as_any(&self) -> &dyn std::any::Any
@@ -256,6 +300,17 @@ pub(crate) trait DynPlugin: Send + Sync + 'static {
     /// Return one or several `Endpoint`s and `ListenAddr` and the router will serve your custom web Endpoint(s).
     fn web_endpoints(&self) -> MultiMap<ListenAddr, Endpoint>;
 
+    /// Return the merge strategies of the context keys written by this plugin.
+    fn context_merges(&self) -> HashMap<String, ContextMerge>;
+
+    /// Applies the new configuration of the plugin on a hot reload.
+    /// Returns false if the plugin must be created again instead.
+    async fn on_config_reload(
+        &self,
+        configuration: &serde_json::Value,
+        supergraph_sdl: Arc<String>,
+    ) -> Result<bool, BoxError>;
+
     fn as_any(&self) -> &dyn std::any::Any;
 }
 
@@ -286,6 +341,19 @@ where
         self.web_endpoints()
     }
 
+    fn context_merges(&self) -> HashMap<String, ContextMerge> {
+        self.context_merges()
+    }
+
+    async fn on_config_reload(
+        &self,
+        configuration: &serde_json::Value,
+        supergraph_sdl: Arc<String>,
+    ) -> Result<bool, BoxError> {
+        let init = PluginInit::try_new(configuration.clone(), supergraph_sdl)?;
+        Plugin::on_config_reload(self, init).await
+    }
+
     fn as_any(&self) -> &dyn std::any::Any {
         self
     }
@@ -316,15 +384,19 @@ macro_rules! register_plugin {
 /// Handler represents a [`Plugin`] endpoint.
 #[derive(Clone)]
 pub(crate) struct Handler {
-    service: Buffer<transport::BoxService, transport::Request>,
+    service: MeasuredBuffer<transport::BoxService, transport::Request>,
 }
 
 impl Handler {
     pub(crate) fn new(service: transport::BoxService) -> Self {
         Self {
-            service: ServiceBuilder::new().buffered().service(service),
+            service: MeasuredBuffer::new(service, BufferMetrics::default()),
         }
     }
+
+    pub(crate) fn set_buffer_metrics(&self, metrics: BufferMetrics) {
+        self.service.set_metrics(metrics);
+    }
 }
 
 impl Service<transport::Request> for Handler {
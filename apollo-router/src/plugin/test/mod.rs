@@ -14,12 +14,22 @@ pub use service::MockSupergraphService;
 use tower::util::BoxService;
 use tower::BoxError;
 use tower::Service;
+#[cfg(test)]
+use tower::ServiceExt;
 
 pub(crate) use self::mock::canned;
+#[cfg(test)]
+use crate::plugin::DynPlugin;
 use crate::services::subgraph_service::SubgraphServiceFactory;
 use crate::services::MakeSubgraphService;
 use crate::services::Plugins;
 use crate::services::SubgraphRequest;
+#[cfg(test)]
+use crate::services::SubgraphResponse;
+#[cfg(test)]
+use crate::services::SupergraphRequest;
+#[cfg(test)]
+use crate::services::SupergraphResponse;
 
 #[derive(Clone)]
 pub(crate) struct MockSubgraphFactory {
@@ -44,3 +54,44 @@ impl SubgraphServiceFactory for MockSubgraphFactory {
         })
     }
 }
+
+/// Creates an instance of the registered plugin `name`, like the router does with its configuration
+#[cfg(test)]
+pub(crate) async fn create_plugin(name: &str, config: &serde_json::Value) -> Box<dyn DynPlugin> {
+    crate::plugin::plugins()
+        .get(name)
+        .expect("Plugin not found")
+        .create_instance(config, Default::default())
+        .await
+        .unwrap()
+}
+
+/// Sends a request through the supergraph service of a plugin, wrapping a mock service
+#[cfg(test)]
+pub(crate) async fn call_supergraph(
+    plugin: &dyn DynPlugin,
+    mock_service: MockSupergraphService,
+    request: SupergraphRequest,
+) -> SupergraphResponse {
+    plugin
+        .supergraph_service(BoxService::new(mock_service))
+        .oneshot(request)
+        .await
+        .unwrap()
+}
+
+/// Sends a request through the service of a plugin for the subgraph `name`, wrapping a mock
+/// service
+#[cfg(test)]
+pub(crate) async fn call_subgraph(
+    plugin: &dyn DynPlugin,
+    name: &str,
+    mock_service: MockSubgraphService,
+    request: SubgraphRequest,
+) -> SubgraphResponse {
+    plugin
+        .subgraph_service(name, BoxService::new(mock_service))
+        .oneshot(request)
+        .await
+        .unwrap()
+}
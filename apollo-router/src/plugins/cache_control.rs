@@ -0,0 +1,339 @@
+//! Set the `Cache-Control` header of the responses from the cache hints of the subgraphs.
+//!
+//! Each subgraph response gives a cache hint, from a header or an extension, and the hints are
+//! merged in the context: the smallest max age and the most restrictive scope win. Responses
+//! to mutations, responses with errors and deferred responses are never cached.
+
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use futures::stream;
+use futures::StreamExt;
+use http::header::CACHE_CONTROL;
+use http::HeaderName;
+use http::HeaderValue;
+use http::StatusCode;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use tower::BoxError;
+use tower::ServiceBuilder;
+use tower::ServiceExt;
+
+use crate::graphql;
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
+use crate::register_plugin;
+use crate::services::subgraph;
+use crate::services::supergraph;
+use crate::services::OPERATION_KIND;
+use crate::Context;
+use crate::ContextMerge;
+use crate::SubgraphResponse;
+use crate::SupergraphResponse;
+
+register_plugin!("apollo", "cache_control", CacheControl);
+
+/// Smallest max age of the subgraph responses, in seconds
+const MAX_AGE: &str = "apollo_cache_control::max_age";
+/// Whether a subgraph response is private, as 0 or 1
+const PRIVATE: &str = "apollo_cache_control::private";
+
+const NO_STORE: &str = "no-store";
+
+fn default_header() -> String {
+    CACHE_CONTROL.to_string()
+}
+
+/// Cache-Control header of the responses, from the cache hints of the subgraphs
+#[derive(Clone, Debug, JsonSchema, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    /// Header of the subgraph responses with their cache hint, in the `Cache-Control` format (default: cache-control)
+    #[serde(default = "default_header")]
+    header: String,
+    /// Extension of the subgraph responses with their cache hint, like `{"maxAge": 60, "scope": "PRIVATE"}`, read when the header is missing
+    extension: Option<String>,
+}
+
+/// The cache hint of a subgraph response
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct CacheHint {
+    max_age: u64,
+    private: bool,
+}
+
+impl CacheHint {
+    /// Parses a `Cache-Control` value. `no-store` and `no-cache` mean a max age of zero
+    fn from_header(value: &str) -> Self {
+        let mut hint = CacheHint::default();
+        let mut no_store = false;
+        for directive in value.split(',').map(|directive| directive.trim()) {
+            let directive = directive.to_ascii_lowercase();
+            match directive.split_once('=') {
+                Some(("max-age", max_age)) => {
+                    hint.max_age = max_age.trim_matches('"').parse().unwrap_or_default()
+                }
+                _ if directive == "private" => hint.private = true,
+                _ if directive == "no-store" || directive == "no-cache" => no_store = true,
+                _ => {}
+            }
+        }
+        if no_store {
+            hint.max_age = 0;
+        }
+        hint
+    }
+
+    /// Parses an extension like `{"maxAge": 60, "scope": "PRIVATE"}`
+    fn from_extension(value: &serde_json_bytes::Value) -> Self {
+        CacheHint {
+            max_age: value
+                .get("maxAge")
+                .and_then(|max_age| max_age.as_u64())
+                .unwrap_or_default(),
+            private: value
+                .get("scope")
+                .and_then(|scope| scope.as_str())
+                .map(|scope| scope.eq_ignore_ascii_case("private"))
+                .unwrap_or_default(),
+        }
+    }
+}
+
+pub(crate) struct CacheControl {
+    header: HeaderName,
+    extension: Option<String>,
+}
+
+#[async_trait::async_trait]
+impl Plugin for CacheControl {
+    type Config = Config;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        let header = HeaderName::from_str(&init.config.header)
+            .map_err(|e| format!("invalid header name '{}': {}", init.config.header, e))?;
+        Ok(CacheControl {
+            header,
+            extension: init.config.extension,
+        })
+    }
+
+    fn supergraph_service(&self, service: supergraph::BoxService) -> supergraph::BoxService {
+        ServiceBuilder::new()
+            .map_future(|future| async move {
+                let response: SupergraphResponse = future.await?;
+                let SupergraphResponse { context, response } = response;
+                // the subgraph requests of the primary response are done with its first part
+                let (mut parts, stream) = response.into_parts();
+                let (first_response, rest) = stream.into_future().await;
+                if let Some(value) =
+                    response_cache_control(&context, parts.status, first_response.as_ref())
+                {
+                    parts.headers.insert(CACHE_CONTROL, value);
+                }
+                Ok::<_, BoxError>(SupergraphResponse {
+                    context,
+                    response: http::Response::from_parts(
+                        parts,
+                        stream::iter(first_response).chain(rest).boxed(),
+                    ),
+                })
+            })
+            .service(service)
+            .boxed()
+    }
+
+    fn subgraph_service(&self, _name: &str, service: subgraph::BoxService) -> subgraph::BoxService {
+        let header = self.header.clone();
+        let extension = self.extension.clone();
+        service
+            .map_response(move |response: SubgraphResponse| {
+                let hint = match response
+                    .response
+                    .headers()
+                    .get(&header)
+                    .and_then(|value| value.to_str().ok())
+                {
+                    Some(value) => CacheHint::from_header(value),
+                    None => extension
+                        .as_ref()
+                        .and_then(|name| response.response.body().extensions.get(name.as_str()))
+                        .map(CacheHint::from_extension)
+                        // a response without cache hint is not cacheable
+                        .unwrap_or_default(),
+                };
+                let _ = response.context.insert(MAX_AGE, hint.max_age);
+                let _ = response.context.insert(PRIVATE, hint.private as u8);
+                response
+            })
+            .boxed()
+    }
+
+    fn context_merges(&self) -> HashMap<String, ContextMerge> {
+        // the smallest max age and the most restrictive scope of the subgraph responses win
+        HashMap::from([
+            (MAX_AGE.to_string(), ContextMerge::Min),
+            (PRIVATE.to_string(), ContextMerge::Max),
+        ])
+    }
+}
+
+/// The `Cache-Control` header of a response, or `None` if no subgraph gave a cache hint
+fn response_cache_control(
+    context: &Context,
+    status: StatusCode,
+    first_response: Option<&graphql::Response>,
+) -> Option<HeaderValue> {
+    let is_mutation = context
+        .get::<_, String>(OPERATION_KIND)
+        .ok()
+        .flatten()
+        .as_deref()
+        == Some("mutation");
+    let has_errors = first_response
+        .map(|response| !response.errors.is_empty())
+        .unwrap_or(true);
+    // the deferred parts of the response are not covered by the cache hints
+    let is_deferred = first_response
+        .and_then(|response| response.has_next)
+        .unwrap_or_default();
+    if is_mutation || has_errors || is_deferred || !status.is_success() {
+        return Some(HeaderValue::from_static(NO_STORE));
+    }
+    let max_age: u64 = context.get(MAX_AGE).ok().flatten()?;
+    if max_age == 0 {
+        return Some(HeaderValue::from_static(NO_STORE));
+    }
+    let private = context
+        .get::<_, u8>(PRIVATE)
+        .ok()
+        .flatten()
+        .unwrap_or_default()
+        > 0;
+    HeaderValue::from_str(&format!(
+        "max-age={}, {}",
+        max_age,
+        if private { "private" } else { "public" }
+    ))
+    .ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use http::header::CACHE_CONTROL;
+    use http::HeaderValue;
+    use http::StatusCode;
+    use tower::ServiceExt;
+
+    use super::response_cache_control;
+    use super::CacheHint;
+    use super::MAX_AGE;
+    use super::NO_STORE;
+    use crate::graphql;
+    use crate::Context;
+    use crate::SupergraphRequest;
+    use crate::TestHarness;
+
+    #[test]
+    fn it_parses_cache_hints() {
+        assert_eq!(
+            CacheHint::from_header("max-age=60, public"),
+            CacheHint {
+                max_age: 60,
+                private: false
+            }
+        );
+        assert_eq!(
+            CacheHint::from_header("Private, max-age=\"30\""),
+            CacheHint {
+                max_age: 30,
+                private: true
+            }
+        );
+        assert_eq!(
+            CacheHint::from_header("max-age=60, no-store"),
+            CacheHint::default()
+        );
+        assert_eq!(
+            CacheHint::from_extension(
+                &serde_json_bytes::json!({"maxAge": 120, "scope": "PRIVATE"})
+            ),
+            CacheHint {
+                max_age: 120,
+                private: true
+            }
+        );
+    }
+
+    #[test]
+    fn it_does_not_cache_deferred_responses() {
+        let context = Context::new();
+        context.insert(MAX_AGE, 60_u64).unwrap();
+        let response = graphql::Response::builder()
+            .data(serde_json_bytes::json!({"topProducts": []}))
+            .build();
+        assert_eq!(
+            response_cache_control(&context, StatusCode::OK, Some(&response)).unwrap(),
+            "max-age=60, public"
+        );
+
+        let deferred = graphql::Response::builder()
+            .data(serde_json_bytes::json!({"topProducts": []}))
+            .has_next(true)
+            .build();
+        assert_eq!(
+            response_cache_control(&context, StatusCode::OK, Some(&deferred)).unwrap(),
+            NO_STORE
+        );
+    }
+
+    #[tokio::test]
+    async fn it_keeps_the_smallest_max_age_of_the_subgraphs() {
+        let response = TestHarness::builder()
+            .configuration_json(serde_json::json!({
+                "cache_control": {
+                    "extension": "cacheControl"
+                }
+            }))
+            .unwrap()
+            .subgraph_hook(|name, service| {
+                let name = name.to_string();
+                service
+                    .map_response(move |mut response| {
+                        match name.as_str() {
+                            "products" => {
+                                response.response.headers_mut().insert(
+                                    CACHE_CONTROL,
+                                    HeaderValue::from_static("max-age=60, public"),
+                                );
+                            }
+                            "reviews" => {
+                                response
+                                    .response
+                                    .headers_mut()
+                                    .insert(CACHE_CONTROL, HeaderValue::from_static("max-age=30"));
+                            }
+                            _ => {
+                                response.response.body_mut().extensions.insert(
+                                    "cacheControl".into(),
+                                    serde_json_bytes::json!({"maxAge": 120, "scope": "PUBLIC"}),
+                                );
+                            }
+                        }
+                        response
+                    })
+                    .boxed()
+            })
+            .build()
+            .await
+            .unwrap()
+            .oneshot(SupergraphRequest::canned_builder().build().unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(
+            response.response.headers().get(CACHE_CONTROL).unwrap(),
+            "max-age=30, public"
+        );
+    }
+}
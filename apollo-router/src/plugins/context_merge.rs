@@ -0,0 +1,155 @@
+//! Merge the context values written by several subgraph requests, instead of keeping the last one.
+
+use std::collections::HashMap;
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+use tower::BoxError;
+
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
+use crate::register_plugin;
+use crate::ContextMerge;
+
+register_plugin!("apollo", "context_merge", ContextMergePlugin);
+
+/// Merge of the context values written by several subgraph requests
+#[derive(Clone, Debug, JsonSchema, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    /// Merge strategy of each context key: `min`, `max`, `last` or `collect` (default: the last
+    /// value written replaces the others)
+    keys: HashMap<String, ContextMerge>,
+}
+
+pub(crate) struct ContextMergePlugin {
+    keys: HashMap<String, ContextMerge>,
+}
+
+#[async_trait::async_trait]
+impl Plugin for ContextMergePlugin {
+    type Config = Config;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        Ok(ContextMergePlugin {
+            keys: init.config.keys,
+        })
+    }
+
+    fn context_merges(&self) -> HashMap<String, ContextMerge> {
+        self.keys.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    use tower::ServiceExt;
+
+    use super::ContextMerge;
+    use crate::SupergraphRequest;
+    use crate::TestHarness;
+
+    #[tokio::test]
+    async fn it_keeps_the_minimum_ttl_of_the_subgraphs() {
+        let response = TestHarness::builder()
+            .configuration_json(serde_json::json!({
+                "context_merge": {
+                    "keys": { "cache_ttl": "min" }
+                }
+            }))
+            .unwrap()
+            .subgraph_hook(|name, service| {
+                let ttl = match name {
+                    "products" => 30,
+                    "reviews" => 60,
+                    _ => return service,
+                };
+                service
+                    .map_response(move |response| {
+                        response.context.insert("cache_ttl", ttl).unwrap();
+                        response
+                    })
+                    .boxed()
+            })
+            .build()
+            .await
+            .unwrap()
+            .oneshot(SupergraphRequest::canned_builder().build().unwrap())
+            .await
+            .unwrap();
+
+        // the reviews subgraph is queried after the products subgraph, and its TTL is not kept
+        assert_eq!(response.context.get("cache_ttl").unwrap(), Some(30));
+    }
+
+    #[tokio::test]
+    async fn it_merges_the_values_written_by_other_plugins() {
+        let response = TestHarness::builder()
+            .configuration_json(serde_json::json!({
+                "context_merge": {
+                    "keys": { "cache_ttl": "collect" }
+                }
+            }))
+            .unwrap()
+            .supergraph_hook(|service| {
+                service
+                    .map_request(|request: SupergraphRequest| {
+                        request.context.insert("cache_ttl", 10).unwrap();
+                        request
+                    })
+                    .boxed()
+            })
+            .subgraph_hook(|name, service| {
+                let ttl = match name {
+                    "products" => 30,
+                    _ => return service,
+                };
+                service
+                    .map_response(move |response| {
+                        response.context.insert("cache_ttl", ttl).unwrap();
+                        response
+                    })
+                    .boxed()
+            })
+            .build()
+            .await
+            .unwrap()
+            .oneshot(SupergraphRequest::canned_builder().build().unwrap())
+            .await
+            .unwrap();
+
+        assert_eq!(response.context.get("cache_ttl").unwrap(), Some(30));
+        assert_eq!(
+            response.context.get("cache_ttl::collected").unwrap(),
+            Some(vec![10, 30])
+        );
+    }
+
+    #[test]
+    fn it_merges_the_values_of_a_key() {
+        let context = crate::Context::new();
+        context.set_merges(Arc::new(HashMap::from([
+            ("low".to_string(), ContextMerge::Min),
+            ("high".to_string(), ContextMerge::Max),
+            ("all".to_string(), ContextMerge::Collect),
+        ])));
+        for value in [30, 10, 20] {
+            context.insert("low", value).unwrap();
+            context.insert("high", value).unwrap();
+            context.insert("all", value).unwrap();
+            context.insert("other", value).unwrap();
+        }
+        assert_eq!(context.get("low").unwrap(), Some(10));
+        assert_eq!(context.get("high").unwrap(), Some(30));
+        // the collected key keeps the type of the written values
+        assert_eq!(context.get("all").unwrap(), Some(20));
+        assert_eq!(
+            context.get("all::collected").unwrap(),
+            Some(vec![30, 10, 20])
+        );
+        assert_eq!(context.get("other").unwrap(), Some(20));
+    }
+}
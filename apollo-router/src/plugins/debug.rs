@@ -0,0 +1,214 @@
+//! Log the bodies of the requests and responses of chosen subgraphs, to debug an integration.
+//!
+//! There is no setting for all the subgraphs: each subgraph must be listed, so the bodies cannot
+//! be logged for the whole graph by accident.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use tower::BoxError;
+use tower::ServiceExt;
+
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
+use crate::plugins::diagnostics::redact;
+use crate::register_plugin;
+use crate::services::subgraph;
+use crate::SubgraphRequest;
+use crate::SubgraphResponse;
+
+register_plugin!("apollo", "debug", Debugging);
+
+#[derive(Clone, Debug, JsonSchema, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+struct Config {
+    /// Debugging options per subgraph
+    #[serde(default)]
+    subgraphs: HashMap<String, SubgraphDebug>,
+}
+
+#[derive(Clone, Debug, JsonSchema, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+struct SubgraphDebug {
+    /// Log the GraphQL request and response bodies of the subgraph at debug level
+    #[serde(default)]
+    log_bodies: bool,
+    /// Maximum size of a logged body in bytes, longer bodies are truncated
+    /// Defaults to 4096
+    #[serde(default = "default_max_body_size")]
+    max_body_size: usize,
+    /// Other names of the fields whose values are redacted from the logged bodies, in addition
+    /// to the ones redacted from the diagnostics endpoint
+    #[serde(default)]
+    redact: Vec<String>,
+}
+
+fn default_max_body_size() -> usize {
+    4096
+}
+
+struct Debugging {
+    config: Config,
+}
+
+#[async_trait::async_trait]
+impl Plugin for Debugging {
+    type Config = Config;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        Ok(Debugging {
+            config: init.config,
+        })
+    }
+
+    fn subgraph_service(&self, name: &str, service: subgraph::BoxService) -> subgraph::BoxService {
+        let debug = match self.config.subgraphs.get(name) {
+            Some(debug) if debug.log_bodies => Arc::new(debug.clone()),
+            _ => return service,
+        };
+
+        let request_debug = debug.clone();
+        let request_name = name.to_string();
+        let response_name = name.to_string();
+        service
+            .map_request(move |request: SubgraphRequest| {
+                tracing::debug!(
+                    subgraph = %request_name,
+                    body = %request_debug.format(request.subgraph_request.body()),
+                    "subgraph request"
+                );
+                request
+            })
+            .map_response(move |response: SubgraphResponse| {
+                tracing::debug!(
+                    subgraph = %response_name,
+                    body = %debug.format(response.response.body()),
+                    "subgraph response"
+                );
+                response
+            })
+            .boxed()
+    }
+}
+
+impl SubgraphDebug {
+    /// The redacted and truncated JSON body
+    fn format(&self, body: &impl Serialize) -> String {
+        let mut body = match serde_json::to_value(body) {
+            Ok(body) => body,
+            Err(err) => return format!("<body could not be serialized: {}>", err),
+        };
+        redact(&mut body, &self.redact);
+        let mut body = body.to_string();
+        if body.len() > self.max_body_size {
+            let mut end = self.max_body_size;
+            while !body.is_char_boundary(end) {
+                end -= 1;
+            }
+            body.truncate(end);
+            body.push_str("...(truncated)");
+        }
+        body
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json_bytes::json;
+
+    use super::*;
+    use crate::graphql;
+    use crate::plugin::test::call_subgraph;
+    use crate::plugin::test::create_plugin;
+    use crate::plugin::test::MockSubgraphService;
+
+    // The logs are captured with the internals of the tracing_test crate, like in the rhai
+    // plugin tests, to avoid installing a global subscriber.
+    #[tokio::test]
+    async fn it_logs_the_bodies_of_flagged_subgraphs_only() {
+        let mock_writer =
+            tracing_test::internal::MockWriter::new(&tracing_test::internal::GLOBAL_BUF);
+        let subscriber = tracing_test::internal::get_subscriber(mock_writer, "apollo_router=debug");
+        let _guard = tracing::dispatcher::set_default(&subscriber);
+
+        let plugin = create_plugin(
+            "apollo.debug",
+            &serde_json::json!({
+                "subgraphs": {
+                    "products": { "log_bodies": true, "max_body_size": 200, "redact": ["password"] },
+                    "accounts": { "log_bodies": false },
+                }
+            }),
+        )
+        .await;
+
+        for subgraph in ["products", "reviews", "accounts"] {
+            let mut mock_service = MockSubgraphService::new();
+            mock_service
+                .expect_call()
+                .times(1)
+                .returning(move |req: SubgraphRequest| {
+                    Ok(SubgraphResponse::fake_builder()
+                        .data(json!({ "answer": format!("{}_response_body", subgraph) }))
+                        .context(req.context)
+                        .build())
+                });
+            call_subgraph(
+                &*plugin,
+                subgraph,
+                mock_service,
+                SubgraphRequest::fake_builder()
+                    .subgraph_request(http::Request::new(
+                        graphql::Request::builder()
+                            .query(format!("{{ {}_request_body }}", subgraph))
+                            .variable("password", "hunter2")
+                            .variable("tail", "x".repeat(300))
+                            .build(),
+                    ))
+                    .build(),
+            )
+            .await;
+        }
+
+        let logged =
+            |value: &str| tracing_test::internal::logs_with_scope_contain("apollo_router", value);
+        assert!(logged("products_request_body"));
+        assert!(logged("products_response_body"));
+        assert!(logged("...(truncated)"));
+        assert!(!logged("hunter2"));
+        assert!(!logged("reviews_request_body"));
+        assert!(!logged("reviews_response_body"));
+        assert!(!logged("accounts_request_body"));
+        assert!(!logged("accounts_response_body"));
+    }
+
+    #[test]
+    fn it_redacts_and_truncates_bodies() {
+        let debug = SubgraphDebug {
+            log_bodies: true,
+            max_body_size: 40,
+            redact: vec!["ssn".to_string()],
+        };
+        assert_eq!(
+            debug.format(&serde_json::json!({ "user": { "ssn": "abc" }, "list": [{ "ssn": 1 }] })),
+            r#"{"user":{"ssn":"[REDACTED]"},"list":[{"s...(truncated)"#
+        );
+        // the secrets redacted from the diagnostics endpoint are redacted from the bodies too
+        assert_eq!(
+            debug.format(&serde_json::json!({ "password": "hunter2" })),
+            r#"{"password":"[REDACTED]"}"#
+        );
+        assert_eq!(
+            debug.format(&serde_json::json!({ "api_key": "abc" })),
+            r#"{"api_key":"[REDACTED]"}"#
+        );
+        // fields merely named after keys are kept
+        assert_eq!(
+            debug.format(&serde_json::json!({ "keys": ["upc"], "cache_key": "a" })),
+            r#"{"keys":["upc"],"cache_key":"a"}"#
+        );
+    }
+}
@@ -0,0 +1,285 @@
+//! Expose the active plugins and their configuration on an admin endpoint.
+
+use std::sync::Arc;
+use std::sync::RwLock;
+
+use http::header::CONTENT_TYPE;
+use http::HeaderValue;
+use http::Method;
+use http::StatusCode;
+use multimap::MultiMap;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use serde_json::Value;
+use tower::service_fn;
+use tower::BoxError;
+use tower::ServiceExt;
+
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
+use crate::register_plugin;
+use crate::router_factory::Endpoint;
+use crate::services::transport;
+use crate::ListenAddr;
+
+pub(crate) const APOLLO_DIAGNOSTICS: &str = "apollo.diagnostics";
+
+/// Configuration values are redacted when their key contains one of these, ignoring case and
+/// reading `-` as `_`. Keys are only secret when named so, unlike a `cache_key`
+const SECRET_KEY_FRAGMENTS: &[&str] = &[
+    "token",
+    "secret",
+    "password",
+    "api_key",
+    "apikey",
+    "private_key",
+    "access_key",
+    "signing_key",
+    "authorization",
+    "credential",
+];
+
+/// Keys naming the object they belong to, like the header of a header rule
+const NAME_KEYS: &[&str] = &["name", "named"];
+
+const REDACTED: &str = "[REDACTED]";
+
+register_plugin!("apollo", "diagnostics", Diagnostics);
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    /// Expose the diagnostics endpoint
+    enabled: bool,
+    /// The socket address and port to listen on, preferably a management port
+    /// Defaults to 127.0.0.1:9090
+    #[serde(default = "default_listen")]
+    listen: ListenAddr,
+    /// The HTTP path of the diagnostics endpoint
+    /// Defaults to /plugins
+    #[serde(default = "default_path")]
+    path: String,
+    /// Other key names of the plugin configurations to redact, in addition to the ones
+    /// containing token, secret, password, api_key, apikey, private_key, access_key,
+    /// signing_key, authorization or credential
+    #[serde(default)]
+    redact: Vec<String>,
+}
+
+fn default_listen() -> ListenAddr {
+    ListenAddr::SocketAddr("127.0.0.1:9090".parse().expect("valid listenAddr"))
+}
+
+fn default_path() -> String {
+    "/plugins".to_string()
+}
+
+/// A plugin of the router, as listed by the diagnostics endpoint
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub(crate) struct ActivePlugin {
+    pub(crate) name: String,
+    #[serde(rename = "type")]
+    pub(crate) type_name: &'static str,
+    pub(crate) config: Value,
+}
+
+pub(crate) struct Diagnostics {
+    config: Config,
+    // set once all the plugins are created, before the endpoint is served
+    plugins: Arc<RwLock<Vec<ActivePlugin>>>,
+}
+
+impl Diagnostics {
+    /// Records the active plugins, redacting their configuration
+    pub(crate) fn set_plugins(&self, plugins: Vec<ActivePlugin>) {
+        let plugins = plugins
+            .into_iter()
+            .map(|mut plugin| {
+                redact(&mut plugin.config, &self.config.redact);
+                plugin
+            })
+            .collect();
+        *self.plugins.write().expect("lock poisoned") = plugins;
+    }
+}
+
+fn is_secret(key: &str, redacted_keys: &[String]) -> bool {
+    let key = key.to_lowercase();
+    let normalized = key.replace('-', "_");
+    SECRET_KEY_FRAGMENTS
+        .iter()
+        .any(|fragment| normalized.contains(fragment))
+        || redacted_keys
+            .iter()
+            .any(|redacted| key == redacted.to_lowercase())
+}
+
+/// Replaces the values of the secret keys of a configuration, and of the other keys to redact
+///
+/// The other fields of an object named after a secret, like a header rule for `authorization`, are
+/// redacted too, and so are the values of the header insert rules, since they can set any header.
+pub(crate) fn redact(value: &mut Value, redacted_keys: &[String]) {
+    match value {
+        Value::Object(object) => {
+            let named_secret = NAME_KEYS
+                .iter()
+                .find_map(|key| object.get(*key))
+                .and_then(Value::as_str)
+                .map(|name| is_secret(name, redacted_keys))
+                .unwrap_or_default();
+            for (key, value) in object.iter_mut() {
+                if is_secret(key, redacted_keys)
+                    || (named_secret && !NAME_KEYS.contains(&key.as_str()))
+                {
+                    *value = Value::String(REDACTED.to_string());
+                } else {
+                    if key == "insert" {
+                        redact_inserted_values(value);
+                    }
+                    redact(value, redacted_keys);
+                }
+            }
+        }
+        Value::Array(values) => values
+            .iter_mut()
+            .for_each(|value| redact(value, redacted_keys)),
+        _ => {}
+    }
+}
+
+fn redact_inserted_values(value: &mut Value) {
+    match value {
+        Value::Object(object) => {
+            if let Some(value) = object.get_mut("value") {
+                *value = Value::String(REDACTED.to_string());
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(redact_inserted_values),
+        _ => {}
+    }
+}
+
+#[async_trait::async_trait]
+impl Plugin for Diagnostics {
+    type Config = Config;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        Ok(Diagnostics {
+            config: init.config,
+            plugins: Default::default(),
+        })
+    }
+
+    fn web_endpoints(&self) -> MultiMap<ListenAddr, Endpoint> {
+        let mut endpoints = MultiMap::new();
+        if self.config.enabled {
+            let plugins = self.plugins.clone();
+            let handler = service_fn(move |req: transport::Request| {
+                let plugins = plugins.clone();
+                async move {
+                    if req.method() != Method::GET {
+                        return http::Response::builder()
+                            .status(StatusCode::METHOD_NOT_ALLOWED)
+                            .body(hyper::Body::empty())
+                            .map_err(BoxError::from);
+                    }
+                    let body = serde_json::to_vec(&serde_json::json!({
+                        "plugins": *plugins.read().expect("lock poisoned"),
+                    }))?;
+                    http::Response::builder()
+                        .status(StatusCode::OK)
+                        .header(CONTENT_TYPE, HeaderValue::from_static("application/json"))
+                        .body(body.into())
+                        .map_err(BoxError::from)
+                }
+            });
+            endpoints.insert(
+                self.config.listen.clone(),
+                Endpoint::new(self.config.path.clone(), handler.boxed()),
+            );
+        }
+        endpoints
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn it_redacts_secrets() {
+        let mut config = json!({
+            "name": "albert",
+            "api_token": "abc",
+            "auth": { "Password": "def", "user": "bob" },
+            "keys": ["a", "b"],
+            "cache_key": "products",
+            "apiKey": "jkl",
+            "x-api-key": "mno",
+            "subgraphs": [{ "private": "ghi", "url": "http://localhost" }],
+        });
+        redact(&mut config, &["private".to_string()]);
+        assert_eq!(
+            config,
+            json!({
+                "name": "albert",
+                "api_token": REDACTED,
+                "auth": { "Password": REDACTED, "user": "bob" },
+                "keys": ["a", "b"],
+                "cache_key": "products",
+                "apiKey": REDACTED,
+                "x-api-key": REDACTED,
+                "subgraphs": [{ "private": REDACTED, "url": "http://localhost" }],
+            })
+        );
+    }
+
+    #[test]
+    fn it_redacts_header_values() {
+        let mut config = json!({
+            "all": {
+                "request": [
+                    { "insert": { "name": "x-tenant", "value": "acme" } },
+                    { "insert": { "name": "x-user", "from_context": "user" } },
+                    { "propagate": { "named": "x-trace-id", "default": "none" } },
+                    { "propagate": { "named": "x-client", "rename": "client" } },
+                    { "propagate": { "named": "authorization", "default": "Bearer abc" } },
+                ]
+            },
+            "subgraphs": {
+                "products": {
+                    "headers": [
+                        { "name": "Authorization", "value": "Bearer abc" },
+                        { "name": "x-custom", "value": "def", "pattern": "[a-z]+" }
+                    ]
+                }
+            }
+        });
+        redact(&mut config, &["x-custom".to_string()]);
+        assert_eq!(
+            config,
+            json!({
+                "all": {
+                    "request": [
+                        { "insert": { "name": "x-tenant", "value": REDACTED } },
+                        { "insert": { "name": "x-user", "from_context": "user" } },
+                        { "propagate": { "named": "x-trace-id", "default": "none" } },
+                        { "propagate": { "named": "x-client", "rename": "client" } },
+                        { "propagate": { "named": "authorization", "default": REDACTED } },
+                    ]
+                },
+                "subgraphs": {
+                    "products": {
+                        "headers": [
+                            { "name": "Authorization", "value": REDACTED },
+                            { "name": "x-custom", "value": REDACTED, "pattern": REDACTED }
+                        ]
+                    }
+                }
+            })
+        );
+    }
+}
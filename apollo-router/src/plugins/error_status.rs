@@ -0,0 +1,230 @@
+//! Override the HTTP status code of responses containing GraphQL errors.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use http::StatusCode;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use tower::BoxError;
+use tower::ServiceExt as _;
+
+use crate::graphql;
+use crate::layers::ServiceExt as _;
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
+use crate::register_plugin;
+use crate::services::supergraph;
+
+register_plugin!("apollo", "error_status", ErrorStatus);
+
+/// HTTP status code of the responses containing GraphQL errors
+#[derive(Clone, Debug, JsonSchema, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+enum Config {
+    #[serde(rename = "always_200")]
+    Always200,
+    #[serde(rename = "errors_as_4xx")]
+    ErrorsAs4xx,
+    Custom(HashMap<String, u16>),
+}
+
+#[derive(Clone)]
+enum Policy {
+    ErrorsAs4xx,
+    Custom(Arc<HashMap<String, StatusCode>>),
+}
+
+impl Policy {
+    fn status(&self, response: &graphql::Response) -> Option<StatusCode> {
+        if response.errors.is_empty() {
+            return None;
+        }
+        match self {
+            Policy::ErrorsAs4xx => Some(StatusCode::BAD_REQUEST),
+            // The first error with a mapped code decides the status
+            Policy::Custom(codes) => response.errors.iter().find_map(|error| {
+                error
+                    .extensions
+                    .get("code")
+                    .and_then(|code| code.as_str())
+                    .and_then(|code| codes.get(code))
+                    .copied()
+            }),
+        }
+    }
+}
+
+struct ErrorStatus {
+    policy: Option<Policy>,
+}
+
+#[async_trait::async_trait]
+impl Plugin for ErrorStatus {
+    type Config = Config;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        let policy = match init.config {
+            Config::Always200 => None,
+            Config::ErrorsAs4xx => Some(Policy::ErrorsAs4xx),
+            Config::Custom(codes) => Some(Policy::Custom(Arc::new(
+                codes
+                    .into_iter()
+                    .map(|(code, status)| match StatusCode::from_u16(status) {
+                        Ok(status) => Ok((code, status)),
+                        Err(e) => Err(format!(
+                            "invalid status {} for code '{}': {}",
+                            status, code, e
+                        )),
+                    })
+                    .collect::<Result<_, _>>()?,
+            ))),
+        };
+        Ok(ErrorStatus { policy })
+    }
+
+    fn supergraph_service(&self, service: supergraph::BoxService) -> supergraph::BoxService {
+        match self.policy.clone() {
+            Some(policy) => service
+                .map_first_graphql_response(move |_context, mut parts, response| {
+                    // Only successful responses are overridden: the router already chose
+                    // a more precise status for the other ones
+                    if parts.status == StatusCode::OK {
+                        if let Some(status) = policy.status(&response) {
+                            parts.status = status;
+                        }
+                    }
+                    (parts, response)
+                })
+                .boxed(),
+            None => service,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http::StatusCode;
+    use serde_json_bytes::json;
+
+    use crate::graphql;
+    use crate::plugin::test;
+    use crate::plugin::test::MockSupergraphService;
+    use crate::SupergraphRequest;
+    use crate::SupergraphResponse;
+
+    fn error(code: &str) -> graphql::Error {
+        graphql::Error::builder()
+            .message("something went wrong")
+            .extension("code", code)
+            .build()
+    }
+
+    async fn call_supergraph(
+        config: serde_json::Value,
+        data: Option<serde_json_bytes::Value>,
+        errors: Vec<graphql::Error>,
+    ) -> StatusCode {
+        let mut mock_service = MockSupergraphService::new();
+        mock_service.expect_call().times(1).returning(move |req| {
+            SupergraphResponse::fake_builder()
+                .and_data(data.clone())
+                .errors(errors.clone())
+                .context(req.context)
+                .build()
+        });
+
+        let plugin = test::create_plugin("apollo.error_status", &config).await;
+        let mut response = test::call_supergraph(
+            &*plugin,
+            mock_service,
+            SupergraphRequest::fake_builder().build().unwrap(),
+        )
+        .await;
+        let status = response.response.status();
+        // the GraphQL response is still sent
+        assert!(response.next_response().await.is_some());
+        status
+    }
+
+    #[tokio::test]
+    async fn always_200() {
+        let config = serde_json::json!("always_200");
+        assert_eq!(
+            call_supergraph(config.clone(), Some(json!({ "a": 1 })), vec![]).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            call_supergraph(config.clone(), None, vec![error("UNAUTHENTICATED")]).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            call_supergraph(config, Some(json!({ "a": 1 })), vec![error("FORBIDDEN")]).await,
+            StatusCode::OK
+        );
+    }
+
+    #[tokio::test]
+    async fn errors_as_4xx() {
+        let config = serde_json::json!("errors_as_4xx");
+        assert_eq!(
+            call_supergraph(config.clone(), Some(json!({ "a": 1 })), vec![]).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            call_supergraph(config.clone(), None, vec![error("UNAUTHENTICATED")]).await,
+            StatusCode::BAD_REQUEST
+        );
+        // partial data with errors
+        assert_eq!(
+            call_supergraph(config, Some(json!({ "a": 1 })), vec![error("FORBIDDEN")]).await,
+            StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[tokio::test]
+    async fn custom() {
+        let config = serde_json::json!({
+            "custom": {
+                "UNAUTHENTICATED": 401,
+                "FORBIDDEN": 403
+            }
+        });
+        assert_eq!(
+            call_supergraph(config.clone(), Some(json!({ "a": 1 })), vec![]).await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            call_supergraph(config.clone(), None, vec![error("UNAUTHENTICATED")]).await,
+            StatusCode::UNAUTHORIZED
+        );
+        // partial data with errors, the first mapped code wins
+        assert_eq!(
+            call_supergraph(
+                config.clone(),
+                Some(json!({ "a": 1 })),
+                vec![error("OTHER"), error("FORBIDDEN"), error("UNAUTHENTICATED")]
+            )
+            .await,
+            StatusCode::FORBIDDEN
+        );
+        // unmapped codes keep the status
+        assert_eq!(
+            call_supergraph(config, None, vec![error("OTHER")]).await,
+            StatusCode::OK
+        );
+    }
+
+    #[tokio::test]
+    async fn it_rejects_invalid_status() {
+        assert!(crate::plugin::plugins()
+            .get("apollo.error_status")
+            .expect("Plugin not found")
+            .create_instance(
+                &serde_json::json!({ "custom": { "UNAUTHENTICATED": 1000 } }),
+                Default::default()
+            )
+            .await
+            .is_err());
+    }
+}
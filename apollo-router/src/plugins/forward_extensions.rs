@@ -0,0 +1,194 @@
+//! Forward client request extensions to subgraphs.
+
+use std::collections::HashMap;
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use tower::BoxError;
+use tower::ServiceExt;
+
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
+use crate::register_plugin;
+use crate::services::subgraph;
+use crate::SubgraphRequest;
+
+/// Extensions consumed by the router itself, they are never forwarded to subgraphs
+const ROUTER_EXTENSIONS: &[&str] = &["persistedQuery"];
+
+register_plugin!("apollo", "forward_extensions", ForwardExtensions);
+
+#[derive(Clone, Debug, PartialEq, JsonSchema, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case", untagged)]
+pub(crate) enum Forward {
+    /// Forward all the extensions, or none of them
+    All(bool),
+    /// Forward only the extensions with these names
+    Named(Vec<String>),
+}
+
+impl Default for Forward {
+    fn default() -> Self {
+        Forward::All(false)
+    }
+}
+
+impl Forward {
+    pub(crate) fn is_forwarded(&self, name: &str) -> bool {
+        match self {
+            Forward::All(all) => *all,
+            Forward::Named(names) => names.iter().any(|n| n == name),
+        }
+    }
+}
+
+#[derive(Clone, Debug, JsonSchema, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+struct Config {
+    /// Extensions forwarded to all subgraphs
+    #[serde(default)]
+    all: Forward,
+    /// Extensions forwarded per subgraph, overrides `all`
+    #[serde(default)]
+    subgraphs: HashMap<String, Forward>,
+}
+
+struct ForwardExtensions {
+    config: Config,
+}
+
+#[async_trait::async_trait]
+impl Plugin for ForwardExtensions {
+    type Config = Config;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        Ok(ForwardExtensions {
+            config: init.config,
+        })
+    }
+
+    fn subgraph_service(&self, name: &str, service: subgraph::BoxService) -> subgraph::BoxService {
+        // Search for subgraph in our configured subgraph map.
+        // If we can't find it, use the "all" value
+        let forward = self
+            .config
+            .subgraphs
+            .get(name)
+            .unwrap_or(&self.config.all)
+            .clone();
+        if forward == Forward::All(false) {
+            return service;
+        }
+
+        service
+            .map_request(move |mut req: SubgraphRequest| {
+                let forwarded: Vec<_> = req
+                    .supergraph_request
+                    .body()
+                    .extensions
+                    .iter()
+                    .filter(|(name, _)| {
+                        !ROUTER_EXTENSIONS.contains(&name.as_str())
+                            && forward.is_forwarded(name.as_str())
+                    })
+                    .map(|(name, value)| (name.clone(), value.clone()))
+                    .collect();
+
+                // Extensions already set on the subgraph request take precedence
+                let extensions = &mut req.subgraph_request.body_mut().extensions;
+                for (name, value) in forwarded {
+                    if !extensions.contains_key(name.as_str()) {
+                        extensions.insert(name, value);
+                    }
+                }
+
+                req
+            })
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use serde_json_bytes::json;
+
+    use crate::graphql;
+    use crate::plugin::test;
+    use crate::plugin::test::MockSubgraphService;
+    use crate::SubgraphRequest;
+    use crate::SubgraphResponse;
+
+    async fn call_subgraph(
+        config: serde_json::Value,
+        subgraph: &'static str,
+        check: fn(&graphql::Request) -> bool,
+    ) {
+        let mut mock_service = MockSubgraphService::new();
+        mock_service
+            .expect_call()
+            .withf(move |req| check(req.subgraph_request.body()))
+            .times(1)
+            .returning(move |req: SubgraphRequest| {
+                Ok(SubgraphResponse::fake_builder()
+                    .context(req.context)
+                    .build())
+            });
+
+        let plugin = test::create_plugin("apollo.forward_extensions", &config).await;
+        let supergraph_request = http::Request::builder()
+            .body(
+                graphql::Request::fake_builder()
+                    .query("{ topProducts { name } }")
+                    .extension("myExtension", json!({"custom": true}))
+                    .extension("otherExtension", json!("other"))
+                    .extension("persistedQuery", json!({"version": 1, "sha256Hash": "abc"}))
+                    .build(),
+            )
+            .unwrap();
+        let subgraph_req = SubgraphRequest::fake_builder()
+            .supergraph_request(Arc::new(supergraph_request))
+            .build();
+
+        test::call_subgraph(&*plugin, subgraph, mock_service, subgraph_req).await;
+    }
+
+    #[tokio::test]
+    async fn it_does_not_forward_extensions_by_default() {
+        call_subgraph(serde_json::json!({}), "products", |body| {
+            body.extensions.is_empty()
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn it_forwards_all_client_extensions() {
+        call_subgraph(serde_json::json!({ "all": true }), "products", |body| {
+            body.extensions.get("myExtension") == Some(&json!({"custom": true}))
+                && body.extensions.get("otherExtension") == Some(&json!("other"))
+                && !body.extensions.contains_key("persistedQuery")
+        })
+        .await;
+    }
+
+    #[tokio::test]
+    async fn it_forwards_named_client_extensions() {
+        call_subgraph(
+            serde_json::json!({
+                "all": true,
+                "subgraphs": {
+                    "products": ["myExtension", "persistedQuery"]
+                }
+            }),
+            "products",
+            |body| {
+                body.extensions.get("myExtension") == Some(&json!({"custom": true}))
+                    && !body.extensions.contains_key("otherExtension")
+                    && !body.extensions.contains_key("persistedQuery")
+            },
+        )
+        .await;
+    }
+}
@@ -14,6 +14,7 @@ use http::header::TE;
 use http::header::TRAILER;
 use http::header::TRANSFER_ENCODING;
 use http::header::UPGRADE;
+use http::HeaderMap;
 use http::HeaderValue;
 use lazy_static::lazy_static;
 use regex::Regex;
@@ -43,7 +44,7 @@ register_plugin!("apollo", "headers", Headers);
 #[derive(Clone, JsonSchema, Deserialize)]
 #[serde(rename_all = "snake_case", deny_unknown_fields)]
 struct HeadersLocation {
-    /// Propagate/Insert/Remove headers from request
+    /// Propagate/Insert/Remove/Rename headers from request
     request: Vec<Operation>,
     // Propagate/Insert/Remove headers from response
     // response: Option<Operation>
@@ -55,6 +56,7 @@ enum Operation {
     Insert(Insert),
     Remove(Remove),
     Propagate(Propagate),
+    Rename(Rename),
 }
 
 #[derive(Clone, JsonSchema, Deserialize)]
@@ -149,6 +151,20 @@ enum Propagate {
     },
 }
 
+#[derive(Clone, JsonSchema, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+/// Rename a header of the subgraph request, whatever rule added it
+struct Rename {
+    #[schemars(schema_with = "string_schema")]
+    #[serde(deserialize_with = "deserialize_header_name")]
+    /// Current name of the header
+    named: HeaderName,
+    #[schemars(schema_with = "string_schema")]
+    #[serde(deserialize_with = "deserialize_header_name")]
+    /// New name of the header, replacing any header already using it
+    to: HeaderName,
+}
+
 #[derive(Clone, JsonSchema, Deserialize)]
 #[serde(rename_all = "snake_case", deny_unknown_fields)]
 struct Config {
@@ -226,11 +242,9 @@ struct HeadersService<S> {
 
 lazy_static! {
     // Headers from https://datatracker.ietf.org/doc/html/rfc2616#section-13.5.1
-    // These are not propagated by default using a regex match as they will not make sense for the
-    // second hop.
-    // In addition because our requests are not regular proxy requests content-type, content-length
-    // and host are also in the exclude list.
-    static ref RESERVED_HEADERS: Vec<HeaderName> = [
+    // These only make sense for the first hop and are never propagated, even when named
+    // explicitly.
+    static ref HOP_BY_HOP_HEADERS: Vec<HeaderName> = [
         CONNECTION,
         PROXY_AUTHENTICATE,
         PROXY_AUTHORIZATION,
@@ -238,12 +252,30 @@ lazy_static! {
         TRAILER,
         TRANSFER_ENCODING,
         UPGRADE,
-        CONTENT_LENGTH,
-        CONTENT_TYPE,
-        HOST,
         HeaderName::from_static("keep-alive")
     ]
     .into();
+
+    // These are not propagated by default using a regex match.
+    // In addition to the hop-by-hop headers, because our requests are not regular proxy
+    // requests content-type, content-length and host are also in the exclude list.
+    static ref RESERVED_HEADERS: Vec<HeaderName> = HOP_BY_HOP_HEADERS
+        .iter()
+        .cloned()
+        .chain([CONTENT_LENGTH, CONTENT_TYPE, HOST])
+        .collect();
+}
+
+/// Whether the header only applies to the connection between the client and the router: a
+/// standard hop-by-hop header, or one listed in the `Connection` header of the client request
+fn is_hop_by_hop(name: &HeaderName, client_headers: &HeaderMap) -> bool {
+    HOP_BY_HOP_HEADERS.contains(name)
+        || client_headers
+            .get_all(CONNECTION)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .flat_map(|value| value.split(','))
+            .any(|listed| listed.trim().eq_ignore_ascii_case(name.as_str()))
 }
 
 impl<S> Service<SubgraphRequest> for HeadersService<S>
@@ -337,15 +369,30 @@ where
                     default,
                 }) => {
                     let headers = req.subgraph_request.headers_mut();
-                    let value = req.supergraph_request.headers().get(named);
-                    if let Some(value) = value.or(default.as_ref()) {
-                        headers.insert(rename.as_ref().unwrap_or(named), value.clone());
+                    let client_headers = req.supergraph_request.headers();
+                    let name = rename.as_ref().unwrap_or(named);
+                    if !is_hop_by_hop(named, client_headers) {
+                        if let Some(value) = client_headers.get(named).or(default.as_ref()) {
+                            headers.insert(name, value.clone());
+                        }
+                    }
+                }
+                Operation::Rename(Rename { named, to }) => {
+                    let headers = req.subgraph_request.headers_mut();
+                    // header names are already lowercase, renaming to the same name is a no-op
+                    if named != to && headers.contains_key(named) {
+                        let values = headers.get_all(named).iter().cloned().collect::<Vec<_>>();
+                        headers.remove(named);
+                        headers.remove(to);
+                        for value in values {
+                            headers.append(to, value);
+                        }
                     }
                 }
                 Operation::Propagate(Propagate::Matching { matching }) => {
                     let headers = req.subgraph_request.headers_mut();
-                    req.supergraph_request
-                        .headers()
+                    let client_headers = req.supergraph_request.headers();
+                    client_headers
                         .iter()
                         .filter(|(name, _)| matching.is_match(name.as_str()))
                         .filter(|(name, _)| !RESERVED_HEADERS.contains(name))
@@ -355,6 +402,18 @@ where
                 }
             }
         }
+        // whatever operation added them, inserting, propagating or renaming, the headers of
+        // the client connection are never sent to the subgraph
+        let client_headers = req.supergraph_request.headers();
+        let headers = req.subgraph_request.headers_mut();
+        let hop_by_hop = headers
+            .keys()
+            .filter(|name| is_hop_by_hop(name, client_headers))
+            .cloned()
+            .collect::<Vec<_>>();
+        for name in hop_by_hop {
+            headers.remove(name);
+        }
         self.inner.call(req)
     }
 }
@@ -628,12 +687,7 @@ mod test {
         mock.expect_call()
             .times(1)
             .withf(|request| {
-                request.assert_headers(vec![
-                    ("aa", "vaa"),
-                    ("ab", "vab"),
-                    ("ac", "vac"),
-                    ("da", "vda"),
-                ])
+                request.assert_headers(vec![("aa", "vaa"), ("ac", "vac"), ("da", "vda")])
             })
             .returning(example_response);
 
@@ -674,6 +728,51 @@ mod test {
         Ok(())
     }
 
+    #[test]
+    fn test_rename_config() {
+        serde_yaml::from_str::<Config>(
+            r#"
+        subgraphs:
+          accounts:
+            request:
+                - rename:
+                    named: "X-User-Id"
+                    to: "user-id"
+        "#,
+        )
+        .unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_rename() -> Result<(), BoxError> {
+        let mut mock = MockSubgraphService::new();
+        mock.expect_call()
+            .times(1)
+            .withf(|request| {
+                request.assert_headers(vec![
+                    ("aa", "vaa"),
+                    ("ab", "vab"),
+                    ("ac", "vac"),
+                    ("user-id", "1"),
+                ])
+            })
+            .returning(example_response);
+
+        let mut service = HeadersLayer::new(vec![Operation::Rename(Rename {
+            named: "X-User-Id".try_into()?,
+            to: "user-id".try_into()?,
+        })])
+        .layer(mock);
+
+        let mut request = example_request();
+        request
+            .subgraph_request
+            .headers_mut()
+            .insert("x-user-id", HeaderValue::from_static("1"));
+        service.ready().await?.call(request).await?;
+        Ok(())
+    }
+
     #[tokio::test]
     async fn test_propagate_exact_default() -> Result<(), BoxError> {
         let mut mock = MockSubgraphService::new();
@@ -700,6 +799,84 @@ mod test {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn test_hop_by_hop_headers_are_never_propagated() -> Result<(), BoxError> {
+        let mut mock = MockSubgraphService::new();
+        mock.expect_call()
+            .times(1)
+            .withf(|request| {
+                request.assert_headers(vec![
+                    ("aa", "vaa"),
+                    ("ab", "vab"),
+                    ("ac", "vac"),
+                    ("da", "vda"),
+                ])
+            })
+            .returning(example_response);
+
+        let mut operations = [
+            "connection",
+            "keep-alive",
+            "te",
+            "transfer-encoding",
+            "upgrade",
+            "x-client-hop",
+        ]
+        .into_iter()
+        .map(|named| {
+            Ok(Operation::Propagate(Propagate::Named {
+                named: named.try_into()?,
+                rename: None,
+                default: None,
+            }))
+        })
+        .collect::<Result<Vec<_>, BoxError>>()?;
+        // a hop-by-hop header cannot be introduced by renaming another header or by inserting
+        // it either
+        operations.push(Operation::Propagate(Propagate::Named {
+            named: "db".try_into()?,
+            rename: Some("upgrade".try_into()?),
+            default: None,
+        }));
+        operations.push(Operation::Propagate(Propagate::Matching {
+            matching: Regex::from_str("(?i)(x-client-hop|keep-alive|upgrade|da)")?,
+        }));
+        operations.push(Operation::Insert(Insert::Static(InsertStatic {
+            name: "te".try_into()?,
+            value: "trailers".try_into()?,
+        })));
+        operations.push(Operation::Insert(Insert::Static(InsertStatic {
+            name: "x-static".try_into()?,
+            value: "static".try_into()?,
+        })));
+        operations.push(Operation::Rename(Rename {
+            named: "x-static".try_into()?,
+            to: "keep-alive".try_into()?,
+        }));
+        operations.push(Operation::Rename(Rename {
+            named: "ab".try_into()?,
+            to: "x-client-hop".try_into()?,
+        }));
+        let mut service = HeadersLayer::new(operations).layer(mock);
+
+        let mut request = example_request();
+        let supergraph_request = Arc::get_mut(&mut request.supergraph_request).unwrap();
+        for (name, value) in [
+            ("connection", "keep-alive, X-Client-Hop"),
+            ("keep-alive", "timeout=5"),
+            ("te", "trailers"),
+            ("transfer-encoding", "chunked"),
+            ("upgrade", "websocket"),
+            ("x-client-hop", "hop"),
+        ] {
+            supergraph_request
+                .headers_mut()
+                .insert(name, HeaderValue::from_static(value));
+        }
+        service.ready().await?.call(request).await?;
+        Ok(())
+    }
+
     fn example_response(_: SubgraphRequest) -> Result<SubgraphResponse, BoxError> {
         Ok(SubgraphResponse::new_from_response(
             http::Response::default(),
@@ -2,12 +2,23 @@
 //!
 //! These plugins are compiled into the router and configured via YAML configuration.
 
+pub(crate) mod cache_control;
+pub(crate) mod context_merge;
 pub(crate) mod csrf;
+mod debug;
+pub(crate) mod diagnostics;
+mod error_status;
 mod expose_query_plan;
 mod forbid_mutations;
+pub(crate) mod forward_extensions;
 mod headers;
 mod include_subgraph_errors;
 pub(crate) mod override_url;
+mod required_headers;
 pub(crate) mod rhai;
+mod schema_export;
+mod subgraph_extensions;
+pub(crate) mod subgraph_signing;
 pub(crate) mod telemetry;
 pub(crate) mod traffic_shaping;
+mod user_agent;
@@ -1,21 +1,59 @@
 //! Allows subgraph URLs to be overridden.
 
 use std::collections::HashMap;
-use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::RwLock;
 
 use http::Uri;
 use tower::BoxError;
 use tower::ServiceExt;
 
+use crate::configuration::TrailingSlash;
 use crate::plugin::Plugin;
 use crate::plugin::PluginInit;
 use crate::register_plugin;
 use crate::services::subgraph;
+use crate::services::subgraph_service::normalize_subgraph_url;
 use crate::SubgraphRequest;
 
+pub(crate) const APOLLO_OVERRIDE_SUBGRAPH_URL: &str = "apollo.override_subgraph_url";
+
 #[derive(Debug, Clone)]
-struct OverrideSubgraphUrl {
-    urls: HashMap<String, Uri>,
+pub(crate) struct OverrideSubgraphUrl {
+    // shared with the subgraph services, so that the URLs can be updated in place
+    urls: Arc<RwLock<HashMap<String, Uri>>>,
+}
+
+impl OverrideSubgraphUrl {
+    /// Replaces the overridden URLs, for the next requests to subgraphs
+    pub(crate) fn update(
+        &self,
+        configuration: serde_json::Value,
+        trailing_slash: TrailingSlash,
+    ) -> Result<(), BoxError> {
+        let urls = uris(serde_json::from_value(configuration)?, trailing_slash)?;
+        *self.urls.write().expect("lock poisoned") = urls;
+        Ok(())
+    }
+
+    /// The overridden URLs, by subgraph name
+    pub(crate) fn urls(&self) -> HashMap<String, Uri> {
+        self.urls.read().expect("lock poisoned").clone()
+    }
+}
+
+fn uris(
+    config: HashMap<String, url::Url>,
+    trailing_slash: TrailingSlash,
+) -> Result<HashMap<String, Uri>, BoxError> {
+    config
+        .into_iter()
+        .map(|(k, v)| {
+            let uri = normalize_subgraph_url(v.as_str(), trailing_slash)
+                .map_err(|err| format!("invalid URL override for subgraph '{}': {}", k, err))?;
+            Ok((k, uri))
+        })
+        .collect()
 }
 
 #[async_trait::async_trait]
@@ -23,12 +61,10 @@ impl Plugin for OverrideSubgraphUrl {
     type Config = HashMap<String, url::Url>;
 
     async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        // the trailing slash policy is applied once the router is created, with the rest
+        // of the configuration
         Ok(OverrideSubgraphUrl {
-            urls: init
-                .config
-                .into_iter()
-                .map(|(k, v)| (k, Uri::from_str(v.as_str()).unwrap()))
-                .collect(),
+            urls: Arc::new(RwLock::new(uris(init.config, TrailingSlash::Preserve)?)),
         })
     }
 
@@ -37,7 +73,12 @@ impl Plugin for OverrideSubgraphUrl {
         subgraph_name: &str,
         service: subgraph::BoxService,
     ) -> subgraph::BoxService {
-        let new_url = self.urls.get(subgraph_name).cloned();
+        let new_url = self
+            .urls
+            .read()
+            .expect("lock poisoned")
+            .get(subgraph_name)
+            .cloned();
         service
             .map_request(move |mut req: SubgraphRequest| {
                 if let Some(new_url) = new_url.clone() {
@@ -0,0 +1,285 @@
+//! Reject the requests missing required headers, before their query is parsed.
+
+use std::ops::ControlFlow;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use http::HeaderMap;
+use http::HeaderName;
+use http::StatusCode;
+use regex::Regex;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use tower::BoxError;
+use tower::ServiceBuilder;
+use tower::ServiceExt;
+
+use crate::layers::ServiceBuilderExt;
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
+use crate::register_plugin;
+use crate::services::supergraph;
+use crate::SupergraphRequest;
+use crate::SupergraphResponse;
+
+register_plugin!("apollo", "required_headers", RequiredHeaders);
+
+fn default_status() -> u16 {
+    StatusCode::BAD_REQUEST.as_u16()
+}
+
+/// Headers that every request must send, checked before the query is parsed
+#[derive(Clone, Debug, JsonSchema, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    /// The required headers
+    headers: Vec<HeaderConfig>,
+    /// HTTP status of the responses to the requests missing a required header, a 4xx client error (default: 400)
+    #[serde(default = "default_status")]
+    status: u16,
+}
+
+/// A header that every request must send
+#[derive(Clone, Debug, JsonSchema, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct HeaderConfig {
+    /// Name of the header
+    name: String,
+    /// Value the header must have
+    value: Option<String>,
+    /// Regular expression the whole value of the header must match
+    pattern: Option<String>,
+}
+
+#[derive(Clone, Debug)]
+struct RequiredHeader {
+    name: HeaderName,
+    expected: Expected,
+}
+
+#[derive(Clone, Debug)]
+enum Expected {
+    Any,
+    Value(String),
+    Pattern(Regex),
+}
+
+impl RequiredHeader {
+    fn parse(config: HeaderConfig) -> Result<Self, BoxError> {
+        let name = HeaderName::from_str(&config.name)
+            .map_err(|e| format!("invalid header name '{}': {}", config.name, e))?;
+        let expected = match (config.value, config.pattern) {
+            (None, None) => Expected::Any,
+            (Some(value), None) => Expected::Value(value),
+            (None, Some(pattern)) => Expected::Pattern(
+                // anchored, so the pattern matches the whole value
+                Regex::new(&format!("^(?:{})$", pattern))
+                    .map_err(|e| format!("invalid pattern for header '{}': {}", name, e))?,
+            ),
+            (Some(_), Some(_)) => {
+                return Err(format!(
+                    "header '{}' can't have both a required value and a pattern",
+                    name
+                )
+                .into())
+            }
+        };
+        Ok(Self { name, expected })
+    }
+
+    /// The reason for rejecting the request, if one of its values for the header is not accepted
+    fn check(&self, headers: &HeaderMap) -> Result<(), String> {
+        let mut values = headers.get_all(&self.name).iter().peekable();
+        if values.peek().is_none() {
+            return Err(format!("missing required header '{}'", self.name));
+        }
+        let accepted = values.any(|value| match (&self.expected, value.to_str()) {
+            (Expected::Any, _) => true,
+            (Expected::Value(expected), Ok(value)) => value == expected,
+            (Expected::Pattern(pattern), Ok(value)) => pattern.is_match(value),
+            (_, Err(_)) => false,
+        });
+        if accepted {
+            Ok(())
+        } else {
+            Err(format!("invalid value for required header '{}'", self.name))
+        }
+    }
+}
+
+struct RequiredHeaders {
+    headers: Arc<Vec<RequiredHeader>>,
+    status: StatusCode,
+}
+
+#[async_trait::async_trait]
+impl Plugin for RequiredHeaders {
+    type Config = Config;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        let status = StatusCode::from_u16(init.config.status)
+            .map_err(|e| format!("invalid status {}: {}", init.config.status, e))?;
+        if !status.is_client_error() {
+            return Err(format!("invalid status {}: must be a 4xx client error", status).into());
+        }
+        let headers = init
+            .config
+            .headers
+            .into_iter()
+            .map(RequiredHeader::parse)
+            .collect::<Result<_, _>>()?;
+        Ok(RequiredHeaders {
+            headers: Arc::new(headers),
+            status,
+        })
+    }
+
+    fn supergraph_service(&self, service: supergraph::BoxService) -> supergraph::BoxService {
+        let headers = self.headers.clone();
+        let status = self.status;
+        ServiceBuilder::new()
+            .checkpoint(move |req: SupergraphRequest| {
+                let request_headers = req.supergraph_request.headers();
+                match headers
+                    .iter()
+                    .find_map(|header| header.check(request_headers).err())
+                {
+                    None => Ok(ControlFlow::Continue(req)),
+                    Some(reason) => {
+                        tracing::debug!("rejecting a request: {}", reason);
+                        let error = crate::error::Error::builder()
+                            .message(reason)
+                            .extension("code", "REQUIRED_HEADER")
+                            .build();
+                        let res = SupergraphResponse::builder()
+                            .error(error)
+                            .status_code(status)
+                            .context(req.context)
+                            .build()?;
+                        Ok(ControlFlow::Break(res))
+                    }
+                }
+            })
+            .service(service)
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http::StatusCode;
+
+    use crate::plugin::test;
+    use crate::plugin::test::MockSupergraphService;
+    use crate::SupergraphRequest;
+    use crate::SupergraphResponse;
+
+    async fn call_supergraph(
+        config: serde_json::Value,
+        headers: &[(&str, &str)],
+    ) -> (StatusCode, Vec<String>) {
+        let mut mock_service = MockSupergraphService::new();
+        mock_service.expect_call().returning(move |req| {
+            SupergraphResponse::fake_builder()
+                .context(req.context)
+                .build()
+        });
+
+        let plugin = test::create_plugin("apollo.required_headers", &config).await;
+        let mut request = SupergraphRequest::fake_builder();
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        let mut response =
+            test::call_supergraph(&*plugin, mock_service, request.build().unwrap()).await;
+        let status = response.response.status();
+        let errors = response
+            .next_response()
+            .await
+            .unwrap()
+            .errors
+            .into_iter()
+            .map(|error| error.message)
+            .collect();
+        (status, errors)
+    }
+
+    #[tokio::test]
+    async fn it_accepts_the_required_headers() {
+        let config = serde_json::json!({
+            "headers": [
+                { "name": "x-api-version", "value": "2" },
+                { "name": "x-tenant", "pattern": "[a-z]+" },
+                { "name": "x-client" }
+            ]
+        });
+        assert_eq!(
+            call_supergraph(
+                config,
+                &[
+                    ("x-api-version", "2"),
+                    ("x-tenant", "acme"),
+                    ("x-client", "web")
+                ]
+            )
+            .await,
+            (StatusCode::OK, vec![])
+        );
+    }
+
+    #[tokio::test]
+    async fn it_rejects_missing_and_mismatched_headers() {
+        let config = serde_json::json!({
+            "headers": [
+                { "name": "x-api-version", "value": "2" },
+                { "name": "x-tenant", "pattern": "[a-z]+" }
+            ],
+            "status": 403
+        });
+        assert_eq!(
+            call_supergraph(config.clone(), &[("x-tenant", "acme")]).await,
+            (
+                StatusCode::FORBIDDEN,
+                vec!["missing required header 'x-api-version'".to_string()]
+            )
+        );
+        assert_eq!(
+            call_supergraph(
+                config.clone(),
+                &[("x-api-version", "1"), ("x-tenant", "acme")]
+            )
+            .await,
+            (
+                StatusCode::FORBIDDEN,
+                vec!["invalid value for required header 'x-api-version'".to_string()]
+            )
+        );
+        // the pattern must match the whole value
+        assert_eq!(
+            call_supergraph(config, &[("x-api-version", "2"), ("x-tenant", "acme-1")]).await,
+            (
+                StatusCode::FORBIDDEN,
+                vec!["invalid value for required header 'x-tenant'".to_string()]
+            )
+        );
+    }
+
+    #[tokio::test]
+    async fn it_rejects_invalid_configuration() {
+        for config in [
+            serde_json::json!({ "headers": [{ "name": "x-api-version", "value": "2", "pattern": "2" }] }),
+            serde_json::json!({ "headers": [{ "name": "x-tenant", "pattern": "[a-z" }] }),
+            serde_json::json!({ "headers": [{ "name": "x tenant" }] }),
+            serde_json::json!({ "headers": [], "status": 1000 }),
+            serde_json::json!({ "headers": [], "status": 200 }),
+            serde_json::json!({ "headers": [], "status": 503 }),
+        ] {
+            assert!(crate::plugin::plugins()
+                .get("apollo.required_headers")
+                .expect("Plugin not found")
+                .create_instance(&config, Default::default())
+                .await
+                .is_err());
+        }
+    }
+}
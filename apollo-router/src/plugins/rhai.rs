@@ -360,6 +360,7 @@ pub(crate) struct Rhai {
     ast: AST,
     engine: Arc<Engine>,
     scope: Arc<Mutex<Scope<'static>>>,
+    on_error: OnError,
 }
 
 /// Configuration for the Rhai Plugin
@@ -368,6 +369,20 @@ pub(crate) struct Rhai {
 pub(crate) struct Conf {
     scripts: Option<PathBuf>,
     main: Option<String>,
+    /// What to do with a request when a script callback fails
+    #[serde(default)]
+    on_error: OnError,
+}
+
+/// Handling of the errors of the script callbacks
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum OnError {
+    /// Answer the request with an error
+    #[default]
+    Fail,
+    /// Log the error and carry on with the request or response as the script left it
+    Ignore,
 }
 
 #[async_trait::async_trait]
@@ -401,6 +416,7 @@ impl Plugin for Rhai {
             ast,
             engine,
             scope: Arc::new(Mutex::new(scope)),
+            on_error: init.config.on_error,
         })
     }
 
@@ -518,7 +534,7 @@ macro_rules! gen_map_request {
                             )
                             .map_err(|err| err.to_string())
                     };
-                    if let Err(error) = result {
+                    if let Err(error) = filter_ignored(&$rhai_service, result) {
                         tracing::error!("map_request callback failed: {error}");
                         let mut guard = shared_request.lock().unwrap();
                         let request_opt = guard.take();
@@ -572,7 +588,7 @@ macro_rules! gen_map_deferred_request {
                     let shared_request = Shared::new(Mutex::new(Some(request)));
                     let result = execute(&$rhai_service, &$callback, (shared_request.clone(),));
 
-                    if let Err(error) = result {
+                    if let Err(error) = filter_ignored(&$rhai_service, result) {
                         tracing::error!("map_request callback failed: {error}");
                         let mut guard = shared_request.lock().unwrap();
                         let request_opt = guard.take();
@@ -634,7 +650,7 @@ macro_rules! gen_map_response {
                             )
                             .map_err(|err| err.to_string())
                     };
-                    if let Err(error) = result {
+                    if let Err(error) = filter_ignored(&$rhai_service, result) {
                         tracing::error!("map_response callback failed: {error}");
                         let mut guard = shared_response.lock().unwrap();
                         let response_opt = guard.take();
@@ -706,7 +722,7 @@ macro_rules! gen_map_deferred_response {
 
                     let result =
                         execute(&$rhai_service, &$callback, (shared_response.clone(),));
-                    if let Err(error) = result {
+                    if let Err(error) = filter_ignored(&$rhai_service, result) {
                         tracing::error!("map_response callback failed: {error}");
                         let mut guard = shared_response.lock().unwrap();
                         let response_opt = guard.take();
@@ -741,7 +757,7 @@ macro_rules! gen_map_deferred_response {
                                 &callback,
                                 (shared_response.clone(),),
                             );
-                            if let Err(error) = result {
+                            if let Err(error) = filter_ignored(&rhai_service, result) {
                                 tracing::error!("map_response callback failed: {error}");
                                 return None;
                             }
@@ -956,6 +972,20 @@ impl ServiceStep {
     }
 }
 
+/// Logs and discards the error of a callback if the plugin is configured to ignore them
+fn filter_ignored(
+    rhai_service: &RhaiService,
+    result: Result<Dynamic, String>,
+) -> Result<Dynamic, String> {
+    match result {
+        Err(error) if rhai_service.on_error == OnError::Ignore => {
+            tracing::warn!("ignoring the rhai callback error: {error}");
+            Ok(Dynamic::UNIT)
+        }
+        result => result,
+    }
+}
+
 fn execute(
     rhai_service: &RhaiService,
     callback: &FnPtr,
@@ -979,6 +1009,7 @@ pub(crate) struct RhaiService {
     service: ServiceStep,
     engine: Arc<Engine>,
     ast: AST,
+    on_error: OnError,
 }
 
 impl Rhai {
@@ -994,6 +1025,7 @@ impl Rhai {
             service,
             engine: self.engine.clone(),
             ast: self.ast.clone(),
+            on_error: self.on_error,
         };
         let mut guard = scope.lock().unwrap();
         match subgraph {
@@ -1515,6 +1547,55 @@ mod tests {
         Ok(())
     }
 
+    #[tokio::test]
+    async fn rhai_plugin_execution_service_ignored_error() -> Result<(), BoxError> {
+        let mut mock_service = MockExecutionService::new();
+        mock_service.expect_clone().return_once(move || {
+            let mut mock_service = MockExecutionService::new();
+            // The error thrown by the execution_service in test.rhai is ignored, so the
+            // request reaches the mock service as the script left it
+            mock_service
+                .expect_call()
+                .times(1)
+                .returning(move |req: ExecutionRequest| {
+                    assert_eq!(req.context.get::<_, i64>("test").unwrap().unwrap(), 25i64);
+                    Ok(ExecutionResponse::fake_builder()
+                        .context(req.context)
+                        .build()
+                        .unwrap())
+                });
+            mock_service
+        });
+
+        let dyn_plugin: Box<dyn DynPlugin> = crate::plugin::plugins()
+            .get("apollo.rhai")
+            .expect("Plugin not found")
+            .create_instance_without_schema(
+                &Value::from_str(
+                    r#"{"scripts":"tests/fixtures", "main":"test.rhai", "on_error":"ignore"}"#,
+                )
+                .unwrap(),
+            )
+            .await
+            .unwrap();
+        let mut router_service = dyn_plugin.execution_service(BoxService::new(mock_service));
+        let context = Context::new();
+        context.insert("test", 5i64).unwrap();
+        let exec_req = ExecutionRequest::fake_builder().context(context).build();
+
+        let mut exec_resp = router_service
+            .ready()
+            .await
+            .unwrap()
+            .call(exec_req)
+            .await
+            .unwrap();
+        assert_eq!(exec_resp.response.status(), http::StatusCode::OK);
+        let body = exec_resp.next_response().await.unwrap();
+        assert!(body.errors.is_empty());
+        Ok(())
+    }
+
     // Some of these tests rely extensively on internal implementation details of the tracing_test crate.
     // These are unstable, so these test may break if the tracing_test crate is updated.
     //
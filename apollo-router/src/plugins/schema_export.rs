@@ -0,0 +1,163 @@
+//! Expose the active supergraph schema on an admin endpoint.
+
+use std::sync::Arc;
+
+use http::header::CONTENT_TYPE;
+use http::HeaderValue;
+use http::Method;
+use http::StatusCode;
+use multimap::MultiMap;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use tower::service_fn;
+use tower::BoxError;
+use tower::ServiceExt;
+
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
+use crate::register_plugin;
+use crate::router_factory::Endpoint;
+use crate::services::transport;
+use crate::ListenAddr;
+
+register_plugin!("apollo", "schema_export", SchemaExport);
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    /// Expose the schema endpoint
+    enabled: bool,
+    /// The socket address and port to listen on, preferably a management port
+    /// Defaults to 127.0.0.1:9090
+    #[serde(default = "default_listen")]
+    listen: ListenAddr,
+    /// The HTTP path of the schema endpoint
+    /// Defaults to /schema
+    #[serde(default = "default_path")]
+    path: String,
+}
+
+fn default_listen() -> ListenAddr {
+    ListenAddr::SocketAddr("127.0.0.1:9090".parse().expect("valid listenAddr"))
+}
+
+fn default_path() -> String {
+    "/schema".to_string()
+}
+
+struct SchemaExport {
+    config: Config,
+    // plugins are created again on every reload, so this is always the active schema
+    supergraph_sdl: Arc<String>,
+}
+
+#[async_trait::async_trait]
+impl Plugin for SchemaExport {
+    type Config = Config;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        Ok(SchemaExport {
+            config: init.config,
+            supergraph_sdl: init.supergraph_sdl,
+        })
+    }
+
+    fn web_endpoints(&self) -> MultiMap<ListenAddr, Endpoint> {
+        let mut endpoints = MultiMap::new();
+        if self.config.enabled {
+            let sdl = self.supergraph_sdl.clone();
+            let handler = service_fn(move |req: transport::Request| {
+                let sdl = sdl.clone();
+                async move {
+                    if req.method() != Method::GET {
+                        return http::Response::builder()
+                            .status(StatusCode::METHOD_NOT_ALLOWED)
+                            .body(hyper::Body::empty())
+                            .map_err(BoxError::from);
+                    }
+                    http::Response::builder()
+                        .status(StatusCode::OK)
+                        .header(
+                            CONTENT_TYPE,
+                            HeaderValue::from_static("text/plain; charset=utf-8"),
+                        )
+                        .body(sdl.as_str().to_owned().into())
+                        .map_err(BoxError::from)
+                }
+            });
+            endpoints.insert(
+                self.config.listen.clone(),
+                Endpoint::new(self.config.path.clone(), handler.boxed()),
+            );
+        }
+        endpoints
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use http::Method;
+    use http::StatusCode;
+    use tower::ServiceExt;
+
+    use crate::plugin::DynPlugin;
+
+    async fn schema_export(config: serde_json::Value, sdl: &str) -> Box<dyn DynPlugin> {
+        crate::plugin::plugins()
+            .get("apollo.schema_export")
+            .expect("Plugin not found")
+            .create_instance(&config, Arc::new(sdl.to_string()))
+            .await
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn it_serves_the_schema() {
+        let plugin = schema_export(
+            serde_json::json!({ "enabled": true, "path": "/sdl" }),
+            "type Query { me: String }",
+        )
+        .await;
+        let endpoints = plugin.web_endpoints();
+        let (listen, endpoints) = endpoints.into_iter().next().unwrap();
+        assert_eq!(listen, super::default_listen());
+        let router = endpoints.into_iter().next().unwrap().into_router();
+
+        let mut response = router
+            .clone()
+            .oneshot(
+                http::Request::get("http://localhost:9090/sdl")
+                    .body(Default::default())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(response.body_mut()).await.unwrap();
+        assert_eq!(&body[..], b"type Query { me: String }");
+
+        let response = router
+            .oneshot(
+                http::Request::builder()
+                    .method(Method::POST)
+                    .uri("http://localhost:9090/sdl")
+                    .body(Default::default())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::METHOD_NOT_ALLOWED);
+    }
+
+    #[tokio::test]
+    async fn it_can_be_disabled() {
+        let plugin = schema_export(
+            serde_json::json!({ "enabled": false }),
+            "type Query { me: String }",
+        )
+        .await;
+        assert!(plugin.web_endpoints().is_empty());
+    }
+}
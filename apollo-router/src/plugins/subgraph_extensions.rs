@@ -0,0 +1,251 @@
+//! Aggregate the extensions of subgraph responses into the client response.
+
+use std::collections::HashMap;
+
+use schemars::JsonSchema;
+use serde::Deserialize;
+use tower::BoxError;
+use tower::ServiceExt;
+
+use crate::json_ext::Object;
+use crate::json_ext::Value;
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
+use crate::plugins::forward_extensions::Forward;
+use crate::register_plugin;
+use crate::services::subgraph;
+use crate::services::supergraph;
+use crate::SubgraphResponse;
+
+/// Extensions of the subgraph responses not yet added to a client response, by subgraph name
+const SUBGRAPH_EXTENSIONS_CONTEXT_KEY: &str = "apollo_subgraph_extensions::pending";
+
+/// Client response extension containing the subgraph extensions, by subgraph name
+const SUBGRAPHS_EXTENSION: &str = "subgraphs";
+
+/// Extensions consumed by the router itself, they are never added to the client response
+const ROUTER_EXTENSIONS: &[&str] = &["ftv1"];
+
+register_plugin!("apollo", "subgraph_extensions", SubgraphExtensions);
+
+#[derive(Clone, Debug, JsonSchema, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+struct Config {
+    /// Extensions of the responses of all subgraphs added to the client response
+    #[serde(default)]
+    all: Forward,
+    /// Extensions added to the client response per subgraph, overrides `all`
+    #[serde(default)]
+    subgraphs: HashMap<String, Forward>,
+}
+
+struct SubgraphExtensions {
+    config: Config,
+}
+
+impl SubgraphExtensions {
+    fn forward(&self, name: &str) -> &Forward {
+        self.config.subgraphs.get(name).unwrap_or(&self.config.all)
+    }
+}
+
+#[async_trait::async_trait]
+impl Plugin for SubgraphExtensions {
+    type Config = Config;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        Ok(SubgraphExtensions {
+            config: init.config,
+        })
+    }
+
+    fn supergraph_service(&self, service: supergraph::BoxService) -> supergraph::BoxService {
+        if self.config.all == Forward::All(false)
+            && self
+                .config
+                .subgraphs
+                .values()
+                .all(|forward| *forward == Forward::All(false))
+        {
+            return service;
+        }
+
+        service
+            .map_response(|response: supergraph::Response| {
+                let context = response.context.clone();
+                // deferred responses get the extensions of the fetches made since the previous response
+                response.map_stream(move |mut response| {
+                    if let Some(Value::Object(subgraphs)) = context.insert_json_value(
+                        SUBGRAPH_EXTENSIONS_CONTEXT_KEY,
+                        Value::Object(Object::default()),
+                    ) {
+                        if !subgraphs.is_empty()
+                            && !response.extensions.contains_key(SUBGRAPHS_EXTENSION)
+                        {
+                            response
+                                .extensions
+                                .insert(SUBGRAPHS_EXTENSION, Value::Object(subgraphs));
+                        }
+                    }
+                    response
+                })
+            })
+            .boxed()
+    }
+
+    fn subgraph_service(&self, name: &str, service: subgraph::BoxService) -> subgraph::BoxService {
+        let forward = self.forward(name).clone();
+        if forward == Forward::All(false) {
+            return service;
+        }
+
+        let name = name.to_string();
+        service
+            .map_response(move |response: SubgraphResponse| {
+                let extensions: Object = response
+                    .response
+                    .body()
+                    .extensions
+                    .iter()
+                    .filter(|(key, _)| {
+                        !ROUTER_EXTENSIONS.contains(&key.as_str())
+                            && forward.is_forwarded(key.as_str())
+                    })
+                    .map(|(key, value)| (key.clone(), value.clone()))
+                    .collect();
+                if extensions.is_empty() {
+                    return response;
+                }
+
+                // a subgraph fetched several times keeps the last value of each extension
+                if let Err(err) = response.context.upsert(
+                    SUBGRAPH_EXTENSIONS_CONTEXT_KEY,
+                    |mut subgraphs: Object| {
+                        match subgraphs.get_mut(name.as_str()) {
+                            Some(Value::Object(aggregated)) => {
+                                for (key, value) in extensions.iter() {
+                                    aggregated.insert(key.clone(), value.clone());
+                                }
+                            }
+                            _ => {
+                                subgraphs.insert(name.clone(), Value::Object(extensions.clone()));
+                            }
+                        }
+                        subgraphs
+                    },
+                ) {
+                    tracing::error!(
+                        "could not aggregate the extensions of subgraph '{}': {}",
+                        name,
+                        err
+                    );
+                }
+                response
+            })
+            .boxed()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use serde_json_bytes::json;
+
+    use crate::plugin::test::call_subgraph;
+    use crate::plugin::test::call_supergraph;
+    use crate::plugin::test::create_plugin;
+    use crate::plugin::test::MockSubgraphService;
+    use crate::plugin::test::MockSupergraphService;
+    use crate::Context;
+    use crate::SubgraphRequest;
+    use crate::SubgraphResponse;
+    use crate::SupergraphRequest;
+    use crate::SupergraphResponse;
+
+    async fn client_extensions(config: serde_json::Value) -> serde_json_bytes::Value {
+        let plugin = create_plugin("apollo.subgraph_extensions", &config).await;
+        let context = Context::new();
+
+        for subgraph in ["products", "reviews"] {
+            let mut mock_service = MockSubgraphService::new();
+            mock_service
+                .expect_call()
+                .times(1)
+                .returning(move |req: SubgraphRequest| {
+                    Ok(SubgraphResponse::fake_builder()
+                        .extension("cost", json!({ "subgraph": subgraph }))
+                        .extension("tracing", json!("trace"))
+                        .extension("ftv1", json!("internal"))
+                        .context(req.context)
+                        .build())
+                });
+            call_subgraph(
+                &*plugin,
+                subgraph,
+                mock_service,
+                SubgraphRequest::fake_builder()
+                    .context(context.clone())
+                    .build(),
+            )
+            .await;
+        }
+
+        let mut mock_service = MockSupergraphService::new();
+        mock_service.expect_call().times(1).returning(|req| {
+            SupergraphResponse::fake_builder()
+                .data(json!({ "topProducts": [] }))
+                .extension("valueCompletion", json!([]))
+                .context(req.context)
+                .build()
+        });
+        let mut response = call_supergraph(
+            &*plugin,
+            mock_service,
+            SupergraphRequest::fake_builder()
+                .context(context)
+                .build()
+                .unwrap(),
+        )
+        .await;
+        let response = response.next_response().await.unwrap();
+        // router extensions are kept
+        assert_eq!(response.extensions.get("valueCompletion"), Some(&json!([])));
+        response
+            .extensions
+            .get("subgraphs")
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    #[tokio::test]
+    async fn it_does_not_aggregate_extensions_by_default() {
+        assert_eq!(
+            client_extensions(serde_json::json!({})).await,
+            serde_json_bytes::Value::Null
+        );
+    }
+
+    #[tokio::test]
+    async fn it_aggregates_extensions_by_subgraph() {
+        assert_eq!(
+            client_extensions(serde_json::json!({ "all": true })).await,
+            json!({
+                "products": { "cost": { "subgraph": "products" }, "tracing": "trace" },
+                "reviews": { "cost": { "subgraph": "reviews" }, "tracing": "trace" },
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn it_aggregates_named_extensions() {
+        assert_eq!(
+            client_extensions(serde_json::json!({
+                "all": ["cost"],
+                "subgraphs": { "reviews": false }
+            }))
+            .await,
+            json!({
+                "products": { "cost": { "subgraph": "products" } },
+            })
+        );
+    }
+}
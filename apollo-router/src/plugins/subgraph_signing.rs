@@ -0,0 +1,210 @@
+//! Sign subgraph requests with an HMAC-SHA256 signature.
+
+use std::collections::HashMap;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
+
+use hmac::Hmac;
+use hmac::Mac;
+use http::HeaderMap;
+use http::HeaderValue;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use sha2::Sha256;
+use tower::BoxError;
+
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
+use crate::register_plugin;
+
+pub(crate) const APOLLO_SUBGRAPH_SIGNING: &str = "apollo.subgraph_signing";
+const SIGNATURE_HEADER: &str = "x-signature";
+const TIMESTAMP_HEADER: &str = "x-timestamp";
+
+register_plugin!("apollo", "subgraph_signing", SubgraphSigning);
+
+// No Debug implementation, the secrets must never be logged
+#[derive(Clone, JsonSchema, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Config {
+    /// Signing configuration for all subgraphs
+    all: Option<SigningConfig>,
+    /// Signing configuration per subgraph, overrides `all`
+    #[serde(default)]
+    subgraphs: HashMap<String, SigningConfig>,
+}
+
+#[derive(Clone, JsonSchema, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SigningConfig {
+    /// Secret of the HMAC-SHA256 signature, usually read from an environment variable with `${env.MY_SECRET}`
+    secret: String,
+}
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// Holds the signers of the subgraphs, the subgraph services sign their requests with them
+pub(crate) struct SubgraphSigning {
+    all: Option<SubgraphSigner>,
+    subgraphs: HashMap<String, SubgraphSigner>,
+}
+
+impl SubgraphSigning {
+    /// The signer of the requests to a subgraph, if they are signed
+    pub(crate) fn signer(&self, name: &str) -> Option<SubgraphSigner> {
+        // Search for subgraph in our configured subgraph map.
+        // If we can't find it, use the "all" value
+        self.subgraphs.get(name).or(self.all.as_ref()).cloned()
+    }
+}
+
+/// Signs the HTTP requests sent to a subgraph
+#[derive(Clone)]
+pub(crate) struct SubgraphSigner {
+    hmac: HmacSha256,
+}
+
+impl SubgraphSigner {
+    pub(crate) fn new(secret: &str) -> Result<Self, BoxError> {
+        if secret.is_empty() {
+            return Err("the signing secret must not be empty".into());
+        }
+        Ok(SubgraphSigner {
+            hmac: HmacSha256::new_from_slice(secret.as_bytes())
+                .map_err(|_| "invalid signing secret")?,
+        })
+    }
+
+    /// Adds the signature of the body, as it is sent to the subgraph, and its timestamp to the
+    /// headers
+    pub(crate) fn sign(&self, headers: &mut HeaderMap, body: &[u8]) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs()
+            .to_string();
+        let signature = sign(&self.hmac, &timestamp, body);
+        headers.insert(
+            SIGNATURE_HEADER,
+            HeaderValue::from_str(&signature).expect("hex is a valid header value"),
+        );
+        headers.insert(
+            TIMESTAMP_HEADER,
+            HeaderValue::from_str(&timestamp).expect("digits are a valid header value"),
+        );
+    }
+}
+
+/// Hex encoded HMAC-SHA256 of the timestamp and the body, separated by a `.`
+fn sign(hmac: &HmacSha256, timestamp: &str, body: &[u8]) -> String {
+    let mut hmac = hmac.clone();
+    hmac.update(timestamp.as_bytes());
+    hmac.update(b".");
+    hmac.update(body);
+    hex::encode(hmac.finalize().into_bytes())
+}
+
+#[async_trait::async_trait]
+impl Plugin for SubgraphSigning {
+    type Config = Config;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        Ok(SubgraphSigning {
+            all: init
+                .config
+                .all
+                .as_ref()
+                .map(SubgraphSigner::new)
+                .transpose()?,
+            subgraphs: init
+                .config
+                .subgraphs
+                .iter()
+                .map(|(name, config)| Ok((name.clone(), SubgraphSigner::new(&config.secret)?)))
+                .collect::<Result<_, BoxError>>()?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hmac::Mac;
+    use http::HeaderMap;
+
+    use super::HmacSha256;
+    use super::SubgraphSigning;
+    use super::SIGNATURE_HEADER;
+    use super::TIMESTAMP_HEADER;
+    use crate::plugin::test;
+    use crate::plugin::DynPlugin;
+
+    fn verify(headers: &HeaderMap, body: &[u8], secret: &str) -> bool {
+        let (signature, timestamp) =
+            match (headers.get(SIGNATURE_HEADER), headers.get(TIMESTAMP_HEADER)) {
+                (Some(signature), Some(timestamp)) => (signature, timestamp),
+                _ => return false,
+            };
+
+        let mut hmac = HmacSha256::new_from_slice(secret.as_bytes()).unwrap();
+        hmac.update(timestamp.as_bytes());
+        hmac.update(b".");
+        hmac.update(body);
+        hmac.verify_slice(&hex::decode(signature.as_bytes()).unwrap())
+            .is_ok()
+    }
+
+    async fn create_plugin(config: serde_json::Value) -> Box<dyn DynPlugin> {
+        test::create_plugin(super::APOLLO_SUBGRAPH_SIGNING, &config).await
+    }
+
+    fn signed_headers(plugin: &dyn DynPlugin, subgraph: &str, body: &[u8]) -> Option<HeaderMap> {
+        let signer = plugin
+            .as_any()
+            .downcast_ref::<SubgraphSigning>()
+            .unwrap()
+            .signer(subgraph)?;
+        let mut headers = HeaderMap::new();
+        signer.sign(&mut headers, body);
+        Some(headers)
+    }
+
+    #[tokio::test]
+    async fn it_signs_requests() {
+        let plugin = create_plugin(serde_json::json!({
+            "all": { "secret": "all-secret" },
+            "subgraphs": {
+                "products": { "secret": "products-secret" }
+            }
+        }))
+        .await;
+        let body = br#"{"query":"{ topProducts { name } }"}"#;
+
+        let headers = signed_headers(&*plugin, "products", body).unwrap();
+        assert!(verify(&headers, body, "products-secret"));
+        let headers = signed_headers(&*plugin, "reviews", body).unwrap();
+        assert!(verify(&headers, body, "all-secret"));
+        assert!(!verify(&headers, b"{}", "all-secret"));
+    }
+
+    #[tokio::test]
+    async fn it_does_not_sign_unconfigured_subgraphs() {
+        let plugin = create_plugin(
+            serde_json::json!({ "subgraphs": { "products": { "secret": "secret" } } }),
+        )
+        .await;
+        assert!(signed_headers(&*plugin, "reviews", b"{}").is_none());
+    }
+
+    #[tokio::test]
+    async fn it_rejects_empty_secrets() {
+        assert!(crate::plugin::plugins()
+            .get(super::APOLLO_SUBGRAPH_SIGNING)
+            .expect("Plugin not found")
+            .create_instance(
+                &serde_json::json!({ "all": { "secret": "" } }),
+                Default::default()
+            )
+            .await
+            .is_err());
+    }
+}
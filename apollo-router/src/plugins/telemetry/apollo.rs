@@ -1,17 +1,26 @@
 //! Configuration for apollo telemetry.
 // This entire file is license key functionality
 use std::collections::HashMap;
+use std::num::NonZeroU64;
+use std::num::NonZeroUsize;
 use std::ops::AddAssign;
+use std::time::Duration;
 use std::time::SystemTime;
 
 use derivative::Derivative;
 use http::header::HeaderName;
+use http::HeaderMap;
+use http::HeaderValue;
 use itertools::Itertools;
 use schemars::JsonSchema;
 use serde::Deserialize;
 use serde::Serialize;
+use sha2::Digest;
+use sha2::Sha256;
+use tower::BoxError;
 use url::Url;
 
+use super::apollo_exporter::EXPORTER_TIMEOUT_DURATION;
 use super::metrics::apollo::studio::ContextualizedStats;
 use super::metrics::apollo::studio::SingleStats;
 use super::metrics::apollo::studio::SingleStatsReport;
@@ -33,6 +42,25 @@ pub(crate) struct Config {
     #[schemars(with = "Option<String>")]
     pub(crate) endpoint: Option<Url>,
 
+    /// The URL the usage reports are posted to, instead of the Apollo Studio ingress.
+    /// Only used when `endpoint` is not set
+    #[schemars(with = "Option<String>")]
+    pub(crate) ingress_url: Option<Url>,
+
+    /// Headers added to the usage report uploads, like the credentials of a proxy in front of the ingress.
+    /// Only used when `endpoint` is not set
+    #[serde(default)]
+    pub(crate) ingress_headers: HashMap<String, String>,
+
+    /// Buffering and retries of the usage report uploads, while the ingress is unreachable.
+    /// Only used when `endpoint` is not set
+    #[serde(default)]
+    pub(crate) report_uploads: ReportUploads,
+
+    /// When the usage reports aggregated in memory are sent, on an interval or once they are large enough
+    #[serde(default)]
+    pub(crate) report_flush: ReportFlush,
+
     /// The Apollo Studio API key.
     #[schemars(skip)]
     #[serde(skip, default = "apollo_key")]
@@ -68,6 +96,11 @@ pub(crate) struct Config {
     /// Value MUST be less than global sampling rate
     pub(crate) field_level_instrumentation_sampler: Option<SamplerOption>,
 
+    /// Types and fields, named `Type` or `Type.field`, left out of the per field stats and field level traces
+    /// sent to Apollo Studio. The requests selecting them are still counted in the operation stats
+    #[serde(default)]
+    pub(crate) field_level_instrumentation_exclude: Vec<String>,
+
     /// To configure which request header names and values are included in trace data that's sent to Apollo Studio.
     #[serde(default)]
     pub(crate) send_headers: ForwardHeaders,
@@ -75,12 +108,75 @@ pub(crate) struct Config {
     #[serde(default)]
     pub(crate) send_variable_values: ForwardValues,
 
+    /// How the operation signatures identifying the operations in Studio reports are formed.
+    /// All the routers reporting to a graph should use the same settings
+    #[serde(default)]
+    pub(crate) operation_signature: OperationSignature,
+
+    /// Precision of the request latencies in Studio reports. A coarser precision makes the latency
+    /// histograms cheaper to compute under very high traffic
+    /// default: nanosecond
+    #[serde(default)]
+    pub(crate) latency_precision: LatencyPrecision,
+
     // This'll get overridden if a user tries to set it.
     // The purpose is to allow is to pass this in to the plugin.
     #[schemars(skip)]
     pub(crate) schema_id: String,
 }
 
+const DEFAULT_INGRESS_URL: &str =
+    "https://usage-reporting.api.apollographql.com/api/ingress/traces";
+const INGRESS_URL_ENV: &str = "APOLLO_USAGE_REPORTING_INGRESS_URL";
+
+impl Config {
+    /// The URL the embedded spaceport posts the usage reports to, and the headers of the uploads,
+    /// once the upload settings are checked
+    ///
+    /// The URL is taken from the configuration, then from the `APOLLO_USAGE_REPORTING_INGRESS_URL`
+    /// environment variable, and defaults to the Apollo Studio ingress.
+    pub(crate) fn ingress(&self) -> Result<(Url, HeaderMap), BoxError> {
+        if self.endpoint.is_some()
+            && (self.ingress_url.is_some() || !self.ingress_headers.is_empty())
+        {
+            return Err("apollo.ingress_url and apollo.ingress_headers cannot be used with apollo.endpoint: the reports are uploaded by the spaceport at the endpoint".into());
+        }
+        let url = match &self.ingress_url {
+            Some(url) => url.clone(),
+            None => match std::env::var(INGRESS_URL_ENV) {
+                Ok(url) => Url::parse(&url)
+                    .map_err(|e| format!("invalid {} '{}': {}", INGRESS_URL_ENV, url, e))?,
+                Err(_) => Url::parse(DEFAULT_INGRESS_URL).expect("default ingress URL is valid"),
+            },
+        };
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(format!(
+                "the usage reporting ingress URL '{}' must use the http or https scheme",
+                url
+            )
+            .into());
+        }
+        let uploads = &self.report_uploads;
+        if uploads.min_backoff.is_zero() || uploads.min_backoff > uploads.max_backoff {
+            return Err(format!(
+                "apollo.report_uploads.min_backoff ({:?}) must be greater than zero and at most max_backoff ({:?})",
+                uploads.min_backoff, uploads.max_backoff
+            )
+            .into());
+        }
+        let mut headers = HeaderMap::new();
+        for (name, value) in &self.ingress_headers {
+            let name = HeaderName::try_from(name)
+                .map_err(|e| format!("invalid apollo.ingress_headers name '{}': {}", name, e))?;
+            let value = HeaderValue::try_from(value).map_err(|e| {
+                format!("invalid apollo.ingress_headers value of '{}': {}", name, e)
+            })?;
+            headers.insert(name, value);
+        }
+        Ok((url, headers))
+    }
+}
+
 fn apollo_key() -> Option<String> {
     std::env::var("APOLLO_KEY").ok()
 }
@@ -113,6 +209,10 @@ impl Default for Config {
     fn default() -> Self {
         Self {
             endpoint: None,
+            ingress_url: None,
+            ingress_headers: HashMap::new(),
+            report_uploads: ReportUploads::default(),
+            report_flush: ReportFlush::default(),
             apollo_key: None,
             apollo_graph_ref: None,
             client_name_header: client_name_header_default(),
@@ -120,8 +220,192 @@ impl Default for Config {
             schema_id: "<no_schema_id>".to_string(),
             buffer_size: default_buffer_size(),
             field_level_instrumentation_sampler: Some(SamplerOption::TraceIdRatioBased(0.01)),
+            field_level_instrumentation_exclude: Vec::new(),
             send_headers: ForwardHeaders::None,
             send_variable_values: ForwardValues::None,
+            operation_signature: OperationSignature::default(),
+            latency_precision: LatencyPrecision::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ReportFlush {
+    /// Time between two sendings of the report
+    /// default: 6s
+    #[serde(with = "humantime_serde", default = "default_flush_interval")]
+    #[schemars(with = "String")]
+    pub(crate) interval: Duration,
+
+    /// Sends the report before the interval once it holds this number of distinct operations
+    pub(crate) max_operation_keys: Option<NonZeroUsize>,
+
+    /// Sends the report before the interval once it counts this number of operations
+    pub(crate) max_operations: Option<NonZeroU64>,
+}
+
+fn default_flush_interval() -> Duration {
+    EXPORTER_TIMEOUT_DURATION
+}
+
+impl Default for ReportFlush {
+    fn default() -> Self {
+        Self {
+            interval: default_flush_interval(),
+            max_operation_keys: None,
+            max_operations: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct ReportUploads {
+    /// Reports kept while their upload fails, the oldest ones are discarded past this number
+    /// default: 100
+    #[serde(default = "default_report_buffer_size")]
+    pub(crate) buffer_size: NonZeroUsize,
+
+    /// Delay before retrying a failed upload, doubled after each failure
+    /// default: 1s
+    #[serde(with = "humantime_serde", default = "default_min_backoff")]
+    #[schemars(with = "String")]
+    pub(crate) min_backoff: Duration,
+
+    /// Longest delay between two attempts at an upload
+    /// default: 60s
+    #[serde(with = "humantime_serde", default = "default_max_backoff")]
+    #[schemars(with = "String")]
+    pub(crate) max_backoff: Duration,
+
+    /// Gzip the reports before uploading them, as expected by the Apollo Studio ingress
+    /// default: true
+    #[serde(default = "default_compress")]
+    pub(crate) compress: bool,
+}
+
+fn default_report_buffer_size() -> NonZeroUsize {
+    NonZeroUsize::new(100).expect("100 is not zero")
+}
+
+fn default_min_backoff() -> Duration {
+    Duration::from_secs(1)
+}
+
+fn default_max_backoff() -> Duration {
+    Duration::from_secs(60)
+}
+
+fn default_compress() -> bool {
+    true
+}
+
+impl Default for ReportUploads {
+    fn default() -> Self {
+        Self {
+            buffer_size: default_report_buffer_size(),
+            min_backoff: default_min_backoff(),
+            max_backoff: default_max_backoff(),
+            compress: default_compress(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub(crate) struct OperationSignature {
+    /// Keep the operation name in the signature. Without it, operations that only differ by
+    /// their name share a signature
+    /// default: true
+    #[serde(default = "default_include_operation_name")]
+    pub(crate) include_operation_name: bool,
+
+    /// Hash function applied to the normalized operation
+    /// default: none
+    #[serde(default)]
+    pub(crate) hash: SignatureHash,
+}
+
+fn default_include_operation_name() -> bool {
+    true
+}
+
+impl Default for OperationSignature {
+    fn default() -> Self {
+        Self {
+            include_operation_name: default_include_operation_name(),
+            hash: SignatureHash::default(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum SignatureHash {
+    /// Keep the normalized operation, as computed by the query planner
+    None,
+    /// Replace the normalized operation with its SHA-256 hash, in hexadecimal
+    Sha256,
+}
+
+impl Default for SignatureHash {
+    fn default() -> Self {
+        SignatureHash::None
+    }
+}
+
+impl OperationSignature {
+    /// The key of an operation in the reports, from the `stats_report_key` of the query planner
+    ///
+    /// The planner keys look like `# OperationName\n<normalized operation>`, and the keys of
+    /// invalid operations like `## GraphQLValidationFailure\n`: those are kept as is.
+    pub(crate) fn stats_report_key(&self, planner_key: &str) -> String {
+        let (name, operation) = match planner_key
+            .strip_prefix("# ")
+            .and_then(|key| key.split_once('\n'))
+        {
+            Some(parts) => parts,
+            None => return planner_key.to_string(),
+        };
+        let name = if self.include_operation_name {
+            name
+        } else {
+            "-"
+        };
+        match self.hash {
+            SignatureHash::None => format!("# {}\n{}", name, operation),
+            SignatureHash::Sha256 => format!(
+                "# {}\n{}",
+                name,
+                hex::encode(Sha256::digest(operation.as_bytes()))
+            ),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum LatencyPrecision {
+    /// Keep the latencies as measured
+    Nanosecond,
+    /// Round the latencies to the nearest millisecond
+    Millisecond,
+}
+
+impl Default for LatencyPrecision {
+    fn default() -> Self {
+        LatencyPrecision::Nanosecond
+    }
+}
+
+impl LatencyPrecision {
+    pub(crate) fn apply(self, latency: Duration) -> Duration {
+        match self {
+            LatencyPrecision::Nanosecond => latency,
+            LatencyPrecision::Millisecond => {
+                Duration::from_millis(((latency.as_nanos() + 500_000) / 1_000_000) as u64)
+            }
         }
     }
 }
@@ -173,6 +457,16 @@ pub(crate) struct Report {
 }
 
 impl Report {
+    /// Whether the report reached a size at which it is sent before the flush interval
+    pub(crate) fn is_full(&self, flush: &ReportFlush) -> bool {
+        flush
+            .max_operation_keys
+            .map_or(false, |max| self.traces_per_query.len() >= max.get())
+            || flush
+                .max_operations
+                .map_or(false, |max| self.operation_count >= max.get())
+    }
+
     #[cfg(test)]
     pub(crate) fn new(reports: Vec<SingleStatsReport>) -> Report {
         let mut aggregated_report = Report::default();
@@ -275,3 +569,128 @@ pub(crate) mod vectorize {
         serde::Serialize::serialize(&container, ser)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use router_bridge::planner::Planner;
+    use router_bridge::planner::QueryPlannerConfig;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn semantically_identical_operations_have_the_same_key() {
+        let planner = Planner::<serde_json::Value>::new(
+            include_str!("../../query_planner/testdata/schema.graphql").to_string(),
+            QueryPlannerConfig {
+                incremental_delivery: None,
+            },
+        )
+        .await
+        .unwrap();
+        let mut keys = Vec::new();
+        for query in [
+            "query Me { me { id name { first last } } }",
+            "query Me {\n  me {\n    name { last first }\n    id\n  }\n}",
+        ] {
+            let usage_reporting = planner
+                .plan(query.to_string(), None)
+                .await
+                .unwrap()
+                .into_result()
+                .unwrap()
+                .usage_reporting;
+            keys.push(
+                OperationSignature::default().stats_report_key(&usage_reporting.stats_report_key),
+            );
+        }
+        assert_eq!(keys[0], keys[1]);
+        assert!(keys[0].starts_with("# Me\n"));
+    }
+
+    #[test]
+    fn it_forms_keys_from_the_signature_settings() {
+        let planner_key = "# Me\nquery Me{me{id}}";
+        assert_eq!(
+            OperationSignature::default().stats_report_key(planner_key),
+            planner_key
+        );
+
+        let signature = OperationSignature {
+            include_operation_name: false,
+            hash: SignatureHash::Sha256,
+        };
+        let key = signature.stats_report_key(planner_key);
+        assert_eq!(
+            key,
+            format!(
+                "# -\n{}",
+                hex::encode(Sha256::digest("query Me{me{id}}".as_bytes()))
+            )
+        );
+        assert_eq!(signature.stats_report_key("# Other\nquery Me{me{id}}"), key);
+
+        // the keys of invalid operations are kept
+        assert_eq!(
+            signature.stats_report_key("## GraphQLValidationFailure\n"),
+            "## GraphQLValidationFailure\n"
+        );
+    }
+
+    #[test]
+    fn it_rejects_invalid_ingress_settings() {
+        let config = Config {
+            ingress_url: Some(Url::parse("https://proxy.example.com/reports").unwrap()),
+            ingress_headers: [("x-proxy-token".to_string(), "secret".to_string())].into(),
+            ..Default::default()
+        };
+        let (url, headers) = config.ingress().unwrap();
+        assert_eq!(url.as_str(), "https://proxy.example.com/reports");
+        assert_eq!(headers["x-proxy-token"], "secret");
+
+        let err = Config {
+            ingress_headers: [("invalid header".to_string(), "secret".to_string())].into(),
+            ..Default::default()
+        }
+        .ingress()
+        .unwrap_err()
+        .to_string();
+        assert!(
+            err.contains("invalid apollo.ingress_headers name"),
+            "{}",
+            err
+        );
+
+        assert!(Config {
+            ingress_url: Some(Url::parse("ftp://proxy.example.com").unwrap()),
+            ..Default::default()
+        }
+        .ingress()
+        .is_err());
+
+        assert!(Config {
+            endpoint: Some(Url::parse("https://spaceport.example.com").unwrap()),
+            ingress_url: Some(Url::parse("https://proxy.example.com").unwrap()),
+            ..Default::default()
+        }
+        .ingress()
+        .is_err());
+    }
+
+    #[test]
+    fn it_rounds_latencies_to_the_precision() {
+        let latency = Duration::from_nanos(1_499_999);
+        assert_eq!(LatencyPrecision::Nanosecond.apply(latency), latency);
+        assert_eq!(
+            LatencyPrecision::Millisecond.apply(latency),
+            Duration::from_millis(1)
+        );
+        assert_eq!(
+            LatencyPrecision::Millisecond.apply(Duration::from_nanos(1_500_000)),
+            Duration::from_millis(2)
+        );
+        assert_eq!(
+            LatencyPrecision::Millisecond.apply(Duration::from_micros(400)),
+            Duration::ZERO
+        );
+    }
+}
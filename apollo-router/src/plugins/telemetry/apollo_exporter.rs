@@ -14,6 +14,7 @@ use tower::BoxError;
 use url::Url;
 
 use super::apollo::Report;
+use super::apollo::ReportFlush;
 use super::apollo::SingleReport;
 use crate::spaceport::ReportHeader;
 use crate::spaceport::Reporter;
@@ -22,6 +23,7 @@ use crate::spaceport::ReporterError;
 
 const DEFAULT_QUEUE_SIZE: usize = 65_536;
 const DEADPOOL_SIZE: usize = 128;
+// Default interval between two reports.
 // Do not set to 5 secs because it's also the default value for the BatchSpanProcesser of tracing.
 // It's less error prone to set a different value to let us compute traces and metrics
 pub(crate) const EXPORTER_TIMEOUT_DURATION: Duration = Duration::from_secs(6);
@@ -65,7 +67,12 @@ impl ApolloExporter {
         apollo_key: &str,
         apollo_graph_ref: &str,
         schema_id: &str,
+        flush: &ReportFlush,
     ) -> Result<ApolloExporter, BoxError> {
+        if flush.interval.is_zero() {
+            return Err("apollo.report_flush.interval must be greater than zero".into());
+        }
+        let flush = flush.clone();
         let apollo_key = apollo_key.to_string();
         // Desired behavior:
         // * Metrics are batched with a timeout.
@@ -119,7 +126,10 @@ impl ApolloExporter {
 
         // This is the task that actually sends metrics
         tokio::spawn(async move {
-            let timeout = tokio::time::interval(EXPORTER_TIMEOUT_DURATION);
+            let timeout = tokio::time::interval_at(
+                tokio::time::Instant::now() + flush.interval,
+                flush.interval,
+            );
             let mut report = Report::default();
 
             tokio::pin!(timeout);
@@ -129,6 +139,11 @@ impl ApolloExporter {
                     single_report = rx.next() => {
                         if let Some(r) = single_report {
                             report += r;
+                            if report.is_full(&flush) {
+                                Self::send_report(&pool, &apollo_key, &header, std::mem::take(&mut report)).await;
+                                // the next report gets a whole interval
+                                timeout.reset();
+                            }
                         } else {
                             tracing::info!(%spaceport_endpoint, "terminating apollo exporter");
                             break;
@@ -229,3 +244,89 @@ pub(crate) fn get_uname() -> Result<String, std::io::Error> {
         sysname, nodename, release, version, machine
     ))
 }
+
+#[cfg(test)]
+mod test {
+    use std::num::NonZeroU64;
+
+    use tokio::net::TcpListener;
+    use tokio_stream::wrappers::TcpListenerStream;
+    use tonic::transport::Server;
+    use tonic::Request;
+    use tonic::Response;
+    use tonic::Status;
+
+    use super::*;
+    use crate::plugins::telemetry::metrics::apollo::studio::SingleStatsReport;
+    use crate::spaceport::reporter_server::Reporter as ReporterService;
+    use crate::spaceport::reporter_server::ReporterServer;
+    use crate::spaceport::ReporterRequest;
+    use crate::spaceport::ReporterResponse;
+
+    /// A spaceport forwarding the reports it receives
+    struct MockSpaceport {
+        reports: tokio::sync::mpsc::UnboundedSender<ReporterRequest>,
+    }
+
+    #[tonic::async_trait]
+    impl ReporterService for MockSpaceport {
+        async fn add(
+            &self,
+            request: Request<ReporterRequest>,
+        ) -> Result<Response<ReporterResponse>, Status> {
+            let _ = self.reports.send(request.into_inner());
+            Ok(Response::new(ReporterResponse {
+                message: "Report accepted".to_string(),
+            }))
+        }
+    }
+
+    fn stats_report(key: &str) -> SingleReport {
+        SingleReport::Stats(SingleStatsReport {
+            stats: [(key.to_string(), Default::default())].into(),
+            operation_count: 1,
+            ..Default::default()
+        })
+    }
+
+    #[tokio::test]
+    async fn it_flushes_the_report_once_it_is_full() {
+        let (reports, mut received) = tokio::sync::mpsc::unbounded_channel();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let endpoint = Url::parse(&format!("http://{}", listener.local_addr().unwrap())).unwrap();
+        tokio::spawn(
+            Server::builder()
+                .add_service(ReporterServer::new(MockSpaceport { reports }))
+                .serve_with_incoming(TcpListenerStream::new(listener)),
+        );
+
+        let flush = ReportFlush {
+            // only the size of the report can trigger a flush during the test
+            interval: Duration::from_secs(3600),
+            max_operations: NonZeroU64::new(3),
+            ..Default::default()
+        };
+        let exporter =
+            ApolloExporter::new(&endpoint, "key", "graph@current", "schema", &flush).unwrap();
+        let sender = exporter.provider();
+
+        sender.send(stats_report("# A\n{a}"));
+        sender.send(stats_report("# B\n{b}"));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(received.try_recv().is_err());
+
+        sender.send(stats_report("# A\n{a}"));
+        let request = tokio::time::timeout(Duration::from_secs(5), received.recv())
+            .await
+            .expect("the full report was not flushed")
+            .unwrap();
+        let report = request.report.unwrap();
+        assert_eq!(report.operation_count, 3);
+        assert_eq!(report.traces_per_query.len(), 2);
+
+        // the next report starts empty
+        sender.send(stats_report("# C\n{c}"));
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(received.try_recv().is_err());
+    }
+}
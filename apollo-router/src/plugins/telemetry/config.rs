@@ -8,6 +8,7 @@ use opentelemetry::KeyValue;
 use opentelemetry::Value;
 use schemars::JsonSchema;
 use serde::Deserialize;
+use serde::Serialize;
 
 use super::metrics::MetricsAttributesConf;
 use super::*;
@@ -46,12 +47,20 @@ impl<T> GenericWith<T> for T where Self: Sized {}
 #[derive(Clone, Default, Debug, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields, rename_all = "snake_case")]
 pub struct Conf {
+    pub(crate) logging: Option<Logging>,
     #[allow(dead_code)]
     pub(crate) metrics: Option<Metrics>,
     pub(crate) tracing: Option<Tracing>,
     pub(crate) apollo: Option<apollo::Config>,
 }
 
+#[derive(Clone, Default, Debug, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub(crate) struct Logging {
+    /// Log filter, with the same syntax as the `--log` argument. It overrides the command line and can be changed on reload.
+    pub(crate) filter: Option<String>,
+}
+
 #[derive(Clone, Default, Debug, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields, rename_all = "snake_case")]
 #[allow(dead_code)]
@@ -59,6 +68,8 @@ pub(crate) struct Metrics {
     pub(crate) common: Option<MetricsCommon>,
     pub(crate) otlp: Option<otlp::Config>,
     pub(crate) prometheus: Option<metrics::prometheus::Config>,
+    /// Send the metrics to a StatsD server
+    pub(crate) statsd: Option<metrics::statsd::Config>,
 }
 
 #[derive(Clone, Default, Debug, Deserialize, JsonSchema)]
@@ -73,6 +84,112 @@ pub(crate) struct MetricsCommon {
     #[serde(default)]
     /// Resources
     pub(crate) resources: HashMap<String, String>,
+    /// Labels/attributes added to all the metrics, like the region or environment of the deployment
+    #[serde(default)]
+    pub(crate) static_attributes: HashMap<String, String>,
+    /// Record how many requests wait in the router's internal buffers, and for how long
+    #[serde(default)]
+    pub(crate) buffer_metrics: bool,
+    /// Record how many connections to each subgraph serve a request or stay idle, and how long
+    /// the requests wait for new connections
+    #[serde(default)]
+    pub(crate) connection_pool_metrics: bool,
+    /// Increment the `apollo_router_uptime_heartbeats_total` counter on an interval, so metrics
+    /// keep flowing when the router receives no requests
+    #[serde(default)]
+    pub(crate) heartbeat: Heartbeat,
+    /// Expose an admin endpoint to disable and enable the metrics exporters at runtime, like
+    /// to isolate a failing OTLP collector without restarting the router. The endpoint is not
+    /// authenticated, so it must only be reachable from a management network
+    #[serde(default)]
+    pub(crate) exporter_toggles: ExporterToggles,
+    /// What to do when the metrics are configured without any exporter
+    /// default: warn
+    #[serde(default)]
+    pub(crate) fallback: MetricsFallback,
+}
+
+/// What to do when the metrics are configured without any exporter
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum MetricsFallback {
+    /// Log a warning that the metrics are not exported
+    Warn,
+    /// Record the metrics in memory, like for tests, without exporting them. They are readable
+    /// in the Prometheus text format at `/metrics/in_memory`, on the listen address of the
+    /// exporter toggles
+    InMemory,
+    /// Nothing, the metrics are not recorded
+    Ignore,
+}
+
+impl Default for MetricsFallback {
+    fn default() -> Self {
+        MetricsFallback::Warn
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub(crate) struct Heartbeat {
+    /// Send the heartbeats
+    /// default: false
+    #[serde(default)]
+    pub(crate) enabled: bool,
+    /// Time between two heartbeats
+    /// default: 30s
+    #[serde(with = "humantime_serde", default = "default_heartbeat_interval")]
+    #[schemars(with = "String")]
+    pub(crate) interval: Duration,
+}
+
+fn default_heartbeat_interval() -> Duration {
+    Duration::from_secs(30)
+}
+
+impl Default for Heartbeat {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            interval: default_heartbeat_interval(),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, JsonSchema)]
+#[serde(deny_unknown_fields, rename_all = "snake_case")]
+pub(crate) struct ExporterToggles {
+    /// Expose the endpoint
+    /// default: false
+    #[serde(default)]
+    pub(crate) enabled: bool,
+    /// The socket address and port to listen on, preferably a management port, apart from the
+    /// Prometheus endpoint which may be exposed to scrapers
+    /// default: 127.0.0.1:9091
+    #[serde(default = "default_exporter_toggles_listen")]
+    pub(crate) listen: ListenAddr,
+    /// The HTTP path of the endpoint
+    /// default: /metrics/exporters
+    #[serde(default = "default_exporter_toggles_path")]
+    pub(crate) path: String,
+}
+
+fn default_exporter_toggles_listen() -> ListenAddr {
+    ListenAddr::SocketAddr("127.0.0.1:9091".parse().expect("valid listenAddr"))
+}
+
+fn default_exporter_toggles_path() -> String {
+    "/metrics/exporters".to_string()
+}
+
+impl Default for ExporterToggles {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            listen: default_exporter_toggles_listen(),
+            path: default_exporter_toggles_path(),
+        }
+    }
 }
 
 #[derive(Clone, Default, Debug, Deserialize, JsonSchema)]
@@ -84,6 +201,10 @@ pub(crate) struct Tracing {
     pub(crate) jaeger: Option<tracing::jaeger::Config>,
     pub(crate) zipkin: Option<tracing::zipkin::Config>,
     pub(crate) datadog: Option<tracing::datadog::Config>,
+    /// Add the query plan summary to the extensions of the responses, as `queryPlanSummary`,
+    /// to debug the query plans (default: false)
+    #[serde(default)]
+    pub(crate) query_plan_summary_extension: bool,
 }
 
 #[derive(Clone, Default, Debug, Deserialize, JsonSchema)]
@@ -94,6 +215,26 @@ pub(crate) struct Propagation {
     pub(crate) jaeger: Option<bool>,
     pub(crate) datadog: Option<bool>,
     pub(crate) zipkin: Option<bool>,
+    /// Formats of the trace context accepted from the headers of incoming requests
+    /// (default: the propagated formats)
+    pub(crate) incoming: Option<Vec<PropagationFormat>>,
+}
+
+/// Format of a trace context sent in HTTP headers
+// The variants are sorted in the order of the propagators: the jaeger propagator must be first
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum PropagationFormat {
+    /// Jaeger `uber-trace-id` header
+    Jaeger,
+    /// W3C `baggage` header
+    Baggage,
+    /// W3C `traceparent` and `tracestate` headers
+    TraceContext,
+    /// Zipkin B3 headers
+    Zipkin,
+    /// Datadog `x-datadog-*` headers
+    Datadog,
 }
 
 #[derive(Default, Debug, Clone, Deserialize, JsonSchema)]
@@ -11,7 +11,7 @@ use crate::plugins::telemetry::config::MetricsCommon;
 use crate::plugins::telemetry::metrics::MetricsBuilder;
 use crate::plugins::telemetry::metrics::MetricsConfigurator;
 
-mod duration_histogram;
+pub(crate) mod duration_histogram;
 pub(crate) mod studio;
 
 impl MetricsConfigurator for Config {
@@ -28,13 +28,15 @@ impl MetricsConfigurator for Config {
                 apollo_key: Some(key),
                 apollo_graph_ref: Some(reference),
                 schema_id,
+                report_flush,
                 ..
             } => {
                 if !ENABLED.swap(true, Ordering::Relaxed) {
                     tracing::info!("Apollo Studio usage reporting is enabled. See https://go.apollo.dev/o/data for details");
                 }
                 tracing::debug!("creating metrics exporter");
-                let exporter = ApolloExporter::new(endpoint, key, reference, schema_id)?;
+                let exporter =
+                    ApolloExporter::new(endpoint, key, reference, schema_id, report_flush)?;
 
                 builder
                     .with_apollo_metrics_collector(exporter.provider())
@@ -67,6 +69,7 @@ mod test {
     use crate::plugins::telemetry::apollo_exporter::Sender;
     use crate::plugins::telemetry::Telemetry;
     use crate::plugins::telemetry::STUDIO_EXCLUDE;
+    use crate::spaceport::StatsContext;
     use crate::Context;
     use crate::SupergraphRequest;
     use crate::TestHarness;
@@ -175,10 +178,58 @@ mod test {
         Ok(())
     }
 
+    #[tokio::test(flavor = "multi_thread")]
+    async fn apollo_metrics_client_name_and_version() -> Result<(), BoxError> {
+        let query = "query {topProducts{name}}";
+        let results = get_metrics_for_request(query, None, None).await?;
+        let report = apollo::Report::new(results);
+        let contexts: Vec<_> = report
+            .traces_per_query
+            .values()
+            .flat_map(|stats| stats.stats_with_context.keys())
+            .collect();
+        assert_eq!(
+            contexts,
+            [&StatsContext {
+                client_name: "test_client".to_string(),
+                client_version: "1.0-test".to_string(),
+            }]
+        );
+
+        // without the headers, the client is unknown
+        let results = get_metrics_for_supergraph_request(
+            SupergraphRequest::fake_builder().query(query).build()?,
+        )
+        .await?;
+        let report = apollo::Report::new(results);
+        let contexts: Vec<_> = report
+            .traces_per_query
+            .values()
+            .flat_map(|stats| stats.stats_with_context.keys())
+            .collect();
+        assert_eq!(contexts, [&StatsContext::default()]);
+        Ok(())
+    }
+
     async fn get_metrics_for_request(
         query: &str,
         operation_name: Option<&str>,
         context: Option<Context>,
+    ) -> Result<Vec<SingleStatsReport>, BoxError> {
+        get_metrics_for_supergraph_request(
+            SupergraphRequest::fake_builder()
+                .header("name_header", "test_client")
+                .header("version_header", "1.0-test")
+                .query(query)
+                .and_operation_name(operation_name)
+                .and_context(context)
+                .build()?,
+        )
+        .await
+    }
+
+    async fn get_metrics_for_supergraph_request(
+        request: SupergraphRequest,
     ) -> Result<Vec<SingleStatsReport>, BoxError> {
         let _ = tracing_subscriber::fmt::try_init();
         let mut plugin = create_plugin().await?;
@@ -189,15 +240,7 @@ mod test {
             .extra_plugin(plugin)
             .build()
             .await?
-            .oneshot(
-                SupergraphRequest::fake_builder()
-                    .header("name_header", "test_client")
-                    .header("version_header", "1.0-test")
-                    .query(query)
-                    .and_operation_name(operation_name)
-                    .and_context(context)
-                    .build()?,
-            )
+            .oneshot(request)
             .await
             .unwrap()
             .next_response()
@@ -240,6 +283,7 @@ mod test {
     ) -> Result<Telemetry, BoxError> {
         Telemetry::new(PluginInit::new(
             config::Conf {
+                logging: None,
                 metrics: None,
                 tracing: None,
                 apollo: Some(apollo_config),
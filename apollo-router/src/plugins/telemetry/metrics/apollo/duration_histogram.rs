@@ -1,6 +1,19 @@
 use std::time::Duration;
 
+use once_cell::sync::Lazy;
 use serde::Serialize;
+
+// Durations up to 10s, the latencies of nearly all requests
+const MILLISECOND_BUCKETS_LIMIT: u64 = 10_000;
+
+// Buckets of the durations in whole milliseconds, like the latencies rounded to the millisecond,
+// looked up instead of computed
+static MILLISECOND_BUCKETS: Lazy<Vec<u16>> = Lazy::new(|| {
+    (0..=MILLISECOND_BUCKETS_LIMIT)
+        .map(|millis| DurationHistogram::compute_bucket(Duration::from_millis(millis)) as u16)
+        .collect()
+});
+
 #[derive(Serialize, Debug)]
 pub(crate) struct DurationHistogram {
     pub(crate) buckets: Vec<i64>,
@@ -32,6 +45,16 @@ impl DurationHistogram {
     }
 
     fn duration_to_bucket(duration: Duration) -> usize {
+        if duration.subsec_nanos() % 1_000_000 == 0 {
+            let millis = duration.as_millis();
+            if millis <= MILLISECOND_BUCKETS_LIMIT as u128 {
+                return MILLISECOND_BUCKETS[millis as usize] as usize;
+            }
+        }
+        DurationHistogram::compute_bucket(duration)
+    }
+
+    fn compute_bucket(duration: Duration) -> usize {
         // If you use as_micros() here to avoid the divide, tests will fail
         // Because, internally, as_micros() is losing remainders
         let log_duration = f64::ln(duration.as_nanos() as f64 / 1000.0);
@@ -64,6 +87,33 @@ impl DurationHistogram {
     }
 }
 
+/// Hook for the benchmarks of `apollo-router-benchmarks`, not part of the public API
+#[cfg(feature = "bench")]
+pub mod bench {
+    use std::time::Duration;
+
+    /// Wraps the crate private histogram of the Apollo Studio reports
+    pub struct DurationHistogram(super::DurationHistogram);
+
+    impl DurationHistogram {
+        /// An empty histogram, of the default size
+        pub fn new() -> Self {
+            Self(super::DurationHistogram::new(None))
+        }
+
+        /// Counts one duration
+        pub fn increment_duration(&mut self, duration: Duration) {
+            self.0.increment_duration(Some(duration), 1)
+        }
+    }
+
+    impl Default for DurationHistogram {
+        fn default() -> Self {
+            Self::new()
+        }
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -177,6 +227,32 @@ mod test {
         );
     }
 
+    #[test]
+    fn it_looks_up_the_buckets_of_whole_milliseconds() {
+        for millis in 0..=MILLISECOND_BUCKETS_LIMIT {
+            let duration = Duration::from_millis(millis);
+            assert_eq!(
+                DurationHistogram::duration_to_bucket(duration),
+                DurationHistogram::compute_bucket(duration),
+                "{:?}",
+                duration
+            );
+        }
+        assert_eq!(
+            DurationHistogram::duration_to_bucket(Duration::from_millis(1)),
+            73
+        );
+        assert_eq!(
+            DurationHistogram::duration_to_bucket(Duration::from_secs(1)),
+            145
+        );
+        // past the table
+        assert_eq!(
+            DurationHistogram::duration_to_bucket(Duration::from_secs(60)),
+            DurationHistogram::compute_bucket(Duration::from_secs(60))
+        );
+    }
+
     #[test]
     fn it_limits_to_last_bucket() {
         assert_eq!(
@@ -48,6 +48,27 @@ pub(crate) struct SingleContextualizedStats {
     pub(crate) per_type_stat: HashMap<String, SingleTypeStat>,
 }
 
+impl SingleContextualizedStats {
+    /// Removes the per field stats of the excluded types and fields, named `Type` or
+    /// `Type.field`. The request is still counted in the query latency stats
+    pub(crate) fn exclude_fields(&mut self, excluded: &[String]) {
+        if excluded.is_empty() {
+            return;
+        }
+        self.per_type_stat.retain(|type_name, type_stat| {
+            if excluded.contains(type_name) {
+                return false;
+            }
+            type_stat.per_field_stat.retain(|field_name, _| {
+                !excluded.iter().any(|excluded| {
+                    excluded.split_once('.') == Some((type_name.as_str(), field_name.as_str()))
+                })
+            });
+            !type_stat.per_field_stat.is_empty()
+        });
+    }
+}
+
 impl Add<SingleContextualizedStats> for SingleContextualizedStats {
     type Output = ContextualizedStats;
 
@@ -328,6 +349,32 @@ mod test {
         );
     }
 
+    #[test]
+    fn test_field_exclusion() {
+        let mut metric = create_test_metric("client_1", "version_1", "report_key_1");
+        let stats = &mut metric
+            .stats
+            .get_mut("report_key_1")
+            .unwrap()
+            .stats_with_context;
+        stats.exclude_fields(&["type1.field1".to_string(), "type2".to_string()]);
+
+        assert_eq!(stats.per_type_stat.len(), 1);
+        let type1 = &stats.per_type_stat["type1"].per_field_stat;
+        assert!(!type1.contains_key("field1"));
+        assert!(type1.contains_key("field2"));
+        assert_eq!(stats.query_latency_stats.latency, Duration::from_secs(1));
+
+        let aggregated_metrics = Report::new(vec![metric]);
+        let stats = &aggregated_metrics.traces_per_query["report_key_1"].stats_with_context;
+        let stats = stats.values().next().unwrap();
+        assert_eq!(stats.query_latency_stats.request_latencies.entries, 1);
+        assert_eq!(
+            stats.per_type_stat.keys().collect::<Vec<_>>(),
+            vec!["type1"]
+        );
+    }
+
     fn create_test_metric(
         client_name: &str,
         client_version: &str,
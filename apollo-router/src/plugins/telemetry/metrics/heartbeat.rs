@@ -0,0 +1,91 @@
+//! A counter incremented periodically, so the router sends metrics even without traffic.
+
+use std::time::Duration;
+
+use tokio::task::JoinHandle;
+
+use super::AggregateMeterProvider;
+
+/// Increments `apollo_router_uptime_heartbeats_total` on an interval, until it is dropped
+pub(crate) struct Heartbeat {
+    task: JoinHandle<()>,
+}
+
+impl Heartbeat {
+    pub(crate) fn start(interval: Duration, meter_provider: &AggregateMeterProvider) -> Self {
+        let heartbeats = meter_provider
+            .meter("apollo/router", None)
+            .build_counter(|m| {
+                m.u64_counter("apollo_router_uptime_heartbeats_total")
+                    .with_description(
+                        "Number of heartbeats of the router, incremented on an interval whether it receives requests or not.",
+                    )
+                    .init()
+            });
+        let mut interval = tokio::time::interval(interval);
+        let task = tokio::spawn(async move {
+            loop {
+                interval.tick().await;
+                heartbeats.add(1, &[]);
+            }
+        });
+        Heartbeat { task }
+    }
+}
+
+impl Drop for Heartbeat {
+    // the heartbeats stop with the telemetry plugin, a reload starts new ones
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use prometheus::Registry;
+
+    use super::*;
+
+    fn heartbeats(registry: &Registry) -> f64 {
+        registry
+            .gather()
+            .iter()
+            .filter(|family| {
+                family
+                    .get_name()
+                    .starts_with("apollo_router_uptime_heartbeats")
+            })
+            .flat_map(|family| family.get_metric())
+            .map(|metric| metric.get_counter().get_value())
+            .sum()
+    }
+
+    #[tokio::test]
+    async fn it_beats_without_requests() {
+        let exporter = opentelemetry_prometheus::exporter().init();
+        let meter_provider = AggregateMeterProvider::new(vec![Arc::new(
+            exporter.provider().expect("meter provider"),
+        )]);
+
+        let heartbeat = Heartbeat::start(Duration::from_millis(20), &meter_provider);
+        tokio::time::sleep(Duration::from_millis(110)).await;
+        let beats = heartbeats(exporter.registry());
+        assert!(
+            beats >= 3.0,
+            "expected at least 3 heartbeats, got {}",
+            beats
+        );
+
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert!(heartbeats(exporter.registry()) > beats);
+
+        drop(heartbeat);
+        // let the aborted task stop
+        tokio::task::yield_now().await;
+        let beats = heartbeats(exporter.registry());
+        tokio::time::sleep(Duration::from_millis(60)).await;
+        assert_eq!(heartbeats(exporter.registry()), beats);
+    }
+}
@@ -1,5 +1,9 @@
 use std::any::Any;
+use std::borrow::Cow;
+use std::collections::BTreeMap;
 use std::collections::HashMap;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 
 use ::serde::Deserialize;
@@ -12,6 +16,7 @@ use opentelemetry::metrics::Counter;
 use opentelemetry::metrics::Meter;
 use opentelemetry::metrics::MeterProvider;
 use opentelemetry::metrics::Number;
+use opentelemetry::metrics::UpDownCounter;
 use opentelemetry::metrics::ValueRecorder;
 use opentelemetry::KeyValue;
 use regex::Regex;
@@ -19,6 +24,7 @@ use schemars::JsonSchema;
 use serde::Serialize;
 use serde_json::Value;
 use tower::BoxError;
+use tower::ServiceExt;
 
 use crate::error::FetchError;
 use crate::graphql;
@@ -28,13 +34,19 @@ use crate::plugin::serde::deserialize_json_query;
 use crate::plugin::serde::deserialize_regex;
 use crate::plugins::telemetry::apollo_exporter::Sender;
 use crate::plugins::telemetry::config::MetricsCommon;
+use crate::plugins::telemetry::config::MetricsFallback;
+use crate::plugins::telemetry::metrics::prometheus::Exemplars;
+use crate::plugins::telemetry::metrics::prometheus::PrometheusService;
 use crate::router_factory::Endpoint;
 use crate::Context;
 use crate::ListenAddr;
 
 pub(crate) mod apollo;
+pub(crate) mod heartbeat;
 pub(crate) mod otlp;
 pub(crate) mod prometheus;
+pub(crate) mod statsd;
+pub(crate) mod toggles;
 
 pub(crate) type MetricsExporterHandle = Box<dyn Any + Send + Sync + 'static>;
 
@@ -46,6 +58,9 @@ pub(crate) struct MetricsAttributesConf {
     pub(crate) router: Option<AttributesForwardConf>,
     /// Configuration to forward header values or body values from subgraph request/response in metric attributes/labels
     pub(crate) subgraph: Option<SubgraphAttributesConf>,
+    /// Names of the router attributes/labels computed from the router request, like `operation_name`, to add to the subgraph metrics too
+    #[serde(default)]
+    pub(crate) inherit_router_attributes: Vec<String>,
 }
 
 #[derive(Debug, Clone, Deserialize, JsonSchema)]
@@ -141,6 +156,107 @@ pub(crate) struct ContextForward {
     pub(crate) default: Option<String>,
 }
 
+#[buildstructor::buildstructor]
+impl AttributesForwardConf {
+    #[builder]
+    pub(crate) fn new(
+        inserts: Vec<Insert>,
+        request: Option<Forward>,
+        response: Option<Forward>,
+        contexts: Vec<ContextForward>,
+        errors: Option<ErrorsForward>,
+    ) -> Self {
+        Self {
+            insert: (!inserts.is_empty()).then_some(inserts),
+            request,
+            response,
+            context: (!contexts.is_empty()).then_some(contexts),
+            errors,
+        }
+    }
+}
+
+#[cfg(test)]
+#[buildstructor::buildstructor]
+impl Insert {
+    #[builder]
+    pub(crate) fn new(name: String, value: String) -> Self {
+        Self { name, value }
+    }
+}
+
+#[cfg(test)]
+#[buildstructor::buildstructor]
+impl Forward {
+    #[builder]
+    pub(crate) fn new(headers: Vec<HeaderForward>, bodies: Vec<BodyForward>) -> Self {
+        Self {
+            header: (!headers.is_empty()).then_some(headers),
+            body: (!bodies.is_empty()).then_some(bodies),
+        }
+    }
+}
+
+#[cfg(test)]
+#[buildstructor::buildstructor]
+impl ErrorsForward {
+    #[builder]
+    pub(crate) fn new(include_messages: Option<bool>, extensions: Vec<BodyForward>) -> Self {
+        Self {
+            include_messages: include_messages.unwrap_or_default(),
+            extensions: (!extensions.is_empty()).then_some(extensions),
+        }
+    }
+}
+
+#[cfg(test)]
+#[buildstructor::buildstructor]
+impl HeaderForward {
+    #[builder]
+    pub(crate) fn named_new(
+        named: HeaderName,
+        rename: Option<String>,
+        default: Option<String>,
+    ) -> Self {
+        HeaderForward::Named {
+            named,
+            rename,
+            default,
+        }
+    }
+
+    #[builder]
+    pub(crate) fn matching_new(matching: Regex) -> Self {
+        HeaderForward::Matching { matching }
+    }
+}
+
+#[cfg(test)]
+#[buildstructor::buildstructor]
+impl BodyForward {
+    #[builder]
+    pub(crate) fn new(path: JSONQuery, name: String, default: Option<String>) -> Self {
+        Self {
+            path,
+            name,
+            default,
+        }
+    }
+}
+
+#[cfg(test)]
+#[buildstructor::buildstructor]
+impl ContextForward {
+    #[builder]
+    pub(crate) fn new(named: String, rename: Option<String>, default: Option<String>) -> Self {
+        Self {
+            named,
+            rename,
+            default,
+        }
+    }
+}
+
 impl HeaderForward {
     pub(crate) fn get_attributes_from_headers(
         &self,
@@ -452,9 +568,11 @@ fn string_schema(gen: &mut schemars::gen::SchemaGenerator) -> schemars::schema::
 #[derive(Default)]
 pub(crate) struct MetricsBuilder {
     exporters: Vec<MetricsExporterHandle>,
-    meter_providers: Vec<Arc<dyn MeterProvider + Send + Sync + 'static>>,
+    meter_providers: Vec<(&'static str, Arc<dyn MeterProvider + Send + Sync + 'static>)>,
     custom_endpoints: MultiMap<ListenAddr, Endpoint>,
     apollo_metrics: Sender,
+    static_attributes: Vec<KeyValue>,
+    exemplars: Option<Exemplars>,
 }
 
 impl MetricsBuilder {
@@ -462,7 +580,8 @@ impl MetricsBuilder {
         std::mem::take(&mut self.exporters)
     }
     pub(crate) fn meter_provider(&mut self) -> AggregateMeterProvider {
-        AggregateMeterProvider::new(std::mem::take(&mut self.meter_providers))
+        AggregateMeterProvider::with_names(std::mem::take(&mut self.meter_providers))
+            .with_static_attributes(std::mem::take(&mut self.static_attributes))
     }
     pub(crate) fn custom_endpoints(&mut self) -> MultiMap<ListenAddr, Endpoint> {
         std::mem::take(&mut self.custom_endpoints)
@@ -471,6 +590,10 @@ impl MetricsBuilder {
     pub(crate) fn apollo_metrics_provider(&mut self) -> Sender {
         self.apollo_metrics.clone()
     }
+
+    pub(crate) fn exemplars(&mut self) -> Option<Exemplars> {
+        self.exemplars.take()
+    }
 }
 
 impl MetricsBuilder {
@@ -479,11 +602,13 @@ impl MetricsBuilder {
         self
     }
 
+    /// Adds a meter provider to the aggregate, under the name of its exporter
     fn with_meter_provider<T: MeterProvider + Send + Sync + 'static>(
         mut self,
+        name: &'static str,
         meter_provider: T,
     ) -> Self {
-        self.meter_providers.push(Arc::new(meter_provider));
+        self.meter_providers.push((name, Arc::new(meter_provider)));
         self
     }
 
@@ -492,12 +617,64 @@ impl MetricsBuilder {
         self
     }
 
+    fn with_exemplars(mut self, exemplars: Exemplars) -> Self {
+        self.exemplars = Some(exemplars);
+        self
+    }
+
     fn with_apollo_metrics_collector(mut self, apollo_metrics: Sender) -> Self {
         self.apollo_metrics = apollo_metrics;
         self
     }
+
+    pub(crate) fn with_static_attributes(mut self, static_attributes: Vec<KeyValue>) -> Self {
+        self.static_attributes = static_attributes;
+        self
+    }
+
+    /// Applies the fallback if no exporter added a meter provider, since the instruments of an
+    /// empty aggregate record nothing. The metrics recorded in memory are readable on the
+    /// management address, in the Prometheus text format
+    pub(crate) fn with_fallback(
+        mut self,
+        fallback: MetricsFallback,
+        listen: &ListenAddr,
+    ) -> Result<Self, BoxError> {
+        if !self.meter_providers.is_empty() {
+            return Ok(self);
+        }
+        match fallback {
+            MetricsFallback::Warn => {
+                tracing::warn!(
+                    "metrics are configured without any exporter, the router metrics are not exported"
+                );
+            }
+            MetricsFallback::InMemory => {
+                tracing::info!(
+                    "metrics are configured without any exporter, the router metrics are recorded in memory only, and readable at {}{}",
+                    listen,
+                    IN_MEMORY_METRICS_PATH
+                );
+                let exporter = opentelemetry_prometheus::exporter().try_init()?;
+                self = self.with_custom_endpoint(
+                    listen.clone(),
+                    Endpoint::new(
+                        IN_MEMORY_METRICS_PATH.to_string(),
+                        PrometheusService::new(exporter.registry().clone()).boxed(),
+                    ),
+                );
+                self = self.with_meter_provider("in_memory", exporter.provider()?);
+                self = self.with_exporter(exporter);
+            }
+            MetricsFallback::Ignore => {}
+        }
+        Ok(self)
+    }
 }
 
+/// The HTTP path of the metrics recorded in memory by the fallback
+const IN_MEMORY_METRICS_PATH: &str = "/metrics/in_memory";
+
 pub(crate) trait MetricsConfigurator {
     fn apply(
         &self,
@@ -511,6 +688,9 @@ pub(crate) struct BasicMetrics {
     pub(crate) http_requests_total: AggregateCounter<u64>,
     pub(crate) http_requests_error_total: AggregateCounter<u64>,
     pub(crate) http_requests_duration: AggregateValueRecorder<f64>,
+    pub(crate) request_body_bytes: AggregateValueRecorder<u64>,
+    pub(crate) response_body_bytes: AggregateValueRecorder<u64>,
+    pub(crate) subgraph_empty_responses_total: AggregateCounter<u64>,
 }
 
 impl BasicMetrics {
@@ -532,17 +712,123 @@ impl BasicMetrics {
                     .with_description("Total number of HTTP requests made.")
                     .init()
             }),
+            request_body_bytes: meter.build_value_recorder(|m| {
+                m.u64_value_recorder("apollo_router_request_body_bytes")
+                    .with_description("Size of the GraphQL requests received, in bytes.")
+                    .init()
+            }),
+            response_body_bytes: meter.build_value_recorder(|m| {
+                m.u64_value_recorder("apollo_router_response_body_bytes")
+                    .with_description(
+                        "Size of the GraphQL responses sent, in bytes. Includes all the parts of deferred responses.",
+                    )
+                    .init()
+            }),
+            subgraph_empty_responses_total: meter.build_counter(|m| {
+                m.u64_counter("apollo_router_subgraph_empty_responses_total")
+                    .with_description(
+                        "Number of subgraph responses with neither data nor errors.",
+                    )
+                    .init()
+            }),
+        }
+    }
+}
+
+/// Length of the JSON serialization of a value, without allocating it
+pub(crate) fn serialized_size<T: Serialize>(value: &T) -> usize {
+    struct ByteCounter(usize);
+
+    impl std::io::Write for ByteCounter {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0 += buf.len();
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
         }
     }
+
+    let mut counter = ByteCounter(0);
+    match serde_json::to_writer(&mut counter, value) {
+        Ok(()) => counter.0,
+        Err(_) => 0,
+    }
+}
+
+/// A meter provider of the aggregate, which can be disabled at runtime
+#[derive(Clone)]
+struct SwitchedMeterProvider {
+    name: String,
+    // shared with the instruments built from the provider
+    enabled: Arc<AtomicBool>,
+    meter_provider: Arc<dyn MeterProvider + Send + Sync + 'static>,
 }
 
 #[derive(Clone, Default)]
-pub(crate) struct AggregateMeterProvider(Vec<Arc<dyn MeterProvider + Send + Sync + 'static>>);
+pub(crate) struct AggregateMeterProvider {
+    meter_providers: Vec<SwitchedMeterProvider>,
+    static_attributes: Arc<Vec<KeyValue>>,
+}
 impl AggregateMeterProvider {
+    /// The aggregate of the meter providers, named after their position
     pub(crate) fn new(
         meters: Vec<Arc<dyn MeterProvider + Send + Sync + 'static>>,
     ) -> AggregateMeterProvider {
-        AggregateMeterProvider(meters)
+        Self::with_names(
+            meters
+                .into_iter()
+                .enumerate()
+                .map(|(index, meter_provider)| (index.to_string(), meter_provider))
+                .collect(),
+        )
+    }
+
+    /// The aggregate of the named meter providers, all of them enabled
+    pub(crate) fn with_names(
+        meters: Vec<(
+            impl Into<String>,
+            Arc<dyn MeterProvider + Send + Sync + 'static>,
+        )>,
+    ) -> AggregateMeterProvider {
+        AggregateMeterProvider {
+            meter_providers: meters
+                .into_iter()
+                .map(|(name, meter_provider)| SwitchedMeterProvider {
+                    name: name.into(),
+                    enabled: Arc::new(AtomicBool::new(true)),
+                    meter_provider,
+                })
+                .collect(),
+            static_attributes: Default::default(),
+        }
+    }
+
+    /// Enables or disables the meter provider of that name, returns `false` if there is none.
+    /// The instruments skip the disabled providers, including the instruments already built
+    pub(crate) fn set_enabled(&self, name: &str, enabled: bool) -> bool {
+        match self.meter_providers.iter().find(|p| p.name == name) {
+            Some(provider) => {
+                provider.enabled.store(enabled, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Whether each meter provider is enabled, by name
+    pub(crate) fn enabled(&self) -> BTreeMap<String, bool> {
+        self.meter_providers
+            .iter()
+            .map(|p| (p.name.clone(), p.enabled.load(Ordering::Relaxed)))
+            .collect()
+    }
+
+    /// Attributes added to every metric recorded by the instruments of this provider
+    pub(crate) fn with_static_attributes(mut self, static_attributes: Vec<KeyValue>) -> Self {
+        self.static_attributes = Arc::new(static_attributes);
+        self
     }
 
     pub(crate) fn meter(
@@ -550,55 +836,348 @@ impl AggregateMeterProvider {
         instrumentation_name: &'static str,
         instrumentation_version: Option<&'static str>,
     ) -> AggregateMeter {
-        AggregateMeter(
-            self.0
+        AggregateMeter {
+            meters: self
+                .meter_providers
                 .iter()
-                .map(|p| Arc::new(p.meter(instrumentation_name, instrumentation_version)))
+                .map(|p| {
+                    (
+                        p.enabled.clone(),
+                        Arc::new(
+                            p.meter_provider
+                                .meter(instrumentation_name, instrumentation_version),
+                        ),
+                    )
+                })
                 .collect(),
-        )
+            static_attributes: self.static_attributes.clone(),
+        }
     }
 }
 
 #[derive(Clone)]
-pub(crate) struct AggregateMeter(Vec<Arc<Meter>>);
+pub(crate) struct AggregateMeter {
+    meters: Vec<(Arc<AtomicBool>, Arc<Meter>)>,
+    static_attributes: Arc<Vec<KeyValue>>,
+}
 impl AggregateMeter {
     pub(crate) fn build_counter<T: Into<Number> + Copy>(
         &self,
         build: fn(&Meter) -> Counter<T>,
     ) -> AggregateCounter<T> {
-        AggregateCounter(self.0.iter().map(|m| build(m)).collect())
+        AggregateCounter {
+            counters: self
+                .meters
+                .iter()
+                .map(|(enabled, m)| (enabled.clone(), build(m)))
+                .collect(),
+            static_attributes: self.static_attributes.clone(),
+        }
+    }
+
+    pub(crate) fn build_up_down_counter<T: Into<Number> + Copy>(
+        &self,
+        build: fn(&Meter) -> UpDownCounter<T>,
+    ) -> AggregateUpDownCounter<T> {
+        AggregateUpDownCounter {
+            counters: self
+                .meters
+                .iter()
+                .map(|(enabled, m)| (enabled.clone(), build(m)))
+                .collect(),
+            static_attributes: self.static_attributes.clone(),
+        }
     }
 
     pub(crate) fn build_value_recorder<T: Into<Number> + Copy>(
         &self,
         build: fn(&Meter) -> ValueRecorder<T>,
     ) -> AggregateValueRecorder<T> {
-        AggregateValueRecorder(self.0.iter().map(|m| build(m)).collect())
+        AggregateValueRecorder {
+            value_recorders: self
+                .meters
+                .iter()
+                .map(|(enabled, m)| (enabled.clone(), build(m)))
+                .collect(),
+            static_attributes: self.static_attributes.clone(),
+        }
+    }
+}
+
+/// Appends the static attributes, if any, to the attributes of a measurement
+fn with_static_attributes<'a>(
+    attributes: &'a [KeyValue],
+    static_attributes: &[KeyValue],
+) -> Cow<'a, [KeyValue]> {
+    if static_attributes.is_empty() {
+        Cow::Borrowed(attributes)
+    } else {
+        Cow::Owned([attributes, static_attributes].concat())
     }
 }
 
 #[derive(Clone)]
-pub(crate) struct AggregateCounter<T: Into<Number> + Copy>(Vec<Counter<T>>);
+pub(crate) struct AggregateCounter<T: Into<Number> + Copy> {
+    counters: Vec<(Arc<AtomicBool>, Counter<T>)>,
+    static_attributes: Arc<Vec<KeyValue>>,
+}
 impl<T> AggregateCounter<T>
 where
     T: Into<Number> + Copy,
 {
     pub(crate) fn add(&self, value: T, attributes: &[KeyValue]) {
-        for counter in &self.0 {
-            counter.add(value, attributes)
+        let attributes = with_static_attributes(attributes, &self.static_attributes);
+        for (enabled, counter) in &self.counters {
+            if enabled.load(Ordering::Relaxed) {
+                counter.add(value, &attributes)
+            }
+        }
+    }
+}
+
+/// A value going up and down, like the number of entries of a cache, exported as a gauge
+#[derive(Clone)]
+pub(crate) struct AggregateUpDownCounter<T: Into<Number> + Copy> {
+    counters: Vec<(Arc<AtomicBool>, UpDownCounter<T>)>,
+    static_attributes: Arc<Vec<KeyValue>>,
+}
+impl<T> AggregateUpDownCounter<T>
+where
+    T: Into<Number> + Copy,
+{
+    pub(crate) fn add(&self, value: T, attributes: &[KeyValue]) {
+        let attributes = with_static_attributes(attributes, &self.static_attributes);
+        for (enabled, counter) in &self.counters {
+            if enabled.load(Ordering::Relaxed) {
+                counter.add(value, &attributes)
+            }
         }
     }
 }
 
 #[derive(Clone)]
-pub(crate) struct AggregateValueRecorder<T: Into<Number> + Copy>(Vec<ValueRecorder<T>>);
+pub(crate) struct AggregateValueRecorder<T: Into<Number> + Copy> {
+    value_recorders: Vec<(Arc<AtomicBool>, ValueRecorder<T>)>,
+    static_attributes: Arc<Vec<KeyValue>>,
+}
 impl<T> AggregateValueRecorder<T>
 where
     T: Into<Number> + Copy,
 {
     pub(crate) fn record(&self, value: T, attributes: &[KeyValue]) {
-        for value_recorder in &self.0 {
-            value_recorder.record(value, attributes)
+        let attributes = with_static_attributes(attributes, &self.static_attributes);
+        for (enabled, value_recorder) in &self.value_recorders {
+            if enabled.load(Ordering::Relaxed) {
+                value_recorder.record(value, &attributes)
+            }
         }
     }
 }
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashMap;
+
+    use access_json::JSONQuery;
+    use http::header::HeaderName;
+    use http::HeaderMap;
+    use http::HeaderValue;
+    use regex::Regex;
+
+    use super::*;
+
+    #[test]
+    fn test_attributes_from_request() {
+        let conf = AttributesForwardConf::builder()
+            .insert(Insert::builder().name("static").value("value").build())
+            .request(
+                Forward::builder()
+                    .header(
+                        HeaderForward::named_builder()
+                            .named(HeaderName::from_static("x-client"))
+                            .rename("client")
+                            .build(),
+                    )
+                    .header(
+                        HeaderForward::named_builder()
+                            .named(HeaderName::from_static("x-missing"))
+                            .default("none")
+                            .build(),
+                    )
+                    .header(
+                        HeaderForward::matching_builder()
+                            .matching(Regex::new("^x-custom-.*").unwrap())
+                            .build(),
+                    )
+                    .body(
+                        BodyForward::builder()
+                            .path(JSONQuery::parse(".operationName").unwrap())
+                            .name("operation_name")
+                            .build(),
+                    )
+                    .body(
+                        BodyForward::builder()
+                            .path(JSONQuery::parse(".variables.missing").unwrap())
+                            .name("missing_variable")
+                            .default("default_value")
+                            .build(),
+                    )
+                    .build(),
+            )
+            .build();
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-client", HeaderValue::from_static("web"));
+        headers.insert("x-custom-a", HeaderValue::from_static("a"));
+        headers.insert("x-other", HeaderValue::from_static("other"));
+        let body = Request::fake_builder()
+            .query("query MyOperation { me }")
+            .operation_name("MyOperation")
+            .build();
+
+        assert_eq!(
+            conf.get_attributes_from_request(&headers, &body),
+            HashMap::from([
+                ("static".to_string(), "value".to_string()),
+                ("client".to_string(), "web".to_string()),
+                ("x-missing".to_string(), "none".to_string()),
+                ("x-custom-a".to_string(), "a".to_string()),
+                ("operation_name".to_string(), "MyOperation".to_string()),
+                ("missing_variable".to_string(), "default_value".to_string()),
+            ])
+        );
+    }
+
+    #[test]
+    fn test_merged_attributes_from_request() {
+        let mut forward = Forward::builder()
+            .header(
+                HeaderForward::named_builder()
+                    .named(HeaderName::from_static("x-client"))
+                    .build(),
+            )
+            .build();
+        forward.merge(
+            Forward::builder()
+                .header(
+                    HeaderForward::named_builder()
+                        .named(HeaderName::from_static("x-version"))
+                        .build(),
+                )
+                .body(
+                    BodyForward::builder()
+                        .path(JSONQuery::parse(".operationName").unwrap())
+                        .name("operation_name")
+                        .build(),
+                )
+                .build(),
+        );
+        let conf = AttributesForwardConf::builder()
+            .request(forward)
+            .context(
+                ContextForward::builder()
+                    .named("missing")
+                    .default("from_default")
+                    .build(),
+            )
+            .build();
+
+        let mut headers = HeaderMap::new();
+        headers.insert("x-client", HeaderValue::from_static("web"));
+        headers.insert("x-version", HeaderValue::from_static("1.0"));
+        let body = Request::fake_builder()
+            .query("query MyOperation { me }")
+            .operation_name("MyOperation")
+            .build();
+
+        assert_eq!(
+            conf.get_attributes_from_request(&headers, &body),
+            HashMap::from([
+                ("x-client".to_string(), "web".to_string()),
+                ("x-version".to_string(), "1.0".to_string()),
+                ("operation_name".to_string(), "MyOperation".to_string()),
+            ])
+        );
+        assert_eq!(
+            conf.get_attributes_from_context(&Context::new()),
+            HashMap::from([("missing".to_string(), "from_default".to_string())])
+        );
+    }
+
+    // relies on the internals of tracing_test to avoid installing a global subscriber, like the
+    // rhai plugin tests
+    #[test]
+    fn it_warns_without_exporters() {
+        let mock_writer =
+            tracing_test::internal::MockWriter::new(&tracing_test::internal::GLOBAL_BUF);
+        let subscriber = tracing_test::internal::get_subscriber(mock_writer, "apollo_router=warn");
+        let _guard = tracing::dispatcher::set_default(&subscriber);
+
+        let mut builder = MetricsBuilder::default()
+            .with_fallback(MetricsFallback::Warn, &listen())
+            .unwrap();
+        assert!(builder.meter_provider().enabled().is_empty());
+        assert!(tracing_test::internal::logs_with_scope_contain(
+            "apollo_router",
+            "metrics are configured without any exporter, the router metrics are not exported"
+        ));
+    }
+
+    fn listen() -> ListenAddr {
+        ListenAddr::SocketAddr("127.0.0.1:9091".parse().unwrap())
+    }
+
+    #[tokio::test]
+    async fn it_records_in_memory_without_exporters() {
+        let mut builder = MetricsBuilder::default()
+            .with_fallback(MetricsFallback::InMemory, &listen())
+            .unwrap();
+        let meter_provider = builder.meter_provider();
+        assert_eq!(
+            meter_provider.enabled(),
+            BTreeMap::from([("in_memory".to_string(), true)])
+        );
+        meter_provider
+            .meter("apollo/router", None)
+            .build_counter(|m| m.u64_counter("test_counter").init())
+            .add(1, &[]);
+        let exporter = builder
+            .exporters()
+            .pop()
+            .unwrap()
+            .downcast::<opentelemetry_prometheus::PrometheusExporter>()
+            .unwrap();
+        assert!(exporter
+            .registry()
+            .gather()
+            .iter()
+            .any(|family| family.get_name().starts_with("test_counter")));
+
+        // the metrics are readable on the management address
+        let endpoint = builder
+            .custom_endpoints()
+            .remove(&listen())
+            .and_then(|endpoints| endpoints.into_iter().next())
+            .expect("in memory metrics endpoint")
+            .into_router();
+        let response = endpoint
+            .oneshot(
+                http::Request::get(IN_MEMORY_METRICS_PATH)
+                    .body(hyper::Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), http::StatusCode::OK);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert!(String::from_utf8_lossy(&body).contains("test_counter"));
+
+        // the fallback only applies without exporters
+        let mut builder = MetricsBuilder::default()
+            .with_meter_provider("otlp", exporter.provider().unwrap())
+            .with_fallback(MetricsFallback::InMemory, &listen())
+            .unwrap();
+        assert_eq!(builder.meter_providers.len(), 1);
+        assert!(builder.custom_endpoints().is_empty());
+    }
+}
@@ -55,7 +55,7 @@ impl MetricsConfigurator for super::super::otlp::Config {
                             .map(|(k, v)| KeyValue::new(k, v)),
                     )
                     .build()?;
-                builder = builder.with_meter_provider(exporter.provider());
+                builder = builder.with_meter_provider("otlp", exporter.provider());
                 builder = builder.with_exporter(exporter);
                 Ok(builder)
             }
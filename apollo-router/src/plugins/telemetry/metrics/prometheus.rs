@@ -1,10 +1,22 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::Arc;
 use std::task::Context;
 use std::task::Poll;
+use std::time::SystemTime;
+use std::time::UNIX_EPOCH;
 
+use dashmap::DashMap;
 use futures::future::BoxFuture;
+use http::header::ACCEPT;
+use http::header::CONTENT_TYPE;
+use http::HeaderValue;
 use http::StatusCode;
 use opentelemetry::sdk::Resource;
+use opentelemetry::trace::TraceContextExt;
 use opentelemetry::KeyValue;
+use prometheus::proto::MetricFamily;
+use prometheus::proto::MetricType;
 use prometheus::Encoder;
 use prometheus::Registry;
 use prometheus::TextEncoder;
@@ -13,6 +25,8 @@ use serde::Deserialize;
 use tower::BoxError;
 use tower::ServiceExt;
 use tower_service::Service;
+use tracing::Span;
+use tracing_opentelemetry::OpenTelemetrySpanExt;
 
 use crate::plugins::telemetry::config::MetricsCommon;
 use crate::plugins::telemetry::metrics::MetricsBuilder;
@@ -29,6 +43,10 @@ pub(crate) struct Config {
     pub(crate) listen: ListenAddr,
     #[serde(default = "prometheus_default_path")]
     pub(crate) path: String,
+    /// Attach the trace ID of sampled requests as exemplars to the request duration histogram,
+    /// for the scrapers accepting the OpenMetrics format (default: false)
+    #[serde(default)]
+    pub(crate) exemplars: bool,
 }
 
 fn prometheus_default_listen_addr() -> ListenAddr {
@@ -45,6 +63,7 @@ impl Default for Config {
             enabled: true,
             listen: prometheus_default_listen_addr(),
             path: prometheus_default_path(),
+            exemplars: false,
         }
     }
 }
@@ -56,30 +75,34 @@ impl MetricsConfigurator for Config {
         metrics_config: &MetricsCommon,
     ) -> Result<MetricsBuilder, BoxError> {
         if self.enabled {
+            let resource = Resource::new(
+                metrics_config
+                    .resources
+                    .clone()
+                    .into_iter()
+                    .map(|(k, v)| KeyValue::new(k, v)),
+            );
+            let exemplars = self.exemplars.then(|| Exemplars::new(&resource));
             let exporter = opentelemetry_prometheus::exporter()
-                .with_default_histogram_boundaries(vec![
-                    0.001, 0.005, 0.015, 0.05, 0.1, 0.2, 0.3, 0.4, 0.5, 1.0, 5.0, 10.0,
-                ])
-                .with_resource(Resource::new(
-                    metrics_config
-                        .resources
-                        .clone()
-                        .into_iter()
-                        .map(|(k, v)| KeyValue::new(k, v)),
-                ))
+                .with_default_histogram_boundaries(HISTOGRAM_BOUNDARIES.to_vec())
+                .with_resource(resource)
                 .try_init()?;
 
+            if let Some(exemplars) = &exemplars {
+                builder = builder.with_exemplars(exemplars.clone());
+            }
             builder = builder.with_custom_endpoint(
                 self.listen.clone(),
                 Endpoint::new(
                     self.path.clone(),
                     PrometheusService {
                         registry: exporter.registry().clone(),
+                        exemplars,
                     }
                     .boxed(),
                 ),
             );
-            builder = builder.with_meter_provider(exporter.provider()?);
+            builder = builder.with_meter_provider("prometheus", exporter.provider()?);
             builder = builder.with_exporter(exporter);
         }
         Ok(builder)
@@ -89,6 +112,17 @@ impl MetricsConfigurator for Config {
 #[derive(Clone)]
 pub(crate) struct PrometheusService {
     registry: Registry,
+    exemplars: Option<Exemplars>,
+}
+
+impl PrometheusService {
+    /// Serves the metrics of the registry in the Prometheus text format
+    pub(crate) fn new(registry: Registry) -> Self {
+        PrometheusService {
+            registry,
+            exemplars: None,
+        }
+    }
 }
 
 impl Service<transport::Request> for PrometheusService {
@@ -100,16 +134,486 @@ impl Service<transport::Request> for PrometheusService {
         Ok(()).into()
     }
 
-    fn call(&mut self, _req: transport::Request) -> Self::Future {
+    fn call(&mut self, req: transport::Request) -> Self::Future {
         let metric_families = self.registry.gather();
+        // the exemplars are only part of the OpenMetrics format
+        let exemplars = self.exemplars.clone().filter(|_| {
+            req.headers()
+                .get_all(ACCEPT)
+                .iter()
+                .filter_map(|value| value.to_str().ok())
+                .any(|value| value.contains(OPENMETRICS_MEDIA_TYPE))
+        });
         Box::pin(async move {
-            let encoder = TextEncoder::new();
-            let mut result = Vec::new();
-            encoder.encode(&metric_families, &mut result)?;
+            let (content_type, result) = match exemplars {
+                Some(exemplars) => (
+                    OPENMETRICS_CONTENT_TYPE.to_string(),
+                    encode_open_metrics(&metric_families, &exemplars).into_bytes(),
+                ),
+                None => {
+                    let encoder = TextEncoder::new();
+                    let mut result = Vec::new();
+                    encoder.encode(&metric_families, &mut result)?;
+                    (encoder.format_type().to_string(), result)
+                }
+            };
             http::Response::builder()
                 .status(StatusCode::OK)
+                .header(CONTENT_TYPE, HeaderValue::from_str(&content_type)?)
                 .body(result.into())
                 .map_err(|err| BoxError::from(err.to_string()))
         })
     }
 }
+
+/// Bucket boundaries of the histograms, in seconds for durations
+const HISTOGRAM_BOUNDARIES: [f64; 12] = [
+    0.001, 0.005, 0.015, 0.05, 0.1, 0.2, 0.3, 0.4, 0.5, 1.0, 5.0, 10.0,
+];
+
+/// The histogram the exemplars are attached to
+const EXEMPLAR_METRIC: &str = "apollo_router_http_request_duration_seconds";
+
+const OPENMETRICS_MEDIA_TYPE: &str = "application/openmetrics-text";
+const OPENMETRICS_CONTENT_TYPE: &str = "application/openmetrics-text; version=1.0.0; charset=utf-8";
+
+#[derive(Clone, Debug, PartialEq)]
+struct Exemplar {
+    trace_id: String,
+    value: f64,
+    timestamp: f64,
+}
+
+/// The sorted label names and values of a series
+type SeriesLabels = Vec<(String, String)>;
+
+/// The latest exemplar of each bucket of the request duration histogram, by series
+#[derive(Clone, Default)]
+pub(crate) struct Exemplars {
+    /// The labels the exporter adds to every series
+    resource: Arc<SeriesLabels>,
+    latest: Arc<DashMap<SeriesLabels, BTreeMap<usize, Exemplar>>>,
+}
+
+impl Exemplars {
+    pub(crate) fn new(resource: &Resource) -> Self {
+        Exemplars {
+            resource: Arc::new(
+                resource
+                    .iter()
+                    .map(|(key, value)| (sanitize(key.as_str()), value.as_str().into_owned()))
+                    .collect(),
+            ),
+            latest: Default::default(),
+        }
+    }
+
+    /// Records a request duration as an exemplar, if the trace of the current span is sampled
+    pub(crate) fn record(&self, value: f64, attributes: &[KeyValue]) {
+        let span_context = Span::current().context().span().span_context().clone();
+        if !span_context.is_valid() || !span_context.is_sampled() {
+            return;
+        }
+        let exemplar = Exemplar {
+            trace_id: span_context.trace_id().to_string(),
+            value,
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|since| since.as_secs_f64())
+                .unwrap_or_default(),
+        };
+        // the series has the attributes of the recording, then the resource labels
+        let mut labels: SeriesLabels = attributes
+            .iter()
+            .map(|kv| (sanitize(kv.key.as_str()), kv.value.as_str().into_owned()))
+            .chain(self.resource.iter().cloned())
+            .collect();
+        labels.sort_by(|a, b| a.0.cmp(&b.0));
+        labels.dedup_by(|a, b| a.0 == b.0);
+        let bucket = HISTOGRAM_BOUNDARIES
+            .iter()
+            .position(|bound| value <= *bound)
+            .unwrap_or(HISTOGRAM_BOUNDARIES.len());
+        self.latest
+            .entry(labels)
+            .or_default()
+            .insert(bucket, exemplar);
+    }
+
+    /// The exemplars recorded with exactly the labels of a series
+    fn of_series(&self, labels: &[(&str, &str)]) -> BTreeMap<usize, Exemplar> {
+        let mut labels: SeriesLabels = labels
+            .iter()
+            .map(|(name, value)| (name.to_string(), value.to_string()))
+            .collect();
+        labels.sort();
+        self.latest
+            .get(&labels)
+            .map(|exemplars| exemplars.clone())
+            .unwrap_or_default()
+    }
+}
+
+/// Label names as the exporter writes them
+fn sanitize(name: &str) -> String {
+    name.chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect()
+}
+
+/// Encodes the metrics in the OpenMetrics text format, with the exemplars on the buckets of the
+/// request duration histogram
+fn encode_open_metrics(metric_families: &[MetricFamily], exemplars: &Exemplars) -> String {
+    let mut out = String::new();
+    for family in metric_families {
+        let name = family.get_name();
+        let (family_name, kind) = match family.get_field_type() {
+            // the counter samples are suffixed with `_total`, their family is not
+            MetricType::COUNTER => (name.strip_suffix("_total").unwrap_or(name), "counter"),
+            MetricType::GAUGE => (name, "gauge"),
+            MetricType::HISTOGRAM => (name, "histogram"),
+            MetricType::SUMMARY => (name, "summary"),
+            MetricType::UNTYPED => (name, "unknown"),
+        };
+        let _ = writeln!(out, "# TYPE {} {}", family_name, kind);
+        if !family.get_help().is_empty() {
+            let _ = writeln!(
+                out,
+                "# HELP {} {}",
+                family_name,
+                family.get_help().replace('\\', "\\\\").replace('\n', "\\n")
+            );
+        }
+        for metric in family.get_metric() {
+            let labels: Vec<(&str, &str)> = metric
+                .get_label()
+                .iter()
+                .map(|label| (label.get_name(), label.get_value()))
+                .collect();
+            match family.get_field_type() {
+                MetricType::COUNTER => write_sample(
+                    &mut out,
+                    &format!("{}_total", family_name),
+                    &labels,
+                    None,
+                    metric.get_counter().get_value(),
+                    None,
+                ),
+                MetricType::GAUGE => write_sample(
+                    &mut out,
+                    name,
+                    &labels,
+                    None,
+                    metric.get_gauge().get_value(),
+                    None,
+                ),
+                MetricType::UNTYPED => write_sample(
+                    &mut out,
+                    name,
+                    &labels,
+                    None,
+                    metric.get_untyped().get_value(),
+                    None,
+                ),
+                MetricType::HISTOGRAM => {
+                    let histogram = metric.get_histogram();
+                    let series_exemplars = if name == EXEMPLAR_METRIC {
+                        exemplars.of_series(&labels)
+                    } else {
+                        BTreeMap::new()
+                    };
+                    let bucket_name = format!("{}_bucket", name);
+                    let mut has_inf = false;
+                    for (index, bucket) in histogram.get_bucket().iter().enumerate() {
+                        has_inf |= bucket.get_upper_bound() == f64::INFINITY;
+                        write_sample(
+                            &mut out,
+                            &bucket_name,
+                            &labels,
+                            Some(("le", &format_float(bucket.get_upper_bound()))),
+                            bucket.get_cumulative_count() as f64,
+                            series_exemplars.get(&index),
+                        );
+                    }
+                    if !has_inf {
+                        write_sample(
+                            &mut out,
+                            &bucket_name,
+                            &labels,
+                            Some(("le", "+Inf")),
+                            histogram.get_sample_count() as f64,
+                            series_exemplars.get(&histogram.get_bucket().len()),
+                        );
+                    }
+                    write_sample(
+                        &mut out,
+                        &format!("{}_count", name),
+                        &labels,
+                        None,
+                        histogram.get_sample_count() as f64,
+                        None,
+                    );
+                    write_sample(
+                        &mut out,
+                        &format!("{}_sum", name),
+                        &labels,
+                        None,
+                        histogram.get_sample_sum(),
+                        None,
+                    );
+                }
+                MetricType::SUMMARY => {
+                    let summary = metric.get_summary();
+                    for quantile in summary.get_quantile() {
+                        write_sample(
+                            &mut out,
+                            name,
+                            &labels,
+                            Some(("quantile", &format_float(quantile.get_quantile()))),
+                            quantile.get_value(),
+                            None,
+                        );
+                    }
+                    write_sample(
+                        &mut out,
+                        &format!("{}_count", name),
+                        &labels,
+                        None,
+                        summary.get_sample_count() as f64,
+                        None,
+                    );
+                    write_sample(
+                        &mut out,
+                        &format!("{}_sum", name),
+                        &labels,
+                        None,
+                        summary.get_sample_sum(),
+                        None,
+                    );
+                }
+            }
+        }
+    }
+    out.push_str("# EOF\n");
+    out
+}
+
+fn write_sample(
+    out: &mut String,
+    name: &str,
+    labels: &[(&str, &str)],
+    extra_label: Option<(&str, &str)>,
+    value: f64,
+    exemplar: Option<&Exemplar>,
+) {
+    out.push_str(name);
+    write_labels(out, labels.iter().copied().chain(extra_label));
+    let _ = write!(out, " {}", format_float(value));
+    if let Some(exemplar) = exemplar {
+        out.push_str(" # ");
+        write_labels(
+            out,
+            std::iter::once(("trace_id", exemplar.trace_id.as_str())),
+        );
+        let _ = write!(
+            out,
+            " {} {}",
+            format_float(exemplar.value),
+            exemplar.timestamp
+        );
+    }
+    out.push('\n');
+}
+
+fn write_labels<'a>(out: &mut String, labels: impl Iterator<Item = (&'a str, &'a str)>) {
+    let mut labels = labels.peekable();
+    if labels.peek().is_none() {
+        return;
+    }
+    out.push('{');
+    for (index, (name, value)) in labels.enumerate() {
+        if index > 0 {
+            out.push(',');
+        }
+        let _ = write!(
+            out,
+            "{}=\"{}\"",
+            name,
+            value
+                .replace('\\', "\\\\")
+                .replace('"', "\\\"")
+                .replace('\n', "\\n")
+        );
+    }
+    out.push('}');
+}
+
+fn format_float(value: f64) -> String {
+    if value == f64::INFINITY {
+        "+Inf".to_string()
+    } else if value == f64::NEG_INFINITY {
+        "-Inf".to_string()
+    } else if value.is_nan() {
+        "NaN".to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use opentelemetry::metrics::MeterProvider;
+    use opentelemetry::trace::TracerProvider;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn it_attaches_the_trace_id_of_sampled_requests() {
+        let resource = Resource::new(vec![KeyValue::new("service.name", "router")]);
+        let exporter = opentelemetry_prometheus::exporter()
+            .with_default_histogram_boundaries(HISTOGRAM_BOUNDARIES.to_vec())
+            .with_resource(resource.clone())
+            .init();
+        let recorder = exporter
+            .provider()
+            .expect("meter provider")
+            .meter("apollo/router", None)
+            .f64_value_recorder(EXEMPLAR_METRIC)
+            .init();
+        let exemplars = Exemplars::new(&resource);
+
+        // the default sampler samples every trace
+        let tracer = opentelemetry::sdk::trace::TracerProvider::builder()
+            .build()
+            .tracer("test");
+        let subscriber = tracing_subscriber::Registry::default()
+            .with(tracing_opentelemetry::layer().with_tracer(tracer));
+        let trace_id = tracing::subscriber::with_default(subscriber, || {
+            let _span = tracing::info_span!("request").entered();
+            let attributes = [KeyValue::new("status", "200")];
+            recorder.record(0.01, &attributes);
+            exemplars.record(0.01, &attributes);
+            // a series with more attributes does not get the exemplars of the first one
+            let attributes = [
+                KeyValue::new("status", "200"),
+                KeyValue::new("subgraph", "products"),
+            ];
+            recorder.record(0.01, &attributes);
+            Span::current()
+                .context()
+                .span()
+                .span_context()
+                .trace_id()
+                .to_string()
+        });
+
+        let mut service = PrometheusService {
+            registry: exporter.registry().clone(),
+            exemplars: Some(exemplars),
+        };
+        let response = service
+            .call(
+                http::Request::get("/metrics")
+                    .header(ACCEPT, "application/openmetrics-text; version=1.0.0")
+                    .body(hyper::Body::empty())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(
+            response.headers().get(CONTENT_TYPE).unwrap(),
+            OPENMETRICS_CONTENT_TYPE
+        );
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        let body = String::from_utf8(body.to_vec()).unwrap();
+        let bucket = |subgraph: bool| {
+            body.lines()
+                .find(|line| {
+                    line.starts_with("apollo_router_http_request_duration_seconds_bucket")
+                        && line.contains("le=\"0.015\"")
+                        && line.contains("subgraph=") == subgraph
+                })
+                .expect("bucket of the request")
+        };
+        assert!(
+            bucket(false).contains(&format!("} 1 # {{trace_id=\"{}\"}} 0.01 ", trace_id)),
+            "{}",
+            bucket(false)
+        );
+        assert!(!bucket(true).contains("trace_id"), "{}", bucket(true));
+        assert!(body.ends_with("# EOF\n"));
+    }
+
+    fn label(name: &str, value: &str) -> prometheus::proto::LabelPair {
+        let mut label = prometheus::proto::LabelPair::default();
+        label.set_name(name.to_string());
+        label.set_value(value.to_string());
+        label
+    }
+
+    #[test]
+    fn it_encodes_counters_and_gauges() {
+        let registry = Registry::new();
+        let counter = prometheus::CounterVec::new(
+            prometheus::Opts::new("apollo_router_http_requests_total", "Total requests"),
+            &["status"],
+        )
+        .unwrap();
+        counter.with_label_values(&["200"]).inc_by(2.0);
+        counter.with_label_values(&["500"]).inc();
+        registry.register(Box::new(counter)).unwrap();
+        let gauge =
+            prometheus::Gauge::new("apollo_router_in_flight", "Requests in flight").unwrap();
+        gauge.set(1.5);
+        registry.register(Box::new(gauge)).unwrap();
+
+        assert_eq!(
+            encode_open_metrics(&registry.gather(), &Exemplars::default()),
+            "# TYPE apollo_router_http_requests counter\n\
+             # HELP apollo_router_http_requests Total requests\n\
+             apollo_router_http_requests_total{status=\"200\"} 2\n\
+             apollo_router_http_requests_total{status=\"500\"} 1\n\
+             # TYPE apollo_router_in_flight gauge\n\
+             # HELP apollo_router_in_flight Requests in flight\n\
+             apollo_router_in_flight 1.5\n\
+             # EOF\n"
+        );
+    }
+
+    #[test]
+    fn it_encodes_summaries() {
+        let quantiles = [(0.5, 0.1), (0.99, 0.4)]
+            .into_iter()
+            .map(|(quantile, value)| {
+                let mut q = prometheus::proto::Quantile::default();
+                q.set_quantile(quantile);
+                q.set_value(value);
+                q
+            })
+            .collect::<Vec<_>>();
+        let mut summary = prometheus::proto::Summary::default();
+        summary.set_quantile(quantiles.into());
+        summary.set_sample_count(2);
+        summary.set_sample_sum(0.5);
+        let mut metric = prometheus::proto::Metric::default();
+        metric.set_label(vec![label("service", "products")].into());
+        metric.set_summary(summary);
+        let mut family = MetricFamily::default();
+        family.set_name("apollo_router_fetch_latency".to_string());
+        family.set_help("Fetch latency".to_string());
+        family.set_field_type(MetricType::SUMMARY);
+        family.set_metric(vec![metric].into());
+
+        assert_eq!(
+            encode_open_metrics(&[family], &Exemplars::default()),
+            "# TYPE apollo_router_fetch_latency summary\n\
+             # HELP apollo_router_fetch_latency Fetch latency\n\
+             apollo_router_fetch_latency{service=\"products\",quantile=\"0.5\"} 0.1\n\
+             apollo_router_fetch_latency{service=\"products\",quantile=\"0.99\"} 0.4\n\
+             apollo_router_fetch_latency_count{service=\"products\"} 2\n\
+             apollo_router_fetch_latency_sum{service=\"products\"} 0.5\n\
+             # EOF\n"
+        );
+    }
+}
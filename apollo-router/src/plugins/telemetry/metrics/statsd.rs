@@ -0,0 +1,427 @@
+//! Sends the metrics to a StatsD server, for the monitoring stacks that ingest neither
+//! Prometheus nor OTLP metrics.
+//!
+//! The metrics are aggregated in a Prometheus registry, which is read on each flush: counters
+//! are sent as the increment since the previous flush, gauges as their current value, and
+//! histograms as the increments of their count and sum.
+//!
+//! Without tags, the increments of a counter for all its attributes add up on the StatsD server,
+//! but the values of a gauge would overwrite each other, so its attributes go in its name.
+
+use std::collections::HashMap;
+use std::time::Duration;
+
+use prometheus::proto::LabelPair;
+use prometheus::proto::MetricFamily;
+use prometheus::proto::MetricType;
+use prometheus::Registry;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use serde::Serialize;
+use tokio::io::AsyncWriteExt;
+use tokio::net::TcpStream;
+use tokio::net::UdpSocket;
+use tokio::task::JoinHandle;
+use tower::BoxError;
+
+use crate::plugins::telemetry::config::MetricsCommon;
+use crate::plugins::telemetry::metrics::MetricsBuilder;
+use crate::plugins::telemetry::metrics::MetricsConfigurator;
+
+// Keeps the datagrams under the MTU of most networks
+const MAX_DATAGRAM_SIZE: usize = 1432;
+
+#[derive(Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Config {
+    /// Address of the StatsD server, like `127.0.0.1:8125`
+    pub(crate) endpoint: String,
+    /// Transport of the metrics
+    /// default: udp
+    #[serde(default)]
+    pub(crate) protocol: Protocol,
+    /// Prefix of the metric names, separated from them by a dot
+    pub(crate) prefix: Option<String>,
+    /// Time between two sendings of the metrics
+    /// default: 10s
+    #[serde(with = "humantime_serde", default = "default_flush_interval")]
+    #[schemars(with = "String")]
+    pub(crate) flush_interval: Duration,
+    /// How the attributes of the metrics are sent
+    /// default: none
+    #[serde(default)]
+    pub(crate) tag_format: TagFormat,
+}
+
+fn default_flush_interval() -> Duration {
+    Duration::from_secs(10)
+}
+
+/// Transport of the StatsD metrics
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum Protocol {
+    /// Datagrams of newline separated metrics
+    Udp,
+    /// A connection of newline separated metrics, opened again after errors
+    Tcp,
+}
+
+impl Default for Protocol {
+    fn default() -> Self {
+        Protocol::Udp
+    }
+}
+
+/// How the attributes of the metrics are sent
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "snake_case")]
+pub(crate) enum TagFormat {
+    /// The attributes are not sent: the counters of all the attributes are combined, and the
+    /// attributes of the gauges are appended to their name, like `name.key.value`
+    None,
+    /// DogStatsD tags: `name:1|c|#key:value`
+    Dogstatsd,
+    /// Graphite tags: `name;key=value:1|c`
+    Graphite,
+}
+
+impl Default for TagFormat {
+    fn default() -> Self {
+        TagFormat::None
+    }
+}
+
+impl MetricsConfigurator for Config {
+    fn apply(
+        &self,
+        mut builder: MetricsBuilder,
+        _metrics_config: &MetricsCommon,
+    ) -> Result<MetricsBuilder, BoxError> {
+        tracing::debug!("configuring StatsD metrics");
+        let exporter = opentelemetry_prometheus::exporter().try_init()?;
+        let task = StatsdExporter::start(self.clone(), exporter.registry().clone());
+        builder = builder.with_meter_provider("statsd", exporter.provider()?);
+        builder = builder.with_exporter(exporter);
+        builder = builder.with_exporter(task);
+        Ok(builder)
+    }
+}
+
+/// Sends the metrics of the registry on an interval, until it is dropped
+struct StatsdExporter {
+    task: JoinHandle<()>,
+}
+
+impl StatsdExporter {
+    fn start(config: Config, registry: Registry) -> Self {
+        let task = tokio::spawn(async move {
+            let mut interval = tokio::time::interval(config.flush_interval);
+            let mut sender = Sender::new(config.endpoint.clone(), config.protocol);
+            let mut previous = HashMap::new();
+            loop {
+                interval.tick().await;
+                let lines = format_metrics(&config, &registry.gather(), &mut previous);
+                if let Err(err) = sender.send(&lines).await {
+                    tracing::warn!(
+                        "could not send the metrics to StatsD at {}: {}",
+                        config.endpoint,
+                        err
+                    );
+                }
+            }
+        });
+        StatsdExporter { task }
+    }
+}
+
+impl Drop for StatsdExporter {
+    // the metrics stop with the telemetry plugin, a reload starts a new exporter
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
+
+struct Sender {
+    endpoint: String,
+    protocol: Protocol,
+    udp: Option<UdpSocket>,
+    tcp: Option<TcpStream>,
+}
+
+impl Sender {
+    fn new(endpoint: String, protocol: Protocol) -> Self {
+        Self {
+            endpoint,
+            protocol,
+            udp: None,
+            tcp: None,
+        }
+    }
+
+    async fn send(&mut self, lines: &[String]) -> Result<(), BoxError> {
+        if lines.is_empty() {
+            return Ok(());
+        }
+        match self.protocol {
+            Protocol::Udp => {
+                if self.udp.is_none() {
+                    let address = tokio::net::lookup_host(&self.endpoint)
+                        .await?
+                        .next()
+                        .ok_or("the endpoint does not resolve to an address")?;
+                    let local = if address.is_ipv4() {
+                        "0.0.0.0:0"
+                    } else {
+                        "[::]:0"
+                    };
+                    let socket = UdpSocket::bind(local).await?;
+                    socket.connect(address).await?;
+                    self.udp = Some(socket);
+                }
+                let socket = self.udp.as_ref().expect("the socket was just created");
+                for datagram in datagrams(lines) {
+                    socket.send(datagram.as_bytes()).await?;
+                }
+            }
+            Protocol::Tcp => {
+                if self.tcp.is_none() {
+                    self.tcp = Some(TcpStream::connect(&self.endpoint).await?);
+                }
+                let stream = self.tcp.as_mut().expect("the stream was just created");
+                let mut payload = lines.join("\n");
+                payload.push('\n');
+                if let Err(err) = stream.write_all(payload.as_bytes()).await {
+                    // the next flush opens a new connection
+                    self.tcp = None;
+                    return Err(err.into());
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Groups the lines in newline separated datagrams
+fn datagrams(lines: &[String]) -> Vec<String> {
+    let mut datagrams = Vec::new();
+    let mut current = String::new();
+    for line in lines {
+        if !current.is_empty() && current.len() + line.len() + 1 > MAX_DATAGRAM_SIZE {
+            datagrams.push(std::mem::take(&mut current));
+        }
+        if !current.is_empty() {
+            current.push('\n');
+        }
+        current.push_str(line);
+    }
+    if !current.is_empty() {
+        datagrams.push(current);
+    }
+    datagrams
+}
+
+/// The StatsD lines of the metrics. `previous` holds the values of the counters at the previous
+/// flush, to send their increments
+fn format_metrics(
+    config: &Config,
+    families: &[MetricFamily],
+    previous: &mut HashMap<String, f64>,
+) -> Vec<String> {
+    let mut lines = Vec::new();
+    for family in families {
+        let name = match &config.prefix {
+            Some(prefix) => format!("{}.{}", prefix, family.get_name()),
+            None => family.get_name().to_string(),
+        };
+        for metric in family.get_metric() {
+            let labels = metric.get_label();
+            match family.get_field_type() {
+                MetricType::COUNTER => lines.extend(increment(
+                    config,
+                    previous,
+                    &name,
+                    labels,
+                    metric.get_counter().get_value(),
+                )),
+                MetricType::HISTOGRAM => {
+                    let histogram = metric.get_histogram();
+                    lines.extend(increment(
+                        config,
+                        previous,
+                        &format!("{}.count", name),
+                        labels,
+                        histogram.get_sample_count() as f64,
+                    ));
+                    lines.extend(increment(
+                        config,
+                        previous,
+                        &format!("{}.sum", name),
+                        labels,
+                        histogram.get_sample_sum(),
+                    ));
+                }
+                MetricType::GAUGE if config.tag_format == TagFormat::None => lines.push(line(
+                    &flattened(&name, labels),
+                    labels,
+                    config.tag_format,
+                    metric.get_gauge().get_value(),
+                    "g",
+                )),
+                MetricType::GAUGE => lines.push(line(
+                    &name,
+                    labels,
+                    config.tag_format,
+                    metric.get_gauge().get_value(),
+                    "g",
+                )),
+                _ => {}
+            }
+        }
+    }
+    lines
+}
+
+/// The counter line of the increment since the previous flush, if there is one
+fn increment(
+    config: &Config,
+    previous: &mut HashMap<String, f64>,
+    name: &str,
+    labels: &[LabelPair],
+    value: f64,
+) -> Option<String> {
+    let key = tagged(name, labels, TagFormat::Graphite);
+    let delta = value - previous.insert(key, value).unwrap_or_default();
+    (delta > 0.0).then(|| line(name, labels, config.tag_format, delta, "c"))
+}
+
+fn line(name: &str, labels: &[LabelPair], format: TagFormat, value: f64, kind: &str) -> String {
+    match format {
+        TagFormat::None => format!("{}:{}|{}", name, value, kind),
+        TagFormat::Graphite => format!("{}:{}|{}", tagged(name, labels, format), value, kind),
+        TagFormat::Dogstatsd if labels.is_empty() => format!("{}:{}|{}", name, value, kind),
+        TagFormat::Dogstatsd => format!(
+            "{}:{}|{}|#{}",
+            name,
+            value,
+            kind,
+            labels
+                .iter()
+                .map(|label| format!("{}:{}", label.get_name(), label.get_value()))
+                .collect::<Vec<_>>()
+                .join(",")
+        ),
+    }
+}
+
+/// The metric name followed by the Graphite tags of its labels
+fn tagged(name: &str, labels: &[LabelPair], format: TagFormat) -> String {
+    let mut tagged = name.to_string();
+    if format == TagFormat::Graphite {
+        for label in labels {
+            tagged.push_str(&format!(";{}={}", label.get_name(), label.get_value()));
+        }
+    }
+    tagged
+}
+
+/// The metric name followed by the names and values of its labels, as dot separated segments.
+/// The characters with a meaning in the StatsD protocol are replaced in the values
+fn flattened(name: &str, labels: &[LabelPair]) -> String {
+    let mut flattened = name.to_string();
+    for label in labels {
+        let value: String = label
+            .get_value()
+            .chars()
+            .map(|c| match c {
+                '.' | ':' | '|' | '@' | '#' | ' ' => '_',
+                c => c,
+            })
+            .collect();
+        flattened.push_str(&format!(".{}.{}", label.get_name(), value));
+    }
+    flattened
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(endpoint: String) -> Config {
+        Config {
+            endpoint,
+            protocol: Protocol::Udp,
+            prefix: Some("router".to_string()),
+            flush_interval: Duration::from_millis(20),
+            tag_format: TagFormat::Dogstatsd,
+        }
+    }
+
+    #[tokio::test]
+    async fn it_sends_counter_increments() {
+        let listener = UdpSocket::bind("127.0.0.1:0").await.unwrap();
+        let mut builder = config(listener.local_addr().unwrap().to_string())
+            .apply(MetricsBuilder::default(), &MetricsCommon::default())
+            .unwrap();
+        let meter_provider = builder.meter_provider();
+        let counter = meter_provider
+            .meter("apollo/router", None)
+            .build_counter(|m| m.u64_counter("test_counter").init());
+        counter.add(3, &[opentelemetry::KeyValue::new("subgraph", "products")]);
+
+        let mut buffer = [0; MAX_DATAGRAM_SIZE];
+        let size = tokio::time::timeout(Duration::from_secs(5), listener.recv(&mut buffer))
+            .await
+            .expect("no StatsD datagram received")
+            .unwrap();
+        let datagram = String::from_utf8_lossy(&buffer[..size]).to_string();
+        assert!(datagram.starts_with("router.test_counter"), "{}", datagram);
+        assert!(datagram.contains(":3|c|#"), "{}", datagram);
+        assert!(datagram.contains("subgraph:products"), "{}", datagram);
+
+        // the next flush only sends the new increments
+        counter.add(1, &[opentelemetry::KeyValue::new("subgraph", "products")]);
+        let size = tokio::time::timeout(Duration::from_secs(5), listener.recv(&mut buffer))
+            .await
+            .expect("no StatsD datagram received")
+            .unwrap();
+        let datagram = String::from_utf8_lossy(&buffer[..size]).to_string();
+        assert!(datagram.contains(":1|c|#"), "{}", datagram);
+    }
+
+    #[test]
+    fn it_keeps_the_gauges_of_each_attribute_apart_without_tags() {
+        let registry = Registry::new();
+        let gauge = prometheus::IntGaugeVec::new(
+            prometheus::Opts::new("connections", "open connections"),
+            &["subgraph"],
+        )
+        .unwrap();
+        registry.register(Box::new(gauge.clone())).unwrap();
+        gauge.with_label_values(&["products"]).set(3);
+        gauge.with_label_values(&["reviews.v2"]).set(5);
+
+        let mut config = config("127.0.0.1:8125".to_string());
+        config.tag_format = TagFormat::None;
+        let mut lines = format_metrics(&config, &registry.gather(), &mut HashMap::new());
+        lines.sort();
+        assert_eq!(
+            lines,
+            vec![
+                "router.connections.subgraph.products:3|g".to_string(),
+                "router.connections.subgraph.reviews_v2:5|g".to_string(),
+            ]
+        );
+    }
+
+    #[test]
+    fn it_splits_datagrams() {
+        let lines: Vec<String> = (0..100)
+            .map(|i| format!("router.metric_{}:{}|c", i, "1".repeat(20)))
+            .collect();
+        let datagrams = datagrams(&lines);
+        assert!(datagrams.len() > 1);
+        assert!(datagrams.iter().all(|d| d.len() <= MAX_DATAGRAM_SIZE));
+        assert_eq!(datagrams.join("\n"), lines.join("\n"));
+    }
+}
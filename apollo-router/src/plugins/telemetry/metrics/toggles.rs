@@ -0,0 +1,145 @@
+//! An admin endpoint to disable and enable the metrics exporters at runtime.
+//!
+//! `GET` returns whether each exporter is enabled, and `POST` takes a JSON object of the exporters
+//! to change, like `{"otlp": false}`. The instruments skip the meter providers of the disabled
+//! exporters. A configuration reload creates new exporters, all of them enabled.
+
+use std::collections::BTreeMap;
+
+use http::header::CONTENT_TYPE;
+use http::HeaderValue;
+use http::Method;
+use http::StatusCode;
+use tower::service_fn;
+use tower::BoxError;
+use tower::ServiceExt;
+
+use super::AggregateMeterProvider;
+use crate::router_factory::Endpoint;
+use crate::services::transport;
+
+pub(crate) fn endpoint(path: String, meter_provider: AggregateMeterProvider) -> Endpoint {
+    let handler = service_fn(move |req: transport::Request| {
+        let meter_provider = meter_provider.clone();
+        async move {
+            let (status, body) = match *req.method() {
+                Method::GET => (StatusCode::OK, exporters(&meter_provider)),
+                Method::POST => {
+                    let body = hyper::body::to_bytes(req.into_body()).await?;
+                    match serde_json::from_slice::<BTreeMap<String, bool>>(&body) {
+                        Ok(changes) => toggle(&meter_provider, changes),
+                        Err(err) => (
+                            StatusCode::BAD_REQUEST,
+                            serde_json::json!({ "error": err.to_string() }),
+                        ),
+                    }
+                }
+                _ => {
+                    return http::Response::builder()
+                        .status(StatusCode::METHOD_NOT_ALLOWED)
+                        .body(hyper::Body::empty())
+                        .map_err(BoxError::from)
+                }
+            };
+            http::Response::builder()
+                .status(status)
+                .header(CONTENT_TYPE, HeaderValue::from_static("application/json"))
+                .body(serde_json::to_vec(&body)?.into())
+                .map_err(BoxError::from)
+        }
+    });
+    Endpoint::new(path, handler.boxed())
+}
+
+fn exporters(meter_provider: &AggregateMeterProvider) -> serde_json::Value {
+    serde_json::json!({ "exporters": meter_provider.enabled() })
+}
+
+/// Applies the changes, unless one of them names an unknown exporter
+fn toggle(
+    meter_provider: &AggregateMeterProvider,
+    changes: BTreeMap<String, bool>,
+) -> (StatusCode, serde_json::Value) {
+    let enabled = meter_provider.enabled();
+    if let Some(unknown) = changes.keys().find(|name| !enabled.contains_key(*name)) {
+        return (
+            StatusCode::BAD_REQUEST,
+            serde_json::json!({ "error": format!("unknown metrics exporter: {}", unknown) }),
+        );
+    }
+    for (name, enable) in changes {
+        if enable != enabled[&name] {
+            tracing::info!(
+                "{} the {} metrics exporter",
+                if enable { "enabling" } else { "disabling" },
+                name
+            );
+            meter_provider.set_enabled(&name, enable);
+        }
+    }
+    (StatusCode::OK, exporters(meter_provider))
+}
+
+#[cfg(test)]
+mod tests {
+    use prometheus::Registry;
+
+    use super::*;
+    use crate::plugins::telemetry::metrics::MetricsBuilder;
+
+    fn counted(registry: &Registry) -> f64 {
+        registry
+            .gather()
+            .iter()
+            .filter(|family| family.get_name().starts_with("test_counter"))
+            .flat_map(|family| family.get_metric())
+            .map(|metric| metric.get_counter().get_value())
+            .sum()
+    }
+
+    #[tokio::test]
+    async fn it_skips_the_disabled_exporters() {
+        let prometheus = opentelemetry_prometheus::exporter().init();
+        let otlp = opentelemetry_prometheus::exporter().init();
+        let meter_provider = MetricsBuilder::default()
+            .with_meter_provider("prometheus", prometheus.provider().expect("meter provider"))
+            .with_meter_provider("otlp", otlp.provider().expect("meter provider"))
+            .meter_provider();
+        let counter = meter_provider
+            .meter("apollo/router", None)
+            .build_counter(|m| m.u64_counter("test_counter").init());
+        counter.add(1, &[]);
+
+        let endpoint = endpoint("/metrics/exporters".to_string(), meter_provider).into_router();
+        let response = endpoint
+            .clone()
+            .oneshot(
+                http::Request::post("/metrics/exporters")
+                    .body(r#"{"otlp": false}"#.into())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&body).unwrap(),
+            serde_json::json!({ "exporters": { "otlp": false, "prometheus": true } })
+        );
+
+        // the instruments built before the change skip the disabled exporter
+        counter.add(2, &[]);
+        assert_eq!(counted(prometheus.registry()), 3.0);
+        assert_eq!(counted(otlp.registry()), 1.0);
+
+        let response = endpoint
+            .oneshot(
+                http::Request::post("/metrics/exporters")
+                    .body(r#"{"statsd": false}"#.into())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    }
+}
@@ -48,16 +48,20 @@ use tracing_opentelemetry::OpenTelemetrySpanExt;
 use tracing_subscriber::prelude::__tracing_subscriber_SubscriberExt;
 use tracing_subscriber::registry::LookupSpan;
 #[cfg(not(feature = "console"))]
+use tracing_subscriber::reload;
+#[cfg(not(feature = "console"))]
 use tracing_subscriber::EnvFilter;
 use tracing_subscriber::Registry;
 use url::Url;
 
 use self::apollo::ForwardValues;
+use self::apollo::OperationSignature;
 use self::apollo::SingleReport;
 use self::apollo_exporter::Sender;
 use self::config::Conf;
 use self::metrics::AttributesForwardConf;
 use self::metrics::MetricsAttributesConf;
+use crate::error::FetchError;
 #[cfg(not(feature = "console"))]
 use crate::executable::GLOBAL_ENV_FILTER;
 use crate::layers::ServiceBuilderExt;
@@ -65,12 +69,16 @@ use crate::plugin::Plugin;
 use crate::plugin::PluginInit;
 use crate::plugins::telemetry::apollo::ForwardHeaders;
 use crate::plugins::telemetry::config::MetricsCommon;
+use crate::plugins::telemetry::config::PropagationFormat;
 use crate::plugins::telemetry::config::Trace;
 use crate::plugins::telemetry::formatters::JsonFields;
 use crate::plugins::telemetry::metrics::apollo::studio::SingleContextualizedStats;
 use crate::plugins::telemetry::metrics::apollo::studio::SingleQueryLatencyStats;
 use crate::plugins::telemetry::metrics::apollo::studio::SingleStats;
 use crate::plugins::telemetry::metrics::apollo::studio::SingleStatsReport;
+use crate::plugins::telemetry::metrics::heartbeat::Heartbeat;
+use crate::plugins::telemetry::metrics::prometheus::Exemplars;
+use crate::plugins::telemetry::metrics::serialized_size;
 use crate::plugins::telemetry::metrics::AggregateMeterProvider;
 use crate::plugins::telemetry::metrics::BasicMetrics;
 use crate::plugins::telemetry::metrics::MetricsBuilder;
@@ -81,8 +89,11 @@ use crate::query_planner::USAGE_REPORTING;
 use crate::register_plugin;
 use crate::router_factory::Endpoint;
 use crate::services::execution;
+use crate::services::measured_buffer::BufferMetrics;
+use crate::services::measured_connector::ConnectionPoolMetrics;
 use crate::services::subgraph;
 use crate::services::supergraph;
+use crate::services::OPERATION_KIND;
 use crate::spaceport::server::ReportSpaceport;
 use crate::spaceport::StatsContext;
 use crate::subgraph::Request;
@@ -99,7 +110,7 @@ pub(crate) mod apollo;
 pub(crate) mod apollo_exporter;
 pub(crate) mod config;
 pub(crate) mod formatters;
-mod metrics;
+pub(crate) mod metrics;
 mod otlp;
 mod tracing;
 pub(crate) const SUPERGRAPH_SPAN_NAME: &str = "supergraph";
@@ -108,12 +119,16 @@ const CLIENT_NAME: &str = "apollo_telemetry::client_name";
 const CLIENT_VERSION: &str = "apollo_telemetry::client_version";
 const ATTRIBUTES: &str = "apollo_telemetry::metrics_attributes";
 const SUBGRAPH_ATTRIBUTES: &str = "apollo_telemetry::subgraph_metrics_attributes";
+const QUERY_PLAN_SUMMARY: &str = "apollo_telemetry::query_plan_summary";
 pub(crate) const STUDIO_EXCLUDE: &str = "apollo_telemetry::studio::exclude";
 pub(crate) const FTV1_DO_NOT_SAMPLE: &str = "apollo_telemetry::studio::ftv1_do_not_sample";
 const DEFAULT_SERVICE_NAME: &str = "apollo-router";
 
 static TELEMETRY_LOADED: OnceCell<bool> = OnceCell::new();
+#[cfg(not(feature = "console"))]
+static LOG_FILTER_RELOAD_HANDLE: OnceCell<reload::Handle<EnvFilter, Registry>> = OnceCell::new();
 static TELEMETRY_REFCOUNT: AtomicU8 = AtomicU8::new(0);
+static INCOMING_PROPAGATOR: OnceCell<TextMapCompositePropagator> = OnceCell::new();
 
 #[doc(hidden)] // Only public for integration tests
 pub struct Telemetry {
@@ -122,10 +137,12 @@ pub struct Telemetry {
     // Typically the handles are a PushController but may be something else. Dropping the handle will
     // shutdown exporter.
     _metrics_exporters: Vec<MetricsExporterHandle>,
+    _heartbeat: Option<Heartbeat>,
     meter_provider: AggregateMeterProvider,
     custom_endpoints: MultiMap<ListenAddr, Endpoint>,
     apollo_metrics_sender: apollo_exporter::Sender,
     field_level_instrumentation_ratio: f64,
+    exemplars: Option<Exemplars>,
 }
 
 #[derive(Debug)]
@@ -150,6 +167,31 @@ fn setup_tracing<T: TracingConfigurator>(
     Ok(builder)
 }
 
+/// The log filter from the configuration, falling back to the command line one
+#[cfg(not(feature = "console"))]
+fn log_filter_directives(config: &config::Conf) -> String {
+    config
+        .logging
+        .as_ref()
+        .and_then(|logging| logging.filter.clone())
+        .or_else(|| GLOBAL_ENV_FILTER.get().cloned())
+        .unwrap_or_else(|| "info".to_string())
+}
+
+#[cfg(not(feature = "console"))]
+fn reload_env_filter<S>(
+    handle: &reload::Handle<EnvFilter, S>,
+    directives: &str,
+) -> Result<(), BoxError>
+where
+    S: Subscriber,
+{
+    let filter = EnvFilter::try_new(directives)
+        .map_err(|e| format!("could not parse log filter '{directives}': {e}"))?;
+    handle.reload(filter)?;
+    Ok(())
+}
+
 fn setup_metrics_exporter<T: MetricsConfigurator>(
     mut builder: MetricsBuilder,
     configurator: &Option<T>,
@@ -184,41 +226,79 @@ impl Plugin for Telemetry {
     fn supergraph_service(&self, service: supergraph::BoxService) -> supergraph::BoxService {
         let metrics_sender = self.apollo_metrics_sender.clone();
         let metrics = BasicMetrics::new(&self.meter_provider);
+        let exemplars = self.exemplars.clone();
         let config = Arc::new(self.config.clone());
         let config_map_res = config.clone();
+        let operation_signature = config
+            .apollo
+            .as_ref()
+            .map(|apollo| apollo.operation_signature.clone())
+            .unwrap_or_default();
+        let query_plan_summary_extension = config
+            .tracing
+            .as_ref()
+            .map(|tracing| tracing.query_plan_summary_extension)
+            .unwrap_or_default();
         ServiceBuilder::new()
             .instrument(Self::supergraph_service_span(
                 self.field_level_instrumentation_ratio,
                 config.apollo.clone().unwrap_or_default(),
             ))
-            .map_response(|resp: SupergraphResponse| {
+            .map_response(move |resp: SupergraphResponse| {
                 if let Ok(Some(usage_reporting)) =
                     resp.context.get::<_, UsageReporting>(USAGE_REPORTING)
                 {
                     // Record the operation signature on the router span
                     Span::current().record(
                         "apollo_private.operation_signature",
-                        &usage_reporting.stats_report_key.as_str(),
+                        &operation_signature
+                            .stats_report_key(&usage_reporting.stats_report_key)
+                            .as_str(),
                     );
                 }
-                resp
+                match resp.context.get::<_, String>(QUERY_PLAN_SUMMARY) {
+                    Ok(Some(summary)) => {
+                        Span::current().record("query_plan.summary", &summary.as_str());
+                        if query_plan_summary_extension {
+                            let mut first = true;
+                            return resp.map_stream(move |mut response| {
+                                if std::mem::take(&mut first) {
+                                    response
+                                        .extensions
+                                        .insert("queryPlanSummary", summary.clone().into());
+                                }
+                                response
+                            });
+                        }
+                        resp
+                    }
+                    _ => resp,
+                }
             })
             .map_future_with_request_data(
                 move |req: &SupergraphRequest| {
                     Self::populate_context(config.clone(), req);
-                    req.context.clone()
+                    (
+                        req.context.clone(),
+                        serialized_size(req.supergraph_request.body()),
+                    )
                 },
-                move |ctx: Context, fut| {
+                move |(ctx, request_size): (Context, usize), fut| {
                     let config = config_map_res.clone();
                     let metrics = metrics.clone();
+                    let exemplars = exemplars.clone();
                     let sender = metrics_sender.clone();
                     let start = Instant::now();
                     async move {
+                        metrics
+                            .request_body_bytes
+                            .record(request_size as u64, &Self::metric_attributes(&ctx));
                         let mut result: Result<SupergraphResponse, BoxError> = fut.await;
                         result = Self::update_otel_metrics(
                             config.clone(),
                             ctx.clone(),
                             metrics.clone(),
+                            exemplars,
                             result,
                             start.elapsed(),
                         )
@@ -255,11 +335,15 @@ impl Plugin for Telemetry {
                     .operation_name
                     .clone()
                     .unwrap_or_default();
+                let summary = &req.query_plan.summary;
+                // also recorded on the supergraph span, once the response is ready
+                let _ = req.context.insert(QUERY_PLAN_SUMMARY, summary.clone());
                 info_span!("execution",
                     graphql.document = query.as_str(),
                     graphql.operation.name = operation_name.as_str(),
                     "otel.kind" = %SpanKind::Internal,
-                    ftv1.do_not_sample_reason = do_not_sample_reason
+                    ftv1.do_not_sample_reason = do_not_sample_reason,
+                    query_plan.summary = summary.as_str()
                 )
             })
             .service(service)
@@ -271,6 +355,7 @@ impl Plugin for Telemetry {
         let subgraph_attribute = KeyValue::new("subgraph", name.to_string());
         let subgraph_metrics_conf_req = self.create_subgraph_metrics_conf(name);
         let subgraph_metrics_conf_resp = subgraph_metrics_conf_req.clone();
+        let inherited_attributes = self.inherited_router_attributes();
         let name = name.to_owned();
         let apollo_handler = self.apollo_handler();
         ServiceBuilder::new()
@@ -302,6 +387,7 @@ impl Plugin for Telemetry {
                 move |sub_request: &SubgraphRequest| {
                     Self::store_subgraph_request_attributes(
                         subgraph_metrics_conf_req.clone(),
+                        &inherited_attributes,
                         sub_request,
                     );
                     sub_request.context.clone()
@@ -347,6 +433,31 @@ impl Telemetry {
         Self::new_common(serde_json::from_value(config)?, Some(subscriber)).await
     }
 
+    /// The meter provider used by the router's internal components, like caches, to send metrics
+    pub(crate) fn meter_provider(&self) -> &AggregateMeterProvider {
+        &self.meter_provider
+    }
+
+    /// The metrics of an internal buffer, if they are enabled
+    pub(crate) fn buffer_metrics(&self, buffer: &str) -> Option<BufferMetrics> {
+        self.config
+            .metrics
+            .as_ref()
+            .and_then(|metrics| metrics.common.as_ref())
+            .filter(|common| common.buffer_metrics)
+            .map(|_| BufferMetrics::new(buffer, &self.meter_provider))
+    }
+
+    /// The metrics of the connection pool of a subgraph, if they are enabled
+    pub(crate) fn connection_pool_metrics(&self, subgraph: &str) -> Option<ConnectionPoolMetrics> {
+        self.config
+            .metrics
+            .as_ref()
+            .and_then(|metrics| metrics.common.as_ref())
+            .filter(|common| common.connection_pool_metrics)
+            .map(|_| ConnectionPoolMetrics::new(subgraph, &self.meter_provider))
+    }
+
     /// This method can be used instead of `Plugin::new` to override the subscriber
     async fn new_common<S>(
         mut config: <Self as Plugin>::Config,
@@ -370,7 +481,14 @@ impl Telemetry {
                 ..
             } => {
                 ::tracing::debug!("starting Spaceport");
-                let report_spaceport = ReportSpaceport::new("127.0.0.1:0".parse()?).await?;
+                let (ingress, headers) = apollo.ingress()?;
+                let report_spaceport = ReportSpaceport::new(
+                    "127.0.0.1:0".parse()?,
+                    ingress,
+                    headers,
+                    apollo.report_uploads.clone(),
+                )
+                .await?;
                 // Now that the port is known update the config
                 apollo.endpoint = Some(Url::parse(&format!(
                     "https://{}",
@@ -378,7 +496,13 @@ impl Telemetry {
                 ))?);
                 Some(report_spaceport)
             }
-            _ => None,
+            _ => {
+                // the configured ingress settings are checked even when they are not used
+                if apollo.ingress_url.is_some() || !apollo.ingress_headers.is_empty() {
+                    apollo.ingress()?;
+                }
+                None
+            }
         };
 
         // Setup metrics
@@ -404,6 +528,9 @@ impl Telemetry {
             global::set_error_handler(handle_error)
                 .expect("otel error handler lock poisoned, fatal");
             global::set_text_map_propagator(Self::create_propagator(&config));
+            if let Some(propagator) = Self::create_incoming_propagator(&config) {
+                let _ = INCOMING_PROPAGATOR.set(propagator);
+            }
 
             #[cfg(feature = "console")]
             {
@@ -418,18 +545,7 @@ impl Telemetry {
 
             #[cfg(not(feature = "console"))]
             {
-                let log_level = GLOBAL_ENV_FILTER
-                    .get()
-                    .map(|s| s.as_str())
-                    .unwrap_or("info");
-
-                let sub_builder = tracing_subscriber::fmt::fmt()
-                    .with_env_filter(
-                        EnvFilter::try_new(log_level)
-                            .context("could not parse log configuration")?,
-                    )
-                    .with_file(true)
-                    .with_line_number(true);
+                let log_level = log_filter_directives(&config);
 
                 if let Some(sub) = subscriber {
                     let telemetry = tracing_opentelemetry::layer().with_tracer(tracer);
@@ -437,31 +553,43 @@ impl Telemetry {
                     if let Err(e) = set_global_default(subscriber) {
                         ::tracing::error!("cannot set global subscriber: {:?}", e);
                     }
-                } else if atty::is(atty::Stream::Stdout) {
-                    let telemetry = tracing_opentelemetry::layer().with_tracer(tracer);
-
-                    let subscriber = sub_builder
-                        .event_format(formatters::TextFormatter::new())
-                        .finish()
-                        .with(telemetry);
-                    if let Err(e) = set_global_default(subscriber) {
-                        ::tracing::error!("cannot set global subscriber: {:?}", e);
-                    }
                 } else {
+                    // The filter is installed behind a reload layer so that configuration
+                    // reloads can change it without replacing the global subscriber
+                    let (filter, reload_handle) = reload::Layer::new(
+                        EnvFilter::try_new(&log_level)
+                            .context("could not parse log configuration")?,
+                    );
+                    let _ = LOG_FILTER_RELOAD_HANDLE.set(reload_handle);
+
+                    let fmt_layer = tracing_subscriber::fmt::layer()
+                        .with_file(true)
+                        .with_line_number(true);
                     let telemetry = tracing_opentelemetry::layer().with_tracer(tracer);
 
-                    let subscriber = sub_builder
-                        .map_event_format(|e| {
-                            e.json()
-                                .with_current_span(true)
-                                .with_span_list(true)
-                                .flatten_event(true)
-                        })
-                        .map_fmt_fields(|_f| JsonFields::new())
-                        .finish()
-                        .with(telemetry);
-                    if let Err(e) = set_global_default(subscriber) {
-                        ::tracing::error!("cannot set global subscriber: {:?}", e);
+                    if atty::is(atty::Stream::Stdout) {
+                        let subscriber = Registry::default()
+                            .with(filter)
+                            .with(fmt_layer.event_format(formatters::TextFormatter::new()))
+                            .with(telemetry);
+                        if let Err(e) = set_global_default(subscriber) {
+                            ::tracing::error!("cannot set global subscriber: {:?}", e);
+                        }
+                    } else {
+                        let subscriber = Registry::default()
+                            .with(filter)
+                            .with(
+                                fmt_layer
+                                    .json()
+                                    .with_current_span(true)
+                                    .with_span_list(true)
+                                    .flatten_event(true)
+                                    .fmt_fields(JsonFields::new()),
+                            )
+                            .with(telemetry);
+                        if let Err(e) = set_global_default(subscriber) {
+                            ::tracing::error!("cannot set global subscriber: {:?}", e);
+                        }
                     }
                 };
             }
@@ -469,15 +597,46 @@ impl Telemetry {
             Ok(true)
         })?;
 
+        // On configuration reloads the subscriber is already set, only the log filter may change
+        #[cfg(not(feature = "console"))]
+        if let Some(handle) = LOG_FILTER_RELOAD_HANDLE.get() {
+            reload_env_filter(handle, &log_filter_directives(&config))?;
+        }
+
         let field_level_instrumentation_ratio =
             config.calculate_field_level_instrumentation_ratio()?;
 
+        let meter_provider = builder.meter_provider();
+        let spaceport_meter_provider = meter_provider.clone();
+        let heartbeat = config
+            .metrics
+            .as_ref()
+            .and_then(|metrics| metrics.common.as_ref())
+            .filter(|common| common.heartbeat.enabled)
+            .map(|common| Heartbeat::start(common.heartbeat.interval, &meter_provider));
+
+        let mut custom_endpoints = builder.custom_endpoints();
+        if let Some(toggles) = config
+            .metrics
+            .as_ref()
+            .and_then(|metrics| metrics.common.as_ref())
+            .map(|common| &common.exporter_toggles)
+            .filter(|toggles| toggles.enabled)
+        {
+            custom_endpoints.insert(
+                toggles.listen.clone(),
+                metrics::toggles::endpoint(toggles.path.clone(), meter_provider.clone()),
+            );
+        }
+
         let plugin = Ok(Telemetry {
-            custom_endpoints: builder.custom_endpoints(),
+            custom_endpoints,
             _metrics_exporters: builder.exporters(),
-            meter_provider: builder.meter_provider(),
+            _heartbeat: heartbeat,
+            meter_provider,
             apollo_metrics_sender: builder.apollo_metrics_provider(),
             field_level_instrumentation_ratio,
+            exemplars: builder.exemplars(),
             config,
         });
 
@@ -486,7 +645,7 @@ impl Telemetry {
         if let Some(spaceport) = spaceport {
             tokio::spawn(async move {
                 ::tracing::debug!("serving spaceport");
-                match spaceport.serve().await {
+                match spaceport.serve(spaceport_meter_provider).await {
                     Ok(v) => {
                         ::tracing::debug!("spaceport terminated normally: {:?}", v);
                     }
@@ -539,6 +698,42 @@ impl Telemetry {
         TextMapCompositePropagator::new(propagators)
     }
 
+    fn create_incoming_propagator(config: &config::Conf) -> Option<TextMapCompositePropagator> {
+        let mut formats = config
+            .tracing
+            .as_ref()?
+            .propagation
+            .as_ref()?
+            .incoming
+            .clone()?;
+        formats.sort();
+        formats.dedup();
+
+        let propagators = formats
+            .into_iter()
+            .map(
+                |format| -> Box<dyn TextMapPropagator + Send + Sync + 'static> {
+                    match format {
+                        PropagationFormat::Jaeger => {
+                            Box::new(opentelemetry_jaeger::Propagator::default())
+                        }
+                        PropagationFormat::Baggage => Box::new(BaggagePropagator::default()),
+                        PropagationFormat::TraceContext => {
+                            Box::new(TraceContextPropagator::default())
+                        }
+                        PropagationFormat::Zipkin => {
+                            Box::new(opentelemetry_zipkin::Propagator::default())
+                        }
+                        PropagationFormat::Datadog => {
+                            Box::new(opentelemetry_datadog::DatadogPropagator::default())
+                        }
+                    }
+                },
+            )
+            .collect();
+        Some(TextMapCompositePropagator::new(propagators))
+    }
+
     fn create_tracer_provider(
         config: &config::Conf,
     ) -> Result<opentelemetry::sdk::trace::TracerProvider, BoxError> {
@@ -585,11 +780,25 @@ impl Telemetry {
             );
         }
 
-        let mut builder = MetricsBuilder::default();
+        let mut builder = MetricsBuilder::default().with_static_attributes(
+            metrics_common_config
+                .static_attributes
+                .iter()
+                .map(|(name, value)| KeyValue::new(name.clone(), value.clone()))
+                .collect(),
+        );
         builder = setup_metrics_exporter(builder, &config.apollo, metrics_common_config)?;
         builder =
             setup_metrics_exporter(builder, &metrics_config.prometheus, metrics_common_config)?;
         builder = setup_metrics_exporter(builder, &metrics_config.otlp, metrics_common_config)?;
+        builder = setup_metrics_exporter(builder, &metrics_config.statsd, metrics_common_config)?;
+        // Only a metrics configuration without exporters is worth a fallback
+        if config.metrics.is_some() {
+            builder = builder.with_fallback(
+                metrics_common_config.fallback,
+                &metrics_common_config.exporter_toggles.listen,
+            )?;
+        }
         Ok(builder)
     }
 
@@ -625,6 +834,7 @@ impl Telemetry {
                 otel.kind = %SpanKind::Internal,
                 apollo_private.field_level_instrumentation_ratio = field_level_instrumentation_ratio,
                 apollo_private.operation_signature = field::Empty,
+                query_plan.summary = field::Empty,
                 apollo_private.graphql.variables = field::Empty,
                 apollo_private.http.request_headers = field::Empty
             );
@@ -729,14 +939,9 @@ impl Telemetry {
         }
     }
 
-    async fn update_otel_metrics(
-        config: Arc<Conf>,
-        context: Context,
-        metrics: BasicMetrics,
-        result: Result<SupergraphResponse, BoxError>,
-        request_duration: Duration,
-    ) -> Result<SupergraphResponse, BoxError> {
-        let mut metric_attrs = context
+    /// Metric attributes stored in the context by `populate_context`
+    fn metric_attributes(context: &Context) -> Vec<KeyValue> {
+        context
             .get::<_, HashMap<String, String>>(ATTRIBUTES)
             .ok()
             .flatten()
@@ -746,7 +951,22 @@ impl Telemetry {
                     .map(|(attr_name, attr_value)| KeyValue::new(attr_name, attr_value))
                     .collect::<Vec<KeyValue>>()
             })
-            .unwrap_or_default();
+            .unwrap_or_default()
+    }
+
+    async fn update_otel_metrics(
+        config: Arc<Conf>,
+        context: Context,
+        metrics: BasicMetrics,
+        exemplars: Option<Exemplars>,
+        result: Result<SupergraphResponse, BoxError>,
+        request_duration: Duration,
+    ) -> Result<SupergraphResponse, BoxError> {
+        let mut metric_attrs = Self::metric_attributes(&context);
+        // set by the supergraph service once the operation is parsed
+        if let Ok(Some(operation_kind)) = context.get::<_, String>(OPERATION_KIND) {
+            metric_attrs.push(KeyValue::new("operation_kind", operation_kind));
+        }
         let res = match result {
             Ok(response) => {
                 metric_attrs.push(KeyValue::new(
@@ -798,6 +1018,10 @@ impl Telemetry {
         metrics
             .http_requests_duration
             .record(request_duration.as_secs_f64(), &metric_attrs);
+        // the trace of the request span, which the supergraph service runs in
+        if let Some(exemplars) = exemplars {
+            exemplars.record(request_duration.as_secs_f64(), &metric_attrs);
+        }
 
         res
     }
@@ -916,25 +1140,57 @@ impl Telemetry {
                     let response = merge_config!(response);
                     let errors = merge_config!(errors);
 
-                    AttributesForwardConf {
-                        insert: (!insert.is_empty()).then_some(insert),
-                        request: (request.header.is_some() || request.body.is_some())
-                            .then_some(request),
-                        response: (response.header.is_some() || response.body.is_some())
-                            .then_some(response),
-                        errors: (errors.extensions.is_some() || errors.include_messages)
-                            .then_some(errors),
-                        context: (!context.is_empty()).then_some(context),
-                    }
+                    AttributesForwardConf::builder()
+                        .inserts(insert)
+                        .and_request(
+                            (request.header.is_some() || request.body.is_some()).then_some(request),
+                        )
+                        .and_response(
+                            (response.header.is_some() || response.body.is_some())
+                                .then_some(response),
+                        )
+                        .and_errors(
+                            (errors.extensions.is_some() || errors.include_messages)
+                                .then_some(errors),
+                        )
+                        .contexts(context)
+                        .build()
                 }),
         )
     }
 
+    /// Names of the router attributes added to the subgraph metrics
+    fn inherited_router_attributes(&self) -> Arc<Vec<String>> {
+        Arc::new(
+            self.config
+                .metrics
+                .as_ref()
+                .and_then(|m| m.common.as_ref())
+                .and_then(|c| c.attributes.as_ref())
+                .map(|a| a.inherit_router_attributes.clone())
+                .unwrap_or_default(),
+        )
+    }
+
     fn store_subgraph_request_attributes(
         attribute_forward_config: Arc<Option<AttributesForwardConf>>,
+        inherited_attributes: &[String],
         sub_request: &Request,
     ) {
         let mut attributes = HashMap::new();
+        // The router attributes come first, the subgraph attributes of the same name override them
+        if !inherited_attributes.is_empty() {
+            if let Ok(Some(router_attributes)) = sub_request
+                .context
+                .get::<_, HashMap<String, String>>(ATTRIBUTES)
+            {
+                attributes.extend(
+                    router_attributes
+                        .into_iter()
+                        .filter(|(name, _)| inherited_attributes.contains(name)),
+                );
+            }
+        }
         if let Some(subgraph_attributes_conf) = &*attribute_forward_config {
             attributes.extend(subgraph_attributes_conf.get_attributes_from_request(
                 sub_request.subgraph_request.headers(),
@@ -968,7 +1224,7 @@ impl Telemetry {
                     .collect::<Vec<KeyValue>>()
             })
             .unwrap_or_default();
-        metric_attrs.push(subgraph_attribute);
+        metric_attrs.push(subgraph_attribute.clone());
         // Fill attributes from context
         if let Some(subgraph_attributes_conf) = &*attribute_forward_config {
             metric_attrs.extend(
@@ -1002,6 +1258,13 @@ impl Telemetry {
                 metrics.http_requests_total.add(1, &metric_attrs);
             }
             Err(err) => {
+                if let Some(FetchError::SubrequestEmptyResponse { .. }) =
+                    err.downcast_ref::<FetchError>()
+                {
+                    metrics
+                        .subgraph_empty_responses_total
+                        .add(1, &[subgraph_attribute.clone()]);
+                }
                 // Fill attributes from error
                 if let Some(subgraph_attributes_conf) = &*attribute_forward_config {
                     metric_attrs.extend(
@@ -1029,10 +1292,32 @@ impl Telemetry {
         start: Instant,
         result: Result<supergraph::Response, BoxError>,
     ) -> Result<supergraph::Response, BoxError> {
+        let operation_signature = config
+            .apollo
+            .as_ref()
+            .map(|apollo| apollo.operation_signature.clone())
+            .unwrap_or_default();
+        let excluded_fields = config
+            .apollo
+            .as_ref()
+            .map(|apollo| apollo.field_level_instrumentation_exclude.clone())
+            .unwrap_or_default();
+        let latency_precision = config
+            .apollo
+            .as_ref()
+            .map(|apollo| apollo.latency_precision)
+            .unwrap_or_default();
         match result {
             Err(e) => {
                 if !matches!(sender, Sender::Noop) {
-                    Self::update_apollo_metrics(ctx, sender, true, start.elapsed());
+                    Self::update_apollo_metrics(
+                        ctx,
+                        sender,
+                        &operation_signature,
+                        &excluded_fields,
+                        true,
+                        latency_precision.apply(start.elapsed()),
+                    );
                 }
                 let mut metric_attrs = Vec::new();
                 // Fill attributes from error
@@ -1057,6 +1342,8 @@ impl Telemetry {
             }
             Ok(router_response) => {
                 let mut has_errors = !router_response.response.status().is_success();
+                let mut response_size = 0;
+                let metric_attrs = Self::metric_attributes(ctx);
                 Ok(router_response.map(move |response_stream| {
                     let sender = sender.clone();
                     let ctx = ctx.clone();
@@ -1066,16 +1353,22 @@ impl Telemetry {
                             if !response.errors.is_empty() {
                                 has_errors = true;
                             }
-
-                            if !response.has_next.unwrap_or(false)
-                                && !matches!(sender, Sender::Noop)
-                            {
-                                Self::update_apollo_metrics(
-                                    &ctx,
-                                    sender.clone(),
-                                    has_errors,
-                                    start.elapsed(),
-                                );
+                            response_size += serialized_size(&response);
+
+                            if !response.has_next.unwrap_or(false) {
+                                metrics
+                                    .response_body_bytes
+                                    .record(response_size as u64, &metric_attrs);
+                                if !matches!(sender, Sender::Noop) {
+                                    Self::update_apollo_metrics(
+                                        &ctx,
+                                        sender.clone(),
+                                        &operation_signature,
+                                        &excluded_fields,
+                                        has_errors,
+                                        latency_precision.apply(start.elapsed()),
+                                    );
+                                }
                             }
                             response
                         })
@@ -1088,6 +1381,8 @@ impl Telemetry {
     fn update_apollo_metrics(
         context: &Context,
         sender: Sender,
+        operation_signature: &OperationSignature,
+        excluded_fields: &[String],
         has_errors: bool,
         duration: Duration,
     ) {
@@ -1114,6 +1409,26 @@ impl Telemetry {
                     ..Default::default()
                 }
             } else {
+                let mut stats_with_context = SingleContextualizedStats {
+                    context: StatsContext {
+                        client_name: context
+                            .get(CLIENT_NAME)
+                            .unwrap_or_default()
+                            .unwrap_or_default(),
+                        client_version: context
+                            .get(CLIENT_VERSION)
+                            .unwrap_or_default()
+                            .unwrap_or_default(),
+                    },
+                    query_latency_stats: SingleQueryLatencyStats {
+                        latency: duration,
+                        has_errors,
+                        persisted_query_hit,
+                        ..Default::default()
+                    },
+                    ..Default::default()
+                };
+                stats_with_context.exclude_fields(excluded_fields);
                 SingleStatsReport {
                     request_id: uuid::Uuid::from_bytes(
                         Span::current()
@@ -1125,27 +1440,9 @@ impl Telemetry {
                     ),
                     operation_count,
                     stats: HashMap::from([(
-                        usage_reporting.stats_report_key.to_string(),
+                        operation_signature.stats_report_key(&usage_reporting.stats_report_key),
                         SingleStats {
-                            stats_with_context: SingleContextualizedStats {
-                                context: StatsContext {
-                                    client_name: context
-                                        .get(CLIENT_NAME)
-                                        .unwrap_or_default()
-                                        .unwrap_or_default(),
-                                    client_version: context
-                                        .get(CLIENT_VERSION)
-                                        .unwrap_or_default()
-                                        .unwrap_or_default(),
-                                },
-                                query_latency_stats: SingleQueryLatencyStats {
-                                    latency: duration,
-                                    has_errors,
-                                    persisted_query_hit,
-                                    ..Default::default()
-                                },
-                                ..Default::default()
-                            },
+                            stats_with_context,
                             referenced_fields_by_type: usage_reporting
                                 .referenced_fields_by_type
                                 .into_iter()
@@ -1208,6 +1505,16 @@ pub(crate) fn is_span_sampled(context: &Context) -> bool {
             .unwrap_or(false)
 }
 
+/// Extracts the trace context sent in the headers of an incoming request, with the propagators
+/// of the `incoming` formats if configured, or else with the global propagator
+pub(crate) fn extract_context(headers: &HeaderMap) -> opentelemetry::Context {
+    let extractor = opentelemetry_http::HeaderExtractor(headers);
+    match INCOMING_PROPAGATOR.get() {
+        Some(propagator) => propagator.extract(&extractor),
+        None => global::get_text_map_propagator(|propagator| propagator.extract(&extractor)),
+    }
+}
+
 register_plugin!("apollo", "telemetry", Telemetry);
 
 /// This enum is a partial cleanup of the telemetry plugin logic.
@@ -1253,12 +1560,16 @@ mod tests {
     use std::str::FromStr;
 
     use http::StatusCode;
+    use opentelemetry::trace::TraceContextExt;
+    use opentelemetry::trace::TracerProvider;
     use serde_json::Value;
     use serde_json_bytes::json;
     use serde_json_bytes::ByteString;
     use tower::util::BoxService;
     use tower::Service;
     use tower::ServiceExt;
+    use tracing_opentelemetry::OpenTelemetrySpanExt;
+    use tracing_subscriber::layer::SubscriberExt;
 
     use crate::error::FetchError;
     use crate::graphql::Error;
@@ -1268,8 +1579,10 @@ mod tests {
     use crate::plugin::test::MockSubgraphService;
     use crate::plugin::test::MockSupergraphService;
     use crate::plugin::DynPlugin;
+    use crate::query_planner::OperationKind;
     use crate::services::SubgraphRequest;
     use crate::services::SubgraphResponse;
+    use crate::services::OPERATION_KIND;
     use crate::SupergraphRequest;
     use crate::SupergraphResponse;
 
@@ -1725,4 +2038,832 @@ mod tests {
         assert!(prom_metrics.contains(r#"apollo_router_http_requests_total{another_test="my_default_value",error="400 Bad Request",myname="label_value",renamed_value="my_value_set",service_name="apollo-router",status="400"} 1"#));
         assert!(prom_metrics.contains(r#"apollo_router_http_requests_error_total{another_test="my_default_value",error="400 Bad Request",myname="label_value",renamed_value="my_value_set",service_name="apollo-router",status="400"} 1"#))
     }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn it_records_request_and_response_body_bytes() {
+        let mut mock_service = MockSupergraphService::new();
+        mock_service
+            .expect_call()
+            .times(1)
+            .returning(move |req: SupergraphRequest| {
+                Ok(SupergraphResponse::fake_builder()
+                    .context(req.context)
+                    .data(json!({"topProducts": [{"name": "Table"}, {"name": "Chair"}]}))
+                    .build()
+                    .unwrap())
+            });
+
+        let dyn_plugin: Box<dyn DynPlugin> = crate::plugin::plugins()
+            .get("apollo.telemetry")
+            .expect("Plugin not found")
+            .create_instance(
+                &serde_json::json!({
+                    "apollo": {
+                        "schema_id": "schema_sha"
+                    },
+                    "metrics": {
+                        "common": {
+                            "service_name": "apollo-router"
+                        },
+                        "prometheus": {
+                            "enabled": true
+                        }
+                    }
+                }),
+                Default::default(),
+            )
+            .await
+            .unwrap();
+        let mut supergraph_service = dyn_plugin.supergraph_service(BoxService::new(mock_service));
+        let router_req = SupergraphRequest::fake_builder()
+            .query("query TopProducts { topProducts { name } }")
+            .operation_name("TopProducts")
+            .build()
+            .unwrap();
+        let request_size = serde_json::to_vec(router_req.supergraph_request.body())
+            .unwrap()
+            .len();
+
+        let router_response = supergraph_service
+            .ready()
+            .await
+            .unwrap()
+            .call(router_req)
+            .await
+            .unwrap()
+            .next_response()
+            .await
+            .unwrap();
+        let response_size = serde_json::to_vec(&router_response).unwrap().len();
+
+        let http_req_prom = http::Request::get("http://localhost:9090/metrics")
+            .body(Default::default())
+            .unwrap();
+        let web_endpoint = dyn_plugin
+            .web_endpoints()
+            .into_iter()
+            .next()
+            .unwrap()
+            .1
+            .into_iter()
+            .next()
+            .unwrap()
+            .into_router();
+        let mut resp = web_endpoint.oneshot(http_req_prom).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(resp.body_mut()).await.unwrap();
+        let prom_metrics = String::from_utf8_lossy(&body);
+        assert!(prom_metrics.contains(&format!(
+            r#"apollo_router_request_body_bytes_sum{{operation_name="TopProducts",service_name="apollo-router"}} {request_size}"#
+        )));
+        assert!(prom_metrics.contains(&format!(
+            r#"apollo_router_response_body_bytes_sum{{operation_name="TopProducts",service_name="apollo-router"}} {response_size}"#
+        )));
+    }
+
+    #[tokio::test]
+    async fn it_attaches_exemplars_to_the_duration_of_sampled_requests() {
+        let mut mock_service = MockSupergraphService::new();
+        mock_service
+            .expect_call()
+            .times(1)
+            .returning(move |req: SupergraphRequest| {
+                Ok(SupergraphResponse::fake_builder()
+                    .context(req.context)
+                    .data(json!({"ok": true}))
+                    .build()
+                    .unwrap())
+            });
+
+        let dyn_plugin: Box<dyn DynPlugin> = crate::plugin::plugins()
+            .get("apollo.telemetry")
+            .expect("Plugin not found")
+            .create_instance(
+                &serde_json::json!({
+                    "apollo": {
+                        "schema_id": "schema_sha"
+                    },
+                    "metrics": {
+                        "common": {
+                            "service_name": "apollo-router"
+                        },
+                        "prometheus": {
+                            "enabled": true,
+                            "exemplars": true
+                        }
+                    }
+                }),
+                Default::default(),
+            )
+            .await
+            .unwrap();
+        let mut supergraph_service = dyn_plugin.supergraph_service(BoxService::new(mock_service));
+
+        // the default sampler samples every trace
+        let tracer = opentelemetry::sdk::trace::TracerProvider::builder()
+            .build()
+            .tracer("test");
+        let subscriber = tracing_subscriber::Registry::default()
+            .with(tracing_opentelemetry::layer().with_tracer(tracer));
+        let _guard = tracing::subscriber::set_default(subscriber);
+        // the supergraph span of the plugin is a child of the request span, in the same trace
+        let request_span = tracing::info_span!("request");
+        let trace_id = request_span
+            .context()
+            .span()
+            .span_context()
+            .trace_id()
+            .to_string();
+        let service = supergraph_service.ready().await.unwrap();
+        let response = request_span.in_scope(|| {
+            service.call(
+                SupergraphRequest::fake_builder()
+                    .query("query { ok }")
+                    .build()
+                    .unwrap(),
+            )
+        });
+        response.await.unwrap().next_response().await.unwrap();
+
+        let web_endpoint = dyn_plugin
+            .web_endpoints()
+            .into_iter()
+            .next()
+            .unwrap()
+            .1
+            .into_iter()
+            .next()
+            .unwrap()
+            .into_router();
+        // exemplars are only sent to the scrapers accepting the OpenMetrics format
+        let mut resp = web_endpoint
+            .clone()
+            .oneshot(
+                http::Request::get("http://localhost:9090/metrics")
+                    .body(Default::default())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let body = hyper::body::to_bytes(resp.body_mut()).await.unwrap();
+        assert!(!String::from_utf8_lossy(&body).contains("trace_id"));
+
+        let mut resp = web_endpoint
+            .oneshot(
+                http::Request::get("http://localhost:9090/metrics")
+                    .header(
+                        http::header::ACCEPT,
+                        "application/openmetrics-text; version=1.0.0",
+                    )
+                    .body(Default::default())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        assert!(resp
+            .headers()
+            .get(http::header::CONTENT_TYPE)
+            .unwrap()
+            .to_str()
+            .unwrap()
+            .starts_with("application/openmetrics-text"));
+        let body = hyper::body::to_bytes(resp.body_mut()).await.unwrap();
+        let prom_metrics = String::from_utf8_lossy(&body);
+        let exemplars: Vec<&str> = prom_metrics
+            .lines()
+            .filter(|line| line.contains(" # {trace_id="))
+            .collect();
+        assert_eq!(exemplars.len(), 1, "{}", prom_metrics);
+        assert!(exemplars[0].starts_with("apollo_router_http_request_duration_seconds_bucket{"));
+        assert!(exemplars[0].contains(&format!("# {{trace_id=\"{}\"}}", trace_id)));
+        assert!(prom_metrics.ends_with("# EOF\n"));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn it_splits_request_metrics_by_operation_kind() {
+        let mut mock_service = MockSupergraphService::new();
+        mock_service
+            .expect_call()
+            .times(2)
+            .returning(move |req: SupergraphRequest| {
+                // the supergraph service sets the kind of the parsed operation
+                let query = req.supergraph_request.body().query.clone().unwrap();
+                let kind = if query.starts_with("mutation") {
+                    OperationKind::Mutation
+                } else {
+                    OperationKind::Query
+                };
+                req.context.insert(OPERATION_KIND, kind).unwrap();
+                Ok(SupergraphResponse::fake_builder()
+                    .context(req.context)
+                    .data(json!({"ok": true}))
+                    .build()
+                    .unwrap())
+            });
+
+        let dyn_plugin: Box<dyn DynPlugin> = crate::plugin::plugins()
+            .get("apollo.telemetry")
+            .expect("Plugin not found")
+            .create_instance(
+                &serde_json::json!({
+                    "apollo": {
+                        "schema_id": "schema_sha"
+                    },
+                    "metrics": {
+                        "common": {
+                            "service_name": "apollo-router"
+                        },
+                        "prometheus": {
+                            "enabled": true
+                        }
+                    }
+                }),
+                Default::default(),
+            )
+            .await
+            .unwrap();
+        let mut supergraph_service = dyn_plugin.supergraph_service(BoxService::new(mock_service));
+        for query in ["query { ok }", "mutation { ok }"] {
+            supergraph_service
+                .ready()
+                .await
+                .unwrap()
+                .call(
+                    SupergraphRequest::fake_builder()
+                        .query(query)
+                        .build()
+                        .unwrap(),
+                )
+                .await
+                .unwrap()
+                .next_response()
+                .await
+                .unwrap();
+        }
+
+        let http_req_prom = http::Request::get("http://localhost:9090/metrics")
+            .body(Default::default())
+            .unwrap();
+        let web_endpoint = dyn_plugin
+            .web_endpoints()
+            .into_iter()
+            .next()
+            .unwrap()
+            .1
+            .into_iter()
+            .next()
+            .unwrap()
+            .into_router();
+        let mut resp = web_endpoint.oneshot(http_req_prom).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(resp.body_mut()).await.unwrap();
+        let prom_metrics = String::from_utf8_lossy(&body);
+        assert!(prom_metrics.contains(r#"apollo_router_http_requests_total{operation_kind="query",service_name="apollo-router",status="200"} 1"#));
+        assert!(prom_metrics.contains(r#"apollo_router_http_requests_total{operation_kind="mutation",service_name="apollo-router",status="200"} 1"#));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn it_adds_static_attributes_to_all_metrics() {
+        let mut mock_service = MockSupergraphService::new();
+        mock_service
+            .expect_call()
+            .times(1)
+            .returning(move |req: SupergraphRequest| {
+                Ok(SupergraphResponse::fake_builder()
+                    .context(req.context)
+                    .data(json!({"topProducts": [{"name": "Table"}]}))
+                    .build()
+                    .unwrap())
+            });
+
+        let dyn_plugin: Box<dyn DynPlugin> = crate::plugin::plugins()
+            .get("apollo.telemetry")
+            .expect("Plugin not found")
+            .create_instance(
+                &serde_json::json!({
+                    "apollo": {
+                        "schema_id": "schema_sha"
+                    },
+                    "metrics": {
+                        "common": {
+                            "service_name": "apollo-router",
+                            "static_attributes": {
+                                "region": "eu-west-1"
+                            }
+                        },
+                        "prometheus": {
+                            "enabled": true
+                        }
+                    }
+                }),
+                Default::default(),
+            )
+            .await
+            .unwrap();
+        let mut supergraph_service = dyn_plugin.supergraph_service(BoxService::new(mock_service));
+        let _router_response = supergraph_service
+            .ready()
+            .await
+            .unwrap()
+            .call(SupergraphRequest::fake_builder().build().unwrap())
+            .await
+            .unwrap()
+            .next_response()
+            .await
+            .unwrap();
+
+        let http_req_prom = http::Request::get("http://localhost:9090/metrics")
+            .body(Default::default())
+            .unwrap();
+        let web_endpoint = dyn_plugin
+            .web_endpoints()
+            .into_iter()
+            .next()
+            .unwrap()
+            .1
+            .into_iter()
+            .next()
+            .unwrap()
+            .into_router();
+        let mut resp = web_endpoint.oneshot(http_req_prom).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(resp.body_mut()).await.unwrap();
+        let prom_metrics = String::from_utf8_lossy(&body);
+        let http_requests_total = prom_metrics
+            .lines()
+            .find(|line| line.starts_with("apollo_router_http_requests_total{"))
+            .expect("apollo_router_http_requests_total should be recorded");
+        assert!(http_requests_total.contains(r#"region="eu-west-1""#));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn it_inherits_router_attributes_in_subgraph_metrics() {
+        let mut mock_service = MockSupergraphService::new();
+        mock_service
+            .expect_call()
+            .times(1)
+            .returning(move |req: SupergraphRequest| {
+                Ok(SupergraphResponse::fake_builder()
+                    .context(req.context)
+                    .build()
+                    .unwrap())
+            });
+        let mut mock_subgraph_service = MockSubgraphService::new();
+        mock_subgraph_service
+            .expect_call()
+            .times(1)
+            .returning(move |req: SubgraphRequest| {
+                Ok(SubgraphResponse::fake_builder()
+                    .context(req.context)
+                    .build())
+            });
+
+        let dyn_plugin: Box<dyn DynPlugin> = crate::plugin::plugins()
+            .get("apollo.telemetry")
+            .expect("Plugin not found")
+            .create_instance(
+                &serde_json::json!({
+                    "apollo": {
+                        "schema_id": "schema_sha"
+                    },
+                    "metrics": {
+                        "common": {
+                            "service_name": "apollo-router",
+                            "attributes": {
+                                "router": {
+                                    "request": {
+                                        "header": [{
+                                            "named": "x-client",
+                                            "rename": "client"
+                                        }, {
+                                            "named": "x-version",
+                                            "rename": "version"
+                                        }]
+                                    }
+                                },
+                                "inherit_router_attributes": ["client"]
+                            }
+                        },
+                        "prometheus": {
+                            "enabled": true
+                        }
+                    }
+                }),
+                Default::default(),
+            )
+            .await
+            .unwrap();
+        let mut supergraph_service = dyn_plugin.supergraph_service(BoxService::new(mock_service));
+        let router_response = supergraph_service
+            .ready()
+            .await
+            .unwrap()
+            .call(
+                SupergraphRequest::fake_builder()
+                    .header("x-client", "web")
+                    .header("x-version", "1.0")
+                    .build()
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+
+        // the subgraph request shares the context of the router request
+        let mut subgraph_service =
+            dyn_plugin.subgraph_service("products", BoxService::new(mock_subgraph_service));
+        let _subgraph_response = subgraph_service
+            .ready()
+            .await
+            .unwrap()
+            .call(
+                SubgraphRequest::fake_builder()
+                    .context(router_response.context.clone())
+                    .build(),
+            )
+            .await
+            .unwrap();
+
+        let http_req_prom = http::Request::get("http://localhost:9090/metrics")
+            .body(Default::default())
+            .unwrap();
+        let web_endpoint = dyn_plugin
+            .web_endpoints()
+            .into_iter()
+            .next()
+            .unwrap()
+            .1
+            .into_iter()
+            .next()
+            .unwrap()
+            .into_router();
+        let mut resp = web_endpoint.oneshot(http_req_prom).await.unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+        let body = hyper::body::to_bytes(resp.body_mut()).await.unwrap();
+        let prom_metrics = String::from_utf8_lossy(&body);
+        let subgraph_requests_total = prom_metrics
+            .lines()
+            .find(|line| {
+                line.starts_with("apollo_router_http_requests_total{")
+                    && line.contains(r#"subgraph="products""#)
+            })
+            .expect("the subgraph requests should be recorded");
+        assert!(subgraph_requests_total.contains(r#"client="web""#));
+        assert!(!subgraph_requests_total.contains("version"));
+    }
+
+    #[tokio::test]
+    async fn it_records_the_query_plan_summary() {
+        use std::sync::Arc;
+        use std::sync::Mutex;
+
+        use tracing_subscriber::layer::Context;
+        use tracing_subscriber::prelude::*;
+        use tracing_subscriber::Layer;
+        use tracing_subscriber::Registry;
+
+        use crate::plugin::test::MockExecutionService;
+        use crate::query_planner::PlanNode;
+        use crate::query_planner::QueryPlan;
+        use crate::ExecutionRequest;
+        use crate::ExecutionResponse;
+
+        // records the attributes of the new spans, by span name
+        #[derive(Clone, Default)]
+        struct SpanAttributes(Arc<Mutex<Vec<(String, String, String)>>>);
+
+        struct Visitor<'a>(&'static str, &'a Mutex<Vec<(String, String, String)>>);
+
+        impl ::tracing::field::Visit for Visitor<'_> {
+            fn record_debug(
+                &mut self,
+                field: &::tracing::field::Field,
+                value: &dyn std::fmt::Debug,
+            ) {
+                self.1.lock().unwrap().push((
+                    self.0.to_string(),
+                    field.name().to_string(),
+                    format!("{:?}", value),
+                ));
+            }
+        }
+
+        impl<S: ::tracing::Subscriber> Layer<S> for SpanAttributes {
+            fn on_new_span(
+                &self,
+                attrs: &::tracing::span::Attributes<'_>,
+                _id: &::tracing::span::Id,
+                _ctx: Context<'_, S>,
+            ) {
+                attrs.record(&mut Visitor(attrs.metadata().name(), &self.0));
+            }
+        }
+
+        let plan: PlanNode = serde_json::from_value(serde_json::json!({
+            "kind": "Sequence",
+            "nodes": [
+                {
+                    "kind": "Fetch",
+                    "serviceName": "products",
+                    "variableUsages": [],
+                    "operation": "{topProducts{__typename upc}}",
+                    "operationKind": "query"
+                },
+                {
+                    "kind": "Flatten",
+                    "path": ["topProducts", "@"],
+                    "node": {
+                        "kind": "Fetch",
+                        "serviceName": "reviews",
+                        "requires": [],
+                        "variableUsages": [],
+                        "operation": "query($representations:[_Any!]!){_entities(representations:$representations){...on Product{reviews{body}}}}",
+                        "operationKind": "query"
+                    }
+                }
+            ]
+        }))
+        .unwrap();
+
+        let mut mock_service = MockExecutionService::new();
+        mock_service
+            .expect_call()
+            .times(1)
+            .returning(move |req: ExecutionRequest| {
+                Ok(ExecutionResponse::fake_builder()
+                    .context(req.context)
+                    .build()
+                    .unwrap())
+            });
+
+        let dyn_plugin: Box<dyn DynPlugin> = crate::plugin::plugins()
+            .get("apollo.telemetry")
+            .expect("Plugin not found")
+            .create_instance(
+                &serde_json::json!({"apollo": {"schema_id":"abc"}, "tracing": {}}),
+                Default::default(),
+            )
+            .await
+            .unwrap();
+
+        let attributes = SpanAttributes::default();
+        let _guard =
+            ::tracing::subscriber::set_default(Registry::default().with(attributes.clone()));
+        let request = ExecutionRequest::fake_builder()
+            .query_plan(QueryPlan::fake_builder().root(plan).build())
+            .build();
+        let context = request.context.clone();
+        dyn_plugin
+            .execution_service(BoxService::new(mock_service))
+            .oneshot(request)
+            .await
+            .unwrap();
+
+        let expected = "fetch=2 sequence=1 parallel=0 flatten=1 defer=0 condition=0 depth=3 subgraphs=products,reviews";
+        assert!(attributes.0.lock().unwrap().contains(&(
+            "execution".to_string(),
+            "query_plan.summary".to_string(),
+            format!("{:?}", expected)
+        )));
+        assert_eq!(
+            context
+                .get::<_, String>(super::QUERY_PLAN_SUMMARY)
+                .unwrap()
+                .as_deref(),
+            Some(expected)
+        );
+    }
+
+    #[tokio::test]
+    async fn it_adds_the_query_plan_summary_extension() {
+        for enabled in [true, false] {
+            let mut mock_service = MockSupergraphService::new();
+            mock_service
+                .expect_call()
+                .times(1)
+                .returning(move |req: SupergraphRequest| {
+                    req.context
+                        .insert(super::QUERY_PLAN_SUMMARY, "fetch=1".to_string())
+                        .unwrap();
+                    Ok(SupergraphResponse::fake_builder()
+                        .context(req.context)
+                        .data(json!({"topProducts": []}))
+                        .build()
+                        .unwrap())
+                });
+
+            let dyn_plugin: Box<dyn DynPlugin> = crate::plugin::plugins()
+                .get("apollo.telemetry")
+                .expect("Plugin not found")
+                .create_instance(
+                    &serde_json::json!({
+                        "apollo": {"schema_id": "abc"},
+                        "tracing": {"query_plan_summary_extension": enabled}
+                    }),
+                    Default::default(),
+                )
+                .await
+                .unwrap();
+            let response = dyn_plugin
+                .supergraph_service(BoxService::new(mock_service))
+                .oneshot(SupergraphRequest::fake_builder().build().unwrap())
+                .await
+                .unwrap()
+                .next_response()
+                .await
+                .unwrap();
+
+            assert_eq!(
+                response.extensions.get("queryPlanSummary"),
+                enabled
+                    .then(|| serde_json_bytes::Value::from("fetch=1"))
+                    .as_ref()
+            );
+        }
+    }
+
+    #[cfg(not(feature = "console"))]
+    #[test]
+    fn it_reloads_log_filter() {
+        use std::sync::atomic::AtomicUsize;
+        use std::sync::atomic::Ordering;
+        use std::sync::Arc;
+
+        use tracing_subscriber::layer::Context;
+        use tracing_subscriber::prelude::*;
+        use tracing_subscriber::reload;
+        use tracing_subscriber::EnvFilter;
+        use tracing_subscriber::Layer;
+        use tracing_subscriber::Registry;
+
+        struct CountEvents(Arc<AtomicUsize>);
+
+        impl<S: ::tracing::Subscriber> Layer<S> for CountEvents {
+            fn on_event(&self, _event: &::tracing::Event<'_>, _ctx: Context<'_, S>) {
+                self.0.fetch_add(1, Ordering::SeqCst);
+            }
+        }
+
+        let events = Arc::new(AtomicUsize::new(0));
+        let (filter, handle) = reload::Layer::new(EnvFilter::try_new("info").unwrap());
+        let subscriber = Registry::default()
+            .with(filter)
+            .with(CountEvents(events.clone()));
+
+        ::tracing::subscriber::with_default(subscriber, || {
+            ::tracing::debug!("filtered out");
+            assert_eq!(events.load(Ordering::SeqCst), 0);
+
+            super::reload_env_filter(&handle, "debug").unwrap();
+            ::tracing::debug!("not filtered out anymore");
+            assert_eq!(events.load(Ordering::SeqCst), 1);
+
+            // an invalid filter is rejected and the previous one is kept
+            assert!(super::reload_env_filter(&handle, "apollo_router=unknown").is_err());
+            ::tracing::debug!("still not filtered out");
+            assert_eq!(events.load(Ordering::SeqCst), 2);
+
+            super::reload_env_filter(&handle, "warn").unwrap();
+            ::tracing::info!("filtered out again");
+            assert_eq!(events.load(Ordering::SeqCst), 2);
+        });
+    }
+
+    #[test]
+    fn incoming_propagation_formats() {
+        use opentelemetry::propagation::TextMapPropagator;
+        use opentelemetry::trace::TraceContextExt;
+
+        const TRACEPARENT: &str = "00-0af7651916cd43dd8448eb211c80319c-b7ad6b7169203331-01";
+        let mut headers = http::HeaderMap::new();
+        headers.insert("traceparent", http::HeaderValue::from_static(TRACEPARENT));
+
+        let incoming_propagator = |propagation: Value| {
+            let config: super::config::Conf = serde_json::from_value(
+                serde_json::json!({ "tracing": { "propagation": propagation } }),
+            )
+            .unwrap();
+            super::Telemetry::create_incoming_propagator(&config)
+        };
+        let extracted_trace_id = |propagator: super::TextMapCompositePropagator| {
+            let context = propagator.extract(&opentelemetry_http::HeaderExtractor(&headers));
+            let span_context = context.span().span_context().clone();
+            span_context
+                .is_valid()
+                .then(|| span_context.trace_id().to_string())
+        };
+
+        // without incoming formats, the propagated formats are accepted
+        assert!(incoming_propagator(serde_json::json!({ "trace_context": true })).is_none());
+
+        let propagator =
+            incoming_propagator(serde_json::json!({ "incoming": ["zipkin", "trace_context"] }))
+                .unwrap();
+        assert_eq!(
+            extracted_trace_id(propagator).as_deref(),
+            Some("0af7651916cd43dd8448eb211c80319c")
+        );
+
+        let propagator = incoming_propagator(
+            serde_json::json!({ "trace_context": true, "incoming": ["datadog"] }),
+        )
+        .unwrap();
+        assert_eq!(extracted_trace_id(propagator), None);
+    }
+
+    #[test]
+    fn it_samples_whole_traces() {
+        use std::collections::HashMap;
+        use std::sync::Arc;
+        use std::sync::Mutex;
+
+        use opentelemetry::sdk::export::trace::ExportResult;
+        use opentelemetry::sdk::export::trace::SpanData;
+        use opentelemetry::sdk::export::trace::SpanExporter;
+        use opentelemetry::trace::TraceId;
+        use opentelemetry::trace::TracerProvider;
+        use tracing_subscriber::prelude::*;
+        use tracing_subscriber::Registry;
+
+        use super::config::SamplerOption;
+        use super::config::Trace;
+        use super::metrics::AggregateMeterProvider;
+
+        #[derive(Debug, Default, Clone)]
+        struct RecordingExporter(Arc<Mutex<Vec<TraceId>>>);
+
+        #[async_trait::async_trait]
+        impl SpanExporter for RecordingExporter {
+            async fn export(&mut self, batch: Vec<SpanData>) -> ExportResult {
+                self.0
+                    .lock()
+                    .unwrap()
+                    .extend(batch.iter().map(|span| span.span_context.trace_id()));
+                Ok(())
+            }
+        }
+
+        // records 100 traces of two spans with the given sampler, and returns the number of
+        // exported spans by trace and the number of recorded requests
+        let record_traces = |sampler: f64| {
+            let exporter = RecordingExporter::default();
+            let exported = exporter.0.clone();
+            let trace_config = Trace {
+                sampler: Some(SamplerOption::TraceIdRatioBased(sampler)),
+                ..Default::default()
+            };
+            let tracer_provider = opentelemetry::sdk::trace::TracerProvider::builder()
+                .with_config((&trace_config).into())
+                .with_simple_exporter(exporter)
+                .build();
+            let subscriber = Registry::default()
+                .with(tracing_opentelemetry::layer().with_tracer(tracer_provider.tracer("test")));
+
+            let prometheus = opentelemetry_prometheus::exporter().init();
+            let meter_provider = AggregateMeterProvider::new(vec![Arc::new(
+                prometheus.provider().expect("meter provider"),
+            )]);
+            let requests = meter_provider
+                .meter("test", None)
+                .build_counter(|m| m.u64_counter("test_requests").init());
+
+            ::tracing::subscriber::with_default(subscriber, || {
+                for _ in 0..100 {
+                    let _request = ::tracing::info_span!("request").entered();
+                    let _supergraph = ::tracing::info_span!("supergraph").entered();
+                    requests.add(1, &[]);
+                }
+            });
+            // shutting down the provider waits for the spans to be exported
+            drop(tracer_provider);
+
+            let mut spans_by_trace = HashMap::new();
+            for trace_id in exported.lock().unwrap().iter() {
+                *spans_by_trace.entry(*trace_id).or_insert(0) += 1;
+            }
+            let recorded: f64 = prometheus
+                .registry()
+                .gather()
+                .iter()
+                .filter(|family| family.get_name().starts_with("test_requests"))
+                .flat_map(|family| family.get_metric())
+                .map(|metric| metric.get_counter().get_value())
+                .sum();
+            (spans_by_trace, recorded)
+        };
+
+        let (spans_by_trace, recorded) = record_traces(0.0);
+        assert!(spans_by_trace.is_empty());
+        // metrics do not depend on the sampling of traces
+        assert_eq!(recorded, 100.0);
+
+        let (spans_by_trace, recorded) = record_traces(1.0);
+        assert_eq!(spans_by_trace.len(), 100);
+        assert_eq!(recorded, 100.0);
+
+        // a trace is either exported with all its spans or not at all
+        let (spans_by_trace, _) = record_traces(0.5);
+        assert!(!spans_by_trace.is_empty() && spans_by_trace.len() < 100);
+        assert!(spans_by_trace.values().all(|spans| *spans == 2));
+    }
 }
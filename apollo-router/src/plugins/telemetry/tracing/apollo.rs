@@ -21,6 +21,8 @@ impl TracingConfigurator for Config {
                 schema_id,
                 buffer_size,
                 field_level_instrumentation_sampler,
+                field_level_instrumentation_exclude,
+                report_flush,
                 ..
             } => {
                 tracing::debug!("configuring exporter to Studio");
@@ -33,6 +35,8 @@ impl TracingConfigurator for Config {
                     .schema_id(schema_id)
                     .buffer_size(*buffer_size)
                     .and_field_execution_sampler(field_level_instrumentation_sampler.clone())
+                    .excluded_fields(field_level_instrumentation_exclude.clone())
+                    .report_flush(report_flush.clone())
                     .build()?;
                 builder.with_batch_exporter(exporter, opentelemetry::runtime::Tokio)
             }
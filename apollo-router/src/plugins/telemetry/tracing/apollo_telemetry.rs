@@ -17,6 +17,7 @@ use thiserror::Error;
 use url::Url;
 
 use crate::axum_factory::utils::REQUEST_SPAN_NAME;
+use crate::plugins::telemetry::apollo::ReportFlush;
 use crate::plugins::telemetry::apollo::SingleReport;
 use crate::plugins::telemetry::apollo_exporter::ApolloExporter;
 use crate::plugins::telemetry::apollo_exporter::Sender;
@@ -32,6 +33,7 @@ use crate::query_planner::FLATTEN_SPAN_NAME;
 use crate::query_planner::PARALLEL_SPAN_NAME;
 use crate::query_planner::SEQUENCE_SPAN_NAME;
 use crate::spaceport::trace::http::Values;
+use crate::spaceport::trace::node::Id;
 use crate::spaceport::trace::query_plan_node::FetchNode;
 use crate::spaceport::trace::query_plan_node::FlattenNode;
 use crate::spaceport::trace::query_plan_node::ParallelNode;
@@ -39,6 +41,7 @@ use crate::spaceport::trace::query_plan_node::ResponsePathElement;
 use crate::spaceport::trace::query_plan_node::SequenceNode;
 use crate::spaceport::trace::Details;
 use crate::spaceport::trace::Http;
+use crate::spaceport::trace::Node;
 use crate::spaceport::trace::QueryPlanNode;
 use crate::spaceport::Message;
 
@@ -93,6 +96,7 @@ pub(crate) struct Exporter {
     #[derivative(Debug = "ignore")]
     apollo_sender: Sender,
     field_execution_weight: f64,
+    excluded_fields: Vec<String>,
 }
 
 enum TreeData {
@@ -118,10 +122,17 @@ impl Exporter {
         schema_id: String,
         buffer_size: usize,
         field_execution_sampler: Option<SamplerOption>,
+        excluded_fields: Vec<String>,
+        report_flush: ReportFlush,
     ) -> Result<Self, BoxError> {
         tracing::debug!("creating studio exporter");
-        let apollo_exporter =
-            ApolloExporter::new(&endpoint, &apollo_key, &apollo_graph_ref, &schema_id)?;
+        let apollo_exporter = ApolloExporter::new(
+            &endpoint,
+            &apollo_key,
+            &apollo_graph_ref,
+            &schema_id,
+            &report_flush,
+        )?;
         Ok(Self {
             spans_by_parent_id: LruCache::new(buffer_size),
             trace_config,
@@ -134,6 +145,7 @@ impl Exporter {
                 Some(SamplerOption::TraceIdRatioBased(ratio)) => 1.0 / ratio,
                 None => 0.0,
             },
+            excluded_fields,
         })
     }
 
@@ -387,9 +399,13 @@ impl Exporter {
             .get(&APOLLO_PRIVATE_FTV1)
             .map(|data| {
                 if let Value::String(data) = data {
-                    Ok(Box::new(crate::spaceport::Trace::decode(Cursor::new(
-                        base64::decode(data.to_string())?,
-                    ))?))
+                    let mut trace = crate::spaceport::Trace::decode(Cursor::new(base64::decode(
+                        data.to_string(),
+                    )?))?;
+                    if let Some(root) = trace.root.as_mut() {
+                        exclude_fields(root, &self.excluded_fields);
+                    }
+                    Ok(Box::new(trace))
                 } else {
                     Err(Error::Ftv1SpanAttribute)
                 }
@@ -444,6 +460,41 @@ impl Exporter {
     }
 }
 
+/// Removes the nodes of the excluded types and fields, named `Type` or `Type.field`, from the
+/// trace tree of a subgraph. The nodes below them that are not excluded are kept, attached to
+/// the parent of the excluded node
+fn exclude_fields(node: &mut Node, excluded: &[String]) {
+    if excluded.is_empty() {
+        return;
+    }
+    let mut children = Vec::with_capacity(node.child.len());
+    for mut child in std::mem::take(&mut node.child) {
+        exclude_fields(&mut child, excluded);
+        if is_excluded(&child, excluded) {
+            children.append(&mut child.child);
+        } else {
+            children.push(child);
+        }
+    }
+    node.child = children;
+}
+
+fn is_excluded(node: &Node, excluded: &[String]) -> bool {
+    // the nodes of list elements have no parent type
+    if node.parent_type.is_empty() {
+        return false;
+    }
+    let field_name = match &node.id {
+        _ if !node.original_field_name.is_empty() => node.original_field_name.as_str(),
+        Some(Id::ResponseName(response_name)) => response_name.as_str(),
+        _ => return false,
+    };
+    excluded.iter().any(|excluded| {
+        *excluded == node.parent_type
+            || excluded.split_once('.') == Some((node.parent_type.as_str(), field_name))
+    })
+}
+
 #[async_trait]
 impl SpanExporter for Exporter {
     /// Export spans to apollo telemetry
@@ -500,3 +551,72 @@ impl SpanExporter for Exporter {
         return ExportResult::Ok(());
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::exclude_fields;
+    use crate::spaceport::trace::node::Id;
+    use crate::spaceport::trace::Node;
+
+    fn field(parent_type: &str, name: &str, child: Vec<Node>) -> Node {
+        Node {
+            id: Some(Id::ResponseName(name.to_string())),
+            parent_type: parent_type.to_string(),
+            child,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_field_exclusion() {
+        let mut root = Node {
+            child: vec![
+                field(
+                    "Query",
+                    "me",
+                    vec![
+                        field("User", "name", vec![]),
+                        field("User", "email", vec![]),
+                    ],
+                ),
+                field(
+                    "Query",
+                    "secrets",
+                    vec![
+                        field("Secret", "value", vec![]),
+                        field("Secret", "owner", vec![field("User", "email", vec![])]),
+                    ],
+                ),
+                field(
+                    "Query",
+                    "topProducts",
+                    vec![field("Product", "upc", vec![])],
+                ),
+            ],
+            ..Default::default()
+        };
+        exclude_fields(
+            &mut root,
+            &[
+                "User.email".to_string(),
+                "Query.secrets".to_string(),
+                "Product".to_string(),
+            ],
+        );
+
+        let names = |node: &Node| {
+            node.child
+                .iter()
+                .map(|child| match &child.id {
+                    Some(Id::ResponseName(name)) => name.clone(),
+                    _ => String::new(),
+                })
+                .collect::<Vec<_>>()
+        };
+        // the fields below an excluded field are attached to its parent
+        assert_eq!(names(&root), vec!["me", "value", "owner", "topProducts"]);
+        assert_eq!(names(&root.child[0]), vec!["name"]);
+        assert!(root.child[2].child.is_empty());
+        assert!(root.child[3].child.is_empty());
+    }
+}
@@ -18,6 +18,7 @@ use tower::ServiceExt;
 use crate::graphql::Request;
 use crate::http_ext;
 use crate::query_planner::fetch::OperationKind;
+use crate::services::batching::BatchQuery;
 use crate::SubgraphRequest;
 use crate::SubgraphResponse;
 
@@ -75,6 +76,15 @@ where
             let mut locked_wait_map = wait_map.lock().await;
             match locked_wait_map.get_mut(&(&request.subgraph_request).into()) {
                 Some(waiter) => {
+                    // the request will not be sent, its batch must not wait for it
+                    if let Some(batch) = request
+                        .subgraph_request
+                        .extensions()
+                        .get::<Arc<BatchQuery>>()
+                    {
+                        batch.leave();
+                    }
+
                     // Register interest in key
                     let mut receiver = waiter.subscribe();
                     drop(locked_wait_map);
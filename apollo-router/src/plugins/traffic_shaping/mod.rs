@@ -5,6 +5,8 @@
 //! * Timeout
 //! * Compression
 //! * Rate limiting
+//! * Retry-After handling
+//! * Concurrency limits
 //!
 
 mod deduplication;
@@ -13,6 +15,7 @@ mod timeout;
 
 use std::collections::HashMap;
 use std::num::NonZeroU64;
+use std::num::NonZeroUsize;
 use std::pin::Pin;
 use std::sync::Mutex;
 use std::time::Duration;
@@ -40,18 +43,20 @@ use crate::plugin::PluginInit;
 use crate::register_plugin;
 use crate::services::subgraph;
 use crate::services::subgraph_service::Compression;
+use crate::services::subgraph_service::RetryAfter;
 use crate::services::supergraph;
 use crate::Configuration;
 use crate::SubgraphRequest;
 
 const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_RETRY_AFTER_MAX_DELAY: Duration = Duration::from_secs(5);
 pub(crate) const APOLLO_TRAFFIC_SHAPING: &str = "apollo.traffic_shaping";
 
 trait Merge {
     fn merge(&self, fallback: Option<&Self>) -> Self;
 }
 
-#[derive(PartialEq, Debug, Clone, Deserialize, JsonSchema)]
+#[derive(PartialEq, Debug, Clone, Default, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 struct Shaping {
     /// Enable query deduplication
@@ -62,8 +67,18 @@ struct Shaping {
     global_rate_limit: Option<RateLimitConf>,
     #[serde(deserialize_with = "humantime_serde::deserialize", default)]
     #[schemars(with = "String", default)]
-    /// Enable timeout for incoming requests
+    /// Enable timeout for incoming requests (deprecated: use `timeouts.subgraph` or `timeouts.subgraphs`)
     timeout: Option<Duration>,
+    /// Follow the HTTP redirects returned by subgraphs (default: false)
+    follow_redirects: Option<bool>,
+    /// Retry the requests rejected with a 429 status, after the delay of their Retry-After header
+    retry_after: Option<RetryAfterConf>,
+    /// Maximum number of concurrent requests to each subgraph, further requests wait for a slot (default: unbounded)
+    concurrency_limit: Option<NonZeroUsize>,
+    /// Send the fetches of a query plan level to this subgraph in a single HTTP request with an array body, for subgraphs supporting batching (default: false)
+    batching: Option<bool>,
+    /// Send the header names of the HTTP/1.1 requests to this subgraph in title case, like `X-User-Id`, for subgraphs sensitive to their casing. HTTP/2 header names are always lowercase (default: false)
+    title_case_headers: Option<bool>,
 }
 
 impl Merge for Shaping {
@@ -74,6 +89,15 @@ impl Merge for Shaping {
                 deduplicate_query: self.deduplicate_query.or(fallback.deduplicate_query),
                 compression: self.compression.or(fallback.compression),
                 timeout: self.timeout.or(fallback.timeout),
+                follow_redirects: self.follow_redirects.or(fallback.follow_redirects),
+                concurrency_limit: self.concurrency_limit.or(fallback.concurrency_limit),
+                batching: self.batching.or(fallback.batching),
+                title_case_headers: self.title_case_headers.or(fallback.title_case_headers),
+                retry_after: self
+                    .retry_after
+                    .as_ref()
+                    .or(fallback.retry_after.as_ref())
+                    .cloned(),
                 global_rate_limit: self
                     .global_rate_limit
                     .as_ref()
@@ -91,10 +115,27 @@ struct RouterShaping {
     global_rate_limit: Option<RateLimitConf>,
     #[serde(deserialize_with = "humantime_serde::deserialize", default)]
     #[schemars(with = "String", default)]
-    /// Enable timeout for incoming requests
+    /// Enable timeout for incoming requests (deprecated: use `timeouts.request`)
     timeout: Option<Duration>,
 }
 
+#[derive(PartialEq, Debug, Clone, Default, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct Timeouts {
+    #[serde(deserialize_with = "humantime_serde::deserialize", default)]
+    #[schemars(with = "String", default)]
+    /// Timeout of the requests to the router (default: 30s)
+    request: Option<Duration>,
+    #[serde(deserialize_with = "humantime_serde::deserialize", default)]
+    #[schemars(with = "String", default)]
+    /// Timeout of the requests to all the subgraphs (default: 30s)
+    subgraph: Option<Duration>,
+    #[serde(default)]
+    #[schemars(with = "HashMap<String, String>")]
+    /// Timeouts of the requests to specific subgraphs, overriding `subgraph`
+    subgraphs: HashMap<String, humantime_serde::Serde<Duration>>,
+}
+
 #[derive(PartialEq, Debug, Clone, Deserialize, JsonSchema)]
 #[serde(deny_unknown_fields)]
 
@@ -110,6 +151,9 @@ pub(crate) struct Config {
     #[serde(default)]
     /// Applied on specific subgraphs
     subgraphs: HashMap<String, Shaping>,
+    #[serde(default)]
+    /// Timeouts of the router and subgraph requests
+    timeouts: Timeouts,
     /// Enable variable deduplication optimization when sending requests to subgraphs (https://github.com/apollographql/router/issues/87)
     deduplicate_variables: Option<bool>,
 }
@@ -125,6 +169,22 @@ struct RateLimitConf {
     interval: Duration,
 }
 
+#[derive(PartialEq, Debug, Clone, Deserialize, JsonSchema)]
+#[serde(deny_unknown_fields)]
+struct RetryAfterConf {
+    /// Maximum number of retries of a request (default: 1)
+    #[serde(default = "default_retry_after_max_retries")]
+    max_retries: usize,
+    #[serde(deserialize_with = "humantime_serde::deserialize", default)]
+    #[schemars(with = "String", default)]
+    /// Maximum delay accepted from a Retry-After header, longer delays fail the request (default: 5s)
+    max_delay: Option<Duration>,
+}
+
+fn default_retry_after_max_retries() -> usize {
+    1
+}
+
 impl Merge for RateLimitConf {
     fn merge(&self, fallback: Option<&Self>) -> Self {
         match fallback {
@@ -190,6 +250,75 @@ impl TrafficShaping {
         merged_subgraph_config.or_else(|| all_config.cloned())
     }
 
+    /// Timeout of the requests to the router
+    fn request_timeout(&self) -> Duration {
+        self.config
+            .timeouts
+            .request
+            .or_else(|| self.config.router.as_ref().and_then(|r| r.timeout))
+            .unwrap_or(DEFAULT_TIMEOUT)
+    }
+
+    /// Timeout of the requests to this subgraph, if one is configured
+    ///
+    /// The `timeouts` block takes precedence over the deprecated `timeout` options. In each of
+    /// them, a timeout set for the subgraph takes precedence over the timeout of all the subgraphs.
+    fn subgraph_timeout(&self, name: &str) -> Option<Duration> {
+        let timeouts = &self.config.timeouts;
+        timeouts
+            .subgraphs
+            .get(name)
+            .map(|timeout| timeout.into_inner())
+            .or(timeouts.subgraph)
+            .or_else(|| {
+                self.config
+                    .subgraphs
+                    .get(name)
+                    .and_then(|shaping| shaping.timeout)
+            })
+            .or_else(|| self.config.all.as_ref().and_then(|all| all.timeout))
+    }
+
+    /// Whether the HTTP redirects returned by this subgraph are followed
+    pub(crate) fn follow_redirects(&self, name: &str) -> bool {
+        Self::merge_config(self.config.all.as_ref(), self.config.subgraphs.get(name))
+            .and_then(|config| config.follow_redirects)
+            .unwrap_or_default()
+    }
+
+    /// Maximum number of concurrent requests to this subgraph
+    pub(crate) fn concurrency_limit(&self, name: &str) -> Option<NonZeroUsize> {
+        Self::merge_config(self.config.all.as_ref(), self.config.subgraphs.get(name))
+            .and_then(|config| config.concurrency_limit)
+    }
+
+    /// Whether the fetches of a query plan level to this subgraph are sent in a single request
+    pub(crate) fn batching(&self, name: &str) -> bool {
+        Self::merge_config(self.config.all.as_ref(), self.config.subgraphs.get(name))
+            .and_then(|config| config.batching)
+            .unwrap_or_default()
+    }
+
+    /// Whether the header names of the HTTP/1.1 requests to this subgraph are sent in title case
+    pub(crate) fn title_case_headers(&self, name: &str) -> bool {
+        Self::merge_config(self.config.all.as_ref(), self.config.subgraphs.get(name))
+            .and_then(|config| config.title_case_headers)
+            .unwrap_or_default()
+    }
+
+    /// How the requests rejected by this subgraph with a 429 status are retried
+    pub(crate) fn retry_after(&self, name: &str) -> Option<RetryAfter> {
+        let config = Self::merge_config(self.config.all.as_ref(), self.config.subgraphs.get(name))?;
+        config.retry_after.map(|retry_after| RetryAfter {
+            max_retries: retry_after.max_retries,
+            max_delay: retry_after
+                .max_delay
+                .unwrap_or(DEFAULT_RETRY_AFTER_MAX_DELAY),
+            // waiting past the subgraph timeout is pointless
+            budget: self.subgraph_timeout(name).unwrap_or(DEFAULT_TIMEOUT),
+        })
+    }
+
     pub(crate) fn supergraph_service_internal<S>(
         &self,
         service: S,
@@ -213,13 +342,7 @@ impl TrafficShaping {
         <S as Service<supergraph::Request>>::Future: std::marker::Send,
     {
         ServiceBuilder::new()
-            .layer(TimeoutLayer::new(
-                self.config
-                    .router
-                    .as_ref()
-                    .and_then(|r| r.timeout)
-                    .unwrap_or(DEFAULT_TIMEOUT),
-            ))
+            .layer(TimeoutLayer::new(self.request_timeout()))
             .option_layer(self.rate_limit_router.clone())
             .service(service)
     }
@@ -271,7 +394,9 @@ impl TrafficShaping {
         // Either we have the subgraph config and we merge it with the all config, or we just have the all config or we have nothing.
         let all_config = self.config.all.as_ref();
         let subgraph_config = self.config.subgraphs.get(name);
-        let final_config = Self::merge_config(all_config, subgraph_config);
+        let final_config = Self::merge_config(all_config, subgraph_config)
+            // a timeout is enough to apply the layers, without other options for the subgraph
+            .or_else(|| self.subgraph_timeout(name).map(|_| Shaping::default()));
 
         if let Some(config) = final_config {
             let rate_limit = config.global_rate_limit.as_ref().map(|rate_limit_conf| {
@@ -289,8 +414,7 @@ impl TrafficShaping {
               QueryDeduplicationLayer::default
             ))
                 .layer(TimeoutLayer::new(
-                    config
-                    .timeout
+                    self.subgraph_timeout(name)
                     .unwrap_or(DEFAULT_TIMEOUT),
                 ))
                 .option_layer(rate_limit)
@@ -341,6 +465,7 @@ mod test {
     use crate::Configuration;
     use crate::PluggableSupergraphServiceBuilder;
     use crate::Schema;
+    use crate::SubgraphResponse;
     use crate::SupergraphRequest;
     use crate::SupergraphResponse;
 
@@ -531,6 +656,159 @@ mod test {
         );
     }
 
+    #[tokio::test]
+    async fn it_configures_retry_after() {
+        let plugin = get_traffic_shaping_plugin(&serde_json::json!({
+            "all": {
+                "retry_after": {}
+            },
+            "subgraphs": {
+                "products": {
+                    "timeout": "2s",
+                    "retry_after": {
+                        "max_retries": 3,
+                        "max_delay": "10s"
+                    }
+                }
+            }
+        }))
+        .await;
+        let shaping = plugin.as_any().downcast_ref::<TrafficShaping>().unwrap();
+
+        assert_eq!(
+            shaping.retry_after("reviews"),
+            Some(RetryAfter {
+                max_retries: 1,
+                max_delay: Duration::from_secs(5),
+                budget: DEFAULT_TIMEOUT,
+            })
+        );
+        assert_eq!(
+            shaping.retry_after("products"),
+            Some(RetryAfter {
+                max_retries: 3,
+                max_delay: Duration::from_secs(10),
+                budget: Duration::from_secs(2),
+            })
+        );
+    }
+
+    #[tokio::test]
+    async fn it_resolves_timeouts_by_precedence() {
+        let plugin = get_traffic_shaping_plugin(&serde_json::json!({
+            "router": { "timeout": "40s" },
+            "all": { "timeout": "20s" },
+            "subgraphs": {
+                "reviews": { "timeout": "15s" },
+                "accounts": { "timeout": "12s" }
+            },
+            "timeouts": {
+                "request": "60s",
+                "subgraph": "10s",
+                "subgraphs": { "products": "5s", "accounts": "3s" }
+            }
+        }))
+        .await;
+        let shaping = plugin.as_any().downcast_ref::<TrafficShaping>().unwrap();
+
+        assert_eq!(shaping.request_timeout(), Duration::from_secs(60));
+        // a subgraph override takes precedence over the global timeout
+        assert_eq!(
+            shaping.subgraph_timeout("products"),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(
+            shaping.subgraph_timeout("accounts"),
+            Some(Duration::from_secs(3))
+        );
+        // the timeouts block takes precedence over the deprecated subgraph timeout
+        assert_eq!(
+            shaping.subgraph_timeout("reviews"),
+            Some(Duration::from_secs(10))
+        );
+        assert_eq!(
+            shaping.subgraph_timeout("inventory"),
+            Some(Duration::from_secs(10))
+        );
+
+        let plugin = get_traffic_shaping_plugin(&serde_json::json!({
+            "timeouts": { "subgraphs": { "products": "5s" } }
+        }))
+        .await;
+        let shaping = plugin.as_any().downcast_ref::<TrafficShaping>().unwrap();
+
+        assert_eq!(shaping.request_timeout(), DEFAULT_TIMEOUT);
+        assert_eq!(
+            shaping.subgraph_timeout("products"),
+            Some(Duration::from_secs(5))
+        );
+        assert_eq!(shaping.subgraph_timeout("inventory"), None);
+    }
+
+    #[tokio::test]
+    async fn it_prefers_the_timeouts_block_to_deprecated_timeouts() {
+        let plugin = get_traffic_shaping_plugin(&serde_json::json!({
+            "router": { "timeout": "40s" },
+            "subgraphs": { "products": { "timeout": "15s" } },
+            "timeouts": { "subgraph": "10s" }
+        }))
+        .await;
+        let shaping = plugin.as_any().downcast_ref::<TrafficShaping>().unwrap();
+
+        assert_eq!(
+            shaping.subgraph_timeout("products"),
+            Some(Duration::from_secs(10))
+        );
+        // the deprecated options still apply to the timeouts the block does not set
+        assert_eq!(shaping.request_timeout(), Duration::from_secs(40));
+
+        let plugin = get_traffic_shaping_plugin(&serde_json::json!({
+            "all": { "timeout": "20s" },
+            "subgraphs": { "products": { "timeout": "15s" } }
+        }))
+        .await;
+        let shaping = plugin.as_any().downcast_ref::<TrafficShaping>().unwrap();
+
+        assert_eq!(
+            shaping.subgraph_timeout("products"),
+            Some(Duration::from_secs(15))
+        );
+        assert_eq!(
+            shaping.subgraph_timeout("reviews"),
+            Some(Duration::from_secs(20))
+        );
+    }
+
+    #[tokio::test]
+    async fn it_applies_subgraph_timeout_overrides() {
+        let plugin = get_traffic_shaping_plugin(&serde_json::json!({
+            "timeouts": {
+                "subgraph": "1s",
+                "subgraphs": { "products": "50ms" }
+            }
+        }))
+        .await;
+        let shaping = plugin.as_any().downcast_ref::<TrafficShaping>().unwrap();
+
+        let slow_service = tower::service_fn(|_request: SubgraphRequest| async {
+            tokio::time::sleep(Duration::from_millis(200)).await;
+            Ok::<_, BoxError>(SubgraphResponse::fake_builder().build())
+        });
+
+        let error = shaping
+            .subgraph_service_internal("products", slow_service.clone())
+            .oneshot(SubgraphRequest::fake_builder().build())
+            .await
+            .expect_err("the products subgraph should time out");
+        assert!(error.is::<Elapsed>());
+
+        shaping
+            .subgraph_service_internal("reviews", slow_service)
+            .oneshot(SubgraphRequest::fake_builder().build())
+            .await
+            .expect("the global timeout is longer than the request");
+    }
+
     #[tokio::test]
     async fn it_rate_limit_subgraph_requests() {
         let config = serde_yaml::from_str::<serde_json::Value>(
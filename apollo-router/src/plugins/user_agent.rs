@@ -0,0 +1,158 @@
+//! Configure the User-Agent header sent to subgraphs.
+
+use std::collections::HashMap;
+
+use http::header::USER_AGENT;
+use http::HeaderValue;
+use schemars::JsonSchema;
+use serde::Deserialize;
+use tower::BoxError;
+use tower::ServiceExt;
+
+use crate::plugin::Plugin;
+use crate::plugin::PluginInit;
+use crate::register_plugin;
+use crate::services::subgraph;
+use crate::SubgraphRequest;
+
+register_plugin!("apollo", "user_agent", UserAgent);
+
+#[derive(Clone, Debug, JsonSchema, Deserialize)]
+#[serde(rename_all = "snake_case", deny_unknown_fields)]
+struct Config {
+    /// User-Agent sent to all subgraphs
+    all: Option<String>,
+    /// User-Agent sent per subgraph, overrides `all`
+    #[serde(default)]
+    subgraphs: HashMap<String, String>,
+}
+
+struct UserAgent {
+    all: Option<HeaderValue>,
+    subgraphs: HashMap<String, HeaderValue>,
+}
+
+fn parse_user_agent(user_agent: &str) -> Result<HeaderValue, BoxError> {
+    HeaderValue::from_str(user_agent)
+        .map_err(|e| format!("invalid user agent '{}': {}", user_agent, e).into())
+}
+
+#[async_trait::async_trait]
+impl Plugin for UserAgent {
+    type Config = Config;
+
+    async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        Ok(UserAgent {
+            all: init
+                .config
+                .all
+                .as_deref()
+                .map(parse_user_agent)
+                .transpose()?,
+            subgraphs: init
+                .config
+                .subgraphs
+                .iter()
+                .map(|(name, user_agent)| Ok((name.clone(), parse_user_agent(user_agent)?)))
+                .collect::<Result<_, BoxError>>()?,
+        })
+    }
+
+    fn subgraph_service(&self, name: &str, service: subgraph::BoxService) -> subgraph::BoxService {
+        // Search for subgraph in our configured subgraph map.
+        // If we can't find it, use the "all" value
+        match self.subgraphs.get(name).or(self.all.as_ref()).cloned() {
+            Some(user_agent) => service
+                .map_request(move |mut req: SubgraphRequest| {
+                    req.subgraph_request
+                        .headers_mut()
+                        .insert(USER_AGENT, user_agent.clone());
+                    req
+                })
+                .boxed(),
+            None => service,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http::header::USER_AGENT;
+
+    use crate::plugin::test;
+    use crate::plugin::test::MockSubgraphService;
+    use crate::SubgraphRequest;
+    use crate::SubgraphResponse;
+
+    async fn call_subgraph(
+        config: serde_json::Value,
+        subgraph: &'static str,
+        expected: Option<&'static str>,
+    ) {
+        let mut mock_service = MockSubgraphService::new();
+        mock_service
+            .expect_call()
+            .withf(move |req| {
+                req.subgraph_request
+                    .headers()
+                    .get(USER_AGENT)
+                    .map(|value| value.to_str().unwrap())
+                    == expected
+            })
+            .times(1)
+            .returning(move |req: SubgraphRequest| {
+                Ok(SubgraphResponse::fake_builder()
+                    .context(req.context)
+                    .build())
+            });
+
+        let plugin = test::create_plugin("apollo.user_agent", &config).await;
+        test::call_subgraph(
+            &*plugin,
+            subgraph,
+            mock_service,
+            SubgraphRequest::fake_builder().build(),
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn it_leaves_user_agent_unset_by_default() {
+        call_subgraph(serde_json::json!({}), "products", None).await;
+    }
+
+    #[tokio::test]
+    async fn it_sets_user_agent_for_all_subgraphs() {
+        call_subgraph(
+            serde_json::json!({ "all": "my-router/1.0" }),
+            "products",
+            Some("my-router/1.0"),
+        )
+        .await;
+    }
+
+    #[tokio::test]
+    async fn it_sets_user_agent_per_subgraph() {
+        let config = serde_json::json!({
+            "all": "my-router/1.0",
+            "subgraphs": {
+                "products": "my-router-products/1.0"
+            }
+        });
+        call_subgraph(config.clone(), "products", Some("my-router-products/1.0")).await;
+        call_subgraph(config, "reviews", Some("my-router/1.0")).await;
+    }
+
+    #[tokio::test]
+    async fn it_rejects_invalid_user_agent() {
+        assert!(crate::plugin::plugins()
+            .get("apollo.user_agent")
+            .expect("Plugin not found")
+            .create_instance(
+                &serde_json::json!({ "all": "bad\nagent" }),
+                Default::default()
+            )
+            .await
+            .is_err());
+    }
+}
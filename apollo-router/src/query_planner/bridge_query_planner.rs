@@ -2,7 +2,9 @@
 
 use std::collections::HashMap;
 use std::fmt::Debug;
+use std::future::Future;
 use std::sync::Arc;
+use std::time::Duration;
 
 use futures::future::BoxFuture;
 use opentelemetry::trace::SpanKind;
@@ -105,13 +107,14 @@ impl BridgeQueryPlanner {
         operation: Option<String>,
         mut selections: Query,
     ) -> Result<QueryPlannerContent, QueryPlannerError> {
-        let planner_result = self
-            .planner
-            .plan(query, operation)
-            .await
-            .map_err(QueryPlannerError::RouterBridgeError)?
-            .into_result()
-            .map_err(QueryPlannerError::from)?;
+        let planner_result = with_timeout(
+            self.configuration.limits.query_planning_timeout,
+            self.planner.plan(query, operation),
+        )
+        .await?
+        .map_err(QueryPlannerError::RouterBridgeError)?
+        .into_result()
+        .map_err(QueryPlannerError::from)?;
 
         match planner_result {
             PlanSuccess {
@@ -127,11 +130,28 @@ impl BridgeQueryPlanner {
                 Ok(QueryPlannerContent::Plan {
                     plan: Arc::new(query_planner::QueryPlan {
                         usage_reporting,
+                        summary: node.summary().to_string(),
                         root: node,
                         formatted_query_plan,
                         query: Arc::new(selections),
                         options: QueryPlanOptions {
                             enable_deduplicate_variables: self.deduplicate_variables,
+                            max_subgraph_fetches: self.configuration.limits.max_subgraph_fetches,
+                            large_integers: self.configuration.supergraph.large_integers,
+                            defer_context_keys: self
+                                .configuration
+                                .supergraph
+                                .defer_context_keys
+                                .clone(),
+                            array_merge_strategy: self
+                                .configuration
+                                .supergraph
+                                .array_merge_strategy
+                                .clone(),
+                            strip_unrequested_fields: self
+                                .configuration
+                                .supergraph
+                                .strip_unrequested_fields,
                         },
                     }),
                 })
@@ -219,6 +239,7 @@ impl Service<QueryPlannerRequest> for BridgeQueryPlanner {
 impl BridgeQueryPlanner {
     async fn get(&self, key: QueryKey) -> Result<QueryPlannerContent, QueryPlannerError> {
         let selections = self.parse_selections(key.0.clone()).await?;
+        selections.validate_operation_name(key.1.as_deref())?;
 
         if selections.contains_introspection() {
             // If we have only one operation containing a single root field `__typename`
@@ -235,10 +256,30 @@ impl BridgeQueryPlanner {
             }
         }
 
-        self.plan(key.0, key.1, selections).await
+        match self.plan(key.0, key.1, selections).await {
+            // There is nothing to fetch when all the root fields are skipped, like with
+            // `@skip(if: true)`, but the operation is still reported
+            Err(QueryPlannerError::EmptyPlan(usage_reporting)) => {
+                Ok(QueryPlannerContent::EmptyPlan { usage_reporting })
+            }
+            result => result,
+        }
     }
 }
 
+/// Stops waiting for the planning once the timeout is reached
+///
+/// The planner cannot be interrupted and still finishes the plan, but the request gets an error
+/// instead of waiting for it.
+async fn with_timeout<T>(
+    timeout: Duration,
+    planning: impl Future<Output = T>,
+) -> Result<T, QueryPlannerError> {
+    tokio::time::timeout(timeout, planning)
+        .await
+        .map_err(|_| QueryPlannerError::Timeout(timeout))
+}
+
 /// Data coming from the `plan` method on the router_bridge
 #[derive(Debug, PartialEq, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -385,4 +426,22 @@ mod tests {
             result.unwrap_err().to_string()
         );
     }
+
+    #[test(tokio::test)]
+    async fn test_planning_timeout() {
+        let slow_planning = async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            "plan"
+        };
+        let result = with_timeout(Duration::from_millis(10), slow_planning).await;
+        assert!(matches!(result, Err(QueryPlannerError::Timeout(_))));
+        assert_eq!(
+            "query planning took longer than 10ms",
+            result.unwrap_err().to_string()
+        );
+
+        let planning = async { "plan" };
+        let result = with_timeout(Duration::from_millis(10), planning).await;
+        assert_eq!("plan", result.unwrap());
+    }
 }
@@ -10,6 +10,7 @@ use serde_json_bytes::value::Serializer;
 use tower::ServiceExt;
 
 use super::USAGE_REPORTING;
+use crate::cache::metrics::CacheMetrics;
 use crate::cache::DeduplicatingCache;
 use crate::error::CacheResolverError;
 use crate::error::QueryPlannerError;
@@ -38,8 +39,13 @@ where
         plan_cache_limit: usize,
         schema_id: Option<String>,
         redis_urls: Option<Vec<String>>,
+        metrics: CacheMetrics,
     ) -> CachingQueryPlanner<T> {
-        let cache = Arc::new(DeduplicatingCache::with_capacity(plan_cache_limit, redis_urls).await);
+        let cache = Arc::new(
+            DeduplicatingCache::with_capacity(plan_cache_limit, redis_urls)
+                .await
+                .with_metrics(metrics),
+        );
         Self {
             cache,
             delegate,
@@ -89,8 +95,11 @@ where
                             entry.insert(Ok(content.clone())).await;
                         }
 
-                        if let Some(QueryPlannerContent::Plan { plan, .. }) = &content {
-                            match (&plan.usage_reporting).serialize(Serializer) {
+                        if let Some(usage_reporting) = content
+                            .as_ref()
+                            .and_then(QueryPlannerContent::usage_reporting)
+                        {
+                            match usage_reporting.serialize(Serializer) {
                                 Ok(v) => {
                                     context.insert_json_value(USAGE_REPORTING, v);
                                 }
@@ -110,7 +119,12 @@ where
                     }
                     Err(error) => {
                         let e = Arc::new(error);
-                        entry.insert(Err(e.clone())).await;
+                        if let QueryPlannerError::Timeout(_) = e.deref() {
+                            // the next request gets another chance to plan the query
+                            entry.send(Err(e.clone())).await;
+                        } else {
+                            entry.insert(Err(e.clone())).await;
+                        }
                         Err(CacheResolverError::RetrievalError(e))
                     }
                 }
@@ -122,8 +136,8 @@ where
 
                 match res {
                     Ok(content) => {
-                        if let QueryPlannerContent::Plan { plan, .. } = &content {
-                            match (&plan.usage_reporting).serialize(Serializer) {
+                        if let Some(usage_reporting) = content.usage_reporting() {
+                            match usage_reporting.serialize(Serializer) {
                                 Ok(v) => {
                                     context.insert_json_value(USAGE_REPORTING, v);
                                 }
@@ -262,7 +276,8 @@ mod tests {
             planner
         });
 
-        let mut planner = CachingQueryPlanner::new(delegate, 10, None, None).await;
+        let mut planner =
+            CachingQueryPlanner::new(delegate, 10, None, None, Default::default()).await;
 
         for _ in 0..5 {
             assert!(planner
@@ -284,6 +299,42 @@ mod tests {
             .is_err());
     }
 
+    #[test(tokio::test)]
+    async fn test_timeouts_are_not_cached() {
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let mut delegate = MockMyQueryPlanner::new();
+        delegate.expect_clone().returning(move || {
+            let calls = calls.clone();
+            let mut planner = MockMyQueryPlanner::new();
+            planner.expect_sync_call().returning(move |_| {
+                // the first planning times out, the retry succeeds
+                if calls.fetch_add(1, std::sync::atomic::Ordering::SeqCst) == 0 {
+                    return Err(QueryPlannerError::Timeout(
+                        std::time::Duration::from_millis(10),
+                    ));
+                }
+                Ok(QueryPlannerResponse::builder()
+                    .content(QueryPlannerContent::Introspection {
+                        response: Box::new(graphql::Response::builder().build()),
+                    })
+                    .context(Context::new())
+                    .build())
+            });
+            planner
+        });
+
+        let mut planner =
+            CachingQueryPlanner::new(delegate, 10, None, None, Default::default()).await;
+
+        let request = || QueryPlannerRequest::new("query1".into(), None, Context::new());
+        assert!(matches!(
+            planner.call(request()).await,
+            Err(CacheResolverError::RetrievalError(error))
+                if matches!(*error, QueryPlannerError::Timeout(_))
+        ));
+        assert!(planner.call(request()).await.unwrap().content.is_some());
+    }
+
     macro_rules! test_query_plan {
         () => {
             include_str!("testdata/query_plan.json")
@@ -297,6 +348,7 @@ mod tests {
             let mut planner = MockMyQueryPlanner::new();
             planner.expect_sync_call().times(0..2).returning(|_| {
                 let query_plan: QueryPlan = QueryPlan {
+                    summary: Default::default(),
                     formatted_query_plan: Default::default(),
                     root: serde_json::from_str(test_query_plan!()).unwrap(),
                     options: QueryPlanOptions::default(),
@@ -318,7 +370,8 @@ mod tests {
             planner
         });
 
-        let mut planner = CachingQueryPlanner::new(delegate, 10, None, None).await;
+        let mut planner =
+            CachingQueryPlanner::new(delegate, 10, None, None, Default::default()).await;
 
         for _ in 0..5 {
             assert!(planner
@@ -1,41 +1,57 @@
 use std::collections::HashMap;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 
 use futures::future::join_all;
 use futures::prelude::*;
 use opentelemetry::trace::SpanKind;
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::broadcast::Receiver;
 use tokio::sync::broadcast::Sender;
-use tokio_stream::wrappers::BroadcastStream;
+use tokio_util::sync::CancellationToken;
 use tracing::Instrument;
 
 use super::log;
+use super::metrics::DeferMetrics;
 use super::DeferredNode;
+use super::ExecutionOptions;
 use super::PlanNode;
 use super::QueryPlan;
 use super::QueryPlanOptions;
 use crate::error::Error;
 use crate::graphql::Request;
 use crate::graphql::Response;
+use crate::json_ext::ArrayMergeStrategy;
+use crate::json_ext::Object;
 use crate::json_ext::Path;
+use crate::json_ext::PathElement;
 use crate::json_ext::Value;
 use crate::json_ext::ValueExt;
+use crate::query_planner::fetch::FetchNode;
 use crate::query_planner::FlattenNode;
 use crate::query_planner::Primary;
 use crate::query_planner::FETCH_SPAN_NAME;
 use crate::query_planner::FLATTEN_SPAN_NAME;
 use crate::query_planner::PARALLEL_SPAN_NAME;
 use crate::query_planner::SEQUENCE_SPAN_NAME;
+use crate::services::batching::Batch;
+use crate::services::batching::BatchQuery;
 use crate::services::subgraph_service::SubgraphServiceFactory;
 use crate::*;
 
 impl QueryPlan {
     /// Execute the plan and return a [`Response`].
+    #[allow(clippy::too_many_arguments)]
     pub(crate) async fn execute<'a, SF>(
         &self,
         context: &'a Context,
         service_factory: &'a Arc<SF>,
         supergraph_request: &'a Arc<http::Request<Request>>,
-        schema: &'a Schema,
+        schema: &'a Arc<Schema>,
+        defer_metrics: &'a DeferMetrics,
+        cancellation: &'a CancellationToken,
+        execution_options: &'a ExecutionOptions,
         sender: futures::channel::mpsc::Sender<Response>,
     ) -> Response
     where
@@ -45,6 +61,7 @@ impl QueryPlan {
 
         log::trace_query_plan(&self.root);
         let deferred_fetches = HashMap::new();
+        let fetches = Arc::new(AtomicUsize::new(0));
         let (value, subselection, errors) = self
             .root
             .execute_recursively(
@@ -56,13 +73,36 @@ impl QueryPlan {
                     deferred_fetches: &deferred_fetches,
                     query: &self.query,
                     options: &self.options,
+                    execution_options,
+                    defer_metrics,
+                    fetches: &fetches,
+                    cancellation,
+                    batch: None,
                 },
                 &root,
                 &Value::default(),
+                &[],
                 sender,
             )
             .await;
 
+        // the request is aborted when it goes over the limit of subgraph fetches: its partial
+        // data is dropped, and so are the deferred parts still running
+        if over_fetch_limit(&fetches, &self.options) {
+            cancellation.cancel();
+            return Response::builder()
+                .error(fetch_limit_error(&self.options))
+                .build();
+        }
+
+        // nothing was fetched, like when all the fields are skipped or deferred without
+        // fetches: the data is an empty object, it is only null when fetching it failed
+        let value = if value.is_null() && errors.is_empty() {
+            Value::Object(Object::default())
+        } else {
+            value
+        };
+
         Response::builder()
             .data(value)
             .and_subselection(subselection)
@@ -76,14 +116,199 @@ impl QueryPlan {
 }
 
 // holds the query plan executon arguments that do not change between calls
+//
+// Deferred nodes run in their own task, so they keep their own handle on these arguments.
+// Everything that is not a small value is behind an `Arc` so that this handle is cheap:
+// - the context is shared, not copied: every entry is visible in deferred execution,
+//   including the ones inserted by the primary response's fetches after the deferral.
+//   With `supergraph.defer_context_keys`, the deferred nodes get a copy of the listed entries
+//   and of the router's own entries instead
+// - the schema, supergraph request, query and subgraph service factory are shared
+// - the options, from the query plan and from the configuration, and the metrics are copied
+// - the deferred fetches are not propagated: each deferred node gets its own
+// - the count of executed fetches is shared, so that limits apply to the whole request
+// - the cancellation token is shared, so that the deferred nodes stop when the client disconnects
+// - the batch is only set for the fetches of a parallel node that target the same subgraph
 pub(crate) struct ExecutionParameters<'a, SF> {
     pub(crate) context: &'a Context,
     pub(crate) service_factory: &'a Arc<SF>,
-    pub(crate) schema: &'a Schema,
+    pub(crate) schema: &'a Arc<Schema>,
     pub(crate) supergraph_request: &'a Arc<http::Request<Request>>,
     pub(crate) deferred_fetches: &'a HashMap<String, Sender<(Value, Vec<Error>)>>,
     pub(crate) query: &'a Arc<Query>,
     pub(crate) options: &'a QueryPlanOptions,
+    pub(crate) execution_options: &'a ExecutionOptions,
+    pub(crate) defer_metrics: &'a DeferMetrics,
+    pub(crate) fetches: &'a Arc<AtomicUsize>,
+    pub(crate) cancellation: &'a CancellationToken,
+    pub(crate) batch: Option<&'a Arc<BatchQuery>>,
+}
+
+impl<'a, SF> ExecutionParameters<'a, SF> {
+    fn with_batch<'b>(&'b self, batch: Option<&'b Arc<BatchQuery>>) -> ExecutionParameters<'b, SF> {
+        ExecutionParameters {
+            context: self.context,
+            service_factory: self.service_factory,
+            schema: self.schema,
+            supergraph_request: self.supergraph_request,
+            deferred_fetches: self.deferred_fetches,
+            query: self.query,
+            options: self.options,
+            execution_options: self.execution_options,
+            defer_metrics: self.defer_metrics,
+            fetches: self.fetches,
+            cancellation: self.cancellation,
+            batch,
+        }
+    }
+}
+
+/// The members of the batches of the fetches of a parallel node, by node
+///
+/// The fetches that target the same subgraph, directly or under a flatten node, are batched
+/// together: the subgraph service sends them in a single request if batching is enabled for the
+/// subgraph, and ignores the batch otherwise.
+fn batches(nodes: &[PlanNode]) -> Vec<Option<Arc<BatchQuery>>> {
+    let fetched_service = |node: &PlanNode| match node {
+        PlanNode::Fetch(fetch) => Some(fetch.service_name.clone()),
+        PlanNode::Flatten(FlattenNode { node, .. }) => match node.as_ref() {
+            PlanNode::Fetch(fetch) => Some(fetch.service_name.clone()),
+            _ => None,
+        },
+        _ => None,
+    };
+
+    let mut fetches: HashMap<String, usize> = HashMap::new();
+    for service in nodes.iter().filter_map(fetched_service) {
+        *fetches.entry(service).or_default() += 1;
+    }
+    let mut members: HashMap<String, std::vec::IntoIter<BatchQuery>> = fetches
+        .into_iter()
+        .filter(|(_, count)| *count > 1)
+        .map(|(service, count)| (service, Batch::members(count).into_iter()))
+        .collect();
+    nodes
+        .iter()
+        .map(|node| {
+            let members = members.get_mut(&fetched_service(node)?)?;
+            members.next().map(Arc::new)
+        })
+        .collect()
+}
+
+/// Builds the value used instead of the data of a failed fetch at `current_dir`,
+/// from the fallbacks configured for the fields that the fetch resolves.
+///
+/// A fallback is only used for the nullable fields selected by the operation, and for entities
+/// that exist: the fetch failure must not make up data that GraphQL would not allow to be null.
+/// Fields already present, because another fetch returned them, are left as they are.
+///
+/// Returns the value, and whether every field resolved by the fetch has a fallback: otherwise
+/// the fetch error is still reported for the others.
+fn fallback_value<SF>(
+    parameters: &ExecutionParameters<'_, SF>,
+    fetch_node: &FetchNode,
+    current_dir: &Path,
+    parent_value: &Value,
+) -> Option<(Value, bool)> {
+    let operation_name = parameters
+        .supergraph_request
+        .body()
+        .operation_name
+        .as_deref();
+    let selected_keys = fetch_node.selected_keys();
+    let mut covered_keys = 0;
+    let mut fallback = None;
+    for key in &selected_keys {
+        let (path, default) =
+            match parameters
+                .execution_options
+                .fallbacks
+                .iter()
+                .find(|(path, _)| {
+                    path.len() == current_dir.len() + 1
+                        && path.starts_with(current_dir)
+                        && matches!(path.last(), Some(PathElement::Key(k)) if k == key)
+                }) {
+                Some(fallback) => fallback,
+                None => continue,
+            };
+        match parameters.query.field_type_at(operation_name, path) {
+            Some(field_type) if !field_type.is_non_null() => {}
+            _ => continue,
+        }
+        covered_keys += 1;
+        parent_value.select_values_and_paths(current_dir, |entity_path, entity| {
+            let missing = match entity {
+                Value::Object(object) => !object.contains_key(key.as_str()),
+                // the root fetch fills the response data, which may not exist yet
+                Value::Null => entity_path.is_empty(),
+                _ => false,
+            };
+            if missing {
+                let mut path = entity_path.clone();
+                path.push(PathElement::Key(key.clone()));
+                fallback
+                    .get_or_insert_with(Value::default)
+                    .deep_merge(Value::from_path(&path, default.clone()));
+            }
+        });
+    }
+    fallback.map(|fallback| (fallback, covered_keys == selected_keys.len()))
+}
+
+/// Checks if a flatten node depends on data that the previous nodes failed to fetch.
+///
+/// The fetches of a flatten node need entities at its path: if a node executed before it, in
+/// this sequence or in an enclosing one, returned errors at a parent of that path and no data
+/// there, they cannot succeed and the node is skipped. The client already gets the errors of the
+/// failed fetch, for the parent of the fields the skipped fetches would resolve.
+/// Nodes with conditions or deferred fragments are always executed, so that all the deferred
+/// responses are sent.
+fn depends_on_failed_fetch(
+    node: &PlanNode,
+    path: &Path,
+    value: &Value,
+    failed_paths: &[Path],
+) -> bool {
+    if node.contains_condition_or_defer()
+        || !failed_paths
+            .iter()
+            .any(|failed_path| path.starts_with(failed_path))
+    {
+        return false;
+    }
+
+    let mut has_data = false;
+    value.select_values_and_paths(path, |_, value| {
+        has_data |= match value {
+            Value::Null => false,
+            Value::Object(object) => !object.is_empty(),
+            _ => true,
+        };
+    });
+    if !has_data {
+        tracing::debug!(
+            "skipping the fetches at path {}: the data they depend on is missing",
+            path
+        );
+    }
+    !has_data
+}
+
+/// The strategy merging the arrays of the result of a node of a sequence or parallel node.
+///
+/// Only the results of root fetches are merged with the configured strategy: the other nodes
+/// return entities at the index of the entities they were fetched for, or the data they were
+/// given, which are always merged by index.
+fn array_merge_strategy<'a>(
+    node: &PlanNode,
+    options: &'a QueryPlanOptions,
+) -> &'a ArrayMergeStrategy {
+    match node {
+        PlanNode::Fetch(fetch) if fetch.requires.is_empty() => &options.array_merge_strategy,
+        _ => &ArrayMergeStrategy::ByIndex,
+    }
 }
 
 impl PlanNode {
@@ -92,6 +317,7 @@ impl PlanNode {
         parameters: &'a ExecutionParameters<'a, SF>,
         current_dir: &'a Path,
         parent_value: &'a Value,
+        failed_paths: &'a [Path],
         sender: futures::channel::mpsc::Sender<Response>,
     ) -> future::BoxFuture<(Value, Option<String>, Vec<Error>)>
     where
@@ -107,14 +333,27 @@ impl PlanNode {
                 PlanNode::Sequence { nodes } => {
                     value = parent_value.clone();
                     errors = Vec::new();
+                    // the paths of the errors of this sequence and of the enclosing ones, the
+                    // next nodes may depend on the data missing there
+                    let mut failed_paths = failed_paths.to_vec();
                     let span = tracing::info_span!(SEQUENCE_SPAN_NAME);
                     for node in nodes {
                         let (v, subselect, err) = node
-                            .execute_recursively(parameters, current_dir, &value, sender.clone())
+                            .execute_recursively(
+                                parameters,
+                                current_dir,
+                                &value,
+                                &failed_paths,
+                                sender.clone(),
+                            )
                             .instrument(span.clone())
                             .in_current_span()
                             .await;
-                        value.deep_merge(v);
+                        value.deep_merge_with(v, array_merge_strategy(node, parameters.options));
+                        failed_paths.extend(
+                            err.iter()
+                                .map(|error| error.path.clone().unwrap_or_default()),
+                        );
                         errors.extend(err.into_iter());
                         subselection = subselect;
                     }
@@ -126,36 +365,50 @@ impl PlanNode {
                     let span = tracing::info_span!(PARALLEL_SPAN_NAME);
                     let mut stream: stream::FuturesUnordered<_> = nodes
                         .iter()
-                        .map(|plan| {
-                            plan.execute_recursively(
-                                parameters,
-                                current_dir,
-                                parent_value,
-                                sender.clone(),
-                            )
+                        .zip(batches(nodes))
+                        .map(|(plan, batch)| {
+                            let sender = sender.clone();
+                            // the node keeps its member of the batch until it is executed, so
+                            // that it leaves the batch if it does not fetch anything
+                            async move {
+                                let result = plan
+                                    .execute_recursively(
+                                        &parameters.with_batch(batch.as_ref()),
+                                        current_dir,
+                                        parent_value,
+                                        failed_paths,
+                                        sender,
+                                    )
+                                    .await;
+                                (array_merge_strategy(plan, parameters.options), result)
+                            }
                             .instrument(span.clone())
                         })
                         .collect();
 
-                    while let Some((v, _subselect, err)) = stream
+                    while let Some((strategy, (v, _subselect, err))) = stream
                         .next()
                         .instrument(span.clone())
                         .in_current_span()
                         .await
                     {
-                        value.deep_merge(v);
+                        value.deep_merge_with(v, strategy);
                         errors.extend(err.into_iter());
                     }
                 }
                 PlanNode::Flatten(FlattenNode { path, node }) => {
                     // Note that the span must be `info` as we need to pick this up in apollo tracing
                     let current_dir = current_dir.join(path);
+                    if depends_on_failed_fetch(node, &current_dir, parent_value, failed_paths) {
+                        return (Value::default(), None, Vec::new());
+                    }
                     let (v, subselect, err) = node
                         .execute_recursively(
                             parameters,
                             // this is the only command that actually changes the "current dir"
                             &current_dir,
                             parent_value,
+                            failed_paths,
                             sender,
                         )
                         .instrument(
@@ -168,6 +421,15 @@ impl PlanNode {
                     subselection = subselect;
                 }
                 PlanNode::Fetch(fetch_node) => {
+                    if fetch_limit_exceeded(parameters.fetches, parameters.options) {
+                        tracing::warn!(
+                            "not fetching from subgraph '{}': the request exceeded the limit of subgraph fetches",
+                            fetch_node.service_name
+                        );
+                        let mut error = fetch_limit_error(parameters.options);
+                        error.path = Some(current_dir.to_owned());
+                        return (Value::default(), None, vec![error]);
+                    }
                     let fetch_time_offset =
                         parameters.context.created_at.elapsed().as_nanos() as i64;
                     match fetch_node
@@ -180,14 +442,47 @@ impl PlanNode {
                         ))
                         .await
                     {
-                        Ok((v, e)) => {
+                        Ok((mut v, mut e)) => {
+                            if let Some(max_response_depth) =
+                                parameters.execution_options.max_response_depth
+                            {
+                                if truncate_depth(&mut v, max_response_depth) {
+                                    e.push(Error {
+                                        message: format!(
+                                            "Subgraph response is nested deeper than the limit of {} levels",
+                                            max_response_depth
+                                        ),
+                                        path: Some(current_dir.to_owned()),
+                                        ..Default::default()
+                                    });
+                                }
+                            }
                             value = v;
                             errors = e;
                         }
                         Err(err) => {
                             failfast_error!("Fetch error: {}", err);
-                            errors = vec![err.to_graphql_error(Some(current_dir.to_owned()))];
-                            value = Value::default();
+                            match fallback_value(parameters, fetch_node, current_dir, parent_value)
+                            {
+                                Some((fallback, complete)) => {
+                                    tracing::warn!(
+                                        "fetch from subgraph '{}' failed, using the configured fallbacks: {}",
+                                        fetch_node.service_name,
+                                        err
+                                    );
+                                    errors = if complete {
+                                        Vec::new()
+                                    } else {
+                                        vec![err.to_graphql_error(Some(current_dir.to_owned()))]
+                                    };
+                                    value = fallback;
+                                }
+                                None => {
+                                    errors =
+                                        vec![err.to_graphql_error(Some(current_dir.to_owned()))];
+                                    value = Value::default();
+                                }
+                            }
                         }
                     }
                 }
@@ -218,9 +513,14 @@ impl PlanNode {
                         futures.push(fut);
                     }
 
+                    let cancellation = parameters.cancellation.clone();
                     tokio::task::spawn(
                         async move {
-                            join_all(futures).await;
+                            // dropping the deferred nodes cancels their subgraph fetches
+                            tokio::select! {
+                                _ = join_all(futures) => {}
+                                _ = cancellation.cancelled() => {}
+                            }
                         }
                         .in_current_span(),
                     );
@@ -238,10 +538,16 @@ impl PlanNode {
                                     supergraph_request: parameters.supergraph_request,
                                     deferred_fetches: &deferred_fetches,
                                     options: parameters.options,
+                                    execution_options: parameters.execution_options,
                                     query: parameters.query,
+                                    defer_metrics: parameters.defer_metrics,
+                                    fetches: parameters.fetches,
+                                    cancellation: parameters.cancellation,
+                                    batch: None,
                                 },
                                 current_dir,
                                 &value,
+                                failed_paths,
                                 sender,
                             )
                             .instrument(span.clone())
@@ -256,6 +562,12 @@ impl PlanNode {
                     } else {
                         let _guard = span.enter();
 
+                        // the primary part fetches nothing: the deferred responses get an
+                        // empty object, rather than null, from it, unless an earlier fetch
+                        // failed to get the data
+                        if value.is_null() && failed_paths.is_empty() {
+                            value = Value::Object(Object::default());
+                        }
                         subselection = primary_subselection.clone();
 
                         let _ = primary_sender.send(value.clone());
@@ -291,6 +603,7 @@ impl PlanNode {
                                     parameters,
                                     current_dir,
                                     parent_value,
+                                    failed_paths,
                                     sender.clone(),
                                 )
                                 .instrument(span.clone())
@@ -307,6 +620,7 @@ impl PlanNode {
                                 parameters,
                                 current_dir,
                                 parent_value,
+                                failed_paths,
                                 sender.clone(),
                             )
                             .instrument(span.clone())
@@ -324,6 +638,53 @@ impl PlanNode {
     }
 }
 
+/// Counts a fetch of the request, returns `true` if it goes over the limit of subgraph fetches.
+fn fetch_limit_exceeded(fetches: &AtomicUsize, options: &QueryPlanOptions) -> bool {
+    match options.max_subgraph_fetches {
+        Some(max_subgraph_fetches) => {
+            fetches.fetch_add(1, Ordering::SeqCst) >= max_subgraph_fetches
+        }
+        None => false,
+    }
+}
+
+/// Returns `true` if the request went over the limit of subgraph fetches.
+fn over_fetch_limit(fetches: &AtomicUsize, options: &QueryPlanOptions) -> bool {
+    match options.max_subgraph_fetches {
+        Some(max_subgraph_fetches) => fetches.load(Ordering::SeqCst) > max_subgraph_fetches,
+        None => false,
+    }
+}
+
+fn fetch_limit_error(options: &QueryPlanOptions) -> Error {
+    Error::builder()
+        .message(format!(
+            "Request exceeded the limit of {} subgraph fetches",
+            options.max_subgraph_fetches.unwrap_or_default()
+        ))
+        .extension("code", "SUBGRAPH_FETCH_LIMIT")
+        .build()
+}
+
+/// Replaces the objects and arrays nested deeper than `max_depth` with null.
+///
+/// Returns `true` if anything was replaced.
+fn truncate_depth(value: &mut Value, max_depth: usize) -> bool {
+    match value {
+        Value::Object(_) | Value::Array(_) if max_depth == 0 => {
+            *value = Value::Null;
+            true
+        }
+        Value::Object(object) => object.values_mut().fold(false, |truncated, value| {
+            truncate_depth(value, max_depth - 1) || truncated
+        }),
+        Value::Array(array) => array.iter_mut().fold(false, |truncated, value| {
+            truncate_depth(value, max_depth - 1) || truncated
+        }),
+        _ => false,
+    }
+}
+
 impl DeferredNode {
     fn execute<'a, 'b, SF>(
         &'b self,
@@ -339,17 +700,18 @@ impl DeferredNode {
         let mut deferred_receivers = Vec::new();
 
         for d in self.depends.iter() {
-            match deferred_fetches.get(&d.id) {
+            let mut receiver = match deferred_fetches.get(&d.id) {
                 None => {
                     let (sender, receiver) = tokio::sync::broadcast::channel(1);
-                    deferred_fetches.insert(d.id.clone(), sender.clone());
-                    deferred_receivers.push(BroadcastStream::new(receiver).into_future());
-                }
-                Some(sender) => {
-                    let receiver = sender.subscribe();
-                    deferred_receivers.push(BroadcastStream::new(receiver).into_future());
+                    deferred_fetches.insert(d.id.clone(), sender);
+                    receiver
                 }
-            }
+                Some(sender) => sender.subscribe(),
+            };
+            let defer_metrics = parameters.defer_metrics.clone();
+            deferred_receivers.push(async move {
+                recv_broadcast(&mut receiver, "deferred_fetch", &defer_metrics).await
+            });
         }
 
         // if a deferred node has no depends (ie not waiting for data from fetches) then it has to
@@ -360,7 +722,7 @@ impl DeferredNode {
         let is_depends_empty = self.depends.is_empty();
 
         let mut stream: stream::FuturesUnordered<_> = deferred_receivers.into_iter().collect();
-        //FIXME/ is there a solution without cloning the entire node? Maybe it could be moved instead?
+        // the node is behind an `Arc`, so this does not clone the plan
         let deferred_inner = self.node.clone();
         let deferred_path = self.path.clone();
         let subselection = self.subselection();
@@ -369,9 +731,16 @@ impl DeferredNode {
         let sc = parameters.schema.clone();
         let orig = parameters.supergraph_request.clone();
         let sf = parameters.service_factory.clone();
-        let ctx = parameters.context.clone();
+        let ctx = match &parameters.options.defer_context_keys {
+            Some(keys) => parameters.context.with_keys(keys),
+            None => parameters.context.clone(),
+        };
         let opt = parameters.options.clone();
+        let execution_options = parameters.execution_options.clone();
         let query = parameters.query.clone();
+        let defer_metrics = parameters.defer_metrics.clone();
+        let fetches = parameters.fetches.clone();
+        let cancellation = parameters.cancellation.clone();
         let mut primary_receiver = primary_sender.subscribe();
         let mut value = parent_value.clone();
 
@@ -379,15 +748,16 @@ impl DeferredNode {
             let mut errors = Vec::new();
 
             if is_depends_empty {
-                let primary_value = primary_receiver.recv().await.unwrap_or_default();
+                let primary_value =
+                    recv_broadcast(&mut primary_receiver, "primary", &defer_metrics)
+                        .await
+                        .unwrap_or_default();
                 value.deep_merge(primary_value);
             } else {
-                while let Some((v, _remaining)) = stream.next().await {
-                    // a Err(RecvError) means either that the fetch was not performed and the
-                    // sender was dropped, possibly because there was no need to do it,
-                    // or because it is lagging, but here we only send one message so it
-                    // will not happen
-                    if let Some(Ok((deferred_value, err))) = v {
+                while let Some(v) = stream.next().await {
+                    // no value means that the fetch was not performed and the sender was
+                    // dropped, possibly because there was no need to do it
+                    if let Some((deferred_value, err)) = v {
                         value.deep_merge(deferred_value);
                         errors.extend(err.into_iter())
                     }
@@ -408,9 +778,15 @@ impl DeferredNode {
                             deferred_fetches: &deferred_fetches,
                             query: &query,
                             options: &opt,
+                            execution_options: &execution_options,
+                            defer_metrics: &defer_metrics,
+                            fetches: &fetches,
+                            cancellation: &cancellation,
+                            batch: None,
                         },
                         &Path::default(),
                         &value,
+                        &[],
                         tx.clone(),
                     )
                     .instrument(span.clone())
@@ -418,22 +794,28 @@ impl DeferredNode {
                     .await;
 
                 if !is_depends_empty {
-                    let primary_value = primary_receiver.recv().await.unwrap_or_default();
+                    let primary_value =
+                        recv_broadcast(&mut primary_receiver, "primary", &defer_metrics)
+                            .await
+                            .unwrap_or_default();
                     v.deep_merge(primary_value);
                 }
 
-                if let Err(e) = tx
-                    .send(
-                        Response::builder()
-                            .data(v)
-                            .errors(err)
-                            .and_path(Some(deferred_path.clone()))
-                            .and_subselection(subselection.or(node_subselection))
-                            .and_label(label)
-                            .build(),
-                    )
-                    .await
-                {
+                // over the limit of subgraph fetches, the request is aborted: this part only
+                // carries the error, and the other deferred parts are stopped
+                let response = if over_fetch_limit(&fetches, &opt) {
+                    cancellation.cancel();
+                    Response::builder().error(fetch_limit_error(&opt)).build()
+                } else {
+                    Response::builder()
+                        .data(v)
+                        .errors(err)
+                        .and_path(Some(deferred_path.clone()))
+                        .and_subselection(subselection.or(node_subselection))
+                        .and_label(label)
+                        .build()
+                };
+                if let Err(e) = tx.send(response).await {
                     tracing::error!(
                         "error sending deferred response at path {}: {:?}",
                         deferred_path,
@@ -442,7 +824,10 @@ impl DeferredNode {
                 };
                 tx.disconnect();
             } else {
-                let primary_value = primary_receiver.recv().await.unwrap_or_default();
+                let primary_value =
+                    recv_broadcast(&mut primary_receiver, "primary", &defer_metrics)
+                        .await
+                        .unwrap_or_default();
                 value.deep_merge(primary_value);
 
                 if let Err(e) = tx
@@ -468,3 +853,29 @@ impl DeferredNode {
         }
     }
 }
+
+/// Receives the next value of a broadcast channel used by deferred execution,
+/// or `None` if the sender was dropped.
+///
+/// The channels carry a single message, so receivers are not expected to lag behind,
+/// but if they do, the lag is recorded and they get the oldest value still in the channel.
+pub(crate) async fn recv_broadcast<T: Clone>(
+    receiver: &mut Receiver<T>,
+    channel: &'static str,
+    defer_metrics: &DeferMetrics,
+) -> Option<T> {
+    loop {
+        match receiver.recv().await {
+            Ok(value) => return Some(value),
+            Err(RecvError::Lagged(skipped)) => {
+                tracing::warn!(
+                    "deferred execution missed {} messages of the {} channel",
+                    skipped,
+                    channel
+                );
+                defer_metrics.record_lagged(channel);
+            }
+            Err(RecvError::Closed) => return None,
+        }
+    }
+}
@@ -2,6 +2,7 @@ use std::collections::HashMap;
 use std::fmt::Display;
 use std::sync::Arc;
 
+use apollo_parser::ast;
 use indexmap::IndexSet;
 use serde::Deserialize;
 use serde::Serialize;
@@ -20,6 +21,7 @@ use crate::json_ext::Path;
 use crate::json_ext::Value;
 use crate::json_ext::ValueExt;
 use crate::services::subgraph_service::SubgraphServiceFactory;
+use crate::spec::query::TYPENAME;
 use crate::*;
 
 /// GraphQL operation type.
@@ -219,7 +221,7 @@ impl FetchNode {
             }
         };
 
-        let subgraph_request = SubgraphRequest::builder()
+        let mut subgraph_request = SubgraphRequest::builder()
             .supergraph_request(parameters.supergraph_request.clone())
             .subgraph_request(
                 http_ext::Request::builder()
@@ -250,6 +252,12 @@ impl FetchNode {
             .operation_kind(*operation_kind)
             .context(parameters.context.clone())
             .build();
+        if let Some(batch) = parameters.batch {
+            subgraph_request
+                .subgraph_request
+                .extensions_mut()
+                .insert(batch.clone());
+        }
 
         let service = parameters
             .service_factory
@@ -409,4 +417,54 @@ impl FetchNode {
     pub(crate) fn operation_kind(&self) -> &OperationKind {
         &self.operation_kind
     }
+
+    /// The response keys of the fields resolved by the fetch: the root fields of its operation,
+    /// or the fields selected on the entities of an `_entities` fetch
+    pub(crate) fn selected_keys(&self) -> Vec<String> {
+        let document = apollo_parser::Parser::new(&self.operation)
+            .parse()
+            .document();
+        let mut keys = Vec::new();
+        for definition in document.definitions() {
+            if let ast::Definition::OperationDefinition(operation) = definition {
+                if let Some(selection_set) = operation.selection_set() {
+                    collect_selected_keys(selection_set, &mut keys);
+                }
+            }
+        }
+        keys
+    }
+}
+
+fn collect_selected_keys(selection_set: ast::SelectionSet, keys: &mut Vec<String>) {
+    for selection in selection_set.selections() {
+        match selection {
+            ast::Selection::Field(field) => {
+                let name = match field.name() {
+                    Some(name) => name.text().to_string(),
+                    None => continue,
+                };
+                if name == "_entities" {
+                    if let Some(selection_set) = field.selection_set() {
+                        collect_selected_keys(selection_set, keys);
+                    }
+                } else if name != TYPENAME {
+                    keys.push(
+                        field
+                            .alias()
+                            .and_then(|alias| alias.name())
+                            .map(|alias| alias.text().to_string())
+                            .unwrap_or(name),
+                    );
+                }
+            }
+            ast::Selection::InlineFragment(fragment) => {
+                if let Some(selection_set) = fragment.selection_set() {
+                    collect_selected_keys(selection_set, keys);
+                }
+            }
+            // the operations of the query planner don't use named fragments
+            ast::Selection::FragmentSpread(_) => {}
+        }
+    }
 }
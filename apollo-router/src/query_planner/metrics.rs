@@ -0,0 +1,51 @@
+//! Metrics of the query plan execution.
+
+use opentelemetry::KeyValue;
+
+use crate::plugins::telemetry::metrics::AggregateCounter;
+use crate::plugins::telemetry::metrics::AggregateMeterProvider;
+
+/// Records the lag of the broadcast channels used by deferred execution, labeled by channel,
+/// and the requests cancelled because their client disconnected
+#[derive(Clone)]
+pub(crate) struct DeferMetrics {
+    lagged: AggregateCounter<u64>,
+    cancelled: AggregateCounter<u64>,
+}
+
+impl DeferMetrics {
+    pub(crate) fn new(meter_provider: &AggregateMeterProvider) -> Self {
+        let meter = meter_provider.meter("apollo/router", None);
+        DeferMetrics {
+            lagged: meter.build_counter(|m| {
+                m.u64_counter("apollo_router_defer_channel_lagged_total")
+                    .with_description(
+                        "Number of times a receiver of deferred execution lagged behind its broadcast channel.",
+                    )
+                    .init()
+            }),
+            cancelled: meter.build_counter(|m| {
+                m.u64_counter("apollo_router_requests_cancelled_total")
+                    .with_description(
+                        "Number of requests cancelled because the client disconnected before receiving the whole response.",
+                    )
+                    .init()
+            }),
+        }
+    }
+
+    pub(crate) fn record_lagged(&self, channel: &'static str) {
+        self.lagged.add(1, &[KeyValue::new("channel", channel)]);
+    }
+
+    pub(crate) fn record_cancelled(&self) {
+        self.cancelled.add(1, &[]);
+    }
+}
+
+impl Default for DeferMetrics {
+    /// Metrics that are not sent anywhere
+    fn default() -> Self {
+        Self::new(&AggregateMeterProvider::default())
+    }
+}
@@ -12,6 +12,7 @@ mod bridge_query_planner;
 mod caching_query_planner;
 mod execution;
 pub(crate) mod fetch;
+pub(crate) mod metrics;
 mod plan;
 mod selection;
 pub use plan::*;
@@ -1,4 +1,6 @@
+use std::collections::BTreeSet;
 use std::collections::HashMap;
+use std::fmt;
 use std::fmt::Write;
 use std::sync::Arc;
 
@@ -8,7 +10,11 @@ use serde::Serialize;
 
 pub(crate) use self::fetch::OperationKind;
 use super::fetch;
+use crate::configuration::LargeIntegers;
+use crate::configuration::Limits;
+use crate::configuration::RedactionStrategy;
 use crate::error::QueryPlannerError;
+use crate::json_ext::ArrayMergeStrategy;
 use crate::json_ext::Object;
 use crate::json_ext::Path;
 use crate::json_ext::Value;
@@ -16,11 +22,89 @@ use crate::spec::query::SubSelection;
 use crate::*;
 
 /// Query planning options.
-#[derive(Clone, Eq, Hash, PartialEq, Debug, Default, Serialize, Deserialize)]
+#[derive(Clone, PartialEq, Debug, Serialize, Deserialize)]
 pub(crate) struct QueryPlanOptions {
     /// Enable the variable deduplication optimization on the QueryPlan
     pub(crate) enable_deduplicate_variables: bool,
+    /// Maximum number of subgraph fetches executed for a request
+    pub(crate) max_subgraph_fetches: Option<usize>,
+    /// How integers of ID and custom scalar fields are formatted in the response
+    pub(crate) large_integers: LargeIntegers,
+    /// Context keys copied to the context of the deferred parts, or `None` to share the context
+    pub(crate) defer_context_keys: Option<Vec<String>>,
+    /// How the arrays of the subgraph responses are merged
+    pub(crate) array_merge_strategy: ArrayMergeStrategy,
+    /// Remove the fields of the subgraph responses that the operation does not select
+    pub(crate) strip_unrequested_fields: bool,
 }
+
+impl Default for QueryPlanOptions {
+    fn default() -> Self {
+        Self {
+            enable_deduplicate_variables: false,
+            max_subgraph_fetches: None,
+            large_integers: LargeIntegers::default(),
+            defer_context_keys: None,
+            array_merge_strategy: ArrayMergeStrategy::default(),
+            strip_unrequested_fields: true,
+        }
+    }
+}
+
+/// Execution options, from the configuration of the router.
+///
+/// Unlike the [`QueryPlanOptions`], they are not stored in the query plans, which are cached: a
+/// plan created with a previous configuration is executed with the current options.
+#[derive(Clone, Debug)]
+pub(crate) struct ExecutionOptions {
+    /// Maximum depth of the data merged from subgraph responses
+    pub(crate) max_response_depth: Option<usize>,
+    /// Values inserted for nullable fields when their fetch fails, by response path
+    pub(crate) fallbacks: Vec<(Path, Value)>,
+    /// Maximum number of deferred responses waiting to be sent to the client
+    pub(crate) max_buffered_deferred_responses: usize,
+    /// How the values of sensitive fields are replaced in the response, by response path
+    pub(crate) redactions: Vec<(Path, RedactionStrategy)>,
+}
+
+impl Default for ExecutionOptions {
+    fn default() -> Self {
+        Self {
+            max_response_depth: None,
+            fallbacks: Vec::new(),
+            max_buffered_deferred_responses: Limits::default().max_buffered_deferred_responses,
+            redactions: Vec::new(),
+        }
+    }
+}
+
+impl ExecutionOptions {
+    pub(crate) fn new(configuration: &Configuration) -> Self {
+        ExecutionOptions {
+            max_response_depth: configuration.limits.max_response_depth,
+            fallbacks: configuration
+                .fallbacks
+                .fields
+                .iter()
+                .map(|(path, value)| {
+                    (
+                        Path::from(path),
+                        serde_json_bytes::to_value(value)
+                            .expect("a JSON value can always be converted"),
+                    )
+                })
+                .collect(),
+            max_buffered_deferred_responses: configuration.limits.max_buffered_deferred_responses,
+            redactions: configuration
+                .redaction
+                .fields
+                .iter()
+                .map(|(path, strategy)| (Path::from(path), strategy.clone()))
+                .collect(),
+        }
+    }
+}
+
 /// A planner key.
 ///
 /// This type consists of a query string and an optional operation string
@@ -35,6 +119,9 @@ pub struct QueryPlan {
     pub(crate) formatted_query_plan: Option<String>,
     pub(crate) query: Arc<Query>,
     pub(crate) options: QueryPlanOptions,
+    /// Shape of the plan, recorded on the request spans
+    #[serde(default)]
+    pub(crate) summary: String,
 }
 
 /// This default impl is useful for test users
@@ -46,12 +133,14 @@ impl QueryPlan {
         root: Option<PlanNode>,
         usage_reporting: Option<UsageReporting>,
     ) -> Self {
+        let root = root.unwrap_or_else(|| PlanNode::Sequence { nodes: Vec::new() });
         Self {
             usage_reporting: usage_reporting.unwrap_or_else(|| UsageReporting {
                 stats_report_key: "this is a test report key".to_string(),
                 referenced_fields_by_type: Default::default(),
             }),
-            root: root.unwrap_or_else(|| PlanNode::Sequence { nodes: Vec::new() }),
+            summary: root.summary().to_string(),
+            root,
             formatted_query_plan: Default::default(),
             query: Arc::new(Query::default()),
             options: QueryPlanOptions::default(),
@@ -187,6 +276,61 @@ impl PlanNode {
         }
     }
 
+    /// Counts the nodes of the plan by kind, with the subgraphs it fetches from
+    pub(crate) fn summary(&self) -> PlanSummary {
+        let mut summary = PlanSummary::default();
+        self.summarize(&mut summary, 1);
+        summary
+    }
+
+    fn summarize(&self, summary: &mut PlanSummary, depth: usize) {
+        summary.depth = summary.depth.max(depth);
+        match self {
+            Self::Sequence { nodes } => {
+                summary.sequences += 1;
+                nodes
+                    .iter()
+                    .for_each(|node| node.summarize(summary, depth + 1));
+            }
+            Self::Parallel { nodes } => {
+                summary.parallels += 1;
+                nodes
+                    .iter()
+                    .for_each(|node| node.summarize(summary, depth + 1));
+            }
+            Self::Fetch(fetch) => {
+                summary.fetches += 1;
+                summary.subgraphs.insert(fetch.service_name().to_string());
+            }
+            Self::Flatten(flatten) => {
+                summary.flattens += 1;
+                flatten.node.summarize(summary, depth + 1);
+            }
+            Self::Defer { primary, deferred } => {
+                summary.defers += 1;
+                if let Some(node) = &primary.node {
+                    node.summarize(summary, depth + 1);
+                }
+                for node in deferred
+                    .iter()
+                    .filter_map(|deferred| deferred.node.as_ref())
+                {
+                    node.summarize(summary, depth + 1);
+                }
+            }
+            Self::Condition {
+                if_clause,
+                else_clause,
+                ..
+            } => {
+                summary.conditions += 1;
+                for node in if_clause.iter().chain(else_clause.iter()) {
+                    node.summarize(summary, depth + 1);
+                }
+            }
+        }
+    }
+
     pub(crate) fn parse_subselections(
         &self,
         schema: &Schema,
@@ -344,6 +488,37 @@ fn reconstruct_full_query(path: &Path, subselection: &str) -> String {
     query
 }
 
+/// The shape of a query plan, recorded on the request spans
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct PlanSummary {
+    pub(crate) fetches: usize,
+    pub(crate) sequences: usize,
+    pub(crate) parallels: usize,
+    pub(crate) flattens: usize,
+    pub(crate) defers: usize,
+    pub(crate) conditions: usize,
+    /// Number of nested nodes on the longest branch of the plan
+    pub(crate) depth: usize,
+    pub(crate) subgraphs: BTreeSet<String>,
+}
+
+impl fmt::Display for PlanSummary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "fetch={} sequence={} parallel={} flatten={} defer={} condition={} depth={} subgraphs={}",
+            self.fetches,
+            self.sequences,
+            self.parallels,
+            self.flattens,
+            self.defers,
+            self.conditions,
+            self.depth,
+            self.subgraphs.iter().map(String::as_str).collect::<Vec<_>>().join(",")
+        )
+    }
+}
+
 /// A flatten node.
 #[derive(Debug, Clone, PartialEq, Deserialize, Serialize)]
 #[serde(rename_all = "camelCase")]
@@ -1,5 +1,6 @@
 use std::collections::HashMap;
 use std::sync::atomic::AtomicBool;
+use std::sync::atomic::AtomicUsize;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
 
@@ -9,12 +10,19 @@ use router_bridge::planner::UsageReporting;
 use serde_json_bytes::json;
 
 use super::*;
+use crate::json_ext::ArrayMergeStrategy;
 use crate::json_ext::Path;
 use crate::json_ext::PathElement;
+use crate::json_ext::Value;
 use crate::plugin::test::MockSubgraph;
 use crate::plugin::test::MockSubgraphFactory;
+use crate::plugins::subgraph_signing::SubgraphSigner;
+use crate::plugins::telemetry::metrics::AggregateMeterProvider;
+use crate::query_planner::execution::recv_broadcast;
 use crate::query_planner::fetch::FetchNode;
+use crate::query_planner::metrics::DeferMetrics;
 use crate::services::subgraph_service::MakeSubgraphService;
+use crate::services::subgraph_service::SubgraphService;
 
 macro_rules! test_query_plan {
     () => {
@@ -57,6 +65,7 @@ fn service_usage() {
 #[should_panic(expected = "this panic should be propagated to the test harness")]
 async fn mock_subgraph_service_withf_panics_should_be_reported_as_service_closed() {
     let query_plan: QueryPlan = QueryPlan {
+        summary: Default::default(),
         root: serde_json::from_str(test_query_plan!()).unwrap(),
         formatted_query_plan: Default::default(),
         options: QueryPlanOptions::default(),
@@ -93,7 +102,10 @@ async fn mock_subgraph_service_withf_panics_should_be_reported_as_service_closed
             &Context::new(),
             &sf,
             &Default::default(),
-            &Schema::parse(test_schema!(), &Default::default()).unwrap(),
+            &Arc::new(Schema::parse(test_schema!(), &Default::default()).unwrap()),
+            &Default::default(),
+            &Default::default(),
+            &Default::default(),
             sender,
         )
         .await;
@@ -107,6 +119,7 @@ async fn mock_subgraph_service_withf_panics_should_be_reported_as_service_closed
 #[tokio::test]
 async fn fetch_includes_operation_name() {
     let query_plan: QueryPlan = QueryPlan {
+        summary: Default::default(),
         root: serde_json::from_str(test_query_plan!()).unwrap(),
         formatted_query_plan: Default::default(),
         usage_reporting: UsageReporting {
@@ -151,7 +164,10 @@ async fn fetch_includes_operation_name() {
             &Context::new(),
             &sf,
             &Default::default(),
-            &Schema::parse(test_schema!(), &Default::default()).unwrap(),
+            &Arc::new(Schema::parse(test_schema!(), &Default::default()).unwrap()),
+            &Default::default(),
+            &Default::default(),
+            &Default::default(),
             sender,
         )
         .await;
@@ -162,6 +178,7 @@ async fn fetch_includes_operation_name() {
 #[tokio::test]
 async fn fetch_makes_post_requests() {
     let query_plan: QueryPlan = QueryPlan {
+        summary: Default::default(),
         root: serde_json::from_str(test_query_plan!()).unwrap(),
         formatted_query_plan: Default::default(),
         usage_reporting: UsageReporting {
@@ -206,7 +223,10 @@ async fn fetch_makes_post_requests() {
             &Context::new(),
             &sf,
             &Default::default(),
-            &Schema::parse(test_schema!(), &Default::default()).unwrap(),
+            &Arc::new(Schema::parse(test_schema!(), &Default::default()).unwrap()),
+            &Default::default(),
+            &Default::default(),
+            &Default::default(),
             sender,
         )
         .await;
@@ -217,74 +237,79 @@ async fn fetch_makes_post_requests() {
     );
 }
 
-#[tokio::test]
-async fn defer() {
-    // plan for { t { x ... @defer { y } }}
-    let query_plan: QueryPlan = QueryPlan {
-            formatted_query_plan: Default::default(),
-            root: PlanNode::Defer {
-                primary: Primary {
-                    path: None,
-                    subselection: Some("{ t { x } }".to_string()),
-                    node: Some(Box::new(PlanNode::Fetch(FetchNode {
-                        service_name: "X".to_string(),
-                        requires: vec![],
+// plan for { t { x ... @defer { y } }}
+fn defer_query_plan() -> QueryPlan {
+    QueryPlan {
+        summary: Default::default(),
+        formatted_query_plan: Default::default(),
+        root: PlanNode::Defer {
+            primary: Primary {
+                path: None,
+                subselection: Some("{ t { x } }".to_string()),
+                node: Some(Box::new(PlanNode::Fetch(FetchNode {
+                    service_name: "X".to_string(),
+                    requires: vec![],
+                    variable_usages: vec![],
+                    operation: "{ t { id __typename x } }".to_string(),
+                    operation_name: Some("t".to_string()),
+                    operation_kind: OperationKind::Query,
+                    id: Some("fetch1".to_string()),
+                }))),
+            },
+            deferred: vec![DeferredNode {
+                depends: vec![Depends {
+                    id: "fetch1".to_string(),
+                    defer_label: None,
+                }],
+                label: None,
+                path: Path(vec![PathElement::Key("t".to_string())]),
+                subselection: Some("{ y }".to_string()),
+                node: Some(Arc::new(PlanNode::Flatten(FlattenNode {
+                    path: Path(vec![PathElement::Key("t".to_string())]),
+                    node: Box::new(PlanNode::Fetch(FetchNode {
+                        service_name: "Y".to_string(),
+                        requires: vec![query_planner::selection::Selection::InlineFragment(
+                            query_planner::selection::InlineFragment {
+                                type_condition: Some("T".into()),
+                                selections: vec![
+                                    query_planner::selection::Selection::Field(
+                                        query_planner::selection::Field {
+                                            alias: None,
+                                            name: "id".into(),
+                                            selections: None,
+                                        },
+                                    ),
+                                    query_planner::selection::Selection::Field(
+                                        query_planner::selection::Field {
+                                            alias: None,
+                                            name: "__typename".into(),
+                                            selections: None,
+                                        },
+                                    ),
+                                ],
+                            },
+                        )],
                         variable_usages: vec![],
-                        operation: "{ t { id __typename x } }".to_string(),
-                        operation_name: Some("t".to_string()),
+                        operation: "query($representations:[_Any!]!){_entities(representations:$representations){...on T{y}}}".to_string(),
+                        operation_name: None,
                         operation_kind: OperationKind::Query,
-                        id: Some("fetch1".to_string()),
-                    }))),
-                },
-                deferred: vec![DeferredNode {
-                    depends: vec![Depends {
-                        id: "fetch1".to_string(),
-                        defer_label: None,
-                    }],
-                    label: None,
-                    path: Path(vec![PathElement::Key("t".to_string())]),
-                    subselection: Some("{ y }".to_string()),
-                    node: Some(Arc::new(PlanNode::Flatten(FlattenNode {
-                        path: Path(vec![PathElement::Key("t".to_string())]),
-                        node: Box::new(PlanNode::Fetch(FetchNode {
-                            service_name: "Y".to_string(),
-                            requires: vec![query_planner::selection::Selection::InlineFragment(
-                                query_planner::selection::InlineFragment {
-                                    type_condition: Some("T".into()),
-                                    selections: vec![
-                                        query_planner::selection::Selection::Field(
-                                            query_planner::selection::Field {
-                                                alias: None,
-                                                name: "id".into(),
-                                                selections: None,
-                                            },
-                                        ),
-                                        query_planner::selection::Selection::Field(
-                                            query_planner::selection::Field {
-                                                alias: None,
-                                                name: "__typename".into(),
-                                                selections: None,
-                                            },
-                                        ),
-                                    ],
-                                },
-                            )],
-                            variable_usages: vec![],
-                            operation: "query($representations:[_Any!]!){_entities(representations:$representations){...on T{y}}}".to_string(),
-                            operation_name: None,
-                            operation_kind: OperationKind::Query,
-                            id: Some("fetch2".to_string()),
-                        })),
-                    }))),
-                }],
-            },
-            usage_reporting: UsageReporting {
-                stats_report_key: "this is a test report key".to_string(),
-                referenced_fields_by_type: Default::default(),
-            },
-            query: Arc::new(Query::default()),
-            options: QueryPlanOptions::default(),
-        };
+                        id: Some("fetch2".to_string()),
+                    })),
+                }))),
+            }],
+        },
+        usage_reporting: UsageReporting {
+            stats_report_key: "this is a test report key".to_string(),
+            referenced_fields_by_type: Default::default(),
+        },
+        query: Arc::new(Query::default()),
+        options: QueryPlanOptions::default(),
+    }
+}
+
+#[tokio::test]
+async fn defer() {
+    let query_plan = defer_query_plan();
 
     let mut mock_x_service = plugin::test::MockSubgraphService::new();
     mock_x_service.expect_clone().return_once(|| {
@@ -326,7 +351,7 @@ async fn defer() {
     let (sender, mut receiver) = futures::channel::mpsc::channel(10);
 
     let schema = include_str!("testdata/defer_schema.graphql");
-    let schema = Schema::parse(schema, &Default::default()).unwrap();
+    let schema = Arc::new(Schema::parse(schema, &Default::default()).unwrap());
     let sf = Arc::new(MockSubgraphFactory {
         subgraphs: HashMap::from([
             (
@@ -342,7 +367,16 @@ async fn defer() {
     });
 
     let response = query_plan
-        .execute(&Context::new(), &sf, &Default::default(), &schema, sender)
+        .execute(
+            &Context::new(),
+            &sf,
+            &Default::default(),
+            &schema,
+            &Default::default(),
+            &Default::default(),
+            &Default::default(),
+            sender,
+        )
         .await;
 
     // primary response
@@ -362,6 +396,178 @@ async fn defer() {
     );
 }
 
+#[tokio::test]
+async fn defer_shares_context() {
+    let query_plan = defer_query_plan();
+
+    let mut mock_x_service = plugin::test::MockSubgraphService::new();
+    mock_x_service.expect_clone().return_once(|| {
+        let mut mock_x_service = plugin::test::MockSubgraphService::new();
+        mock_x_service.expect_call().times(1).returning(|request| {
+            // inserted after the deferred node was started
+            request
+                .context
+                .insert("primary", "done".to_string())
+                .unwrap();
+            Ok(SubgraphResponse::fake_builder()
+                .data(serde_json::json! {{
+                    "t": {"id": 1234, "__typename": "T", "x": "X"}
+                }})
+                .build())
+        });
+        mock_x_service
+    });
+
+    let mut mock_y_service = plugin::test::MockSubgraphService::new();
+    mock_y_service.expect_clone().return_once(|| {
+        let mut mock_y_service = plugin::test::MockSubgraphService::new();
+        mock_y_service
+            .expect_call()
+            .times(1)
+            .withf(|request| {
+                request.context.get::<_, String>("primary").unwrap() == Some("done".to_string())
+            })
+            .returning(|_| {
+                Ok(SubgraphResponse::fake_builder()
+                    .data(serde_json::json! {{
+                        "_entities": [{"y": "Y", "__typename": "T"}]
+                    }})
+                    .build())
+            });
+        mock_y_service
+    });
+
+    let (sender, mut receiver) = futures::channel::mpsc::channel(10);
+
+    let schema = include_str!("testdata/defer_schema.graphql");
+    let schema = Arc::new(Schema::parse(schema, &Default::default()).unwrap());
+    let sf = Arc::new(MockSubgraphFactory {
+        subgraphs: HashMap::from([
+            (
+                "X".into(),
+                Arc::new(mock_x_service) as Arc<dyn MakeSubgraphService>,
+            ),
+            (
+                "Y".into(),
+                Arc::new(mock_y_service) as Arc<dyn MakeSubgraphService>,
+            ),
+        ]),
+        plugins: Default::default(),
+    });
+
+    let context = Context::new();
+    query_plan
+        .execute(
+            &context,
+            &sf,
+            &Default::default(),
+            &schema,
+            &Default::default(),
+            &Default::default(),
+            &Default::default(),
+            sender,
+        )
+        .await;
+
+    // the deferred fetch was made with the context updated by the primary fetch
+    let response = receiver.next().await.unwrap();
+    assert!(response.errors.is_empty());
+    assert_eq!(
+        context.get::<_, String>("primary").unwrap(),
+        Some("done".to_string())
+    );
+}
+
+#[tokio::test]
+async fn defer_propagates_the_configured_context_keys() {
+    let mut query_plan = defer_query_plan();
+    query_plan.options.defer_context_keys = Some(vec!["propagated".to_string()]);
+
+    let mut mock_x_service = plugin::test::MockSubgraphService::new();
+    mock_x_service.expect_clone().return_once(|| {
+        let mut mock_x_service = plugin::test::MockSubgraphService::new();
+        mock_x_service.expect_call().times(1).returning(|_| {
+            Ok(SubgraphResponse::fake_builder()
+                .data(serde_json::json! {{
+                    "t": {"id": 1234, "__typename": "T", "x": "X"}
+                }})
+                .build())
+        });
+        mock_x_service
+    });
+
+    let mut mock_y_service = plugin::test::MockSubgraphService::new();
+    mock_y_service.expect_clone().return_once(|| {
+        let mut mock_y_service = plugin::test::MockSubgraphService::new();
+        mock_y_service
+            .expect_call()
+            .times(1)
+            .withf(|request| {
+                request.context.get::<_, String>("propagated").unwrap() == Some("yes".to_string())
+                    && request.context.get::<_, String>("apollo_test").unwrap()
+                        == Some("yes".to_string())
+                    && request
+                        .context
+                        .get::<_, String>("private")
+                        .unwrap()
+                        .is_none()
+            })
+            .returning(|request| {
+                request
+                    .context
+                    .insert("deferred", "done".to_string())
+                    .unwrap();
+                Ok(SubgraphResponse::fake_builder()
+                    .data(serde_json::json! {{
+                        "_entities": [{"y": "Y", "__typename": "T"}]
+                    }})
+                    .build())
+            });
+        mock_y_service
+    });
+
+    let (sender, mut receiver) = futures::channel::mpsc::channel(10);
+
+    let schema = include_str!("testdata/defer_schema.graphql");
+    let schema = Arc::new(Schema::parse(schema, &Default::default()).unwrap());
+    let sf = Arc::new(MockSubgraphFactory {
+        subgraphs: HashMap::from([
+            (
+                "X".into(),
+                Arc::new(mock_x_service) as Arc<dyn MakeSubgraphService>,
+            ),
+            (
+                "Y".into(),
+                Arc::new(mock_y_service) as Arc<dyn MakeSubgraphService>,
+            ),
+        ]),
+        plugins: Default::default(),
+    });
+
+    let context = Context::new();
+    for key in ["propagated", "private", "apollo_test"] {
+        context.insert(key, "yes".to_string()).unwrap();
+    }
+    query_plan
+        .execute(
+            &context,
+            &sf,
+            &Default::default(),
+            &schema,
+            &Default::default(),
+            &Default::default(),
+            &Default::default(),
+            sender,
+        )
+        .await;
+
+    // the deferred fetch only saw the configured keys and the router's own entries, and wrote
+    // to its own copy of the context
+    let response = receiver.next().await.unwrap();
+    assert!(response.errors.is_empty());
+    assert!(context.get::<_, String>("deferred").unwrap().is_none());
+}
+
 #[tokio::test]
 async fn defer_if_condition() {
     let query = r#"
@@ -376,12 +582,13 @@ async fn defer_if_condition() {
           }"#;
 
     let schema = include_str!("testdata/defer_clause.graphql");
-    let schema = Schema::parse(schema, &Default::default()).unwrap();
+    let schema = Arc::new(Schema::parse(schema, &Default::default()).unwrap());
 
     let root: PlanNode =
         serde_json::from_str(include_str!("testdata/defer_clause_plan.json")).unwrap();
 
     let query_plan = QueryPlan {
+        summary: Default::default(),
         root,
         usage_reporting: UsageReporting {
             stats_report_key: "this is a test report key".to_string(),
@@ -439,6 +646,9 @@ async fn defer_if_condition() {
                     .unwrap(),
             ),
             &schema,
+            &Default::default(),
+            &Default::default(),
+            &Default::default(),
             sender,
         )
         .await;
@@ -457,6 +667,9 @@ async fn defer_if_condition() {
             &service_factory,
             &Default::default(),
             &schema,
+            &Default::default(),
+            &Default::default(),
+            &Default::default(),
             default_sender,
         )
         .await;
@@ -481,6 +694,9 @@ async fn defer_if_condition() {
                     .unwrap(),
             ),
             &schema,
+            &Default::default(),
+            &Default::default(),
+            &Default::default(),
             sender,
         )
         .await;
@@ -520,6 +736,7 @@ async fn dependent_mutations() {
       }"#;
 
     let query_plan: QueryPlan = QueryPlan {
+        summary: Default::default(),
         // generated from:
         // mutation {
         //   mutationA {
@@ -598,8 +815,1097 @@ async fn dependent_mutations() {
             &Context::new(),
             &sf,
             &Default::default(),
-            &Schema::parse(schema, &Default::default()).unwrap(),
+            &Arc::new(Schema::parse(schema, &Default::default()).unwrap()),
+            &Default::default(),
+            &Default::default(),
+            &Default::default(),
             sender,
         )
         .await;
 }
+
+async fn execute_with_response_depth_limit(max_response_depth: Option<usize>) -> graphql::Response {
+    let query_plan: QueryPlan = QueryPlan {
+        summary: Default::default(),
+        formatted_query_plan: Default::default(),
+        root: PlanNode::Fetch(FetchNode {
+            service_name: "X".to_string(),
+            requires: vec![],
+            variable_usages: vec![],
+            operation: "{ t { id x } }".to_string(),
+            operation_name: Some("t".to_string()),
+            operation_kind: OperationKind::Query,
+            id: None,
+        }),
+        usage_reporting: UsageReporting {
+            stats_report_key: "this is a test report key".to_string(),
+            referenced_fields_by_type: Default::default(),
+        },
+        query: Arc::new(Query::default()),
+        options: QueryPlanOptions::default(),
+    };
+
+    let mut mock_x_service = plugin::test::MockSubgraphService::new();
+    mock_x_service.expect_clone().return_once(|| {
+        let mut mock_x_service = plugin::test::MockSubgraphService::new();
+        mock_x_service.expect_call().times(1).returning(|_| {
+            Ok(SubgraphResponse::fake_builder()
+                .data(serde_json::json! {{
+                    "t": {
+                        "id": 1234,
+                        "x": [{ "nested": { "deeper": { "deepest": "X" } } }]
+                    }
+                }})
+                .build())
+        });
+        mock_x_service
+    });
+
+    let (sender, _) = futures::channel::mpsc::channel(10);
+
+    let schema = include_str!("testdata/defer_schema.graphql");
+    let schema = Arc::new(Schema::parse(schema, &Default::default()).unwrap());
+    let sf = Arc::new(MockSubgraphFactory {
+        subgraphs: HashMap::from([(
+            "X".into(),
+            Arc::new(mock_x_service) as Arc<dyn MakeSubgraphService>,
+        )]),
+        plugins: Default::default(),
+    });
+
+    query_plan
+        .execute(
+            &Context::new(),
+            &sf,
+            &Default::default(),
+            &schema,
+            &Default::default(),
+            &Default::default(),
+            &ExecutionOptions {
+                max_response_depth,
+                ..Default::default()
+            },
+            sender,
+        )
+        .await
+}
+
+#[tokio::test]
+async fn response_depth_limit() {
+    // unbounded by default
+    let response = execute_with_response_depth_limit(None).await;
+    assert!(response.errors.is_empty());
+    assert_eq!(
+        serde_json::to_value(&response.data).unwrap(),
+        serde_json::json! {{
+            "t": { "id": 1234, "x": [{ "nested": { "deeper": { "deepest": "X" } } }] }
+        }}
+    );
+
+    // the data fits exactly in the limit
+    let response = execute_with_response_depth_limit(Some(6)).await;
+    assert!(response.errors.is_empty());
+
+    // nested values over the limit are truncated
+    let response = execute_with_response_depth_limit(Some(3)).await;
+    assert_eq!(
+        serde_json::to_value(&response.data).unwrap(),
+        serde_json::json! {{
+            "t": { "id": 1234, "x": [null] }
+        }}
+    );
+    assert_eq!(response.errors.len(), 1);
+    assert_eq!(
+        response.errors[0].message,
+        "Subgraph response is nested deeper than the limit of 3 levels"
+    );
+}
+
+async fn execute_with_subgraph_fetch_limit(
+    max_subgraph_fetches: Option<usize>,
+    fetched: &[&str],
+) -> graphql::Response {
+    let fetch = |service_name: &str, operation: &str| {
+        PlanNode::Fetch(FetchNode {
+            service_name: service_name.to_string(),
+            requires: vec![],
+            variable_usages: vec![],
+            operation: operation.to_string(),
+            operation_name: None,
+            operation_kind: OperationKind::Query,
+            id: None,
+        })
+    };
+    let query_plan: QueryPlan = QueryPlan {
+        summary: Default::default(),
+        formatted_query_plan: Default::default(),
+        root: PlanNode::Sequence {
+            nodes: vec![
+                fetch("X", "{ t { id } }"),
+                PlanNode::Parallel {
+                    nodes: vec![fetch("Y", "{ t { x } }"), fetch("Z", "{ t { y } }")],
+                },
+            ],
+        },
+        usage_reporting: UsageReporting {
+            stats_report_key: "this is a test report key".to_string(),
+            referenced_fields_by_type: Default::default(),
+        },
+        query: Arc::new(Query::default()),
+        options: QueryPlanOptions {
+            max_subgraph_fetches,
+            ..Default::default()
+        },
+    };
+
+    let subgraphs = [
+        ("X", json! {{ "t": { "id": 1234 } }}),
+        ("Y", json! {{ "t": { "x": "X" } }}),
+        ("Z", json! {{ "t": { "y": "Y" } }}),
+    ]
+    .into_iter()
+    .map(|(name, data)| {
+        // a subgraph that is not fetched has no expectations, and panics if it is used
+        let mut mock_service = plugin::test::MockSubgraphService::new();
+        if fetched.contains(&name) {
+            mock_service.expect_clone().return_once(move || {
+                let mut mock_service = plugin::test::MockSubgraphService::new();
+                mock_service.expect_call().times(1).returning(move |_| {
+                    Ok(SubgraphResponse::fake_builder().data(data.clone()).build())
+                });
+                mock_service
+            });
+        }
+        (
+            name.to_string(),
+            Arc::new(mock_service) as Arc<dyn MakeSubgraphService>,
+        )
+    })
+    .collect();
+
+    let (sender, _) = futures::channel::mpsc::channel(10);
+    let sf = Arc::new(MockSubgraphFactory {
+        subgraphs,
+        plugins: Default::default(),
+    });
+
+    query_plan
+        .execute(
+            &Context::new(),
+            &sf,
+            &Default::default(),
+            &Arc::new(Schema::parse(test_schema!(), &Default::default()).unwrap()),
+            &Default::default(),
+            &Default::default(),
+            &Default::default(),
+            sender,
+        )
+        .await
+}
+
+#[tokio::test]
+async fn subgraph_fetch_limit() {
+    // unbounded by default
+    let response = execute_with_subgraph_fetch_limit(None, &["X", "Y", "Z"]).await;
+    assert!(response.errors.is_empty());
+    assert_eq!(
+        serde_json::to_value(&response.data).unwrap(),
+        serde_json::json! {{ "t": { "id": 1234, "x": "X", "y": "Y" } }}
+    );
+
+    // the plan fits exactly in the limit
+    let response = execute_with_subgraph_fetch_limit(Some(3), &["X", "Y", "Z"]).await;
+    assert!(response.errors.is_empty());
+
+    // the count accumulates across the sequence and parallel nodes, the fetches over the
+    // limit are not sent and the request is aborted without its partial data
+    let response = execute_with_subgraph_fetch_limit(Some(1), &["X"]).await;
+    assert_eq!(response.data, None);
+    assert_eq!(response.errors.len(), 1);
+    assert_eq!(
+        response.errors[0].message,
+        "Request exceeded the limit of 1 subgraph fetches"
+    );
+    assert_eq!(
+        response.errors[0].extensions.get("code"),
+        Some(&serde_json_bytes::Value::from("SUBGRAPH_FETCH_LIMIT"))
+    );
+}
+
+/// Executes a plan where two parallel fetches return the same entities in a different order
+async fn execute_with_array_merge_strategy(
+    array_merge_strategy: ArrayMergeStrategy,
+) -> graphql::Response {
+    let fetch = |service_name: &str, operation: &str| {
+        PlanNode::Fetch(FetchNode {
+            service_name: service_name.to_string(),
+            requires: vec![],
+            variable_usages: vec![],
+            operation: operation.to_string(),
+            operation_name: None,
+            operation_kind: OperationKind::Query,
+            id: None,
+        })
+    };
+    let query_plan: QueryPlan = QueryPlan {
+        summary: Default::default(),
+        formatted_query_plan: Default::default(),
+        root: PlanNode::Parallel {
+            nodes: vec![fetch("X", "{ ts { id x } }"), fetch("Y", "{ ts { id y } }")],
+        },
+        usage_reporting: UsageReporting {
+            stats_report_key: "this is a test report key".to_string(),
+            referenced_fields_by_type: Default::default(),
+        },
+        query: Arc::new(Query::default()),
+        options: QueryPlanOptions {
+            array_merge_strategy,
+            ..Default::default()
+        },
+    };
+
+    let subgraphs = [
+        (
+            "X",
+            json! {{ "ts": [{ "id": 1, "x": "X1" }, { "id": 2, "x": "X2" }] }},
+        ),
+        (
+            "Y",
+            json! {{ "ts": [{ "id": 2, "y": "Y2" }, { "id": 1, "y": "Y1" }] }},
+        ),
+    ]
+    .into_iter()
+    .map(|(name, data)| {
+        let mut mock_service = plugin::test::MockSubgraphService::new();
+        mock_service.expect_clone().return_once(move || {
+            let mut mock_service = plugin::test::MockSubgraphService::new();
+            mock_service.expect_call().times(1).returning(move |_| {
+                Ok(SubgraphResponse::fake_builder().data(data.clone()).build())
+            });
+            mock_service
+        });
+        (
+            name.to_string(),
+            Arc::new(mock_service) as Arc<dyn MakeSubgraphService>,
+        )
+    })
+    .collect();
+
+    let (sender, _) = futures::channel::mpsc::channel(10);
+    let sf = Arc::new(MockSubgraphFactory {
+        subgraphs,
+        plugins: Default::default(),
+    });
+
+    query_plan
+        .execute(
+            &Context::new(),
+            &sf,
+            &Default::default(),
+            &Arc::new(
+                Schema::parse(
+                    include_str!("testdata/defer_schema.graphql"),
+                    &Default::default(),
+                )
+                .unwrap(),
+            ),
+            &Default::default(),
+            &Default::default(),
+            &Default::default(),
+            sender,
+        )
+        .await
+}
+
+#[tokio::test]
+async fn array_merge_strategy() {
+    // by default, the entities at the same index are merged, whatever their id
+    let response = execute_with_array_merge_strategy(ArrayMergeStrategy::default()).await;
+    let ts = response.data.as_ref().unwrap().get("ts").unwrap();
+    assert_eq!(ts.as_array().unwrap().len(), 2);
+    assert!(ts
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|t| t.get("x") == Some(&json!("X1")) && t.get("y") == Some(&json!("Y2"))));
+
+    // merged by key, each entity gets the fields fetched for it
+    let response =
+        execute_with_array_merge_strategy(ArrayMergeStrategy::ByKey("id".to_string())).await;
+    let ts = response.data.as_ref().unwrap().get("ts").unwrap();
+    assert_eq!(ts.as_array().unwrap().len(), 2);
+    for (id, x, y) in [(1, "X1", "Y1"), (2, "X2", "Y2")] {
+        assert!(ts
+            .as_array()
+            .unwrap()
+            .contains(&json!({ "id": id, "x": x, "y": y })));
+    }
+}
+
+#[tokio::test]
+async fn array_merge_strategy_does_not_apply_to_entities() {
+    let schema = include_str!("testdata/defer_schema.graphql");
+    let schema = Arc::new(Schema::parse(schema, &Default::default()).unwrap());
+    let query_plan = QueryPlan {
+        summary: Default::default(),
+        formatted_query_plan: Default::default(),
+        root: PlanNode::Sequence {
+            nodes: vec![
+                PlanNode::Fetch(FetchNode {
+                    service_name: "X".to_string(),
+                    requires: vec![],
+                    variable_usages: vec![],
+                    operation: "{ ts { id __typename x } }".to_string(),
+                    operation_name: None,
+                    operation_kind: OperationKind::Query,
+                    id: None,
+                }),
+                PlanNode::Flatten(FlattenNode {
+                    path: Path::from("ts/@"),
+                    node: Box::new(PlanNode::Fetch(FetchNode {
+                        service_name: "Y".to_string(),
+                        requires: vec![query_planner::selection::Selection::InlineFragment(
+                            query_planner::selection::InlineFragment {
+                                type_condition: Some("T".into()),
+                                selections: vec![
+                                    query_planner::selection::Selection::Field(
+                                        query_planner::selection::Field {
+                                            alias: None,
+                                            name: "id".into(),
+                                            selections: None,
+                                        },
+                                    ),
+                                    query_planner::selection::Selection::Field(
+                                        query_planner::selection::Field {
+                                            alias: None,
+                                            name: "__typename".into(),
+                                            selections: None,
+                                        },
+                                    ),
+                                ],
+                            },
+                        )],
+                        variable_usages: vec![],
+                        operation: "query($representations:[_Any!]!){_entities(representations:$representations){...on T{y}}}".to_string(),
+                        operation_name: None,
+                        operation_kind: OperationKind::Query,
+                        id: None,
+                    })),
+                }),
+            ],
+        },
+        usage_reporting: UsageReporting {
+            stats_report_key: "this is a test report key".to_string(),
+            referenced_fields_by_type: Default::default(),
+        },
+        query: Arc::new(Query::default()),
+        options: QueryPlanOptions {
+            array_merge_strategy: ArrayMergeStrategy::Concatenate,
+            ..Default::default()
+        },
+    };
+
+    let subgraphs = [
+        (
+            "X",
+            json! {{ "ts": [
+                { "id": 1, "__typename": "T", "x": "X1" },
+                { "id": 2, "__typename": "T", "x": "X2" }
+            ] }},
+        ),
+        ("Y", json! {{ "_entities": [{ "y": "Y1" }, { "y": "Y2" }] }}),
+    ]
+    .into_iter()
+    .map(|(name, data)| {
+        let mut mock_service = plugin::test::MockSubgraphService::new();
+        mock_service.expect_clone().return_once(move || {
+            let mut mock_service = plugin::test::MockSubgraphService::new();
+            mock_service.expect_call().times(1).returning(move |_| {
+                Ok(SubgraphResponse::fake_builder().data(data.clone()).build())
+            });
+            mock_service
+        });
+        (
+            name.to_string(),
+            Arc::new(mock_service) as Arc<dyn MakeSubgraphService>,
+        )
+    })
+    .collect();
+
+    let (sender, _) = futures::channel::mpsc::channel(10);
+    let sf = Arc::new(MockSubgraphFactory {
+        subgraphs,
+        plugins: Default::default(),
+    });
+
+    let response = query_plan
+        .execute(
+            &Context::new(),
+            &sf,
+            &Default::default(),
+            &schema,
+            &Default::default(),
+            &Default::default(),
+            &Default::default(),
+            sender,
+        )
+        .await;
+
+    // the entities are merged at their index, not concatenated
+    let ts = response.data.as_ref().unwrap().get("ts").unwrap();
+    assert_eq!(ts.as_array().unwrap().len(), 2);
+    for (id, x, y) in [(1, "X1", "Y1"), (2, "X2", "Y2")] {
+        assert!(ts
+            .as_array()
+            .unwrap()
+            .contains(&json!({ "id": id, "__typename": "T", "x": x, "y": y })));
+    }
+}
+
+/// Executes a plan where the fetch of `t` from the subgraph X succeeds, and the fetch of
+/// `y_selection` on the entities from the subgraph Y fails
+async fn execute_with_failed_entities_fetch(
+    y_selection: &str,
+    query: &str,
+    fallbacks: Vec<(Path, Value)>,
+) -> graphql::Response {
+    let schema = include_str!("testdata/defer_schema.graphql");
+    let schema = Arc::new(Schema::parse(schema, &Default::default()).unwrap());
+    let query_plan = QueryPlan {
+        summary: Default::default(),
+        formatted_query_plan: Default::default(),
+        root: PlanNode::Sequence {
+            nodes: vec![
+                PlanNode::Fetch(FetchNode {
+                    service_name: "X".to_string(),
+                    requires: vec![],
+                    variable_usages: vec![],
+                    operation: "{ t { id __typename x } }".to_string(),
+                    operation_name: Some("t".to_string()),
+                    operation_kind: OperationKind::Query,
+                    id: None,
+                }),
+                PlanNode::Flatten(FlattenNode {
+                    path: Path(vec![PathElement::Key("t".to_string())]),
+                    node: Box::new(PlanNode::Fetch(FetchNode {
+                        service_name: "Y".to_string(),
+                        requires: vec![query_planner::selection::Selection::InlineFragment(
+                            query_planner::selection::InlineFragment {
+                                type_condition: Some("T".into()),
+                                selections: vec![
+                                    query_planner::selection::Selection::Field(
+                                        query_planner::selection::Field {
+                                            alias: None,
+                                            name: "id".into(),
+                                            selections: None,
+                                        },
+                                    ),
+                                    query_planner::selection::Selection::Field(
+                                        query_planner::selection::Field {
+                                            alias: None,
+                                            name: "__typename".into(),
+                                            selections: None,
+                                        },
+                                    ),
+                                ],
+                            },
+                        )],
+                        variable_usages: vec![],
+                        operation: format!("query($representations:[_Any!]!){{_entities(representations:$representations){{...on T{{{}}}}}}}", y_selection),
+                        operation_name: None,
+                        operation_kind: OperationKind::Query,
+                        id: None,
+                    })),
+                }),
+            ],
+        },
+        usage_reporting: UsageReporting {
+            stats_report_key: "this is a test report key".to_string(),
+            referenced_fields_by_type: Default::default(),
+        },
+        query: Arc::new(Query::parse(query, &schema, &Default::default()).unwrap()),
+        options: QueryPlanOptions::default(),
+    };
+
+    let mut mock_x_service = plugin::test::MockSubgraphService::new();
+    mock_x_service.expect_clone().return_once(|| {
+        let mut mock_x_service = plugin::test::MockSubgraphService::new();
+        mock_x_service.expect_call().times(1).returning(|_| {
+            Ok(SubgraphResponse::fake_builder()
+                .data(serde_json::json! {{
+                    "t": { "id": 1234, "__typename": "T", "x": "X" }
+                }})
+                .build())
+        });
+        mock_x_service
+    });
+    let mut mock_y_service = plugin::test::MockSubgraphService::new();
+    mock_y_service.expect_clone().return_once(|| {
+        let mut mock_y_service = plugin::test::MockSubgraphService::new();
+        mock_y_service
+            .expect_call()
+            .times(1)
+            .returning(|_| Err("subgraph Y is down".into()));
+        mock_y_service
+    });
+
+    let (sender, _) = futures::channel::mpsc::channel(10);
+    let sf = Arc::new(MockSubgraphFactory {
+        subgraphs: HashMap::from([
+            (
+                "X".into(),
+                Arc::new(mock_x_service) as Arc<dyn MakeSubgraphService>,
+            ),
+            (
+                "Y".into(),
+                Arc::new(mock_y_service) as Arc<dyn MakeSubgraphService>,
+            ),
+        ]),
+        plugins: Default::default(),
+    });
+
+    query_plan
+        .execute(
+            &Context::new(),
+            &sf,
+            &Default::default(),
+            &schema,
+            &Default::default(),
+            &Default::default(),
+            &ExecutionOptions {
+                fallbacks,
+                ..Default::default()
+            },
+            sender,
+        )
+        .await
+}
+
+#[tokio::test]
+async fn fetch_failure_fallbacks() {
+    let response = execute_with_failed_entities_fetch(
+        "y",
+        "{ t { id x y } }",
+        vec![
+            (Path::from("t/y"), json!("unavailable")),
+            // x was returned by the first fetch and is kept
+            (Path::from("t/x"), json!("ignored")),
+            // not selected by the query
+            (Path::from("t/z"), json!("ignored")),
+        ],
+    )
+    .await;
+    assert!(response.errors.is_empty());
+    assert_eq!(
+        serde_json::to_value(&response.data).unwrap(),
+        serde_json::json! {{
+            "t": { "id": 1234, "__typename": "T", "x": "X", "y": "unavailable" }
+        }}
+    );
+}
+
+#[tokio::test]
+async fn fetch_failure_fallbacks_keep_the_error_of_the_other_fields() {
+    let response = execute_with_failed_entities_fetch(
+        "y z",
+        "{ t { id x y z } }",
+        vec![(Path::from("t/y"), json!("unavailable"))],
+    )
+    .await;
+    // z has no fallback and is still reported as failed
+    assert_eq!(response.errors.len(), 1);
+    assert_eq!(response.errors[0].path, Some(Path::from("t")));
+    assert_eq!(
+        serde_json::to_value(&response.data).unwrap(),
+        serde_json::json! {{
+            "t": { "id": 1234, "__typename": "T", "x": "X", "y": "unavailable" }
+        }}
+    );
+}
+
+#[tokio::test]
+async fn failed_fetch_skips_dependent_fetches() {
+    // the entity fetches depending on the first fetch are in sequences nested in a parallel node
+    let query_plan = QueryPlan {
+        summary: Default::default(),
+        root: serde_json::from_str(test_query_plan!()).unwrap(),
+        formatted_query_plan: Default::default(),
+        usage_reporting: UsageReporting {
+            stats_report_key: "this is a test report key".to_string(),
+            referenced_fields_by_type: Default::default(),
+        },
+        query: Arc::new(Query::default()),
+        options: QueryPlanOptions {
+            // skipped fetches are not counted
+            max_subgraph_fetches: Some(1),
+            ..Default::default()
+        },
+    };
+
+    let mut mock_products_service = plugin::test::MockSubgraphService::new();
+    mock_products_service.expect_clone().return_once(|| {
+        let mut mock_products_service = plugin::test::MockSubgraphService::new();
+        mock_products_service
+            .expect_call()
+            .times(1)
+            .returning(|_| Err("subgraph product is down".into()));
+        mock_products_service
+    });
+    // the first fetch failed, so there should never be a call to books
+    let mut mock_books_service = plugin::test::MockSubgraphService::new();
+    mock_books_service.expect_clone().never();
+
+    let (sender, _) = futures::channel::mpsc::channel(10);
+    let sf = Arc::new(MockSubgraphFactory {
+        subgraphs: HashMap::from([
+            (
+                "product".into(),
+                Arc::new(mock_products_service) as Arc<dyn MakeSubgraphService>,
+            ),
+            (
+                "books".into(),
+                Arc::new(mock_books_service) as Arc<dyn MakeSubgraphService>,
+            ),
+        ]),
+        plugins: Default::default(),
+    });
+
+    let response = query_plan
+        .execute(
+            &Context::new(),
+            &sf,
+            &Default::default(),
+            &Arc::new(Schema::parse(test_schema!(), &Default::default()).unwrap()),
+            &Default::default(),
+            &Default::default(),
+            &Default::default(),
+            sender,
+        )
+        .await;
+    // only the error of the failed fetch is returned
+    assert_eq!(response.errors.len(), 1);
+    assert!(response.errors[0]
+        .message
+        .contains("subgraph product is down"));
+}
+
+#[tokio::test]
+async fn deferred_broadcast_lag_is_counted() {
+    let exporter = opentelemetry_prometheus::exporter().init();
+    let meter_provider =
+        AggregateMeterProvider::new(vec![Arc::new(exporter.provider().expect("meter provider"))]);
+    let defer_metrics = DeferMetrics::new(&meter_provider);
+
+    // deferred execution uses channels with a capacity of 1 and sends a single value,
+    // a larger channel flooded with values makes the receiver lag behind
+    let (sender, mut receiver) = tokio::sync::broadcast::channel(2);
+    for i in 0..5 {
+        sender.send(i).unwrap();
+    }
+    assert_eq!(
+        recv_broadcast(&mut receiver, "primary", &defer_metrics).await,
+        Some(3)
+    );
+    assert_eq!(
+        recv_broadcast(&mut receiver, "primary", &defer_metrics).await,
+        Some(4)
+    );
+    drop(sender);
+    assert_eq!(
+        recv_broadcast(&mut receiver, "primary", &defer_metrics).await,
+        None
+    );
+
+    let lagged: f64 = exporter
+        .registry()
+        .gather()
+        .iter()
+        .filter(|family| {
+            family
+                .get_name()
+                .starts_with("apollo_router_defer_channel_lagged")
+        })
+        .flat_map(|family| family.get_metric())
+        .map(|metric| metric.get_counter().get_value())
+        .sum();
+    assert_eq!(lagged, 1.0);
+}
+
+#[tokio::test]
+async fn large_integers_are_preserved() {
+    let query_plan = QueryPlan {
+        summary: Default::default(),
+        formatted_query_plan: Default::default(),
+        root: PlanNode::Fetch(FetchNode {
+            service_name: "X".to_string(),
+            requires: vec![],
+            variable_usages: vec![],
+            operation: "{ t { id } }".to_string(),
+            operation_name: Some("t".to_string()),
+            operation_kind: OperationKind::Query,
+            id: None,
+        }),
+        usage_reporting: UsageReporting {
+            stats_report_key: "this is a test report key".to_string(),
+            referenced_fields_by_type: Default::default(),
+        },
+        query: Arc::new(Query::default()),
+        options: QueryPlanOptions::default(),
+    };
+
+    let mut mock_x_service = plugin::test::MockSubgraphService::new();
+    mock_x_service.expect_clone().return_once(|| {
+        let mut mock_x_service = plugin::test::MockSubgraphService::new();
+        mock_x_service.expect_call().times(1).returning(|_| {
+            Ok(SubgraphResponse::fake_builder()
+                .data(
+                    // 2^53 + 1 and the largest 64 bits integers, which are not exact as doubles
+                    serde_json::from_str::<serde_json::Value>(
+                        r#"{ "t": { "id": 9007199254740993, "min": -9223372036854775808, "max": 18446744073709551615 } }"#,
+                    )
+                    .unwrap(),
+                )
+                .build())
+        });
+        mock_x_service
+    });
+
+    let (sender, _) = futures::channel::mpsc::channel(10);
+    let schema = include_str!("testdata/defer_schema.graphql");
+    let schema = Arc::new(Schema::parse(schema, &Default::default()).unwrap());
+    let sf = Arc::new(MockSubgraphFactory {
+        subgraphs: HashMap::from([(
+            "X".into(),
+            Arc::new(mock_x_service) as Arc<dyn MakeSubgraphService>,
+        )]),
+        plugins: Default::default(),
+    });
+
+    let response = query_plan
+        .execute(
+            &Context::new(),
+            &sf,
+            &Default::default(),
+            &schema,
+            &Default::default(),
+            &Default::default(),
+            &Default::default(),
+            sender,
+        )
+        .await;
+    assert_eq!(
+        serde_json::to_string(&response.data).unwrap(),
+        r#"{"t":{"id":9007199254740993,"min":-9223372036854775808,"max":18446744073709551615}}"#
+    );
+}
+
+async fn execute_without_subgraphs(
+    root: PlanNode,
+) -> (
+    graphql::Response,
+    futures::channel::mpsc::Receiver<graphql::Response>,
+) {
+    let query_plan = QueryPlan {
+        summary: Default::default(),
+        formatted_query_plan: Default::default(),
+        root,
+        usage_reporting: UsageReporting {
+            stats_report_key: "this is a test report key".to_string(),
+            referenced_fields_by_type: Default::default(),
+        },
+        query: Arc::new(Query::default()),
+        options: QueryPlanOptions::default(),
+    };
+    let schema = include_str!("testdata/defer_schema.graphql");
+    let schema = Arc::new(Schema::parse(schema, &Default::default()).unwrap());
+    let sf = Arc::new(MockSubgraphFactory {
+        subgraphs: HashMap::new(),
+        plugins: Default::default(),
+    });
+    let (sender, receiver) = futures::channel::mpsc::channel(10);
+
+    let response = query_plan
+        .execute(
+            &Context::new(),
+            &sf,
+            &Default::default(),
+            &schema,
+            &Default::default(),
+            &Default::default(),
+            &Default::default(),
+            sender,
+        )
+        .await;
+    (response, receiver)
+}
+
+#[tokio::test]
+async fn plan_without_fetches_has_empty_data() {
+    let (response, _) = execute_without_subgraphs(PlanNode::Sequence { nodes: vec![] }).await;
+
+    assert_eq!(
+        serde_json::to_value(&response).unwrap(),
+        serde_json::json! {{"data":{}}}
+    );
+}
+
+// plan for { ... @defer { t @skip(if: true) { x } } }, with nothing left to fetch
+#[tokio::test]
+async fn deferred_plan_without_fetches_has_empty_data() {
+    let (response, mut receiver) = execute_without_subgraphs(PlanNode::Defer {
+        primary: Primary {
+            path: None,
+            subselection: None,
+            node: None,
+        },
+        deferred: vec![DeferredNode {
+            depends: vec![],
+            label: None,
+            path: Path::default(),
+            subselection: None,
+            node: None,
+        }],
+    })
+    .await;
+
+    // primary response
+    assert_eq!(
+        serde_json::to_value(&response).unwrap(),
+        serde_json::json! {{"data":{}}}
+    );
+
+    // deferred response
+    let response = receiver.next().await.unwrap();
+    assert_eq!(response.data, Some(json! {{}}));
+    assert!(response.errors.is_empty());
+    assert!(receiver.next().await.is_none());
+}
+
+fn fetch_t(operation: &str) -> PlanNode {
+    PlanNode::Fetch(FetchNode {
+        service_name: "X".to_string(),
+        requires: vec![],
+        variable_usages: vec![],
+        operation: operation.to_string(),
+        operation_name: None,
+        operation_kind: OperationKind::Query,
+        id: None,
+    })
+}
+
+/// Executes two parallel fetches to a subgraph answering batches, which checks the headers and
+/// the body of its requests, and returns the response and the number of HTTP requests
+async fn execute_batched_fetches(
+    subgraph_service: SubgraphService,
+    check: fn(&http::HeaderMap, &[u8]) -> bool,
+) -> (graphql::Response, usize) {
+    // answers each query of the batch, in order
+    let http_requests = Arc::new(AtomicUsize::new(0));
+    let counter = http_requests.clone();
+    let make_service = hyper::service::make_service_fn(move |_| {
+        let counter = counter.clone();
+        async move {
+            Ok::<_, std::convert::Infallible>(hyper::service::service_fn(
+                move |request: http::Request<hyper::Body>| {
+                    let counter = counter.clone();
+                    async move {
+                        counter.fetch_add(1, Ordering::SeqCst);
+                        let (parts, body) = request.into_parts();
+                        let body = hyper::body::to_bytes(body).await.unwrap();
+                        if !check(&parts.headers, &body) {
+                            return Ok::<_, std::convert::Infallible>(
+                                http::Response::builder()
+                                    .status(http::StatusCode::UNAUTHORIZED)
+                                    .body(hyper::Body::empty())
+                                    .unwrap(),
+                            );
+                        }
+                        let queries: Vec<graphql::Request> =
+                            serde_json::from_slice(&body).expect("the body is a batch");
+                        let responses: Vec<_> = queries
+                            .iter()
+                            .map(|query| match query.query.as_deref() {
+                                Some("{ t { x } }") => {
+                                    serde_json::json!({"data": {"t": {"x": "a"}}})
+                                }
+                                _ => serde_json::json!({"data": {"t": {"id": "1"}}}),
+                            })
+                            .collect();
+                        Ok::<_, std::convert::Infallible>(
+                            http::Response::builder()
+                                .header(http::header::CONTENT_TYPE, "application/json")
+                                .body(hyper::Body::from(serde_json::to_vec(&responses).unwrap()))
+                                .unwrap(),
+                        )
+                    }
+                },
+            ))
+        }
+    });
+    let server = hyper::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(make_service);
+    let address = server.local_addr();
+    tokio::spawn(server);
+
+    let query_plan = QueryPlan {
+        summary: Default::default(),
+        formatted_query_plan: Default::default(),
+        root: PlanNode::Parallel {
+            nodes: vec![fetch_t("{ t { x } }"), fetch_t("{ t { id } }")],
+        },
+        usage_reporting: UsageReporting {
+            stats_report_key: "this is a test report key".to_string(),
+            referenced_fields_by_type: Default::default(),
+        },
+        query: Arc::new(Query::default()),
+        options: QueryPlanOptions::default(),
+    };
+    let schema = include_str!("testdata/defer_schema.graphql")
+        .replace("http://X", &format!("http://{}", address));
+    let schema = Arc::new(Schema::parse(&schema, &Default::default()).unwrap());
+    let sf = Arc::new(MockSubgraphFactory {
+        subgraphs: HashMap::from([(
+            "X".into(),
+            Arc::new(subgraph_service.with_batching(true)) as Arc<dyn MakeSubgraphService>,
+        )]),
+        plugins: Default::default(),
+    });
+    let (sender, _) = futures::channel::mpsc::channel(10);
+
+    let response = query_plan
+        .execute(
+            &Context::new(),
+            &sf,
+            &Default::default(),
+            &schema,
+            &Default::default(),
+            &Default::default(),
+            &Default::default(),
+            sender,
+        )
+        .await;
+    (response, http_requests.load(Ordering::SeqCst))
+}
+
+#[tokio::test]
+async fn parallel_fetches_to_a_subgraph_are_batched() {
+    let (response, http_requests) =
+        execute_batched_fetches(SubgraphService::new("X"), |_, _| true).await;
+
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(
+        response.data,
+        Some(serde_json_bytes::json!({"t": {"x": "a", "id": "1"}}))
+    );
+    assert_eq!(http_requests, 1);
+}
+
+#[tokio::test]
+async fn batched_fetches_are_signed() {
+    // the signature covers the whole batch, as it is sent
+    fn check(headers: &http::HeaderMap, body: &[u8]) -> bool {
+        use hmac::Mac;
+        let (timestamp, signature) = match (headers.get("x-timestamp"), headers.get("x-signature"))
+        {
+            (Some(timestamp), Some(signature)) => (timestamp, signature),
+            _ => return false,
+        };
+        let mut hmac = hmac::Hmac::<sha2::Sha256>::new_from_slice(b"secret").unwrap();
+        hmac.update(timestamp.as_bytes());
+        hmac.update(b".");
+        hmac.update(body);
+        hmac.verify_slice(&hex::decode(signature.as_bytes()).unwrap())
+            .is_ok()
+    }
+
+    let subgraph_service =
+        SubgraphService::new("X").with_signer(Some(SubgraphSigner::new("secret").unwrap()));
+    let (response, http_requests) = execute_batched_fetches(subgraph_service, check).await;
+
+    assert!(response.errors.is_empty(), "{:?}", response.errors);
+    assert_eq!(
+        response.data,
+        Some(serde_json_bytes::json!({"t": {"x": "a", "id": "1"}}))
+    );
+    assert_eq!(http_requests, 1);
+}
+
+#[tokio::test]
+async fn deferred_plan_after_a_failed_fetch_keeps_null_data() {
+    let query_plan = QueryPlan {
+        summary: Default::default(),
+        formatted_query_plan: Default::default(),
+        root: PlanNode::Sequence {
+            nodes: vec![
+                PlanNode::Fetch(FetchNode {
+                    service_name: "X".to_string(),
+                    requires: vec![],
+                    variable_usages: vec![],
+                    operation: "{ t { id } }".to_string(),
+                    operation_name: Some("t".to_string()),
+                    operation_kind: OperationKind::Query,
+                    id: None,
+                }),
+                PlanNode::Defer {
+                    primary: Primary {
+                        path: None,
+                        subselection: None,
+                        node: None,
+                    },
+                    deferred: vec![DeferredNode {
+                        depends: vec![],
+                        label: None,
+                        path: Path::default(),
+                        subselection: None,
+                        node: None,
+                    }],
+                },
+            ],
+        },
+        usage_reporting: UsageReporting {
+            stats_report_key: "this is a test report key".to_string(),
+            referenced_fields_by_type: Default::default(),
+        },
+        query: Arc::new(Query::default()),
+        options: QueryPlanOptions::default(),
+    };
+
+    let mut mock_x_service = plugin::test::MockSubgraphService::new();
+    mock_x_service.expect_clone().return_once(|| {
+        let mut mock_x_service = plugin::test::MockSubgraphService::new();
+        mock_x_service
+            .expect_call()
+            .times(1)
+            .returning(|_| Err("subgraph X is down".into()));
+        mock_x_service
+    });
+
+    let (sender, mut receiver) = futures::channel::mpsc::channel(10);
+    let schema = include_str!("testdata/defer_schema.graphql");
+    let schema = Arc::new(Schema::parse(schema, &Default::default()).unwrap());
+    let sf = Arc::new(MockSubgraphFactory {
+        subgraphs: HashMap::from([(
+            "X".into(),
+            Arc::new(mock_x_service) as Arc<dyn MakeSubgraphService>,
+        )]),
+        plugins: Default::default(),
+    });
+
+    let response = query_plan
+        .execute(
+            &Context::new(),
+            &sf,
+            &Default::default(),
+            &schema,
+            &Default::default(),
+            &Default::default(),
+            &Default::default(),
+            sender,
+        )
+        .await;
+
+    // the data is missing because the fetch failed, not because nothing was fetched
+    assert_eq!(response.data, Some(Value::Null));
+    assert_eq!(response.errors.len(), 1);
+    let deferred = receiver.next().await.unwrap();
+    assert_eq!(deferred.data, Some(Value::Null));
+}
@@ -1,7 +1,9 @@
 #![allow(missing_docs)] // FIXME
 #![allow(deprecated)] // Note: Required to prevents complaints on enum declaration
 
+use std::collections::HashMap;
 use std::fs;
+use std::io::Read;
 use std::net::IpAddr;
 use std::path::Path;
 use std::path::PathBuf;
@@ -17,10 +19,12 @@ use derive_more::From;
 use displaydoc::Display as DisplayDoc;
 use futures::channel::oneshot;
 use futures::prelude::*;
+use futures::stream::BoxStream;
 use futures::FutureExt;
 use http_body::Body as _;
 use hyper::Body;
 use thiserror::Error;
+use tokio::runtime::Handle;
 use tokio::sync::RwLock;
 use tokio::task::spawn;
 use tower::BoxError;
@@ -39,17 +43,29 @@ use crate::axum_factory::ListenAddrAndRouter;
 use crate::cache::DeduplicatingCache;
 use crate::configuration::Configuration;
 use crate::configuration::ListenAddr;
+use crate::graphql;
 use crate::plugin::DynPlugin;
 use crate::router_factory::SupergraphServiceConfigurator;
 use crate::router_factory::SupergraphServiceFactory;
 use crate::router_factory::YamlSupergraphServiceFactory;
 use crate::services::layers::apq::APQLayer;
+use crate::services::supergraph;
 use crate::services::transport;
 use crate::spec::Schema;
 use crate::state_machine::StateMachine;
+use crate::state_machine::SupergraphServiceMaker;
 
 type SchemaStream = Pin<Box<dyn Stream<Item = String> + Send>>;
 
+/// The query sent through the pipeline to check that the router can serve requests
+const HEALTH_QUERY: &str = "{ __typename }";
+
+/// The delay between the health queries of [`RouterHttpServer::ready_to_serve`]
+const HEALTH_QUERY_INTERVAL: Duration = Duration::from_millis(50);
+
+/// The first bytes of a gzip compressed file
+const GZIP_MAGIC_BYTES: [u8; 2] = [0x1f, 0x8b];
+
 // For now this is unused:
 #[allow(unused)]
 // Later we might add a public API for this (probably a builder similar to `test_harness.rs`),
@@ -61,7 +77,7 @@ async fn make_transport_service<RF>(
     extra_plugins: Vec<(String, Box<dyn DynPlugin>)>,
 ) -> Result<transport::BoxCloneService, BoxError> {
     let schema = Arc::new(Schema::parse(schema, &configuration)?);
-    let service_factory = YamlSupergraphServiceFactory
+    let service_factory = YamlSupergraphServiceFactory::default()
         .create(configuration.clone(), schema, None, Some(extra_plugins))
         .await?;
 
@@ -118,6 +134,9 @@ pub enum ApolloRouterError {
 
     /// tried to register two endpoints on `{0}:{1}{2}`
     SameRouteUsedTwice(IpAddr, u16, String),
+
+    /// the router could not answer a health query within {0:?}
+    NotReadyToServe(Duration),
 }
 
 /// The user supplied schema. Either a static string or a stream for hot reloading.
@@ -133,6 +152,21 @@ pub enum SchemaSource {
     #[display(fmt = "Stream")]
     Stream(#[derivative(Debug = "ignore")] SchemaStream),
 
+    /// A stream of schema, connected again when it ends.
+    ///
+    /// Useful for schema sources that can be disconnected temporarily.
+    #[display(fmt = "Reconnecting")]
+    Reconnecting {
+        /// Opens the stream of schema, on startup and each time the previous stream ends.
+        #[derivative(Debug = "ignore")]
+        connect: SchemaStreamConnector,
+
+        /// How long the new streams have to deliver a schema after the previous one ended.
+        /// The streams ending without a schema are opened again until then; if none delivers
+        /// a schema, there are no more schema updates.
+        grace: Duration,
+    },
+
     /// A YAML file that may be watched for changes.
     #[display(fmt = "File")]
     File {
@@ -181,6 +215,9 @@ impl SchemaSource {
                 stream::once(future::ready(UpdateSchema(schema))).boxed()
             }
             SchemaSource::Stream(stream) => stream.map(UpdateSchema).boxed(),
+            SchemaSource::Reconnecting { connect, grace } => {
+                reconnecting_schema_stream(connect, grace).boxed()
+            }
             #[allow(deprecated)]
             SchemaSource::File {
                 path,
@@ -196,12 +233,12 @@ impl SchemaSource {
                     stream::empty().boxed()
                 } else {
                     //The schema file exists try and load it
-                    match std::fs::read_to_string(&path) {
+                    match SchemaSource::read_schema(&path) {
                         Ok(schema) => {
                             if watch {
                                 crate::files::watch(&path)
                                     .filter_map(move |_| {
-                                        future::ready(std::fs::read_to_string(&path).ok())
+                                        future::ready(SchemaSource::read_schema(&path).ok())
                                     })
                                     .map(UpdateSchema)
                                     .boxed()
@@ -239,6 +276,75 @@ impl SchemaSource {
         }
         .chain(stream::iter(vec![NoMoreSchema]))
     }
+
+    /// Reads a schema file, gzip compressed files are detected by their header and decompressed
+    fn read_schema(path: &Path) -> std::io::Result<String> {
+        let bytes = fs::read(path)?;
+        if bytes.starts_with(&GZIP_MAGIC_BYTES) {
+            let mut schema = String::new();
+            flate2::read::GzDecoder::new(bytes.as_slice()).read_to_string(&mut schema)?;
+            Ok(schema)
+        } else {
+            String::from_utf8(bytes)
+                .map_err(|err| std::io::Error::new(std::io::ErrorKind::InvalidData, err))
+        }
+    }
+}
+
+/// Delay between two connections to a schema stream that ended without delivering a schema
+const SCHEMA_RECONNECT_DELAY: Duration = Duration::from_millis(100);
+
+/// Schema updates from streams opened by `connect`, until no new stream delivers a schema within
+/// `grace` after the previous one ended
+fn reconnecting_schema_stream(
+    connect: SchemaStreamConnector,
+    grace: Duration,
+) -> impl Stream<Item = Event> {
+    // the deadline is only set while waiting for the first schema of a new stream
+    stream::unfold(
+        (connect.connect(), None),
+        move |(mut stream, mut deadline): (SchemaStream, Option<tokio::time::Instant>)| {
+            let connect = connect.clone();
+            async move {
+                loop {
+                    match deadline {
+                        None => match stream.next().await {
+                            Some(schema) => return Some((UpdateSchema(schema), (stream, None))),
+                            None => {
+                                tracing::warn!(
+                                    "the schema stream ended, reconnecting for {}",
+                                    humantime::format_duration(grace)
+                                );
+                                stream = connect.connect();
+                                deadline = Some(tokio::time::Instant::now() + grace);
+                            }
+                        },
+                        Some(until) => match tokio::time::timeout_at(until, stream.next()).await {
+                            Ok(Some(schema)) => {
+                                return Some((UpdateSchema(schema), (stream, None)))
+                            }
+                            // the new stream ended too: connect again until the deadline
+                            Ok(None) => {
+                                tokio::time::sleep_until(std::cmp::min(
+                                    tokio::time::Instant::now() + SCHEMA_RECONNECT_DELAY,
+                                    until,
+                                ))
+                                .await;
+                                stream = connect.connect();
+                            }
+                            Err(_) => {
+                                tracing::error!(
+                                    "the schema stream did not deliver a schema within {} after reconnecting",
+                                    humantime::format_duration(grace)
+                                );
+                                return None;
+                            }
+                        },
+                    }
+                }
+            }
+        },
+    )
 }
 
 type ConfigurationStream = Pin<Box<dyn Stream<Item = Configuration> + Send>>;
@@ -275,6 +381,24 @@ pub enum ConfigurationSource {
         #[deprecated]
         delay: Option<Duration>,
     },
+
+    /// Yaml files merged in order, that may be watched for changes
+    ///
+    /// Later files override earlier ones: maps are merged key by key, while scalars and
+    /// arrays replace the previous value entirely.
+    #[display(fmt = "Files")]
+    Files {
+        /// The paths of the configuration files, from the base to the last overlay.
+        paths: Vec<PathBuf>,
+
+        /// `true` to watch the files for changes and hot apply them.
+        watch: bool,
+
+        /// When watching, the delay to wait before applying the new configuration.
+        /// Note: This variable is deprecated and has no effect.
+        #[deprecated]
+        delay: Option<Duration>,
+    },
 }
 
 impl Default for ConfigurationSource {
@@ -298,85 +422,136 @@ impl ConfigurationSource {
                 path,
                 watch,
                 delay: _,
-            } => {
-                // Sanity check, does the config file exists, if it doesn't then bail.
-                if !path.exists() {
-                    tracing::error!(
-                        "configuration file at path '{}' does not exist.",
-                        path.to_string_lossy()
-                    );
-                    stream::empty().boxed()
-                } else if watch {
-                    crate::files::watch(&path)
-                        .map(move |_| match ConfigurationSource::read_config(&path) {
-                            Ok(config) => UpdateConfiguration(Box::new(config)),
-                            Err(err) => {
-                                tracing::error!("{}", err);
-                                NoMoreConfiguration
-                            }
-                        })
-                        .boxed()
-                } else {
-                    match ConfigurationSource::read_config(&path) {
-                        Ok(configuration) => {
-                            #[cfg(any(test, not(unix)))]
-                            {
-                                stream::once(future::ready(UpdateConfiguration(Box::new(
-                                    configuration,
-                                ))))
-                                .boxed()
-                            }
+            } => ConfigurationSource::files_into_stream(vec![path], watch),
+            #[allow(deprecated)]
+            ConfigurationSource::Files {
+                paths,
+                watch,
+                delay: _,
+            } => ConfigurationSource::files_into_stream(paths, watch),
+        }
+        .chain(stream::iter(vec![NoMoreConfiguration]))
+        .boxed()
+    }
+
+    fn files_into_stream(paths: Vec<PathBuf>, watch: bool) -> BoxStream<'static, Event> {
+        // Sanity check, do the config files exist, if one of them doesn't then bail.
+        if let Some(path) = paths.iter().find(|path| !path.exists()) {
+            tracing::error!(
+                "configuration file at path '{}' does not exist.",
+                path.to_string_lossy()
+            );
+            stream::empty().boxed()
+        } else if watch {
+            // Each watch starts with a notification, only the first one is kept
+            // so that the configuration is read once at startup
+            let changes = stream::select_all(
+                paths
+                    .iter()
+                    .map(|path| crate::files::watch(path).skip(1).boxed()),
+            );
+            stream::once(future::ready(()))
+                .chain(changes)
+                .map(move |_| match ConfigurationSource::read_configs(&paths) {
+                    Ok(config) => UpdateConfiguration(Box::new(config)),
+                    Err(err) => {
+                        tracing::error!("{}", err);
+                        NoMoreConfiguration
+                    }
+                })
+                .boxed()
+        } else {
+            match ConfigurationSource::read_configs(&paths) {
+                Ok(configuration) => {
+                    #[cfg(any(test, not(unix)))]
+                    {
+                        stream::once(future::ready(UpdateConfiguration(Box::new(configuration))))
+                            .boxed()
+                    }
 
-                            #[cfg(all(not(test), unix))]
-                            {
-                                let mut sighup_stream = tokio::signal::unix::signal(
-                                    tokio::signal::unix::SignalKind::hangup(),
-                                )
+                    #[cfg(all(not(test), unix))]
+                    {
+                        let mut sighup_stream =
+                            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup())
                                 .expect("Failed to install SIGHUP signal handler");
 
-                                let (mut tx, rx) = futures::channel::mpsc::channel(1);
-                                tokio::task::spawn(async move {
-                                    while let Some(()) = sighup_stream.recv().await {
-                                        tx.send(()).await.unwrap();
-                                    }
-                                });
-                                futures::stream::select(
-                                    stream::once(future::ready(UpdateConfiguration(Box::new(
-                                        configuration,
-                                    ))))
-                                    .boxed(),
-                                    rx.filter_map(
-                                        move |()| match ConfigurationSource::read_config(&path) {
-                                            Ok(configuration) => future::ready(Some(
-                                                UpdateConfiguration(Box::new(configuration)),
-                                            )),
-                                            Err(err) => {
-                                                tracing::error!("{}", err);
-                                                future::ready(None)
-                                            }
-                                        },
-                                    )
-                                    .boxed(),
-                                )
-                                .boxed()
+                        let (mut tx, rx) = futures::channel::mpsc::channel(1);
+                        tokio::task::spawn(async move {
+                            while let Some(()) = sighup_stream.recv().await {
+                                tx.send(()).await.unwrap();
                             }
-                        }
-                        Err(err) => {
-                            tracing::error!("{}", err);
-                            stream::empty().boxed()
-                        }
+                        });
+                        futures::stream::select(
+                            stream::once(future::ready(UpdateConfiguration(Box::new(
+                                configuration,
+                            ))))
+                            .boxed(),
+                            rx.filter_map(move |()| {
+                                match ConfigurationSource::read_configs(&paths) {
+                                    Ok(configuration) => future::ready(Some(UpdateConfiguration(
+                                        Box::new(configuration),
+                                    ))),
+                                    Err(err) => {
+                                        tracing::error!("{}", err);
+                                        future::ready(None)
+                                    }
+                                }
+                            })
+                            .boxed(),
+                        )
+                        .boxed()
                     }
                 }
+                Err(err) => {
+                    tracing::error!("{}", err);
+                    stream::empty().boxed()
+                }
             }
         }
-        .chain(stream::iter(vec![NoMoreConfiguration]))
-        .boxed()
     }
 
     fn read_config(path: &Path) -> Result<Configuration, ReadConfigError> {
         let config = fs::read_to_string(path)?;
         config.parse().map_err(ReadConfigError::Validation)
     }
+
+    fn read_configs(paths: &[PathBuf]) -> Result<Configuration, ReadConfigError> {
+        // A single file is parsed as is, to keep the line numbers in validation errors
+        if let [path] = paths {
+            return ConfigurationSource::read_config(path);
+        }
+
+        let mut merged = serde_yaml::Value::Null;
+        for path in paths {
+            let config = fs::read_to_string(path)?;
+            if !config.trim().is_empty() {
+                merge_yaml(&mut merged, serde_yaml::from_str(&config)?);
+            }
+        }
+        let merged = match merged {
+            serde_yaml::Value::Null => String::new(),
+            merged => serde_yaml::to_string(&merged)?,
+        };
+        merged.parse().map_err(ReadConfigError::Validation)
+    }
+}
+
+/// Deep merge `overlay` into `base`: maps are merged key by key, while scalars and
+/// arrays from `overlay` replace the values of `base`
+fn merge_yaml(base: &mut serde_yaml::Value, overlay: serde_yaml::Value) {
+    match (base, overlay) {
+        (serde_yaml::Value::Mapping(base), serde_yaml::Value::Mapping(overlay)) => {
+            for (key, value) in overlay {
+                match base.get_mut(&key) {
+                    Some(base_value) => merge_yaml(base_value, value),
+                    None => {
+                        base.insert(key, value);
+                    }
+                }
+            }
+        }
+        (base, overlay) => *base = overlay,
+    }
 }
 
 #[derive(From, Display)]
@@ -385,6 +560,8 @@ enum ReadConfigError {
     Io(std::io::Error),
     /// {0}
     Validation(crate::configuration::ConfigurationError),
+    /// could not merge configuration files: {0}
+    Merge(serde_yaml::Error),
 }
 
 type ShutdownFuture = Pin<Box<dyn Future<Output = ()> + Send>>;
@@ -485,6 +662,7 @@ pub struct RouterHttpServer {
     result: Pin<Box<dyn Future<Output = Result<(), ApolloRouterError>> + Send>>,
     graphql_listen_address: Arc<RwLock<Option<ListenAddr>>>,
     extra_listen_adresses: Arc<RwLock<Vec<ListenAddr>>>,
+    supergraph_service: Arc<RwLock<Option<SupergraphServiceMaker>>>,
     shutdown_sender: Option<oneshot::Sender<()>>,
 }
 
@@ -509,6 +687,32 @@ impl RouterHttpServer {
     ///   Specifies when the server should gracefully shut down.
     ///   If not provided, the default is [`ShutdownSource::CtrlC`].
     ///
+    /// * `.on_schema_reload(impl Into<`[`SchemaReloadCallback`]`>)`
+    ///   Optional.
+    ///   Specifies a function called with the hash of the new schema
+    ///   each time a schema reload completes successfully.
+    ///
+    /// * `.schema_validator(impl Into<`[`SchemaValidator`]`>)`
+    ///   Optional, may be called multiple times.
+    ///   Adds a function checking each new schema before it is used.
+    ///   A schema rejected by a validator is not loaded, and the router keeps the previous one.
+    ///
+    /// * `.subgraph_client_hook(impl Into<`[`SubgraphClientHook`]`>)`
+    ///   Optional.
+    ///   Specifies a function wrapping the HTTP client of each subgraph,
+    ///   to add custom logic like authentication, tracing or metrics.
+    ///
+    /// * `.subgraph_response_adapter(impl Into<`[`String`]`>, impl Into<`[`SubgraphResponseAdapter`]`>)`
+    ///   Optional, may be called multiple times.
+    ///   Specifies a function converting the JSON responses of the named subgraph
+    ///   to GraphQL responses, for subgraphs that do not follow the GraphQL response format.
+    ///
+    /// * `.runtime(tokio::runtime::Handle)`
+    ///   Optional.
+    ///   Specifies the Tokio runtime running the server,
+    ///   for applications embedding the router with multiple runtimes.
+    ///   If not provided, the server runs on the runtime calling `start()`.
+    ///
     /// * `.start()`
     ///   Finishes the builder,
     ///   starts an HTTP server in a separate Tokio task,
@@ -528,6 +732,11 @@ impl RouterHttpServer {
         schema: SchemaSource,
         configuration: Option<ConfigurationSource>,
         shutdown: Option<ShutdownSource>,
+        on_schema_reload: Option<SchemaReloadCallback>,
+        schema_validators: Vec<SchemaValidator>,
+        subgraph_client_hook: Option<SubgraphClientHook>,
+        subgraph_response_adapters: HashMap<String, SubgraphResponseAdapter>,
+        runtime: Option<Handle>,
     ) -> RouterHttpServer {
         let (shutdown_sender, shutdown_receiver) = oneshot::channel::<()>();
         let event_stream = generate_event_stream(
@@ -537,14 +746,22 @@ impl RouterHttpServer {
             shutdown_receiver,
         );
         let server_factory = AxumHttpServerFactory::new();
-        let router_factory = YamlSupergraphServiceFactory::default();
-        let state_machine = StateMachine::new(server_factory, router_factory);
+        let router_factory = YamlSupergraphServiceFactory {
+            subgraph_client_hook,
+            subgraph_response_adapters,
+        };
+        let state_machine = StateMachine::new(server_factory, router_factory)
+            .with_schema_reload_callback(on_schema_reload)
+            .with_schema_validators(schema_validators);
         let extra_listen_adresses = state_machine.extra_listen_adresses.clone();
         let graphql_listen_address = state_machine.graphql_listen_address.clone();
-        let result = spawn(
-            async move { state_machine.process_events(event_stream).await }
-                .with_current_subscriber(),
-        )
+        let supergraph_service = state_machine.supergraph_service.clone();
+        let server = async move { state_machine.process_events(event_stream).await }
+            .with_current_subscriber();
+        let result = match runtime {
+            Some(runtime) => runtime.spawn(server),
+            None => spawn(server),
+        }
         .map(|r| match r {
             Ok(Ok(ok)) => Ok(ok),
             Ok(Err(err)) => Err(err),
@@ -561,6 +778,7 @@ impl RouterHttpServer {
             shutdown_sender: Some(shutdown_sender),
             graphql_listen_address,
             extra_listen_adresses,
+            supergraph_service,
         }
     }
 
@@ -574,6 +792,63 @@ impl RouterHttpServer {
         self.graphql_listen_address.read().await.clone()
     }
 
+    /// Returns the listen address when the router can answer GraphQL requests.
+    ///
+    /// The listen address is known once the server listens. From then on, this method sends a
+    /// `{ __typename }` query through the query planner and execution pipeline until the router
+    /// answers it without errors, so traffic can be held until the router actually serves
+    /// requests. The query goes through the request limits but not the plugins: they can't
+    /// reject it, like for a missing preflight header, and it is not counted in the metrics and
+    /// usage reports.
+    ///
+    /// Returns [`ApolloRouterError::NotReadyToServe`] if the router does not answer within
+    /// `timeout`, like when it never receives a valid schema.
+    pub async fn ready_to_serve(&self, timeout: Duration) -> Result<ListenAddr, ApolloRouterError> {
+        tokio::time::timeout(timeout, async {
+            loop {
+                if let Some(listen_address) = self.listen_address().await {
+                    if self.answers_health_query().await {
+                        return listen_address;
+                    }
+                }
+                tokio::time::sleep(HEALTH_QUERY_INTERVAL).await;
+            }
+        })
+        .await
+        .map_err(|_| ApolloRouterError::NotReadyToServe(timeout))
+    }
+
+    async fn answers_health_query(&self) -> bool {
+        let make_service = match self.supergraph_service.read().await.clone() {
+            Some(make_service) => make_service,
+            None => return false,
+        };
+        let request = match supergraph::Request::builder()
+            .query(HEALTH_QUERY)
+            .context(crate::Context::new())
+            .uri(http::Uri::from_static("/"))
+            .method(http::Method::POST)
+            .build()
+        {
+            Ok(request) => request,
+            Err(_) => return false,
+        };
+        let service = match make_service() {
+            Some(service) => service,
+            None => return false,
+        };
+        match service.oneshot(request).await {
+            Ok(mut response) => match response.next_response().await {
+                Some(response) => response.data.is_some() && response.errors.is_empty(),
+                None => false,
+            },
+            Err(err) => {
+                tracing::debug!("the health query failed: {}", err);
+                false
+            }
+        }
+    }
+
     /// Returns the extra listen addresses the router can receive requests to.
     ///
     /// Combine it with `listen_address` to have an exhaustive list
@@ -592,6 +867,233 @@ impl RouterHttpServer {
     }
 }
 
+/// A function called with the hash of the new schema when a schema reload completes.
+///
+/// It runs on a blocking thread, so a slow callback does not delay the processing of requests.
+#[derive(Clone)]
+pub struct SchemaReloadCallback(Arc<dyn Fn(&str) + Send + Sync>);
+
+impl SchemaReloadCallback {
+    pub(crate) fn call(&self, schema_hash: &str) {
+        (self.0)(schema_hash)
+    }
+}
+
+impl<F> From<F> for SchemaReloadCallback
+where
+    F: Fn(&str) + Send + Sync + 'static,
+{
+    fn from(callback: F) -> Self {
+        Self(Arc::new(callback))
+    }
+}
+
+/// A function opening a stream of schema, for [`SchemaSource::Reconnecting`].
+#[derive(Clone)]
+pub struct SchemaStreamConnector(Arc<dyn Fn() -> SchemaStream + Send + Sync>);
+
+impl SchemaStreamConnector {
+    pub(crate) fn connect(&self) -> SchemaStream {
+        (self.0)()
+    }
+}
+
+impl<F> From<F> for SchemaStreamConnector
+where
+    F: Fn() -> SchemaStream + Send + Sync + 'static,
+{
+    fn from(connect: F) -> Self {
+        Self(Arc::new(connect))
+    }
+}
+
+/// A function checking a new supergraph schema before the router uses it.
+///
+/// It returns the reasons for rejecting the schema, which are logged as errors. A schema rejected
+/// on startup is not loaded until a valid one is received, and a schema rejected on reload is
+/// ignored: the router keeps serving the previous one.
+///
+/// ```
+/// use apollo_router::SchemaDiagnostic;
+/// use apollo_router::SchemaValidator;
+/// use apollo_router::SupergraphSchema;
+///
+/// let validator = SchemaValidator::from(|schema: &SupergraphSchema| {
+///     if schema.has_type("Product") {
+///         Ok(())
+///     } else {
+///         Err(vec![SchemaDiagnostic::new("the Product type is required")])
+///     }
+/// });
+/// ```
+#[derive(Clone)]
+pub struct SchemaValidator(
+    Arc<dyn Fn(&SupergraphSchema) -> Result<(), Vec<SchemaDiagnostic>> + Send + Sync>,
+);
+
+impl SchemaValidator {
+    pub(crate) fn validate(&self, schema: &Schema) -> Result<(), Vec<SchemaDiagnostic>> {
+        (self.0)(&SupergraphSchema(schema))
+    }
+}
+
+impl<F> From<F> for SchemaValidator
+where
+    F: Fn(&SupergraphSchema) -> Result<(), Vec<SchemaDiagnostic>> + Send + Sync + 'static,
+{
+    fn from(validator: F) -> Self {
+        Self(Arc::new(validator))
+    }
+}
+
+/// A parsed supergraph schema, as given to a [`SchemaValidator`].
+pub struct SupergraphSchema<'a>(&'a Schema);
+
+impl SupergraphSchema<'_> {
+    /// Returns the schema definition language of the supergraph.
+    pub fn sdl(&self) -> &str {
+        self.0.as_string()
+    }
+
+    /// Returns `true` if the schema defines a type with this name.
+    pub fn has_type(&self, name: &str) -> bool {
+        self.0.object_types.contains_key(name)
+            || self.0.interfaces.contains_key(name)
+            || self.0.input_types.contains_key(name)
+            || self.0.custom_scalars.contains(name)
+            || self.0.enums.contains_key(name)
+    }
+
+    /// Returns the names and URLs of the subgraphs.
+    pub fn subgraphs(&self) -> impl Iterator<Item = (&str, String)> {
+        self.0
+            .subgraphs()
+            .map(|(name, url)| (name.as_str(), url.to_string()))
+    }
+}
+
+/// A reason for a [`SchemaValidator`] to reject a schema.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SchemaDiagnostic {
+    message: String,
+}
+
+impl SchemaDiagnostic {
+    /// Creates a diagnostic with this message.
+    pub fn new(message: impl Into<String>) -> Self {
+        Self {
+            message: message.into(),
+        }
+    }
+
+    /// Returns the message of the diagnostic.
+    pub fn message(&self) -> &str {
+        &self.message
+    }
+}
+
+impl std::fmt::Display for SchemaDiagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+/// A function wrapping the HTTP client used to send requests to a subgraph.
+///
+/// It is called with the name of the subgraph and its HTTP client each time the subgraph services
+/// are created, and returns the service used instead of that client. The built-in behaviours, like
+/// timeouts, retries and redirects, are applied around the returned service, so it sees every
+/// HTTP request sent to the subgraph.
+///
+/// ```
+/// use apollo_router::services::transport;
+/// use apollo_router::SubgraphClientHook;
+/// use tower::ServiceExt;
+///
+/// let hook = SubgraphClientHook::from(|name: &str, client: transport::BoxService| {
+///     let name = name.to_string();
+///     client
+///         .map_request(move |request: transport::Request| {
+///             tracing::info!("sending a request to {}", name);
+///             request
+///         })
+///         .boxed()
+/// });
+/// ```
+#[derive(Clone)]
+pub struct SubgraphClientHook(
+    Arc<dyn Fn(&str, transport::BoxService) -> transport::BoxService + Send + Sync>,
+);
+
+impl SubgraphClientHook {
+    pub(crate) fn wrap(
+        &self,
+        subgraph: &str,
+        client: transport::BoxService,
+    ) -> transport::BoxService {
+        (self.0)(subgraph, client)
+    }
+}
+
+impl<F> From<F> for SubgraphClientHook
+where
+    F: Fn(&str, transport::BoxService) -> transport::BoxService + Send + Sync + 'static,
+{
+    fn from(hook: F) -> Self {
+        Self(Arc::new(hook))
+    }
+}
+
+/// A function converting the JSON response of a subgraph to a GraphQL response.
+///
+/// It replaces the parsing of the responses of a subgraph returning non-standard shapes, like
+/// errors under another key. It is called with the JSON body of each response, before the
+/// response enters the execution pipeline, and its errors fail the fetch like malformed responses.
+///
+/// ```
+/// use apollo_router::graphql;
+/// use apollo_router::SubgraphResponseAdapter;
+/// use serde_json_bytes::Value;
+///
+/// let adapter = SubgraphResponseAdapter::from(|body: Value| {
+///     // this subgraph returns its errors as a list of messages under `failures`
+///     let errors = match body.get("failures") {
+///         Some(Value::Array(failures)) => failures
+///             .iter()
+///             .filter_map(|failure| failure.as_str())
+///             .map(|message| graphql::Error::builder().message(message).build())
+///             .collect(),
+///         _ => Vec::new(),
+///     };
+///     Ok(graphql::Response::builder()
+///         .and_data(body.get("data").cloned())
+///         .errors(errors)
+///         .build())
+/// });
+/// ```
+#[derive(Clone)]
+pub struct SubgraphResponseAdapter(
+    Arc<dyn Fn(serde_json_bytes::Value) -> Result<graphql::Response, BoxError> + Send + Sync>,
+);
+
+impl SubgraphResponseAdapter {
+    pub(crate) fn adapt(
+        &self,
+        body: serde_json_bytes::Value,
+    ) -> Result<graphql::Response, BoxError> {
+        (self.0)(body)
+    }
+}
+
+impl<F> From<F> for SubgraphResponseAdapter
+where
+    F: Fn(serde_json_bytes::Value) -> Result<graphql::Response, BoxError> + Send + Sync + 'static,
+{
+    fn from(adapter: F) -> Self {
+        Self(Arc::new(adapter))
+    }
+}
+
 /// Messages that are broadcast across the app.
 #[derive(Debug)]
 pub(crate) enum Event {
@@ -686,6 +1188,51 @@ mod tests {
         router_handle.shutdown().await.unwrap();
     }
 
+    #[test]
+    fn server_runs_on_the_provided_runtime() {
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .thread_name("provided-runtime")
+            .enable_all()
+            .build()
+            .unwrap();
+        // subgraph clients are created by the server task, on the runtime running it
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let sender = std::sync::Mutex::new(sender);
+        let hook = move |_: &str, client: transport::BoxService| {
+            let thread_name = std::thread::current().name().map(str::to_string);
+            sender.lock().unwrap().send(thread_name).unwrap();
+            client
+        };
+
+        tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap()
+            .block_on(async {
+                let configuration = serde_yaml::from_str::<Configuration>(include_str!(
+                    "testdata/supergraph_config.yaml"
+                ))
+                .unwrap();
+                let mut router_handle = RouterHttpServer::builder()
+                    .configuration(configuration)
+                    .schema(include_str!("testdata/supergraph.graphql"))
+                    .subgraph_client_hook(hook)
+                    .runtime(runtime.handle().clone())
+                    .start();
+                router_handle
+                    .listen_address()
+                    .await
+                    .expect("router failed to start");
+                router_handle.shutdown().await.unwrap();
+            });
+
+        let thread_names: Vec<_> = receiver.try_iter().collect();
+        assert!(!thread_names.is_empty());
+        assert!(thread_names
+            .iter()
+            .all(|name| name.as_deref() == Some("provided-runtime")));
+    }
+
     async fn assert_federated_response(listen_addr: &ListenAddr, request: &str) {
         let request = Request::builder().query(request).build();
         let expected = query(listen_addr, &request).await.unwrap();
@@ -709,6 +1256,252 @@ mod tests {
             .expect("couldn't deserialize into json"))
     }
 
+    async fn exported_schema(listen_addr: &ListenAddr) -> Option<String> {
+        reqwest::get(format!("{}/schema", listen_addr))
+            .await
+            .ok()?
+            .text()
+            .await
+            .ok()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn schema_export_reflects_reloads() {
+        let configuration = serde_yaml::from_str::<Configuration>(
+            r#"
+supergraph:
+  listen: 127.0.0.1:0
+health-check:
+  listen: 127.0.0.1:0
+schema_export:
+  enabled: true
+  listen: 127.0.0.1:0
+"#,
+        )
+        .unwrap();
+        let schema = include_str!("testdata/supergraph.graphql");
+        let (schemas, receiver) = futures::channel::mpsc::unbounded();
+        schemas.unbounded_send(schema.to_string()).unwrap();
+        let mut router_handle = RouterHttpServer::builder()
+            .configuration(configuration)
+            .schema(SchemaSource::Stream(receiver.boxed()))
+            .start();
+        let listen_address = router_handle
+            .listen_address()
+            .await
+            .expect("router failed to start");
+        assert_eq!(
+            exported_schema(&listen_address).await.as_deref(),
+            Some(schema)
+        );
+
+        let reloaded = format!("{}\n# reloaded\n", schema);
+        schemas.unbounded_send(reloaded.clone()).unwrap();
+        // the server restarts with the new schema in the background
+        let mut exported = None;
+        for _ in 0..50 {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            let listen_address = router_handle
+                .listen_address()
+                .await
+                .expect("router failed to restart");
+            exported = exported_schema(&listen_address).await;
+            if exported.as_ref() == Some(&reloaded) {
+                break;
+            }
+        }
+        assert_eq!(exported, Some(reloaded));
+        router_handle.shutdown().await.unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn ready_to_serve_waits_for_the_pipeline() {
+        let configuration = serde_yaml::from_str::<Configuration>(
+            r#"
+supergraph:
+  listen: 127.0.0.1:0
+health-check:
+  listen: 127.0.0.1:0
+"#,
+        )
+        .unwrap();
+        let (schemas, receiver) = futures::channel::mpsc::unbounded();
+        let mut router_handle = RouterHttpServer::builder()
+            .configuration(configuration)
+            .schema(SchemaSource::Stream(receiver.boxed()))
+            .start();
+
+        // without a schema, the router can't answer queries
+        assert!(matches!(
+            router_handle
+                .ready_to_serve(Duration::from_millis(200))
+                .await,
+            Err(ApolloRouterError::NotReadyToServe(_))
+        ));
+
+        schemas
+            .unbounded_send(include_str!("testdata/supergraph.graphql").to_string())
+            .unwrap();
+        let listen_address = router_handle
+            .ready_to_serve(Duration::from_secs(10))
+            .await
+            .expect("router failed to serve");
+        let response = query(
+            &listen_address,
+            &Request::builder().query("{ __typename }").build(),
+        )
+        .await
+        .unwrap();
+        assert!(response.errors.is_empty());
+        assert_eq!(
+            response.data,
+            Some(serde_json_bytes::json!({ "__typename": "Query" }))
+        );
+        router_handle.shutdown().await.unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn ready_to_serve_is_not_rejected_by_the_plugins() {
+        let configuration = serde_yaml::from_str::<Configuration>(
+            r#"
+supergraph:
+  listen: 127.0.0.1:0
+health-check:
+  listen: 127.0.0.1:0
+"#,
+        )
+        .unwrap();
+        let mut router_handle = RouterHttpServer::builder()
+            .configuration(configuration)
+            .schema(include_str!("testdata/supergraph.graphql"))
+            .start();
+
+        // the health query sends no preflight header, the CSRF plugin would reject it
+        let listen_address = router_handle
+            .ready_to_serve(Duration::from_secs(10))
+            .await
+            .expect("router failed to serve");
+        // the client requests still go through the plugins
+        let response: graphql::Response = reqwest::Client::new()
+            .get(format!("{}/", listen_address))
+            .query(&[("query", "{ __typename }")])
+            .send()
+            .await
+            .expect("couldn't send request")
+            .json()
+            .await
+            .expect("couldn't deserialize into json");
+        assert!(response.errors[0]
+            .message
+            .contains("Cross-Site Request Forgery"));
+        router_handle.shutdown().await.unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn ready_to_serve_goes_through_the_layers() {
+        let configuration = serde_yaml::from_str::<Configuration>(
+            r#"
+supergraph:
+  listen: 127.0.0.1:0
+health-check:
+  listen: 127.0.0.1:0
+limits:
+  max_query_bytes: 8
+"#,
+        )
+        .unwrap();
+        let mut router_handle = RouterHttpServer::builder()
+            .configuration(configuration)
+            .schema(include_str!("testdata/supergraph.graphql"))
+            .start();
+
+        // the health query is longer than the limit
+        assert!(matches!(
+            router_handle.ready_to_serve(Duration::from_secs(1)).await,
+            Err(ApolloRouterError::NotReadyToServe(_))
+        ));
+        router_handle.shutdown().await.unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn subgraph_url_overrides_are_reloaded_in_place() {
+        use std::sync::atomic::AtomicUsize;
+        use std::sync::atomic::Ordering;
+        use std::sync::Mutex;
+
+        const BLUE: &str = "http://products-blue/graphql";
+        const GREEN: &str = "http://products-green/graphql";
+        let configuration = |url: &str| {
+            serde_yaml::from_str::<Configuration>(&format!(
+                r#"
+supergraph:
+  listen: 127.0.0.1:0
+health-check:
+  listen: 127.0.0.1:0
+override_subgraph_url:
+  products: {url}
+"#
+            ))
+            .unwrap()
+        };
+
+        // the subgraph clients are created when building the router, and record the URIs
+        // of the requests instead of sending them
+        let created_clients = Arc::new(AtomicUsize::new(0));
+        let requested_uris = Arc::new(Mutex::new(Vec::new()));
+        let created = created_clients.clone();
+        let requested = requested_uris.clone();
+        let hook = move |_: &str, _client: transport::BoxService| {
+            created.fetch_add(1, Ordering::SeqCst);
+            let requested = requested.clone();
+            tower::service_fn(move |request: transport::Request| {
+                requested.lock().unwrap().push(request.uri().to_string());
+                async {
+                    Ok::<_, BoxError>(
+                        http::Response::builder()
+                            .header(http::header::CONTENT_TYPE, "application/json")
+                            .body(Body::from(r#"{"data":{"topProducts":[{"name":"Table"}]}}"#))
+                            .unwrap(),
+                    )
+                }
+            })
+            .boxed()
+        };
+        let last_requested_uri = || requested_uris.lock().unwrap().last().cloned();
+
+        let (configurations, receiver) = futures::channel::mpsc::unbounded();
+        configurations.unbounded_send(configuration(BLUE)).unwrap();
+        let mut router_handle = RouterHttpServer::builder()
+            .configuration(ConfigurationSource::Stream(receiver.boxed()))
+            .schema(include_str!("testdata/supergraph.graphql"))
+            .subgraph_client_hook(hook)
+            .start();
+        let listen_address = router_handle
+            .listen_address()
+            .await
+            .expect("router failed to start");
+        let request = Request::builder()
+            .query(r#"{ topProducts { name } }"#)
+            .build();
+        query(&listen_address, &request).await.unwrap();
+        assert_eq!(last_requested_uri().as_deref(), Some(BLUE));
+        let clients = created_clients.load(Ordering::SeqCst);
+
+        configurations.unbounded_send(configuration(GREEN)).unwrap();
+        // the new URL is applied in the background
+        for _ in 0..50 {
+            tokio::time::sleep(Duration::from_millis(100)).await;
+            query(&listen_address, &request).await.unwrap();
+            if last_requested_uri().as_deref() == Some(GREEN) {
+                break;
+            }
+        }
+        assert_eq!(last_requested_uri().as_deref(), Some(GREEN));
+        // the router was not rebuilt, and kept its subgraph clients
+        assert_eq!(created_clients.load(Ordering::SeqCst), clients);
+        router_handle.shutdown().await.unwrap();
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn config_by_file_watching() {
         let (path, mut file) = create_temp_file();
@@ -813,6 +1606,114 @@ mod tests {
         assert!(matches!(stream.next().await.unwrap(), NoMoreConfiguration));
     }
 
+    const BASE_CONFIG: &str = r#"
+supergraph:
+  listen: 127.0.0.1:4001
+  introspection: true
+cors:
+  origins:
+    - https://a.example.com
+    - https://b.example.com
+"#;
+
+    const OVERLAY_CONFIG: &str = r#"
+supergraph:
+  listen: 127.0.0.1:4002
+cors:
+  origins:
+    - https://c.example.com
+"#;
+
+    fn assert_merged(configuration: &Configuration, listen: &str) {
+        // scalars are overridden
+        assert_eq!(
+            configuration.supergraph.listen,
+            ListenAddr::from(listen.parse::<std::net::SocketAddr>().unwrap())
+        );
+        // maps are merged
+        assert!(configuration.supergraph.introspection);
+        // arrays are replaced
+        assert_eq!(configuration.cors.origins, vec!["https://c.example.com"]);
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn config_by_files_merges_overlays() {
+        let (base_path, mut base_file) = create_temp_file();
+        write_and_flush(&mut base_file, BASE_CONFIG).await;
+        let (overlay_path, mut overlay_file) = create_temp_file();
+        write_and_flush(&mut overlay_file, OVERLAY_CONFIG).await;
+
+        let mut stream = ConfigurationSource::Files {
+            paths: vec![base_path, overlay_path],
+            watch: false,
+            delay: None,
+        }
+        .into_stream();
+        match stream.next().await.unwrap() {
+            UpdateConfiguration(configuration) => assert_merged(&configuration, "127.0.0.1:4002"),
+            _ => panic!("the event from the stream must be UpdateConfiguration"),
+        }
+        assert!(matches!(stream.next().await.unwrap(), NoMoreConfiguration));
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn config_by_files_watching() {
+        let (base_path, mut base_file) = create_temp_file();
+        write_and_flush(&mut base_file, BASE_CONFIG).await;
+        let (overlay_path, mut overlay_file) = create_temp_file();
+        write_and_flush(&mut overlay_file, OVERLAY_CONFIG).await;
+
+        let mut stream = ConfigurationSource::Files {
+            paths: vec![base_path, overlay_path],
+            watch: true,
+            delay: None,
+        }
+        .into_stream()
+        .boxed();
+
+        // First update is guaranteed
+        assert!(matches!(
+            stream.next().await.unwrap(),
+            UpdateConfiguration(_)
+        ));
+
+        // Modifying the overlay merges the files again
+        write_and_flush(
+            &mut overlay_file,
+            &OVERLAY_CONFIG.replace("127.0.0.1:4002", "127.0.0.1:4003"),
+        )
+        .await;
+        // The truncation of the file may be noticed before the new contents are written
+        let expected = ListenAddr::from("127.0.0.1:4003".parse::<std::net::SocketAddr>().unwrap());
+        loop {
+            match stream.next().await.unwrap() {
+                UpdateConfiguration(configuration)
+                    if configuration.supergraph.listen == expected =>
+                {
+                    assert_merged(&configuration, "127.0.0.1:4003");
+                    break;
+                }
+                UpdateConfiguration(_) => {}
+                _ => panic!("the event from the stream must be UpdateConfiguration"),
+            }
+        }
+    }
+
+    #[test]
+    fn merge_yaml_semantics() {
+        let mut base: serde_yaml::Value =
+            serde_yaml::from_str("{a: {b: 1, c: [1, 2]}, d: x}").unwrap();
+        merge_yaml(
+            &mut base,
+            serde_yaml::from_str("{a: {c: [3], e: 2}, d: {f: y}}").unwrap(),
+        );
+        assert_eq!(
+            base,
+            serde_yaml::from_str::<serde_yaml::Value>("{a: {b: 1, c: [3], e: 2}, d: {f: y}}")
+                .unwrap()
+        );
+    }
+
     #[test(tokio::test)]
     async fn schema_by_file_watching() {
         let (path, mut file) = create_temp_file();
@@ -862,4 +1763,82 @@ mod tests {
         assert!(matches!(stream.next().await.unwrap(), UpdateSchema(_)));
         assert!(matches!(stream.next().await.unwrap(), NoMoreSchema));
     }
+
+    #[test(tokio::test)]
+    async fn schema_by_gzip_file() {
+        use std::io::Write;
+
+        let schema = include_str!("testdata/supergraph.graphql");
+        let (path, file) = create_temp_file();
+        let mut encoder = flate2::write::GzEncoder::new(file, flate2::Compression::default());
+        encoder.write_all(schema.as_bytes()).unwrap();
+        encoder.finish().unwrap();
+
+        let mut stream = SchemaSource::File {
+            path,
+            watch: false,
+            delay: None,
+        }
+        .into_stream();
+        match stream.next().await.unwrap() {
+            UpdateSchema(sdl) => {
+                assert_eq!(sdl, schema);
+                assert!(Schema::parse(&sdl, &Default::default()).is_ok());
+            }
+            _ => panic!("the gzipped schema should be loaded"),
+        }
+        assert!(matches!(stream.next().await.unwrap(), NoMoreSchema));
+    }
+
+    // each stream delivers its schemas after the delay, then ends
+    fn connector(delays: Vec<(Duration, Vec<&'static str>)>) -> SchemaStreamConnector {
+        let delays = std::sync::Mutex::new(delays.into_iter());
+        SchemaStreamConnector::from(move || {
+            let (delay, schemas) = delays
+                .lock()
+                .unwrap()
+                .next()
+                .unwrap_or((Duration::ZERO, vec![]));
+            stream::once(tokio::time::sleep(delay))
+                .flat_map(move |_| stream::iter(schemas.clone()))
+                .map(String::from)
+                .boxed()
+        })
+    }
+
+    #[test(tokio::test)]
+    async fn schema_by_reconnecting_stream_within_grace() {
+        let mut stream = SchemaSource::Reconnecting {
+            connect: connector(vec![
+                (Duration::ZERO, vec!["first"]),
+                (Duration::ZERO, vec![]),
+                (Duration::ZERO, vec![]),
+                (Duration::from_millis(50), vec!["second"]),
+            ]),
+            grace: Duration::from_secs(1),
+        }
+        .into_stream();
+
+        assert!(matches!(stream.next().await.unwrap(), UpdateSchema(schema) if schema == "first"));
+        // the streams ending without a schema are connected again, until the fourth one delivers
+        // a schema within the grace period
+        assert!(matches!(stream.next().await.unwrap(), UpdateSchema(schema) if schema == "second"));
+        // the next streams end without a schema until the grace period expires
+        assert!(matches!(stream.next().await.unwrap(), NoMoreSchema));
+    }
+
+    #[test(tokio::test)]
+    async fn schema_by_reconnecting_stream_after_grace() {
+        let mut stream = SchemaSource::Reconnecting {
+            connect: connector(vec![
+                (Duration::ZERO, vec!["first"]),
+                (Duration::from_secs(5), vec!["second"]),
+            ]),
+            grace: Duration::from_millis(50),
+        }
+        .into_stream();
+
+        assert!(matches!(stream.next().await.unwrap(), UpdateSchema(schema) if schema == "first"));
+        assert!(matches!(stream.next().await.unwrap(), NoMoreSchema));
+    }
 }
@@ -1,7 +1,9 @@
 // With regards to ELv2 licensing, this entire file is license key functionality
+use std::collections::HashMap;
 use std::sync::Arc;
 
 use axum::response::IntoResponse;
+use futures::future::BoxFuture;
 use http::StatusCode;
 use multimap::MultiMap;
 use serde_json::Map;
@@ -14,19 +16,37 @@ use tower_service::Service;
 
 use crate::configuration::Configuration;
 use crate::configuration::ConfigurationError;
+use crate::configuration::OnPluginError;
+use crate::configuration::RedactionStrategy;
+use crate::configuration::WarmupOperation;
+use crate::json_ext::Path;
 use crate::plugin::DynPlugin;
 use crate::plugin::Handler;
+use crate::plugins::diagnostics::ActivePlugin;
+use crate::plugins::diagnostics::Diagnostics;
+use crate::plugins::diagnostics::APOLLO_DIAGNOSTICS;
+use crate::plugins::override_url::OverrideSubgraphUrl;
+use crate::plugins::override_url::APOLLO_OVERRIDE_SUBGRAPH_URL;
+use crate::plugins::subgraph_signing::SubgraphSigning;
+use crate::plugins::subgraph_signing::APOLLO_SUBGRAPH_SIGNING;
+use crate::plugins::telemetry::Telemetry;
 use crate::plugins::traffic_shaping::TrafficShaping;
 use crate::plugins::traffic_shaping::APOLLO_TRAFFIC_SHAPING;
+use crate::services::measured_buffer::BufferMetrics;
 use crate::services::new_service::NewService;
+use crate::services::subgraph_service::differ_cosmetically;
+use crate::services::supergraph;
 use crate::services::RouterCreator;
 use crate::services::SubgraphService;
 use crate::services::SupergraphRequest;
 use crate::services::SupergraphResponse;
+use crate::spec::FieldType;
 use crate::transport;
 use crate::ListenAddr;
 use crate::PluggableSupergraphServiceBuilder;
 use crate::Schema;
+use crate::SubgraphClientHook;
+use crate::SubgraphResponseAdapter;
 
 #[derive(Clone)]
 /// A path and a handler to be exposed as a web_endpoint for plugins
@@ -53,6 +73,12 @@ impl Endpoint {
             handler: Handler::new(handler),
         }
     }
+
+    /// Records how many requests wait for the handler and for how long
+    pub(crate) fn set_buffer_metrics(&self, metrics: BufferMetrics) {
+        self.handler.set_buffer_metrics(metrics);
+    }
+
     pub(crate) fn into_router(self) -> axum::Router {
         let handler = move |req: http::Request<hyper::Body>| {
             let endpoint = self.handler.clone();
@@ -83,6 +109,40 @@ pub(crate) trait SupergraphServiceFactory:
     type Future: Send;
 
     fn web_endpoints(&self) -> MultiMap<ListenAddr, Endpoint>;
+
+    /// Records a request whose client disconnected before receiving the whole response
+    fn record_client_disconnect(&self) {}
+
+    /// Applies the subgraph URL overrides of a new configuration to the next requests.
+    /// Returns false if the router must be rebuilt to apply them
+    fn update_subgraph_urls(&self, _configuration: &Configuration) -> bool {
+        false
+    }
+
+    /// Applies the new configuration of the given plugins to the next requests, through
+    /// [`crate::plugin::Plugin::on_config_reload`]. If a plugin does not apply it, the plugins
+    /// that did get the previous configuration back.
+    /// Returns false if the router must be rebuilt to apply it
+    fn reload_plugins<'a>(
+        &'a self,
+        _plugins: &'a [String],
+        _previous_configuration: &'a Configuration,
+        _configuration: &'a Configuration,
+    ) -> BoxFuture<'a, bool> {
+        Box::pin(async { false })
+    }
+
+    /// Plans the given operations, so their query plans are cached before the first requests
+    fn warm_up(&self, _operations: Vec<WarmupOperation>) -> BoxFuture<'static, ()> {
+        Box::pin(async {})
+    }
+
+    /// Creates a service for the requests of the router itself, like its health queries, that
+    /// runs below the plugins: they can't reject these requests, and don't count them in their
+    /// metrics and usage reports. Returns `None` if the factory can't create one
+    fn new_internal_service(&self) -> Option<supergraph::BoxService> {
+        None
+    }
 }
 
 /// Factory for creating a SupergraphServiceFactory
@@ -104,7 +164,12 @@ pub(crate) trait SupergraphServiceConfigurator: Send + Sync + 'static {
 
 /// Main implementation of the SupergraphService factory, supporting the extensions system
 #[derive(Default)]
-pub(crate) struct YamlSupergraphServiceFactory;
+pub(crate) struct YamlSupergraphServiceFactory {
+    /// Wraps the HTTP client of each subgraph
+    pub(crate) subgraph_client_hook: Option<SubgraphClientHook>,
+    /// Converts the responses of subgraphs with a non-standard format, by subgraph name
+    pub(crate) subgraph_response_adapters: HashMap<String, SubgraphResponseAdapter>,
+}
 
 #[async_trait::async_trait]
 impl SupergraphServiceConfigurator for YamlSupergraphServiceFactory {
@@ -117,22 +182,76 @@ impl SupergraphServiceConfigurator for YamlSupergraphServiceFactory {
         _previous_router: Option<&'a Self::SupergraphServiceFactory>,
         extra_plugins: Option<Vec<(String, Box<dyn DynPlugin>)>>,
     ) -> Result<Self::SupergraphServiceFactory, BoxError> {
+        validate_fallbacks(&configuration, &schema)?;
+        validate_redaction(&configuration, &schema)?;
+
         // Process the plugins.
         let plugins = create_plugins(&configuration, &schema, extra_plugins).await?;
+        if let Some(override_url) = plugins
+            .iter()
+            .find(|i| i.0.as_str() == APOLLO_OVERRIDE_SUBGRAPH_URL)
+            .and_then(|plugin| (&*plugin.1).as_any().downcast_ref::<OverrideSubgraphUrl>())
+        {
+            normalize_subgraph_url_overrides(&configuration, &schema, override_url)?;
+        }
 
         let mut builder = PluggableSupergraphServiceBuilder::new(schema.clone());
-        builder = builder.with_configuration(configuration);
+        builder = builder.with_configuration(configuration.clone());
+
+        let telemetry = plugins
+            .iter()
+            .find(|i| i.0.as_str() == "apollo.telemetry")
+            .and_then(|plugin| (&*plugin.1).as_any().downcast_ref::<Telemetry>());
+
+        let shaping = plugins
+            .iter()
+            .find(|i| i.0.as_str() == APOLLO_TRAFFIC_SHAPING)
+            .and_then(|plugin| (&*plugin.1).as_any().downcast_ref::<TrafficShaping>());
 
         for (name, _) in schema.subgraphs() {
-            let subgraph_service = match plugins
+            // the client hook wraps the client built with this setting
+            let mut subgraph_service = SubgraphService::new(name)
+                .with_title_case_headers(
+                    shaping
+                        .map(|shaping| shaping.title_case_headers(name))
+                        .unwrap_or_default(),
+                )
+                .with_accept(configuration.subgraph.accept(name))
+                .with_url_template(configuration.subgraph.url_template(name))
+                .with_reject_empty_responses(configuration.subgraph.reject_empty_responses(name));
+            if let Some(hook) = &self.subgraph_client_hook {
+                let buffer_metrics = telemetry
+                    .and_then(|telemetry| telemetry.buffer_metrics(&format!("subgraph.{}", name)))
+                    .unwrap_or_default();
+                subgraph_service = subgraph_service.with_client_hook(hook, buffer_metrics);
+            }
+            if let Some(metrics) =
+                telemetry.and_then(|telemetry| telemetry.connection_pool_metrics(name))
+            {
+                subgraph_service = subgraph_service.with_connection_pool_metrics(metrics);
+            }
+            if let Some(adapter) = self.subgraph_response_adapters.get(name) {
+                subgraph_service = subgraph_service.with_response_adapter(adapter.clone());
+            }
+            if let Some(signing) = plugins
                 .iter()
-                .find(|i| i.0.as_str() == APOLLO_TRAFFIC_SHAPING)
-                .and_then(|plugin| (&*plugin.1).as_any().downcast_ref::<TrafficShaping>())
+                .find(|i| i.0.as_str() == APOLLO_SUBGRAPH_SIGNING)
+                .and_then(|plugin| (&*plugin.1).as_any().downcast_ref::<SubgraphSigning>())
             {
-                Some(shaping) => {
-                    Either::A(shaping.subgraph_service_internal(name, SubgraphService::new(name)))
-                }
-                None => Either::B(SubgraphService::new(name)),
+                subgraph_service = subgraph_service.with_signer(signing.signer(name));
+            }
+            let subgraph_service = match shaping {
+                Some(shaping) => Either::A(
+                    shaping.subgraph_service_internal(
+                        name,
+                        subgraph_service
+                            .with_follow_redirects(shaping.follow_redirects(name))
+                            .with_retry_after(shaping.retry_after(name))
+                            .with_concurrency_limit(shaping.concurrency_limit(name))
+                            .with_batching(shaping.batching(name)),
+                    ),
+                ),
+                None => Either::B(subgraph_service),
             };
             builder = builder.with_subgraph_service(name, subgraph_service);
         }
@@ -171,6 +290,108 @@ caused by
     );
 }
 
+/// Checks that the fallbacks are only configured for nullable fields of the schema, with values
+/// of the type of the field
+fn validate_fallbacks(
+    configuration: &Configuration,
+    schema: &Schema,
+) -> Result<(), ConfigurationError> {
+    for (path, value) in &configuration.fallbacks.fields {
+        if let Some(field_type) = schema.field_type_at(&Path::from(path)) {
+            if field_type.is_non_null() {
+                return Err(ConfigurationError::InvalidConfiguration {
+                    message: "invalid 'fallbacks.fields' configuration",
+                    error: format!("'{}' is non-nullable and can't have a fallback value", path),
+                });
+            }
+            let value =
+                serde_json_bytes::to_value(value).expect("a JSON value can always be converted");
+            if field_type.validate_output_value(&value, schema).is_err() {
+                return Err(ConfigurationError::InvalidConfiguration {
+                    message: "invalid 'fallbacks.fields' configuration",
+                    error: format!(
+                        "the fallback value of '{}' is not a valid '{}' value",
+                        path, field_type
+                    ),
+                });
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Checks that the redacted fields exist in the schema, that only nullable fields are nullified,
+/// and that only `String` and `ID` fields are masked or hashed, since their value is replaced
+/// with a string
+fn validate_redaction(
+    configuration: &Configuration,
+    schema: &Schema,
+) -> Result<(), ConfigurationError> {
+    for (path, strategy) in &configuration.redaction.fields {
+        let field_type = schema.field_type_at(&Path::from(path)).ok_or_else(|| {
+            ConfigurationError::InvalidConfiguration {
+                message: "invalid 'redaction.fields' configuration",
+                error: format!(
+                    "'{}' does not match a field of the schema, or matches fields of different types",
+                    path
+                ),
+            }
+        })?;
+        let leaf_type = match field_type {
+            FieldType::NonNull(inner) => inner.as_ref(),
+            field_type => field_type,
+        };
+        let error = match strategy {
+            RedactionStrategy::Nullify if field_type.is_non_null() => format!(
+                "'{}' is non-nullable and can't be nullified, use a mask or a hash instead",
+                path
+            ),
+            RedactionStrategy::Mask(_) | RedactionStrategy::Hash
+                if !matches!(leaf_type, FieldType::String | FieldType::Id) =>
+            {
+                format!(
+                    "'{}' is a '{}' field, only String and ID fields can be masked or hashed",
+                    path, field_type
+                )
+            }
+            _ => continue,
+        };
+        return Err(ConfigurationError::InvalidConfiguration {
+            message: "invalid 'redaction.fields' configuration",
+            error,
+        });
+    }
+    Ok(())
+}
+
+/// Applies the trailing slash policy to the overridden subgraph URLs, and warns about the
+/// overrides that only differ from the URL of the supergraph schema by their scheme or a
+/// trailing slash, which are usually mistakes
+fn normalize_subgraph_url_overrides(
+    configuration: &Configuration,
+    schema: &Schema,
+    override_url: &OverrideSubgraphUrl,
+) -> Result<(), BoxError> {
+    override_url.update(
+        configuration.subgraph_url_overrides(),
+        configuration.supergraph.subgraph_url_trailing_slash,
+    )?;
+    let overrides = override_url.urls();
+    for (name, url) in schema.subgraphs() {
+        if let Some(override_url) = overrides.get(name) {
+            if differ_cosmetically(url, override_url) {
+                tracing::warn!(
+                    "the URL override of subgraph '{}' ({}) only differs from its URL in the supergraph schema ({}) by its scheme or a trailing slash",
+                    name,
+                    override_url,
+                    url
+                );
+            }
+        }
+    }
+    Ok(())
+}
+
 async fn create_plugins(
     configuration: &Configuration,
     schema: &Schema,
@@ -183,6 +404,7 @@ async fn create_plugins(
         "apollo.telemetry",
     ];
 
+    let on_error = configuration.on_plugin_error;
     let mut errors = Vec::new();
     let plugin_registry = crate::plugin::plugins();
     let mut plugin_instances = Vec::new();
@@ -196,11 +418,16 @@ async fn create_plugins(
 
         match plugin_registry.get(name.as_str()) {
             Some(factory) => {
-                tracing::debug!(
-                    "creating plugin: '{}' with configuration:\n{:#}",
-                    name,
-                    configuration
-                );
+                if name == APOLLO_SUBGRAPH_SIGNING {
+                    // the signing secrets must never be logged
+                    tracing::debug!("creating plugin: '{}'", name);
+                } else {
+                    tracing::debug!(
+                        "creating plugin: '{}' with configuration:\n{:#}",
+                        name,
+                        configuration
+                    );
+                }
                 if name == "apollo.telemetry" {
                     inject_schema_id(schema, &mut configuration);
                 }
@@ -212,6 +439,16 @@ async fn create_plugins(
                     Ok(plugin) => {
                         plugin_instances.push((name, plugin));
                     }
+                    Err(err)
+                        if on_error == OnPluginError::Skip
+                            && !mandatory_plugins.contains(&name.as_str()) =>
+                    {
+                        tracing::error!(
+                            "plugin {} could not be configured and is skipped: {}",
+                            name,
+                            err
+                        );
+                    }
                     Err(err) => errors.push(ConfigurationError::PluginConfiguration {
                         plugin: name,
                         error: err.to_string(),
@@ -273,6 +510,11 @@ async fn create_plugins(
         }
     }
 
+    list_active_plugins(
+        configuration,
+        plugin_instances.iter().map(|(name, plugin)| (name, plugin)),
+    );
+
     let plugin_details = plugin_instances
         .iter()
         .map(|(name, plugin)| (name, plugin.name()))
@@ -302,6 +544,29 @@ async fn create_plugins(
     }
 }
 
+/// Lists the plugins with their configuration in the diagnostics plugin, if it is enabled
+pub(crate) fn list_active_plugins<'a>(
+    configuration: &Configuration,
+    plugins: impl Iterator<Item = (&'a String, &'a Box<dyn DynPlugin>)> + Clone,
+) {
+    if let Some(diagnostics) = plugins
+        .clone()
+        .find(|(name, _)| name.as_str() == APOLLO_DIAGNOSTICS)
+        .and_then(|(_, plugin)| (&**plugin).as_any().downcast_ref::<Diagnostics>())
+    {
+        let configurations: HashMap<String, Value> = configuration.plugins().into_iter().collect();
+        diagnostics.set_plugins(
+            plugins
+                .map(|(name, plugin)| ActivePlugin {
+                    name: name.clone(),
+                    type_name: plugin.name(),
+                    config: configurations.get(name).cloned().unwrap_or_default(),
+                })
+                .collect(),
+        );
+    }
+}
+
 fn inject_schema_id(schema: &Schema, configuration: &mut Value) {
     if configuration.get("apollo").is_none() {
         if let Some(telemetry) = configuration.as_object_mut() {
@@ -327,18 +592,22 @@ mod test {
     use std::error::Error;
     use std::fmt;
     use std::sync::Arc;
+    use std::sync::Mutex;
 
     use schemars::JsonSchema;
     use serde::Deserialize;
     use serde_json::json;
+    use tower::ServiceExt;
     use tower_http::BoxError;
 
     use crate::configuration::Configuration;
     use crate::plugin::Plugin;
     use crate::plugin::PluginInit;
     use crate::register_plugin;
+    use crate::router_factory::create_plugins;
     use crate::router_factory::inject_schema_id;
     use crate::router_factory::SupergraphServiceConfigurator;
+    use crate::router_factory::SupergraphServiceFactory;
     use crate::router_factory::YamlSupergraphServiceFactory;
     use crate::Schema;
 
@@ -400,6 +669,38 @@ mod test {
         AlwaysFailsToStartPlugin
     );
 
+    // Applies its new configuration on reload
+
+    // The names applied by the reloads, in order
+    static RELOADED_NAMES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+    #[derive(Debug)]
+    struct ReloadablePlugin {
+        name: Mutex<String>,
+    }
+
+    #[async_trait::async_trait]
+    impl Plugin for ReloadablePlugin {
+        type Config = Conf;
+
+        async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+            Ok(ReloadablePlugin {
+                name: Mutex::new(init.config.name),
+            })
+        }
+
+        async fn on_config_reload(&self, init: PluginInit<Self::Config>) -> Result<bool, BoxError> {
+            RELOADED_NAMES
+                .lock()
+                .unwrap()
+                .push(init.config.name.clone());
+            *self.name.lock().unwrap() = init.config.name;
+            Ok(true)
+        }
+    }
+
+    register_plugin!("apollo.test", "reloadable", ReloadablePlugin);
+
     #[tokio::test]
     async fn test_yaml_no_extras() {
         let config = Configuration::builder().build().unwrap();
@@ -451,6 +752,162 @@ mod test {
         assert!(service.is_err())
     }
 
+    #[tokio::test]
+    async fn test_yaml_plugins_abort_on_error() {
+        let config: Configuration = serde_yaml::from_str(
+            r#"
+            on_plugin_error: abort
+            plugins:
+                apollo.test.always_starts_and_stops:
+                    name: albert
+                apollo.test.always_fails_to_start:
+                    name: albert
+        "#,
+        )
+        .unwrap();
+        let error = create_service(config).await.unwrap_err();
+        assert!(error
+            .to_string()
+            .contains("apollo.test.always_fails_to_start"));
+    }
+
+    #[tokio::test]
+    async fn test_yaml_plugins_skip_on_error() {
+        let config: Configuration = serde_yaml::from_str(
+            r#"
+            on_plugin_error: skip
+            plugins:
+                apollo.test.always_starts_and_stops:
+                    name: albert
+                apollo.test.always_fails_to_start:
+                    name: albert
+        "#,
+        )
+        .unwrap();
+        let schema = include_str!("testdata/supergraph.graphql");
+        let schema = Schema::parse(schema, &config).unwrap();
+        let plugins = create_plugins(&config, &schema, None).await.unwrap();
+        // the failing plugin was dropped, the other one started
+        assert!(plugins
+            .iter()
+            .any(|(name, _)| name == "apollo.test.always_starts_and_stops"));
+        assert!(!plugins
+            .iter()
+            .any(|(name, _)| name == "apollo.test.always_fails_to_start"));
+
+        let service = create_service(config).await;
+        assert!(service.is_ok())
+    }
+
+    #[test]
+    fn test_yaml_plugins_invalid_on_error() {
+        let config = serde_yaml::from_str::<Configuration>(
+            r#"
+            on_plugin_error: ignore
+        "#,
+        );
+        assert!(config.is_err())
+    }
+
+    #[tokio::test]
+    async fn test_diagnostics_lists_plugins_with_redacted_configuration() {
+        let config: Configuration = serde_yaml::from_str(
+            r#"
+            diagnostics:
+                enabled: true
+            plugins:
+                apollo.test.always_starts_and_stops:
+                    name: albert
+                    api_token: abc
+        "#,
+        )
+        .unwrap();
+        let schema = include_str!("testdata/supergraph.graphql");
+        let schema = Schema::parse(schema, &config).unwrap();
+        let plugins = create_plugins(&config, &schema, None).await.unwrap();
+
+        let (_, diagnostics) = plugins
+            .iter()
+            .find(|(name, _)| name == "apollo.diagnostics")
+            .unwrap();
+        let endpoints = diagnostics.web_endpoints();
+        let (_, endpoints) = endpoints.into_iter().next().unwrap();
+        let router = endpoints.into_iter().next().unwrap().into_router();
+        let mut response = router
+            .oneshot(
+                http::Request::get("http://localhost:9090/plugins")
+                    .body(Default::default())
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let body = hyper::body::to_bytes(response.body_mut()).await.unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+
+        let plugin = body["plugins"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|plugin| plugin["name"] == "apollo.test.always_starts_and_stops")
+            .expect("the plugin should be listed");
+        assert_eq!(
+            plugin["config"],
+            json!({ "name": "albert", "api_token": "[REDACTED]" })
+        );
+        assert!(plugin["type"]
+            .as_str()
+            .unwrap()
+            .ends_with("AlwaysStartsAndStopsPlugin"));
+    }
+
+    #[tokio::test]
+    async fn test_plugins_apply_their_new_configuration_on_reload() {
+        let config = |reloadable: &str, other: &str| -> Configuration {
+            serde_yaml::from_str(&format!(
+                r#"
+            plugins:
+                apollo.test.reloadable:
+                    name: {}
+                apollo.test.always_starts_and_stops:
+                    name: {}
+        "#,
+                reloadable, other
+            ))
+            .unwrap()
+        };
+        let current = config("albert", "albert");
+        let schema = include_str!("testdata/supergraph.graphql");
+        let schema = Schema::parse(schema, &current).unwrap();
+        let factory = YamlSupergraphServiceFactory::default()
+            .create(Arc::new(current.clone()), Arc::new(schema), None, None)
+            .await
+            .unwrap();
+
+        let new = config("barbara", "albert");
+        let plugins = current.changed_plugins(&new).unwrap();
+        assert_eq!(plugins, vec!["apollo.test.reloadable".to_string()]);
+        assert!(factory.reload_plugins(&plugins, &current, &new).await);
+        assert_eq!(*RELOADED_NAMES.lock().unwrap(), vec!["barbara"]);
+
+        // a plugin without the hook needs a new router
+        let current = new;
+        let new = config("albert", "barbara");
+        let plugins = current.changed_plugins(&new).unwrap();
+        assert!(!factory.reload_plugins(&plugins, &current, &new).await);
+
+        // when the second plugin declines, the first one gets its previous configuration back
+        let new = config("charlie", "charlie");
+        let plugins = vec![
+            "apollo.test.reloadable".to_string(),
+            "apollo.test.always_starts_and_stops".to_string(),
+        ];
+        assert!(!factory.reload_plugins(&plugins, &current, &new).await);
+        assert_eq!(
+            *RELOADED_NAMES.lock().unwrap(),
+            vec!["barbara", "charlie", "barbara"]
+        );
+    }
+
     async fn create_service(config: Configuration) -> Result<(), BoxError> {
         let schema = include_str!("testdata/supergraph.graphql");
         let schema = Schema::parse(schema, &config).unwrap();
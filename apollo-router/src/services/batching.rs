@@ -0,0 +1,243 @@
+//! Batching of the requests sent to a subgraph by the fetches of a parallel plan node.
+//!
+//! When several fetches of a parallel node target the same subgraph, each of them gets a member
+//! of a [`Batch`] in the extensions of its HTTP request. If batching is enabled for the subgraph,
+//! the first member to reach the subgraph service leads the batch: it waits until every other
+//! member either submitted its query or left the batch, sends all the queries in a single HTTP
+//! request with an array body, and splits the array response between the members.
+//!
+//! The batch is sent with the headers of the leader, so a member whose headers differ, like when a
+//! plugin set a header for that fetch only, leaves the batch and sends its query on its own.
+//!
+//! A member leaves the batch when it is dropped without submitting its query, like when the fetch
+//! had no entities to fetch or a plugin answered the request, or when its request waits for an
+//! identical one instead of being sent, so the leader never waits for a request that will not come.
+
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+use http::HeaderMap;
+use tokio::sync::oneshot;
+use tokio::sync::Notify;
+
+use crate::error::FetchError;
+use crate::graphql;
+
+/// The response of a member of a batch, sent by the leader
+pub(crate) type BatchResponse = Result<http::Response<graphql::Response>, FetchError>;
+
+pub(crate) struct Batch {
+    state: Mutex<BatchState>,
+    // notified when a member submits its query or leaves the batch
+    changed: Notify,
+}
+
+struct BatchState {
+    // members that neither submitted their query nor left the batch
+    pending: usize,
+    // the headers of the leader, once it submitted its query
+    leader_headers: Option<HeaderMap>,
+    followers: Vec<Follower>,
+}
+
+impl Batch {
+    /// The members of a new batch of `size` requests
+    pub(crate) fn members(size: usize) -> Vec<BatchQuery> {
+        let batch = Arc::new(Batch {
+            state: Mutex::new(BatchState {
+                pending: size,
+                leader_headers: None,
+                followers: Vec::new(),
+            }),
+            changed: Notify::new(),
+        });
+        (0..size)
+            .map(|_| BatchQuery {
+                batch: batch.clone(),
+                done: AtomicBool::new(false),
+            })
+            .collect()
+    }
+}
+
+/// A member of a batch, carried in the extensions of the HTTP request of a fetch
+pub(crate) struct BatchQuery {
+    batch: Arc<Batch>,
+    // set once the query is submitted or the member left the batch
+    done: AtomicBool,
+}
+
+impl BatchQuery {
+    /// Adds the query to the batch, unless the member already left it or its headers differ from
+    /// the headers of the leader
+    pub(crate) fn submit(&self, body: graphql::Request, headers: &HeaderMap) -> Submission {
+        if self.done.swap(true, Ordering::SeqCst) {
+            return Submission::Alone(body);
+        }
+        let mut state = self.batch.state.lock().expect("lock poisoned");
+        state.pending -= 1;
+        let submission = match &state.leader_headers {
+            Some(leader_headers) if leader_headers == headers => {
+                let (sender, receiver) = oneshot::channel();
+                state.followers.push(Follower { body, sender });
+                Submission::Follower(receiver)
+            }
+            Some(_) => Submission::Alone(body),
+            None => {
+                state.leader_headers = Some(headers.clone());
+                Submission::Leader(
+                    Leader {
+                        batch: self.batch.clone(),
+                    },
+                    body,
+                )
+            }
+        };
+        drop(state);
+        self.batch.changed.notify_one();
+        submission
+    }
+
+    /// Leaves the batch, the query of the member will be sent on its own if it is sent at all
+    pub(crate) fn leave(&self) {
+        if !self.done.swap(true, Ordering::SeqCst) {
+            self.batch.state.lock().expect("lock poisoned").pending -= 1;
+            self.batch.changed.notify_one();
+        }
+    }
+}
+
+impl Drop for BatchQuery {
+    fn drop(&mut self) {
+        self.leave();
+    }
+}
+
+pub(crate) enum Submission {
+    /// Sends the batch, starting with its own query
+    Leader(Leader, graphql::Request),
+    /// Waits for its response from the leader
+    Follower(oneshot::Receiver<BatchResponse>),
+    /// Sends its query on its own, after leaving the batch
+    Alone(graphql::Request),
+}
+
+pub(crate) struct Leader {
+    batch: Arc<Batch>,
+}
+
+impl Leader {
+    /// The other queries of the batch, once all the members submitted their query or left
+    pub(crate) async fn followers(self) -> Vec<Follower> {
+        loop {
+            {
+                let mut state = self.batch.state.lock().expect("lock poisoned");
+                if state.pending == 0 {
+                    return std::mem::take(&mut state.followers);
+                }
+            }
+            // only the leader waits, so a notification sent before this point is not lost
+            self.batch.changed.notified().await;
+        }
+    }
+}
+
+pub(crate) struct Follower {
+    pub(crate) body: graphql::Request,
+    sender: oneshot::Sender<BatchResponse>,
+}
+
+impl Follower {
+    pub(crate) fn respond(self, response: BatchResponse) {
+        // the follower may have been cancelled, like when its request timed out
+        let _ = self.sender.send(response);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    fn request(query: &str) -> graphql::Request {
+        graphql::Request::builder().query(query.to_string()).build()
+    }
+
+    #[tokio::test]
+    async fn it_waits_for_the_members_that_did_not_leave() {
+        let mut members = Batch::members(3).into_iter();
+        let (first, second, third) = (
+            members.next().unwrap(),
+            members.next().unwrap(),
+            members.next().unwrap(),
+        );
+
+        let leader = match first.submit(request("{ a }"), &HeaderMap::new()) {
+            Submission::Leader(leader, body) => {
+                assert_eq!(body.query.as_deref(), Some("{ a }"));
+                leader
+            }
+            _ => panic!("the first member leads the batch"),
+        };
+        let followers = tokio::spawn(leader.followers());
+
+        let receiver = match second.submit(request("{ b }"), &HeaderMap::new()) {
+            Submission::Follower(receiver) => receiver,
+            _ => panic!("the batch already has a leader"),
+        };
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        assert!(!followers.is_finished());
+
+        // the third fetch has nothing to send
+        drop(third);
+        let followers = tokio::time::timeout(Duration::from_secs(5), followers)
+            .await
+            .expect("the leader should not wait for the member that left")
+            .unwrap();
+        assert_eq!(followers.len(), 1);
+        assert_eq!(followers[0].body.query.as_deref(), Some("{ b }"));
+
+        followers.into_iter().for_each(|follower| {
+            follower.respond(Ok(http::Response::new(
+                graphql::Response::builder().build(),
+            )))
+        });
+        assert!(receiver.await.unwrap().is_ok());
+    }
+
+    #[tokio::test]
+    async fn members_with_other_headers_are_sent_alone() {
+        let mut members = Batch::members(3).into_iter();
+        let (first, second, third) = (
+            members.next().unwrap(),
+            members.next().unwrap(),
+            members.next().unwrap(),
+        );
+        let mut headers = HeaderMap::new();
+        headers.insert("x-tenant", "a".parse().unwrap());
+        let mut other_headers = HeaderMap::new();
+        other_headers.insert("x-tenant", "b".parse().unwrap());
+
+        let leader = match first.submit(request("{ a }"), &headers) {
+            Submission::Leader(leader, _) => leader,
+            _ => panic!("the first member leads the batch"),
+        };
+        assert!(matches!(
+            second.submit(request("{ b }"), &other_headers),
+            Submission::Alone(_)
+        ));
+        assert!(matches!(
+            third.submit(request("{ c }"), &headers),
+            Submission::Follower(_)
+        ));
+
+        let followers = tokio::time::timeout(Duration::from_secs(5), leader.followers())
+            .await
+            .expect("the leader should not wait for the member sent alone");
+        assert_eq!(followers.len(), 1);
+        assert_eq!(followers[0].body.query.as_deref(), Some("{ c }"));
+    }
+}
@@ -11,6 +11,9 @@ use futures::future::BoxFuture;
 use futures::stream::once;
 use futures::SinkExt;
 use futures::StreamExt;
+use sha2::Digest;
+use sha2::Sha256;
+use tokio_util::sync::CancellationToken;
 use tower::BoxError;
 use tower::ServiceBuilder;
 use tower::ServiceExt;
@@ -21,10 +24,16 @@ use super::layers::allow_only_http_post_mutations::AllowOnlyHttpPostMutationsLay
 use super::new_service::NewService;
 use super::subgraph_service::SubgraphServiceFactory;
 use super::Plugins;
+use crate::configuration::RedactionStrategy;
 use crate::graphql::IncrementalResponse;
 use crate::graphql::Response;
+use crate::json_ext::Path;
+use crate::json_ext::Value;
 use crate::json_ext::ValueExt;
+use crate::query_planner::metrics::DeferMetrics;
+use crate::query_planner::ExecutionOptions;
 use crate::services::execution;
+use crate::spec::Query;
 use crate::ExecutionRequest;
 use crate::ExecutionResponse;
 use crate::Schema;
@@ -34,6 +43,8 @@ use crate::Schema;
 pub(crate) struct ExecutionService<SF: SubgraphServiceFactory> {
     pub(crate) schema: Arc<Schema>,
     pub(crate) subgraph_creator: Arc<SF>,
+    pub(crate) defer_metrics: DeferMetrics,
+    pub(crate) options: Arc<ExecutionOptions>,
 }
 
 impl<SF> Service<ExecutionRequest> for ExecutionService<SF>
@@ -63,7 +74,13 @@ where
         let fut = async move {
             let context = req.context;
             let ctx = context.clone();
-            let (sender, receiver) = futures::channel::mpsc::channel(10);
+            let cancellation = CancellationToken::new();
+            // owned by this future, then by the response stream: dropping either, like when the
+            // client disconnects, stops the deferred nodes still running
+            let cancel_on_drop = cancellation.clone().drop_guard();
+            let buffer_size = this.options.max_buffered_deferred_responses;
+            // the deferred parts wait for the client to read when the buffer is full
+            let (sender, receiver) = futures::channel::mpsc::channel(buffer_size);
             let variables = req.supergraph_request.body().variables.clone();
             let operation_name = req.supergraph_request.body().operation_name.clone();
 
@@ -78,13 +95,19 @@ where
                     &this.subgraph_creator,
                     &Arc::new(req.supergraph_request),
                     &this.schema,
+                    &this.defer_metrics,
+                    &cancellation,
+                    &this.options,
                     sender,
                 )
                 .await;
 
             let query = req.query_plan.query.clone();
+            let large_integers = req.query_plan.options.large_integers;
+            let strip_unrequested_fields = req.query_plan.options.strip_unrequested_fields;
+            let redactions = this.options.redactions.clone();
             let stream = if is_deferred {
-                filter_stream(first, receiver).boxed()
+                filter_stream(first, receiver, buffer_size).boxed()
             } else {
                 once(ready(first)).chain(receiver).boxed()
             };
@@ -93,6 +116,7 @@ where
 
             let stream = stream
                 .map(move |mut response: Response| {
+                    let _cancel_on_drop = &cancel_on_drop;
                     let has_next = response.has_next.unwrap_or(true);
                     tracing::debug_span!("format_response").in_scope(|| {
                         query.format_response(
@@ -101,8 +125,13 @@ where
                             is_deferred,
                             variables.clone(),
                             schema.api_schema(),
+                            large_integers,
+                            strip_unrequested_fields,
                         )
                     });
+                    if let Some(data) = response.data.as_mut() {
+                        redact(&query, operation_name.as_deref(), &redactions, data);
+                    }
 
                     match (response.path.as_ref(), response.data.as_ref()) {
                         (None, _) | (_, None) => {
@@ -169,9 +198,48 @@ where
     }
 }
 
+/// Replaces the values of the redacted fields in the response data.
+///
+/// The configured paths are made of schema field names, so aliased fields are redacted too. Null
+/// values are left as they are.
+fn redact(
+    query: &Query,
+    operation_name: Option<&str>,
+    redactions: &[(Path, RedactionStrategy)],
+    data: &mut Value,
+) {
+    for (path, strategy) in redactions {
+        query.for_each_field_value_mut(operation_name, path, data, &mut |value| {
+            if !value.is_null() {
+                *value = redacted_value(strategy, value);
+            }
+        });
+    }
+}
+
+fn redacted_value(strategy: &RedactionStrategy, value: &Value) -> Value {
+    match strategy {
+        RedactionStrategy::Nullify => Value::Null,
+        RedactionStrategy::Mask(mask) => Value::String(mask.as_str().into()),
+        RedactionStrategy::Hash => {
+            let digest = match value {
+                Value::String(string) => Sha256::digest(string.as_str().as_bytes()),
+                _ => Sha256::digest(
+                    serde_json::to_vec(value).expect("JSON serialization should not fail"),
+                ),
+            };
+            Value::String(hex::encode(digest).into())
+        }
+    }
+}
+
 // modifies the response stream to set `has_next` to `false` on the last response
-fn filter_stream(first: Response, mut stream: Receiver<Response>) -> Receiver<Response> {
-    let (mut sender, receiver) = futures::channel::mpsc::channel(10);
+fn filter_stream(
+    first: Response,
+    mut stream: Receiver<Response>,
+    buffer_size: usize,
+) -> Receiver<Response> {
+    let (mut sender, receiver) = futures::channel::mpsc::channel(buffer_size);
 
     tokio::task::spawn(async move {
         let mut seen_last_message = consume_responses(first, &mut stream, &mut sender).await?;
@@ -246,6 +314,8 @@ pub(crate) struct ExecutionCreator<SF: SubgraphServiceFactory> {
     pub(crate) schema: Arc<Schema>,
     pub(crate) plugins: Arc<Plugins>,
     pub(crate) subgraph_creator: Arc<SF>,
+    pub(crate) defer_metrics: DeferMetrics,
+    pub(crate) options: Arc<ExecutionOptions>,
 }
 
 impl<SF> NewService<ExecutionRequest> for ExecutionCreator<SF>
@@ -262,6 +332,8 @@ where
                     crate::services::execution_service::ExecutionService {
                         schema: self.schema.clone(),
                         subgraph_creator: self.subgraph_creator.clone(),
+                        defer_metrics: self.defer_metrics.clone(),
+                        options: self.options.clone(),
                     }
                     .boxed(),
                     |acc, (_, e)| e.execution_service(acc),
@@ -277,3 +349,49 @@ impl<SF: SubgraphServiceFactory> ExecutionServiceFactory for ExecutionCreator<SF
         ExecutionRequest,
     >>::Future;
 }
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn slow_clients_throttle_deferred_responses() {
+        let buffer_size = 2;
+        let (mut sender, receiver) = futures::channel::mpsc::channel(buffer_size);
+        let sent = Arc::new(AtomicUsize::new(0));
+        let sent_by_producer = sent.clone();
+        tokio::task::spawn(async move {
+            for i in 0..100 {
+                let response = Response::builder().label(i.to_string()).build();
+                sender.send(response).await.unwrap();
+                sent_by_producer.fetch_add(1, Ordering::SeqCst);
+            }
+        });
+        let mut stream = filter_stream(Response::builder().build(), receiver, buffer_size);
+
+        // the client does not read: the producer waits once the buffers are full
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        let buffered = sent.load(Ordering::SeqCst);
+        assert!(
+            buffered <= 4 * (buffer_size + 1),
+            "{} responses were buffered",
+            buffered
+        );
+
+        // the client reads slowly, and eventually gets all the responses
+        let mut received = Vec::new();
+        while let Some(response) = stream.next().await {
+            received.push(response);
+            tokio::time::sleep(Duration::from_millis(1)).await;
+        }
+        // an empty last response is added if the producer was not done when the buffer emptied
+        let deferred = received.iter().filter(|r| r.label.is_some()).count();
+        assert_eq!(deferred, 100);
+        assert_eq!(received.last().unwrap().has_next, Some(false));
+        assert_eq!(sent.load(Ordering::SeqCst), 100);
+    }
+}
@@ -11,14 +11,14 @@ use serde_json_bytes::json;
 use serde_json_bytes::Value;
 use sha2::Digest;
 use sha2::Sha256;
-use tower::buffer::Buffer;
 use tower::BoxError;
 use tower::Layer;
 use tower::Service;
 
 use crate::cache::DeduplicatingCache;
 use crate::layers::async_checkpoint::AsyncCheckpointService;
-use crate::layers::DEFAULT_BUFFER_SIZE;
+use crate::services::measured_buffer::BufferMetrics;
+use crate::services::measured_buffer::MeasuredBuffer;
 use crate::SupergraphRequest;
 use crate::SupergraphResponse;
 
@@ -35,17 +35,25 @@ struct PersistedQuery {
 #[derive(Clone)]
 pub(crate) struct APQLayer {
     cache: DeduplicatingCache<String, String>,
+    buffer_metrics: BufferMetrics,
 }
 
 impl APQLayer {
     pub(crate) async fn new() -> Self {
+        Self::with_cache(DeduplicatingCache::new().await)
+    }
+
+    pub(crate) fn with_cache(cache: DeduplicatingCache<String, String>) -> Self {
         Self {
-            cache: DeduplicatingCache::new().await,
+            cache,
+            buffer_metrics: BufferMetrics::default(),
         }
     }
 
-    pub(crate) fn with_cache(cache: DeduplicatingCache<String, String>) -> Self {
-        Self { cache }
+    /// Records how many requests wait in the buffer of the layered services and for how long
+    pub(crate) fn with_buffer_metrics(mut self, buffer_metrics: BufferMetrics) -> Self {
+        self.buffer_metrics = buffer_metrics;
+        self
     }
 
     pub(crate) async fn apq_request(
@@ -122,7 +130,7 @@ where
     <S as Service<SupergraphRequest>>::Future: Send + 'static,
 {
     type Service = AsyncCheckpointService<
-        Buffer<S, SupergraphRequest>,
+        MeasuredBuffer<S, SupergraphRequest>,
         BoxFuture<
             'static,
             Result<
@@ -216,7 +224,7 @@ where
                         >,
                     >
             },
-            Buffer::new(service, DEFAULT_BUFFER_SIZE),
+            MeasuredBuffer::new(service, self.buffer_metrics.clone()),
         )
     }
 }
@@ -0,0 +1,228 @@
+//! Catch the panics happening while processing a [`SupergraphRequest`].
+//!
+//! See [`Layer`] and [`Service`] for more details.
+//!
+//! A panic in a plugin or in the services it wraps would otherwise end the request with an
+//! opaque error. The panic is logged and counted instead, and the client gets a GraphQL error
+//! with the configured message.
+
+use std::any::Any;
+use std::panic::AssertUnwindSafe;
+use std::task::Poll;
+
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use http::StatusCode;
+use tower::BoxError;
+use tower::Layer;
+use tower::Service;
+
+use crate::plugins::telemetry::metrics::AggregateCounter;
+use crate::plugins::telemetry::metrics::AggregateMeterProvider;
+use crate::Context;
+use crate::SupergraphRequest;
+use crate::SupergraphResponse;
+
+#[derive(Clone)]
+pub(crate) struct CatchPanic {
+    message: String,
+    panics: AggregateCounter<u64>,
+}
+
+impl CatchPanic {
+    pub(crate) fn new(message: String, meter_provider: &AggregateMeterProvider) -> Self {
+        let meter = meter_provider.meter("apollo/router", None);
+        Self {
+            message,
+            panics: meter.build_counter(|m| {
+                m.u64_counter("apollo_router_panics_total")
+                    .with_description("Number of panics caught while processing requests.")
+                    .init()
+            }),
+        }
+    }
+}
+
+impl<S> Layer<S> for CatchPanic {
+    type Service = CatchPanicService<S>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        CatchPanicService {
+            inner: service,
+            message: self.message.clone(),
+            panics: self.panics.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct CatchPanicService<S> {
+    inner: S,
+    message: String,
+    panics: AggregateCounter<u64>,
+}
+
+impl<S> Service<SupergraphRequest> for CatchPanicService<S>
+where
+    S: Service<SupergraphRequest, Response = SupergraphResponse, Error = BoxError>,
+    S::Future: Send + 'static,
+{
+    type Response = SupergraphResponse;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut std::task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: SupergraphRequest) -> Self::Future {
+        let context = req.context.clone();
+        let message = self.message.clone();
+        let panics = self.panics.clone();
+
+        // plugins can panic while handling the request, before returning a future
+        let inner = &mut self.inner;
+        let future = match std::panic::catch_unwind(AssertUnwindSafe(|| inner.call(req))) {
+            Ok(future) => future,
+            Err(payload) => {
+                let response = panic_response(payload, message, &panics, context);
+                return futures::future::ready(Ok(response)).boxed();
+            }
+        };
+
+        async move {
+            match AssertUnwindSafe(future).catch_unwind().await {
+                Ok(result) => result,
+                Err(payload) => Ok(panic_response(payload, message, &panics, context)),
+            }
+        }
+        .boxed()
+    }
+}
+
+fn panic_response(
+    payload: Box<dyn Any + Send>,
+    message: String,
+    panics: &AggregateCounter<u64>,
+    context: Context,
+) -> SupergraphResponse {
+    let panic = payload
+        .downcast_ref::<&str>()
+        .copied()
+        .or_else(|| payload.downcast_ref::<String>().map(String::as_str))
+        .unwrap_or("unknown panic payload");
+    tracing::error!(panic, "the processing of a request panicked");
+    panics.add(1, &[]);
+
+    let errors = vec![crate::error::Error {
+        message,
+        extensions: serde_json_bytes::json!({
+            "code": "INTERNAL_SERVER_ERROR",
+        })
+        .as_object()
+        .unwrap()
+        .to_owned(),
+        ..Default::default()
+    }];
+
+    //We do not copy headers from the request to the response as the panic may have happened at any point
+    SupergraphResponse::builder()
+        .errors(errors)
+        .status_code(StatusCode::INTERNAL_SERVER_ERROR)
+        .context(context)
+        .build()
+        .expect("building a response like this should not fail")
+}
+
+#[cfg(test)]
+mod catch_panic_tests {
+    use std::sync::Arc;
+
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::plugin::test::MockSupergraphService;
+    use crate::plugin::Plugin;
+    use crate::plugin::PluginInit;
+    use crate::services::supergraph;
+
+    const MESSAGE: &str = "Something went wrong, please try again later.";
+
+    struct PanickingPlugin {
+        on_response: bool,
+    }
+
+    #[async_trait::async_trait]
+    impl Plugin for PanickingPlugin {
+        type Config = bool;
+
+        async fn new(init: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+            Ok(PanickingPlugin {
+                on_response: init.config,
+            })
+        }
+
+        fn supergraph_service(&self, service: supergraph::BoxService) -> supergraph::BoxService {
+            if self.on_response {
+                service
+                    .map_response(|_response| panic!("plugin panicked on the response"))
+                    .boxed()
+            } else {
+                service
+                    .map_request(|_request: SupergraphRequest| {
+                        panic!("plugin panicked on the request")
+                    })
+                    .boxed()
+            }
+        }
+    }
+
+    fn caught_panics(exporter: &opentelemetry_prometheus::PrometheusExporter) -> f64 {
+        exporter
+            .registry()
+            .gather()
+            .iter()
+            .filter(|family| family.get_name().starts_with("apollo_router_panics"))
+            .flat_map(|family| family.get_metric())
+            .map(|metric| metric.get_counter().get_value())
+            .sum()
+    }
+
+    #[tokio::test]
+    async fn panics_are_turned_into_graphql_errors() {
+        let exporter = opentelemetry_prometheus::exporter().init();
+        let meter_provider = AggregateMeterProvider::new(vec![Arc::new(
+            exporter.provider().expect("meter provider"),
+        )]);
+        let catch_panic = CatchPanic::new(MESSAGE.to_string(), &meter_provider);
+
+        for (on_response, panics) in [(false, 1.0), (true, 2.0)] {
+            let mut mock_service = MockSupergraphService::new();
+            mock_service.expect_call().returning(move |_req| {
+                Ok(SupergraphResponse::fake_builder()
+                    .build()
+                    .expect("expecting valid response"))
+            });
+            let plugin = PanickingPlugin { on_response };
+            let service_stack = catch_panic.layer(plugin.supergraph_service(mock_service.boxed()));
+
+            let request = SupergraphRequest::fake_builder()
+                .query("{ topProducts { name } }")
+                .build()
+                .expect("expecting valid request");
+
+            let mut response = service_stack.oneshot(request).await.unwrap();
+            assert_eq!(
+                response.response.status(),
+                StatusCode::INTERNAL_SERVER_ERROR
+            );
+            let response = response.next_response().await.unwrap();
+            assert_eq!(response.errors[0].message, MESSAGE);
+            assert_eq!(
+                response.errors[0].extensions.get("code"),
+                Some(&"INTERNAL_SERVER_ERROR".into())
+            );
+            assert_eq!(caught_panics(&exporter), panics);
+        }
+    }
+}
@@ -1,4 +1,8 @@
 //! Layers that are internal to the execution pipeline.
 pub(crate) mod allow_only_http_post_mutations;
 pub(crate) mod apq;
+pub(crate) mod catch_panic;
 pub(crate) mod ensure_query_presence;
+pub(crate) mod query_size_limit;
+pub(crate) mod response_size_limit;
+pub(crate) mod variables_limit;
@@ -0,0 +1,148 @@
+//! Ensure that the query of a [`SupergraphRequest`] is not too long.
+//!
+//! See [`Layer`] and [`Service`] for more details.
+//!
+//! If the query is longer than the configured limit, then the request is rejected
+//! before the query gets parsed.
+
+use std::ops::ControlFlow;
+
+use http::StatusCode;
+use serde_json_bytes::Value;
+use tower::BoxError;
+use tower::Layer;
+use tower::Service;
+
+use crate::layers::sync_checkpoint::CheckpointService;
+use crate::SupergraphRequest;
+use crate::SupergraphResponse;
+
+#[derive(Default)]
+pub(crate) struct QuerySizeLimit {
+    max_query_bytes: Option<usize>,
+}
+
+impl QuerySizeLimit {
+    pub(crate) fn new(max_query_bytes: Option<usize>) -> Self {
+        Self { max_query_bytes }
+    }
+}
+
+impl<S> Layer<S> for QuerySizeLimit
+where
+    S: Service<SupergraphRequest, Response = SupergraphResponse> + Send + 'static,
+    <S as Service<SupergraphRequest>>::Future: Send + 'static,
+    <S as Service<SupergraphRequest>>::Error: Into<BoxError> + Send + 'static,
+{
+    type Service = CheckpointService<S, SupergraphRequest>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        let max_query_bytes = self.max_query_bytes;
+        CheckpointService::new(
+            move |req: SupergraphRequest| {
+                let query_bytes = req
+                    .supergraph_request
+                    .body()
+                    .query
+                    .as_ref()
+                    .map(|query| query.len())
+                    .unwrap_or_default();
+                match max_query_bytes {
+                    Some(max_query_bytes) if query_bytes > max_query_bytes => {
+                        let errors = vec![crate::error::Error {
+                            message: format!(
+                                "Query is {} bytes long, over the limit of {} bytes.",
+                                query_bytes, max_query_bytes
+                            ),
+                            ..Default::default()
+                        }];
+
+                        //We do not copy headers from the request to the response as this may lead to leakable of sensitive data
+                        let res = SupergraphResponse::builder()
+                            .data(Value::default())
+                            .errors(errors)
+                            .status_code(StatusCode::PAYLOAD_TOO_LARGE)
+                            .context(req.context)
+                            .build()
+                            .expect("response is valid");
+                        Ok(ControlFlow::Break(res))
+                    }
+                    _ => Ok(ControlFlow::Continue(req)),
+                }
+            },
+            service,
+        )
+    }
+}
+
+#[cfg(test)]
+mod query_size_limit_tests {
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::plugin::test::MockSupergraphService;
+
+    const QUERY: &str = "{ topProducts { name } }";
+
+    #[tokio::test]
+    async fn it_works_without_limit() {
+        let mut mock_service = MockSupergraphService::new();
+        mock_service.expect_call().times(1).returning(move |_req| {
+            Ok(SupergraphResponse::fake_builder()
+                .build()
+                .expect("expecting valid request"))
+        });
+
+        let service_stack = QuerySizeLimit::default().layer(mock_service);
+
+        let request: crate::SupergraphRequest = SupergraphRequest::fake_builder()
+            .query(QUERY.to_string())
+            .build()
+            .expect("expecting valid request");
+
+        let _ = service_stack.oneshot(request).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_works_with_query_at_the_limit() {
+        let mut mock_service = MockSupergraphService::new();
+        mock_service.expect_call().times(1).returning(move |_req| {
+            Ok(SupergraphResponse::fake_builder()
+                .build()
+                .expect("expecting valid request"))
+        });
+
+        let service_stack = QuerySizeLimit::new(Some(QUERY.len())).layer(mock_service);
+
+        let request: crate::SupergraphRequest = SupergraphRequest::fake_builder()
+            .query(QUERY.to_string())
+            .build()
+            .expect("expecting valid request");
+
+        let _ = service_stack.oneshot(request).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_fails_on_query_over_the_limit() {
+        // The inner service, which plans the query, must not be called
+        let service_stack =
+            QuerySizeLimit::new(Some(QUERY.len() - 1)).layer(MockSupergraphService::new());
+
+        let request: crate::SupergraphRequest = SupergraphRequest::fake_builder()
+            .query(QUERY.to_string())
+            .build()
+            .expect("expecting valid request");
+
+        let mut response = service_stack.oneshot(request).await.unwrap();
+        assert_eq!(response.response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        let response = response.next_response().await.unwrap();
+        assert_eq!(
+            response.errors[0].message,
+            format!(
+                "Query is {} bytes long, over the limit of {} bytes.",
+                QUERY.len(),
+                QUERY.len() - 1
+            )
+        );
+    }
+}
@@ -0,0 +1,121 @@
+//! Limit the total size of the responses to a [`SupergraphRequest`].
+//!
+//! See [`Layer`] and [`Service`] for more details.
+//!
+//! The size of each response of the stream is added up, deferred responses included. The response
+//! that exceeds the limit is replaced with an error, and the stream ends after it, which stops the
+//! execution of the remaining deferred parts.
+
+use std::task::Poll;
+
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use futures::StreamExt;
+use futures::TryFutureExt;
+use tower::BoxError;
+use tower::Layer;
+use tower::Service;
+
+use crate::graphql;
+use crate::plugins::telemetry::metrics::serialized_size;
+use crate::SupergraphRequest;
+use crate::SupergraphResponse;
+
+#[derive(Clone, Default)]
+pub(crate) struct ResponseSizeLimit {
+    max_response_bytes: Option<usize>,
+}
+
+impl ResponseSizeLimit {
+    pub(crate) fn new(max_response_bytes: Option<usize>) -> Self {
+        Self { max_response_bytes }
+    }
+}
+
+impl<S> Layer<S> for ResponseSizeLimit {
+    type Service = ResponseSizeLimitService<S>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        ResponseSizeLimitService {
+            inner: service,
+            max_response_bytes: self.max_response_bytes,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct ResponseSizeLimitService<S> {
+    inner: S,
+    max_response_bytes: Option<usize>,
+}
+
+impl<S> Service<SupergraphRequest> for ResponseSizeLimitService<S>
+where
+    S: Service<SupergraphRequest, Response = SupergraphResponse, Error = BoxError>,
+    S::Future: Send + 'static,
+{
+    type Response = SupergraphResponse;
+    type Error = BoxError;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut std::task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: SupergraphRequest) -> Self::Future {
+        let future = self.inner.call(req);
+        match self.max_response_bytes {
+            Some(max_response_bytes) => future
+                .map_ok(move |response| {
+                    response.map(move |stream| limit_stream(stream, max_response_bytes))
+                })
+                .boxed(),
+            None => future.boxed(),
+        }
+    }
+}
+
+fn limit_stream(
+    stream: graphql::ResponseStream,
+    max_response_bytes: usize,
+) -> graphql::ResponseStream {
+    // the bytes sent so far, or None once the limit is exceeded
+    stream
+        .scan(Some(0usize), move |sent, response| {
+            let response = match *sent {
+                Some(sent_bytes) => {
+                    let sent_bytes = sent_bytes + serialized_size(&response);
+                    if sent_bytes > max_response_bytes {
+                        *sent = None;
+                        Some(limit_error(&response, max_response_bytes))
+                    } else {
+                        *sent = Some(sent_bytes);
+                        Some(response)
+                    }
+                }
+                None => None,
+            };
+            futures::future::ready(response)
+        })
+        .boxed()
+}
+
+/// The last response of the stream, replacing the one that exceeds the limit
+fn limit_error(response: &graphql::Response, max_response_bytes: usize) -> graphql::Response {
+    tracing::debug!(
+        "ending a response stream over the limit of {} bytes",
+        max_response_bytes
+    );
+    graphql::Response::builder()
+        .error(
+            graphql::Error::builder()
+                .message(format!(
+                    "the response exceeded the limit of {} bytes",
+                    max_response_bytes
+                ))
+                .extension("code", "RESPONSE_SIZE_LIMIT")
+                .build(),
+        )
+        .and_has_next(response.has_next.map(|_| false))
+        .build()
+}
@@ -0,0 +1,117 @@
+//! Ensure that a [`SupergraphRequest`] does not have too many variables.
+//!
+//! See [`Layer`] and [`Service`] for more details.
+//!
+//! If the request has more variables than the configured limit, then the request is rejected
+//! before the query gets planned and the variables get coerced.
+
+use std::ops::ControlFlow;
+
+use http::StatusCode;
+use serde_json_bytes::Value;
+use tower::BoxError;
+use tower::Layer;
+use tower::Service;
+
+use crate::layers::sync_checkpoint::CheckpointService;
+use crate::SupergraphRequest;
+use crate::SupergraphResponse;
+
+#[derive(Default)]
+pub(crate) struct VariablesLimit {
+    max_variables: Option<usize>,
+}
+
+impl VariablesLimit {
+    pub(crate) fn new(max_variables: Option<usize>) -> Self {
+        Self { max_variables }
+    }
+}
+
+impl<S> Layer<S> for VariablesLimit
+where
+    S: Service<SupergraphRequest, Response = SupergraphResponse> + Send + 'static,
+    <S as Service<SupergraphRequest>>::Future: Send + 'static,
+    <S as Service<SupergraphRequest>>::Error: Into<BoxError> + Send + 'static,
+{
+    type Service = CheckpointService<S, SupergraphRequest>;
+
+    fn layer(&self, service: S) -> Self::Service {
+        let max_variables = self.max_variables;
+        CheckpointService::new(
+            move |req: SupergraphRequest| {
+                let variables = req.supergraph_request.body().variables.len();
+                match max_variables {
+                    Some(max_variables) if variables > max_variables => {
+                        let errors = vec![crate::error::Error {
+                            message: format!(
+                                "Request has {} variables, over the limit of {} variables.",
+                                variables, max_variables
+                            ),
+                            ..Default::default()
+                        }];
+
+                        //We do not copy headers from the request to the response as this may lead to leakable of sensitive data
+                        let res = SupergraphResponse::builder()
+                            .data(Value::default())
+                            .errors(errors)
+                            .status_code(StatusCode::BAD_REQUEST)
+                            .context(req.context)
+                            .build()
+                            .expect("response is valid");
+                        Ok(ControlFlow::Break(res))
+                    }
+                    _ => Ok(ControlFlow::Continue(req)),
+                }
+            },
+            service,
+        )
+    }
+}
+
+#[cfg(test)]
+mod variables_limit_tests {
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::plugin::test::MockSupergraphService;
+
+    const QUERY: &str = "query($a: Int, $b: Int) { topProducts(first: $a) { name } }";
+
+    fn request() -> SupergraphRequest {
+        SupergraphRequest::fake_builder()
+            .query(QUERY.to_string())
+            .variable("a", 1)
+            .variable("b", 2)
+            .build()
+            .expect("expecting valid request")
+    }
+
+    #[tokio::test]
+    async fn it_works_with_variables_at_the_limit() {
+        let mut mock_service = MockSupergraphService::new();
+        mock_service.expect_call().times(1).returning(move |_req| {
+            Ok(SupergraphResponse::fake_builder()
+                .build()
+                .expect("expecting valid request"))
+        });
+
+        let service_stack = VariablesLimit::new(Some(2)).layer(mock_service);
+
+        let _ = service_stack.oneshot(request()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_fails_on_variables_over_the_limit() {
+        // The inner service, which plans the query, must not be called
+        let service_stack = VariablesLimit::new(Some(1)).layer(MockSupergraphService::new());
+
+        let mut response = service_stack.oneshot(request()).await.unwrap();
+        assert_eq!(response.response.status(), StatusCode::BAD_REQUEST);
+        let response = response.next_response().await.unwrap();
+        assert_eq!(
+            response.errors[0].message,
+            "Request has 2 variables, over the limit of 1 variables."
+        );
+    }
+}
@@ -0,0 +1,320 @@
+//! A [`Buffer`] recording how many requests wait in its queue and for how long.
+//!
+//! The requests are timestamped when they enter the buffer, and measured again when the
+//! buffer's worker hands them to the inner service. A request dropped while it is still
+//! queued leaves the queue as well.
+
+use std::sync::Arc;
+use std::sync::RwLock;
+use std::task::Poll;
+use std::time::Instant;
+
+use opentelemetry::KeyValue;
+use tower::buffer::future::ResponseFuture;
+use tower::buffer::Buffer;
+use tower::BoxError;
+use tower::Service;
+
+use crate::layers::DEFAULT_BUFFER_SIZE;
+use crate::plugins::telemetry::metrics::AggregateMeterProvider;
+use crate::plugins::telemetry::metrics::AggregateUpDownCounter;
+use crate::plugins::telemetry::metrics::AggregateValueRecorder;
+use crate::services::transport;
+use crate::SupergraphRequest;
+
+/// Records the occupancy of a buffer and the time spent by the requests in its queue, labeled
+/// by buffer name
+#[derive(Clone)]
+pub(crate) struct BufferMetrics {
+    attributes: Arc<[KeyValue; 1]>,
+    queued_requests: AggregateUpDownCounter<i64>,
+    wait_time: AggregateValueRecorder<f64>,
+}
+
+impl BufferMetrics {
+    pub(crate) fn new(buffer: impl Into<String>, meter_provider: &AggregateMeterProvider) -> Self {
+        let meter = meter_provider.meter("apollo/router", None);
+        BufferMetrics {
+            attributes: Arc::new([KeyValue::new("buffer", buffer.into())]),
+            queued_requests: meter.build_up_down_counter(|m| {
+                m.i64_up_down_counter("apollo_router_buffer_queued_requests")
+                    .with_description("Number of requests waiting in the buffer.")
+                    .init()
+            }),
+            wait_time: meter.build_value_recorder(|m| {
+                m.f64_value_recorder("apollo_router_buffer_wait_seconds")
+                    .with_description("Time spent by a request in the buffer, in seconds.")
+                    .init()
+            }),
+        }
+    }
+
+    fn enqueue(&self) {
+        self.queued_requests.add(1, &*self.attributes);
+    }
+
+    fn dequeue(&self) {
+        self.queued_requests.add(-1, &*self.attributes);
+    }
+
+    fn record_wait(&self, enqueued_at: Instant) {
+        self.wait_time
+            .record(enqueued_at.elapsed().as_secs_f64(), &*self.attributes);
+    }
+}
+
+impl Default for BufferMetrics {
+    /// Metrics that are not sent anywhere
+    fn default() -> Self {
+        Self::new("default", &AggregateMeterProvider::default())
+    }
+}
+
+/// Marks a request waiting in the buffer, it leaves the queue when dropped
+struct Enqueued {
+    at: Instant,
+    metrics: BufferMetrics,
+}
+
+impl Drop for Enqueued {
+    fn drop(&mut self) {
+        self.metrics.dequeue();
+    }
+}
+
+/// Requests that carry their [`Enqueued`] marker through the buffer
+pub(crate) trait BufferedRequest: Send + 'static {
+    fn extensions_mut(&mut self) -> &mut http::Extensions;
+}
+
+impl BufferedRequest for transport::Request {
+    fn extensions_mut(&mut self) -> &mut http::Extensions {
+        http::Request::extensions_mut(self)
+    }
+}
+
+impl BufferedRequest for SupergraphRequest {
+    fn extensions_mut(&mut self) -> &mut http::Extensions {
+        self.supergraph_request.extensions_mut()
+    }
+}
+
+/// A [`Buffer`] with [`BufferMetrics`]
+pub(crate) struct MeasuredBuffer<S, Req>
+where
+    S: Service<Req>,
+    Req: BufferedRequest,
+{
+    buffer: Buffer<Dequeue<S>, Req>,
+    // shared by the clones of the buffer, so they all use the metrics set later
+    metrics: Arc<RwLock<BufferMetrics>>,
+}
+
+impl<S, Req> Clone for MeasuredBuffer<S, Req>
+where
+    S: Service<Req>,
+    Req: BufferedRequest,
+{
+    fn clone(&self) -> Self {
+        Self {
+            buffer: self.buffer.clone(),
+            metrics: self.metrics.clone(),
+        }
+    }
+}
+
+impl<S, Req> MeasuredBuffer<S, Req>
+where
+    S: Service<Req> + Send + 'static,
+    S::Future: Send,
+    S::Error: Into<BoxError> + Send + Sync,
+    Req: BufferedRequest,
+{
+    pub(crate) fn new(service: S, metrics: BufferMetrics) -> Self {
+        Self {
+            buffer: Buffer::new(Dequeue { service }, DEFAULT_BUFFER_SIZE),
+            metrics: Arc::new(RwLock::new(metrics)),
+        }
+    }
+
+    /// Records the next requests with other metrics
+    pub(crate) fn set_metrics(&self, metrics: BufferMetrics) {
+        *self.metrics.write().expect("lock poisoned") = metrics;
+    }
+}
+
+impl<S, Req> Service<Req> for MeasuredBuffer<S, Req>
+where
+    S: Service<Req>,
+    S::Error: Into<BoxError>,
+    Req: BufferedRequest,
+{
+    type Response = S::Response;
+    type Error = BoxError;
+    type Future = ResponseFuture<S::Future>;
+
+    fn poll_ready(&mut self, cx: &mut std::task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.buffer.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Req) -> Self::Future {
+        let metrics = self.metrics.read().expect("lock poisoned").clone();
+        metrics.enqueue();
+        req.extensions_mut().insert(Enqueued {
+            at: Instant::now(),
+            metrics,
+        });
+        self.buffer.call(req)
+    }
+}
+
+/// The service called by the buffer's worker, when a request leaves the queue
+struct Dequeue<S> {
+    service: S,
+}
+
+impl<S, Req> Service<Req> for Dequeue<S>
+where
+    S: Service<Req>,
+    Req: BufferedRequest,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = S::Future;
+
+    fn poll_ready(&mut self, cx: &mut std::task::Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.service.poll_ready(cx)
+    }
+
+    fn call(&mut self, mut req: Req) -> Self::Future {
+        if let Some(enqueued) = req.extensions_mut().remove::<Enqueued>() {
+            enqueued.metrics.record_wait(enqueued.at);
+        }
+        self.service.call(req)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use tokio::sync::Semaphore;
+    use tower::Layer;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::services::layers::apq::APQLayer;
+
+    fn gauge(exporter: &opentelemetry_prometheus::PrometheusExporter, name: &str) -> f64 {
+        exporter
+            .registry()
+            .gather()
+            .iter()
+            .filter(|family| family.get_name().starts_with(name))
+            .flat_map(|family| family.get_metric())
+            .map(|metric| metric.get_gauge().get_value())
+            .sum()
+    }
+
+    fn gathered(exporter: &opentelemetry_prometheus::PrometheusExporter, name: &str) -> (u64, f64) {
+        exporter
+            .registry()
+            .gather()
+            .iter()
+            .filter(|family| family.get_name().starts_with(name))
+            .flat_map(|family| family.get_metric())
+            .map(|metric| {
+                let histogram = metric.get_histogram();
+                (histogram.get_sample_count(), histogram.get_sample_sum())
+            })
+            .fold((0, 0.0), |(count, sum), (c, s)| (count + c, sum + s))
+    }
+
+    #[tokio::test]
+    async fn it_records_the_wait_time_of_a_saturated_buffer() {
+        let exporter = opentelemetry_prometheus::exporter().init();
+        let meter_provider = AggregateMeterProvider::new(vec![Arc::new(
+            exporter.provider().expect("meter provider"),
+        )]);
+
+        // the inner service is only ready when a permit is released, so the requests queue up
+        let permits = Arc::new(Semaphore::new(0));
+        let service = {
+            let permits = permits.clone();
+            tower::service_fn(move |_req: transport::Request| {
+                let permits = permits.clone();
+                async move {
+                    permits.acquire().await?.forget();
+                    Ok::<_, BoxError>(http::Response::new(hyper::Body::empty()))
+                }
+            })
+        };
+        let buffer = MeasuredBuffer::new(
+            tower::util::BoxService::new(tower::limit::ConcurrencyLimit::new(service, 1)),
+            BufferMetrics::new("test", &meter_provider),
+        );
+
+        let requests: Vec<_> = (0..3)
+            .map(|_| {
+                let buffer = buffer.clone();
+                tokio::spawn(buffer.oneshot(http::Request::new(hyper::Body::empty())))
+            })
+            .collect();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        // the first request was handed to the inner service, the others wait in the queue
+        assert_eq!(
+            gauge(&exporter, "apollo_router_buffer_queued_requests"),
+            2.0
+        );
+        permits.add_permits(3);
+        for request in requests {
+            request.await.unwrap().unwrap();
+        }
+
+        let (waits, wait_time) = gathered(&exporter, "apollo_router_buffer_wait_seconds");
+        assert_eq!(waits, 3);
+        assert!(wait_time > 0.0);
+        // each request entered and left the queue
+        assert_eq!(
+            gauge(&exporter, "apollo_router_buffer_queued_requests"),
+            0.0
+        );
+    }
+
+    #[tokio::test]
+    async fn it_records_the_wait_time_of_the_apq_buffer() {
+        let exporter = opentelemetry_prometheus::exporter().init();
+        let meter_provider = AggregateMeterProvider::new(vec![Arc::new(
+            exporter.provider().expect("meter provider"),
+        )]);
+
+        let service = tower::service_fn(|request: SupergraphRequest| async move {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            crate::SupergraphResponse::fake_builder()
+                .context(request.context)
+                .build()
+        });
+        let mut service = APQLayer::new()
+            .await
+            .with_buffer_metrics(BufferMetrics::new("apq", &meter_provider))
+            .layer(tower::limit::ConcurrencyLimit::new(service, 1));
+
+        // the requests wait in the buffer while the previous one is handled
+        let mut requests = Vec::new();
+        for _ in 0..3 {
+            let request = SupergraphRequest::fake_builder().build().unwrap();
+            requests.push(tokio::spawn(service.ready().await.unwrap().call(request)));
+        }
+        for request in requests {
+            request.await.unwrap().unwrap();
+        }
+
+        let (waits, wait_time) = gathered(&exporter, "apollo_router_buffer_wait_seconds");
+        assert_eq!(waits, 3);
+        assert!(wait_time > 0.0);
+        assert_eq!(
+            gauge(&exporter, "apollo_router_buffer_queued_requests"),
+            0.0
+        );
+    }
+}
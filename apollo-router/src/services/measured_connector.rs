@@ -0,0 +1,280 @@
+//! A connector recording the connections opened to a subgraph and how they are used.
+//!
+//! The connections are counted when the connector establishes them, and until the client's
+//! pool drops them. The requests in flight are counted by the subgraph service, so the
+//! connections serving a request can be told apart from the idle ones kept in the pool.
+//! Without metrics, the connector only wraps the connections.
+
+use std::io::IoSlice;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::RwLock;
+use std::task::Context;
+use std::task::Poll;
+use std::time::Instant;
+
+use bytes::Bytes;
+use futures::future::BoxFuture;
+use futures::Stream;
+use http::header::CONTENT_LENGTH;
+use http::HeaderValue;
+use http::Uri;
+use hyper::body::HttpBody;
+use hyper::client::connect::Connected;
+use hyper::client::connect::Connection;
+use hyper::Body;
+use opentelemetry::KeyValue;
+use pin_project_lite::pin_project;
+use tokio::io::AsyncRead;
+use tokio::io::AsyncWrite;
+use tokio::io::ReadBuf;
+use tower::Service;
+
+use crate::plugins::telemetry::metrics::AggregateMeterProvider;
+use crate::plugins::telemetry::metrics::AggregateUpDownCounter;
+use crate::plugins::telemetry::metrics::AggregateValueRecorder;
+
+/// Records the saturation of the connection pool of a subgraph, labeled by subgraph name
+#[derive(Clone)]
+pub(crate) struct ConnectionPoolMetrics {
+    attributes: Arc<[KeyValue; 1]>,
+    usage: Arc<Mutex<Usage>>,
+    active_connections: AggregateUpDownCounter<i64>,
+    idle_connections: AggregateUpDownCounter<i64>,
+    connection_wait: AggregateValueRecorder<f64>,
+}
+
+/// The open connections and requests in flight, with the connection counts last added to the
+/// gauges
+#[derive(Default)]
+struct Usage {
+    open: u64,
+    in_flight: u64,
+    active: i64,
+    idle: i64,
+}
+
+impl ConnectionPoolMetrics {
+    pub(crate) fn new(
+        subgraph: impl Into<String>,
+        meter_provider: &AggregateMeterProvider,
+    ) -> Self {
+        let meter = meter_provider.meter("apollo/router", None);
+        ConnectionPoolMetrics {
+            attributes: Arc::new([KeyValue::new("subgraph", subgraph.into())]),
+            usage: Default::default(),
+            active_connections: meter.build_up_down_counter(|m| {
+                m.i64_up_down_counter("apollo_router_subgraph_connections_active")
+                    .with_description("Number of connections to the subgraph serving a request.")
+                    .init()
+            }),
+            idle_connections: meter.build_up_down_counter(|m| {
+                m.i64_up_down_counter("apollo_router_subgraph_connections_idle")
+                    .with_description(
+                        "Number of connections to the subgraph waiting in the pool for a request.",
+                    )
+                    .init()
+            }),
+            connection_wait: meter.build_value_recorder(|m| {
+                m.f64_value_recorder("apollo_router_subgraph_connection_wait_seconds")
+                    .with_description(
+                        "Time spent by a request waiting for a connection to the subgraph, from the pool or new, in seconds.",
+                    )
+                    .init()
+            }),
+        }
+    }
+
+    /// Marks a request sent to the subgraph, until the returned guard is dropped
+    pub(crate) fn request(&self) -> InFlight {
+        self.update_usage(|usage| usage.in_flight += 1);
+        InFlight {
+            metrics: self.clone(),
+        }
+    }
+
+    /// Records how long the request waits for a connection: the client only starts sending its
+    /// body once it got one, from the pool or newly established
+    pub(crate) fn wait_for_connection(&self, request: http::Request<Body>) -> http::Request<Body> {
+        let (mut parts, body) = request.into_parts();
+        // a request without a body, like a GET request, is not measured
+        let length = match body.size_hint().exact() {
+            Some(length) if length > 0 => length,
+            _ => return http::Request::from_parts(parts, body),
+        };
+        // the client does not know the length of a streamed body, it must not be sent in chunks
+        parts
+            .headers
+            .entry(CONTENT_LENGTH)
+            .or_insert_with(|| HeaderValue::from(length));
+        let body = WaitingBody {
+            body,
+            waiting_since: Some(Instant::now()),
+            metrics: self.clone(),
+        };
+        http::Request::from_parts(parts, Body::wrap_stream(body))
+    }
+
+    fn open(&self) -> Open {
+        self.update_usage(|usage| usage.open += 1);
+        Open {
+            metrics: self.clone(),
+        }
+    }
+
+    // A request waiting for a connection is not counted until the connection is open, and
+    // multiplexed HTTP/2 requests share a single active connection
+    fn update_usage(&self, update: impl FnOnce(&mut Usage)) {
+        let mut usage = self.usage.lock().expect("lock poisoned");
+        update(&mut usage);
+        let active = usage.open.min(usage.in_flight) as i64;
+        let idle = usage.open as i64 - active;
+        // the gauges move by the difference with the counts they already have
+        self.active_connections
+            .add(active - usage.active, &*self.attributes);
+        self.idle_connections
+            .add(idle - usage.idle, &*self.attributes);
+        usage.active = active;
+        usage.idle = idle;
+    }
+}
+
+/// Marks a request in flight, it is done when dropped
+pub(crate) struct InFlight {
+    metrics: ConnectionPoolMetrics,
+}
+
+impl Drop for InFlight {
+    fn drop(&mut self) {
+        self.metrics.update_usage(|usage| usage.in_flight -= 1);
+    }
+}
+
+/// The body of a request waiting for a connection, until the client polls it
+struct WaitingBody {
+    body: Body,
+    waiting_since: Option<Instant>,
+    metrics: ConnectionPoolMetrics,
+}
+
+impl Stream for WaitingBody {
+    type Item = Result<Bytes, hyper::Error>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = self.get_mut();
+        if let Some(waiting_since) = this.waiting_since.take() {
+            this.metrics.connection_wait.record(
+                waiting_since.elapsed().as_secs_f64(),
+                &*this.metrics.attributes,
+            );
+        }
+        Pin::new(&mut this.body).poll_data(cx)
+    }
+}
+
+/// Marks an open connection, it is closed when dropped
+struct Open {
+    metrics: ConnectionPoolMetrics,
+}
+
+impl Drop for Open {
+    fn drop(&mut self) {
+        self.metrics.update_usage(|usage| usage.open -= 1);
+    }
+}
+
+/// A connector of a hyper client with [`ConnectionPoolMetrics`]
+#[derive(Clone)]
+pub(crate) struct MeasuredConnector<C> {
+    connector: C,
+    // shared with the subgraph service, so the connections use the metrics set later
+    metrics: Arc<RwLock<Option<ConnectionPoolMetrics>>>,
+}
+
+impl<C> MeasuredConnector<C> {
+    pub(crate) fn new(connector: C, metrics: Arc<RwLock<Option<ConnectionPoolMetrics>>>) -> Self {
+        Self { connector, metrics }
+    }
+}
+
+impl<C> Service<Uri> for MeasuredConnector<C>
+where
+    C: Service<Uri>,
+    C::Future: Send + 'static,
+{
+    type Response = MeasuredConnection<C::Response>;
+    type Error = C::Error;
+    type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.connector.poll_ready(cx)
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let metrics = self.metrics.read().expect("lock poisoned").clone();
+        let connecting = self.connector.call(uri);
+        Box::pin(async move {
+            let connection = connecting.await?;
+            Ok(MeasuredConnection {
+                connection,
+                _open: metrics.map(|metrics| metrics.open()),
+            })
+        })
+    }
+}
+
+pin_project! {
+    /// A connection counted by the [`ConnectionPoolMetrics`], if any, until it is dropped
+    pub(crate) struct MeasuredConnection<T> {
+        #[pin]
+        connection: T,
+        _open: Option<Open>,
+    }
+}
+
+impl<T: Connection> Connection for MeasuredConnection<T> {
+    fn connected(&self) -> Connected {
+        self.connection.connected()
+    }
+}
+
+impl<T: AsyncRead> AsyncRead for MeasuredConnection<T> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<std::io::Result<()>> {
+        self.project().connection.poll_read(cx, buf)
+    }
+}
+
+impl<T: AsyncWrite> AsyncWrite for MeasuredConnection<T> {
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<std::io::Result<usize>> {
+        self.project().connection.poll_write(cx, buf)
+    }
+
+    fn poll_write_vectored(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        bufs: &[IoSlice<'_>],
+    ) -> Poll<std::io::Result<usize>> {
+        self.project().connection.poll_write_vectored(cx, bufs)
+    }
+
+    fn is_write_vectored(&self) -> bool {
+        self.connection.is_write_vectored()
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        self.project().connection.poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<std::io::Result<()>> {
+        self.project().connection.poll_shutdown(cx)
+    }
+}
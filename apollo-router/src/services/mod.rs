@@ -19,9 +19,12 @@ pub(crate) use crate::services::subgraph::Response as SubgraphResponse;
 pub(crate) use crate::services::supergraph::Request as SupergraphRequest;
 pub(crate) use crate::services::supergraph::Response as SupergraphResponse;
 
+pub(crate) mod batching;
 pub mod execution;
 mod execution_service;
 pub(crate) mod layers;
+pub(crate) mod measured_buffer;
+pub(crate) mod measured_connector;
 pub(crate) mod new_service;
 pub(crate) mod query_planner;
 pub mod subgraph;
@@ -2,6 +2,7 @@
 
 use std::sync::Arc;
 
+use router_bridge::planner::UsageReporting;
 use serde::Deserialize;
 use serde::Serialize;
 use static_assertions::assert_impl_all;
@@ -46,9 +47,29 @@ pub(crate) struct Response {
 /// Query, QueryPlan and Introspection data.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub(crate) enum QueryPlannerContent {
-    Plan { plan: Arc<QueryPlan> },
-    Introspection { response: Box<graphql::Response> },
+    Plan {
+        plan: Arc<QueryPlan>,
+    },
+    Introspection {
+        response: Box<graphql::Response>,
+    },
     IntrospectionDisabled,
+    /// All the root fields are skipped, so there is nothing to fetch
+    EmptyPlan {
+        usage_reporting: UsageReporting,
+    },
+}
+
+impl QueryPlannerContent {
+    /// The usage reporting of the operation, if it was planned
+    pub(crate) fn usage_reporting(&self) -> Option<&UsageReporting> {
+        match self {
+            QueryPlannerContent::Plan { plan } => Some(&plan.usage_reporting),
+            QueryPlannerContent::EmptyPlan { usage_reporting } => Some(usage_reporting),
+            QueryPlannerContent::Introspection { .. }
+            | QueryPlannerContent::IntrospectionDisabled => None,
+        }
+    }
 }
 
 #[buildstructor::buildstructor]
@@ -14,12 +14,29 @@ use crate::graphql;
 use crate::json_ext::Object;
 use crate::json_ext::Path;
 use crate::query_planner::fetch::OperationKind;
+use crate::services::measured_buffer::BufferMetrics;
+use crate::services::transport;
+use crate::services::SubgraphService;
 use crate::Context;
 
 pub type BoxService = tower::util::BoxService<Request, Response, BoxError>;
 pub type BoxCloneService = tower::util::BoxCloneService<Request, Response, BoxError>;
 pub type ServiceResult = Result<Response, BoxError>;
 
+/// Creates a service sending the requests of the named subgraph with the given HTTP client.
+///
+/// The service serializes and compresses the GraphQL requests, and parses the responses, like the
+/// subgraph services created by the router. The client can be a mock recording the requests in
+/// tests, or a tuned client shared by several subgraphs so they use a single connection pool.
+pub fn service_with_client(
+    subgraph: impl Into<String>,
+    client: transport::BoxService,
+) -> BoxCloneService {
+    BoxCloneService::new(
+        SubgraphService::new(subgraph).with_http_client(client, BufferMetrics::default()),
+    )
+}
+
 assert_impl_all!(Request: Send);
 #[non_exhaustive]
 pub struct Request {
@@ -2,28 +2,46 @@
 
 use std::collections::HashMap;
 use std::fmt::Display;
+use std::num::NonZeroUsize;
+use std::str::FromStr;
 use std::sync::Arc;
+use std::sync::RwLock;
 use std::task::Poll;
+use std::time::Duration;
+use std::time::Instant;
 
 use ::serde::Deserialize;
 use async_compression::tokio::write::BrotliEncoder;
 use async_compression::tokio::write::GzipEncoder;
 use async_compression::tokio::write::ZlibEncoder;
+use bytes::Bytes;
 use futures::future::BoxFuture;
 use global::get_text_map_propagator;
 use http::header::ACCEPT;
+use http::header::AUTHORIZATION;
 use http::header::CONTENT_ENCODING;
+use http::header::CONTENT_LENGTH;
 use http::header::CONTENT_TYPE;
+use http::header::COOKIE;
+use http::header::LOCATION;
+use http::header::PROXY_AUTHORIZATION;
+use http::header::RETRY_AFTER;
+use http::header::USER_AGENT;
 use http::header::{self};
 use http::HeaderMap;
 use http::HeaderValue;
+use http::Method;
+use http::StatusCode;
+use http::Uri;
 use hyper::client::HttpConnector;
 use hyper_rustls::HttpsConnector;
 use opentelemetry::global;
 use opentelemetry::trace::SpanKind;
 use schemars::JsonSchema;
 use tokio::io::AsyncWriteExt;
+use tokio::sync::Semaphore;
 use tower::util::BoxService;
+use tower::util::Either;
 use tower::BoxError;
 use tower::Service;
 use tower::ServiceBuilder;
@@ -33,12 +51,39 @@ use tower_http::decompression::DecompressionLayer;
 use tracing::Instrument;
 use tracing::Span;
 use tracing_opentelemetry::OpenTelemetrySpanExt;
+use url::Url;
 
 use super::Plugins;
 use crate::axum_factory::utils::APPLICATION_JSON_HEADER_VALUE;
 use crate::axum_factory::utils::GRAPHQL_JSON_RESPONSE_HEADER_VALUE;
+use crate::configuration::TrailingSlash;
 use crate::error::FetchError;
 use crate::graphql;
+use crate::plugins::subgraph_signing::SubgraphSigner;
+use crate::services::batching::BatchQuery;
+use crate::services::batching::Submission;
+use crate::services::measured_buffer::BufferMetrics;
+use crate::services::measured_buffer::MeasuredBuffer;
+use crate::services::measured_connector::ConnectionPoolMetrics;
+use crate::services::measured_connector::MeasuredConnector;
+use crate::services::transport;
+use crate::Context;
+use crate::SubgraphClientHook;
+use crate::SubgraphResponseAdapter;
+
+/// User-Agent sent to subgraphs unless another one is configured
+const DEFAULT_USER_AGENT: &str = concat!("apollo-router/", env!("CARGO_PKG_VERSION"));
+
+/// Maximum number of redirects followed for a single subgraph request
+const MAX_REDIRECTS: usize = 10;
+
+/// The connector of the hyper client, measuring the connections to the subgraph
+type Connector = MeasuredConnector<HttpsConnector<HttpConnector>>;
+/// The hyper client, wrapped by the subgraph client hook if there is one
+type HttpClient = Decompression<
+    Either<hyper::Client<Connector>, MeasuredBuffer<transport::BoxService, transport::Request>>,
+>;
+type HttpResponse = <HttpClient as Service<http::Request<hyper::Body>>>::Response;
 
 #[derive(PartialEq, Debug, Clone, Deserialize, JsonSchema, Copy)]
 #[serde(rename_all = "lowercase")]
@@ -61,33 +106,196 @@ impl Display for Compression {
     }
 }
 
+/// Retries of the requests rejected by a subgraph with a 429 status and a `Retry-After` header
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub(crate) struct RetryAfter {
+    /// Maximum number of retries of a request
+    pub(crate) max_retries: usize,
+    /// Maximum delay accepted from a single `Retry-After` header
+    pub(crate) max_delay: Duration,
+    /// Time after which the request is abandoned anyway, usually the subgraph timeout
+    pub(crate) budget: Duration,
+}
+
 /// Client for interacting with subgraphs.
 #[derive(Clone)]
 pub(crate) struct SubgraphService {
-    client: Decompression<hyper::Client<HttpsConnector<HttpConnector>>>,
+    client: HttpClient,
     service: Arc<String>,
+    follow_redirects: bool,
+    retry_after: Option<RetryAfter>,
+    // shared by the clones of the service, so it limits all the requests to the subgraph
+    concurrency_limit: Option<Arc<Semaphore>>,
+    response_adapter: Option<SubgraphResponseAdapter>,
+    batching: bool,
+    // the default Accept header is sent if there is none
+    accept: Option<HeaderValue>,
+    reject_empty_responses: bool,
+    // replaces the URL of the query plan if set
+    url_template: Option<UrlTemplate>,
+    // shared with the connector of the client, so the connections use the metrics set later
+    pool_metrics: Arc<RwLock<Option<ConnectionPoolMetrics>>>,
+    signer: Option<SubgraphSigner>,
+    // the header names of the HTTP/1.1 requests are sent in title case
+    title_case_headers: bool,
 }
 
 impl SubgraphService {
     pub(crate) fn new(service: impl Into<String>) -> Self {
-        let mut http_connector = HttpConnector::new();
-        http_connector.set_nodelay(true);
-        http_connector.set_keepalive(Some(std::time::Duration::from_secs(60)));
-        http_connector.enforce_http(false);
-        let connector = hyper_rustls::HttpsConnectorBuilder::new()
-            .with_native_roots()
-            .https_or_http()
-            .enable_http1()
-            .enable_http2()
-            .wrap_connector(http_connector);
-
+        let pool_metrics: Arc<RwLock<Option<ConnectionPoolMetrics>>> = Default::default();
         Self {
             client: ServiceBuilder::new()
                 .layer(DecompressionLayer::new())
-                .service(hyper::Client::builder().build(connector)),
+                .service(Either::A(hyper_client(pool_metrics.clone(), false))),
             service: Arc::new(service.into()),
+            follow_redirects: false,
+            retry_after: None,
+            concurrency_limit: None,
+            response_adapter: None,
+            batching: false,
+            accept: None,
+            reject_empty_responses: false,
+            url_template: None,
+            pool_metrics,
+            signer: None,
+            title_case_headers: false,
         }
     }
+
+    /// Send the header names of the HTTP/1.1 requests to the subgraph in title case, like
+    /// `X-User-Id`, instead of lowercase
+    ///
+    /// This replaces the HTTP client, so it must be called before `with_client_hook`, which
+    /// wraps a client built with this setting. HTTP/2 header names are always lowercase.
+    pub(crate) fn with_title_case_headers(mut self, title_case_headers: bool) -> Self {
+        self.title_case_headers = title_case_headers;
+        self.client = ServiceBuilder::new()
+            .layer(DecompressionLayer::new())
+            .service(Either::A(hyper_client(
+                self.pool_metrics.clone(),
+                title_case_headers,
+            )));
+        self
+    }
+
+    /// Follow the redirects returned by the subgraph instead of failing the request
+    pub(crate) fn with_follow_redirects(mut self, follow_redirects: bool) -> Self {
+        self.follow_redirects = follow_redirects;
+        self
+    }
+
+    /// Let the hook wrap the HTTP client of this subgraph
+    ///
+    /// The hook sees every HTTP request sent to the subgraph, including retries and redirects,
+    /// and the responses before their decompression.
+    pub(crate) fn with_client_hook(
+        mut self,
+        hook: &SubgraphClientHook,
+        buffer_metrics: BufferMetrics,
+    ) -> Self {
+        let client = hook.wrap(
+            &self.service,
+            hyper_client(self.pool_metrics.clone(), self.title_case_headers)
+                .map_err(BoxError::from)
+                .boxed(),
+        );
+        self.with_http_client(client, buffer_metrics)
+    }
+
+    /// Send the HTTP requests to the subgraph with this client instead of the router's own
+    pub(crate) fn with_http_client(
+        mut self,
+        client: transport::BoxService,
+        buffer_metrics: BufferMetrics,
+    ) -> Self {
+        self.client = ServiceBuilder::new()
+            .layer(DecompressionLayer::new())
+            .service(Either::B(MeasuredBuffer::new(client, buffer_metrics)));
+        self
+    }
+
+    /// Wait for the delay of the `Retry-After` header and retry the requests rejected with a 429 status
+    pub(crate) fn with_retry_after(mut self, retry_after: Option<RetryAfter>) -> Self {
+        self.retry_after = retry_after;
+        self
+    }
+
+    /// Convert the JSON responses of the subgraph with the adapter, instead of parsing them as
+    /// GraphQL responses
+    pub(crate) fn with_response_adapter(mut self, adapter: SubgraphResponseAdapter) -> Self {
+        self.response_adapter = Some(adapter);
+        self
+    }
+
+    /// Limit the number of concurrent requests to the subgraph, further requests wait for a slot
+    pub(crate) fn with_concurrency_limit(
+        mut self,
+        concurrency_limit: Option<NonZeroUsize>,
+    ) -> Self {
+        self.concurrency_limit =
+            concurrency_limit.map(|limit| Arc::new(Semaphore::new(limit.get())));
+        self
+    }
+
+    /// Send the queries of the fetches batched together by the query plan in a single HTTP
+    /// request, with an array body
+    pub(crate) fn with_batching(mut self, batching: bool) -> Self {
+        self.batching = batching;
+        self
+    }
+
+    /// Send this `Accept` header to the subgraph instead of `application/json` and
+    /// `application/graphql-response+json`
+    pub(crate) fn with_accept(mut self, accept: Option<HeaderValue>) -> Self {
+        self.accept = accept;
+        self
+    }
+
+    /// Fail the requests answered with neither data nor errors, instead of handling them as
+    /// responses without data
+    pub(crate) fn with_reject_empty_responses(mut self, reject_empty_responses: bool) -> Self {
+        self.reject_empty_responses = reject_empty_responses;
+        self
+    }
+
+    /// Send the requests to the URL of the template filled with context values, instead of the
+    /// URL of the subgraph in the supergraph schema
+    pub(crate) fn with_url_template(mut self, url_template: Option<UrlTemplate>) -> Self {
+        self.url_template = url_template;
+        self
+    }
+
+    /// Sign the requests sent to the subgraph, over their body as it is sent
+    pub(crate) fn with_signer(mut self, signer: Option<SubgraphSigner>) -> Self {
+        self.signer = signer;
+        self
+    }
+
+    /// Record the connections to the subgraph and the requests they serve
+    pub(crate) fn with_connection_pool_metrics(self, metrics: ConnectionPoolMetrics) -> Self {
+        *self.pool_metrics.write().expect("lock poisoned") = Some(metrics);
+        self
+    }
+}
+
+fn hyper_client(
+    pool_metrics: Arc<RwLock<Option<ConnectionPoolMetrics>>>,
+    title_case_headers: bool,
+) -> hyper::Client<Connector> {
+    let mut http_connector = HttpConnector::new();
+    http_connector.set_nodelay(true);
+    http_connector.set_keepalive(Some(std::time::Duration::from_secs(60)));
+    http_connector.enforce_http(false);
+    let connector = hyper_rustls::HttpsConnectorBuilder::new()
+        .with_native_roots()
+        .https_or_http()
+        .enable_http1()
+        .enable_http2()
+        .wrap_connector(http_connector);
+
+    hyper::Client::builder()
+        .http1_title_case_headers(title_case_headers)
+        .build(MeasuredConnector::new(connector, pool_metrics))
 }
 
 impl tower::Service<crate::SubgraphRequest> for SubgraphService {
@@ -96,9 +304,7 @@ impl tower::Service<crate::SubgraphRequest> for SubgraphService {
     type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
 
     fn poll_ready(&mut self, cx: &mut std::task::Context<'_>) -> Poll<Result<(), Self::Error>> {
-        self.client
-            .poll_ready(cx)
-            .map(|res| res.map_err(|e| Box::new(e) as BoxError))
+        self.client.poll_ready(cx)
     }
 
     fn call(&mut self, request: crate::SubgraphRequest) -> Self::Future {
@@ -112,109 +318,328 @@ impl tower::Service<crate::SubgraphRequest> for SubgraphService {
 
         let mut client = std::mem::replace(&mut self.client, clone);
         let service_name = (*self.service).to_owned();
+        let follow_redirects = self.follow_redirects;
+        let retry_after = self.retry_after;
+        let concurrency_limit = self.concurrency_limit.clone();
+        let response_adapter = self.response_adapter.clone();
+        let batching = self.batching;
+        let accept = self.accept.clone();
+        let reject_empty_responses = self.reject_empty_responses;
+        let url_template = self.url_template.clone();
+        let pool_metrics = self.pool_metrics.read().expect("lock poisoned").clone();
+        let signer = self.signer.clone();
 
         Box::pin(async move {
-            let (parts, body) = subgraph_request.into_parts();
-
-            let body = serde_json::to_string(&body).expect("JSON serialization should not fail");
-
-            let compressed_body = compress(body, &parts.headers)
-                .instrument(tracing::debug_span!("body_compression"))
-                .await
-                .map_err(|err| {
-                    tracing::error!(compress_error = format!("{:?}", err).as_str());
-
-                    FetchError::CompressionError {
+            let (mut parts, body) = subgraph_request.into_parts();
+            if let Some(url_template) = &url_template {
+                parts.uri = url_template.render(&context).map_err(|reason| {
+                    FetchError::SubrequestUrlError {
                         service: service_name.clone(),
-                        reason: err.to_string(),
+                        reason,
                     }
                 })?;
+            }
 
-            let mut request = http::request::Request::from_parts(parts, compressed_body.into());
-            let app_json: HeaderValue = HeaderValue::from_static(APPLICATION_JSON_HEADER_VALUE);
-            let app_graphql_json: HeaderValue =
-                HeaderValue::from_static(GRAPHQL_JSON_RESPONSE_HEADER_VALUE);
-            request.headers_mut().insert(CONTENT_TYPE, app_json.clone());
-            request.headers_mut().insert(ACCEPT, app_json);
-            request.headers_mut().append(ACCEPT, app_graphql_json);
-
-            get_text_map_propagator(|propagator| {
-                propagator.inject_context(
-                    &Span::current().context(),
-                    &mut opentelemetry_http::HeaderInjector(request.headers_mut()),
+            // the other fetches of the batch wait for this one if it leads the batch
+            let (body, followers) = match parts.extensions.remove::<Arc<BatchQuery>>() {
+                Some(member) if batching => match member.submit(body, &parts.headers) {
+                    Submission::Leader(leader, body) => (body, leader.followers().await),
+                    Submission::Follower(response) => {
+                        let response =
+                            response
+                                .await
+                                .map_err(|_| FetchError::SubrequestHttpError {
+                                    service: service_name.clone(),
+                                    reason: "the batch of this request was cancelled".to_string(),
+                                })??;
+                        return Ok(crate::SubgraphResponse::new_from_response(
+                            response, context,
+                        ));
+                    }
+                    Submission::Alone(body) => (body, Vec::new()),
+                },
+                _ => (body, Vec::new()),
+            };
+
+            let body = if followers.is_empty() {
+                serde_json::to_string(&body)
+            } else {
+                serde_json::to_string(
+                    &std::iter::once(&body)
+                        .chain(followers.iter().map(|follower| &follower.body))
+                        .collect::<Vec<_>>(),
                 )
-            });
+            }
+            .expect("JSON serialization should not fail");
+
+            let fetched = async {
+                let compressed_body = compress(body, &parts.headers)
+                    .instrument(tracing::debug_span!("body_compression"))
+                    .await
+                    .map_err(|err| {
+                        tracing::error!(compress_error = format!("{:?}", err).as_str());
+
+                        FetchError::CompressionError {
+                            service: service_name.clone(),
+                            reason: err.to_string(),
+                        }
+                    })?;
 
-            let schema_uri = request.uri();
-            let host = schema_uri.host().map(String::from).unwrap_or_default();
-            let port = schema_uri.port_u16().unwrap_or_else(|| {
-                let scheme = schema_uri.scheme_str();
-                if scheme == Some("https") {
-                    443
-                } else if scheme == Some("http") {
-                    80
-                } else {
-                    0
+                let compressed_body = Bytes::from(compressed_body);
+                let mut request =
+                    http::request::Request::from_parts(parts, compressed_body.clone().into());
+                let app_json: HeaderValue = HeaderValue::from_static(APPLICATION_JSON_HEADER_VALUE);
+                let app_graphql_json: HeaderValue =
+                    HeaderValue::from_static(GRAPHQL_JSON_RESPONSE_HEADER_VALUE);
+                request.headers_mut().insert(CONTENT_TYPE, app_json.clone());
+                match &accept {
+                    Some(accept) => {
+                        request.headers_mut().insert(ACCEPT, accept.clone());
+                    }
+                    None => {
+                        request.headers_mut().insert(ACCEPT, app_json);
+                        request.headers_mut().append(ACCEPT, app_graphql_json);
+                    }
                 }
-            });
-            let path = schema_uri.path().to_string();
-            let response = client
-                .call(request)
-                .instrument(tracing::info_span!("subgraph_request",
+                if !request.headers().contains_key(USER_AGENT) {
+                    request
+                        .headers_mut()
+                        .insert(USER_AGENT, HeaderValue::from_static(DEFAULT_USER_AGENT));
+                }
+                if let Some(signer) = &signer {
+                    // the body is signed as it is sent, after the batching and the compression
+                    signer.sign(request.headers_mut(), &compressed_body);
+                }
+
+                get_text_map_propagator(|propagator| {
+                    propagator.inject_context(
+                        &Span::current().context(),
+                        &mut opentelemetry_http::HeaderInjector(request.headers_mut()),
+                    )
+                });
+
+                let schema_uri = request.uri();
+                let host = schema_uri.host().map(String::from).unwrap_or_default();
+                let port = schema_uri.port_u16().unwrap_or_else(|| {
+                    let scheme = schema_uri.scheme_str();
+                    if scheme == Some("https") {
+                        443
+                    } else if scheme == Some("http") {
+                        80
+                    } else {
+                        0
+                    }
+                });
+                let path = schema_uri.path().to_string();
+                let subgraph_request_span = tracing::info_span!("subgraph_request",
                     "otel.kind" = %SpanKind::Client,
                     "net.peer.name" = &display(host),
                     "net.peer.port" = &display(port),
                     "http.route" = &display(path),
                     "net.transport" = "ip_tcp",
                     "apollo.subgraph.name" = %service_name
-                ))
-                .await
-                .map_err(|err| {
-                    tracing::error!(fetch_error = format!("{:?}", err).as_str());
+                );
 
-                    FetchError::SubrequestHttpError {
-                        service: service_name.clone(),
-                        reason: err.to_string(),
-                    }
-                })?;
+                // Kept to send the request again if the subgraph redirects it or asks to retry it
+                let mut uri = request.uri().clone();
+                let mut method = request.method().clone();
+                let version = request.version();
+                let mut headers = request.headers().clone();
+                let mut body = compressed_body.clone();
+
+                // the slot is kept until the response is received, including redirects and retries
+                let _permit = match concurrency_limit {
+                    Some(semaphore) => Some(
+                        semaphore
+                            .acquire_owned()
+                            .await
+                            .expect("the semaphore is never closed; qed"),
+                    ),
+                    None => None,
+                };
+
+                // the request keeps a connection busy until its response body is read
+                let in_flight = pool_metrics.as_ref().map(ConnectionPoolMetrics::request);
+                let start = Instant::now();
+                let mut response = send(&mut client, request, pool_metrics.as_ref(), &service_name)
+                    .instrument(subgraph_request_span.clone())
+                    .await?;
+
+                let mut redirects = 0;
+                let mut retries = 0;
+                loop {
+                    if let Some(location) = redirect_location(&response, &uri) {
+                        if !follow_redirects {
+                            return Err(FetchError::SubrequestHttpError {
+                                service: service_name.clone(),
+                                reason: format!(
+                                    "subgraph responded with a redirect ({}) to {}, and redirects are not followed for this subgraph",
+                                    response.status(),
+                                    location
+                                ),
+                            });
+                        }
+                        if redirects == MAX_REDIRECTS {
+                            return Err(FetchError::SubrequestHttpError {
+                                service: service_name.clone(),
+                                reason: format!(
+                                    "subgraph responded with more than {} redirects, the last one to {}",
+                                    MAX_REDIRECTS, location
+                                ),
+                            });
+                        }
+                        redirects += 1;
+
+                        // A 303 redirect asks to get the result elsewhere, without the request body
+                        if response.status() == StatusCode::SEE_OTHER && method != Method::HEAD {
+                            method = Method::GET;
+                            body = Bytes::new();
+                            headers.remove(CONTENT_TYPE);
+                            headers.remove(CONTENT_ENCODING);
+                            headers.remove(CONTENT_LENGTH);
+                        }
 
-            // Keep our parts, we'll need them later
-            let (parts, body) = response.into_parts();
-            if let Some(content_type) = parts.headers.get(header::CONTENT_TYPE) {
-                if let Ok(content_type_str) = content_type.to_str() {
-                    // Using .contains because sometimes we could have charset included (example: "application/json; charset=utf-8")
-                    if !content_type_str.contains(APPLICATION_JSON_HEADER_VALUE)
-                        && !content_type_str.contains(GRAPHQL_JSON_RESPONSE_HEADER_VALUE)
+                        // Credentials are only meant for the host they were configured for
+                        if location.host() != uri.host() {
+                            headers.remove(AUTHORIZATION);
+                            headers.remove(COOKIE);
+                            headers.remove(PROXY_AUTHORIZATION);
+                        }
+                        uri = location;
+                    } else if let (Some(retry_after), Some(delay)) =
+                        (retry_after, retry_after_delay(&response))
                     {
-                        return Err(BoxError::from(FetchError::SubrequestHttpError {
-                            service: service_name.clone(),
-                            reason: format!("subgraph didn't return JSON (expected content-type: application/json or content-type: application/graphql+json; found content-type: {content_type:?})"),
-                        }));
+                        if retries == retry_after.max_retries {
+                            // the 429 response is handled like any other response
+                            break;
+                        }
+                        // Waiting would only end with a timeout, fail fast instead
+                        if delay > retry_after.max_delay || start.elapsed() + delay > retry_after.budget
+                        {
+                            return Err(FetchError::SubrequestHttpError {
+                                service: service_name.clone(),
+                                reason: format!(
+                                    "subgraph is rate limiting requests and asked to retry after {}, which exceeds the retry budget",
+                                    humantime::format_duration(delay)
+                                ),
+                            });
+                        }
+                        retries += 1;
+                        tokio::time::sleep(delay).await;
+                    } else {
+                        break;
+                    }
+
+                    let mut request = http::Request::builder()
+                        .method(method.clone())
+                        .version(version)
+                        .uri(uri.clone())
+                        .body(body.clone().into())
+                        .expect("method, version and uri come from a valid request; qed");
+                    *request.headers_mut() = headers.clone();
+
+                    response = send(&mut client, request, pool_metrics.as_ref(), &service_name)
+                        .instrument(subgraph_request_span.clone())
+                        .await?;
+                }
+
+                // Keep our parts, we'll need them later
+                let (parts, body) = response.into_parts();
+                if let Some(content_type) = parts.headers.get(header::CONTENT_TYPE) {
+                    if let Ok(content_type_str) = content_type.to_str() {
+                        // Using .contains because sometimes we could have charset included (example: "application/json; charset=utf-8")
+                        if !content_type_str.contains(APPLICATION_JSON_HEADER_VALUE)
+                            && !content_type_str.contains(GRAPHQL_JSON_RESPONSE_HEADER_VALUE)
+                        {
+                            return Err(FetchError::SubrequestHttpError {
+                                service: service_name.clone(),
+                                reason: format!("subgraph didn't return JSON (expected content-type: application/json or content-type: application/graphql+json; found content-type: {content_type:?})"),
+                            });
+                        }
                     }
                 }
+
+                let body = hyper::body::to_bytes(body)
+                    .instrument(tracing::debug_span!("aggregate_response_data"))
+                    .await
+                    .map_err(|err| {
+                        tracing::error!(fetch_error = format!("{:?}", err).as_str());
+
+                        FetchError::SubrequestHttpError {
+                            service: service_name.clone(),
+                            reason: err.to_string(),
+                        }
+                    })?;
+                drop(in_flight);
+
+                Ok::<_, FetchError>((parts, body))
             }
+            .await;
 
-            let body = hyper::body::to_bytes(body)
-                .instrument(tracing::debug_span!("aggregate_response_data"))
-                .await
-                .map_err(|err| {
-                    tracing::error!(fetch_error = format!("{:?}", err).as_str());
+            let (parts, body) = match fetched {
+                Ok(fetched) => fetched,
+                Err(err) => {
+                    for follower in followers {
+                        follower.respond(Err(err.clone()));
+                    }
+                    return Err(err.into());
+                }
+            };
 
-                    FetchError::SubrequestHttpError {
+            let size = followers.len() + 1;
+            let responses = tracing::debug_span!("parse_subgraph_response").in_scope(|| {
+                let value = serde_json_bytes::Value::from_bytes(body).map_err(|error| {
+                    FetchError::SubrequestMalformedResponse {
                         service: service_name.clone(),
-                        reason: err.to_string(),
+                        reason: error.to_string(),
                     }
                 })?;
+                if followers.is_empty() {
+                    return Ok(vec![parse_response(
+                        &service_name,
+                        &response_adapter,
+                        reject_empty_responses,
+                        value,
+                    )?]);
+                }
+                match value {
+                    serde_json_bytes::Value::Array(values) if values.len() == size => values
+                        .into_iter()
+                        .map(|value| {
+                            parse_response(
+                                &service_name,
+                                &response_adapter,
+                                reject_empty_responses,
+                                value,
+                            )
+                        })
+                        .collect::<Result<Vec<_>, _>>(),
+                    _ => Err(FetchError::SubrequestMalformedResponse {
+                        service: service_name.clone(),
+                        reason: format!("expected an array of {} responses to the batch", size),
+                    }),
+                }
+            });
+            let mut responses = match responses {
+                Ok(responses) => responses.into_iter(),
+                Err(err) => {
+                    for follower in followers {
+                        follower.respond(Err(err.clone()));
+                    }
+                    return Err(err.into());
+                }
+            };
 
-            let graphql: graphql::Response = tracing::debug_span!("parse_subgraph_response")
-                .in_scope(|| {
-                    graphql::Response::from_bytes(&service_name, body).map_err(|error| {
-                        FetchError::SubrequestMalformedResponse {
-                            service: service_name.clone(),
-                            reason: error.to_string(),
-                        }
-                    })
-                })?;
+            let graphql = responses
+                .next()
+                .expect("there is a response per query; qed");
+            for (follower, graphql) in followers.into_iter().zip(responses) {
+                let mut response = http::Response::new(graphql);
+                *response.status_mut() = parts.status;
+                *response.version_mut() = parts.version;
+                *response.headers_mut() = parts.headers.clone();
+                follower.respond(Ok(response));
+            }
 
             let resp = http::Response::from_parts(parts, graphql);
 
@@ -223,6 +648,86 @@ impl tower::Service<crate::SubgraphRequest> for SubgraphService {
     }
 }
 
+/// Parses a response of the subgraph, with the response adapter if there is one
+fn parse_response(
+    service_name: &str,
+    response_adapter: &Option<SubgraphResponseAdapter>,
+    reject_empty_responses: bool,
+    value: serde_json_bytes::Value,
+) -> Result<graphql::Response, FetchError> {
+    let response = match response_adapter {
+        Some(adapter) => {
+            adapter
+                .adapt(value)
+                .map_err(|error| FetchError::SubrequestMalformedResponse {
+                    service: service_name.to_string(),
+                    reason: error.to_string(),
+                })
+        }
+        None => graphql::Response::from_value(service_name, value),
+    }?;
+    // a GraphQL response must have data, errors or both
+    if reject_empty_responses && response.data.is_none() && response.errors.is_empty() {
+        return Err(FetchError::SubrequestEmptyResponse {
+            service: service_name.to_string(),
+        });
+    }
+    Ok(response)
+}
+
+async fn send(
+    client: &mut HttpClient,
+    request: http::Request<hyper::Body>,
+    pool_metrics: Option<&ConnectionPoolMetrics>,
+    service_name: &str,
+) -> Result<HttpResponse, FetchError> {
+    let request = match pool_metrics {
+        Some(pool_metrics) => pool_metrics.wait_for_connection(request),
+        None => request,
+    };
+    client.call(request).await.map_err(|err| {
+        tracing::error!(fetch_error = format!("{:?}", err).as_str());
+
+        FetchError::SubrequestHttpError {
+            service: service_name.to_string(),
+            reason: err.to_string(),
+        }
+    })
+}
+
+/// Delay requested by the `Retry-After` header of a 429 response
+///
+/// Only delays in seconds are supported, HTTP dates are ignored.
+fn retry_after_delay<B>(response: &http::Response<B>) -> Option<Duration> {
+    if response.status() != StatusCode::TOO_MANY_REQUESTS {
+        return None;
+    }
+    let seconds = response
+        .headers()
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()?;
+    Some(Duration::from_secs(seconds))
+}
+
+/// Absolute target of a redirect response, resolved against the URI of the request
+fn redirect_location<B>(response: &http::Response<B>, uri: &Uri) -> Option<Uri> {
+    if !response.status().is_redirection() {
+        return None;
+    }
+    let location = response.headers().get(LOCATION)?.to_str().ok()?;
+    Url::parse(&uri.to_string())
+        .ok()?
+        .join(location)
+        .ok()?
+        .as_str()
+        .parse()
+        .ok()
+}
+
 pub(crate) async fn compress(body: String, headers: &HeaderMap) -> Result<Vec<u8>, BoxError> {
     let content_encoding = headers.get(&CONTENT_ENCODING);
     match content_encoding {
@@ -332,11 +837,144 @@ impl SubgraphServiceFactory for SubgraphCreator {
     }
 }
 
+/// Canonicalizes a subgraph URL: the scheme and host are lowercased, the default port of the
+/// scheme is removed, the `.` and `..` path segments are resolved, and the trailing slash of the
+/// path follows the configured policy
+pub(crate) fn normalize_subgraph_url(
+    url: &str,
+    trailing_slash: TrailingSlash,
+) -> Result<Uri, String> {
+    let mut url = Url::parse(url).map_err(|err| err.to_string())?;
+    if url.scheme() != "http" && url.scheme() != "https" {
+        return Err(format!(
+            "unsupported scheme '{}', expected http or https",
+            url.scheme()
+        ));
+    }
+    url.set_fragment(None);
+    let path = url.path().to_string();
+    match trailing_slash {
+        TrailingSlash::Preserve => {}
+        TrailingSlash::Remove => {
+            if path.len() > 1 && path.ends_with('/') {
+                url.set_path(path.trim_end_matches('/'));
+            }
+        }
+        TrailingSlash::Add => {
+            if !path.ends_with('/') {
+                url.set_path(&format!("{}/", path));
+            }
+        }
+    }
+    Uri::from_str(url.as_str()).map_err(|err| err.to_string())
+}
+
+/// A subgraph URL with `{name}` placeholders, filled with the context values of each request
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct UrlTemplate {
+    parts: Vec<UrlTemplatePart>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum UrlTemplatePart {
+    Text(String),
+    Placeholder(String),
+}
+
+impl UrlTemplate {
+    pub(crate) fn parse(template: &str) -> Result<Self, String> {
+        let mut parts = Vec::new();
+        let mut rest = template;
+        while let Some(start) = rest.find('{') {
+            let end = rest[start..]
+                .find('}')
+                .map(|end| start + end)
+                .ok_or_else(|| format!("unclosed placeholder in '{}'", template))?;
+            let name = &rest[start + 1..end];
+            if name.is_empty() || name.contains('{') {
+                return Err(format!(
+                    "invalid placeholder '{{{}}}' in '{}'",
+                    name, template
+                ));
+            }
+            if start > 0 {
+                parts.push(UrlTemplatePart::Text(rest[..start].to_string()));
+            }
+            parts.push(UrlTemplatePart::Placeholder(name.to_string()));
+            rest = &rest[end + 1..];
+        }
+        if rest.contains('}') {
+            return Err(format!("unmatched '}}' in '{}'", template));
+        }
+        if !rest.is_empty() {
+            parts.push(UrlTemplatePart::Text(rest.to_string()));
+        }
+
+        let template = Self { parts };
+        // the placeholders are filled with URL safe values, so any value gives a valid URL
+        normalize_subgraph_url(
+            &template.fill(|_| Ok("placeholder".to_string()))?,
+            TrailingSlash::Preserve,
+        )?;
+        Ok(template)
+    }
+
+    /// The URL of a request, with the context values of the placeholders
+    pub(crate) fn render(&self, context: &Context) -> Result<Uri, String> {
+        let url = self.fill(|name| match context.get::<_, String>(name) {
+            Ok(Some(value)) if is_url_safe(&value) => Ok(value),
+            Ok(Some(value)) => Err(format!(
+                "the context value '{}' cannot be used in a URL: '{}'",
+                name, value
+            )),
+            Ok(None) => Err(format!(
+                "missing the context value '{}' of the URL template",
+                name
+            )),
+            Err(_) => Err(format!("the context value '{}' is not a string", name)),
+        })?;
+        normalize_subgraph_url(&url, TrailingSlash::Preserve)
+    }
+
+    fn fill(
+        &self,
+        mut value: impl FnMut(&str) -> Result<String, String>,
+    ) -> Result<String, String> {
+        let mut url = String::new();
+        for part in &self.parts {
+            match part {
+                UrlTemplatePart::Text(text) => url.push_str(text),
+                UrlTemplatePart::Placeholder(name) => url.push_str(&value(name)?),
+            }
+        }
+        Ok(url)
+    }
+}
+
+/// Whether the value can fill a placeholder without changing the structure of the URL
+fn is_url_safe(value: &str) -> bool {
+    // `.` and `..` would be resolved as path segments
+    value.chars().any(|c| c != '.')
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '.' | '_' | '~'))
+}
+
+/// Whether two normalized subgraph URLs differ, but only by their scheme or a trailing slash
+pub(crate) fn differ_cosmetically(url: &Uri, other: &Uri) -> bool {
+    url != other
+        && url.host() == other.host()
+        && url.port_u16() == other.port_u16()
+        && url.path().trim_end_matches('/') == other.path().trim_end_matches('/')
+        && url.query() == other.query()
+}
+
 #[cfg(test)]
 mod tests {
     use std::convert::Infallible;
     use std::net::SocketAddr;
-    use std::str::FromStr;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
 
     use axum::Server;
     use http::header::HOST;
@@ -346,6 +984,7 @@ mod tests {
     use hyper::Body;
     use serde_json_bytes::ByteString;
     use serde_json_bytes::Value;
+    use tokio::io::AsyncReadExt;
     use tower::service_fn;
     use tower::ServiceExt;
 
@@ -353,6 +992,7 @@ mod tests {
     use crate::graphql::Error;
     use crate::graphql::Request;
     use crate::graphql::Response;
+    use crate::plugins::telemetry::metrics::AggregateMeterProvider;
     use crate::query_planner::fetch::OperationKind;
     use crate::Context;
     use crate::SubgraphRequest;
@@ -447,6 +1087,63 @@ mod tests {
         }
     }
 
+    // starts a local server emulating a subgraph checking the user agent it receives
+    async fn emulate_subgraph_user_agent(socket_addr: SocketAddr, expected: &'static str) {
+        let handle = move |request: http::Request<Body>| async move {
+            assert_eq!(request.headers().get(USER_AGENT).unwrap(), expected);
+            Ok::<_, Infallible>(
+                http::Response::builder()
+                    .header(CONTENT_TYPE, APPLICATION_JSON_HEADER_VALUE)
+                    .status(StatusCode::OK)
+                    .body(
+                        serde_json::to_string(&Response::default())
+                            .expect("always valid")
+                            .into(),
+                    )
+                    .unwrap(),
+            )
+        };
+
+        let make_svc =
+            make_service_fn(move |_conn| async move { Ok::<_, Infallible>(service_fn(handle)) });
+        let server = Server::bind(&socket_addr).serve(make_svc);
+        if let Err(e) = server.await {
+            eprintln!("server error: {}", e);
+        }
+    }
+
+    // starts a local server emulating a subgraph redirecting its requests to `/moved`
+    async fn emulate_subgraph_redirect(socket_addr: SocketAddr) {
+        async fn handle(request: http::Request<Body>) -> Result<http::Response<Body>, Infallible> {
+            if request.uri().path() == "/moved" {
+                Ok(http::Response::builder()
+                    .header(CONTENT_TYPE, APPLICATION_JSON_HEADER_VALUE)
+                    .status(StatusCode::OK)
+                    .body(
+                        serde_json::to_string(&Response {
+                            data: Some(Value::String(ByteString::from("moved"))),
+                            ..Response::default()
+                        })
+                        .expect("always valid")
+                        .into(),
+                    )
+                    .unwrap())
+            } else {
+                Ok(http::Response::builder()
+                    .header(LOCATION, "/moved")
+                    .status(StatusCode::FOUND)
+                    .body(Body::empty())
+                    .unwrap())
+            }
+        }
+
+        let make_svc = make_service_fn(|_conn| async { Ok::<_, Infallible>(service_fn(handle)) });
+        let server = Server::bind(&socket_addr).serve(make_svc);
+        if let Err(e) = server.await {
+            eprintln!("server error: {}", e);
+        }
+    }
+
     #[tokio::test(flavor = "multi_thread")]
     async fn test_bad_status_code_should_not_fail() {
         let socket_addr = SocketAddr::from_str("127.0.0.1:2626").unwrap();
@@ -549,4 +1246,1002 @@ mod tests {
 
         assert_eq!(resp.response.body(), &resp_from_subgraph);
     }
+
+    // starts a local server emulating a subgraph verifying the signature of the body it receives
+    async fn emulate_subgraph_verifying_signature(socket_addr: SocketAddr) {
+        async fn handle(request: http::Request<Body>) -> Result<http::Response<Body>, Infallible> {
+            let timestamp = request.headers().get("x-timestamp").unwrap().clone();
+            let signature = request.headers().get("x-signature").unwrap().clone();
+            // the body is signed as it is received, still compressed
+            let body = hyper::body::to_bytes(request.into_body()).await.unwrap();
+            let mut hmac = hmac::Hmac::<sha2::Sha256>::new_from_slice(b"secret").unwrap();
+            hmac::Mac::update(&mut hmac, timestamp.as_bytes());
+            hmac::Mac::update(&mut hmac, b".");
+            hmac::Mac::update(&mut hmac, &body);
+            let status =
+                match hmac::Mac::verify_slice(hmac, &hex::decode(signature.as_bytes()).unwrap()) {
+                    Ok(()) => StatusCode::OK,
+                    Err(_) => StatusCode::UNAUTHORIZED,
+                };
+            Ok(http::Response::builder()
+                .header(CONTENT_TYPE, APPLICATION_JSON_HEADER_VALUE)
+                .status(status)
+                .body(
+                    serde_json::to_string(&Response {
+                        data: Some(Value::Object(Default::default())),
+                        ..Response::default()
+                    })
+                    .expect("always valid")
+                    .into(),
+                )
+                .unwrap())
+        }
+
+        let make_svc = make_service_fn(|_conn| async { Ok::<_, Infallible>(service_fn(handle)) });
+        let server = Server::bind(&socket_addr).serve(make_svc);
+        if let Err(e) = server.await {
+            eprintln!("server error: {}", e);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_signed_compressed_request() {
+        let socket_addr = SocketAddr::from_str("127.0.0.1:4444").unwrap();
+        tokio::task::spawn(emulate_subgraph_verifying_signature(socket_addr));
+        let subgraph_service =
+            SubgraphService::new("test").with_signer(Some(SubgraphSigner::new("secret").unwrap()));
+
+        let url = Uri::from_str(&format!("http://{}", socket_addr)).unwrap();
+        let response = subgraph_service
+            .oneshot(SubgraphRequest {
+                supergraph_request: Arc::new(
+                    http::Request::builder()
+                        .header(HOST, "host")
+                        .header(CONTENT_TYPE, APPLICATION_JSON_HEADER_VALUE)
+                        .body(Request::builder().query("query").build())
+                        .expect("expecting valid request"),
+                ),
+                subgraph_request: http::Request::builder()
+                    .header(HOST, "rhost")
+                    .header(CONTENT_TYPE, APPLICATION_JSON_HEADER_VALUE)
+                    .header(CONTENT_ENCODING, "gzip")
+                    .uri(url)
+                    .body(Request::builder().query("query").build())
+                    .expect("expecting valid request"),
+                operation_kind: OperationKind::Query,
+                context: Context::new(),
+            })
+            .await
+            .unwrap();
+        assert_eq!(response.response.status(), StatusCode::OK);
+    }
+
+    async fn call_with_user_agent(socket_addr: SocketAddr, user_agent: Option<&str>) {
+        let subgraph_service = SubgraphService::new("test");
+
+        let url = Uri::from_str(&format!("http://{}", socket_addr)).unwrap();
+        let mut subgraph_request = http::Request::builder()
+            .header(HOST, "rhost")
+            .header(CONTENT_TYPE, APPLICATION_JSON_HEADER_VALUE)
+            .uri(url);
+        if let Some(user_agent) = user_agent {
+            subgraph_request = subgraph_request.header(USER_AGENT, user_agent);
+        }
+        let response = subgraph_service
+            .oneshot(SubgraphRequest {
+                supergraph_request: Arc::new(
+                    http::Request::builder()
+                        .header(HOST, "host")
+                        .header(CONTENT_TYPE, APPLICATION_JSON_HEADER_VALUE)
+                        .body(Request::builder().query("query").build())
+                        .expect("expecting valid request"),
+                ),
+                subgraph_request: subgraph_request
+                    .body(Request::builder().query("query").build())
+                    .expect("expecting valid request"),
+                operation_kind: OperationKind::Query,
+                context: Context::new(),
+            })
+            .await
+            .unwrap();
+        assert!(response.response.body().errors.is_empty());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_default_user_agent() {
+        let socket_addr = SocketAddr::from_str("127.0.0.1:2828").unwrap();
+        tokio::task::spawn(emulate_subgraph_user_agent(socket_addr, DEFAULT_USER_AGENT));
+        call_with_user_agent(socket_addr, None).await;
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_configured_user_agent() {
+        let socket_addr = SocketAddr::from_str("127.0.0.1:2929").unwrap();
+        tokio::task::spawn(emulate_subgraph_user_agent(socket_addr, "my-router/1.0"));
+        call_with_user_agent(socket_addr, Some("my-router/1.0")).await;
+    }
+
+    // starts a local server emulating a subgraph rejecting its first request with a 429 status
+    async fn emulate_subgraph_rate_limited(socket_addr: SocketAddr, retry_after: &'static str) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let make_svc = make_service_fn(move |_conn| {
+            let calls = calls.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |_request: http::Request<Body>| {
+                    let first_call = calls.fetch_add(1, Ordering::SeqCst) == 0;
+                    async move {
+                        if first_call {
+                            Ok::<_, Infallible>(
+                                http::Response::builder()
+                                    .header(RETRY_AFTER, retry_after)
+                                    .status(StatusCode::TOO_MANY_REQUESTS)
+                                    .body(Body::empty())
+                                    .unwrap(),
+                            )
+                        } else {
+                            Ok(http::Response::builder()
+                                .header(CONTENT_TYPE, APPLICATION_JSON_HEADER_VALUE)
+                                .status(StatusCode::OK)
+                                .body(
+                                    serde_json::to_string(&Response {
+                                        data: Some(Value::String(ByteString::from("retried"))),
+                                        ..Response::default()
+                                    })
+                                    .expect("always valid")
+                                    .into(),
+                                )
+                                .unwrap())
+                        }
+                    }
+                }))
+            }
+        });
+        let server = Server::bind(&socket_addr).serve(make_svc);
+        if let Err(e) = server.await {
+            eprintln!("server error: {}", e);
+        }
+    }
+
+    // Responds with an empty object, neither data nor errors
+    fn emulate_subgraph_empty_response() -> SocketAddr {
+        async fn handle(_request: http::Request<Body>) -> Result<http::Response<Body>, Infallible> {
+            Ok(http::Response::builder()
+                .header(CONTENT_TYPE, APPLICATION_JSON_HEADER_VALUE)
+                .status(StatusCode::OK)
+                .body(r#"{}"#.into())
+                .unwrap())
+        }
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let socket_addr = listener.local_addr().unwrap();
+        let make_svc = make_service_fn(|_conn| async { Ok::<_, Infallible>(service_fn(handle)) });
+        let server = Server::from_tcp(listener).unwrap().serve(make_svc);
+        tokio::task::spawn(async move {
+            if let Err(e) = server.await {
+                eprintln!("server error: {}", e);
+            }
+        });
+        socket_addr
+    }
+
+    // Responds with the Accept headers of the request as data
+    fn emulate_subgraph_echoing_accept() -> SocketAddr {
+        async fn handle(request: http::Request<Body>) -> Result<http::Response<Body>, Infallible> {
+            let accept: Vec<&str> = request
+                .headers()
+                .get_all(ACCEPT)
+                .iter()
+                .map(|value| value.to_str().unwrap())
+                .collect();
+            Ok(http::Response::builder()
+                .header(CONTENT_TYPE, APPLICATION_JSON_HEADER_VALUE)
+                .status(StatusCode::OK)
+                .body(
+                    serde_json::to_string(&Response {
+                        data: Some(Value::String(ByteString::from(accept.join(", ")))),
+                        ..Response::default()
+                    })
+                    .expect("always valid")
+                    .into(),
+                )
+                .unwrap())
+        }
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let socket_addr = listener.local_addr().unwrap();
+        let make_svc = make_service_fn(|_conn| async { Ok::<_, Infallible>(service_fn(handle)) });
+        let server = Server::from_tcp(listener).unwrap().serve(make_svc);
+        tokio::task::spawn(async move {
+            if let Err(e) = server.await {
+                eprintln!("server error: {}", e);
+            }
+        });
+        socket_addr
+    }
+
+    // Responds with the path of the request as data
+    fn emulate_subgraph_echoing_path() -> SocketAddr {
+        async fn handle(request: http::Request<Body>) -> Result<http::Response<Body>, Infallible> {
+            Ok(http::Response::builder()
+                .header(CONTENT_TYPE, APPLICATION_JSON_HEADER_VALUE)
+                .status(StatusCode::OK)
+                .body(
+                    serde_json::to_string(&Response {
+                        data: Some(Value::String(ByteString::from(request.uri().path()))),
+                        ..Response::default()
+                    })
+                    .expect("always valid")
+                    .into(),
+                )
+                .unwrap())
+        }
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let socket_addr = listener.local_addr().unwrap();
+        let make_svc = make_service_fn(|_conn| async { Ok::<_, Infallible>(service_fn(handle)) });
+        let server = Server::from_tcp(listener).unwrap().serve(make_svc);
+        tokio::task::spawn(async move {
+            if let Err(e) = server.await {
+                eprintln!("server error: {}", e);
+            }
+        });
+        socket_addr
+    }
+
+    // Responds with the x-user-id header name as it was sent, read from the raw HTTP/1.1 request
+    async fn emulate_subgraph_echoing_header_case(socket_addr: SocketAddr) {
+        let listener = tokio::net::TcpListener::bind(socket_addr).await.unwrap();
+        loop {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut head = Vec::new();
+            let mut buffer = [0; 1024];
+            while !head.windows(4).any(|window| window == b"\r\n\r\n") {
+                let read = stream.read(&mut buffer).await.unwrap();
+                if read == 0 {
+                    break;
+                }
+                head.extend_from_slice(&buffer[..read]);
+            }
+            let head = String::from_utf8_lossy(&head);
+            let name = head
+                .lines()
+                .filter_map(|line| line.split_once(':'))
+                .map(|(name, _)| name)
+                .find(|name| name.eq_ignore_ascii_case("x-user-id"))
+                .unwrap_or_default();
+            let body = serde_json::to_string(&Response {
+                data: Some(Value::String(ByteString::from(name))),
+                ..Response::default()
+            })
+            .expect("always valid");
+            let response = format!(
+                "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            stream.write_all(response.as_bytes()).await.unwrap();
+        }
+    }
+
+    fn request_to(socket_addr: SocketAddr) -> SubgraphRequest {
+        let url = Uri::from_str(&format!("http://{}", socket_addr)).unwrap();
+        SubgraphRequest {
+            supergraph_request: Arc::new(
+                http::Request::builder()
+                    .header(HOST, "host")
+                    .header(CONTENT_TYPE, APPLICATION_JSON_HEADER_VALUE)
+                    .body(Request::builder().query("query").build())
+                    .expect("expecting valid request"),
+            ),
+            subgraph_request: http::Request::builder()
+                .header(HOST, "rhost")
+                .header(CONTENT_TYPE, APPLICATION_JSON_HEADER_VALUE)
+                .uri(url)
+                .body(Request::builder().query("query").build())
+                .expect("expecting valid request"),
+            operation_kind: OperationKind::Query,
+            context: Context::new(),
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_redirects_are_not_followed_by_default() {
+        let socket_addr = SocketAddr::from_str("127.0.0.1:3030").unwrap();
+        tokio::task::spawn(emulate_subgraph_redirect(socket_addr));
+        let subgraph_service = SubgraphService::new("test");
+
+        let err = subgraph_service
+            .oneshot(request_to(socket_addr))
+            .await
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "HTTP fetch failed from 'test': subgraph responded with a redirect (302 Found) to http://127.0.0.1:3030/moved, and redirects are not followed for this subgraph"
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_follow_redirects() {
+        let socket_addr = SocketAddr::from_str("127.0.0.1:3131").unwrap();
+        tokio::task::spawn(emulate_subgraph_redirect(socket_addr));
+        let subgraph_service = SubgraphService::new("test").with_follow_redirects(true);
+
+        let response = subgraph_service
+            .oneshot(request_to(socket_addr))
+            .await
+            .unwrap();
+        assert_eq!(
+            response.response.body().data,
+            Some(Value::String(ByteString::from("moved")))
+        );
+    }
+
+    // starts a local server emulating a subgraph answering its POST requests with a 303 redirect
+    // to `/result`, which returns the method and the body size of the redirected request
+    fn emulate_subgraph_see_other() -> SocketAddr {
+        async fn handle(request: http::Request<Body>) -> Result<http::Response<Body>, Infallible> {
+            if request.uri().path() == "/result" {
+                let method = request.method().clone();
+                let content_type = request.headers().contains_key(CONTENT_TYPE);
+                let body = hyper::body::to_bytes(request.into_body()).await.unwrap();
+                Ok(http::Response::builder()
+                    .header(CONTENT_TYPE, APPLICATION_JSON_HEADER_VALUE)
+                    .status(StatusCode::OK)
+                    .body(
+                        serde_json::to_string(&Response {
+                            data: Some(Value::String(ByteString::from(format!(
+                                "{} {} bytes, content type: {}",
+                                method,
+                                body.len(),
+                                content_type
+                            )))),
+                            ..Response::default()
+                        })
+                        .expect("always valid")
+                        .into(),
+                    )
+                    .unwrap())
+            } else {
+                Ok(http::Response::builder()
+                    .header(LOCATION, "/result")
+                    .status(StatusCode::SEE_OTHER)
+                    .body(Body::empty())
+                    .unwrap())
+            }
+        }
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let socket_addr = listener.local_addr().unwrap();
+        let make_svc = make_service_fn(|_conn| async { Ok::<_, Infallible>(service_fn(handle)) });
+        let server = Server::from_tcp(listener).unwrap().serve(make_svc);
+        tokio::task::spawn(async move {
+            if let Err(e) = server.await {
+                eprintln!("server error: {}", e);
+            }
+        });
+        socket_addr
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_follow_see_other_redirects_with_get() {
+        let socket_addr = emulate_subgraph_see_other();
+        let subgraph_service = SubgraphService::new("test").with_follow_redirects(true);
+
+        let response = subgraph_service
+            .oneshot(request_to(socket_addr))
+            .await
+            .unwrap();
+        assert_eq!(
+            response.response.body().data,
+            Some(Value::String(ByteString::from(
+                "GET 0 bytes, content type: false"
+            )))
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_accept_header() {
+        let socket_addr = emulate_subgraph_echoing_accept();
+
+        let response = SubgraphService::new("test")
+            .oneshot(request_to(socket_addr))
+            .await
+            .unwrap();
+        assert_eq!(
+            response.response.body().data,
+            Some(Value::String(ByteString::from(
+                "application/json, application/graphql-response+json"
+            )))
+        );
+
+        let response = SubgraphService::new("test")
+            .with_accept(Some(HeaderValue::from_static(
+                "application/graphql-response+json",
+            )))
+            .oneshot(request_to(socket_addr))
+            .await
+            .unwrap();
+        assert_eq!(
+            response.response.body().data,
+            Some(Value::String(ByteString::from(
+                "application/graphql-response+json"
+            )))
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_title_case_headers() {
+        let socket_addr = SocketAddr::from_str("127.0.0.1:4545").unwrap();
+        tokio::task::spawn(emulate_subgraph_echoing_header_case(socket_addr));
+        let request = || {
+            let mut request = request_to(socket_addr);
+            request
+                .subgraph_request
+                .headers_mut()
+                .insert("x-user-id", HeaderValue::from_static("1"));
+            request
+        };
+
+        let response = SubgraphService::new("test")
+            .oneshot(request())
+            .await
+            .unwrap();
+        assert_eq!(
+            response.response.body().data,
+            Some(Value::String(ByteString::from("x-user-id")))
+        );
+
+        let response = SubgraphService::new("test")
+            .with_title_case_headers(true)
+            .oneshot(request())
+            .await
+            .unwrap();
+        assert_eq!(
+            response.response.body().data,
+            Some(Value::String(ByteString::from("X-User-Id")))
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_empty_response() {
+        let socket_addr = emulate_subgraph_empty_response();
+
+        let response = SubgraphService::new("test")
+            .oneshot(request_to(socket_addr))
+            .await
+            .unwrap();
+        assert_eq!(response.response.body().data, None);
+
+        let err = SubgraphService::new("test")
+            .with_reject_empty_responses(true)
+            .oneshot(request_to(socket_addr))
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<FetchError>(),
+            Some(FetchError::SubrequestEmptyResponse { service }) if service == "test"
+        ));
+        assert_eq!(
+            err.to_string(),
+            "service 'test' returned a response with neither data nor errors"
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_url_template() {
+        let socket_addr = emulate_subgraph_echoing_path();
+        let url_template = UrlTemplate::parse(&format!(
+            "http://{{host}}:{}/{{region}}/graphql",
+            socket_addr.port()
+        ))
+        .unwrap();
+
+        // the URL of the query plan points to another port, where nothing listens
+        let request = request_to(SocketAddr::from_str("127.0.0.1:1").unwrap());
+        request
+            .context
+            .insert("host", "127.0.0.1".to_string())
+            .unwrap();
+        request
+            .context
+            .insert("region", "eu-west".to_string())
+            .unwrap();
+        let response = SubgraphService::new("test")
+            .with_url_template(Some(url_template.clone()))
+            .oneshot(request)
+            .await
+            .unwrap();
+        assert_eq!(
+            response.response.body().data,
+            Some(Value::String(ByteString::from("/eu-west/graphql")))
+        );
+
+        let request = request_to(socket_addr);
+        request
+            .context
+            .insert("host", "127.0.0.1".to_string())
+            .unwrap();
+        let err = SubgraphService::new("test")
+            .with_url_template(Some(url_template))
+            .oneshot(request)
+            .await
+            .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<FetchError>(),
+            Some(FetchError::SubrequestUrlError { service, .. }) if service == "test"
+        ));
+        assert_eq!(
+            err.to_string(),
+            "could not build the URL of service 'test': missing the context value 'region' of the URL template"
+        );
+    }
+
+    #[test]
+    fn it_parses_url_templates() {
+        let template = UrlTemplate::parse("https://{region}.example.com/{tenant}/graphql").unwrap();
+        let context = Context::new();
+        context.insert("region", "eu".to_string()).unwrap();
+        context.insert("tenant", "acme".to_string()).unwrap();
+        assert_eq!(
+            template.render(&context).unwrap(),
+            Uri::from_static("https://eu.example.com/acme/graphql")
+        );
+
+        // the values can't change the structure of the URL
+        context
+            .insert("tenant", "acme/../admin".to_string())
+            .unwrap();
+        assert!(template.render(&context).is_err());
+
+        assert!(UrlTemplate::parse("https://{region.example.com/graphql").is_err());
+        assert!(UrlTemplate::parse("https://region}.example.com/graphql").is_err());
+        assert!(UrlTemplate::parse("https://{}.example.com/graphql").is_err());
+        assert!(UrlTemplate::parse("{scheme}://example.com/graphql").is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_retry_after() {
+        let socket_addr = SocketAddr::from_str("127.0.0.1:3232").unwrap();
+        tokio::task::spawn(emulate_subgraph_rate_limited(socket_addr, "1"));
+        let subgraph_service = SubgraphService::new("test").with_retry_after(Some(RetryAfter {
+            max_retries: 1,
+            max_delay: Duration::from_secs(5),
+            budget: Duration::from_secs(30),
+        }));
+
+        let start = Instant::now();
+        let response = subgraph_service
+            .oneshot(request_to(socket_addr))
+            .await
+            .unwrap();
+        assert!(start.elapsed() >= Duration::from_secs(1));
+        assert_eq!(
+            response.response.body().data,
+            Some(Value::String(ByteString::from("retried")))
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_retry_after_exceeding_the_budget() {
+        let socket_addr = SocketAddr::from_str("127.0.0.1:3333").unwrap();
+        tokio::task::spawn(emulate_subgraph_rate_limited(socket_addr, "60"));
+        let subgraph_service = SubgraphService::new("test").with_retry_after(Some(RetryAfter {
+            max_retries: 1,
+            max_delay: Duration::from_secs(120),
+            budget: Duration::from_secs(30),
+        }));
+
+        let start = Instant::now();
+        let err = subgraph_service
+            .oneshot(request_to(socket_addr))
+            .await
+            .unwrap_err();
+        assert!(start.elapsed() < Duration::from_secs(5));
+        assert_eq!(
+            err.to_string(),
+            "HTTP fetch failed from 'test': subgraph is rate limiting requests and asked to retry after 1m, which exceeds the retry budget"
+        );
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_client_hook() {
+        let socket_addr = SocketAddr::from_str("127.0.0.1:3434").unwrap();
+        tokio::task::spawn(emulate_subgraph_redirect(socket_addr));
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = calls.clone();
+        let hook = SubgraphClientHook::from(move |name: &str, client: transport::BoxService| {
+            assert_eq!(name, "test");
+            let counter = counter.clone();
+            client
+                .map_request(move |request: transport::Request| {
+                    counter.fetch_add(1, Ordering::SeqCst);
+                    request
+                })
+                .boxed()
+        });
+        let subgraph_service = SubgraphService::new("test")
+            .with_client_hook(&hook, BufferMetrics::default())
+            .with_follow_redirects(true);
+
+        let response = subgraph_service
+            .oneshot(request_to(socket_addr))
+            .await
+            .unwrap();
+        assert_eq!(
+            response.response.body().data,
+            Some(Value::String(ByteString::from("moved")))
+        );
+        // the redirect and the redirected request both go through the hook
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_service_with_client() {
+        let requests = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = requests.clone();
+        let client = service_fn(move |request: transport::Request| {
+            let recorded = recorded.clone();
+            async move {
+                let (parts, body) = request.into_parts();
+                let body = hyper::body::to_bytes(body).await?;
+                recorded.lock().unwrap().push((parts.uri, body));
+                Ok::<_, BoxError>(
+                    http::Response::builder()
+                        .header(CONTENT_TYPE, APPLICATION_JSON_HEADER_VALUE)
+                        .body(Body::from(r#"{"data":"mocked"}"#))
+                        .unwrap(),
+                )
+            }
+        });
+        let subgraph_service =
+            crate::services::subgraph::service_with_client("test", client.boxed());
+
+        let socket_addr = SocketAddr::from_str("127.0.0.1:3939").unwrap();
+        let response = subgraph_service
+            .oneshot(request_to(socket_addr))
+            .await
+            .unwrap();
+        assert_eq!(
+            response.response.body().data,
+            Some(Value::String(ByteString::from("mocked")))
+        );
+        // nothing listens on the address, the request only went through the injected client
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].0.port_u16(), Some(3939));
+        assert_eq!(
+            serde_json::from_slice::<serde_json::Value>(&requests[0].1).unwrap(),
+            serde_json::json!({ "query": "query" })
+        );
+    }
+
+    // starts a local server emulating a subgraph returning its errors under a `failures` key
+    async fn emulate_subgraph_legacy_errors(socket_addr: SocketAddr) {
+        async fn handle(_request: http::Request<Body>) -> Result<http::Response<Body>, Infallible> {
+            Ok(http::Response::builder()
+                .header(CONTENT_TYPE, APPLICATION_JSON_HEADER_VALUE)
+                .status(StatusCode::OK)
+                .body(r#"{"data":{"me":null},"failures":[{"reason":"user not found"}]}"#.into())
+                .unwrap())
+        }
+
+        let make_svc = make_service_fn(|_conn| async { Ok::<_, Infallible>(service_fn(handle)) });
+        let server = Server::bind(&socket_addr).serve(make_svc);
+        if let Err(e) = server.await {
+            eprintln!("server error: {}", e);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_response_adapter() {
+        let socket_addr = SocketAddr::from_str("127.0.0.1:3737").unwrap();
+        tokio::task::spawn(emulate_subgraph_legacy_errors(socket_addr));
+        let adapter = SubgraphResponseAdapter::from(|body: Value| {
+            let errors = match body.get("failures") {
+                Some(Value::Array(failures)) => failures
+                    .iter()
+                    .filter_map(|failure| failure.get("reason").and_then(Value::as_str))
+                    .map(|reason| Error::builder().message(reason).build())
+                    .collect(),
+                _ => Vec::new(),
+            };
+            Ok(Response::builder()
+                .and_data(body.get("data").cloned())
+                .errors(errors)
+                .build())
+        });
+
+        // without an adapter, the unknown key is ignored
+        let response = SubgraphService::new("test")
+            .oneshot(request_to(socket_addr))
+            .await
+            .unwrap();
+        assert!(response.response.body().errors.is_empty());
+
+        let response = SubgraphService::new("test")
+            .with_response_adapter(adapter)
+            .oneshot(request_to(socket_addr))
+            .await
+            .unwrap();
+        let body = response.response.body();
+        assert_eq!(body.data, Some(serde_json_bytes::json!({ "me": null })));
+        assert_eq!(
+            body.errors,
+            vec![Error::builder().message("user not found").build()]
+        );
+    }
+
+    // starts a local server emulating a slow subgraph, recording the maximum number of requests in flight
+    async fn emulate_subgraph_in_flight(socket_addr: SocketAddr, max_in_flight: Arc<AtomicUsize>) {
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let make_svc = make_service_fn(move |_conn| {
+            let in_flight = in_flight.clone();
+            let max_in_flight = max_in_flight.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |_request: http::Request<Body>| {
+                    let in_flight = in_flight.clone();
+                    let current = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_in_flight.fetch_max(current, Ordering::SeqCst);
+                    async move {
+                        tokio::time::sleep(Duration::from_millis(100)).await;
+                        in_flight.fetch_sub(1, Ordering::SeqCst);
+                        Ok::<_, Infallible>(
+                            http::Response::builder()
+                                .header(CONTENT_TYPE, APPLICATION_JSON_HEADER_VALUE)
+                                .status(StatusCode::OK)
+                                .body(
+                                    serde_json::to_string(&Response {
+                                        data: Some(Value::String(ByteString::from("done"))),
+                                        ..Response::default()
+                                    })
+                                    .expect("always valid")
+                                    .into(),
+                                )
+                                .unwrap(),
+                        )
+                    }
+                }))
+            }
+        });
+        let server = Server::bind(&socket_addr).serve(make_svc);
+        if let Err(e) = server.await {
+            eprintln!("server error: {}", e);
+        }
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_concurrency_limit() {
+        let limited_addr = SocketAddr::from_str("127.0.0.1:3535").unwrap();
+        let unlimited_addr = SocketAddr::from_str("127.0.0.1:3636").unwrap();
+        let limited_max = Arc::new(AtomicUsize::new(0));
+        let unlimited_max = Arc::new(AtomicUsize::new(0));
+        tokio::task::spawn(emulate_subgraph_in_flight(
+            limited_addr,
+            limited_max.clone(),
+        ));
+        tokio::task::spawn(emulate_subgraph_in_flight(
+            unlimited_addr,
+            unlimited_max.clone(),
+        ));
+        let limited = SubgraphService::new("limited").with_concurrency_limit(NonZeroUsize::new(2));
+        let unlimited = SubgraphService::new("unlimited");
+
+        let requests = (0..6).flat_map(|_| {
+            [
+                limited.clone().oneshot(request_to(limited_addr)),
+                unlimited.clone().oneshot(request_to(unlimited_addr)),
+            ]
+        });
+        for response in futures::future::join_all(requests).await {
+            assert_eq!(
+                response.unwrap().response.body().data,
+                Some(Value::String(ByteString::from("done")))
+            );
+        }
+        assert_eq!(limited_max.load(Ordering::SeqCst), 2);
+        // the limit of a subgraph does not hold back the requests to other subgraphs
+        assert!(unlimited_max.load(Ordering::SeqCst) > 2);
+    }
+
+    fn gauge(exporter: &opentelemetry_prometheus::PrometheusExporter, name: &str) -> f64 {
+        exporter
+            .registry()
+            .gather()
+            .iter()
+            .filter(|family| family.get_name().starts_with(name))
+            .flat_map(|family| family.get_metric())
+            .map(|metric| metric.get_gauge().get_value())
+            .sum()
+    }
+
+    // starts a local server emulating a subgraph on a free port, and returns its address. The
+    // arrival of each request is sent to `received`, and its response waits for a permit of
+    // `release`
+    fn emulate_subgraph_held(
+        received: tokio::sync::mpsc::UnboundedSender<()>,
+        release: Arc<Semaphore>,
+    ) -> SocketAddr {
+        let make_svc = make_service_fn(move |_conn| {
+            let received = received.clone();
+            let release = release.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |_request: http::Request<Body>| {
+                    let _ = received.send(());
+                    let release = release.clone();
+                    async move {
+                        release
+                            .acquire()
+                            .await
+                            .expect("the semaphore is never closed; qed")
+                            .forget();
+                        Ok::<_, Infallible>(
+                            http::Response::builder()
+                                .header(CONTENT_TYPE, APPLICATION_JSON_HEADER_VALUE)
+                                .status(StatusCode::OK)
+                                .body(
+                                    serde_json::to_string(&Response {
+                                        data: Some(Value::String(ByteString::from("done"))),
+                                        ..Response::default()
+                                    })
+                                    .expect("always valid")
+                                    .into(),
+                                )
+                                .unwrap(),
+                        )
+                    }
+                }))
+            }
+        });
+        let server = Server::bind(&SocketAddr::from(([127, 0, 0, 1], 0))).serve(make_svc);
+        let socket_addr = server.local_addr();
+        tokio::task::spawn(async move {
+            if let Err(e) = server.await {
+                eprintln!("server error: {}", e);
+            }
+        });
+        socket_addr
+    }
+
+    fn gathered_count(exporter: &opentelemetry_prometheus::PrometheusExporter, name: &str) -> u64 {
+        exporter
+            .registry()
+            .gather()
+            .iter()
+            .filter(|family| family.get_name().starts_with(name))
+            .flat_map(|family| family.get_metric())
+            .map(|metric| metric.get_histogram().get_sample_count())
+            .sum()
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_connection_pool_metrics() {
+        let exporter = opentelemetry_prometheus::exporter().init();
+        let meter_provider = AggregateMeterProvider::new(vec![Arc::new(
+            exporter.provider().expect("meter provider"),
+        )]);
+        let (received, mut arrivals) = tokio::sync::mpsc::unbounded_channel();
+        let release = Arc::new(Semaphore::new(0));
+        let socket_addr = emulate_subgraph_held(received, release.clone());
+        let metrics = ConnectionPoolMetrics::new("test", &meter_provider);
+        let subgraph_service =
+            SubgraphService::new("test").with_connection_pool_metrics(metrics.clone());
+
+        let requests: Vec<_> = (0..4)
+            .map(|_| tokio::spawn(subgraph_service.clone().oneshot(request_to(socket_addr))))
+            .collect();
+
+        // the subgraph holds the responses until they are released: each concurrent request
+        // keeps a connection busy
+        for _ in 0..4 {
+            arrivals.recv().await.unwrap();
+        }
+        assert_eq!(
+            gauge(&exporter, "apollo_router_subgraph_connections_active"),
+            4.0
+        );
+        assert_eq!(
+            gauge(&exporter, "apollo_router_subgraph_connections_idle"),
+            0.0
+        );
+        // each request waited for a connection before it was sent
+        assert_eq!(
+            gathered_count(&exporter, "apollo_router_subgraph_connection_wait_seconds"),
+            4
+        );
+
+        release.add_permits(4);
+        for request in requests {
+            assert_eq!(
+                request.await.unwrap().unwrap().response.body().data,
+                Some(Value::String(ByteString::from("done")))
+            );
+        }
+        // once the responses are read, the connections stay idle in the pool
+        assert_eq!(
+            gauge(&exporter, "apollo_router_subgraph_connections_active"),
+            0.0
+        );
+        assert!(gauge(&exporter, "apollo_router_subgraph_connections_idle") > 0.0);
+    }
+
+    #[test]
+    fn it_normalizes_subgraph_urls() {
+        let normalize = |url: &str, trailing_slash: TrailingSlash| {
+            normalize_subgraph_url(url, trailing_slash)
+                .unwrap()
+                .to_string()
+        };
+        assert_eq!(
+            normalize(
+                "HTTP://Products.Example.com:80/graphql",
+                TrailingSlash::Preserve
+            ),
+            "http://products.example.com/graphql"
+        );
+        assert_eq!(
+            normalize(
+                "https://example.com:443/a/./b/../graphql/",
+                TrailingSlash::Preserve
+            ),
+            "https://example.com/a/graphql/"
+        );
+        assert_eq!(
+            normalize(
+                "https://example.com:4001/graphql#fragment",
+                TrailingSlash::Preserve
+            ),
+            "https://example.com:4001/graphql"
+        );
+        assert_eq!(
+            normalize("http://example.com/graphql/", TrailingSlash::Remove),
+            "http://example.com/graphql"
+        );
+        assert_eq!(
+            normalize("http://example.com/", TrailingSlash::Remove),
+            "http://example.com/"
+        );
+        assert_eq!(
+            normalize("http://example.com/graphql?v=2", TrailingSlash::Add),
+            "http://example.com/graphql/?v=2"
+        );
+        assert_eq!(
+            normalize("http://example.com", TrailingSlash::Add),
+            "http://example.com/"
+        );
+    }
+
+    #[test]
+    fn it_rejects_malformed_subgraph_urls() {
+        assert!(normalize_subgraph_url("localhost:4001/graphql", TrailingSlash::Preserve).is_err());
+        assert!(normalize_subgraph_url("/graphql", TrailingSlash::Preserve).is_err());
+        assert!(normalize_subgraph_url("http://", TrailingSlash::Preserve).is_err());
+        assert_eq!(
+            normalize_subgraph_url("ftp://example.com/graphql", TrailingSlash::Preserve)
+                .unwrap_err(),
+            "unsupported scheme 'ftp', expected http or https"
+        );
+    }
+
+    #[test]
+    fn it_detects_cosmetic_url_differences() {
+        let uri = |url: &str| Uri::from_str(url).unwrap();
+        let url = uri("http://example.com/graphql");
+        assert!(differ_cosmetically(
+            &url,
+            &uri("http://example.com/graphql/")
+        ));
+        assert!(differ_cosmetically(
+            &url,
+            &uri("https://example.com/graphql")
+        ));
+        assert!(!differ_cosmetically(
+            &url,
+            &uri("http://example.com/graphql")
+        ));
+        assert!(!differ_cosmetically(
+            &url,
+            &uri("http://example.com:4001/graphql")
+        ));
+        assert!(!differ_cosmetically(&url, &uri("http://other.com/graphql")));
+    }
 }
@@ -1,5 +1,6 @@
 //! Implements the router phase of the request lifecycle.
 
+use std::collections::HashMap;
 use std::sync::Arc;
 use std::task::Poll;
 
@@ -10,6 +11,7 @@ use http::StatusCode;
 use indexmap::IndexMap;
 use multimap::MultiMap;
 use opentelemetry::trace::SpanKind;
+use serde_json_bytes::ByteString;
 use tower::util::BoxService;
 use tower::util::Either;
 use tower::BoxError;
@@ -21,25 +23,46 @@ use tracing_futures::Instrument;
 use super::new_service::NewService;
 use super::subgraph_service::MakeSubgraphService;
 use super::subgraph_service::SubgraphCreator;
+use super::supergraph;
 use super::ExecutionCreator;
 use super::ExecutionServiceFactory;
 use super::QueryPlannerContent;
 use crate::axum_factory::utils::accepts_multipart;
+use crate::cache::metrics::CacheMetrics;
+use crate::configuration::UnknownVariables;
+use crate::configuration::VariableCoercion;
+use crate::configuration::WarmupOperation;
 use crate::error::CacheResolverError;
+use crate::error::QueryPlannerError;
 use crate::error::ServiceBuildError;
 use crate::graphql;
 use crate::graphql::IntoGraphQLErrors;
 use crate::introspection::Introspection;
+use crate::json_ext::Object;
 use crate::plugin::DynPlugin;
+use crate::plugins::override_url::OverrideSubgraphUrl;
+use crate::plugins::override_url::APOLLO_OVERRIDE_SUBGRAPH_URL;
+use crate::plugins::telemetry::Telemetry;
 use crate::plugins::traffic_shaping::TrafficShaping;
 use crate::plugins::traffic_shaping::APOLLO_TRAFFIC_SHAPING;
+use crate::query_planner::metrics::DeferMetrics;
 use crate::query_planner::BridgeQueryPlanner;
 use crate::query_planner::CachingQueryPlanner;
+use crate::query_planner::ExecutionOptions;
+use crate::router_factory::list_active_plugins;
 use crate::router_factory::Endpoint;
 use crate::router_factory::SupergraphServiceFactory;
+use crate::services::layers::catch_panic::CatchPanic;
 use crate::services::layers::ensure_query_presence::EnsureQueryPresence;
+use crate::services::layers::query_size_limit::QuerySizeLimit;
+use crate::services::layers::response_size_limit::ResponseSizeLimit;
+use crate::services::layers::variables_limit::VariablesLimit;
+use crate::services::measured_buffer::BufferMetrics;
+use crate::services::measured_buffer::MeasuredBuffer;
+use crate::spec::Query;
 use crate::Configuration;
 use crate::Context;
+use crate::ContextMerge;
 use crate::ExecutionRequest;
 use crate::ExecutionResponse;
 use crate::ListenAddr;
@@ -49,6 +72,9 @@ use crate::Schema;
 use crate::SupergraphRequest;
 use crate::SupergraphResponse;
 
+/// Kind of the executed operation: `query`, `mutation` or `subscription`
+pub(crate) const OPERATION_KIND: &str = "apollo_router::operation_kind";
+
 /// An [`IndexMap`] of available plugins.
 pub(crate) type Plugins = IndexMap<String, Box<dyn DynPlugin>>;
 
@@ -58,6 +84,10 @@ pub(crate) struct SupergraphService<ExecutionFactory> {
     execution_service_factory: ExecutionFactory,
     query_planner_service: CachingQueryPlanner<BridgeQueryPlanner>,
     schema: Arc<Schema>,
+    variable_coercion: VariableCoercion,
+    unknown_variables: UnknownVariables,
+    require_operation_name: bool,
+    default_variables: Arc<Object>,
 }
 
 #[buildstructor::buildstructor]
@@ -67,11 +97,19 @@ impl<ExecutionFactory> SupergraphService<ExecutionFactory> {
         query_planner_service: CachingQueryPlanner<BridgeQueryPlanner>,
         execution_service_factory: ExecutionFactory,
         schema: Arc<Schema>,
+        variable_coercion: VariableCoercion,
+        unknown_variables: Option<UnknownVariables>,
+        require_operation_name: Option<bool>,
+        default_variables: Option<Arc<Object>>,
     ) -> Self {
         SupergraphService {
             query_planner_service,
             execution_service_factory,
             schema,
+            variable_coercion,
+            unknown_variables: unknown_variables.unwrap_or_default(),
+            require_operation_name: require_operation_name.unwrap_or_default(),
+            default_variables: default_variables.unwrap_or_default(),
         }
     }
 }
@@ -98,28 +136,41 @@ where
         let execution = self.execution_service_factory.new_service();
 
         let schema = self.schema.clone();
+        let variable_coercion = self.variable_coercion;
+        let unknown_variables = self.unknown_variables;
+        let require_operation_name = self.require_operation_name;
+        let default_variables = self.default_variables.clone();
 
         let context_cloned = req.context.clone();
-        let fut =
-            service_call(planning, execution, schema, req).or_else(|error: BoxError| async move {
-                let errors = vec![crate::error::Error {
-                    message: error.to_string(),
-                    extensions: serde_json_bytes::json!({
-                        "code": "INTERNAL_SERVER_ERROR",
-                    })
-                    .as_object()
-                    .unwrap()
-                    .to_owned(),
-                    ..Default::default()
-                }];
-
-                Ok(SupergraphResponse::builder()
-                    .errors(errors)
-                    .status_code(StatusCode::INTERNAL_SERVER_ERROR)
-                    .context(context_cloned)
-                    .build()
-                    .expect("building a response like this should not fail"))
-            });
+        let fut = service_call(
+            planning,
+            execution,
+            schema,
+            variable_coercion,
+            unknown_variables,
+            require_operation_name,
+            default_variables,
+            req,
+        )
+        .or_else(|error: BoxError| async move {
+            let errors = vec![crate::error::Error {
+                message: error.to_string(),
+                extensions: serde_json_bytes::json!({
+                    "code": "INTERNAL_SERVER_ERROR",
+                })
+                .as_object()
+                .unwrap()
+                .to_owned(),
+                ..Default::default()
+            }];
+
+            Ok(SupergraphResponse::builder()
+                .errors(errors)
+                .status_code(StatusCode::INTERNAL_SERVER_ERROR)
+                .context(context_cloned)
+                .build()
+                .expect("building a response like this should not fail"))
+        });
         // FIXME: Enable it later
         // .and_then(|mut res| async move {
         //     if let Some(trace_id) = TraceId::maybe_new().map(|t| t.to_string()) {
@@ -142,32 +193,48 @@ async fn service_call<ExecutionService>(
     planning: CachingQueryPlanner<BridgeQueryPlanner>,
     execution: ExecutionService,
     schema: Arc<Schema>,
-    req: SupergraphRequest,
+    variable_coercion: VariableCoercion,
+    unknown_variables: UnknownVariables,
+    require_operation_name: bool,
+    default_variables: Arc<Object>,
+    mut req: SupergraphRequest,
 ) -> Result<SupergraphResponse, BoxError>
 where
     ExecutionService:
         Service<ExecutionRequest, Response = ExecutionResponse, Error = BoxError> + Send,
 {
+    if !default_variables.is_empty() {
+        inject_default_variables(&mut req, &default_variables);
+    }
+
     let context = req.context;
     let body = req.supergraph_request.body();
-    let variables = body.variables.clone();
     let QueryPlannerResponse {
         content,
         context,
         errors,
     } = match plan_query(planning, body, context.clone()).await {
         Ok(resp) => resp,
-        Err(err) => match err.into_graphql_errors() {
-            Ok(gql_errors) => {
-                return Ok(SupergraphResponse::builder()
-                    .context(context)
-                    .errors(gql_errors)
-                    .status_code(StatusCode::BAD_REQUEST) // If it's a graphql error we return a status code 400
-                    .build()
-                    .expect("this response build must not fail"));
+        Err(err) => {
+            // the query itself may be fine when the planner ran out of time, so it is not a 400
+            let CacheResolverError::RetrievalError(planner_error) = &err;
+            let status_code = if matches!(**planner_error, QueryPlannerError::Timeout(_)) {
+                StatusCode::GATEWAY_TIMEOUT
+            } else {
+                StatusCode::BAD_REQUEST // If it's a graphql error we return a status code 400
+            };
+            match err.into_graphql_errors() {
+                Ok(gql_errors) => {
+                    return Ok(SupergraphResponse::builder()
+                        .context(context)
+                        .errors(gql_errors)
+                        .status_code(status_code)
+                        .build()
+                        .expect("this response build must not fail"));
+                }
+                Err(err) => return Err(err.into()),
             }
-            Err(err) => return Err(err.into()),
-        },
+        }
     };
 
     if !errors.is_empty() {
@@ -183,6 +250,15 @@ where
         Some(QueryPlannerContent::Introspection { response }) => Ok(
             SupergraphResponse::new_from_graphql_response(*response, context),
         ),
+        // all the root fields are skipped, so the response has empty data
+        Some(QueryPlannerContent::EmptyPlan { .. }) => {
+            Ok(SupergraphResponse::new_from_graphql_response(
+                graphql::Response::builder()
+                    .data(serde_json_bytes::json!({}))
+                    .build(),
+                context,
+            ))
+        }
         Some(QueryPlannerContent::IntrospectionDisabled) => {
             let mut response = SupergraphResponse::new_from_graphql_response(
                 graphql::Response::builder()
@@ -198,6 +274,10 @@ where
 
         Some(QueryPlannerContent::Plan { plan }) => {
             let operation_name = body.operation_name.clone();
+            let variables = body.variables.clone();
+            if let Some(kind) = plan.query.operation_kind(operation_name.as_deref()) {
+                context.insert(OPERATION_KIND, kind)?;
+            }
             let is_deferred = plan.is_deferred(operation_name.as_deref(), &variables);
             if is_deferred && !accepts_multipart(req.supergraph_request.headers()) {
                 let mut response = SupergraphResponse::new_from_graphql_response(graphql::Response::builder()
@@ -207,7 +287,20 @@ where
                     .build(), context);
                 *response.response.status_mut() = StatusCode::NOT_ACCEPTABLE;
                 Ok(response)
-            } else if let Some(err) = plan.query.validate_variables(body, &schema).err() {
+            } else if require_operation_name && plan.query.is_anonymous(operation_name.as_deref()) {
+                let mut response = SupergraphResponse::new_from_graphql_response(graphql::Response::builder()
+                    .errors(vec![crate::error::Error::builder()
+                        .message(String::from("the router requires operations to be named, for example `query MyOperation { ... }`. Add a name to the operation"))
+                        .extension("code", "OPERATION_NAME_REQUIRED")
+                        .build()])
+                    .build(), context);
+                *response.response.status_mut() = StatusCode::BAD_REQUEST;
+                Ok(response)
+            } else if let Some(err) = plan
+                .query
+                .validate_variables(body, &schema, variable_coercion, unknown_variables)
+                .err()
+            {
                 let mut res = SupergraphResponse::new_from_graphql_response(err, context);
                 *res.response.status_mut() = StatusCode::BAD_REQUEST;
                 Ok(res)
@@ -240,6 +333,25 @@ where
     }
 }
 
+/// Adds the configured default values of the variables that the operation declares without a
+/// default value of its own. The variables sent by the client and the defaults declared by the
+/// operation take precedence, and undeclared variables are left alone, so they pass the validation
+/// of the variables
+fn inject_default_variables(req: &mut SupergraphRequest, default_variables: &Object) {
+    let body = req.supergraph_request.body_mut();
+    let query = match body.query.as_deref() {
+        Some(query) => query,
+        None => return,
+    };
+    for name in Query::variables_without_default(query, body.operation_name.as_deref()) {
+        if let Some(value) = default_variables.get(name.as_str()) {
+            if !body.variables.contains_key(name.as_str()) {
+                body.variables.insert(name.into(), value.clone());
+            }
+        }
+    }
+}
+
 async fn plan_query(
     mut planning: CachingQueryPlanner<BridgeQueryPlanner>,
     body: &graphql::Request,
@@ -327,6 +439,28 @@ impl PluggableSupergraphServiceBuilder {
         // the plugins in their original order.
 
         let configuration = self.configuration.unwrap_or_default();
+        let max_query_bytes = configuration.limits.max_query_bytes;
+        let max_variables = configuration.limits.max_variables;
+        let max_response_bytes = configuration.limits.max_response_bytes;
+        let variable_coercion = configuration.supergraph.variable_coercion;
+        let unknown_variables = configuration.supergraph.unknown_variables;
+        let require_operation_name = configuration.supergraph.require_operation_name;
+        let default_variables = Arc::new(
+            configuration
+                .supergraph
+                .default_variables
+                .iter()
+                .map(|(name, value)| {
+                    (
+                        ByteString::from(name.as_str()),
+                        serde_json_bytes::to_value(value)
+                            .expect("a JSON value can always be converted"),
+                    )
+                })
+                .collect::<Object>(),
+        );
+        let panic_message = configuration.supergraph.panic_message.clone();
+        let execution_options = Arc::new(ExecutionOptions::new(&configuration));
 
         let plan_cache_limit = std::env::var("ROUTER_PLAN_CACHE_LIMIT")
             .ok()
@@ -345,13 +479,32 @@ impl PluggableSupergraphServiceBuilder {
             BridgeQueryPlanner::new(self.schema.clone(), introspection, configuration)
                 .await
                 .map_err(ServiceBuildError::QueryPlannerError)?;
+        let meter_provider = self
+            .plugins
+            .iter()
+            .find(|i| i.0.as_str() == "apollo.telemetry")
+            .and_then(|plugin| plugin.1.as_any().downcast_ref::<Telemetry>())
+            .map(|telemetry| telemetry.meter_provider().clone())
+            .unwrap_or_default();
         let query_planner_service = CachingQueryPlanner::new(
             bridge_query_planner,
             plan_cache_limit,
             self.schema.schema_id.clone(),
             redis_urls,
+            CacheMetrics::new("query_planner", &meter_provider),
         )
         .await;
+        let defer_metrics = DeferMetrics::new(&meter_provider);
+        let catch_panic = CatchPanic::new(panic_message, &meter_provider);
+
+        // the merge strategies are set on the context before the plugins run, so that they
+        // apply to all the writes
+        let context_merges: HashMap<String, ContextMerge> = self
+            .plugins
+            .iter()
+            .flat_map(|(_, plugin)| plugin.context_merges())
+            .collect();
+        let context_merges = Arc::new(context_merges);
 
         let plugins = Arc::new(self.plugins);
 
@@ -365,6 +518,17 @@ impl PluggableSupergraphServiceBuilder {
             subgraph_creator,
             schema: self.schema,
             plugins,
+            max_query_bytes,
+            max_variables,
+            max_response_bytes,
+            variable_coercion,
+            unknown_variables,
+            require_operation_name,
+            default_variables,
+            defer_metrics,
+            catch_panic,
+            context_merges,
+            execution_options,
         })
     }
 }
@@ -376,6 +540,17 @@ pub(crate) struct RouterCreator {
     subgraph_creator: Arc<SubgraphCreator>,
     schema: Arc<Schema>,
     plugins: Arc<Plugins>,
+    max_query_bytes: Option<usize>,
+    max_variables: Option<usize>,
+    max_response_bytes: Option<usize>,
+    variable_coercion: VariableCoercion,
+    unknown_variables: UnknownVariables,
+    require_operation_name: bool,
+    default_variables: Arc<Object>,
+    defer_metrics: DeferMetrics,
+    catch_panic: CatchPanic,
+    context_merges: Arc<HashMap<String, ContextMerge>>,
+    execution_options: Arc<ExecutionOptions>,
 }
 
 impl NewService<SupergraphRequest> for RouterCreator {
@@ -392,16 +567,176 @@ impl SupergraphServiceFactory for RouterCreator {
         SupergraphRequest,
     >>::Future;
 
+    fn new_internal_service(&self) -> Option<supergraph::BoxService> {
+        Some(self.make_internal().boxed())
+    }
+
     fn web_endpoints(&self) -> MultiMap<ListenAddr, Endpoint> {
         let mut mm = MultiMap::new();
         self.plugins
             .values()
             .for_each(|p| mm.extend(p.web_endpoints()));
+
+        if let Some(telemetry) = self.telemetry() {
+            for endpoint in mm.iter_all().flat_map(|(_, endpoints)| endpoints) {
+                if let Some(metrics) =
+                    telemetry.buffer_metrics(&format!("endpoint.{}", endpoint.path))
+                {
+                    endpoint.set_buffer_metrics(metrics);
+                }
+            }
+        }
         mm
     }
+
+    fn record_client_disconnect(&self) {
+        self.defer_metrics.record_cancelled();
+    }
+
+    fn update_subgraph_urls(&self, configuration: &Configuration) -> bool {
+        let override_url = match self
+            .plugins
+            .iter()
+            .find(|i| i.0.as_str() == APOLLO_OVERRIDE_SUBGRAPH_URL)
+            .and_then(|plugin| plugin.1.as_any().downcast_ref::<OverrideSubgraphUrl>())
+        {
+            Some(override_url) => override_url,
+            None => return false,
+        };
+        match override_url.update(
+            configuration.subgraph_url_overrides(),
+            configuration.supergraph.subgraph_url_trailing_slash,
+        ) {
+            Ok(()) => true,
+            Err(err) => {
+                tracing::error!("invalid subgraph URL overrides: {}", err);
+                false
+            }
+        }
+    }
+
+    fn reload_plugins<'a>(
+        &'a self,
+        plugins: &'a [String],
+        previous_configuration: &'a Configuration,
+        configuration: &'a Configuration,
+    ) -> BoxFuture<'a, bool> {
+        Box::pin(async move {
+            let mut applied = Vec::new();
+            for name in plugins {
+                if self.reload_plugin(name, configuration).await {
+                    applied.push(name);
+                    continue;
+                }
+                // the router keeps these plugins until it is rebuilt, and keeps them if the
+                // rebuild fails: they must not serve requests with the rejected configuration
+                for name in applied {
+                    if !self.reload_plugin(name, previous_configuration).await {
+                        tracing::error!(
+                            "plugin {} could not restore its previous configuration",
+                            name
+                        );
+                    }
+                }
+                return false;
+            }
+            list_active_plugins(configuration, self.plugins.iter());
+            true
+        })
+    }
+
+    fn warm_up(&self, operations: Vec<WarmupOperation>) -> BoxFuture<'static, ()> {
+        let mut planning = self.query_planner_service.clone();
+        Box::pin(async move {
+            for operation in operations {
+                let name = operation.operation_name.clone().unwrap_or_default();
+                let result = planning
+                    .call(
+                        QueryPlannerRequest::builder()
+                            .query(operation.query)
+                            .and_operation_name(operation.operation_name)
+                            .context(Context::new())
+                            .build(),
+                    )
+                    .await;
+                match result {
+                    Ok(response) if response.errors.is_empty() => {}
+                    Ok(response) => tracing::warn!(
+                        "could not plan the warmup operation '{}': {:?}",
+                        name,
+                        response.errors
+                    ),
+                    Err(err) => {
+                        tracing::warn!("could not plan the warmup operation '{}': {}", name, err)
+                    }
+                }
+            }
+        })
+    }
 }
 
 impl RouterCreator {
+    fn telemetry(&self) -> Option<&Telemetry> {
+        self.plugins
+            .iter()
+            .find(|i| i.0.as_str() == "apollo.telemetry")
+            .and_then(|plugin| plugin.1.as_any().downcast_ref::<Telemetry>())
+    }
+
+    /// The metrics of an internal buffer, sent nowhere unless they are enabled
+    pub(crate) fn buffer_metrics(&self, buffer: &str) -> BufferMetrics {
+        self.telemetry()
+            .and_then(|telemetry| telemetry.buffer_metrics(buffer))
+            .unwrap_or_default()
+    }
+
+    /// Applies the configuration of a plugin through its reload hook.
+    /// Returns false if the plugin did not apply it
+    async fn reload_plugin(&self, name: &str, configuration: &Configuration) -> bool {
+        let (plugin, plugin_configuration) = match (
+            self.plugins.get(name),
+            configuration.plugin_configuration(name),
+        ) {
+            (Some(plugin), Some(plugin_configuration)) => (plugin, plugin_configuration),
+            _ => return false,
+        };
+        match plugin
+            .on_config_reload(&plugin_configuration, self.schema.as_string().clone())
+            .await
+        {
+            Ok(applied) => applied,
+            Err(err) => {
+                tracing::error!(
+                    "plugin {} could not apply its new configuration: {}",
+                    name,
+                    err
+                );
+                false
+            }
+        }
+    }
+
+    fn supergraph_service(
+        &self,
+        plugins: Arc<Plugins>,
+    ) -> SupergraphService<ExecutionCreator<SubgraphCreator>> {
+        SupergraphService::builder()
+            .query_planner_service(self.query_planner_service.clone())
+            .execution_service_factory(ExecutionCreator {
+                schema: self.schema.clone(),
+                plugins,
+                subgraph_creator: self.subgraph_creator.clone(),
+                defer_metrics: self.defer_metrics.clone(),
+                options: self.execution_options.clone(),
+            })
+            .schema(self.schema.clone())
+            .variable_coercion(self.variable_coercion)
+            .unknown_variables(self.unknown_variables)
+            .require_operation_name(self.require_operation_name)
+            .default_variables(self.default_variables.clone())
+            .build()
+    }
+
     pub(crate) fn make(
         &self,
     ) -> impl Service<
@@ -410,15 +745,32 @@ impl RouterCreator {
         Error = BoxError,
         Future = BoxFuture<'static, Result<SupergraphResponse, BoxError>>,
     > + Send {
-        let supergraph_service = SupergraphService::builder()
-            .query_planner_service(self.query_planner_service.clone())
-            .execution_service_factory(ExecutionCreator {
-                schema: self.schema.clone(),
-                plugins: self.plugins.clone(),
-                subgraph_creator: self.subgraph_creator.clone(),
-            })
-            .schema(self.schema.clone())
-            .build();
+        self.make_with_plugins(self.plugins.clone())
+    }
+
+    /// Creates the supergraph service with its layers but without the plugins, for the requests
+    /// of the router itself
+    fn make_internal(
+        &self,
+    ) -> impl Service<
+        SupergraphRequest,
+        Response = SupergraphResponse,
+        Error = BoxError,
+        Future = BoxFuture<'static, Result<SupergraphResponse, BoxError>>,
+    > + Send {
+        self.make_with_plugins(Default::default())
+    }
+
+    fn make_with_plugins(
+        &self,
+        plugins: Arc<Plugins>,
+    ) -> impl Service<
+        SupergraphRequest,
+        Response = SupergraphResponse,
+        Error = BoxError,
+        Future = BoxFuture<'static, Result<SupergraphResponse, BoxError>>,
+    > + Send {
+        let supergraph_service = self.supergraph_service(plugins.clone());
 
         let supergraph_service = match self
             .plugins
@@ -430,10 +782,20 @@ impl RouterCreator {
             None => Either::B(supergraph_service),
         };
 
+        let context_merges = self.context_merges.clone();
+
         ServiceBuilder::new()
+            .map_request(move |req: SupergraphRequest| {
+                req.context.set_merges(context_merges.clone());
+                req
+            })
+            .layer(self.catch_panic.clone())
             .layer(EnsureQueryPresence::default())
+            .layer(QuerySizeLimit::new(self.max_query_bytes))
+            .layer(VariablesLimit::new(self.max_variables))
+            .layer(ResponseSizeLimit::new(self.max_response_bytes))
             .service(
-                self.plugins
+                plugins
                     .iter()
                     .rev()
                     .fold(BoxService::new(supergraph_service), |acc, (_, e)| {
@@ -447,16 +809,17 @@ impl RouterCreator {
     pub(crate) fn test_service(
         &self,
     ) -> tower::util::BoxCloneService<SupergraphRequest, SupergraphResponse, BoxError> {
-        use tower::buffer::Buffer;
-
-        Buffer::new(self.make(), 512).boxed_clone()
+        MeasuredBuffer::new(self.make(), self.buffer_metrics("supergraph")).boxed_clone()
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use router_bridge::planner::UsageReporting;
+
     use super::*;
     use crate::plugin::test::MockSubgraph;
+    use crate::query_planner::USAGE_REPORTING;
     use crate::services::supergraph;
     use crate::test_harness::MockedSubgraphs;
     use crate::TestHarness;
@@ -539,6 +902,58 @@ mod tests {
         insta::assert_json_snapshot!(response);
     }
 
+    #[tokio::test]
+    async fn extra_subgraph_fields_are_stripped() {
+        for strip_unrequested_fields in [true, false] {
+            // the subgraph returns fields that the operation did not select
+            let subgraphs = MockedSubgraphs([
+            ("user", MockSubgraph::builder().with_json(
+                    serde_json::json!{{"query":"{currentUser{activeOrganization{__typename id}}}"}},
+                    serde_json::json!{{"data": {
+                        "currentUser": { "activeOrganization": null, "name": "Ada", "password": "hunter2" },
+                        "secret": "s3cr3t"
+                    }}}
+                ).build()),
+            ("orga", MockSubgraph::default())
+        ].into_iter().collect());
+
+            let service = TestHarness::builder()
+                .configuration_json(serde_json::json!({
+                    "include_subgraph_errors": { "all": true },
+                    "supergraph": { "strip_unrequested_fields": strip_unrequested_fields }
+                }))
+                .unwrap()
+                .schema(SCHEMA)
+                .extra_plugin(subgraphs)
+                .build()
+                .await
+                .unwrap();
+
+            let request = supergraph::Request::fake_builder()
+                .query("query { currentUser { activeOrganization { id creatorUser { name } } } }")
+                .build()
+                .unwrap();
+            let response = service
+                .oneshot(request)
+                .await
+                .unwrap()
+                .next_response()
+                .await
+                .unwrap();
+
+            assert!(response.errors.is_empty());
+            let expected = if strip_unrequested_fields {
+                serde_json_bytes::json!({ "currentUser": { "activeOrganization": null } })
+            } else {
+                serde_json_bytes::json!({
+                    "currentUser": { "activeOrganization": null, "name": "Ada", "password": "hunter2" },
+                    "secret": "s3cr3t"
+                })
+            };
+            assert_eq!(response.data, Some(expected));
+        }
+    }
+
     #[tokio::test]
     async fn errors_on_deferred_responses() {
         let subgraphs = MockedSubgraphs([
@@ -592,6 +1007,161 @@ mod tests {
         insta::assert_json_snapshot!(stream.next_response().await.unwrap());
     }
 
+    #[tokio::test]
+    async fn redacts_response_fields() {
+        let subgraphs = MockedSubgraphs([
+            ("user", MockSubgraph::builder().with_json(
+                    serde_json::json!{{"query":"{currentUser{id n:name}}"}},
+                    serde_json::json!{{"data": {"currentUser": { "id": "0", "n": "Ada" }}}}
+                ).build()),
+            ("orga", MockSubgraph::default())
+        ].into_iter().collect());
+
+        let service = TestHarness::builder()
+            .configuration_json(serde_json::json!({
+                "redaction": {
+                    "fields": {
+                        "currentUser/id": { "mask": "***" },
+                        "currentUser/name": "nullify"
+                    }
+                }
+            }))
+            .unwrap()
+            .schema(SCHEMA)
+            .extra_plugin(subgraphs)
+            .build()
+            .await
+            .unwrap();
+
+        // the paths are made of field names, so an alias does not bypass the redaction
+        let request = supergraph::Request::fake_builder()
+            .query("query { currentUser { id n: name } }")
+            .build()
+            .unwrap();
+
+        let response = service
+            .oneshot(request)
+            .await
+            .unwrap()
+            .next_response()
+            .await
+            .unwrap();
+        assert!(response.errors.is_empty());
+        assert_eq!(
+            response.data,
+            Some(serde_json_bytes::json!({
+                "currentUser": { "id": "***", "n": null }
+            }))
+        );
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_redactions() {
+        for fields in [
+            // null would break the non-null constraint
+            serde_json::json!({ "currentUser/id": "nullify" }),
+            // only strings and IDs can be replaced with a string
+            serde_json::json!({ "currentUser/activeOrganization": "hash" }),
+            serde_json::json!({ "currentUser/activeOrganization/suborga": { "mask": "***" } }),
+            serde_json::json!({ "currentUser/unknown": "hash" }),
+        ] {
+            let result = TestHarness::builder()
+                .configuration_json(serde_json::json!({ "redaction": { "fields": fields } }))
+                .unwrap()
+                .schema(SCHEMA)
+                .build()
+                .await;
+            assert!(result.is_err(), "{} should be rejected", fields);
+        }
+    }
+
+    #[tokio::test]
+    async fn rejects_invalid_fallback_values() {
+        for fields in [
+            serde_json::json!({ "currentUser/id": "0" }),
+            serde_json::json!({ "currentUser/name": 1 }),
+            serde_json::json!({ "currentUser/activeOrganization": { "unknown": "field" } }),
+        ] {
+            let result = TestHarness::builder()
+                .configuration_json(serde_json::json!({ "fallbacks": { "fields": fields } }))
+                .unwrap()
+                .schema(SCHEMA)
+                .build()
+                .await;
+            assert!(result.is_err(), "{} should be rejected", fields);
+        }
+
+        assert!(TestHarness::builder()
+            .configuration_json(serde_json::json!({
+                "fallbacks": {
+                    "fields": {
+                        "currentUser/name": "anonymous",
+                        "currentUser/activeOrganization": { "id": "0" }
+                    }
+                }
+            }))
+            .unwrap()
+            .schema(SCHEMA)
+            .build()
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn deferred_responses_over_the_size_limit() {
+        let subgraphs = MockedSubgraphs([
+        ("user", MockSubgraph::builder().with_json(
+                serde_json::json!{{"query":"{currentUser{__typename id}}"}},
+                serde_json::json!{{"data": {"currentUser": { "__typename": "User", "id": "0" }}}}
+            )
+            .with_json(
+                serde_json::json!{{
+                    "query":"query($representations:[_Any!]!){_entities(representations:$representations){...on User{name}}}",
+                    "variables": {
+                        "representations":[{"__typename": "User", "id":"0"}]
+                    }
+                }},
+                serde_json::json!{{
+                    "data": {
+                        "_entities": [{ "name": "A".repeat(500) }]
+                    }
+                }}
+            ).build()),
+        ("orga", MockSubgraph::default())
+    ].into_iter().collect());
+
+        let service = TestHarness::builder()
+            .configuration_json(serde_json::json!({"limits": { "max_response_bytes": 200 } }))
+            .unwrap()
+            .schema(SCHEMA)
+            .extra_plugin(subgraphs)
+            .build()
+            .await
+            .unwrap();
+
+        let request = supergraph::Request::fake_builder()
+            .header("Accept", "multipart/mixed; deferSpec=20220824")
+            .query("query { currentUser { id  ...@defer { name } } }")
+            .build()
+            .unwrap();
+
+        let mut stream = service.oneshot(request).await.unwrap();
+
+        let primary = stream.next_response().await.unwrap();
+        assert!(primary.errors.is_empty());
+        assert_eq!(primary.has_next, Some(true));
+
+        // the deferred response exceeds the limit
+        let last = stream.next_response().await.unwrap();
+        assert_eq!(
+            last.errors[0].message,
+            "the response exceeded the limit of 200 bytes"
+        );
+        assert!(last.incremental.is_empty());
+        assert_eq!(last.has_next, Some(false));
+        assert!(stream.next_response().await.is_none());
+    }
+
     #[tokio::test]
     async fn errors_on_incremental_responses() {
         let subgraphs = MockedSubgraphs([
@@ -673,4 +1243,297 @@ mod tests {
 
         insta::assert_json_snapshot!(stream.next_response().await.unwrap());
     }
+
+    #[tokio::test]
+    async fn require_operation_name() {
+        let subgraphs = MockedSubgraphs([
+        ("user", MockSubgraph::builder().with_json(
+                serde_json::json!{{"query":"query CurrentUser__user__0{currentUser{id}}","operationName":"CurrentUser__user__0"}},
+                serde_json::json!{{"data": {"currentUser": { "id": "0" }}}}
+            ).build()),
+        ("orga", MockSubgraph::default())
+    ].into_iter().collect());
+
+        let service = TestHarness::builder()
+            .configuration_json(
+                serde_json::json!({"supergraph": { "require_operation_name": true } }),
+            )
+            .unwrap()
+            .schema(SCHEMA)
+            .extra_plugin(subgraphs)
+            .build()
+            .await
+            .unwrap();
+
+        let request = supergraph::Request::fake_builder()
+            .query("query { currentUser { id } }")
+            .build()
+            .unwrap();
+        let mut response = service.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.response.status(), StatusCode::BAD_REQUEST);
+        let response = response.next_response().await.unwrap();
+        assert_eq!(
+            response.errors[0].extensions.get("code"),
+            Some(&serde_json_bytes::Value::from("OPERATION_NAME_REQUIRED"))
+        );
+
+        let request = supergraph::Request::fake_builder()
+            .query("query CurrentUser { currentUser { id } }")
+            .build()
+            .unwrap();
+        let mut response = service.oneshot(request).await.unwrap();
+        assert_eq!(response.response.status(), StatusCode::OK);
+        let response = response.next_response().await.unwrap();
+        assert!(response.errors.is_empty());
+        assert_eq!(
+            response.data,
+            Some(serde_json_bytes::json!({ "currentUser": { "id": "0" } }))
+        );
+    }
+
+    #[tokio::test]
+    async fn query_planning_timeout() {
+        let subgraphs = MockedSubgraphs(
+            [
+                ("user", MockSubgraph::default()),
+                ("orga", MockSubgraph::default()),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        // the planner answers asynchronously, so it can never make it in a nanosecond
+        let service = TestHarness::builder()
+            .configuration_json(serde_json::json!({"limits": { "query_planning_timeout": "1ns" } }))
+            .unwrap()
+            .schema(SCHEMA)
+            .extra_plugin(subgraphs)
+            .build()
+            .await
+            .unwrap();
+
+        let request = supergraph::Request::fake_builder()
+            .query("query { currentUser { id } }")
+            .build()
+            .unwrap();
+        let mut response = service.oneshot(request).await.unwrap();
+        assert_eq!(response.response.status(), StatusCode::GATEWAY_TIMEOUT);
+        let response = response.next_response().await.unwrap();
+        assert_eq!(response.data, None);
+        assert_eq!(response.errors.len(), 1);
+        assert_eq!(
+            response.errors[0].extensions.get("code"),
+            Some(&serde_json_bytes::Value::from("QUERY_PLANNING_TIMEOUT"))
+        );
+    }
+
+    #[tokio::test]
+    async fn subscriptions_are_rejected() {
+        let subgraphs = MockedSubgraphs(
+            [
+                ("user", MockSubgraph::default()),
+                ("orga", MockSubgraph::default()),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        let service = TestHarness::builder()
+            .configuration_json(serde_json::json!({}))
+            .unwrap()
+            .schema(SCHEMA)
+            .extra_plugin(subgraphs)
+            .build()
+            .await
+            .unwrap();
+
+        let request = supergraph::Request::fake_builder()
+            .query("subscription { currentUser { id } }")
+            .build()
+            .unwrap();
+        let mut response = service.oneshot(request).await.unwrap();
+        assert_eq!(response.response.status(), StatusCode::BAD_REQUEST);
+        let response = response.next_response().await.unwrap();
+        assert_eq!(response.data, None);
+        assert_eq!(response.errors.len(), 1);
+        assert_eq!(
+            response.errors[0].message,
+            "subscriptions are not supported"
+        );
+        assert_eq!(
+            response.errors[0].extensions.get("code"),
+            Some(&serde_json_bytes::Value::from("SUBSCRIPTION_NOT_SUPPORTED"))
+        );
+    }
+
+    #[tokio::test]
+    async fn all_fields_skipped() {
+        let subgraphs = MockedSubgraphs(
+            [
+                ("user", MockSubgraph::default()),
+                ("orga", MockSubgraph::default()),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        let service = TestHarness::builder()
+            .configuration_json(serde_json::json!({"include_subgraph_errors": { "all": true } }))
+            .unwrap()
+            .schema(SCHEMA)
+            .extra_plugin(subgraphs)
+            .build()
+            .await
+            .unwrap();
+
+        let request = supergraph::Request::fake_builder()
+            .query("query { currentUser @skip(if: true) { id } }")
+            .build()
+            .unwrap();
+        let mut response = service.oneshot(request).await.unwrap();
+        assert_eq!(response.response.status(), StatusCode::OK);
+        // the operation is still reported
+        assert!(response
+            .context
+            .get::<_, UsageReporting>(USAGE_REPORTING)
+            .unwrap()
+            .is_some());
+        let response = response.next_response().await.unwrap();
+        assert!(response.errors.is_empty());
+        assert_eq!(response.data, Some(serde_json_bytes::json!({})));
+    }
+
+    #[tokio::test]
+    async fn all_deferred_fields_skipped() {
+        let subgraphs = MockedSubgraphs(
+            [
+                ("user", MockSubgraph::default()),
+                ("orga", MockSubgraph::default()),
+            ]
+            .into_iter()
+            .collect(),
+        );
+        let service = TestHarness::builder()
+            .configuration_json(serde_json::json!({"include_subgraph_errors": { "all": true } }))
+            .unwrap()
+            .schema(SCHEMA)
+            .extra_plugin(subgraphs)
+            .build()
+            .await
+            .unwrap();
+
+        let request = supergraph::Request::fake_builder()
+            .header("Accept", "multipart/mixed; deferSpec=20220824")
+            .query("query { ... @defer { currentUser @skip(if: true) { id } } }")
+            .build()
+            .unwrap();
+        let mut stream = service.oneshot(request).await.unwrap();
+        assert_eq!(stream.response.status(), StatusCode::OK);
+
+        let response = stream.next_response().await.unwrap();
+        assert!(response.errors.is_empty());
+        assert_eq!(response.data, Some(serde_json_bytes::json!({})));
+
+        while let Some(response) = stream.next_response().await {
+            assert!(response.errors.is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn default_variables() {
+        let variables = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let recorded = variables.clone();
+        let service = TestHarness::builder()
+            .configuration_json(serde_json::json!({"supergraph": {
+                "default_variables": { "withName": true, "locale": "en" }
+            }}))
+            .unwrap()
+            .schema(SCHEMA)
+            .execution_hook(move |service| {
+                let recorded = recorded.clone();
+                service
+                    .map_request(move |req: ExecutionRequest| {
+                        recorded
+                            .lock()
+                            .unwrap()
+                            .push(req.supergraph_request.body().variables.clone());
+                        req
+                    })
+                    .boxed()
+            })
+            .build()
+            .await
+            .unwrap();
+
+        // the client omits the variable: the default value is used
+        let request = supergraph::Request::fake_builder()
+            .query("query($withName: Boolean!) { currentUser { id name @include(if: $withName) } }")
+            .build()
+            .unwrap();
+        let response = service.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.response.status(), StatusCode::OK);
+
+        // the value sent by the client takes precedence
+        let request = supergraph::Request::fake_builder()
+            .query("query($withName: Boolean!) { currentUser { id name @include(if: $withName) } }")
+            .variable("withName", false)
+            .build()
+            .unwrap();
+        let response = service.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.response.status(), StatusCode::OK);
+
+        // the default value declared by the operation takes precedence
+        let request = supergraph::Request::fake_builder()
+            .query("query($withName: Boolean! = false) { currentUser { id name @include(if: $withName) } }")
+            .build()
+            .unwrap();
+        let response = service.oneshot(request).await.unwrap();
+        assert_eq!(response.response.status(), StatusCode::OK);
+
+        // the operation does not declare `locale`, which gets no default value
+        let variables = variables.lock().unwrap();
+        assert_eq!(
+            variables[0],
+            serde_json_bytes::json!({ "withName": true })
+                .as_object()
+                .unwrap()
+                .clone()
+        );
+        assert_eq!(
+            variables[1],
+            serde_json_bytes::json!({ "withName": false })
+                .as_object()
+                .unwrap()
+                .clone()
+        );
+        assert!(variables[2].is_empty());
+    }
+
+    #[tokio::test]
+    async fn default_variables_with_unknown_variables_rejected() {
+        let service = TestHarness::builder()
+            .configuration_json(serde_json::json!({"supergraph": {
+                "default_variables": { "withName": true, "locale": "en" },
+                "unknown_variables": "reject"
+            }}))
+            .unwrap()
+            .schema(SCHEMA)
+            .build()
+            .await
+            .unwrap();
+
+        // the undeclared default variable is not rejected
+        let request = supergraph::Request::fake_builder()
+            .query("query($withName: Boolean!) { currentUser { id name @include(if: $withName) } }")
+            .build()
+            .unwrap();
+        let response = service.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.response.status(), StatusCode::OK);
+
+        // a variable sent by the client is still rejected
+        let request = supergraph::Request::fake_builder()
+            .query("query($withName: Boolean!) { currentUser { id name @include(if: $withName) } }")
+            .variable("locale", "fr")
+            .build()
+            .unwrap();
+        let response = service.oneshot(request).await.unwrap();
+        assert_eq!(response.response.status(), StatusCode::BAD_REQUEST);
+    }
 }
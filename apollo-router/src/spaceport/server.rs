@@ -1,73 +1,85 @@
 // This entire file is license key functionality
+use std::collections::VecDeque;
 use std::io::Write;
 use std::net::SocketAddr;
 
 use bytes::BytesMut;
 use flate2::write::GzEncoder;
 use flate2::Compression;
+use opentelemetry::KeyValue;
 use prost::Message;
+use reqwest::header::HeaderMap;
 use reqwest::header::CONTENT_TYPE;
 use reqwest::Client;
 use tokio::net::TcpListener;
 use tokio::sync::mpsc::error::TrySendError;
+use tokio::sync::mpsc::Receiver;
 use tokio::sync::mpsc::Sender;
 use tokio::time::Duration;
+use tokio::time::Instant;
 use tokio_stream::wrappers::TcpListenerStream;
 use tonic::transport::Error;
 use tonic::transport::Server;
+use tonic::Code;
 use tonic::Request;
 use tonic::Response;
 use tonic::Status;
+use url::Url;
 
+use crate::plugins::telemetry::apollo::ReportUploads;
+use crate::plugins::telemetry::metrics::AggregateCounter;
+use crate::plugins::telemetry::metrics::AggregateMeterProvider;
 use crate::spaceport::agent::reporter_server::Reporter;
 use crate::spaceport::agent::reporter_server::ReporterServer;
 use crate::spaceport::agent::ReporterRequest;
 use crate::spaceport::agent::ReporterResponse;
 use crate::spaceport::report::Report;
 
-static DEFAULT_APOLLO_USAGE_REPORTING_INGRESS_URL: &str =
-    "https://usage-reporting.api.apollographql.com/api/ingress/traces";
-const BACKOFF_INCREMENT: Duration = Duration::from_millis(50);
-
 /// Accept Traces and Stats from clients and transfer to an Apollo Ingress
 pub(crate) struct ReportSpaceport {
     listener: Option<TcpListener>,
     addr: SocketAddr,
     tx: Sender<ReporterRequest>,
+    uploader: Option<Uploader>,
+}
+
+/// Transfers the reports to the ingress, in order, buffering them while the ingress is unreachable
+struct Uploader {
+    rx: Receiver<ReporterRequest>,
+    ingress: Url,
+    headers: HeaderMap,
+    config: ReportUploads,
 }
 
 impl ReportSpaceport {
     /// Create a new ReportSpaceport which is configured to serve requests at the
     /// supplied address
     ///
-    /// The spaceport will transfer reports to the Apollo Ingress.
+    /// The spaceport will transfer reports to the ingress, with the supplied headers.
     ///
-    /// The spaceport will attempt to make the transfer 5 times before failing. If
-    /// the spaceport fails, the data is discarded.
-    pub(crate) async fn new(addr: SocketAddr) -> Result<Self, std::io::Error> {
+    /// The uploads failing with a server or network error are retried with an exponential
+    /// backoff. The reports received meanwhile are buffered, and the oldest ones are discarded
+    /// when the buffer is full.
+    pub(crate) async fn new(
+        addr: SocketAddr,
+        ingress: Url,
+        headers: HeaderMap,
+        config: ReportUploads,
+    ) -> Result<Self, std::io::Error> {
         let listener = TcpListener::bind(addr).await?;
         let addr = listener.local_addr()?;
 
-        // Spawn a task which will transmit reports
-        let (tx, mut rx) = tokio::sync::mpsc::channel::<ReporterRequest>(1024);
-
-        tokio::task::spawn(async move {
-            let client = Client::new();
-            while let Some(report) = rx.recv().await {
-                if let Some(report_to_send) = report.report {
-                    match ReportSpaceport::submit_report(&client, report.apollo_key, report_to_send)
-                        .await
-                    {
-                        Ok(v) => tracing::debug!("report submission succeeded: {:?}", v),
-                        Err(e) => tracing::error!("report submission failed: {}", e),
-                    }
-                }
-            }
-        });
+        let (tx, rx) = tokio::sync::mpsc::channel::<ReporterRequest>(1024);
         Ok(Self {
             listener: Some(listener),
             addr,
             tx,
+            uploader: Some(Uploader {
+                rx,
+                ingress,
+                headers,
+                config,
+            }),
         })
     }
 
@@ -75,22 +87,126 @@ impl ReportSpaceport {
         &self.addr
     }
 
-    /// Start serving requests.
-    pub(crate) async fn serve(mut self) -> Result<(), Error> {
+    /// Start serving requests, and transferring the reports to the ingress.
+    pub(crate) async fn serve(
+        mut self,
+        meter_provider: AggregateMeterProvider,
+    ) -> Result<(), Error> {
         let listener = self
             .listener
             .take()
             .expect("should have allocated listener");
+        let uploader = self
+            .uploader
+            .take()
+            .expect("should have created the uploader");
+        let dropped_reports = meter_provider
+            .meter("apollo/router", None)
+            .build_counter(|m| {
+                m.u64_counter("apollo_router_studio_reports_dropped_total")
+                    .with_description(
+                        "Number of usage reports discarded without reaching the ingress.",
+                    )
+                    .init()
+            });
+        // Spawn a task which will transmit reports
+        tokio::task::spawn(uploader.run(dropped_reports));
         Server::builder()
             .add_service(ReporterServer::new(self))
             .serve_with_incoming(TcpListenerStream::new(listener))
             .await
     }
+}
 
+impl Uploader {
+    async fn run(mut self, dropped_reports: AggregateCounter<u64>) {
+        let client = Client::new();
+        let mut buffer = VecDeque::new();
+        let mut backoff = None;
+        let mut retry_at = Instant::now();
+        loop {
+            // wait for a report, or for the next attempt at the oldest one
+            if buffer.is_empty() || Instant::now() < retry_at {
+                tokio::select! {
+                    received = self.rx.recv() => match received {
+                        Some(report) => buffer.push_back(report),
+                        // the spaceport stopped
+                        None => break,
+                    },
+                    _ = tokio::time::sleep_until(retry_at), if !buffer.is_empty() => {}
+                }
+            }
+            while let Ok(report) = self.rx.try_recv() {
+                buffer.push_back(report);
+            }
+            while buffer.len() > self.config.buffer_size.get() {
+                buffer.pop_front();
+                tracing::warn!("the usage report buffer is full, the oldest report is discarded");
+                dropped_reports.add(1, &[KeyValue::new("reason", "buffer_full")]);
+            }
+            if Instant::now() < retry_at {
+                continue;
+            }
+
+            let (key, report) = match buffer.front() {
+                Some(ReporterRequest {
+                    apollo_key,
+                    report: Some(report),
+                }) => (apollo_key, report),
+                _ => {
+                    buffer.pop_front();
+                    continue;
+                }
+            };
+            match ReportSpaceport::submit_report(
+                &client,
+                &self.ingress,
+                &self.headers,
+                self.config.compress,
+                key,
+                report,
+            )
+            .await
+            {
+                Ok(v) => {
+                    tracing::debug!("report submission succeeded: {:?}", v);
+                    buffer.pop_front();
+                    backoff = None;
+                }
+                // the ingress or the network may be back later
+                Err(e) if e.code() == Code::Unavailable => {
+                    let delay = backoff
+                        .map(|delay: Duration| (delay * 2).min(self.config.max_backoff))
+                        .unwrap_or(self.config.min_backoff);
+                    tracing::warn!(
+                        "report submission failed, retrying in {:?}: {}",
+                        delay,
+                        e.message()
+                    );
+                    backoff = Some(delay);
+                    retry_at = Instant::now() + delay;
+                }
+                Err(e) => {
+                    tracing::error!("report submission failed: {}", e);
+                    buffer.pop_front();
+                    backoff = None;
+                    dropped_reports.add(1, &[KeyValue::new("reason", "rejected")]);
+                }
+            }
+        }
+    }
+}
+
+impl ReportSpaceport {
+    /// Makes one attempt at transferring a report. The errors with the `Unavailable` code may be
+    /// transient
     async fn submit_report(
         client: &Client,
-        key: String,
-        report: Report,
+        ingress: &Url,
+        headers: &HeaderMap,
+        compress: bool,
+        key: &str,
+        report: &Report,
     ) -> Result<Response<ReporterResponse>, Status> {
         tracing::debug!("submitting report: {:?}", report);
         // Protobuf encode message
@@ -98,26 +214,27 @@ impl ReportSpaceport {
         report
             .encode(&mut content)
             .map_err(|e| Status::invalid_argument(e.to_string()))?;
-        // Create a gzip encoder
-        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
-        // Write our content to our encoder
-        encoder
-            .write_all(&content)
-            .map_err(|e| Status::internal(e.to_string()))?;
-        // Finish encoding and retrieve content
-        let compressed_content = encoder
-            .finish()
-            .map_err(|e| Status::internal(e.to_string()))?;
-        let mut backoff = Duration::from_millis(0);
-        let ingress = match std::env::var("APOLLO_USAGE_REPORTING_INGRESS_URL") {
-            Ok(v) => v,
-            Err(_e) => DEFAULT_APOLLO_USAGE_REPORTING_INGRESS_URL.to_string(),
-        };
-        let req = client
-            .post(ingress)
-            .body(compressed_content)
+        let mut request = client.post(ingress.clone());
+        if compress {
+            // Create a gzip encoder
+            let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+            // Write our content to our encoder
+            encoder
+                .write_all(&content)
+                .map_err(|e| Status::internal(e.to_string()))?;
+            // Finish encoding and retrieve content
+            let compressed_content = encoder
+                .finish()
+                .map_err(|e| Status::internal(e.to_string()))?;
+            request = request
+                .body(compressed_content)
+                .header("Content-Encoding", "gzip");
+        } else {
+            request = request.body(content.freeze());
+        }
+        let req = request
+            .headers(headers.clone())
             .header("X-Api-Key", key)
-            .header("Content-Encoding", "gzip")
             .header(CONTENT_TYPE, "application/protobuf")
             .header("Accept", "application/json")
             .header(
@@ -129,49 +246,37 @@ impl ReportSpaceport {
                 ),
             )
             .build()
-            .map_err(|e| Status::unavailable(e.to_string()))?;
+            .map_err(|e| Status::internal(e.to_string()))?;
 
-        let mut msg = "default error message".to_string();
-        for i in 0..5 {
-            // We know these requests can be cloned
-            let task_req = req.try_clone().expect("requests must be clone-able");
-            match client.execute(task_req).await {
-                Ok(v) => {
-                    let status = v.status();
-                    let data = v
-                        .text()
-                        .await
-                        .map_err(|e| Status::internal(e.to_string()))?;
-                    // Handle various kinds of status:
-                    //  - if client error, terminate immediately
-                    //  - if server error, it may be transient so treat as retry-able
-                    //  - if ok, return ok
-                    if status.is_client_error() {
-                        tracing::error!("client error reported at ingress: {}", data);
-                        return Err(Status::invalid_argument(data));
-                    } else if status.is_server_error() {
-                        tracing::warn!("attempt: {}, could not transfer: {}", i + 1, data);
-                        msg = data;
-                    } else {
-                        tracing::debug!("ingress response text: {:?}", data);
-                        let response = ReporterResponse {
-                            message: "Report accepted".to_string(),
-                        };
-                        return Ok(Response::new(response));
-                    }
-                }
-                Err(e) => {
-                    // TODO: Ultimately need more sophisticated handling here. For example
-                    // a redirect should not be treated the same way as a connect or a
-                    // type builder error...
-                    tracing::warn!("attempt: {}, could not transfer: {}", i + 1, e);
-                    msg = e.to_string();
+        match client.execute(req).await {
+            Ok(v) => {
+                let status = v.status();
+                let data = v
+                    .text()
+                    .await
+                    .map_err(|e| Status::unavailable(e.to_string()))?;
+                // Handle various kinds of status:
+                //  - if client error, terminate immediately
+                //  - if server error, it may be transient so treat as retry-able
+                //  - if ok, return ok
+                if status.is_client_error() {
+                    tracing::error!("client error reported at ingress: {}", data);
+                    Err(Status::invalid_argument(data))
+                } else if status.is_server_error() {
+                    Err(Status::unavailable(data))
+                } else {
+                    tracing::debug!("ingress response text: {:?}", data);
+                    let response = ReporterResponse {
+                        message: "Report accepted".to_string(),
+                    };
+                    Ok(Response::new(response))
                 }
             }
-            backoff += BACKOFF_INCREMENT;
-            tokio::time::sleep(backoff).await;
+            // TODO: Ultimately need more sophisticated handling here. For example
+            // a redirect should not be treated the same way as a connect or a
+            // type builder error...
+            Err(e) => Err(Status::unavailable(e.to_string())),
         }
-        Err(Status::unavailable(msg))
     }
 }
 
@@ -204,3 +309,185 @@ impl ReportSpaceport {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::io::Read;
+    use std::sync::Arc;
+    use std::sync::Mutex;
+
+    use http::StatusCode;
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::spaceport::Reporter as ReporterClient;
+
+    /// An ingress answering with the statuses, in order, then accepting the uploads. Records the
+    /// path, the headers, the body and the response status of the uploads
+    fn mock_ingress(
+        statuses: Vec<StatusCode>,
+    ) -> (
+        Url,
+        mpsc::UnboundedReceiver<(String, http::HeaderMap, bytes::Bytes, StatusCode)>,
+    ) {
+        let (uploads, received) = mpsc::unbounded_channel();
+        let statuses = Arc::new(Mutex::new(VecDeque::from(statuses)));
+        let make_service = hyper::service::make_service_fn(move |_| {
+            let uploads = uploads.clone();
+            let statuses = statuses.clone();
+            async move {
+                Ok::<_, std::convert::Infallible>(hyper::service::service_fn(
+                    move |request: http::Request<hyper::Body>| {
+                        let uploads = uploads.clone();
+                        let status = statuses
+                            .lock()
+                            .unwrap()
+                            .pop_front()
+                            .unwrap_or(StatusCode::OK);
+                        async move {
+                            let path = request.uri().path().to_string();
+                            let headers = request.headers().clone();
+                            let body = hyper::body::to_bytes(request.into_body()).await.unwrap();
+                            let _ = uploads.send((path, headers, body, status));
+                            Ok::<_, std::convert::Infallible>(
+                                http::Response::builder()
+                                    .status(status)
+                                    .body(hyper::Body::empty())
+                                    .unwrap(),
+                            )
+                        }
+                    },
+                ))
+            }
+        });
+        let server = hyper::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(make_service);
+        let ingress =
+            Url::parse(&format!("http://{}/custom/ingress", server.local_addr())).unwrap();
+        tokio::spawn(server);
+        (ingress, received)
+    }
+
+    async fn submit_to_spaceport(
+        ingress: Url,
+        headers: HeaderMap,
+        config: ReportUploads,
+        report: Report,
+    ) {
+        let spaceport =
+            ReportSpaceport::new("127.0.0.1:0".parse().unwrap(), ingress, headers, config)
+                .await
+                .unwrap();
+        let address = *spaceport.address();
+        tokio::spawn(spaceport.serve(AggregateMeterProvider::default()));
+
+        let mut reporter = ReporterClient::try_new(format!("http://{}", address))
+            .await
+            .unwrap();
+        reporter
+            .submit(ReporterRequest {
+                apollo_key: "key".to_string(),
+                report: Some(report),
+            })
+            .await
+            .unwrap();
+    }
+
+    #[tokio::test]
+    async fn it_posts_reports_to_the_ingress() {
+        let (ingress, mut received) = mock_ingress(Vec::new());
+        let mut headers = HeaderMap::new();
+        headers.insert("x-proxy-token", "secret".parse().unwrap());
+        submit_to_spaceport(
+            ingress,
+            headers,
+            ReportUploads::default(),
+            Report::default(),
+        )
+        .await;
+
+        let (path, headers, _, _) = tokio::time::timeout(Duration::from_secs(5), received.recv())
+            .await
+            .expect("the report was not posted to the ingress")
+            .unwrap();
+        assert_eq!(path, "/custom/ingress");
+        assert_eq!(headers["x-proxy-token"], "secret");
+        assert_eq!(headers["x-api-key"], "key");
+    }
+
+    #[tokio::test]
+    async fn it_retries_the_uploads_after_transient_failures() {
+        let (ingress, mut received) = mock_ingress(vec![
+            StatusCode::SERVICE_UNAVAILABLE,
+            StatusCode::BAD_GATEWAY,
+        ]);
+        let config = ReportUploads {
+            min_backoff: Duration::from_millis(10),
+            ..Default::default()
+        };
+        submit_to_spaceport(ingress, HeaderMap::new(), config, Report::default()).await;
+
+        let mut attempts = Vec::new();
+        while attempts.len() < 3 {
+            let attempt = tokio::time::timeout(Duration::from_secs(5), received.recv())
+                .await
+                .expect("the upload was not retried")
+                .unwrap();
+            attempts.push(attempt);
+        }
+        let statuses: Vec<_> = attempts.iter().map(|(_, _, _, status)| *status).collect();
+        assert_eq!(
+            statuses,
+            [
+                StatusCode::SERVICE_UNAVAILABLE,
+                StatusCode::BAD_GATEWAY,
+                StatusCode::OK
+            ]
+        );
+        // the same report is sent again
+        assert!(attempts
+            .iter()
+            .all(|(_, _, body, _)| body == &attempts[0].2));
+
+        // the report is not sent again once it is delivered
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(received.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn it_compresses_the_uploads() {
+        let report = Report {
+            operation_count: 7,
+            ..Default::default()
+        };
+        let (ingress, mut received) = mock_ingress(Vec::new());
+        submit_to_spaceport(
+            ingress.clone(),
+            HeaderMap::new(),
+            ReportUploads::default(),
+            report.clone(),
+        )
+        .await;
+        let (_, headers, body, _) = tokio::time::timeout(Duration::from_secs(5), received.recv())
+            .await
+            .expect("the report was not posted to the ingress")
+            .unwrap();
+        assert_eq!(headers["content-encoding"], "gzip");
+        let mut decompressed = Vec::new();
+        flate2::read::GzDecoder::new(&body[..])
+            .read_to_end(&mut decompressed)
+            .unwrap();
+        assert_eq!(Report::decode(&decompressed[..]).unwrap(), report);
+
+        let config = ReportUploads {
+            compress: false,
+            ..Default::default()
+        };
+        submit_to_spaceport(ingress, HeaderMap::new(), config, report.clone()).await;
+        let (_, headers, body, _) = tokio::time::timeout(Duration::from_secs(5), received.recv())
+            .await
+            .expect("the report was not posted to the ingress")
+            .unwrap();
+        assert!(!headers.contains_key("content-encoding"));
+        assert_eq!(Report::decode(body).unwrap(), report);
+    }
+}
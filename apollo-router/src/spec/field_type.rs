@@ -2,8 +2,10 @@ use apollo_parser::ast;
 use serde::Deserialize;
 use serde::Serialize;
 
+use crate::configuration::VariableCoercion;
 use crate::json_ext::Value;
 use crate::json_ext::ValueExt;
+use crate::spec::query::TYPENAME;
 use crate::*;
 
 #[derive(Debug)]
@@ -50,11 +52,14 @@ impl std::fmt::Display for FieldType {
 impl FieldType {
     // This function validates input values according to the graphql specification.
     // Each of the values are validated against the "input coercion" rules.
+    // With `VariableCoercion::Strict`, values that would need an implicit coercion are rejected.
     pub(crate) fn validate_input_value(
         &self,
         value: &Value,
         schema: &Schema,
+        coercion: VariableCoercion,
     ) -> Result<(), InvalidValue> {
+        let strict = coercion == VariableCoercion::Strict;
         match (self, value) {
             (FieldType::String, Value::String(_)) => Ok(()),
             // Spec: https://spec.graphql.org/June2018/#sec-Int
@@ -66,6 +71,7 @@ impl FieldType {
                 }
             }
             // Spec: https://spec.graphql.org/draft/#sec-Float.Input-Coercion
+            (FieldType::Float, Value::Number(n)) if strict && !n.is_f64() => Err(InvalidValue),
             (FieldType::Float, maybe_float) => {
                 if maybe_float == &Value::Null || maybe_float.is_valid_float_input() {
                     Ok(())
@@ -80,6 +86,7 @@ impl FieldType {
             //
             // In practice it seems Int works too
             (FieldType::Id, Value::String(_)) => Ok(()),
+            (FieldType::Id, Value::Number(_)) if strict => Err(InvalidValue),
             (FieldType::Id, maybe_int) => {
                 if maybe_int == &Value::Null || maybe_int.is_valid_int_input() {
                     Ok(())
@@ -90,16 +97,20 @@ impl FieldType {
             (FieldType::Boolean, Value::Bool(_)) => Ok(()),
             (FieldType::List(inner_ty), Value::Array(vec)) => vec
                 .iter()
-                .try_for_each(|x| inner_ty.validate_input_value(x, schema)),
+                .try_for_each(|x| inner_ty.validate_input_value(x, schema, coercion)),
             // For coercion from single value to list
             (FieldType::List(inner_ty), val) if val != &Value::Null => {
-                inner_ty.validate_input_value(val, schema)
+                if strict {
+                    Err(InvalidValue)
+                } else {
+                    inner_ty.validate_input_value(val, schema, coercion)
+                }
             }
             (FieldType::NonNull(inner_ty), value) => {
                 if value.is_null() {
                     Err(InvalidValue)
                 } else {
-                    inner_ty.validate_input_value(value, schema)
+                    inner_ty.validate_input_value(value, schema, coercion)
                 }
             }
             (FieldType::Named(name), _)
@@ -113,7 +124,7 @@ impl FieldType {
                 if let Some(value) = value.as_object() {
                     if let Some(object_ty) = schema.input_types.get(name) {
                         object_ty
-                            .validate_object(value, schema)
+                            .validate_object(value, schema, coercion)
                             .map_err(|_| InvalidValue)
                     } else {
                         Err(InvalidValue)
@@ -126,6 +137,61 @@ impl FieldType {
         }
     }
 
+    /// Validates a value that the router returns for a field of this type, like a configured
+    /// fallback value: unlike input values, it is not coerced, and may be an object of an output
+    /// type.
+    pub(crate) fn validate_output_value(
+        &self,
+        value: &Value,
+        schema: &Schema,
+    ) -> Result<(), InvalidValue> {
+        match (self, value) {
+            (FieldType::NonNull(_), Value::Null) => Err(InvalidValue),
+            (FieldType::NonNull(inner_ty), value) => inner_ty.validate_output_value(value, schema),
+            (_, Value::Null) => Ok(()),
+            (FieldType::List(inner_ty), Value::Array(vec)) => vec
+                .iter()
+                .try_for_each(|x| inner_ty.validate_output_value(x, schema)),
+            (FieldType::String, Value::String(_)) => Ok(()),
+            (FieldType::Id, Value::String(_)) => Ok(()),
+            (FieldType::Int | FieldType::Id, value) if value.is_valid_int_input() => Ok(()),
+            (FieldType::Float, value) if value.is_valid_float_input() => Ok(()),
+            (FieldType::Boolean, Value::Bool(_)) => Ok(()),
+            (FieldType::Introspection(_), _) => Ok(()),
+            (FieldType::Named(name), _) if schema.custom_scalars.contains(name) => Ok(()),
+            (FieldType::Named(name), Value::String(value)) if schema.enums.contains_key(name) => {
+                if schema.enums[name].contains(value.as_str()) {
+                    Ok(())
+                } else {
+                    Err(InvalidValue)
+                }
+            }
+            (FieldType::Named(name), Value::Object(object)) => {
+                let field = |key: &str| {
+                    schema
+                        .object_types
+                        .get(name)
+                        .and_then(|ty| ty.field(key))
+                        .or_else(|| schema.interfaces.get(name).and_then(|ty| ty.field(key)))
+                };
+                // unions have no fields of their own, only their members can be checked
+                if !schema.object_types.contains_key(name) && !schema.interfaces.contains_key(name)
+                {
+                    return Ok(());
+                }
+                object.iter().try_for_each(|(key, value)| {
+                    if key.as_str() == TYPENAME {
+                        return Ok(());
+                    }
+                    field(key.as_str())
+                        .ok_or(InvalidValue)?
+                        .validate_output_value(value, schema)
+                })
+            }
+            _ => Err(InvalidValue),
+        }
+    }
+
     /// return the name of the type on which selections happen
     ///
     /// Example if we get the field `list: [User!]!`, it will return "User"
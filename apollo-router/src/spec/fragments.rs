@@ -123,6 +123,108 @@ impl Fragments {
     }
 }
 
+/// Rejects the fragments spreading themselves, and the fragment spreads nested deeper than
+/// `max_depth`
+///
+/// An operation spreading a fragment adds a level, so the fragments themselves can nest at most
+/// `max_depth - 1` spreads, whether the operations use them or not. Unknown fragments are left to
+/// the validation of the query planner.
+pub(crate) fn validate_fragment_spreads(
+    document: &ast::Document,
+    max_depth: Option<usize>,
+) -> Result<(), SpecError> {
+    let fragment_spreads: HashMap<String, Vec<String>> = document
+        .definitions()
+        .filter_map(|definition| match definition {
+            ast::Definition::FragmentDefinition(fragment_definition) => {
+                let name = fragment_definition.fragment_name()?.name()?;
+                let mut spreads = Vec::new();
+                if let Some(selection_set) = fragment_definition.selection_set() {
+                    collect_spreads(&selection_set, &mut spreads);
+                }
+                Some((name.text().to_string(), spreads))
+            }
+            _ => None,
+        })
+        .collect();
+
+    let mut depths = HashMap::new();
+    for name in fragment_spreads.keys() {
+        spread_depth(
+            name,
+            &fragment_spreads,
+            &mut depths,
+            &mut Vec::new(),
+            max_depth,
+        )?;
+    }
+    Ok(())
+}
+
+/// Names of the fragments spread in the selection set, including the nested selection sets
+fn collect_spreads(selection_set: &ast::SelectionSet, spreads: &mut Vec<String>) {
+    for selection in selection_set.selections() {
+        let selection_set = match selection {
+            ast::Selection::Field(field) => field.selection_set(),
+            ast::Selection::InlineFragment(inline_fragment) => inline_fragment.selection_set(),
+            ast::Selection::FragmentSpread(fragment_spread) => {
+                if let Some(name) = fragment_spread
+                    .fragment_name()
+                    .and_then(|fragment_name| fragment_name.name())
+                {
+                    spreads.push(name.text().to_string());
+                }
+                None
+            }
+        };
+        if let Some(selection_set) = selection_set {
+            collect_spreads(&selection_set, spreads);
+        }
+    }
+}
+
+/// Number of nested fragment spreads in the fragment
+///
+/// `path` holds the fragments being visited, a fragment found in it spreads itself.
+fn spread_depth(
+    name: &str,
+    fragment_spreads: &HashMap<String, Vec<String>>,
+    depths: &mut HashMap<String, usize>,
+    path: &mut Vec<String>,
+    max_depth: Option<usize>,
+) -> Result<usize, SpecError> {
+    if let Some(depth) = depths.get(name) {
+        return Ok(*depth);
+    }
+    if path.iter().any(|visited| visited == name) {
+        return Err(SpecError::CyclicFragment(name.to_string()));
+    }
+    // the first fragment of the path is already too deep, stop following the chain
+    if max_depth.map_or(false, |max_depth| path.len() >= max_depth) {
+        return Err(SpecError::FragmentDepthLimitExceeded(
+            max_depth.unwrap_or_default(),
+        ));
+    }
+    let spreads = match fragment_spreads.get(name) {
+        Some(spreads) => spreads,
+        None => return Ok(0),
+    };
+
+    path.push(name.to_string());
+    let mut depth = 0;
+    for spread in spreads {
+        depth = depth.max(spread_depth(spread, fragment_spreads, depths, path, max_depth)? + 1);
+    }
+    path.pop();
+    if let Some(max_depth) = max_depth {
+        if depth >= max_depth {
+            return Err(SpecError::FragmentDepthLimitExceeded(max_depth));
+        }
+    }
+    depths.insert(name.to_string(), depth);
+    Ok(depth)
+}
+
 impl Fragments {
     pub(crate) fn get(&self, key: impl AsRef<str>) -> Option<&Fragment> {
         self.map.get(key.as_ref())
@@ -31,8 +31,16 @@ pub(crate) enum SpecError {
     InvalidField(String, String),
     /// parsing error: {0}
     ParsingError(String),
-    /// subscription operation is not supported
+    /// subscriptions are not supported
     SubscriptionNotSupported,
+    /// Must provide operation name if query contains multiple operations.
+    OperationNameRequired,
+    /// Unknown operation named "{0}"
+    UnknownOperation(String),
+    /// fragment '{0}' spreads itself, directly or through other fragments
+    CyclicFragment(String),
+    /// fragment spreads are nested more than {0} levels deep
+    FragmentDepthLimitExceeded(usize),
 }
 
 impl SpecError {
@@ -42,4 +50,16 @@ impl SpecError {
             _ => "## GraphQLValidationFailure\n",
         }
     }
+
+    /// Error code sent in the extensions of the GraphQL error, matching the
+    /// ones returned by the query planner for the same validation failures.
+    pub(crate) const fn extension_code(&self) -> Option<&'static str> {
+        match self {
+            SpecError::OperationNameRequired
+            | SpecError::UnknownOperation(_)
+            | SpecError::CyclicFragment(_) => Some("GRAPHQL_VALIDATION_FAILED"),
+            SpecError::SubscriptionNotSupported => Some("SUBSCRIPTION_NOT_SUPPORTED"),
+            _ => None,
+        }
+    }
 }
@@ -4,7 +4,6 @@
 #![allow(clippy::mutable_key_type)]
 
 use std::collections::HashMap;
-use std::collections::HashSet;
 
 use apollo_parser::ast;
 use apollo_parser::ast::AstNode;
@@ -16,6 +15,9 @@ use serde::Serialize;
 use serde_json_bytes::ByteString;
 use tracing::level_filters::LevelFilter;
 
+use crate::configuration::LargeIntegers;
+use crate::configuration::UnknownVariables;
+use crate::configuration::VariableCoercion;
 use crate::error::FetchError;
 use crate::graphql::Request;
 use crate::graphql::Response;
@@ -93,8 +95,9 @@ impl<'de> Visitor<'de> for SubSelectionVisitor {
 impl Query {
     /// Re-format the response value to match this query.
     ///
-    /// This will discard unrequested fields and re-order the output to match the order of the
-    /// query.
+    /// This will discard unrequested fields, unless `strip_unrequested_fields` is false, and
+    /// re-order the output to match the order of the query.
+    #[allow(clippy::too_many_arguments)]
     #[tracing::instrument(skip_all, level = "trace")]
     pub(crate) fn format_response(
         &self,
@@ -103,6 +106,8 @@ impl Query {
         is_deferred: bool,
         variables: Object,
         schema: &Schema,
+        large_integers: LargeIntegers,
+        strip_unrequested_fields: bool,
     ) {
         let data = std::mem::take(&mut response.data);
         if let Some(Value::Object(mut input)) = data {
@@ -121,6 +126,8 @@ impl Query {
                                 variables: &variables,
                                 schema,
                                 errors: Vec::new(),
+                                large_integers,
+                                strip_unrequested_fields,
                             };
                             response.data = Some(
                                 match self.apply_root_selection_set(
@@ -130,7 +137,10 @@ impl Query {
                                     &mut output,
                                     &mut Path::default(),
                                 ) {
-                                    Ok(()) => output.into(),
+                                    Ok(()) => {
+                                        parameters.keep_unrequested_fields(&mut input, &mut output);
+                                        output.into()
+                                    }
                                     Err(InvalidValue) => Value::Null,
                                 },
                             );
@@ -167,6 +177,8 @@ impl Query {
                     variables: &all_variables,
                     schema,
                     errors: Vec::new(),
+                    large_integers,
+                    strip_unrequested_fields,
                 };
 
                 response.data = Some(
@@ -177,7 +189,10 @@ impl Query {
                         &mut output,
                         &mut Path::default(),
                     ) {
-                        Ok(()) => output.into(),
+                        Ok(()) => {
+                            parameters.keep_unrequested_fields(&mut input, &mut output);
+                            output.into()
+                        }
                         Err(InvalidValue) => Value::Null,
                     },
                 );
@@ -223,6 +238,7 @@ impl Query {
         }
 
         let document = tree.document();
+        validate_fragment_spreads(&document, configuration.limits.max_fragment_depth)?;
         let fragments = Fragments::from_ast(&document, schema)?;
 
         let operations: Vec<Operation> = document
@@ -345,7 +361,7 @@ impl Query {
                 // we cannot know about the expected format of custom scalars
                 // so we must pass them directly to the client
                 if parameters.schema.custom_scalars.contains(type_name) {
-                    *output = input.clone();
+                    *output = format_integer(input, parameters.large_integers);
                     return Ok(());
                 } else if let Some(enum_type) = parameters.schema.enums.get(type_name) {
                     return match input.as_str() {
@@ -381,18 +397,18 @@ impl Query {
                         }
                         let output_object = output.as_object_mut().ok_or(InvalidValue)?;
 
-                        if self
-                            .apply_selection_set(
-                                selection_set,
-                                parameters,
-                                input_object,
-                                output_object,
-                                path,
-                                &FieldType::Named(type_name.to_string()),
-                            )
-                            .is_err()
-                        {
-                            *output = Value::Null;
+                        match self.apply_selection_set(
+                            selection_set,
+                            parameters,
+                            input_object,
+                            output_object,
+                            path,
+                            &FieldType::Named(type_name.to_string()),
+                        ) {
+                            Ok(()) => {
+                                parameters.keep_unrequested_fields(input_object, output_object)
+                            }
+                            Err(InvalidValue) => *output = Value::Null,
                         }
 
                         Ok(())
@@ -449,7 +465,7 @@ impl Query {
             }
             FieldType::Id => {
                 if input.is_string() || input.is_i64() || input.is_u64() || input.is_f64() {
-                    *output = input.clone();
+                    *output = format_integer(input, parameters.large_integers);
                 } else {
                     *output = Value::Null;
                 }
@@ -800,6 +816,8 @@ impl Query {
         &self,
         request: &Request,
         schema: &Schema,
+        coercion: VariableCoercion,
+        unknown_variables: UnknownVariables,
     ) -> Result<(), Response> {
         let operation_name = request.operation_name.as_deref();
         let operation_variable_types =
@@ -812,21 +830,29 @@ impl Query {
                     acc
                 });
 
-        if LevelFilter::current() >= LevelFilter::DEBUG {
-            let known_variables = operation_variable_types.keys().cloned().collect();
-            let provided_variables = request
+        let mut unknown_variable_errors = Vec::new();
+        if unknown_variables == UnknownVariables::Reject
+            || LevelFilter::current() >= LevelFilter::DEBUG
+        {
+            let mut unknown = request
                 .variables
                 .keys()
                 .map(|k| k.as_str())
-                .collect::<HashSet<_>>();
-            let unknown_variables = provided_variables
-                .difference(&known_variables)
+                .filter(|name| !operation_variable_types.contains_key(name))
                 .collect::<Vec<_>>();
-            if !unknown_variables.is_empty() {
-                failfast_debug!(
-                    "Received variable unknown to the query: {:?}",
-                    unknown_variables,
-                );
+            if unknown_variables == UnknownVariables::Reject {
+                unknown.sort_unstable();
+                unknown_variable_errors = unknown
+                    .iter()
+                    .map(|name| {
+                        FetchError::ValidationUnknownVariable {
+                            name: name.to_string(),
+                        }
+                        .to_graphql_error(None)
+                    })
+                    .collect();
+            } else if !unknown.is_empty() {
+                failfast_debug!("Received variable unknown to the query: {:?}", unknown);
             }
         }
 
@@ -845,14 +871,28 @@ impl Query {
                         .get(*name)
                         .or(default_value.as_ref())
                         .unwrap_or(&Value::Null);
-                    ty.validate_input_value(value, schema).err().map(|_| {
-                        FetchError::ValidationInvalidTypeVariable {
-                            name: name.to_string(),
-                        }
-                        .to_graphql_error(None)
-                    })
+                    ty.validate_input_value(value, schema, coercion)
+                        .err()
+                        .map(|_| {
+                            // tell strict clients when the value would have been accepted with coercion
+                            let coerced = coercion == VariableCoercion::Strict
+                                && ty
+                                    .validate_input_value(value, schema, VariableCoercion::Lenient)
+                                    .is_ok();
+                            if coerced {
+                                FetchError::ValidationCoercedVariable {
+                                    name: name.to_string(),
+                                }
+                            } else {
+                                FetchError::ValidationInvalidTypeVariable {
+                                    name: name.to_string(),
+                                }
+                            }
+                            .to_graphql_error(None)
+                        })
                 },
             )
+            .chain(unknown_variable_errors)
             .collect::<Vec<_>>();
 
         if errors.is_empty() {
@@ -893,6 +933,213 @@ impl Query {
         })
     }
 
+    /// Checks that the operation to execute can be selected, as defined by the GraphQL specification:
+    /// the operation name is required when the query contains multiple operations,
+    /// and it must match one of them when provided.
+    pub(crate) fn validate_operation_name(
+        &self,
+        operation_name: Option<&str>,
+    ) -> Result<(), SpecError> {
+        match operation_name {
+            None if self.operations.len() > 1 => Err(SpecError::OperationNameRequired),
+            Some(name) if self.operation(Some(name)).is_none() => {
+                Err(SpecError::UnknownOperation(name.to_string()))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the type of the field selected at a response path, ignoring list indexes,
+    /// or `None` if the operation does not select it.
+    pub(crate) fn field_type_at(
+        &self,
+        operation_name: Option<&str>,
+        path: &Path,
+    ) -> Option<&FieldType> {
+        let mut keys = path
+            .iter()
+            .filter_map(|element| match element {
+                PathElement::Key(key) => Some(key.as_str()),
+                _ => None,
+            })
+            .peekable();
+        let mut selection_set = self.operation(operation_name)?.selection_set.as_slice();
+        loop {
+            let (field_type, field_selection_set) = self.find_field(selection_set, keys.next()?)?;
+            if keys.peek().is_none() {
+                return Some(field_type);
+            }
+            selection_set = field_selection_set?;
+        }
+    }
+
+    fn find_field<'a>(
+        &'a self,
+        selection_set: &'a [Selection],
+        key: &str,
+    ) -> Option<(&'a FieldType, Option<&'a [Selection]>)> {
+        selection_set.iter().find_map(|selection| match selection {
+            Selection::Field {
+                name,
+                alias,
+                selection_set,
+                field_type,
+                ..
+            } => (alias.as_ref().unwrap_or(name).as_str() == key)
+                .then(|| (field_type, selection_set.as_deref())),
+            Selection::InlineFragment { selection_set, .. } => self.find_field(selection_set, key),
+            Selection::FragmentSpread { name, .. } => self
+                .fragments
+                .get(name)
+                .and_then(|fragment| self.find_field(&fragment.selection_set, key)),
+        })
+    }
+
+    /// Calls `f` with the values of the fields at a path of schema field names, whatever their
+    /// alias in the operation. The arrays of the response are traversed, and the array elements of
+    /// the path are ignored.
+    pub(crate) fn for_each_field_value_mut(
+        &self,
+        operation_name: Option<&str>,
+        path: &Path,
+        data: &mut Value,
+        f: &mut impl FnMut(&mut Value),
+    ) {
+        let field_names = path
+            .iter()
+            .filter_map(|element| match element {
+                PathElement::Key(key) => Some(key.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        if let Some(operation) = self.operation(operation_name) {
+            self.visit_fields_mut(&[operation.selection_set.as_slice()], &field_names, data, f);
+        }
+    }
+
+    fn visit_fields_mut(
+        &self,
+        selection_sets: &[&[Selection]],
+        field_names: &[&str],
+        value: &mut Value,
+        f: &mut impl FnMut(&mut Value),
+    ) {
+        let (field_name, rest) = match field_names.split_first() {
+            Some(split) => split,
+            None => return,
+        };
+        match value {
+            Value::Array(array) => {
+                for value in array {
+                    self.visit_fields_mut(selection_sets, field_names, value, f);
+                }
+            }
+            Value::Object(object) => {
+                let mut fields = Vec::new();
+                for selection_set in selection_sets {
+                    self.collect_fields(selection_set, field_name, &mut fields);
+                }
+                // each response key is visited once, even if several selections have that key
+                for (key, selection_sets) in fields {
+                    if let Some(value) = object.get_mut(key) {
+                        if rest.is_empty() {
+                            f(value);
+                        } else {
+                            self.visit_fields_mut(&selection_sets, rest, value, f);
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Collects the response keys of the selections of a field, with the selection sets of each key
+    fn collect_fields<'a>(
+        &'a self,
+        selection_set: &'a [Selection],
+        field_name: &str,
+        fields: &mut Vec<(&'a str, Vec<&'a [Selection]>)>,
+    ) {
+        for selection in selection_set {
+            match selection {
+                Selection::Field {
+                    name,
+                    alias,
+                    selection_set,
+                    ..
+                } if name.as_str() == field_name => {
+                    let key = alias.as_ref().unwrap_or(name).as_str();
+                    let index = match fields.iter().position(|(k, _)| *k == key) {
+                        Some(index) => index,
+                        None => {
+                            fields.push((key, Vec::new()));
+                            fields.len() - 1
+                        }
+                    };
+                    if let Some(selection_set) = selection_set {
+                        fields[index].1.push(selection_set.as_slice());
+                    }
+                }
+                Selection::Field { .. } => {}
+                Selection::InlineFragment { selection_set, .. } => {
+                    self.collect_fields(selection_set, field_name, fields)
+                }
+                Selection::FragmentSpread { name, .. } => {
+                    if let Some(fragment) = self.fragments.get(name) {
+                        self.collect_fields(&fragment.selection_set, field_name, fields)
+                    }
+                }
+            }
+        }
+    }
+
+    /// Names of the variables declared without a default value by the executed operation.
+    ///
+    /// Only the query text is parsed, so this can run before query planning.
+    pub(crate) fn variables_without_default(
+        query: &str,
+        operation_name: Option<&str>,
+    ) -> Vec<String> {
+        let tree = apollo_parser::Parser::new(query).parse();
+        let operation = tree
+            .document()
+            .definitions()
+            .filter_map(|definition| match definition {
+                ast::Definition::OperationDefinition(operation) => Some(operation),
+                _ => None,
+            })
+            .find(|operation| match operation_name {
+                Some(name) => operation
+                    .name()
+                    .map(|op_name| op_name.text() == name)
+                    .unwrap_or_default(),
+                None => true,
+            });
+
+        operation
+            .iter()
+            .flat_map(|operation| operation.variable_definitions())
+            .flat_map(|definitions| definitions.variable_definitions())
+            .filter(|definition| definition.default_value().is_none())
+            .filter_map(|definition| definition.variable()?.name())
+            .map(|name| name.text().to_string())
+            .collect()
+    }
+
+    /// Returns `true` if the executed operation does not have a name.
+    pub(crate) fn is_anonymous(&self, operation_name: Option<&str>) -> bool {
+        self.operation(operation_name)
+            .map(|operation| operation.name.is_none())
+            .unwrap_or_default()
+    }
+
+    /// Returns the kind of the executed operation, if it exists.
+    pub(crate) fn operation_kind(&self, operation_name: Option<&str>) -> Option<OperationKind> {
+        self.operation(operation_name)
+            .map(|operation| operation.kind)
+    }
+
     fn operation(&self, operation_name: Option<&str>) -> Option<&Operation> {
         match operation_name {
             Some(name) => self
@@ -916,6 +1163,38 @@ struct FormatParameters<'a> {
     variables: &'a Object,
     errors: Vec<Error>,
     schema: &'a Schema,
+    large_integers: LargeIntegers,
+    strip_unrequested_fields: bool,
+}
+
+impl FormatParameters<'_> {
+    /// Copies the fields that the selection set did not request, unless they are stripped
+    fn keep_unrequested_fields(&self, input: &mut Object, output: &mut Object) {
+        if self.strip_unrequested_fields {
+            return;
+        }
+        for (key, value) in input.iter_mut() {
+            if !output.contains_key(key.as_str()) {
+                output.insert(key.clone(), std::mem::take(value));
+            }
+        }
+    }
+}
+
+/// The largest integer that IEEE 754 doubles, used by JavaScript numbers, represent exactly
+const MAX_SAFE_INTEGER: u64 = (1 << 53) - 1;
+
+/// Converts an integer to a string if it is configured so and it is not a safe integer
+fn format_integer(input: &Value, large_integers: LargeIntegers) -> Value {
+    match (input, large_integers) {
+        (Value::Number(n), LargeIntegers::String)
+            if n.as_i64().map(i64::unsigned_abs).or_else(|| n.as_u64())
+                > Some(MAX_SAFE_INTEGER) =>
+        {
+            Value::String(n.to_string().into())
+        }
+        _ => input.clone(),
+    }
 }
 
 #[derive(Debug, Serialize, Deserialize)]
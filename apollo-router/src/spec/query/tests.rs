@@ -2,6 +2,10 @@ use serde_json_bytes::json;
 use test_log::test;
 
 use super::*;
+use crate::configuration::LargeIntegers;
+use crate::configuration::Limits;
+use crate::configuration::UnknownVariables;
+use crate::configuration::VariableCoercion;
 use crate::json_ext::ValueExt;
 
 macro_rules! assert_eq_and_ordered {
@@ -28,6 +32,8 @@ struct FormatTest {
     expected_errors: Option<serde_json_bytes::Value>,
     federation_version: FederationVersion,
     is_deferred: bool,
+    large_integers: LargeIntegers,
+    keep_unrequested_fields: bool,
 }
 
 enum FederationVersion {
@@ -92,6 +98,16 @@ impl FormatTest {
         self
     }
 
+    fn large_integers(mut self, large_integers: LargeIntegers) -> Self {
+        self.large_integers = large_integers;
+        self
+    }
+
+    fn keep_unrequested_fields(mut self) -> Self {
+        self.keep_unrequested_fields = true;
+        self
+    }
+
     #[track_caller]
     fn test(self) {
         let schema = self.schema.expect("missing schema");
@@ -120,6 +136,8 @@ impl FormatTest {
                 .unwrap()
                 .clone(),
             api_schema,
+            self.large_integers,
+            !self.keep_unrequested_fields,
         );
 
         if let Some(e) = self.expected {
@@ -931,6 +949,66 @@ fn reformat_response_array_of_id_duplicate() {
         .test();
 }
 
+#[test]
+fn reformat_response_large_integers() {
+    let schema =
+        "type Query { get: Thing } scalar Long type Thing { id: ID count: Long small: ID }";
+    let query = "{ get { id count small } }";
+    // 2^53 + 1, which JavaScript numbers cannot represent
+    let response = json! {{
+        "get": { "id": 9007199254740993u64, "count": -9007199254740993i64, "small": 42 }
+    }};
+
+    // integers are preserved by default
+    FormatTest::builder()
+        .schema(schema)
+        .query(query)
+        .response(response.clone())
+        .expected(response.clone())
+        .test();
+
+    FormatTest::builder()
+        .schema(schema)
+        .query(query)
+        .response(response)
+        .large_integers(LargeIntegers::String)
+        .expected(json! {{
+            "get": { "id": "9007199254740993", "count": "-9007199254740993", "small": 42 }
+        }})
+        .test();
+}
+
+#[test]
+fn reformat_response_unrequested_fields() {
+    let schema = "type Query { get: Thing } type Thing { id: ID name: String secret: String }";
+    let query = "{ get { name } }";
+    // the subgraph returned more fields than the operation selected
+    let response = json! {{
+        "get": { "id": "1", "secret": "s3cr3t", "name": "a" },
+        "other": 1
+    }};
+
+    // they are stripped by default
+    FormatTest::builder()
+        .schema(schema)
+        .query(query)
+        .response(response.clone())
+        .expected(json! {{ "get": { "name": "a" } }})
+        .test();
+
+    // or kept after the requested fields
+    FormatTest::builder()
+        .schema(schema)
+        .query(query)
+        .response(response)
+        .keep_unrequested_fields()
+        .expected(json! {{
+            "get": { "name": "a", "id": "1", "secret": "s3cr3t" },
+            "other": 1
+        }})
+        .test();
+}
+
 #[test]
 fn solve_query_with_single_typename() {
     FormatTest::builder()
@@ -979,7 +1057,7 @@ fn reformat_response_query_with_root_typename() {
 }
 
 macro_rules! run_validation {
-    ($schema:expr, $query:expr, $variables:expr $(,)?) => {{
+    ($schema:expr, $query:expr, $variables:expr, $coercion:expr, $unknown_variables:expr $(,)?) => {{
         let variables = match $variables {
             Value::Object(object) => object,
             _ => unreachable!("variables must be an object"),
@@ -998,7 +1076,19 @@ macro_rules! run_validation {
             &Default::default(),
         )
         .expect("could not parse query");
-        query.validate_variables(&request, &schema)
+        query.validate_variables(&request, &schema, $coercion, $unknown_variables)
+    }};
+    ($schema:expr, $query:expr, $variables:expr, $coercion:expr $(,)?) => {{
+        run_validation!(
+            $schema,
+            $query,
+            $variables,
+            $coercion,
+            UnknownVariables::Ignore
+        )
+    }};
+    ($schema:expr, $query:expr, $variables:expr $(,)?) => {{
+        run_validation!($schema, $query, $variables, VariableCoercion::Lenient)
     }};
 }
 
@@ -1016,6 +1106,116 @@ macro_rules! assert_validation_error {
     }};
 }
 
+#[test]
+fn variable_coercion() {
+    let schema = with_supergraph_boilerplate("type Query { x: String }");
+    let strict_error = |query: &str, variables: Value| {
+        let response = run_validation!(schema, query, variables, VariableCoercion::Strict)
+            .expect_err("validation should have failed");
+        response.errors[0].message.clone()
+    };
+
+    // a single value is coerced to a list
+    assert!(run_validation!(schema, "query($foo:[Int]){x}", json!({"foo":1})).is_ok());
+    assert_eq!(
+        strict_error("query($foo:[Int]){x}", json!({"foo":1})),
+        "variable 'foo' needs an implicit coercion, which is disabled by 'supergraph.variable_coercion: strict'"
+    );
+    assert!(run_validation!(
+        schema,
+        "query($foo:[Int]){x}",
+        json!({"foo":[1]}),
+        VariableCoercion::Strict
+    )
+    .is_ok());
+
+    // an integer is coerced to a float
+    assert!(run_validation!(schema, "query($foo:Float){x}", json!({"foo":2})).is_ok());
+    assert_eq!(
+        strict_error("query($foo:Float){x}", json!({"foo":2})),
+        "variable 'foo' needs an implicit coercion, which is disabled by 'supergraph.variable_coercion: strict'"
+    );
+    assert!(run_validation!(
+        schema,
+        "query($foo:Float){x}",
+        json!({"foo":2.0}),
+        VariableCoercion::Strict
+    )
+    .is_ok());
+
+    // an integer is coerced to an ID
+    assert!(run_validation!(schema, "query($foo:ID){x}", json!({"foo":2})).is_ok());
+    assert!(run_validation!(
+        schema,
+        "query($foo:ID){x}",
+        json!({"foo":2}),
+        VariableCoercion::Strict
+    )
+    .is_err());
+
+    // values that cannot be coerced keep the usual error
+    assert_eq!(
+        strict_error("query($foo:Float){x}", json!({"foo":"2.0"})),
+        "invalid type for variable: 'foo'"
+    );
+}
+
+#[test]
+fn unknown_variables() {
+    let schema = with_supergraph_boilerplate("type Query { x(foo: Int): String }");
+    let query = "query($foo:Int){x(foo:$foo)}";
+    let variables = json!({"foo": 1, "bar": 2});
+
+    // extra variables are ignored by default
+    assert!(run_validation!(schema, query, variables.clone()).is_ok());
+
+    let response = run_validation!(
+        schema,
+        query,
+        variables,
+        VariableCoercion::Lenient,
+        UnknownVariables::Reject
+    )
+    .expect_err("validation should have failed");
+    assert_eq!(response.errors.len(), 1);
+    assert_eq!(
+        response.errors[0].message,
+        "variable 'bar' is not declared by the operation, which is rejected by 'supergraph.unknown_variables: reject'"
+    );
+
+    // declared variables are accepted
+    assert!(run_validation!(
+        schema,
+        query,
+        json!({"foo": 1}),
+        VariableCoercion::Lenient,
+        UnknownVariables::Reject
+    )
+    .is_ok());
+}
+
+#[test]
+fn field_type_at() {
+    let schema = with_supergraph_boilerplate(
+        "type Query { me: User! } type User { name: String friends: [User!]! }",
+    );
+    let schema = Schema::parse(&schema, &Default::default()).expect("could not parse schema");
+    let query = Query::parse(
+        "{ me { name ...F } } fragment F on User { buddies: friends { name } }",
+        &schema,
+        &Default::default(),
+    )
+    .expect("could not parse query");
+
+    let field_type = |path: &str| query.field_type_at(None, &Path::from(path));
+    assert!(field_type("me").unwrap().is_non_null());
+    assert!(!field_type("me/name").unwrap().is_non_null());
+    assert!(field_type("me/buddies").unwrap().is_non_null());
+    assert!(!field_type("me/buddies/@/name").unwrap().is_non_null());
+    assert!(field_type("me/friends").is_none());
+    assert!(field_type("me/name/length").is_none());
+}
+
 #[test]
 fn variable_validation() {
     let schema = "type Query { x: String }";
@@ -4473,7 +4673,14 @@ fn fragment_on_interface_on_query() {
         }})
         .build();
 
-    query.format_response(&mut response, None, false, Default::default(), api_schema);
+    query.format_response(
+        &mut response,
+        None,
+        false,
+        Default::default(),
+        api_schema,
+        Default::default(),
+    );
     assert_eq_and_ordered!(
         response.data.as_ref().unwrap(),
         &json! {{
@@ -4963,3 +5170,124 @@ fn query_operation_nullification() {
         .expected(Value::Null)
         .test();
 }
+
+#[test]
+fn validate_operation_name() {
+    let schema = with_supergraph_boilerplate(
+        "type Query {
+        name: String
+        review: String
+    }",
+    );
+    let schema = Schema::parse(&schema, &Default::default()).expect("could not parse schema");
+
+    // a single operation is selected without a name
+    let query = Query::parse("query { name }", &schema, &Default::default()).unwrap();
+    assert!(query.validate_operation_name(None).is_ok());
+
+    let query = Query::parse(
+        "query Name { name }
+        query Review { review }",
+        &schema,
+        &Default::default(),
+    )
+    .unwrap();
+
+    // multiple operations with a name
+    assert!(query.validate_operation_name(Some("Review")).is_ok());
+
+    // multiple operations without a name
+    assert!(matches!(
+        query.validate_operation_name(None),
+        Err(SpecError::OperationNameRequired)
+    ));
+
+    // wrong name
+    assert!(matches!(
+        query.validate_operation_name(Some("Product")),
+        Err(SpecError::UnknownOperation(name)) if name == "Product"
+    ));
+
+    // a name that does not match the single operation
+    let query = Query::parse("query { name }", &schema, &Default::default()).unwrap();
+    assert!(matches!(
+        query.validate_operation_name(Some("Name")),
+        Err(SpecError::UnknownOperation(name)) if name == "Name"
+    ));
+}
+
+#[test]
+fn fragment_spread_depth() {
+    let schema = with_supergraph_boilerplate(
+        "type Query {
+        me: User
+    }
+    type User {
+        id: ID
+        name: String
+    }",
+    );
+    let schema = Schema::parse(&schema, &Default::default()).expect("could not parse schema");
+    let config = Configuration::builder()
+        .limits(Limits::builder().max_fragment_depth(2).build())
+        .build()
+        .unwrap();
+
+    // the operation spreads A, which spreads B: two levels
+    let query = "query { me { ...A } }
+    fragment A on User { id ...B }
+    fragment B on User { name }";
+    assert!(Query::parse(query, &schema, &config).is_ok());
+
+    // three levels
+    let query = "query { me { ...A } }
+    fragment A on User { id ...B }
+    fragment B on User { name ...C }
+    fragment C on User { id }";
+    assert!(matches!(
+        Query::parse(query, &schema, &config),
+        Err(SpecError::FragmentDepthLimitExceeded(2))
+    ));
+    // unbounded by default
+    assert!(Query::parse(query, &schema, &Default::default()).is_ok());
+}
+
+#[test]
+fn cyclic_fragments() {
+    let schema = with_supergraph_boilerplate(
+        "type Query {
+        me: User
+    }
+    type User {
+        id: ID
+        friend: User
+    }",
+    );
+    let schema = Schema::parse(&schema, &Default::default()).expect("could not parse schema");
+
+    let query = "query { me { ...A } }
+    fragment A on User { id friend { ...B } }
+    fragment B on User { ...A }";
+    assert!(matches!(
+        Query::parse(query, &schema, &Default::default()),
+        Err(SpecError::CyclicFragment(_))
+    ));
+}
+
+#[test]
+fn variables_without_default() {
+    let query = "query A($a: Int, $b: Int = 1) { a } query B($c: String!) { b }";
+    assert_eq!(
+        Query::variables_without_default(query, Some("A")),
+        vec!["a".to_string()]
+    );
+    assert_eq!(
+        Query::variables_without_default(query, Some("B")),
+        vec!["c".to_string()]
+    );
+    assert!(Query::variables_without_default(query, Some("C")).is_empty());
+    assert_eq!(
+        Query::variables_without_default("query($d: ID) { a }", None),
+        vec!["d".to_string()]
+    );
+}
@@ -2,7 +2,6 @@
 
 use std::collections::HashMap;
 use std::collections::HashSet;
-use std::str::FromStr;
 use std::sync::Arc;
 
 use apollo_parser::ast;
@@ -12,11 +11,15 @@ use router_bridge::api_schema;
 use sha2::Digest;
 use sha2::Sha256;
 
+use crate::configuration::VariableCoercion;
 use crate::error::ParseErrors;
 use crate::error::SchemaError;
 use crate::json_ext::Object;
+use crate::json_ext::Path;
+use crate::json_ext::PathElement;
 use crate::json_ext::Value;
 use crate::query_planner::OperationKind;
+use crate::services::subgraph_service::normalize_subgraph_url;
 use crate::spec::query::parse_value;
 use crate::*;
 
@@ -55,7 +58,7 @@ impl Schema {
             parse(&api_schema, configuration)
         }
 
-        fn parse(schema: &str, _configuration: &Configuration) -> Result<Schema, SchemaError> {
+        fn parse(schema: &str, configuration: &Configuration) -> Result<Schema, SchemaError> {
             let schema_with_introspection = Schema::with_introspection(schema);
             let parser = apollo_parser::Parser::new(&schema_with_introspection);
             let tree = parser.parse();
@@ -204,7 +207,13 @@ impl Schema {
                                                     if subgraphs
                                                         .insert(
                                                             name.clone(),
-                                                            Uri::from_str(&url).map_err(|err| {
+                                                            normalize_subgraph_url(
+                                                                &url,
+                                                                configuration
+                                                                    .supergraph
+                                                                    .subgraph_url_trailing_slash,
+                                                            )
+                                                            .map_err(|err| {
                                                                 SchemaError::UrlParse(
                                                                     name.clone(),
                                                                     err,
@@ -509,6 +518,56 @@ impl Schema {
             .map(|s| s.as_str())
             .unwrap_or_else(|| kind.as_str())
     }
+
+    /// Returns the type of the field at a path of field names, from the query root type or else
+    /// from the mutation root type. The array elements of the path are ignored.
+    ///
+    /// Returns `None` if the path does not match a field, or if the members of an interface or
+    /// union on the path define the field with different types.
+    pub(crate) fn field_type_at(&self, path: &Path) -> Option<&FieldType> {
+        [OperationKind::Query, OperationKind::Mutation]
+            .into_iter()
+            .find_map(|kind| {
+                let mut type_name = Some(self.root_operation_name(kind));
+                let mut field_type = None;
+                for key in path.iter().filter_map(|element| match element {
+                    PathElement::Key(key) => Some(key.as_str()),
+                    _ => None,
+                }) {
+                    let current = self.field_of(type_name?, key)?;
+                    type_name = current.inner_type_name();
+                    field_type = Some(current);
+                }
+                field_type
+            })
+    }
+
+    /// Returns the field of a type. The fields of an interface or union can also be selected on
+    /// their members, through fragments: they are only returned if all the members defining them
+    /// agree on their type.
+    fn field_of(&self, type_name: &str, key: &str) -> Option<&FieldType> {
+        if let Some(object) = self.object_types.get(type_name) {
+            return object.field(key);
+        }
+        if let Some(field) = self
+            .interfaces
+            .get(type_name)
+            .and_then(|interface| interface.field(key))
+        {
+            return Some(field);
+        }
+        let mut fields = self
+            .subtype_map
+            .get(type_name)?
+            .iter()
+            .filter_map(|member| self.object_types.get(member)?.field(key));
+        let field = fields.next()?;
+        if fields.all(|other| other == field) {
+            Some(field)
+        } else {
+            None
+        }
+    }
 }
 
 #[derive(Debug)]
@@ -625,6 +684,7 @@ macro_rules! implement_input_object_type_or_interface {
                 &self,
                 object: &Object,
                 schema: &Schema,
+                coercion: VariableCoercion,
             ) -> Result<(), InvalidObject> {
                  self
                     .fields
@@ -634,7 +694,7 @@ macro_rules! implement_input_object_type_or_interface {
                             Some(&Value::Null) | None => default_value.as_ref().unwrap_or(&Value::Null),
                             Some(value) => value,
                         };
-                        ty.validate_input_value(value, schema)
+                        ty.validate_input_value(value, schema, coercion)
                     })
                     .map_err(|_| InvalidObject)
             }
@@ -800,6 +860,46 @@ mod tests {
         assert!(schema.is_subtype("Baz", "InterfaceType2"));
     }
 
+    #[test]
+    fn field_type_at() {
+        let schema = with_supergraph_boilerplate(
+            r#"
+            type Query {
+              node: Node
+              search: [Result]
+            }
+            interface Node {
+              id: ID!
+            }
+            type User implements Node {
+              id: ID!
+              name: String
+              email: String
+            }
+            type Product implements Node {
+              id: ID!
+              name: String!
+              price: Int
+            }
+            union Result = User | Product
+            "#,
+        );
+        let schema = Schema::parse(&schema, &Default::default()).unwrap();
+        let field_type = |path: &str| schema.field_type_at(&Path::from(path)).cloned();
+
+        assert_eq!(
+            field_type("node/id"),
+            Some(FieldType::NonNull(Box::new(FieldType::Id)))
+        );
+        // fields of the members, selected through fragments
+        assert_eq!(field_type("node/email"), Some(FieldType::String));
+        assert_eq!(field_type("search/@/price"), Some(FieldType::Int));
+        // the members disagree on the type of the field
+        assert_eq!(field_type("search/@/name"), None);
+        assert_eq!(field_type("node/unknown"), None);
+        assert_eq!(field_type("node/id/unknown"), None);
+    }
+
     #[test]
     fn routing_urls() {
         let schema = r#"
@@ -1,8 +1,11 @@
+use std::collections::HashSet;
 use std::fmt::Display;
 use std::fmt::Formatter;
+use std::pin::Pin;
 use std::sync::Arc;
 
 use futures::prelude::*;
+use futures::stream::Peekable;
 use tokio::sync::OwnedRwLockWriteGuard;
 use tokio::sync::RwLock;
 use Event::NoMoreConfiguration;
@@ -23,9 +26,17 @@ use super::state_machine::State::Startup;
 use super::state_machine::State::Stopped;
 use crate::configuration::Configuration;
 use crate::configuration::ListenAddr;
+use crate::configuration::WarmupMode;
 use crate::router_factory::SupergraphServiceConfigurator;
 use crate::router_factory::SupergraphServiceFactory;
+use crate::services::supergraph;
 use crate::Schema;
+use crate::SchemaReloadCallback;
+use crate::SchemaValidator;
+
+/// Creates a service of the running supergraph pipeline for the requests of the router itself
+pub(crate) type SupergraphServiceMaker =
+    Arc<dyn Fn() -> Option<supergraph::BoxService> + Send + Sync>;
 
 /// This state maintains private information that is not exposed to the user via state listener.
 #[derive(derivative::Derivative)]
@@ -77,6 +88,10 @@ where
     pub(crate) extra_listen_adresses: Arc<RwLock<Vec<ListenAddr>>>,
     extra_listen_addresses_guard: Option<OwnedRwLockWriteGuard<Vec<ListenAddr>>>,
     graphql_listen_address_guard: Option<OwnedRwLockWriteGuard<Option<ListenAddr>>>,
+    // Creates a service of the running pipeline, to check that it answers requests.
+    pub(crate) supergraph_service: Arc<RwLock<Option<SupergraphServiceMaker>>>,
+    on_schema_reload: Option<SchemaReloadCallback>,
+    schema_validators: Vec<SchemaValidator>,
 }
 
 impl<S, FA> StateMachine<S, FA>
@@ -97,14 +112,35 @@ where
             graphql_listen_address_guard: Some(graphql_ready_guard),
             extra_listen_adresses: extra_ready,
             extra_listen_addresses_guard: Some(extra_ready_guard),
+            supergraph_service: Default::default(),
+            on_schema_reload: None,
+            schema_validators: Vec::new(),
         }
     }
 
+    pub(crate) fn with_schema_reload_callback(
+        mut self,
+        on_schema_reload: Option<SchemaReloadCallback>,
+    ) -> Self {
+        self.on_schema_reload = on_schema_reload;
+        self
+    }
+
+    pub(crate) fn with_schema_validators(
+        mut self,
+        schema_validators: Vec<SchemaValidator>,
+    ) -> Self {
+        self.schema_validators = schema_validators;
+        self
+    }
+
     pub(crate) async fn process_events(
         mut self,
-        mut messages: impl Stream<Item = Event> + Unpin,
+        messages: impl Stream<Item = Event> + Unpin,
     ) -> Result<(), ApolloRouterError> {
         tracing::debug!("starting");
+        // the warmup looks at the next message, to stop when the router shuts down
+        let mut messages = messages.peekable();
         let mut state = Startup {
             configuration: None,
             schema: None,
@@ -113,19 +149,25 @@ where
             let new_state = match (state, message) {
                 // Startup: Handle configuration updates, maybe transition to running.
                 (Startup { configuration, .. }, UpdateSchema(new_schema)) => self
-                    .maybe_transition_to_running(Startup {
-                        configuration,
-                        schema: Some(new_schema),
-                    })
+                    .maybe_transition_to_running(
+                        Startup {
+                            configuration,
+                            schema: Some(new_schema),
+                        },
+                        &mut messages,
+                    )
                     .await
                     .into_ok_or_err2(),
 
                 // Startup: Handle schema updates, maybe transition to running.
                 (Startup { schema, .. }, UpdateConfiguration(new_configuration)) => self
-                    .maybe_transition_to_running(Startup {
-                        configuration: Some(*new_configuration),
-                        schema,
-                    })
+                    .maybe_transition_to_running(
+                        Startup {
+                            configuration: Some(*new_configuration),
+                            schema,
+                        },
+                        &mut messages,
+                    )
                     .await
                     .into_ok_or_err2(),
 
@@ -165,6 +207,17 @@ where
                 ) => {
                     tracing::info!("reloading schema");
                     match Schema::parse(&new_schema, &configuration) {
+                        Ok(new_schema)
+                            if !Self::check_subgraphs(&configuration, &schema, &new_schema)
+                                || !self.validate_schema(&new_schema) =>
+                        {
+                            Running {
+                                configuration,
+                                schema,
+                                router_service_factory,
+                                server_handle,
+                            }
+                        }
                         Ok(new_schema) => self
                             .reload_server(
                                 configuration,
@@ -173,8 +226,13 @@ where
                                 server_handle,
                                 None,
                                 Some(Arc::new(new_schema)),
+                                &mut messages,
                             )
                             .await
+                            .map(|s| {
+                                self.notify_schema_reload(&s);
+                                s
+                            })
                             .into_ok_or_err2(),
                         Err(e) => {
                             tracing::error!("could not parse schema: {:?}", e);
@@ -208,6 +266,41 @@ where
                             router_service_factory,
                             server_handle,
                         }
+                    } else if configuration.only_subgraph_urls_changed(&new_configuration)
+                        && !Self::check_configured_subgraphs(&new_configuration, &schema)
+                    {
+                        Running {
+                            configuration,
+                            schema,
+                            router_service_factory,
+                            server_handle,
+                        }
+                    } else if configuration.only_subgraph_urls_changed(&new_configuration)
+                        && router_service_factory.update_subgraph_urls(&new_configuration)
+                    {
+                        // the schema, query plans and HTTP server stay the same
+                        tracing::info!("reloaded subgraph URLs");
+                        Running {
+                            configuration: Arc::new(*new_configuration),
+                            schema,
+                            router_service_factory,
+                            server_handle,
+                        }
+                    } else if Self::reload_plugins(
+                        &configuration,
+                        &new_configuration,
+                        &router_service_factory,
+                    )
+                    .await
+                    {
+                        // the plugins applied their new configuration themselves
+                        tracing::info!("reloaded plugin configuration");
+                        Running {
+                            configuration: Arc::new(*new_configuration),
+                            schema,
+                            router_service_factory,
+                            server_handle,
+                        }
                     } else {
                         self.reload_server(
                             configuration,
@@ -216,6 +309,7 @@ where
                             server_handle,
                             Some(Arc::new(*new_configuration)),
                             None,
+                            &mut messages,
                         )
                         .await
                         .map(|s| {
@@ -260,18 +354,134 @@ where
         }
     }
 
+    /// Applies a new configuration changing only the configuration of plugins, if they all
+    /// support it. Returns false if the router must be rebuilt instead
+    async fn reload_plugins(
+        configuration: &Configuration,
+        new_configuration: &Configuration,
+        router_service_factory: &<FA as SupergraphServiceConfigurator>::SupergraphServiceFactory,
+    ) -> bool {
+        match configuration.changed_plugins(new_configuration) {
+            Some(plugins) => {
+                router_service_factory
+                    .reload_plugins(&plugins, configuration, new_configuration)
+                    .await
+            }
+            None => false,
+        }
+    }
+
+    /// Checks that a new schema still contains the configured subgraphs of the current one.
+    /// Returns false if the new schema must be rejected
+    fn check_subgraphs(
+        configuration: &Configuration,
+        schema: &Schema,
+        new_schema: &Schema,
+    ) -> bool {
+        let new_subgraphs: HashSet<&String> =
+            new_schema.subgraphs().map(|(name, _)| name).collect();
+        let mut dropped: Vec<&String> = schema
+            .subgraphs()
+            .map(|(name, _)| name)
+            .filter(|name| !new_subgraphs.contains(name))
+            .collect();
+        let configured = configuration.configured_subgraphs();
+        dropped.retain(|name| configured.contains(name.as_str()));
+        if dropped.is_empty() {
+            return true;
+        }
+        dropped.sort();
+
+        if configuration.schema.strict_subgraph_check {
+            tracing::error!(
+                "new schema removes the configured subgraphs {:?}, keeping the previous schema",
+                dropped
+            );
+            false
+        } else {
+            tracing::warn!(
+                "new schema removes the configured subgraphs {:?}, their configuration will be ignored",
+                dropped
+            );
+            true
+        }
+    }
+
+    /// Checks that the subgraphs of a new configuration all exist in the current schema.
+    /// Returns false if the new configuration must be rejected
+    fn check_configured_subgraphs(configuration: &Configuration, schema: &Schema) -> bool {
+        let subgraphs: HashSet<&String> = schema.subgraphs().map(|(name, _)| name).collect();
+        let mut unknown: Vec<String> = configuration
+            .configured_subgraphs()
+            .into_iter()
+            .filter(|name| !subgraphs.contains(name))
+            .collect();
+        if unknown.is_empty() {
+            return true;
+        }
+        unknown.sort();
+
+        if configuration.schema.strict_subgraph_check {
+            tracing::error!(
+                "new configuration configures the subgraphs {:?} missing from the schema, keeping the previous configuration",
+                unknown
+            );
+            false
+        } else {
+            tracing::warn!(
+                "new configuration configures the subgraphs {:?} missing from the schema, their configuration will be ignored",
+                unknown
+            );
+            true
+        }
+    }
+
+    /// Runs the schema validators, logging the reasons for rejecting the schema.
+    /// Returns false if the schema must be rejected
+    fn validate_schema(&self, schema: &Schema) -> bool {
+        let mut valid = true;
+        for validator in &self.schema_validators {
+            if let Err(diagnostics) = validator.validate(schema) {
+                for diagnostic in diagnostics {
+                    tracing::error!("schema validation failed: {}", diagnostic);
+                }
+                valid = false;
+            }
+        }
+        valid
+    }
+
+    /// Calls the schema reload callback, if any, off the event processing path
+    fn notify_schema_reload<RS>(&self, state: &State<RS>) {
+        if let (Some(on_schema_reload), Running { schema, .. }) = (&self.on_schema_reload, state) {
+            let on_schema_reload = on_schema_reload.clone();
+            let schema_hash = schema.schema_id.clone().unwrap_or_default();
+            tokio::task::spawn_blocking(move || on_schema_reload.call(&schema_hash));
+        }
+    }
+
     async fn maybe_update_listen_addresses(
         &mut self,
         state: &mut State<<FA as SupergraphServiceConfigurator>::SupergraphServiceFactory>,
     ) {
-        let (graphql_listen_address, extra_listen_addresses) =
-            if let Running { server_handle, .. } = &state {
-                let listen_addresses = server_handle.listen_addresses().to_vec();
-                let graphql_listen_address = server_handle.graphql_listen_address().clone();
-                (graphql_listen_address, listen_addresses)
-            } else {
-                return;
-            };
+        let (graphql_listen_address, extra_listen_addresses, make_service) = if let Running {
+            server_handle,
+            router_service_factory,
+            ..
+        } = &state
+        {
+            let listen_addresses = server_handle.listen_addresses().to_vec();
+            let graphql_listen_address = server_handle.graphql_listen_address().clone();
+            let factory = router_service_factory.clone();
+            let make_service: SupergraphServiceMaker =
+                Arc::new(move || factory.new_internal_service());
+            (graphql_listen_address, listen_addresses, make_service)
+        } else {
+            return;
+        };
+
+        // updated first, so the pipeline can be checked as soon as the listen address is known
+        *self.supergraph_service.write().await = Some(make_service);
 
         if let Some(mut listen_address_guard) = self.graphql_listen_address_guard.take() {
             *listen_address_guard = graphql_listen_address;
@@ -289,6 +499,7 @@ where
     async fn maybe_transition_to_running(
         &mut self,
         state: State<<FA as SupergraphServiceConfigurator>::SupergraphServiceFactory>,
+        messages: &mut Peekable<impl Stream<Item = Event> + Unpin>,
     ) -> Result<
         State<<FA as SupergraphServiceConfigurator>::SupergraphServiceFactory>,
         State<<FA as SupergraphServiceConfigurator>::SupergraphServiceFactory>,
@@ -308,6 +519,12 @@ where
                     });
                 }
             };
+            if !self.validate_schema(&schema) {
+                return Ok(Startup {
+                    configuration: Some(configuration),
+                    schema: None,
+                });
+            }
             tracing::debug!("starting http");
             let configuration = Arc::new(configuration);
             let schema = Arc::new(schema);
@@ -321,6 +538,8 @@ where
                     Errored(ApolloRouterError::ServiceCreationError(err))
                 })?;
 
+            warm_up(&configuration, &router_factory, messages).await;
+
             let web_endpoints = router_factory.web_endpoints();
 
             let server_handle = self
@@ -358,6 +577,7 @@ where
         server_handle: HttpServerHandle,
         new_configuration: Option<Arc<Configuration>>,
         new_schema: Option<Arc<Schema>>,
+        messages: &mut Peekable<impl Stream<Item = Event> + Unpin>,
     ) -> Result<
         State<<FA as SupergraphServiceConfigurator>::SupergraphServiceFactory>,
         State<<FA as SupergraphServiceConfigurator>::SupergraphServiceFactory>,
@@ -376,6 +596,9 @@ where
             .await
         {
             Ok(new_router_service) => {
+                // the previous router keeps serving traffic during the warmup
+                warm_up(&new_configuration, &new_router_service, messages).await;
+
                 let web_endpoints = new_router_service.web_endpoints();
 
                 let server_handle = server_handle
@@ -413,6 +636,44 @@ where
     }
 }
 
+/// In eager mode, plans the configured warmup operations before the router serves traffic,
+/// waiting at most for the warmup timeout. A shutdown stops the warmup, the other events wait
+/// for it
+async fn warm_up<RF>(
+    configuration: &Configuration,
+    router_factory: &RF,
+    messages: &mut Peekable<impl Stream<Item = Event> + Unpin>,
+) where
+    RF: SupergraphServiceFactory,
+{
+    let warmup = &configuration.warmup;
+    if warmup.mode != WarmupMode::Eager || warmup.operations.is_empty() {
+        return;
+    }
+    tracing::info!("planning {} warmup operations", warmup.operations.len());
+    let warming_up = tokio::time::timeout(
+        warmup.timeout,
+        router_factory.warm_up(warmup.operations.clone()),
+    );
+    let shutdown = async {
+        match Pin::new(messages).peek().await {
+            Some(Shutdown) => {}
+            _ => future::pending().await,
+        }
+    };
+    tokio::select! {
+        warmed_up = warming_up => {
+            if warmed_up.is_err() {
+                tracing::warn!(
+                    "the warmup did not complete in {:?}, serving traffic anyway",
+                    warmup.timeout
+                );
+            }
+        }
+        _ = shutdown => tracing::info!("shutting down, the warmup is stopped"),
+    }
+}
+
 trait ResultExt<T> {
     // Unstable method can be deleted in future
     fn into_ok_or_err2(self) -> T;
@@ -432,9 +693,13 @@ mod tests {
     use std::net::SocketAddr;
     use std::pin::Pin;
     use std::str::FromStr;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::atomic::AtomicUsize;
+    use std::sync::atomic::Ordering;
     use std::sync::Mutex;
     use std::task::Context;
     use std::task::Poll;
+    use std::time::Duration;
 
     use futures::channel::oneshot;
     use futures::future::BoxFuture;
@@ -446,6 +711,8 @@ mod tests {
     use tower::Service;
 
     use super::*;
+    use crate::configuration::Warmup;
+    use crate::configuration::WarmupOperation;
     use crate::http_server_factory::Listener;
     use crate::plugin::DynPlugin;
     use crate::router_factory::Endpoint;
@@ -649,6 +916,383 @@ mod tests {
         assert_eq!(shutdown_receivers.lock().unwrap().len(), 1);
     }
 
+    #[test(tokio::test)]
+    async fn schema_reload_callback() {
+        let router_factory = create_mock_router_configurator(3);
+        let (server_factory, _) = create_mock_server_factory(3);
+        let minimal_schema = include_str!("testdata/minimal_supergraph.graphql");
+        let schema_hash = |schema: &str| {
+            Schema::parse(schema, &Default::default())
+                .unwrap()
+                .schema_id
+                .unwrap()
+        };
+
+        let (sender, mut receiver) = tokio::sync::mpsc::unbounded_channel();
+        let state_machine = StateMachine::new(server_factory, router_factory)
+            .with_schema_reload_callback(Some(SchemaReloadCallback::from(
+                move |schema_hash: &str| {
+                    sender.send(schema_hash.to_string()).unwrap();
+                },
+            )));
+        assert!(matches!(
+            state_machine
+                .process_events(
+                    stream::iter(vec![
+                        UpdateConfiguration(Configuration::builder().build().unwrap().boxed()),
+                        UpdateSchema(example_schema()),
+                        UpdateSchema(minimal_schema.to_owned()),
+                        UpdateSchema(example_schema()),
+                        Shutdown
+                    ])
+                    .boxed()
+                )
+                .await,
+            Ok(()),
+        ));
+
+        // The callback is not called on startup, only on reloads
+        let mut reloads = vec![
+            receiver.recv().await.unwrap(),
+            receiver.recv().await.unwrap(),
+        ];
+        reloads.sort();
+        let mut expected = vec![schema_hash(minimal_schema), schema_hash(&example_schema())];
+        expected.sort();
+        assert_eq!(reloads, expected);
+        assert!(receiver.recv().await.is_none());
+    }
+
+    #[test(tokio::test)]
+    async fn schema_validators_reject_schema() {
+        let router_factory = create_mock_router_configurator(2);
+        let (server_factory, shutdown_receivers) = create_mock_server_factory(2);
+        let minimal_schema = include_str!("testdata/minimal_supergraph.graphql");
+
+        let validations = Arc::new(Mutex::new(Vec::new()));
+        let recorded = validations.clone();
+        let state_machine = StateMachine::new(server_factory, router_factory)
+            .with_schema_validators(vec![SchemaValidator::from(
+                move |schema: &crate::SupergraphSchema| {
+                    let valid = schema.has_type("Product");
+                    recorded.lock().unwrap().push(valid);
+                    if valid {
+                        Ok(())
+                    } else {
+                        Err(vec![crate::SchemaDiagnostic::new(
+                            "the Product type is required",
+                        )])
+                    }
+                },
+            )]);
+        assert!(matches!(
+            state_machine
+                .process_events(
+                    stream::iter(vec![
+                        UpdateConfiguration(Configuration::builder().build().unwrap().boxed()),
+                        // rejected on startup, the router waits for a valid schema
+                        UpdateSchema(minimal_schema.to_owned()),
+                        UpdateSchema(example_schema()),
+                        // rejected on reload, the router keeps the previous schema
+                        UpdateSchema(minimal_schema.to_owned()),
+                        UpdateSchema(example_schema()),
+                        Shutdown
+                    ])
+                    .boxed()
+                )
+                .await,
+            Ok(()),
+        ));
+        assert_eq!(*validations.lock().unwrap(), vec![false, true, false, true]);
+        assert_eq!(shutdown_receivers.lock().unwrap().len(), 2);
+    }
+
+    fn subgraph_check_configuration(strict: bool) -> Configuration {
+        Configuration::builder()
+            .schema(
+                crate::configuration::SchemaReload::builder()
+                    .strict_subgraph_check(strict)
+                    .build(),
+            )
+            .apollo_plugins(
+                serde_json::json!({
+                    "override_subgraph_url": { "products": "http://localhost:4003/graphql" }
+                })
+                .as_object()
+                .unwrap()
+                .clone(),
+            )
+            .build()
+            .unwrap()
+    }
+
+    #[test(tokio::test)]
+    async fn strict_subgraph_check_rejects_schema_dropping_configured_subgraph() {
+        let router_factory = create_mock_router_configurator(1);
+        let (server_factory, shutdown_receivers) = create_mock_server_factory(1);
+        let minimal_schema = include_str!("testdata/minimal_supergraph.graphql");
+        assert!(matches!(
+            execute(
+                server_factory,
+                router_factory,
+                vec![
+                    UpdateConfiguration(subgraph_check_configuration(true).boxed()),
+                    UpdateSchema(example_schema()),
+                    UpdateSchema(minimal_schema.to_owned()),
+                    Shutdown
+                ],
+            )
+            .await,
+            Ok(()),
+        ));
+        assert_eq!(shutdown_receivers.lock().unwrap().len(), 1);
+    }
+
+    #[test(tokio::test)]
+    async fn subgraph_check_accepts_schema_dropping_configured_subgraph() {
+        let router_factory = create_mock_router_configurator(2);
+        let (server_factory, shutdown_receivers) = create_mock_server_factory(2);
+        let minimal_schema = include_str!("testdata/minimal_supergraph.graphql");
+        assert!(matches!(
+            execute(
+                server_factory,
+                router_factory,
+                vec![
+                    UpdateConfiguration(subgraph_check_configuration(false).boxed()),
+                    UpdateSchema(example_schema()),
+                    UpdateSchema(minimal_schema.to_owned()),
+                    Shutdown
+                ],
+            )
+            .await,
+            Ok(()),
+        ));
+        assert_eq!(shutdown_receivers.lock().unwrap().len(), 2);
+    }
+
+    fn subgraph_url_reload_configuration(strict: bool, subgraph: &str) -> Box<Configuration> {
+        let mut configuration = subgraph_check_configuration(strict);
+        configuration.apollo_plugins.plugins.insert(
+            "override_subgraph_url".to_string(),
+            serde_json::json!({ subgraph: "http://localhost:4004/graphql" }),
+        );
+        configuration.boxed()
+    }
+
+    fn create_subgraph_url_router_configurator(
+        expect_url_updates: usize,
+    ) -> MockMyRouterConfigurator {
+        let mut router_factory = MockMyRouterConfigurator::new();
+        router_factory
+            .expect_create()
+            .times(1)
+            .returning(move |_, _, _, _| {
+                let mut router = MockMyRouterFactory::new();
+                router.expect_clone().returning(MockMyRouterFactory::new);
+                router
+                    .expect_update_subgraph_urls()
+                    .times(expect_url_updates)
+                    .return_const(true);
+                router.expect_web_endpoints().returning(MultiMap::new);
+                Ok(router)
+            });
+        router_factory
+    }
+
+    #[test(tokio::test)]
+    async fn strict_subgraph_check_rejects_subgraph_url_reload_for_unknown_subgraph() {
+        let router_factory = create_subgraph_url_router_configurator(0);
+        let (server_factory, shutdown_receivers) = create_mock_server_factory(1);
+        assert!(matches!(
+            execute(
+                server_factory,
+                router_factory,
+                vec![
+                    UpdateConfiguration(subgraph_check_configuration(true).boxed()),
+                    UpdateSchema(example_schema()),
+                    UpdateConfiguration(subgraph_url_reload_configuration(true, "unknown")),
+                    Shutdown
+                ],
+            )
+            .await,
+            Ok(()),
+        ));
+        assert_eq!(shutdown_receivers.lock().unwrap().len(), 1);
+    }
+
+    #[test(tokio::test)]
+    async fn strict_subgraph_check_accepts_subgraph_url_reload_for_known_subgraph() {
+        let router_factory = create_subgraph_url_router_configurator(1);
+        let (server_factory, shutdown_receivers) = create_mock_server_factory(1);
+        assert!(matches!(
+            execute(
+                server_factory,
+                router_factory,
+                vec![
+                    UpdateConfiguration(subgraph_check_configuration(true).boxed()),
+                    UpdateSchema(example_schema()),
+                    UpdateConfiguration(subgraph_url_reload_configuration(true, "products")),
+                    Shutdown
+                ],
+            )
+            .await,
+            Ok(()),
+        ));
+        assert_eq!(shutdown_receivers.lock().unwrap().len(), 1);
+    }
+
+    fn eager_warmup_configuration(timeout: Duration) -> Box<Configuration> {
+        Configuration::builder()
+            .warmup(
+                Warmup::builder()
+                    .mode(WarmupMode::Eager)
+                    .operations(vec![WarmupOperation {
+                        query: "{ me { name } }".to_string(),
+                        operation_name: None,
+                    }])
+                    .timeout(timeout)
+                    .build(),
+            )
+            .build()
+            .unwrap()
+            .boxed()
+    }
+
+    fn create_warming_router_configurator(
+        warm_up: impl Fn() -> BoxFuture<'static, ()> + Send + Sync + Clone + 'static,
+    ) -> MockMyRouterConfigurator {
+        let mut router_factory = MockMyRouterConfigurator::new();
+        router_factory
+            .expect_create()
+            .times(1)
+            .returning(move |_, _, _, _| {
+                let mut router = MockMyRouterFactory::new();
+                router.expect_clone().return_once(MockMyRouterFactory::new);
+                router.expect_web_endpoints().returning(MultiMap::new);
+                let warm_up = warm_up.clone();
+                router
+                    .expect_warm_up()
+                    .times(1)
+                    .returning(move |operations| {
+                        assert_eq!(operations.len(), 1);
+                        warm_up()
+                    });
+                Ok(router)
+            });
+        router_factory
+    }
+
+    #[test(tokio::test)]
+    async fn eager_warmup_delays_traffic() {
+        let warmed_up = Arc::new(AtomicBool::new(false));
+        let warmed_up_clone = warmed_up.clone();
+        let router_factory = create_warming_router_configurator(move || {
+            let warmed_up = warmed_up_clone.clone();
+            Box::pin(async move {
+                tokio::time::sleep(Duration::from_millis(50)).await;
+                warmed_up.store(true, Ordering::SeqCst);
+            })
+        });
+
+        // the server must not accept traffic before the operations are planned
+        let mut server_factory = MockMyHttpServerFactory::new();
+        server_factory
+            .expect_create_server()
+            .times(1)
+            .returning(move |configuration, _| {
+                assert!(warmed_up.load(Ordering::SeqCst));
+                let (shutdown_sender, _) = oneshot::channel();
+                let server = async {
+                    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+                    Ok((Listener::Tcp(listener), vec![]))
+                };
+                Ok(HttpServerHandle::new(
+                    shutdown_sender,
+                    Box::pin(server),
+                    Some(configuration.supergraph.listen.clone()),
+                    vec![],
+                ))
+            });
+
+        assert!(matches!(
+            execute(
+                server_factory,
+                router_factory,
+                vec![
+                    UpdateConfiguration(eager_warmup_configuration(Duration::from_secs(10))),
+                    UpdateSchema(example_schema()),
+                    Shutdown
+                ],
+            )
+            .await,
+            Ok(()),
+        ));
+    }
+
+    #[test(tokio::test)]
+    async fn eager_warmup_timeout() {
+        let router_factory =
+            create_warming_router_configurator(|| Box::pin(futures::future::pending()));
+        let (server_factory, shutdown_receivers) = create_mock_server_factory(1);
+
+        assert!(matches!(
+            execute(
+                server_factory,
+                router_factory,
+                vec![
+                    UpdateConfiguration(eager_warmup_configuration(Duration::from_millis(50))),
+                    UpdateSchema(example_schema()),
+                    Shutdown
+                ],
+            )
+            .await,
+            Ok(()),
+        ));
+        assert_eq!(shutdown_receivers.lock().unwrap().len(), 1);
+    }
+
+    #[test(tokio::test)]
+    async fn shutdown_stops_the_eager_warmup_of_a_reload() {
+        let created = Arc::new(AtomicUsize::new(0));
+        let mut router_factory = MockMyRouterConfigurator::new();
+        router_factory
+            .expect_create()
+            .times(2)
+            .returning(move |_, _, _, _| {
+                // the warmup of the reloaded router never completes
+                let reloaded = created.fetch_add(1, Ordering::SeqCst) > 0;
+                let mut router = MockMyRouterFactory::new();
+                router.expect_clone().return_once(MockMyRouterFactory::new);
+                router.expect_web_endpoints().returning(MultiMap::new);
+                router.expect_warm_up().times(1).returning(move |_| {
+                    if reloaded {
+                        Box::pin(future::pending())
+                    } else {
+                        Box::pin(future::ready(()))
+                    }
+                });
+                Ok(router)
+            });
+        let (server_factory, shutdown_receivers) = create_mock_server_factory(2);
+        let minimal_schema = include_str!("testdata/minimal_supergraph.graphql");
+
+        assert!(matches!(
+            execute(
+                server_factory,
+                router_factory,
+                vec![
+                    UpdateConfiguration(eager_warmup_configuration(Duration::from_secs(3600))),
+                    UpdateSchema(minimal_schema.to_owned()),
+                    UpdateSchema(example_schema()),
+                    Shutdown
+                ],
+            )
+            .await,
+            Ok(()),
+        ));
+        assert_eq!(shutdown_receivers.lock().unwrap().len(), 2);
+    }
+
     mock! {
         #[derive(Debug)]
         MyRouterConfigurator {}
@@ -675,6 +1319,8 @@ mod tests {
             type SupergraphService = MockMyRouter;
             type Future = <Self::SupergraphService as Service<SupergraphRequest>>::Future;
             fn web_endpoints(&self) -> MultiMap<ListenAddr, Endpoint>;
+            fn warm_up(&self, operations: Vec<WarmupOperation>) -> BoxFuture<'static, ()>;
+            fn update_subgraph_urls(&self, configuration: &Configuration) -> bool;
         }
         impl  NewService<SupergraphRequest> for MyRouterFactory {
             type Service = MockMyRouter;
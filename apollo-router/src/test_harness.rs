@@ -201,7 +201,7 @@ impl<'a> TestHarness<'a> {
         let canned_schema = include_str!("../testing_schema.graphql");
         let schema = builder.schema.unwrap_or(canned_schema);
         let schema = Arc::new(Schema::parse(schema, &config)?);
-        let router_creator = YamlSupergraphServiceFactory
+        let router_creator = YamlSupergraphServiceFactory::default()
             .create(config.clone(), schema, None, Some(builder.extra_plugins))
             .await?;
 
@@ -211,7 +211,9 @@ impl<'a> TestHarness<'a> {
     /// Builds the GraphQL service
     pub async fn build(self) -> Result<supergraph::BoxCloneService, BoxError> {
         let (_config, router_creator) = self.build_common().await?;
-        let apq = APQLayer::new().await;
+        let apq = APQLayer::new()
+            .await
+            .with_buffer_metrics(router_creator.buffer_metrics("apq"));
 
         Ok(tower::service_fn(move |request| {
             // APQ must be added here because it is implemented in the HTTP server
@@ -236,6 +238,23 @@ impl<'a> TestHarness<'a> {
         let ListenAddrAndRouter(_listener, router) = routers.main;
         Ok(router.boxed())
     }
+
+    /// Serves the router over HTTP, on the listen address of the configuration
+    #[cfg(test)]
+    pub(crate) async fn build_http_server(
+        self,
+    ) -> Result<crate::http_server_factory::HttpServerHandle, BoxError> {
+        use crate::axum_factory::AxumHttpServerFactory;
+        use crate::http_server_factory::HttpServerFactory;
+        use crate::router_factory::SupergraphServiceFactory;
+
+        let (config, router_creator) = self.build_common().await?;
+        let web_endpoints = router_creator.web_endpoints();
+        let server = AxumHttpServerFactory::new()
+            .create(router_creator, config, None, vec![], web_endpoints)
+            .await?;
+        Ok(server)
+    }
 }
 
 /// An HTTP-level service, as would be given to Hyper’s server
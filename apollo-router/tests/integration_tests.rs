@@ -925,6 +925,39 @@ impl Plugin for CountingServiceRegistry {
     }
 }
 
+#[derive(Debug, serde::Deserialize, schemars::JsonSchema)]
+struct SchemaPluginConfig {
+    /// A name to greet
+    name: String,
+}
+
+struct SchemaPlugin;
+
+#[async_trait::async_trait]
+impl Plugin for SchemaPlugin {
+    type Config = SchemaPluginConfig;
+
+    async fn new(_: PluginInit<Self::Config>) -> Result<Self, BoxError> {
+        unreachable!()
+    }
+}
+
+apollo_router::register_plugin!("test", "schema_plugin", SchemaPlugin);
+
+#[test]
+fn plugin_schemas_list_registered_plugins() {
+    let schemas = apollo_router::plugin::plugin_schemas();
+    assert!(schemas.contains_key("apollo.telemetry"));
+
+    let schema = serde_json::to_value(&schemas["test.schema_plugin"]).unwrap();
+    assert_eq!(schema["required"], serde_json::json!(["name"]));
+    assert_eq!(schema["properties"]["name"]["type"], "string");
+    assert_eq!(
+        schema["properties"]["name"]["description"],
+        "A name to greet"
+    );
+}
+
 trait ValueExt {
     fn eq_and_ordered(&self, other: &Self) -> bool;
 }